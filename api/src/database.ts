@@ -182,6 +182,92 @@ export class Database {
     );
     return result.rows;
   }
+
+  // SLO queries
+  async getSloStatuses(alertingOnly = false): Promise<any[]> {
+    const result = await this.query(
+      `SELECT status FROM slo_status
+       WHERE $1 = FALSE OR alert_severity IS NOT NULL
+       ORDER BY error_budget_remaining ASC`,
+      [alertingOnly]
+    );
+    return result.rows.map((row) => row.status);
+  }
+
+  async getSloStatus(sloId: string): Promise<any | null> {
+    const result = await this.query('SELECT status FROM slo_status WHERE slo_id = $1', [sloId]);
+    return result.rows.length > 0 ? result.rows[0].status : null;
+  }
+
+  // Experiment queries
+  async getExperimentReports(verdict?: string): Promise<any[]> {
+    const result = await this.query(
+      `SELECT report FROM experiment_reports
+       WHERE $1::TEXT IS NULL OR verdict = $1
+       ORDER BY started_at DESC`,
+      [verdict ?? null]
+    );
+    return result.rows.map((row) => row.report);
+  }
+
+  async getExperimentReport(experimentId: string): Promise<any | null> {
+    const result = await this.query(
+      'SELECT report FROM experiment_reports WHERE experiment_id = $1',
+      [experimentId]
+    );
+    return result.rows.length > 0 ? result.rows[0].report : null;
+  }
+
+  // Session queries
+  async getSessionTimeseries(bucket: string, startTime: Date, endTime: Date): Promise<any[]> {
+    const result = await this.query(
+      `SELECT time_bucket($1::INTERVAL, started_at) AS bucket,
+              COUNT(*) AS sessions,
+              AVG(turns) AS avg_turns,
+              AVG(duration_seconds) AS avg_duration_secs,
+              AVG(total_tokens) AS avg_tokens,
+              AVG(cost_usd) AS avg_cost_usd,
+              SUM(cost_usd) AS total_cost_usd,
+              AVG(abandoned::INT) AS abandonment_rate
+       FROM session_metrics
+       WHERE started_at >= $2 AND started_at < $3
+       GROUP BY bucket
+       ORDER BY bucket ASC`,
+      [bucket, startTime, endTime]
+    );
+    return result.rows;
+  }
+
+  async getTtftByTurn(startTime: Date, endTime: Date, maxTurn: number): Promise<any[]> {
+    const result = await this.query(
+      `SELECT turn_index,
+              COUNT(*) AS samples,
+              percentile_cont(0.50) WITHIN GROUP (ORDER BY ttft_ms) AS ttft_p50_ms,
+              percentile_cont(0.90) WITHIN GROUP (ORDER BY ttft_ms) AS ttft_p90_ms,
+              percentile_cont(0.99) WITHIN GROUP (ORDER BY ttft_ms) AS ttft_p99_ms
+       FROM session_turns
+       WHERE timestamp >= $1 AND timestamp < $2
+       AND turn_index <= $3 AND ttft_ms IS NOT NULL
+       GROUP BY turn_index
+       ORDER BY turn_index ASC`,
+      [startTime, endTime, maxTurn]
+    );
+    return result.rows;
+  }
+
+  async getAbandonmentPoints(startTime: Date, endTime: Date): Promise<any[]> {
+    const result = await this.query(
+      `SELECT turns AS turn_index,
+              COUNT(*) AS sessions_ended,
+              COUNT(*) FILTER (WHERE abandoned) AS sessions_abandoned
+       FROM session_metrics
+       WHERE started_at >= $1 AND started_at < $2
+       GROUP BY turns
+       ORDER BY turns ASC`,
+      [startTime, endTime]
+    );
+    return result.rows;
+  }
 }
 
 export { pool };
@@ -0,0 +1,84 @@
+/**
+ * A/B and canary experiment API routes
+ */
+
+import { FastifyInstance, FastifyRequest, FastifyReply } from 'fastify';
+
+export async function experimentsRoutes(fastify: FastifyInstance) {
+  // List experiment verdict reports
+  fastify.get(
+    '/',
+    {
+      schema: {
+        description: 'List experiment verdict reports, most recently started first',
+        tags: ['experiments'],
+        querystring: {
+          type: 'object',
+          properties: {
+            verdict: {
+              type: 'string',
+              enum: [
+                'promote_treatment',
+                'rollback_treatment',
+                'no_significant_difference',
+                'continue',
+              ],
+            },
+          },
+        },
+      },
+    },
+    async (request: FastifyRequest, reply: FastifyReply) => {
+      const query = request.query as any;
+
+      try {
+        const experiments = await fastify.db.getExperimentReports(query.verdict);
+
+        reply.send({
+          experiments,
+          count: experiments.length,
+        });
+      } catch (err) {
+        fastify.log.error({ err }, 'Failed to list experiment reports');
+        reply.code(500).send({ error: 'Failed to list experiment reports' });
+      }
+    }
+  );
+
+  // Get experiment verdict report by ID
+  fastify.get(
+    '/:experimentId',
+    {
+      schema: {
+        description: 'Get the verdict report of an experiment',
+        tags: ['experiments'],
+        params: {
+          type: 'object',
+          properties: {
+            experimentId: { type: 'string' },
+          },
+        },
+      },
+    },
+    async (
+      request: FastifyRequest<{ Params: { experimentId: string } }>,
+      reply: FastifyReply
+    ) => {
+      const { experimentId } = request.params;
+
+      try {
+        const report = await fastify.db.getExperimentReport(experimentId);
+
+        if (!report) {
+          reply.code(404).send({ error: 'Experiment not found' });
+          return;
+        }
+
+        reply.send(report);
+      } catch (err) {
+        fastify.log.error({ err }, 'Failed to get experiment report');
+        reply.code(500).send({ error: 'Failed to get experiment report' });
+      }
+    }
+  );
+}
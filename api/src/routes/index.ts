@@ -6,10 +6,16 @@ import { FastifyInstance } from 'fastify';
 import { eventsRoutes } from './events';
 import { metricsRoutes } from './metrics';
 import { analyticsRoutes } from './analytics';
+import { sloRoutes } from './slo';
+import { experimentsRoutes } from './experiments';
+import { sessionsRoutes } from './sessions';
 
 export function registerRoutes(fastify: FastifyInstance): void {
   // Register route modules
   fastify.register(eventsRoutes, { prefix: '/api/v1/events' });
   fastify.register(metricsRoutes, { prefix: '/api/v1/metrics' });
   fastify.register(analyticsRoutes, { prefix: '/api/v1/analytics' });
+  fastify.register(sloRoutes, { prefix: '/api/v1/slos' });
+  fastify.register(experimentsRoutes, { prefix: '/api/v1/experiments' });
+  fastify.register(sessionsRoutes, { prefix: '/api/v1/sessions' });
 }
@@ -0,0 +1,121 @@
+/**
+ * Session and conversation analytics API routes
+ */
+
+import { FastifyInstance, FastifyRequest, FastifyReply } from 'fastify';
+
+const timeRange = {
+  start_time: { type: 'string', format: 'date-time' },
+  end_time: { type: 'string', format: 'date-time' },
+};
+
+export async function sessionsRoutes(fastify: FastifyInstance) {
+  // Session totals over time buckets
+  fastify.get(
+    '/timeseries',
+    {
+      schema: {
+        description: 'Get session count, turns, duration, tokens, cost and abandonment per time bucket',
+        tags: ['sessions'],
+        querystring: {
+          type: 'object',
+          properties: {
+            ...timeRange,
+            bucket: { type: 'string', enum: ['5m', '1h', '1d'], default: '1h' },
+          },
+          required: ['start_time', 'end_time'],
+        },
+      },
+    },
+    async (request: FastifyRequest, reply: FastifyReply) => {
+      const query = request.query as any;
+      const buckets: Record<string, string> = {
+        '5m': '5 minutes',
+        '1h': '1 hour',
+        '1d': '1 day',
+      };
+
+      try {
+        const series = await fastify.db.getSessionTimeseries(
+          buckets[query.bucket ?? '1h'],
+          new Date(query.start_time),
+          new Date(query.end_time)
+        );
+
+        reply.send({
+          series,
+          count: series.length,
+        });
+      } catch (err) {
+        fastify.log.error({ err }, 'Failed to query session time-series');
+        reply.code(500).send({ error: 'Failed to query session time-series' });
+      }
+    }
+  );
+
+  // Time-to-first-token distribution by turn index
+  fastify.get(
+    '/ttft-by-turn',
+    {
+      schema: {
+        description: 'Get time-to-first-token percentiles by turn index',
+        tags: ['sessions'],
+        querystring: {
+          type: 'object',
+          properties: {
+            ...timeRange,
+            max_turn: { type: 'integer', minimum: 1, default: 20 },
+          },
+          required: ['start_time', 'end_time'],
+        },
+      },
+    },
+    async (request: FastifyRequest, reply: FastifyReply) => {
+      const query = request.query as any;
+
+      try {
+        const turns = await fastify.db.getTtftByTurn(
+          new Date(query.start_time),
+          new Date(query.end_time),
+          query.max_turn ?? 20
+        );
+
+        reply.send({ turns });
+      } catch (err) {
+        fastify.log.error({ err }, 'Failed to query TTFT by turn');
+        reply.code(500).send({ error: 'Failed to query TTFT by turn' });
+      }
+    }
+  );
+
+  // Where sessions end and are abandoned
+  fastify.get(
+    '/abandonment',
+    {
+      schema: {
+        description: 'Get sessions ended and abandoned at each turn index',
+        tags: ['sessions'],
+        querystring: {
+          type: 'object',
+          properties: timeRange,
+          required: ['start_time', 'end_time'],
+        },
+      },
+    },
+    async (request: FastifyRequest, reply: FastifyReply) => {
+      const query = request.query as any;
+
+      try {
+        const points = await fastify.db.getAbandonmentPoints(
+          new Date(query.start_time),
+          new Date(query.end_time)
+        );
+
+        reply.send({ points });
+      } catch (err) {
+        fastify.log.error({ err }, 'Failed to query session abandonment');
+        reply.code(500).send({ error: 'Failed to query session abandonment' });
+      }
+    }
+  );
+}
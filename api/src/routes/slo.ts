@@ -0,0 +1,73 @@
+/**
+ * SLO and error-budget API routes
+ */
+
+import { FastifyInstance, FastifyRequest, FastifyReply } from 'fastify';
+
+export async function sloRoutes(fastify: FastifyInstance) {
+  // List SLO statuses
+  fastify.get(
+    '/',
+    {
+      schema: {
+        description: 'List SLO error-budget statuses, least remaining budget first',
+        tags: ['slos'],
+        querystring: {
+          type: 'object',
+          properties: {
+            alerting: { type: 'boolean', default: false },
+          },
+        },
+      },
+    },
+    async (request: FastifyRequest, reply: FastifyReply) => {
+      const query = request.query as any;
+
+      try {
+        const slos = await fastify.db.getSloStatuses(query.alerting === true);
+
+        reply.send({
+          slos,
+          count: slos.length,
+        });
+      } catch (err) {
+        fastify.log.error({ err }, 'Failed to list SLO statuses');
+        reply.code(500).send({ error: 'Failed to list SLO statuses' });
+      }
+    }
+  );
+
+  // Get SLO status by ID
+  fastify.get(
+    '/:sloId',
+    {
+      schema: {
+        description: 'Get the error-budget status of an SLO',
+        tags: ['slos'],
+        params: {
+          type: 'object',
+          properties: {
+            sloId: { type: 'string' },
+          },
+        },
+      },
+    },
+    async (request: FastifyRequest<{ Params: { sloId: string } }>, reply: FastifyReply) => {
+      const { sloId } = request.params;
+
+      try {
+        const status = await fastify.db.getSloStatus(sloId);
+
+        if (!status) {
+          reply.code(404).send({ error: 'SLO not found' });
+          return;
+        }
+
+        reply.send(status);
+      } catch (err) {
+        fastify.log.error({ err }, 'Failed to get SLO status');
+        reply.code(500).send({ error: 'Failed to get SLO status' });
+      }
+    }
+  );
+}
@@ -3,15 +3,16 @@
 //! Real-time metrics aggregation with multiple time windows and statistical measures.
 
 use crate::models::metrics::{
-    AggregatedMetric, MetricValues, StatisticalMeasures, TimeWindow,
+    AggregatedMetric, CounterMetric, MetricValues, StatisticalMeasures, TimeWindow, WindowFunction,
 };
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use dashmap::DashMap;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tracing::debug;
 
+use super::window_functions::{self, Sample};
 use super::AnalyticsConfig;
 
 /// Real-time aggregation engine
@@ -79,6 +80,54 @@ impl AggregationEngine {
         Ok(())
     }
 
+    /// Add a counter reading; rates over counters are adjusted for resets.
+    /// Readings older than each window are dropped, since only the trailing
+    /// window is evaluated.
+    pub fn add_counter(&self, counter: &CounterMetric) -> Result<()> {
+        for window_map in self.aggregations.iter() {
+            let window = *window_map.key();
+            let mut state = window_map
+                .value()
+                .entry(counter.name.clone())
+                .or_insert_with(AggregationState::new);
+            state.counter = true;
+            state.add_value(counter.value as f64, counter.timestamp);
+            state.retain_window(window);
+        }
+        Ok(())
+    }
+
+    /// Evaluate a window function over the samples in the trailing window
+    /// ending at the metric's latest sample
+    pub fn compute(
+        &self,
+        metric_name: &str,
+        window: TimeWindow,
+        function: WindowFunction,
+    ) -> Option<f64> {
+        let window_map = self.aggregations.get(&window)?;
+        let state = window_map.get(metric_name)?;
+        function.evaluate(&state.window_samples(window), state.counter)
+    }
+
+    /// Evaluate a window function at every sample in the trailing window,
+    /// with scalar functions computed over the trailing `range`
+    pub fn compute_series(
+        &self,
+        metric_name: &str,
+        window: TimeWindow,
+        function: WindowFunction,
+        range: Duration,
+    ) -> Vec<Sample> {
+        let Some(window_map) = self.aggregations.get(&window) else {
+            return Vec::new();
+        };
+        let Some(state) = window_map.get(metric_name) else {
+            return Vec::new();
+        };
+        function.evaluate_series(&state.window_samples(window), range, state.counter)
+    }
+
     /// Get aggregated metrics for a time window
     pub fn get_aggregated(
         &self,
@@ -88,15 +137,23 @@ impl AggregationEngine {
         let window_map = self.aggregations.get(&window)?;
         let state = window_map.get(metric_name)?;
 
-        let stats = state.calculate_statistics();
         let (window_start, window_end) = state.get_time_bounds();
+        let values = if state.counter {
+            let samples = state.window_samples(window);
+            MetricValues::Counter {
+                value: samples.last().map_or(0.0, |(_, v)| *v) as u64,
+                rate: window_functions::rate(&samples, true).unwrap_or(0.0),
+            }
+        } else {
+            MetricValues::Stats(state.calculate_statistics())
+        };
 
         Some(AggregatedMetric {
             name: metric_name.to_string(),
             window,
             window_start,
             window_end,
-            values: MetricValues::Stats(stats),
+            values,
             tags: HashMap::new(),
         })
     }
@@ -157,6 +214,8 @@ struct AggregationState {
     timestamps: Vec<DateTime<Utc>>,
     min_timestamp: Option<DateTime<Utc>>,
     max_timestamp: Option<DateTime<Utc>>,
+    /// Values are readings of a monotonic counter
+    counter: bool,
 }
 
 impl AggregationState {
@@ -166,9 +225,49 @@ impl AggregationState {
             timestamps: Vec::new(),
             min_timestamp: None,
             max_timestamp: None,
+            counter: false,
         }
     }
 
+    /// Time-ordered samples within the window ending at the latest sample
+    fn window_samples(&self, window: TimeWindow) -> Vec<Sample> {
+        let Some(end) = self.max_timestamp else {
+            return Vec::new();
+        };
+        let start = end - Duration::seconds(window.to_seconds() as i64);
+        let mut samples: Vec<Sample> = self
+            .timestamps
+            .iter()
+            .copied()
+            .zip(self.values.iter().copied())
+            .filter(|(t, _)| *t >= start)
+            .collect();
+        samples.sort_by_key(|(t, _)| *t);
+        samples
+    }
+
+    /// Drop samples before the window ending at the latest sample
+    fn retain_window(&mut self, window: TimeWindow) {
+        let Some(end) = self.max_timestamp else {
+            return;
+        };
+        let start = end - Duration::seconds(window.to_seconds() as i64);
+        if self.min_timestamp.map_or(true, |min| min >= start) {
+            return;
+        }
+
+        let (timestamps, values): (Vec<DateTime<Utc>>, Vec<f64>) = self
+            .timestamps
+            .iter()
+            .copied()
+            .zip(self.values.iter().copied())
+            .filter(|(t, _)| *t >= start)
+            .unzip();
+        self.min_timestamp = timestamps.iter().min().copied();
+        self.timestamps = timestamps;
+        self.values = values;
+    }
+
     fn add_value(&mut self, value: f64, timestamp: DateTime<Utc>) {
         self.values.push(value);
         self.timestamps.push(timestamp);
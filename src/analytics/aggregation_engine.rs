@@ -2,25 +2,27 @@
 //!
 //! High-performance aggregation of events into statistical measures across
 //! multiple time windows (1m, 5m, 15m, 1h, 6h, 1d, 1w, 1M).
+//!
+//! Windows follow event time (see [`super::windowing`]): a window is stored
+//! once the watermark of every source partition has passed its end, and late
+//! events within the allowed lateness upsert the stored aggregate again.
 
+use super::windowing::{Emission, EventTimeWindows, WindowingConfig, WindowingStats};
 use crate::database::Database;
 use crate::models::metrics::{StatisticalMeasures, TimeWindow};
 use crate::schemas::events::AnalyticsEvent;
-use anyhow::{Context, Result};
+use anyhow::Result;
 use chrono::{DateTime, Duration, Utc};
-use dashmap::DashMap;
+use parking_lot::Mutex;
 use std::collections::HashMap;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{debug, info, instrument};
+use tracing::{debug, info, instrument, warn};
 
 /// Aggregation engine for time-series data
 pub struct AggregationEngine {
     database: Arc<Database>,
     // Metric name + window -> Aggregated data
-    aggregates: Arc<DashMap<AggregateKey, WindowedAggregates>>,
-    // Active time buckets
-    active_buckets: Arc<RwLock<HashMap<TimeWindow, DateTime<Utc>>>>,
+    windows: Mutex<EventTimeWindows<AggregateKey, WindowedAggregates>>,
 }
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
@@ -33,34 +35,45 @@ struct AggregateKey {
 impl AggregationEngine {
     /// Create a new aggregation engine
     pub fn new(database: Arc<Database>) -> Self {
+        Self::with_windowing(database, WindowingConfig::default())
+    }
+
+    /// Create an aggregation engine with custom watermark and lateness settings
+    pub fn with_windowing(database: Arc<Database>, config: WindowingConfig) -> Self {
         Self {
             database,
-            aggregates: Arc::new(DashMap::new()),
-            active_buckets: Arc::new(RwLock::new(HashMap::new())),
+            windows: Mutex::new(EventTimeWindows::new(config)),
         }
     }
 
-    /// Process an event and update aggregations
+    /// Process an event read from a source partition and update
+    /// aggregations. Returns `false` if the event was too late for any of
+    /// its windows; such events belong on the side output when the late data
+    /// policy asks for one.
     #[instrument(skip(self, event))]
-    pub async fn process_event(&self, event: &AnalyticsEvent) -> Result<()> {
+    pub fn process_event(&self, partition: i32, event: &AnalyticsEvent) -> Result<bool> {
         // Extract numeric metrics from the event
         let metrics = self.extract_metrics(event)?;
 
+        let mut windows = self.windows.lock();
+        windows.observe(partition, event.common.timestamp, Utc::now());
+
+        let mut accepted = true;
         for (metric_name, value) in metrics {
             // Aggregate across all time windows
-            for window in Self::all_windows() {
-                self.update_aggregation(
+            for &window in Self::all_windows() {
+                accepted &= self.update_aggregation(
+                    &mut windows,
                     &metric_name,
                     value,
                     window,
                     event.common.timestamp,
                     &event.common.tags,
-                )
-                .await?;
+                );
             }
         }
 
-        Ok(())
+        Ok(accepted)
     }
 
     /// Extract numeric metrics from an event
@@ -82,70 +95,94 @@ impl AggregationEngine {
         Ok(metrics)
     }
 
-    /// Update aggregation for a specific metric and time window
-    async fn update_aggregation(
+    /// Update aggregation for a specific metric and time window, returning
+    /// `false` if the window is past its allowed lateness
+    fn update_aggregation(
         &self,
+        windows: &mut EventTimeWindows<AggregateKey, WindowedAggregates>,
         metric_name: &str,
         value: f64,
         window: TimeWindow,
         timestamp: DateTime<Utc>,
         tags: &HashMap<String, String>,
-    ) -> Result<()> {
-        let window_start = self.align_to_window(timestamp, window);
-        let tags_hash = self.hash_tags(tags);
-
+    ) -> bool {
         let key = AggregateKey {
             metric_name: metric_name.to_string(),
             window,
-            tags_hash,
+            tags_hash: self.hash_tags(tags),
         };
+        let size = Duration::seconds(window.to_seconds() as i64);
 
-        // Get or create windowed aggregates
-        let mut agg = self.aggregates
-            .entry(key.clone())
-            .or_insert_with(|| WindowedAggregates::new(window_start));
-
-        // Add value to current window
-        agg.add_value(value);
-
-        // Check if we need to flush this window
-        if timestamp >= agg.window_end() {
-            // Compute statistics and store
-            let measures = agg.compute_statistics();
-
-            // Store to database
-            let tags_json = serde_json::to_value(tags)?;
-            self.database
-                .store_aggregated_metric(
-                    metric_name,
-                    window,
-                    agg.window_start,
-                    &tags_json,
-                    &measures,
-                )
-                .await?;
-
-            debug!(
-                "Flushed {} window for {}: avg={:.2}, count={}",
-                window.as_str(),
-                metric_name,
-                measures.avg,
-                measures.count
-            );
+        match windows.assign(key, size, timestamp, None) {
+            Some(agg) => {
+                if agg.values.is_empty() {
+                    agg.tags = tags.clone();
+                }
+                agg.add_value(value);
+                true
+            }
+            None => false,
+        }
+    }
 
-            // Create new window
-            *agg = WindowedAggregates::new(window_start);
+    /// Store windows the watermark has closed, late updates to stored
+    /// windows and due early results. Returns the number of windows stored.
+    pub async fn fire(&self) -> Result<usize> {
+        let outputs = self.windows.lock().fire(Utc::now(), |_, agg| {
+            (agg.compute_statistics(), agg.tags.clone())
+        });
+
+        let mut stored = 0;
+        for output in outputs {
+            let (measures, tags) = &output.result;
+            let key = &output.key;
+            match self.store(key, output.window_start, tags, measures).await {
+                Ok(()) => {
+                    debug!(
+                        "Stored {} window for {} ({:?}): avg={:.2}, count={}",
+                        key.window.as_str(),
+                        key.metric_name,
+                        output.emission,
+                        measures.avg,
+                        measures.count
+                    );
+                    stored += 1;
+                }
+                Err(e) => {
+                    warn!("Failed to store {} window: {}", key.metric_name, e);
+                    // Late emissions are retried with the next firing
+                    if output.emission != Emission::Early {
+                        self.windows.lock().retry(key, output.window_start);
+                    }
+                }
+            }
         }
 
-        Ok(())
+        Ok(stored)
+    }
+
+    async fn store(
+        &self,
+        key: &AggregateKey,
+        window_start: DateTime<Utc>,
+        tags: &HashMap<String, String>,
+        measures: &StatisticalMeasures,
+    ) -> Result<()> {
+        let tags_json = serde_json::to_value(tags)?;
+        self.database
+            .store_aggregated_metric(
+                &key.metric_name,
+                key.window,
+                window_start,
+                &tags_json,
+                measures,
+            )
+            .await
     }
 
-    /// Align timestamp to window boundary
-    fn align_to_window(&self, timestamp: DateTime<Utc>, window: TimeWindow) -> DateTime<Utc> {
-        let seconds = window.to_seconds() as i64;
-        let ts = timestamp.timestamp();
-        let aligned = (ts / seconds) * seconds;
-        DateTime::from_timestamp(aligned, 0).unwrap_or(timestamp)
+    /// Windowing statistics
+    pub fn windowing_stats(&self) -> WindowingStats {
+        self.windows.lock().stats()
     }
 
     /// Hash tags for deduplication
@@ -205,31 +242,18 @@ impl AggregationEngine {
         Ok(stats)
     }
 
-    /// Force flush all pending aggregates
+    /// Force flush all pending aggregates, including open windows
     pub async fn flush_all(&self) -> Result<usize> {
-        let mut flushed = 0;
+        let outputs = self
+            .windows
+            .lock()
+            .flush_all(|_, agg| (agg.compute_statistics(), agg.tags.clone()));
 
-        for entry in self.aggregates.iter() {
-            let key = entry.key();
-            let agg = entry.value();
-
-            if agg.values.is_empty() {
-                continue;
-            }
-
-            let measures = agg.compute_statistics();
-            let tags_json = serde_json::json!({});
-
-            self.database
-                .store_aggregated_metric(
-                    &key.metric_name,
-                    key.window,
-                    agg.window_start,
-                    &tags_json,
-                    &measures,
-                )
+        let mut flushed = 0;
+        for output in outputs {
+            let (measures, tags) = &output.result;
+            self.store(&output.key, output.window_start, tags, measures)
                 .await?;
-
             flushed += 1;
         }
 
@@ -239,29 +263,17 @@ impl AggregationEngine {
 }
 
 /// Windowed aggregates for a metric
+#[derive(Default)]
 struct WindowedAggregates {
-    window_start: DateTime<Utc>,
-    window_duration: Duration,
     values: Vec<f64>,
+    tags: HashMap<String, String>,
 }
 
 impl WindowedAggregates {
-    fn new(window_start: DateTime<Utc>) -> Self {
-        Self {
-            window_start,
-            window_duration: Duration::minutes(1),
-            values: Vec::new(),
-        }
-    }
-
     fn add_value(&mut self, value: f64) {
         self.values.push(value);
     }
 
-    fn window_end(&self) -> DateTime<Utc> {
-        self.window_start + self.window_duration
-    }
-
     fn compute_statistics(&self) -> StatisticalMeasures {
         if self.values.is_empty() {
             return StatisticalMeasures::default();
@@ -328,7 +340,7 @@ mod tests {
 
     #[test]
     fn test_statistical_measures() {
-        let mut agg = WindowedAggregates::new(Utc::now());
+        let mut agg = WindowedAggregates::default();
         for i in 1..=10 {
             agg.add_value(i as f64);
         }
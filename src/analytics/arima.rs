@@ -0,0 +1,465 @@
+//! ARIMA / SARIMA Estimation
+//!
+//! Seasonal ARIMA(p,d,q)(P,D,Q)s models estimated by conditional sum of
+//! squares. AR and MA polynomials are parameterized through partial
+//! autocorrelations so every fitted model is stationary and invertible.
+//! Forecast standard errors come from the psi-weights of the full
+//! (differenced) model.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::prediction::ForecastStep;
+use super::stats::{nelder_mead, std_dev};
+
+/// Non-seasonal ARIMA order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArimaOrder {
+    /// Autoregressive order
+    pub p: usize,
+    /// Differencing order
+    pub d: usize,
+    /// Moving-average order
+    pub q: usize,
+}
+
+/// Seasonal ARIMA order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeasonalOrder {
+    /// Seasonal autoregressive order
+    pub p: usize,
+    /// Seasonal differencing order
+    pub d: usize,
+    /// Seasonal moving-average order
+    pub q: usize,
+    /// Season length in samples
+    pub period: usize,
+}
+
+/// Fitted (S)ARIMA model
+#[derive(Debug, Clone)]
+pub struct Arima {
+    order: ArimaOrder,
+    seasonal: Option<SeasonalOrder>,
+    ar: Vec<f64>,
+    ma: Vec<f64>,
+    seasonal_ar: Vec<f64>,
+    seasonal_ma: Vec<f64>,
+    mean: f64,
+    sigma2: f64,
+    aic: f64,
+    /// Original observations
+    history: Vec<f64>,
+    /// Innovations aligned with `history` (zero where not estimable)
+    residuals: Vec<f64>,
+}
+
+impl Arima {
+    /// Fit a model of the given order
+    pub fn fit(values: &[f64], order: ArimaOrder, seasonal: Option<SeasonalOrder>) -> Result<Self> {
+        if let Some(s) = seasonal {
+            if s.period < 2 {
+                anyhow::bail!("Seasonal period must be at least 2");
+            }
+        }
+
+        let (sp, sd, sq, period) = seasonal.map_or((0, 0, 0, 0), |s| (s.p, s.d, s.q, s.period));
+        let differenced = difference(values, order.d, sd, period);
+        let max_ar_lag = order.p + sp * period;
+        let n_params = order.p + order.q + sp + sq;
+
+        if differenced.len() < max_ar_lag + n_params + 8 {
+            anyhow::bail!(
+                "Insufficient data for ARIMA({},{},{}) fit ({} points)",
+                order.p,
+                order.d,
+                order.q,
+                values.len()
+            );
+        }
+
+        let include_mean = order.d + sd == 0;
+        let mean = if include_mean {
+            differenced.iter().sum::<f64>() / differenced.len() as f64
+        } else {
+            0.0
+        };
+        let centered: Vec<f64> = differenced.iter().map(|w| w - mean).collect();
+
+        let split = |x: &[f64]| {
+            let (ar, rest) = x.split_at(order.p);
+            let (ma, rest) = rest.split_at(order.q);
+            let (sar, sma) = rest.split_at(sp);
+            (
+                pacf_to_coefficients(ar),
+                negate(&pacf_to_coefficients(ma)),
+                pacf_to_coefficients(sar),
+                negate(&pacf_to_coefficients(sma)),
+            )
+        };
+
+        let (coefficients, sse) = if n_params == 0 {
+            (
+                Vec::new(),
+                css_residuals(&centered, &[], &[], &[], &[], period).0,
+            )
+        } else {
+            let objective = |x: &[f64]| {
+                let (ar, ma, sar, sma) = split(x);
+                css_residuals(&centered, &ar, &ma, &sar, &sma, period).0
+            };
+            nelder_mead(objective, &vec![0.1; n_params], 0.5, 200 * n_params, 1e-9)
+        };
+
+        if !sse.is_finite() {
+            anyhow::bail!("ARIMA estimation did not converge");
+        }
+
+        let (ar, ma, seasonal_ar, seasonal_ma) = split(&coefficients);
+        let (_, innovations) =
+            css_residuals(&centered, &ar, &ma, &seasonal_ar, &seasonal_ma, period);
+
+        let effective = innovations.len().saturating_sub(max_ar_lag).max(1);
+        let dof = effective.saturating_sub(n_params).max(1);
+        let sigma2 = sse / dof as f64;
+        let k = n_params + usize::from(include_mean) + 1;
+        let aic = effective as f64 * (sse / effective as f64).max(f64::MIN_POSITIVE).ln()
+            + 2.0 * k as f64;
+
+        // Align innovations with the original series
+        let offset = values.len() - innovations.len();
+        let mut residuals = vec![0.0; offset];
+        residuals.extend(innovations);
+
+        Ok(Self {
+            order,
+            seasonal,
+            ar,
+            ma,
+            seasonal_ar,
+            seasonal_ma,
+            mean,
+            sigma2,
+            aic,
+            history: values.to_vec(),
+            residuals,
+        })
+    }
+
+    /// Select differencing by the minimum-variance rule, then pick the
+    /// ARMA orders with the lowest AIC
+    pub fn auto_fit(values: &[f64], period: Option<usize>) -> Result<Self> {
+        let period = period.filter(|&s| s >= 2 && values.len() >= 4 * s);
+
+        let seasonal_d = match period {
+            Some(s) if std_dev(&difference(values, 0, 1, s)) < std_dev(values) => 1,
+            _ => 0,
+        };
+
+        let base = difference(values, 0, seasonal_d, period.unwrap_or(0));
+        let mut d = 0;
+        let mut best_std = std_dev(&base);
+        for candidate in 1..=2 {
+            let diffed = difference(&base, candidate, 0, 0);
+            if diffed.len() < 8 {
+                break;
+            }
+            let s = std_dev(&diffed);
+            if s < best_std {
+                best_std = s;
+                d = candidate;
+            } else {
+                break;
+            }
+        }
+
+        let seasonal_orders: Vec<(usize, usize)> = if period.is_some() {
+            vec![(0, 0), (1, 0), (0, 1), (1, 1)]
+        } else {
+            vec![(0, 0)]
+        };
+
+        let mut best: Option<Self> = None;
+        for p in 0..=2 {
+            for q in 0..=2 {
+                for &(sp, sq) in &seasonal_orders {
+                    let seasonal = period.map(|s| SeasonalOrder {
+                        p: sp,
+                        d: seasonal_d,
+                        q: sq,
+                        period: s,
+                    });
+                    if let Ok(model) = Self::fit(values, ArimaOrder { p, d, q }, seasonal) {
+                        let improves = match &best {
+                            Some(current) => model.aic < current.aic,
+                            None => true,
+                        };
+                        if improves {
+                            best = Some(model);
+                        }
+                    }
+                }
+            }
+        }
+
+        best.ok_or_else(|| anyhow::anyhow!("No ARIMA model could be fitted"))
+    }
+
+    /// Forecast `steps` periods ahead with their standard errors
+    pub fn forecast(&self, steps: usize) -> Vec<ForecastStep> {
+        let period = self.seasonal.map_or(0, |s| s.period);
+        let seasonal_d = self.seasonal.map_or(0, |s| s.d);
+
+        // Full AR polynomial including differencing: A(B) = phi(B) Phi(B^s) (1-B)^d (1-B^s)^D
+        let mut ar_poly = multiply(
+            &lag_polynomial(&self.ar, 1),
+            &lag_polynomial(&self.seasonal_ar, period),
+        );
+        for _ in 0..self.order.d {
+            ar_poly = multiply(&ar_poly, &[1.0, -1.0]);
+        }
+        for _ in 0..seasonal_d {
+            let mut seasonal_diff = vec![0.0; period + 1];
+            seasonal_diff[0] = 1.0;
+            seasonal_diff[period] = -1.0;
+            ar_poly = multiply(&ar_poly, &seasonal_diff);
+        }
+        let ma_poly = multiply(
+            &lag_polynomial(&negate(&self.ma), 1),
+            &lag_polynomial(&negate(&self.seasonal_ma), period),
+        );
+
+        let mut y: Vec<f64> = self.history.iter().map(|v| v - self.mean).collect();
+        let mut e = self.residuals.clone();
+        let n = y.len();
+
+        // psi-weights of M(B) / A(B)
+        let mut psi = vec![1.0];
+        for j in 1..steps {
+            let mut value = ma_poly.get(j).copied().unwrap_or(0.0);
+            for k in 1..=j.min(ar_poly.len() - 1) {
+                value -= ar_poly[k] * psi[j - k];
+            }
+            psi.push(value);
+        }
+
+        let mut forecasts = Vec::with_capacity(steps);
+        let mut cumulative = 0.0;
+        for (h, weight) in psi.iter().take(steps).enumerate() {
+            let t = n + h;
+            let mut value = 0.0;
+            for (k, a) in ar_poly.iter().enumerate().skip(1) {
+                if t >= k {
+                    value -= a * y[t - k];
+                }
+            }
+            for (k, m) in ma_poly.iter().enumerate().skip(1) {
+                if t >= k {
+                    value += m * e[t - k];
+                }
+            }
+            y.push(value);
+            e.push(0.0);
+
+            cumulative += weight * weight;
+            forecasts.push(ForecastStep {
+                value: value + self.mean,
+                std_error: (self.sigma2 * cumulative).sqrt(),
+            });
+        }
+
+        forecasts
+    }
+
+    /// Non-seasonal order
+    pub fn order(&self) -> ArimaOrder {
+        self.order
+    }
+
+    /// Seasonal order, if any
+    pub fn seasonal_order(&self) -> Option<SeasonalOrder> {
+        self.seasonal
+    }
+
+    /// Akaike information criterion of the CSS fit
+    pub fn aic(&self) -> f64 {
+        self.aic
+    }
+
+    /// Innovation variance
+    pub fn sigma2(&self) -> f64 {
+        self.sigma2
+    }
+}
+
+/// Apply `d` regular and `seasonal_d` seasonal differences
+fn difference(values: &[f64], d: usize, seasonal_d: usize, period: usize) -> Vec<f64> {
+    let mut out = values.to_vec();
+    for _ in 0..seasonal_d {
+        if out.len() <= period {
+            return Vec::new();
+        }
+        out = (period..out.len())
+            .map(|t| out[t] - out[t - period])
+            .collect();
+    }
+    for _ in 0..d {
+        if out.len() < 2 {
+            return Vec::new();
+        }
+        out = out.windows(2).map(|w| w[1] - w[0]).collect();
+    }
+    out
+}
+
+/// Map values in the real line to a stationary AR coefficient vector via
+/// the Durbin-Levinson recursion on tanh-bounded partial autocorrelations
+fn pacf_to_coefficients(x: &[f64]) -> Vec<f64> {
+    let mut phi: Vec<f64> = Vec::with_capacity(x.len());
+    for (k, &raw) in x.iter().enumerate() {
+        let r = raw.tanh();
+        let prev = phi.clone();
+        for j in 0..k {
+            phi[j] = prev[j] - r * prev[k - 1 - j];
+        }
+        phi.push(r);
+    }
+    phi
+}
+
+fn negate(values: &[f64]) -> Vec<f64> {
+    values.iter().map(|v| -v).collect()
+}
+
+/// Lag polynomial 1 - c1 B^s - c2 B^2s - ...
+fn lag_polynomial(coefficients: &[f64], spacing: usize) -> Vec<f64> {
+    let mut poly = vec![0.0; coefficients.len() * spacing + 1];
+    poly[0] = 1.0;
+    for (i, c) in coefficients.iter().enumerate() {
+        poly[(i + 1) * spacing] = -c;
+    }
+    poly
+}
+
+fn multiply(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut out = vec![0.0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            out[i + j] += x * y;
+        }
+    }
+    out
+}
+
+/// Conditional residuals of an ARMA model on a centered series.
+///
+/// Returns the sum of squares over the estimable range together with all
+/// residuals (zero before the first estimable index).
+fn css_residuals(
+    w: &[f64],
+    ar: &[f64],
+    ma: &[f64],
+    seasonal_ar: &[f64],
+    seasonal_ma: &[f64],
+    period: usize,
+) -> (f64, Vec<f64>) {
+    let ar_poly = multiply(
+        &lag_polynomial(ar, 1),
+        &lag_polynomial(seasonal_ar, period.max(1)),
+    );
+    let ma_poly = multiply(
+        &lag_polynomial(&negate(ma), 1),
+        &lag_polynomial(&negate(seasonal_ma), period.max(1)),
+    );
+    let start = ar_poly.len() - 1;
+
+    let mut e = vec![0.0; w.len()];
+    let mut sse = 0.0;
+    for t in start..w.len() {
+        let mut value: f64 = ar_poly.iter().enumerate().map(|(k, a)| a * w[t - k]).sum();
+        for (k, m) in ma_poly.iter().enumerate().skip(1) {
+            if t >= k {
+                value -= m * e[t - k];
+            }
+        }
+        e[t] = value;
+        sse += value * value;
+    }
+
+    (sse, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random noise in [-0.5, 0.5)
+    fn noise(n: usize) -> Vec<f64> {
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        (0..n)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state % 10_000) as f64 / 10_000.0 - 0.5
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pacf_transform_is_stationary() {
+        let phi = pacf_to_coefficients(&[5.0, -5.0]);
+        // AR(2) stationarity triangle
+        assert!(phi[1].abs() < 1.0);
+        assert!(phi[0] + phi[1] < 1.0);
+        assert!(phi[1] - phi[0] < 1.0);
+    }
+
+    #[test]
+    fn test_recovers_ar1_coefficient() {
+        let eps = noise(400);
+        let mut y = vec![0.0; 400];
+        for t in 1..400 {
+            y[t] = 0.7 * y[t - 1] + eps[t];
+        }
+        let model = Arima::fit(&y, ArimaOrder { p: 1, d: 0, q: 0 }, None).unwrap();
+        assert!((model.ar[0] - 0.7).abs() < 0.1, "phi = {}", model.ar[0]);
+    }
+
+    #[test]
+    fn test_random_walk_intervals_grow_with_sqrt_horizon() {
+        let eps = noise(200);
+        let mut y = vec![10.0; 200];
+        for t in 1..200 {
+            y[t] = y[t - 1] + eps[t];
+        }
+        let model = Arima::fit(&y, ArimaOrder { p: 0, d: 1, q: 0 }, None).unwrap();
+        let forecast = model.forecast(4);
+
+        assert!((forecast[0].value - y[199]).abs() < 1e-9);
+        let ratio = forecast[3].std_error / forecast[0].std_error;
+        assert!((ratio - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_seasonal_forecast_repeats_pattern() {
+        let period = 12;
+        let eps = noise(144);
+        let y: Vec<f64> = (0..144)
+            .map(|t| 50.0 + 5.0 * ((t % period) as f64 - 6.0).abs() + 0.2 * eps[t])
+            .collect();
+        let model = Arima::auto_fit(&y, Some(period)).unwrap();
+        let forecast = model.forecast(period);
+
+        for (h, step) in forecast.iter().enumerate() {
+            let expected = 50.0 + 5.0 * (((144 + h) % period) as f64 - 6.0).abs();
+            assert!(
+                (step.value - expected).abs() < 1.5,
+                "h={} {} vs {}",
+                h + 1,
+                step.value,
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,381 @@
+//! Forecast Backtesting
+//!
+//! Rolling-origin evaluation of the forecasting methods and automatic model
+//! selection per metric. Each origin trains on the history up to that point
+//! and scores the next `horizon` observations.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use super::arima::Arima;
+use super::holt_winters::{HoltWinters, Seasonality};
+use super::prediction::ForecastStep;
+use super::stats::{self, logistic, logit, nelder_mead};
+
+/// Forecasting methods available for backtesting and selection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ForecastMethod {
+    MovingAverage,
+    ExponentialSmoothing,
+    HoltWinters,
+    Arima,
+}
+
+impl ForecastMethod {
+    /// All methods, in the order they are evaluated
+    pub const ALL: [ForecastMethod; 4] = [
+        ForecastMethod::MovingAverage,
+        ForecastMethod::ExponentialSmoothing,
+        ForecastMethod::HoltWinters,
+        ForecastMethod::Arima,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ForecastMethod::MovingAverage => "moving_average",
+            ForecastMethod::ExponentialSmoothing => "exponential_smoothing",
+            ForecastMethod::HoltWinters => "holt_winters",
+            ForecastMethod::Arima => "arima",
+        }
+    }
+
+    /// Fit the method on `history` and forecast `steps` periods ahead.
+    ///
+    /// `seasonal_period` is the season length in samples; it also sets the
+    /// moving-average window (24 samples when unset).
+    pub fn forecast(
+        &self,
+        history: &[f64],
+        steps: usize,
+        seasonal_period: Option<usize>,
+    ) -> Result<Vec<ForecastStep>> {
+        if history.len() < 4 {
+            anyhow::bail!("Insufficient data for {} forecast", self.as_str());
+        }
+
+        match self {
+            ForecastMethod::MovingAverage => {
+                let window = seasonal_period.unwrap_or(24).clamp(2, history.len());
+                let recent = &history[history.len() - window..];
+                let value = stats::mean(recent);
+                // Variance of a new observation around the window mean
+                let std_error = stats::std_dev(recent) * (1.0 + 1.0 / window as f64).sqrt();
+                Ok(vec![ForecastStep { value, std_error }; steps])
+            }
+            ForecastMethod::ExponentialSmoothing => {
+                Ok(simple_exponential_smoothing(history, steps))
+            }
+            ForecastMethod::HoltWinters => {
+                let model = match seasonal_period {
+                    Some(period) if history.len() >= 2 * period + 2 => {
+                        HoltWinters::fit(history, Seasonality::Additive, period)?
+                    }
+                    _ => HoltWinters::fit(history, Seasonality::None, 0)?,
+                };
+                Ok(model.forecast(steps))
+            }
+            ForecastMethod::Arima => Ok(Arima::auto_fit(history, seasonal_period)?.forecast(steps)),
+        }
+    }
+}
+
+impl fmt::Display for ForecastMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ForecastMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ForecastMethod::ALL
+            .iter()
+            .copied()
+            .find(|m| m.as_str() == s)
+            .ok_or_else(|| anyhow::anyhow!("Unknown forecast method: {}", s))
+    }
+}
+
+/// Simple exponential smoothing with alpha fitted on one-step squared error
+fn simple_exponential_smoothing(history: &[f64], steps: usize) -> Vec<ForecastStep> {
+    let run = |alpha: f64| {
+        let mut level = history[0];
+        let mut sse = 0.0;
+        for &y in &history[1..] {
+            sse += (y - level).powi(2);
+            level = alpha * y + (1.0 - alpha) * level;
+        }
+        (level, sse)
+    };
+
+    let (x, _) = nelder_mead(|x| run(logistic(x[0])).1, &[logit(0.3)], 1.0, 200, 1e-10);
+    let alpha = logistic(x[0]);
+    let (level, sse) = run(alpha);
+    let sigma2 = sse / (history.len() - 2).max(1) as f64;
+
+    // ETS(A,N,N): Var(h) = sigma^2 * (1 + (h - 1) * alpha^2)
+    (1..=steps)
+        .map(|h| ForecastStep {
+            value: level,
+            std_error: (sigma2 * (1.0 + (h - 1) as f64 * alpha * alpha)).sqrt(),
+        })
+        .collect()
+}
+
+/// Backtest configuration
+#[derive(Debug, Clone)]
+pub struct BacktestConfig {
+    /// Forecast horizon scored at each origin
+    pub horizon: usize,
+    /// Minimum number of training points before the first origin
+    pub min_train_size: usize,
+    /// Distance between consecutive origins
+    pub step: usize,
+    /// Maximum number of origins (the most recent ones are used)
+    pub max_origins: usize,
+    /// Nominal prediction interval coverage
+    pub confidence_level: f64,
+    /// Season length in samples
+    pub seasonal_period: Option<usize>,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self {
+            horizon: 12,
+            min_train_size: 48,
+            step: 6,
+            max_origins: 8,
+            confidence_level: 0.95,
+            seasonal_period: None,
+        }
+    }
+}
+
+/// Accuracy of one method over all backtest origins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BacktestResult {
+    pub method: ForecastMethod,
+    /// Number of origins evaluated
+    pub origins: usize,
+    /// Number of scored forecast points
+    pub forecasts: usize,
+    /// Mean absolute error
+    pub mae: f64,
+    /// Mean absolute percentage error (None when all actuals are zero)
+    pub mape: Option<f64>,
+    /// Symmetric mean absolute percentage error (0-200)
+    pub smape: f64,
+    /// Fraction of actuals inside the prediction interval
+    pub coverage: f64,
+}
+
+/// Backtest results for a metric and the selected method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelSelection {
+    pub metric_name: String,
+    pub selected: ForecastMethod,
+    pub confidence_level: f64,
+    pub results: Vec<BacktestResult>,
+    pub evaluated_at: DateTime<Utc>,
+}
+
+impl ModelSelection {
+    /// Result of the selected method
+    pub fn selected_result(&self) -> Option<&BacktestResult> {
+        self.results.iter().find(|r| r.method == self.selected)
+    }
+}
+
+/// Coverage shortfall below which a method's intervals are considered unreliable
+const MAX_COVERAGE_SHORTFALL: f64 = 0.2;
+
+/// Origins (training lengths) for a series of `len` points, oldest first
+fn origins(len: usize, config: &BacktestConfig) -> Vec<usize> {
+    if config.horizon == 0 || len < config.min_train_size + config.horizon {
+        return Vec::new();
+    }
+
+    let mut origins: Vec<usize> = (0..config.max_origins)
+        .map_while(|i| (len - config.horizon).checked_sub(i * config.step.max(1)))
+        .filter(|&origin| origin >= config.min_train_size)
+        .collect();
+    origins.reverse();
+    origins
+}
+
+/// Run a rolling-origin backtest of one method
+pub fn backtest(
+    values: &[f64],
+    method: ForecastMethod,
+    config: &BacktestConfig,
+) -> Result<BacktestResult> {
+    let origins = origins(values.len(), config);
+    if origins.is_empty() {
+        anyhow::bail!(
+            "Insufficient data for backtesting (need at least {} points)",
+            config.min_train_size + config.horizon
+        );
+    }
+
+    let z = stats::normal_z(config.confidence_level);
+    let mut abs_errors = Vec::new();
+    let mut pct_errors = Vec::new();
+    let mut sym_errors = Vec::new();
+    let mut covered = 0usize;
+
+    for &origin in &origins {
+        let forecast =
+            method.forecast(&values[..origin], config.horizon, config.seasonal_period)?;
+
+        for (step, &actual) in forecast
+            .iter()
+            .zip(&values[origin..origin + config.horizon])
+        {
+            let error = (actual - step.value).abs();
+            abs_errors.push(error);
+
+            if actual != 0.0 {
+                pct_errors.push(100.0 * error / actual.abs());
+            }
+
+            let denominator = actual.abs() + step.value.abs();
+            sym_errors.push(if denominator > 0.0 {
+                200.0 * error / denominator
+            } else {
+                0.0
+            });
+
+            if (actual - step.value).abs() <= z * step.std_error {
+                covered += 1;
+            }
+        }
+    }
+
+    Ok(BacktestResult {
+        method,
+        origins: origins.len(),
+        forecasts: abs_errors.len(),
+        mae: stats::mean(&abs_errors),
+        mape: (!pct_errors.is_empty()).then(|| stats::mean(&pct_errors)),
+        smape: stats::mean(&sym_errors),
+        coverage: covered as f64 / abs_errors.len() as f64,
+    })
+}
+
+/// Backtest every method and select the most accurate one.
+///
+/// Methods are ranked by sMAPE. Methods whose interval coverage falls well
+/// short of the nominal level are only chosen when no better-calibrated
+/// method is available.
+pub fn select_model(
+    metric_name: &str,
+    values: &[f64],
+    config: &BacktestConfig,
+) -> Result<ModelSelection> {
+    let results: Vec<BacktestResult> = ForecastMethod::ALL
+        .iter()
+        .filter_map(|&method| backtest(values, method, config).ok())
+        .collect();
+
+    let by_smape = |a: &&BacktestResult, b: &&BacktestResult| a.smape.total_cmp(&b.smape);
+    let calibrated = results
+        .iter()
+        .filter(|r| r.coverage >= config.confidence_level - MAX_COVERAGE_SHORTFALL)
+        .min_by(by_smape);
+
+    let selected = calibrated
+        .or_else(|| results.iter().min_by(by_smape))
+        .map(|r| r.method)
+        .ok_or_else(|| {
+            anyhow::anyhow!("No forecast method could be backtested for {}", metric_name)
+        })?;
+
+    Ok(ModelSelection {
+        metric_name: metric_name.to_string(),
+        selected,
+        confidence_level: config.confidence_level,
+        results,
+        evaluated_at: Utc::now(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seasonal_series(n: usize) -> Vec<f64> {
+        (0..n)
+            .map(|t| {
+                let phase = (t % 24) as f64 / 24.0 * std::f64::consts::TAU;
+                500.0 + 2.0 * t as f64 + 80.0 * phase.sin() + ((t * 37) % 11) as f64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_origins_are_spaced_and_bounded() {
+        let config = BacktestConfig {
+            horizon: 10,
+            min_train_size: 50,
+            step: 5,
+            max_origins: 4,
+            ..Default::default()
+        };
+        assert_eq!(origins(100, &config), vec![75, 80, 85, 90]);
+        assert!(origins(55, &config).is_empty());
+    }
+
+    #[test]
+    fn test_backtest_metrics_for_constant_series() {
+        let values = vec![10.0; 80];
+        let result = backtest(
+            &values,
+            ForecastMethod::MovingAverage,
+            &BacktestConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(result.mae, 0.0);
+        assert_eq!(result.smape, 0.0);
+        assert_eq!(result.mape, Some(0.0));
+        assert_eq!(result.forecasts, result.origins * 12);
+    }
+
+    #[test]
+    fn test_selection_prefers_seasonal_models() {
+        let values = seasonal_series(168);
+        let config = BacktestConfig {
+            seasonal_period: Some(24),
+            horizon: 24,
+            min_train_size: 96,
+            step: 12,
+            max_origins: 3,
+            ..Default::default()
+        };
+        let selection = select_model("requests_per_hour", &values, &config).unwrap();
+
+        assert_eq!(selection.results.len(), ForecastMethod::ALL.len());
+        assert!(matches!(
+            selection.selected,
+            ForecastMethod::HoltWinters | ForecastMethod::Arima
+        ));
+        let moving_average = selection
+            .results
+            .iter()
+            .find(|r| r.method == ForecastMethod::MovingAverage)
+            .unwrap();
+        assert!(selection.selected_result().unwrap().smape < moving_average.smape);
+    }
+
+    #[test]
+    fn test_method_round_trips_through_str() {
+        for method in ForecastMethod::ALL {
+            assert_eq!(method.as_str().parse::<ForecastMethod>().unwrap(), method);
+        }
+    }
+}
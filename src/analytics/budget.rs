@@ -0,0 +1,616 @@
+//! Budget Burn-Rate Forecasting
+//!
+//! Tracks spend per budget scope (team / project) from token and API cost
+//! events, projects spend to the end of the budget period and estimates when
+//! the budget will be exhausted. Forecasted overruns are emitted as
+//! `BudgetAlertType::ForecastedOverrun` events ahead of the actual breach.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{ContinuousCDF, Normal};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use super::stats;
+use crate::adapters::costops::BudgetStatus;
+use crate::schemas::events::{
+    AnalyticsEvent, BudgetAlertEvent, BudgetAlertType, CommonEventFields, CostPayload,
+    EventPayload, EventType, Severity, SourceModule, SCHEMA_VERSION,
+};
+
+/// Tag keys identifying the team that incurred a cost
+const TEAM_TAGS: [&str; 2] = ["team", "team_id"];
+
+/// Tag keys identifying the project that incurred a cost
+const PROJECT_TAGS: [&str; 2] = ["project", "project_id"];
+
+/// Burn-rate engine configuration
+#[derive(Debug, Clone)]
+pub struct BurnRateConfig {
+    /// Spend bucket size used to estimate the burn rate
+    pub bucket: Duration,
+    /// History used to estimate the burn rate
+    pub lookback: Duration,
+    /// Minimum number of completed buckets before projecting
+    pub min_buckets: usize,
+    /// Confidence level of projection bands
+    pub confidence_level: f64,
+    /// Overrun probability at which a forecasted-overrun alert is raised
+    pub alert_probability: f64,
+    /// Minimum time between alerts for the same budget
+    pub alert_cooldown: Duration,
+}
+
+impl Default for BurnRateConfig {
+    fn default() -> Self {
+        Self {
+            bucket: Duration::hours(1),
+            lookback: Duration::days(7),
+            min_buckets: 6,
+            confidence_level: 0.90,
+            alert_probability: 0.8,
+            alert_cooldown: Duration::hours(24),
+        }
+    }
+}
+
+/// Team / project a budget applies to; `None` matches any value
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BudgetScope {
+    pub team: Option<String>,
+    pub project: Option<String>,
+}
+
+impl BudgetScope {
+    /// Whether an event with the given tags falls within this scope
+    pub fn matches(&self, tags: &HashMap<String, String>) -> bool {
+        let tag_matches = |expected: &Option<String>, keys: &[&str]| match expected {
+            Some(expected) => keys.iter().any(|k| tags.get(*k) == Some(expected)),
+            None => true,
+        };
+
+        tag_matches(&self.team, &TEAM_TAGS) && tag_matches(&self.project, &PROJECT_TAGS)
+    }
+}
+
+/// Budget definition for a period
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Budget {
+    pub budget_id: String,
+    pub budget_name: String,
+    pub scope: BudgetScope,
+    pub limit_usd: f64,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    /// Spend already incurred before `tracked_since`
+    pub opening_spend_usd: f64,
+    /// Only events after this time are counted
+    pub tracked_since: DateTime<Utc>,
+}
+
+impl Budget {
+    /// Build a budget from a CostOps status snapshot taken at `synced_at`.
+    ///
+    /// Spend reported by CostOps becomes the opening balance and only cost
+    /// events after the snapshot are added to it.
+    pub fn from_status(
+        status: &BudgetStatus,
+        budget_name: &str,
+        project: Option<String>,
+        period_start: DateTime<Utc>,
+        period_end: DateTime<Utc>,
+        synced_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            budget_id: status.budget_id.clone(),
+            budget_name: budget_name.to_string(),
+            scope: BudgetScope {
+                team: status.team_id.clone(),
+                project,
+            },
+            limit_usd: status.period_budget_usd,
+            period_start,
+            period_end,
+            opening_spend_usd: status.spent_usd,
+            tracked_since: synced_at,
+        }
+    }
+}
+
+/// Spend projection for a budget
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BudgetProjection {
+    pub budget_id: String,
+    pub computed_at: DateTime<Utc>,
+    pub limit_usd: f64,
+    pub spent_usd: f64,
+    /// Mean spend per hour over the lookback window
+    pub hourly_burn_rate_usd: f64,
+    /// Expected total spend at period end
+    pub projected_spend_usd: f64,
+    pub projected_spend_lower_usd: f64,
+    pub projected_spend_upper_usd: f64,
+    /// Probability that spend exceeds the limit by period end
+    pub overrun_probability: f64,
+    /// Expected exhaustion time (None if the burn rate is zero)
+    pub exhaustion_at: Option<DateTime<Utc>>,
+    /// Earliest plausible exhaustion time (upper spend band)
+    pub exhaustion_earliest: Option<DateTime<Utc>>,
+    /// Latest plausible exhaustion time (lower spend band; None if never reached)
+    pub exhaustion_latest: Option<DateTime<Utc>>,
+    pub confidence_level: f64,
+}
+
+impl BudgetProjection {
+    /// Whether the budget is expected to run out before the period ends
+    pub fn exhausts_before(&self, period_end: DateTime<Utc>) -> bool {
+        self.exhaustion_at.is_some_and(|at| at < period_end)
+    }
+}
+
+/// Per-budget tracking state
+struct BudgetState {
+    budget: Budget,
+    /// Bucket start -> spend in that bucket
+    buckets: BTreeMap<DateTime<Utc>, f64>,
+    last_alert_at: Option<DateTime<Utc>>,
+}
+
+/// Burn-rate forecasting engine
+pub struct BurnRateEngine {
+    config: BurnRateConfig,
+    // Budget ID -> Tracking state
+    budgets: Arc<DashMap<String, BudgetState>>,
+}
+
+impl BurnRateEngine {
+    /// Create a new burn-rate engine
+    pub fn new(config: BurnRateConfig) -> Self {
+        Self {
+            config,
+            budgets: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Register or replace a budget. Spend already tracked for the same
+    /// budget ID is kept when the period is unchanged.
+    pub fn upsert_budget(&self, budget: Budget) {
+        info!(budget_id = %budget.budget_id, limit = budget.limit_usd, "Tracking budget");
+
+        if let Some(mut state) = self.budgets.get_mut(&budget.budget_id) {
+            if state.budget.period_start == budget.period_start {
+                // Events after the new snapshot are counted on top of its opening balance
+                let since = budget.tracked_since;
+                state.buckets.retain(|start, _| *start >= since);
+                state.budget = budget;
+                return;
+            }
+        }
+
+        self.budgets.insert(
+            budget.budget_id.clone(),
+            BudgetState {
+                budget,
+                buckets: BTreeMap::new(),
+                last_alert_at: None,
+            },
+        );
+    }
+
+    /// Stop tracking a budget
+    pub fn remove_budget(&self, budget_id: &str) {
+        self.budgets.remove(budget_id);
+    }
+
+    /// Record a cost event and return any forecasted-overrun alerts it triggers
+    pub fn record_event(&self, event: &AnalyticsEvent) -> Vec<AnalyticsEvent> {
+        let cost = match &event.payload {
+            EventPayload::Cost(CostPayload::TokenCost(cost)) => cost.total_cost_usd,
+            EventPayload::Cost(CostPayload::ApiCost(cost)) => cost.total_cost_usd,
+            _ => return Vec::new(),
+        };
+
+        let timestamp = event.common.timestamp;
+        let bucket = self.bucket_start(timestamp);
+        let mut alerts = Vec::new();
+
+        for mut state in self.budgets.iter_mut() {
+            let budget = &state.budget;
+            if timestamp <= budget.tracked_since
+                || timestamp < budget.period_start
+                || timestamp >= budget.period_end
+                || !budget.scope.matches(&event.common.tags)
+            {
+                continue;
+            }
+
+            *state.buckets.entry(bucket).or_insert(0.0) += cost;
+
+            if let Some(alert) = self.evaluate_state(&mut state, timestamp) {
+                alerts.push(alert);
+            }
+        }
+
+        alerts
+    }
+
+    /// Re-evaluate every budget at `now` (e.g. on a timer when no events arrive)
+    pub fn evaluate_all(&self, now: DateTime<Utc>) -> Vec<AnalyticsEvent> {
+        self.budgets
+            .iter_mut()
+            .filter_map(|mut state| self.evaluate_state(&mut state, now))
+            .collect()
+    }
+
+    /// Project spend for a budget at `now`
+    pub fn project(&self, budget_id: &str, now: DateTime<Utc>) -> Result<BudgetProjection> {
+        let state = self
+            .budgets
+            .get(budget_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown budget: {}", budget_id))?;
+
+        self.project_state(&state, now)
+    }
+
+    /// Projections for all budgets with enough history
+    pub fn project_all(&self, now: DateTime<Utc>) -> Vec<BudgetProjection> {
+        self.budgets
+            .iter()
+            .filter_map(|state| self.project_state(&state, now).ok())
+            .collect()
+    }
+
+    fn bucket_start(&self, timestamp: DateTime<Utc>) -> DateTime<Utc> {
+        let bucket_ms = self.config.bucket.num_milliseconds().max(1);
+        let ms = timestamp.timestamp_millis();
+        DateTime::from_timestamp_millis(ms - ms.rem_euclid(bucket_ms)).unwrap_or(timestamp)
+    }
+
+    fn project_state(&self, state: &BudgetState, now: DateTime<Utc>) -> Result<BudgetProjection> {
+        let budget = &state.budget;
+        let bucket = self.config.bucket;
+        let bucket_secs = bucket.num_seconds().max(1) as f64;
+
+        // Completed buckets in the lookback window, including empty ones
+        let tracking_start = budget.tracked_since.max(budget.period_start);
+        let window_start = self
+            .bucket_start(tracking_start.max(now - self.config.lookback))
+            .max(self.bucket_start(tracking_start));
+        let current_bucket = self.bucket_start(now);
+
+        let mut samples = Vec::new();
+        let mut start = window_start;
+        while start < current_bucket {
+            samples.push(state.buckets.get(&start).copied().unwrap_or(0.0));
+            start += bucket;
+        }
+
+        if samples.len() < self.config.min_buckets {
+            anyhow::bail!(
+                "Insufficient history for budget {} ({} of {} buckets)",
+                budget.budget_id,
+                samples.len(),
+                self.config.min_buckets
+            );
+        }
+
+        let spent = budget.opening_spend_usd + state.buckets.values().sum::<f64>();
+        let rate = stats::mean(&samples);
+        let variance = stats::variance(&samples);
+        let n = samples.len() as f64;
+
+        // Spend over `t` future buckets: mean t*rate, variance t*var + t^2*var/n
+        // (bucket noise plus uncertainty in the estimated rate)
+        let spend_sd = |t: f64| (t * variance + t * t * variance / n).sqrt();
+
+        let remaining_buckets =
+            ((budget.period_end - now).num_seconds().max(0) as f64) / bucket_secs;
+        let z = stats::normal_z(self.config.confidence_level);
+        let expected_additional = remaining_buckets * rate;
+        let sd = spend_sd(remaining_buckets);
+        let remaining_budget = budget.limit_usd - spent;
+
+        let overrun_probability = if remaining_budget <= 0.0 {
+            1.0
+        } else if sd > 0.0 {
+            let normal = Normal::new(0.0, 1.0).expect("standard normal is valid");
+            1.0 - normal.cdf((remaining_budget - expected_additional) / sd)
+        } else if expected_additional > remaining_budget {
+            1.0
+        } else {
+            0.0
+        };
+
+        let to_time = |t: f64| now + Duration::milliseconds((t * bucket_secs * 1000.0) as i64);
+        let (exhaustion_at, earliest, latest) = if remaining_budget <= 0.0 {
+            (Some(now), Some(now), Some(now))
+        } else {
+            let expected = (rate > 0.0).then(|| to_time(remaining_budget / rate));
+            // Search far enough past the period end to find late crossings
+            let horizon = 10.0
+                * remaining_buckets
+                    .max(remaining_budget / rate.max(f64::MIN_POSITIVE))
+                    .min(1e7);
+            let earliest =
+                first_crossing(|t| t * rate + z * spend_sd(t), remaining_budget, horizon);
+            let latest = first_crossing(|t| t * rate - z * spend_sd(t), remaining_budget, horizon);
+            (expected, earliest.map(to_time), latest.map(to_time))
+        };
+
+        debug!(
+            budget_id = %budget.budget_id,
+            spent,
+            rate,
+            overrun_probability,
+            "Projected budget spend"
+        );
+
+        Ok(BudgetProjection {
+            budget_id: budget.budget_id.clone(),
+            computed_at: now,
+            limit_usd: budget.limit_usd,
+            spent_usd: spent,
+            hourly_burn_rate_usd: rate * 3600.0 / bucket_secs,
+            projected_spend_usd: spent + expected_additional,
+            projected_spend_lower_usd: spent + (expected_additional - z * sd).max(0.0),
+            projected_spend_upper_usd: spent + expected_additional + z * sd,
+            overrun_probability,
+            exhaustion_at,
+            exhaustion_earliest: earliest,
+            exhaustion_latest: latest,
+            confidence_level: self.config.confidence_level,
+        })
+    }
+
+    /// Raise a forecasted-overrun alert if the projection warrants one
+    fn evaluate_state(
+        &self,
+        state: &mut BudgetState,
+        now: DateTime<Utc>,
+    ) -> Option<AnalyticsEvent> {
+        let projection = self.project_state(state, now).ok()?;
+        let budget = &state.budget;
+
+        let already_exceeded = projection.spent_usd >= budget.limit_usd;
+        let in_cooldown = state
+            .last_alert_at
+            .is_some_and(|at| now - at < self.config.alert_cooldown);
+
+        if already_exceeded
+            || in_cooldown
+            || projection.overrun_probability < self.config.alert_probability
+            || !projection.exhausts_before(budget.period_end)
+        {
+            return None;
+        }
+
+        state.last_alert_at = Some(now);
+        info!(
+            budget_id = %budget.budget_id,
+            exhaustion_at = ?projection.exhaustion_at,
+            probability = projection.overrun_probability,
+            "Budget overrun forecasted"
+        );
+
+        Some(forecast_alert_event(budget, &projection))
+    }
+}
+
+impl Default for BurnRateEngine {
+    fn default() -> Self {
+        Self::new(BurnRateConfig::default())
+    }
+}
+
+/// Smallest `t` in (0, horizon] with `f(t) >= target`, assuming `f` crosses once
+fn first_crossing<F>(f: F, target: f64, horizon: f64) -> Option<f64>
+where
+    F: Fn(f64) -> f64,
+{
+    if !horizon.is_finite() || horizon <= 0.0 {
+        return None;
+    }
+
+    // Coarse scan to bracket the first crossing, then bisect
+    const SCAN_STEPS: usize = 256;
+    let mut previous = 0.0;
+    for i in 1..=SCAN_STEPS {
+        let t = horizon * i as f64 / SCAN_STEPS as f64;
+        if f(t) >= target {
+            let (mut lo, mut hi) = (previous, t);
+            for _ in 0..60 {
+                let mid = 0.5 * (lo + hi);
+                if f(mid) >= target {
+                    hi = mid;
+                } else {
+                    lo = mid;
+                }
+            }
+            return Some(hi);
+        }
+        previous = t;
+    }
+
+    None
+}
+
+/// Build the `ForecastedOverrun` alert event for a projection
+fn forecast_alert_event(budget: &Budget, projection: &BudgetProjection) -> AnalyticsEvent {
+    let mut tags = HashMap::new();
+    if let Some(team) = &budget.scope.team {
+        tags.insert("team".to_string(), team.clone());
+    }
+    if let Some(project) = &budget.scope.project {
+        tags.insert("project".to_string(), project.clone());
+    }
+    tags.insert(
+        "overrun_probability".to_string(),
+        format!("{:.3}", projection.overrun_probability),
+    );
+    tags.insert(
+        "projected_spend_usd".to_string(),
+        format!("{:.2}", projection.projected_spend_usd),
+    );
+    for (key, value) in [
+        ("exhaustion_earliest", projection.exhaustion_earliest),
+        ("exhaustion_latest", projection.exhaustion_latest),
+    ] {
+        if let Some(at) = value {
+            tags.insert(key.to_string(), at.to_rfc3339());
+        }
+    }
+
+    AnalyticsEvent {
+        common: CommonEventFields {
+            event_id: Uuid::new_v4(),
+            timestamp: projection.computed_at,
+            source_module: SourceModule::LlmAnalyticsHub,
+            event_type: EventType::Alert,
+            correlation_id: None,
+            parent_event_id: None,
+            schema_version: SCHEMA_VERSION.to_string(),
+            severity: Severity::Warning,
+            environment: "production".to_string(),
+            tags,
+        },
+        payload: EventPayload::Cost(CostPayload::BudgetAlert(BudgetAlertEvent {
+            budget_id: budget.budget_id.clone(),
+            budget_name: budget.budget_name.clone(),
+            budget_limit_usd: budget.limit_usd,
+            current_spend_usd: projection.spent_usd,
+            threshold_percent: 100.0 * projection.projected_spend_usd
+                / budget.limit_usd.max(f64::MIN_POSITIVE),
+            alert_type: BudgetAlertType::ForecastedOverrun,
+            projected_exhaustion_at: projection.exhaustion_at,
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::events::{fixtures, ApiCostEvent, TokenCostEvent};
+
+    fn cost_event(timestamp: DateTime<Utc>, cost: f64, team: &str) -> AnalyticsEvent {
+        fixtures::event(EventPayload::Cost(CostPayload::TokenCost(TokenCostEvent {
+            model_id: "gpt-4".to_string(),
+            request_id: Uuid::new_v4().to_string(),
+            prompt_tokens: 1000,
+            completion_tokens: 500,
+            total_tokens: 1500,
+            cost_per_prompt_token: 0.00003,
+            cost_per_completion_token: 0.00006,
+            total_cost_usd: cost,
+            currency: "USD".to_string(),
+        })))
+        .at(timestamp)
+        .with_source(SourceModule::LlmCostOps)
+        .with_tags(&[("team", team)])
+    }
+
+    fn budget(limit_usd: f64, period_start: DateTime<Utc>) -> Budget {
+        Budget {
+            budget_id: "ml-platform-monthly".to_string(),
+            budget_name: "ML Platform".to_string(),
+            scope: BudgetScope {
+                team: Some("ml-platform".to_string()),
+                project: None,
+            },
+            limit_usd,
+            period_start,
+            period_end: period_start + Duration::days(30),
+            opening_spend_usd: 0.0,
+            tracked_since: period_start,
+        }
+    }
+
+    #[test]
+    fn test_projects_exhaustion_with_bands() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let engine = BurnRateEngine::default();
+        engine.upsert_budget(budget(1000.0, start));
+
+        // ~$2/hour for two days: budget runs out around day 21
+        for hour in 1..=48 {
+            let cost = if hour % 2 == 0 { 2.5 } else { 1.5 };
+            engine.record_event(&cost_event(
+                start + Duration::hours(hour),
+                cost,
+                "ml-platform",
+            ));
+        }
+
+        let now = start + Duration::hours(49);
+        let projection = engine.project("ml-platform-monthly", now).unwrap();
+
+        assert!((projection.spent_usd - 96.0).abs() < 1e-9);
+        assert!((projection.hourly_burn_rate_usd - 2.0).abs() < 0.1);
+
+        let exhaustion = projection.exhaustion_at.unwrap();
+        let earliest = projection.exhaustion_earliest.unwrap();
+        let latest = projection.exhaustion_latest.unwrap();
+        assert!(earliest <= exhaustion && exhaustion <= latest);
+        assert!((exhaustion - start).num_days() >= 19 && (exhaustion - start).num_days() <= 22);
+        assert!(projection.projected_spend_lower_usd <= projection.projected_spend_usd);
+        assert!(projection.projected_spend_usd <= projection.projected_spend_upper_usd);
+    }
+
+    #[test]
+    fn test_emits_forecasted_overrun_once_per_cooldown() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let engine = BurnRateEngine::default();
+        engine.upsert_budget(budget(500.0, start));
+
+        let mut alerts = Vec::new();
+        for hour in 1..=24 {
+            alerts.extend(engine.record_event(&cost_event(
+                start + Duration::hours(hour),
+                2.0,
+                "ml-platform",
+            )));
+        }
+
+        assert_eq!(alerts.len(), 1);
+        match &alerts[0].payload {
+            EventPayload::Cost(CostPayload::BudgetAlert(alert)) => {
+                assert_eq!(alert.alert_type, BudgetAlertType::ForecastedOverrun);
+                assert!(alert.current_spend_usd < alert.budget_limit_usd);
+                assert!(alert.projected_exhaustion_at.unwrap() < start + Duration::days(30));
+            }
+            other => panic!("unexpected payload: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_ignores_events_outside_scope() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let engine = BurnRateEngine::default();
+        engine.upsert_budget(budget(500.0, start));
+
+        for hour in 1..=24 {
+            engine.record_event(&cost_event(start + Duration::hours(hour), 5.0, "search"));
+        }
+        let api_event = AnalyticsEvent {
+            payload: EventPayload::Cost(CostPayload::ApiCost(ApiCostEvent {
+                provider: "openai".to_string(),
+                api_endpoint: "/v1/embeddings".to_string(),
+                request_count: 10,
+                cost_per_request: 0.1,
+                total_cost_usd: 1.0,
+                billing_period: "2023-11".to_string(),
+            })),
+            ..cost_event(start + Duration::hours(3), 0.0, "ml-platform")
+        };
+        engine.record_event(&api_event);
+
+        let projection = engine
+            .project("ml-platform-monthly", start + Duration::hours(25))
+            .unwrap();
+        assert!((projection.spent_usd - 1.0).abs() < 1e-9);
+        assert!(projection.overrun_probability < 0.01);
+    }
+}
@@ -0,0 +1,657 @@
+//! Metric Causality Analysis
+//!
+//! Statistical relationships between metric series: Pearson and Spearman
+//! correlation with lag search, and Granger causality tests. Series are
+//! bucketed onto a regular grid before analysis so that lags correspond to a
+//! fixed amount of time.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{ContinuousCDF, FisherSnedecor, StudentsT};
+use std::collections::BTreeMap;
+
+use crate::models::correlation::CorrelationType;
+
+/// How samples falling into the same bucket are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregation {
+    /// Gauges (latency, error rate); empty buckets carry the previous value
+    Mean,
+    /// Counters (tokens, cost, event counts); empty buckets are zero
+    Sum,
+}
+
+/// Correlation coefficient
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorrelationMethod {
+    Pearson,
+    Spearman,
+}
+
+/// Correlation at a given lag
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LaggedCorrelation {
+    /// Lag in buckets; positive when the first series leads the second
+    pub lag: isize,
+    pub coefficient: f64,
+    /// Number of overlapping samples at this lag
+    pub samples: usize,
+}
+
+/// Outcome of a Granger causality F-test
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GrangerResult {
+    /// Number of lags in the autoregressive models
+    pub lag_order: usize,
+    pub f_statistic: f64,
+    pub p_value: f64,
+    /// Observations used in the regressions
+    pub observations: usize,
+}
+
+/// Causality analysis configuration
+#[derive(Debug, Clone)]
+pub struct CausalityConfig {
+    /// Bucket width of the regular grid
+    pub bucket: Duration,
+    /// Largest lag (in buckets) searched in either direction
+    pub max_lag: usize,
+    /// Largest autoregressive order considered by the Granger test
+    pub max_granger_order: usize,
+    /// Significance level for the Granger test
+    pub significance: f64,
+    /// Minimum absolute correlation reported as a temporal relationship
+    pub min_strength: f64,
+    /// Minimum number of aligned buckets
+    pub min_samples: usize,
+}
+
+impl Default for CausalityConfig {
+    fn default() -> Self {
+        Self {
+            bucket: Duration::minutes(1),
+            max_lag: 15,
+            max_granger_order: 5,
+            significance: 0.05,
+            min_strength: 0.6,
+            min_samples: 30,
+        }
+    }
+}
+
+/// Statistical relationship from one metric series to another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricRelationship {
+    pub cause: String,
+    pub effect: String,
+    /// `CausalChain` when the cause Granger-causes the effect, `Temporal`
+    /// when the series are only correlated, None when neither holds
+    pub correlation_type: Option<CorrelationType>,
+    /// Lag (in buckets) with the strongest correlation; positive when the
+    /// cause leads the effect
+    pub lag: isize,
+    /// Lag converted to seconds
+    pub lag_seconds: i64,
+    /// Pearson coefficient at the best lag
+    pub pearson: f64,
+    /// Spearman coefficient at the best lag
+    pub spearman: f64,
+    /// Absolute correlation at the best lag (larger of Pearson and Spearman)
+    pub strength: f64,
+    /// One minus the p-value of the test backing the classification
+    pub confidence: f64,
+    /// Granger test of cause -> effect, run when the cause leads
+    pub granger: Option<GrangerResult>,
+    /// Number of aligned buckets
+    pub samples: usize,
+}
+
+impl MetricRelationship {
+    /// Human-readable summary, e.g. "a.total_tokens -> b.cost_usd (+5m, ...)"
+    pub fn describe(&self) -> String {
+        let kind = match self.correlation_type {
+            Some(CorrelationType::CausalChain) => "causal",
+            Some(_) => "temporal",
+            None => "none",
+        };
+        let mut description = format!(
+            "{} -> {} ({}, lag {:+}s, r={:.2}, rho={:.2}",
+            self.cause, self.effect, kind, self.lag_seconds, self.pearson, self.spearman
+        );
+        if let Some(granger) = &self.granger {
+            description.push_str(&format!(", granger p={:.4}", granger.p_value));
+        }
+        description.push(')');
+        description
+    }
+}
+
+/// A series on a regular grid of buckets
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegularSeries {
+    /// Index of the first bucket (timestamp / bucket width)
+    pub start: i64,
+    pub values: Vec<f64>,
+}
+
+/// Bucket sums and sample counts, keyed by bucket index
+pub type Buckets = BTreeMap<i64, (f64, usize)>;
+
+/// Index of the bucket containing `timestamp`
+pub fn bucket_index(timestamp: DateTime<Utc>, bucket: Duration) -> i64 {
+    timestamp
+        .timestamp_millis()
+        .div_euclid(bucket.num_milliseconds().max(1))
+}
+
+/// Place samples onto a regular grid spanning the first to the last sample
+pub fn regularize(
+    points: &[(DateTime<Utc>, f64)],
+    bucket: Duration,
+    aggregation: Aggregation,
+) -> Option<RegularSeries> {
+    let mut buckets = Buckets::new();
+    for (timestamp, value) in points {
+        let entry = buckets
+            .entry(bucket_index(*timestamp, bucket))
+            .or_insert((0.0, 0));
+        entry.0 += value;
+        entry.1 += 1;
+    }
+    regularize_buckets(&buckets, aggregation)
+}
+
+/// Place pre-bucketed samples onto a regular grid spanning the first to the
+/// last bucket
+pub fn regularize_buckets(buckets: &Buckets, aggregation: Aggregation) -> Option<RegularSeries> {
+    let start = *buckets.keys().next()?;
+    let end = *buckets.keys().next_back()?;
+    let mut values = Vec::with_capacity((end - start + 1) as usize);
+    let mut previous = 0.0;
+    for index in start..=end {
+        let value = match (buckets.get(&index), aggregation) {
+            (Some((sum, _)), Aggregation::Sum) => *sum,
+            (Some((sum, count)), Aggregation::Mean) => sum / *count as f64,
+            (None, Aggregation::Sum) => 0.0,
+            (None, Aggregation::Mean) => previous,
+        };
+        previous = value;
+        values.push(value);
+    }
+
+    Some(RegularSeries { start, values })
+}
+
+/// Restrict two regular series to the buckets they share
+pub fn overlap<'a>(a: &'a RegularSeries, b: &'a RegularSeries) -> (&'a [f64], &'a [f64]) {
+    let start = a.start.max(b.start);
+    let end = (a.start + a.values.len() as i64).min(b.start + b.values.len() as i64);
+    if end <= start {
+        return (&[], &[]);
+    }
+    let slice = |s: &'a RegularSeries| {
+        let from = (start - s.start) as usize;
+        &s.values[from..from + (end - start) as usize]
+    };
+    (slice(a), slice(b))
+}
+
+/// Pearson correlation (None for fewer than 3 points or a constant series)
+pub fn pearson(x: &[f64], y: &[f64]) -> Option<f64> {
+    let n = x.len().min(y.len());
+    if n < 3 {
+        return None;
+    }
+    let (x, y) = (&x[..n], &y[..n]);
+    let mean_x = x.iter().sum::<f64>() / n as f64;
+    let mean_y = y.iter().sum::<f64>() / n as f64;
+
+    let mut covariance = 0.0;
+    let mut var_x = 0.0;
+    let mut var_y = 0.0;
+    for (a, b) in x.iter().zip(y) {
+        covariance += (a - mean_x) * (b - mean_y);
+        var_x += (a - mean_x).powi(2);
+        var_y += (b - mean_y).powi(2);
+    }
+
+    if var_x <= f64::EPSILON || var_y <= f64::EPSILON {
+        return None;
+    }
+    Some((covariance / (var_x * var_y).sqrt()).clamp(-1.0, 1.0))
+}
+
+/// Spearman rank correlation, using average ranks for ties
+pub fn spearman(x: &[f64], y: &[f64]) -> Option<f64> {
+    let n = x.len().min(y.len());
+    pearson(&ranks(&x[..n]), &ranks(&y[..n]))
+}
+
+fn ranks(values: &[f64]) -> Vec<f64> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|&a, &b| values[a].total_cmp(&values[b]));
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut i = 0;
+    while i < order.len() {
+        let mut j = i;
+        while j + 1 < order.len() && values[order[j + 1]] == values[order[i]] {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        for &index in &order[i..=j] {
+            ranks[index] = rank;
+        }
+        i = j + 1;
+    }
+    ranks
+}
+
+/// Two-sided p-value of a correlation coefficient over `n` samples
+pub fn correlation_p_value(r: f64, n: usize) -> f64 {
+    if n < 3 {
+        return 1.0;
+    }
+    let dof = (n - 2) as f64;
+    if r.abs() >= 1.0 {
+        return 0.0;
+    }
+    let t = r * (dof / (1.0 - r * r)).sqrt();
+    let dist = StudentsT::new(0.0, 1.0, dof).expect("degrees of freedom are positive");
+    2.0 * (1.0 - dist.cdf(t.abs()))
+}
+
+/// Overlapping parts of `x` and `y` when `y` is shifted by `lag`
+fn lagged<'a>(x: &'a [f64], y: &'a [f64], lag: isize) -> (&'a [f64], &'a [f64]) {
+    let n = x.len().min(y.len());
+    let shift = lag.unsigned_abs();
+    if shift >= n {
+        return (&[], &[]);
+    }
+    if lag >= 0 {
+        (&x[..n - shift], &y[shift..n])
+    } else {
+        (&x[shift..n], &y[..n - shift])
+    }
+}
+
+/// Correlation of `x[t]` with `y[t + lag]` for every lag in `-max_lag..=max_lag`
+pub fn cross_correlation(
+    x: &[f64],
+    y: &[f64],
+    max_lag: usize,
+    method: CorrelationMethod,
+) -> Vec<LaggedCorrelation> {
+    let max_lag = max_lag as isize;
+    (-max_lag..=max_lag)
+        .filter_map(|lag| {
+            let (a, b) = lagged(x, y, lag);
+            let coefficient = match method {
+                CorrelationMethod::Pearson => pearson(a, b),
+                CorrelationMethod::Spearman => spearman(a, b),
+            }?;
+            Some(LaggedCorrelation {
+                lag,
+                coefficient,
+                samples: a.len(),
+            })
+        })
+        .collect()
+}
+
+/// Lag with the largest absolute correlation; ties go to the smaller lag
+pub fn best_lag(
+    x: &[f64],
+    y: &[f64],
+    max_lag: usize,
+    method: CorrelationMethod,
+) -> Option<LaggedCorrelation> {
+    cross_correlation(x, y, max_lag, method)
+        .into_iter()
+        .min_by(|a, b| {
+            b.coefficient
+                .abs()
+                .total_cmp(&a.coefficient.abs())
+                .then(a.lag.unsigned_abs().cmp(&b.lag.unsigned_abs()))
+        })
+}
+
+/// Test whether past values of `cause` improve the prediction of `effect`
+/// beyond `effect`'s own past, using `order` lags of each.
+pub fn granger_causality(cause: &[f64], effect: &[f64], order: usize) -> Result<GrangerResult> {
+    granger_from(cause, effect, order, order)
+}
+
+/// Granger test with the lag order chosen by AIC of the unrestricted model
+pub fn granger_auto(cause: &[f64], effect: &[f64], max_order: usize) -> Result<GrangerResult> {
+    if max_order == 0 {
+        anyhow::bail!("Granger lag order must be at least 1");
+    }
+
+    // Compare orders on a common sample so the AIC values are comparable
+    let mut best: Option<(usize, f64)> = None;
+    for order in 1..=max_order {
+        let Some((rss, observations)) = ar_rss(cause, effect, order, max_order, true) else {
+            continue;
+        };
+        let params = 2 * order + 1;
+        let aic = observations as f64 * (rss.max(f64::MIN_POSITIVE) / observations as f64).ln()
+            + 2.0 * params as f64;
+        let improves = match best {
+            Some((_, current)) => aic < current,
+            None => true,
+        };
+        if improves {
+            best = Some((order, aic));
+        }
+    }
+
+    let (order, _) =
+        best.ok_or_else(|| anyhow::anyhow!("Insufficient data for a Granger causality test"))?;
+    granger_causality(cause, effect, order)
+}
+
+fn granger_from(
+    cause: &[f64],
+    effect: &[f64],
+    order: usize,
+    start: usize,
+) -> Result<GrangerResult> {
+    if order == 0 {
+        anyhow::bail!("Granger lag order must be at least 1");
+    }
+
+    let (restricted, observations) = ar_rss(cause, effect, order, start, false)
+        .ok_or_else(|| anyhow::anyhow!("Insufficient data for a Granger causality test"))?;
+    let (unrestricted, _) = ar_rss(cause, effect, order, start, true)
+        .ok_or_else(|| anyhow::anyhow!("Granger regression is singular"))?;
+
+    let df_num = order as f64;
+    let df_den = (observations - (2 * order + 1)) as f64;
+    let improvement = (restricted - unrestricted).max(0.0);
+
+    let (f_statistic, p_value) = if unrestricted <= 1e-12 * restricted.max(1e-12) {
+        // The cause explains the effect exactly
+        if improvement > 0.0 {
+            (f64::INFINITY, 0.0)
+        } else {
+            (0.0, 1.0)
+        }
+    } else {
+        let f = (improvement / df_num) / (unrestricted / df_den);
+        let dist = FisherSnedecor::new(df_num, df_den)
+            .map_err(|e| anyhow::anyhow!("Invalid F distribution: {}", e))?;
+        (f, 1.0 - dist.cdf(f))
+    };
+
+    Ok(GrangerResult {
+        lag_order: order,
+        f_statistic,
+        p_value,
+        observations,
+    })
+}
+
+/// Residual sum of squares of `effect[t]` regressed on a constant and `order`
+/// lags of `effect` (and of `cause` when `with_cause`), for `t >= start`.
+fn ar_rss(
+    cause: &[f64],
+    effect: &[f64],
+    order: usize,
+    start: usize,
+    with_cause: bool,
+) -> Option<(f64, usize)> {
+    let n = cause.len().min(effect.len());
+    let k = 1 + order * if with_cause { 2 } else { 1 };
+    // Leave enough degrees of freedom for the unrestricted model
+    if n <= start || n - start <= 2 * order + 2 {
+        return None;
+    }
+
+    let rows: Vec<(Vec<f64>, f64)> = (start..n)
+        .map(|t| {
+            let mut row = Vec::with_capacity(k);
+            row.push(1.0);
+            row.extend((1..=order).map(|l| effect[t - l]));
+            if with_cause {
+                row.extend((1..=order).map(|l| cause[t - l]));
+            }
+            (row, effect[t])
+        })
+        .collect();
+
+    let coefficients = least_squares(&rows, k)?;
+    let rss = rows
+        .iter()
+        .map(|(row, y)| {
+            let fitted: f64 = row.iter().zip(&coefficients).map(|(a, b)| a * b).sum();
+            (y - fitted).powi(2)
+        })
+        .sum();
+    Some((rss, rows.len()))
+}
+
+/// Ordinary least squares via the normal equations
+fn least_squares(rows: &[(Vec<f64>, f64)], k: usize) -> Option<Vec<f64>> {
+    // Augmented matrix [X'X | X'y]
+    let mut a = vec![vec![0.0; k + 1]; k];
+    for (row, y) in rows {
+        for i in 0..k {
+            for j in 0..k {
+                a[i][j] += row[i] * row[j];
+            }
+            a[i][k] += row[i] * y;
+        }
+    }
+
+    // Gaussian elimination with partial pivoting
+    for col in 0..k {
+        let pivot = (col..k).max_by(|&r1, &r2| a[r1][col].abs().total_cmp(&a[r2][col].abs()))?;
+        let scale = a[col][col].abs().max(a[pivot][col].abs()).max(1.0);
+        if a[pivot][col].abs() < 1e-10 * scale {
+            return None;
+        }
+        a.swap(col, pivot);
+        for r in col + 1..k {
+            let factor = a[r][col] / a[col][col];
+            if factor != 0.0 {
+                let pivot_row = a[col].clone();
+                for (value, p) in a[r].iter_mut().zip(&pivot_row).skip(col) {
+                    *value -= factor * p;
+                }
+            }
+        }
+    }
+
+    let mut x = vec![0.0; k];
+    for i in (0..k).rev() {
+        let tail: f64 = (i + 1..k).map(|j| a[i][j] * x[j]).sum();
+        x[i] = (a[i][k] - tail) / a[i][i];
+    }
+    Some(x)
+}
+
+/// Analyze the relationship from `cause` to `effect` on aligned series
+pub fn analyze_series(
+    cause_name: &str,
+    cause: &[f64],
+    effect_name: &str,
+    effect: &[f64],
+    config: &CausalityConfig,
+) -> Result<MetricRelationship> {
+    let n = cause.len().min(effect.len());
+    if n < config.min_samples.max(3) {
+        anyhow::bail!(
+            "Insufficient overlap between {} and {} ({} buckets, need {})",
+            cause_name,
+            effect_name,
+            n,
+            config.min_samples
+        );
+    }
+
+    let best = best_lag(cause, effect, config.max_lag, CorrelationMethod::Pearson)
+        .ok_or_else(|| anyhow::anyhow!("{} or {} is constant", cause_name, effect_name))?;
+    let (a, b) = lagged(cause, effect, best.lag);
+    let rank = spearman(a, b).unwrap_or(0.0);
+    let strength = best.coefficient.abs().max(rank.abs());
+
+    // Only test for causality in the direction the lag search supports
+    let granger = if best.lag > 0 {
+        granger_auto(cause, effect, config.max_granger_order).ok()
+    } else {
+        None
+    };
+
+    let (correlation_type, confidence) = match granger {
+        Some(result) if result.p_value < config.significance => {
+            (Some(CorrelationType::CausalChain), 1.0 - result.p_value)
+        }
+        _ => {
+            let confidence = 1.0 - correlation_p_value(best.coefficient, best.samples);
+            let temporal = strength >= config.min_strength;
+            (temporal.then_some(CorrelationType::Temporal), confidence)
+        }
+    };
+
+    Ok(MetricRelationship {
+        cause: cause_name.to_string(),
+        effect: effect_name.to_string(),
+        correlation_type,
+        lag: best.lag,
+        lag_seconds: best.lag as i64 * config.bucket.num_seconds(),
+        pearson: best.coefficient,
+        spearman: rank,
+        strength,
+        confidence,
+        granger,
+        samples: n,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic noise in [-0.5, 0.5)
+    fn noise(seed: usize, n: usize) -> Vec<f64> {
+        let mut state = (seed as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        (0..n)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 11) as f64 / (1u64 << 53) as f64 - 0.5
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pearson_and_spearman() {
+        let x: Vec<f64> = (0..20).map(|i| i as f64).collect();
+        let y: Vec<f64> = x.iter().map(|v| v.powi(3)).collect();
+        assert!((spearman(&x, &y).unwrap() - 1.0).abs() < 1e-12);
+        assert!(pearson(&x, &y).unwrap() < 0.95);
+
+        let negated: Vec<f64> = x.iter().map(|v| -2.0 * v).collect();
+        assert!((pearson(&x, &negated).unwrap() + 1.0).abs() < 1e-12);
+        assert!(pearson(&x, &[1.0; 20]).is_none());
+    }
+
+    #[test]
+    fn test_ranks_average_ties() {
+        assert_eq!(ranks(&[3.0, 1.0, 3.0, 2.0]), vec![3.5, 1.0, 3.5, 2.0]);
+    }
+
+    #[test]
+    fn test_best_lag_finds_shift() {
+        let x = noise(1, 200);
+        let y: Vec<f64> = (0..200)
+            .map(|t| if t >= 5 { 3.0 * x[t - 5] } else { 0.0 })
+            .collect();
+        let best = best_lag(&x, &y, 10, CorrelationMethod::Pearson).unwrap();
+        assert_eq!(best.lag, 5);
+        assert!(best.coefficient > 0.99);
+
+        let reverse = best_lag(&y, &x, 10, CorrelationMethod::Pearson).unwrap();
+        assert_eq!(reverse.lag, -5);
+    }
+
+    #[test]
+    fn test_granger_detects_direction() {
+        let x = noise(2, 300);
+        let e = noise(3, 300);
+        let y: Vec<f64> = (0..300)
+            .map(|t| {
+                if t >= 2 {
+                    0.8 * x[t - 2] + 0.2 * e[t]
+                } else {
+                    e[t]
+                }
+            })
+            .collect();
+
+        let forward = granger_auto(&x, &y, 4).unwrap();
+        assert!(forward.p_value < 0.001, "p={}", forward.p_value);
+        assert!(forward.lag_order >= 2);
+
+        let backward = granger_causality(&y, &x, 2).unwrap();
+        assert!(backward.p_value > 0.01, "p={}", backward.p_value);
+    }
+
+    #[test]
+    fn test_analyze_series_classifies_causal_chain() {
+        let tokens: Vec<f64> = noise(4, 240).iter().map(|v| 1000.0 + 400.0 * v).collect();
+        let jitter = noise(5, 240);
+        let cost: Vec<f64> = (0..240)
+            .map(|t| {
+                let lagged_tokens = if t >= 5 { tokens[t - 5] } else { 1000.0 };
+                lagged_tokens * 0.00003 + 0.001 * jitter[t]
+            })
+            .collect();
+
+        let config = CausalityConfig {
+            max_granger_order: 6,
+            ..Default::default()
+        };
+        let relationship = analyze_series("tokens", &tokens, "cost", &cost, &config).unwrap();
+        assert_eq!(
+            relationship.correlation_type,
+            Some(CorrelationType::CausalChain)
+        );
+        assert_eq!(relationship.lag, 5);
+        assert_eq!(relationship.lag_seconds, 300);
+        assert!(relationship.strength > 0.9);
+
+        let unrelated = noise(6, 240);
+        let relationship = analyze_series("tokens", &tokens, "other", &unrelated, &config).unwrap();
+        assert_eq!(relationship.correlation_type, None);
+    }
+
+    #[test]
+    fn test_regularize_fills_gaps() {
+        let start = DateTime::from_timestamp(1_700_000_040, 0).unwrap();
+        let points = vec![
+            (start, 2.0),
+            (start + Duration::seconds(10), 4.0),
+            (start + Duration::minutes(3), 1.0),
+        ];
+
+        let sums = regularize(&points, Duration::minutes(1), Aggregation::Sum).unwrap();
+        assert_eq!(sums.values, vec![6.0, 0.0, 0.0, 1.0]);
+
+        let means = regularize(&points, Duration::minutes(1), Aggregation::Mean).unwrap();
+        assert_eq!(means.values, vec![3.0, 3.0, 3.0, 1.0]);
+
+        let shifted = RegularSeries {
+            start: sums.start + 2,
+            values: vec![7.0, 8.0, 9.0],
+        };
+        let (a, b) = overlap(&sums, &shifted);
+        assert_eq!(a, &[0.0, 1.0]);
+        assert_eq!(b, &[7.0, 8.0]);
+    }
+}
@@ -0,0 +1,980 @@
+//! Complex Event Processing
+//!
+//! Matches [`CorrelationPattern`] sequences against the live event stream.
+//! Every pattern runs one state machine per partition key (e.g. per user):
+//! a partial match advances when an event satisfies its next step and expires
+//! once that step's time bound has passed. Negated steps ("A then no B within
+//! 5m") hold until their window elapses and abort the match if a matching
+//! event arrives first. Completed sequences are emitted as
+//! [`EventCorrelation`]s.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+use serde_json::Value;
+use std::cell::OnceCell;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use super::correlation::{event_metrics, EVENT_COUNT_METRIC};
+use super::root_cause::payload_kind;
+use crate::models::correlation::{
+    CorrelatedEvent, CorrelationId, CorrelationPattern, CorrelationType, EventCorrelation,
+    EventRole, PatternStep, TimeWindow,
+};
+use crate::schemas::events::{AnalyticsEvent, EventType};
+
+/// CEP engine configuration
+#[derive(Debug, Clone)]
+pub struct CepConfig {
+    /// Longest a sequence may take from its first event; also bounds steps
+    /// without a time offset
+    pub max_match_duration: Duration,
+    /// Partial matches kept per pattern and key; the oldest are dropped first
+    pub max_partials_per_key: usize,
+}
+
+impl Default for CepConfig {
+    fn default() -> Self {
+        Self {
+            max_match_duration: Duration::hours(1),
+            max_partials_per_key: 100,
+        }
+    }
+}
+
+/// CEP engine counters
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CepStats {
+    pub patterns: usize,
+    pub partial_matches: usize,
+    pub matches_emitted: u64,
+    pub partials_expired: u64,
+    pub partials_aborted: u64,
+}
+
+/// A sequence in progress for one pattern and partition key
+#[derive(Debug, Clone)]
+struct PartialMatch {
+    /// Events matched so far, in step order
+    events: Vec<AnalyticsEvent>,
+    /// Index of the next step to satisfy
+    next_step: usize,
+    started_at: DateTime<Utc>,
+    /// Time the next step's offset is measured from
+    reference: DateTime<Utc>,
+}
+
+/// State of a partial match at a point in time
+enum Progress {
+    Pending,
+    Complete(DateTime<Utc>),
+    Expired,
+}
+
+/// Effect of an event on a partial match
+enum Outcome {
+    Unchanged,
+    Advanced,
+    Aborted,
+}
+
+/// Pattern matcher over the event stream
+pub struct CepEngine {
+    config: CepConfig,
+    patterns: Arc<DashMap<String, Arc<CorrelationPattern>>>,
+    /// (pattern ID, partition key) -> partial matches, oldest first
+    partials: Arc<DashMap<(String, String), Vec<PartialMatch>>>,
+    matches_emitted: AtomicU64,
+    partials_expired: AtomicU64,
+    partials_aborted: AtomicU64,
+}
+
+impl CepEngine {
+    /// Create a new CEP engine
+    pub fn new(config: CepConfig) -> Self {
+        Self {
+            config,
+            patterns: Arc::new(DashMap::new()),
+            partials: Arc::new(DashMap::new()),
+            matches_emitted: AtomicU64::new(0),
+            partials_expired: AtomicU64::new(0),
+            partials_aborted: AtomicU64::new(0),
+        }
+    }
+
+    /// Register a pattern, replacing any pattern with the same ID.
+    ///
+    /// Steps are ordered by `step_number`. A pattern must start with a
+    /// positive step, and a trailing negated step needs a time offset since
+    /// it is only satisfied once its window has elapsed.
+    pub fn add_pattern(&self, mut pattern: CorrelationPattern) -> Result<()> {
+        pattern.sequence.sort_by_key(|step| step.step_number);
+        validate(&pattern)?;
+
+        let pattern_id = pattern.pattern_id.clone();
+        self.partials.retain(|(id, _), _| *id != pattern_id);
+        self.patterns.insert(pattern_id, Arc::new(pattern));
+        Ok(())
+    }
+
+    /// Remove a pattern and its partial matches
+    pub fn remove_pattern(&self, pattern_id: &str) -> Option<CorrelationPattern> {
+        self.partials.retain(|(id, _), _| id != pattern_id);
+        self.patterns
+            .remove(pattern_id)
+            .map(|(_, pattern)| pattern.as_ref().clone())
+    }
+
+    /// Registered patterns
+    pub fn patterns(&self) -> Vec<CorrelationPattern> {
+        self.patterns
+            .iter()
+            .map(|p| p.value().as_ref().clone())
+            .collect()
+    }
+
+    /// Snapshot of the registered patterns, so no pattern lock is held while
+    /// partial matches are updated
+    fn pattern_snapshot(&self) -> HashMap<String, Arc<CorrelationPattern>> {
+        self.patterns
+            .iter()
+            .map(|p| (p.key().clone(), p.value().clone()))
+            .collect()
+    }
+
+    /// Feed an event through every pattern and return the completed matches.
+    ///
+    /// Partial matches for the event's keys are first advanced to the event's
+    /// timestamp, so negation windows that elapsed before it complete here.
+    pub fn process_event(&self, event: &AnalyticsEvent) -> Vec<EventCorrelation> {
+        let now = event.common.timestamp;
+        let view = EventView::new(event);
+        let mut matches = Vec::new();
+
+        for pattern in self.pattern_snapshot().values() {
+            let Some(key) = partition_key(pattern, &view) else {
+                continue;
+            };
+            let map_key = (pattern.pattern_id.clone(), key);
+            let starts = step_matches(&pattern.sequence[0], &view);
+
+            if !starts && !self.partials.contains_key(&map_key) {
+                continue;
+            }
+            let mut partials = self.partials.entry(map_key.clone()).or_default();
+
+            let mut remaining = Vec::with_capacity(partials.len() + 1);
+            for mut partial in partials.drain(..) {
+                match self.progress(pattern, &partial, now) {
+                    Progress::Expired => {
+                        self.partials_expired.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                    Progress::Complete(end) => {
+                        matches.push(self.emit(pattern, &map_key.1, &partial, end));
+                        continue;
+                    }
+                    Progress::Pending => {}
+                }
+
+                match advance(pattern, &mut partial, &view) {
+                    Outcome::Aborted => {
+                        self.partials_aborted.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Outcome::Unchanged => remaining.push(partial),
+                    Outcome::Advanced => match self.progress(pattern, &partial, now) {
+                        Progress::Complete(end) => {
+                            matches.push(self.emit(pattern, &map_key.1, &partial, end));
+                        }
+                        _ => remaining.push(partial),
+                    },
+                }
+            }
+
+            if starts {
+                let partial = PartialMatch {
+                    events: vec![event.clone()],
+                    next_step: 1,
+                    started_at: now,
+                    reference: now,
+                };
+                match self.progress(pattern, &partial, now) {
+                    Progress::Complete(end) => {
+                        matches.push(self.emit(pattern, &map_key.1, &partial, end));
+                    }
+                    _ => remaining.push(partial),
+                }
+            }
+
+            if remaining.len() > self.config.max_partials_per_key {
+                let excess = remaining.len() - self.config.max_partials_per_key;
+                remaining.drain(..excess);
+                self.partials_expired
+                    .fetch_add(excess as u64, Ordering::Relaxed);
+            }
+
+            let empty = remaining.is_empty();
+            *partials = remaining;
+            drop(partials);
+            if empty {
+                self.partials.remove(&map_key);
+            }
+        }
+
+        matches
+    }
+
+    /// Advance every partial match to `watermark`, completing sequences whose
+    /// negation windows have elapsed and dropping those past their deadline.
+    ///
+    /// The watermark should trail the stream's event time by the allowed
+    /// lateness, otherwise late events can no longer abort a negation.
+    pub fn advance_to(&self, watermark: DateTime<Utc>) -> Vec<EventCorrelation> {
+        let patterns = self.pattern_snapshot();
+        let mut matches = Vec::new();
+
+        self.partials.retain(|(pattern_id, key), partials| {
+            let Some(pattern) = patterns.get(pattern_id) else {
+                return false;
+            };
+
+            partials.retain(|partial| match self.progress(pattern, partial, watermark) {
+                Progress::Pending => true,
+                Progress::Complete(end) => {
+                    matches.push(self.emit(pattern, key, partial, end));
+                    false
+                }
+                Progress::Expired => {
+                    self.partials_expired.fetch_add(1, Ordering::Relaxed);
+                    false
+                }
+            });
+            !partials.is_empty()
+        });
+
+        matches
+    }
+
+    /// Number of sequences in progress
+    pub fn active_partial_matches(&self) -> usize {
+        self.partials.iter().map(|p| p.value().len()).sum()
+    }
+
+    /// Engine counters
+    pub fn stats(&self) -> CepStats {
+        CepStats {
+            patterns: self.patterns.len(),
+            partial_matches: self.active_partial_matches(),
+            matches_emitted: self.matches_emitted.load(Ordering::Relaxed),
+            partials_expired: self.partials_expired.load(Ordering::Relaxed),
+            partials_aborted: self.partials_aborted.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Where a partial match stands at `now`, without consuming any event.
+    /// Negation windows that have elapsed by `now` are treated as satisfied.
+    fn progress(
+        &self,
+        pattern: &CorrelationPattern,
+        partial: &PartialMatch,
+        now: DateTime<Utc>,
+    ) -> Progress {
+        let sequence = &pattern.sequence;
+        let mut next_step = partial.next_step;
+        let mut reference = partial.reference;
+
+        loop {
+            let Some(step) = sequence.get(next_step) else {
+                return Progress::Complete(reference);
+            };
+
+            if step.negated {
+                if let Some(offset) = step.time_offset_ms {
+                    let guard_end = reference + Duration::milliseconds(offset);
+                    if guard_end > now {
+                        return Progress::Pending;
+                    }
+                    next_step += 1;
+                    reference = guard_end;
+                    continue;
+                }
+                // Without an offset the guard lasts until the following step
+                // matches, so it shares that step's deadline
+                next_step += 1;
+                continue;
+            }
+
+            return if self.deadline(step, partial.started_at, reference) < now {
+                Progress::Expired
+            } else {
+                Progress::Pending
+            };
+        }
+    }
+
+    /// Latest time a positive step can match
+    fn deadline(
+        &self,
+        step: &PatternStep,
+        started_at: DateTime<Utc>,
+        reference: DateTime<Utc>,
+    ) -> DateTime<Utc> {
+        let overall = started_at + self.config.max_match_duration;
+        match step.time_offset_ms {
+            Some(offset) => overall.min(reference + Duration::milliseconds(offset)),
+            None => overall,
+        }
+    }
+
+    fn emit(
+        &self,
+        pattern: &CorrelationPattern,
+        key: &str,
+        partial: &PartialMatch,
+        end: DateTime<Utc>,
+    ) -> EventCorrelation {
+        self.matches_emitted.fetch_add(1, Ordering::Relaxed);
+
+        let last = partial.events.len() - 1;
+        let events = partial
+            .events
+            .iter()
+            .enumerate()
+            .map(|(i, event)| CorrelatedEvent {
+                event_id: event.common.event_id,
+                source_module: event.common.source_module.clone(),
+                event_type: event.common.event_type.clone(),
+                severity: event.common.severity.clone(),
+                timestamp: event.common.timestamp,
+                role: match i {
+                    0 => EventRole::RootCause,
+                    i if i == last => EventRole::Effect,
+                    _ => EventRole::Contributor,
+                },
+                summary: payload_kind(&event.payload).to_string(),
+                metrics: event_metrics(event)
+                    .into_iter()
+                    .filter(|(name, _, _)| *name != EVENT_COUNT_METRIC)
+                    .map(|(name, value, _)| (name.to_string(), value))
+                    .collect(),
+            })
+            .collect();
+
+        let mut metadata = HashMap::new();
+        metadata.insert("pattern_id".to_string(), pattern.pattern_id.clone());
+        metadata.insert("pattern_name".to_string(), pattern.name.clone());
+        if !key.is_empty() {
+            metadata.insert("partition_key".to_string(), key.to_string());
+        }
+
+        EventCorrelation {
+            correlation_id: CorrelationId::new(),
+            correlation_type: pattern_correlation_type(pattern),
+            events,
+            strength: 1.0,
+            confidence: 1.0,
+            time_window: TimeWindow {
+                start: partial.started_at,
+                end,
+            },
+            pattern: Some(pattern.clone()),
+            detected_at: Utc::now(),
+            metadata,
+        }
+    }
+}
+
+impl Default for CepEngine {
+    fn default() -> Self {
+        Self::new(CepConfig::default())
+    }
+}
+
+fn validate(pattern: &CorrelationPattern) -> Result<()> {
+    let sequence = &pattern.sequence;
+    let Some(first) = sequence.first() else {
+        anyhow::bail!("Pattern {} has no steps", pattern.pattern_id);
+    };
+    if first.negated {
+        anyhow::bail!(
+            "Pattern {} cannot start with a negated step",
+            pattern.pattern_id
+        );
+    }
+
+    for (i, step) in sequence.iter().enumerate() {
+        if step.time_offset_ms.is_some_and(|offset| offset < 0) {
+            anyhow::bail!(
+                "Pattern {} step {} has a negative time offset",
+                pattern.pattern_id,
+                step.step_number
+            );
+        }
+        if !step.negated {
+            continue;
+        }
+        match sequence.get(i + 1) {
+            Some(next) if next.negated => anyhow::bail!(
+                "Pattern {} has consecutive negated steps {} and {}",
+                pattern.pattern_id,
+                step.step_number,
+                next.step_number
+            ),
+            None if step.time_offset_ms.is_none() => anyhow::bail!(
+                "Pattern {} ends with negated step {} without a time offset",
+                pattern.pattern_id,
+                step.step_number
+            ),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Apply an event to a partial match that is still pending at the event's time
+fn advance(pattern: &CorrelationPattern, partial: &mut PartialMatch, view: &EventView) -> Outcome {
+    let timestamp = view.event.common.timestamp;
+    if timestamp < partial.reference {
+        return Outcome::Unchanged;
+    }
+
+    // Skip negation windows that elapsed before this event
+    let sequence = &pattern.sequence;
+    while let Some(step) = sequence.get(partial.next_step) {
+        match step.time_offset_ms {
+            Some(offset) if step.negated => {
+                let guard_end = partial.reference + Duration::milliseconds(offset);
+                if guard_end > timestamp {
+                    break;
+                }
+                partial.next_step += 1;
+                partial.reference = guard_end;
+            }
+            _ => break,
+        }
+    }
+
+    let Some(step) = sequence.get(partial.next_step) else {
+        return Outcome::Unchanged;
+    };
+
+    if step.negated {
+        if step_matches(step, view) {
+            return Outcome::Aborted;
+        }
+        if step.time_offset_ms.is_some() {
+            return Outcome::Unchanged;
+        }
+        // Open-ended guard: the following step closes it
+        match sequence.get(partial.next_step + 1) {
+            Some(next) if step_matches(next, view) => {
+                partial.next_step += 2;
+            }
+            _ => return Outcome::Unchanged,
+        }
+    } else if step_matches(step, view) {
+        partial.next_step += 1;
+    } else {
+        return Outcome::Unchanged;
+    }
+
+    partial.events.push(view.event.clone());
+    partial.reference = timestamp;
+    Outcome::Advanced
+}
+
+fn pattern_correlation_type(pattern: &CorrelationPattern) -> CorrelationType {
+    let positive = || pattern.sequence.iter().filter(|step| !step.negated);
+    if positive().any(|step| step.event_type == EventType::Security) {
+        CorrelationType::SecurityIncident
+    } else if positive().any(|step| step.event_type == EventType::Governance) {
+        CorrelationType::ComplianceCascade
+    } else {
+        CorrelationType::PatternMatch
+    }
+}
+
+/// An event with its JSON form computed on first field lookup
+struct EventView<'a> {
+    event: &'a AnalyticsEvent,
+    json: OnceCell<Value>,
+}
+
+impl<'a> EventView<'a> {
+    fn new(event: &'a AnalyticsEvent) -> Self {
+        Self {
+            event,
+            json: OnceCell::new(),
+        }
+    }
+
+    /// Look up a field by name: tags first, then common fields, then the
+    /// payload data. Dotted names address nested objects (`data.export_bytes`).
+    fn field(&self, name: &str) -> Option<Value> {
+        if let Some(tag) = self.event.common.tags.get(name) {
+            return Some(Value::String(tag.clone()));
+        }
+
+        let json = self
+            .json
+            .get_or_init(|| serde_json::to_value(self.event).unwrap_or(Value::Null));
+        let lookup = |root: &Value| {
+            name.split('.')
+                .try_fold(root, |value, part| value.get(part))
+                .filter(|value| !value.is_null())
+                .cloned()
+        };
+
+        lookup(json).or_else(|| json.pointer("/payload/data").and_then(lookup))
+    }
+}
+
+fn step_matches(step: &PatternStep, view: &EventView) -> bool {
+    let common = &view.event.common;
+    common.source_module == step.module
+        && common.event_type == step.event_type
+        && step
+            .conditions
+            .iter()
+            .all(|(field, expected)| condition_matches(view.field(field).as_ref(), expected))
+}
+
+/// Evaluate a step condition against a field value.
+///
+/// Supported forms: `value` (equality), `!value`, `>n`, `>=n`, `<n`, `<=n`
+/// and `*` (field present).
+fn condition_matches(actual: Option<&Value>, expected: &str) -> bool {
+    let expected = expected.trim();
+    if expected == "*" {
+        return actual.is_some();
+    }
+    if let Some(rejected) = expected.strip_prefix('!') {
+        return actual.map_or(true, |value| value_string(value) != rejected.trim());
+    }
+
+    let Some(actual) = actual else {
+        return false;
+    };
+
+    for (op, compare) in [
+        (">=", f64::ge as fn(&f64, &f64) -> bool),
+        ("<=", f64::le),
+        (">", f64::gt),
+        ("<", f64::lt),
+    ] {
+        if let Some(threshold) = expected.strip_prefix(op) {
+            let value = match actual {
+                Value::Number(n) => n.as_f64(),
+                other => value_string(other).parse().ok(),
+            };
+            return match (value, threshold.trim().parse::<f64>()) {
+                (Some(value), Ok(threshold)) => compare(&value, &threshold),
+                _ => false,
+            };
+        }
+    }
+
+    value_string(actual) == expected
+}
+
+fn value_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Partition key of an event for a pattern, or None if a key field is missing
+fn partition_key(pattern: &CorrelationPattern, view: &EventView) -> Option<String> {
+    let parts = pattern
+        .partition_by
+        .iter()
+        .map(|field| view.field(field).map(|value| value_string(&value)))
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("|"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::events::{
+        AuthAction, AuthEvent, CommonEventFields, CustomPayload, EventPayload, SecurityPayload,
+        Severity, SourceModule, SCHEMA_VERSION,
+    };
+
+    fn base(minute: i64, source_module: SourceModule, event_type: EventType) -> CommonEventFields {
+        CommonEventFields {
+            event_id: uuid::Uuid::new_v4(),
+            timestamp: DateTime::<Utc>::UNIX_EPOCH + Duration::minutes(minute),
+            source_module,
+            event_type,
+            correlation_id: None,
+            parent_event_id: None,
+            schema_version: SCHEMA_VERSION.to_string(),
+            severity: Severity::Warning,
+            environment: "test".to_string(),
+            tags: HashMap::new(),
+        }
+    }
+
+    fn auth_failure(minute: i64, user: &str) -> AnalyticsEvent {
+        AnalyticsEvent {
+            common: base(minute, SourceModule::LlmSentinel, EventType::Security),
+            payload: EventPayload::Security(SecurityPayload::Auth(AuthEvent {
+                user_id: user.to_string(),
+                action: AuthAction::Login,
+                resource: "console".to_string(),
+                success: false,
+                failure_reason: Some("bad password".to_string()),
+            })),
+        }
+    }
+
+    fn custom(
+        minute: i64,
+        source_module: SourceModule,
+        event_type: EventType,
+        user: &str,
+        data: Value,
+    ) -> AnalyticsEvent {
+        let mut common = base(minute, source_module, event_type);
+        common.tags.insert("user_id".to_string(), user.to_string());
+        AnalyticsEvent {
+            common,
+            payload: EventPayload::Custom(CustomPayload {
+                custom_type: "test".to_string(),
+                data,
+            }),
+        }
+    }
+
+    fn privilege_change(minute: i64, user: &str) -> AnalyticsEvent {
+        custom(
+            minute,
+            SourceModule::LlmGovernanceDashboard,
+            EventType::Audit,
+            user,
+            serde_json::json!({"action": "grant_role", "role": "admin"}),
+        )
+    }
+
+    fn export(minute: i64, user: &str, bytes: u64) -> AnalyticsEvent {
+        custom(
+            minute,
+            SourceModule::LlmObservatory,
+            EventType::Audit,
+            user,
+            serde_json::json!({"action": "export", "bytes": bytes}),
+        )
+    }
+
+    fn step(
+        step_number: u32,
+        module: SourceModule,
+        event_type: EventType,
+        offset_minutes: Option<i64>,
+        conditions: &[(&str, &str)],
+        negated: bool,
+    ) -> PatternStep {
+        PatternStep {
+            step_number,
+            module,
+            event_type,
+            time_offset_ms: offset_minutes.map(|m| m * 60_000),
+            conditions: conditions
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            negated,
+        }
+    }
+
+    fn pattern(id: &str, sequence: Vec<PatternStep>) -> CorrelationPattern {
+        CorrelationPattern {
+            pattern_id: id.to_string(),
+            name: id.replace('_', " "),
+            description: String::new(),
+            modules: sequence.iter().map(|s| s.module.clone()).collect(),
+            sequence,
+            partition_by: vec!["user_id".to_string()],
+        }
+    }
+
+    fn exfiltration_pattern() -> CorrelationPattern {
+        pattern(
+            "exfiltration",
+            vec![
+                step(
+                    1,
+                    SourceModule::LlmSentinel,
+                    EventType::Security,
+                    None,
+                    &[("success", "false")],
+                    false,
+                ),
+                step(
+                    2,
+                    SourceModule::LlmGovernanceDashboard,
+                    EventType::Audit,
+                    Some(10),
+                    &[("data.action", "grant_role")],
+                    false,
+                ),
+                step(
+                    3,
+                    SourceModule::LlmObservatory,
+                    EventType::Audit,
+                    Some(30),
+                    &[("data.action", "export"), ("data.bytes", ">1000000")],
+                    false,
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_sequence_matches_per_key() {
+        let engine = CepEngine::default();
+        engine.add_pattern(exfiltration_pattern()).unwrap();
+
+        let events = [
+            auth_failure(0, "alice"),
+            auth_failure(1, "bob"),
+            privilege_change(5, "alice"),
+            export(6, "bob", 5_000_000),
+            export(20, "alice", 10),
+            export(25, "alice", 5_000_000),
+        ];
+        let matches: Vec<EventCorrelation> = events
+            .iter()
+            .flat_map(|e| engine.process_event(e))
+            .collect();
+
+        assert_eq!(matches.len(), 1);
+        let correlation = &matches[0];
+        assert_eq!(
+            correlation.correlation_type,
+            CorrelationType::SecurityIncident
+        );
+        assert_eq!(correlation.metadata["partition_key"], "alice");
+        assert_eq!(correlation.time_window.duration_seconds(), 25 * 60);
+
+        let ids: Vec<_> = correlation.events.iter().map(|e| e.event_id).collect();
+        assert_eq!(
+            ids,
+            vec![
+                events[0].common.event_id,
+                events[2].common.event_id,
+                events[5].common.event_id
+            ]
+        );
+        let roles: Vec<_> = correlation.events.iter().map(|e| e.role.clone()).collect();
+        assert_eq!(
+            roles,
+            vec![
+                EventRole::RootCause,
+                EventRole::Contributor,
+                EventRole::Effect
+            ]
+        );
+
+        // Bob's sequence is still waiting for a privilege change
+        assert_eq!(engine.active_partial_matches(), 1);
+    }
+
+    #[test]
+    fn test_partial_match_expires_after_step_deadline() {
+        let engine = CepEngine::default();
+        engine.add_pattern(exfiltration_pattern()).unwrap();
+
+        assert!(engine.process_event(&auth_failure(0, "alice")).is_empty());
+        // Privilege change arrives after the 10 minute bound
+        assert!(engine
+            .process_event(&privilege_change(11, "alice"))
+            .is_empty());
+        assert!(engine
+            .process_event(&export(12, "alice", 5_000_000))
+            .is_empty());
+
+        let stats = engine.stats();
+        assert_eq!(stats.partials_expired, 1);
+        assert_eq!(stats.matches_emitted, 0);
+
+        assert!(engine
+            .advance_to(DateTime::<Utc>::UNIX_EPOCH + Duration::hours(2))
+            .is_empty());
+        assert_eq!(engine.active_partial_matches(), 0);
+    }
+
+    fn unacknowledged_failure() -> CorrelationPattern {
+        // Auth failure with no privilege change review within 5 minutes
+        pattern(
+            "unreviewed_failure",
+            vec![
+                step(
+                    1,
+                    SourceModule::LlmSentinel,
+                    EventType::Security,
+                    None,
+                    &[("success", "false")],
+                    false,
+                ),
+                step(
+                    2,
+                    SourceModule::LlmGovernanceDashboard,
+                    EventType::Audit,
+                    Some(5),
+                    &[],
+                    true,
+                ),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_negated_step_completes_when_window_elapses() {
+        let engine = CepEngine::default();
+        engine.add_pattern(unacknowledged_failure()).unwrap();
+
+        let failure = auth_failure(0, "alice");
+        assert!(engine.process_event(&failure).is_empty());
+        assert!(engine
+            .advance_to(DateTime::<Utc>::UNIX_EPOCH + Duration::minutes(4))
+            .is_empty());
+
+        let matches = engine.advance_to(DateTime::<Utc>::UNIX_EPOCH + Duration::minutes(5));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].events.len(), 1);
+        assert_eq!(matches[0].events[0].event_id, failure.common.event_id);
+        assert_eq!(matches[0].events[0].role, EventRole::RootCause);
+        assert_eq!(matches[0].time_window.duration_seconds(), 300);
+    }
+
+    #[test]
+    fn test_negated_step_aborts_on_matching_event() {
+        let engine = CepEngine::default();
+        engine.add_pattern(unacknowledged_failure()).unwrap();
+
+        engine.process_event(&auth_failure(0, "alice"));
+        engine.process_event(&auth_failure(0, "bob"));
+        // Only alice's failure is reviewed in time
+        engine.process_event(&privilege_change(3, "alice"));
+
+        let matches = engine.advance_to(DateTime::<Utc>::UNIX_EPOCH + Duration::minutes(10));
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].metadata["partition_key"], "bob");
+        assert_eq!(engine.stats().partials_aborted, 1);
+    }
+
+    #[test]
+    fn test_open_negation_between_steps() {
+        // Failure followed by an export with no privilege change in between
+        let engine = CepEngine::default();
+        engine
+            .add_pattern(pattern(
+                "export_without_grant",
+                vec![
+                    step(
+                        1,
+                        SourceModule::LlmSentinel,
+                        EventType::Security,
+                        None,
+                        &[],
+                        false,
+                    ),
+                    step(
+                        2,
+                        SourceModule::LlmGovernanceDashboard,
+                        EventType::Audit,
+                        None,
+                        &[],
+                        true,
+                    ),
+                    step(
+                        3,
+                        SourceModule::LlmObservatory,
+                        EventType::Audit,
+                        Some(15),
+                        &[],
+                        false,
+                    ),
+                ],
+            ))
+            .unwrap();
+
+        engine.process_event(&auth_failure(0, "alice"));
+        engine.process_event(&auth_failure(0, "bob"));
+        engine.process_event(&privilege_change(2, "bob"));
+        let alice = engine.process_event(&export(4, "alice", 1));
+        let bob = engine.process_event(&export(4, "bob", 1));
+
+        assert_eq!(alice.len(), 1);
+        assert_eq!(alice[0].events.len(), 2);
+        assert_eq!(alice[0].correlation_type, CorrelationType::SecurityIncident);
+        assert!(bob.is_empty());
+    }
+
+    #[test]
+    fn test_condition_operators() {
+        let number = Value::from(42.5);
+        let text = Value::from("denied");
+
+        assert!(condition_matches(Some(&number), ">40"));
+        assert!(condition_matches(Some(&number), ">=42.5"));
+        assert!(!condition_matches(Some(&number), "<42.5"));
+        assert!(condition_matches(Some(&number), "<= 50"));
+        assert!(condition_matches(Some(&number), "42.5"));
+        assert!(condition_matches(Some(&text), "denied"));
+        assert!(condition_matches(Some(&text), "!granted"));
+        assert!(!condition_matches(Some(&text), "!denied"));
+        assert!(!condition_matches(Some(&text), ">1"));
+        assert!(condition_matches(Some(&text), "*"));
+        assert!(!condition_matches(None, "*"));
+        assert!(condition_matches(None, "!denied"));
+    }
+
+    #[test]
+    fn test_rejects_invalid_patterns() {
+        let engine = CepEngine::default();
+        let leading_negation = pattern(
+            "leading",
+            vec![step(
+                1,
+                SourceModule::LlmSentinel,
+                EventType::Security,
+                Some(5),
+                &[],
+                true,
+            )],
+        );
+        assert!(engine.add_pattern(leading_negation).is_err());
+
+        let open_trailing_negation = pattern(
+            "trailing",
+            vec![
+                step(
+                    1,
+                    SourceModule::LlmSentinel,
+                    EventType::Security,
+                    None,
+                    &[],
+                    false,
+                ),
+                step(
+                    2,
+                    SourceModule::LlmSentinel,
+                    EventType::Security,
+                    None,
+                    &[],
+                    true,
+                ),
+            ],
+        );
+        assert!(engine.add_pattern(open_trailing_negation).is_err());
+        assert!(engine.patterns().is_empty());
+    }
+}
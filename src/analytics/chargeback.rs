@@ -0,0 +1,1207 @@
+//! Cost Attribution and Chargeback
+//!
+//! Allocates spend from `CostPayload` events to cost centres (team, product,
+//! feature) and produces monthly chargeback reports.
+//!
+//! Allocation rules:
+//! - direct: costs are charged to the cost centre named by their tags, or to
+//!   the cost centre of a matching `Direct` rule
+//! - proportional: untagged shared infrastructure costs matching a
+//!   `Proportional` rule are split across cost centres by their direct usage
+//! - amortised: reserved capacity is spread evenly over its term and each
+//!   month's slice is split by fixed weights or by direct cost
+//!
+//! Rules are applied when an event is recorded; amortised capacity is applied
+//! when a report is built. Recorded spend is booked to a ledger per month and
+//! account; services take the entries booked since the last flush and add
+//! them to the shared ledger in the database, so that reports cover the spend
+//! seen by every instance. Reports can be reconciled against CostOps totals
+//! and exported as CSV or JSON.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, TimeZone, Utc};
+use dashmap::DashMap;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use tracing::{debug, info};
+
+use crate::adapters::costops::CostSummary;
+use crate::schemas::events::{AnalyticsEvent, CostPayload, EventPayload};
+
+/// Tag keys identifying the team that incurred a cost
+const TEAM_TAGS: [&str; 2] = ["team", "team_id"];
+
+/// Tag keys identifying the product that incurred a cost
+const PRODUCT_TAGS: [&str; 2] = ["product", "product_id"];
+
+/// Tag keys identifying the feature that incurred a cost
+const FEATURE_TAGS: [&str; 2] = ["feature", "feature_id"];
+
+/// Chargeback engine configuration
+#[derive(Debug, Clone)]
+pub struct ChargebackConfig {
+    /// Relative difference to CostOps totals accepted by reconciliation
+    pub reconciliation_tolerance: f64,
+}
+
+impl Default for ChargebackConfig {
+    fn default() -> Self {
+        Self {
+            reconciliation_tolerance: 0.01,
+        }
+    }
+}
+
+/// Team / product / feature that spend is charged to
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CostCentre {
+    pub team: Option<String>,
+    pub product: Option<String>,
+    pub feature: Option<String>,
+}
+
+impl CostCentre {
+    /// Derive the cost centre from event tags, if any dimension is tagged
+    pub fn from_tags(tags: &HashMap<String, String>) -> Option<Self> {
+        let tag = |keys: &[&str]| keys.iter().find_map(|k| tags.get(*k).cloned());
+        let centre = Self {
+            team: tag(&TEAM_TAGS),
+            product: tag(&PRODUCT_TAGS),
+            feature: tag(&FEATURE_TAGS),
+        };
+        (!centre.is_empty()).then_some(centre)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.team.is_none() && self.product.is_none() && self.feature.is_none()
+    }
+}
+
+impl fmt::Display for CostCentre {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let part = |p: &Option<String>| p.clone().unwrap_or_else(|| "-".to_string());
+        write!(
+            f,
+            "{}/{}/{}",
+            part(&self.team),
+            part(&self.product),
+            part(&self.feature)
+        )
+    }
+}
+
+/// Usage measure used to split shared costs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareBasis {
+    DirectCost,
+    Tokens,
+    Requests,
+}
+
+/// Fixed weight of a cost centre in an amortised reservation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CostCentreShare {
+    pub cost_centre: CostCentre,
+    pub weight: f64,
+}
+
+/// How a rule allocates spend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Allocation {
+    /// Charge costs whose tags all match to a fixed cost centre
+    Direct {
+        tags: HashMap<String, String>,
+        cost_centre: CostCentre,
+    },
+    /// Split untagged shared costs whose tags all match (an empty selector
+    /// matches every untagged cost) by the direct usage of each cost centre
+    Proportional {
+        #[serde(default)]
+        tags: HashMap<String, String>,
+        basis: ShareBasis,
+    },
+    /// Spread reserved capacity evenly over its term; without shares each
+    /// month's slice is split by direct cost
+    Amortized {
+        total_cost_usd: f64,
+        term_start: DateTime<Utc>,
+        term_end: DateTime<Utc>,
+        #[serde(default)]
+        shares: Vec<CostCentreShare>,
+    },
+}
+
+/// Cost allocation rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllocationRule {
+    pub rule_id: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Rules with a lower priority are evaluated first
+    #[serde(default)]
+    pub priority: i32,
+    pub allocation: Allocation,
+}
+
+impl AllocationRule {
+    /// Validate the rule
+    pub fn validate(&self) -> Result<()> {
+        if self.rule_id.is_empty() {
+            anyhow::bail!("Rule ID must not be empty");
+        }
+        match &self.allocation {
+            Allocation::Direct { tags, cost_centre } => {
+                if tags.is_empty() {
+                    anyhow::bail!("Direct rule {} needs a tag selector", self.rule_id);
+                }
+                if cost_centre.is_empty() {
+                    anyhow::bail!("Direct rule {} needs a cost centre", self.rule_id);
+                }
+            }
+            Allocation::Proportional { .. } => {}
+            Allocation::Amortized {
+                total_cost_usd,
+                term_start,
+                term_end,
+                shares,
+            } => {
+                if !(total_cost_usd.is_finite() && *total_cost_usd >= 0.0) {
+                    anyhow::bail!("Amortized rule {} needs a non-negative cost", self.rule_id);
+                }
+                if term_end <= term_start {
+                    anyhow::bail!(
+                        "Amortized rule {} term must end after it starts",
+                        self.rule_id
+                    );
+                }
+                if shares
+                    .iter()
+                    .any(|s| !(s.weight.is_finite() && s.weight >= 0.0))
+                    || (!shares.is_empty() && shares.iter().all(|s| s.weight == 0.0))
+                {
+                    anyhow::bail!("Amortized rule {} has invalid share weights", self.rule_id);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Usage charged to a cost centre
+#[derive(Debug, Clone, Copy, Default)]
+struct Usage {
+    cost_usd: f64,
+    tokens: u64,
+    requests: u64,
+}
+
+impl Usage {
+    fn add(&mut self, other: Usage) {
+        self.cost_usd += other.cost_usd;
+        self.tokens += other.tokens;
+        self.requests += other.requests;
+    }
+
+    fn basis(&self, basis: ShareBasis) -> f64 {
+        match basis {
+            ShareBasis::DirectCost => self.cost_usd,
+            ShareBasis::Tokens => self.tokens as f64,
+            ShareBasis::Requests => self.requests as f64,
+        }
+    }
+}
+
+/// Ledger account that spend is booked to
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LedgerAccount {
+    /// Spend charged directly to a cost centre
+    Direct { cost_centre: CostCentre },
+    /// Shared cost pooled by a proportional rule
+    Shared { rule_id: String, basis: ShareBasis },
+    /// Spend no rule could attribute
+    Unallocated,
+}
+
+/// Spend booked to an account in a month
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub period: BillingMonth,
+    pub account: LedgerAccount,
+    pub cost_usd: f64,
+    pub tokens: u64,
+    pub requests: u64,
+}
+
+/// Spend booked since the last flush, taken to be added to the persisted
+/// ledger
+#[derive(Debug, Default)]
+pub struct LedgerFlush {
+    pending: HashMap<Option<i32>, PendingSpend>,
+}
+
+impl LedgerFlush {
+    /// Booked spend per month and account
+    pub fn entries(&self) -> Vec<LedgerEntry> {
+        let mut months: HashMap<BillingMonth, MonthLedger> = HashMap::new();
+        for pending in self.pending.values() {
+            merge(&mut months, &pending.months);
+        }
+        let mut entries: Vec<LedgerEntry> = months
+            .into_iter()
+            .flat_map(|(period, ledger)| {
+                ledger.into_iter().map(move |(account, usage)| LedgerEntry {
+                    period,
+                    account,
+                    cost_usd: usage.cost_usd,
+                    tokens: usage.tokens,
+                    requests: usage.requests,
+                })
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.period);
+        entries
+    }
+
+    /// Partition -> offset of the last event booked
+    pub fn offsets(&self) -> HashMap<i32, i64> {
+        self.pending
+            .iter()
+            .filter_map(|(partition, pending)| Some(((*partition)?, pending.offsets?.1)))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Spend booked for one calendar month
+type MonthLedger = HashMap<LedgerAccount, Usage>;
+
+/// Spend booked since the last flush from one partition
+#[derive(Debug, Default)]
+struct PendingSpend {
+    months: HashMap<BillingMonth, MonthLedger>,
+    // First and last offset of the events booked
+    offsets: Option<(i64, i64)>,
+}
+
+impl PendingSpend {
+    fn book(&mut self, period: BillingMonth, account: LedgerAccount, usage: Usage) {
+        self.months
+            .entry(period)
+            .or_default()
+            .entry(account)
+            .or_default()
+            .add(usage);
+    }
+}
+
+/// Spend not yet flushed and the offsets booked per partition
+#[derive(Debug, Default)]
+struct Ledger {
+    // Partition -> spend booked since the last flush; events recorded
+    // without a partition are booked under `None`
+    pending: HashMap<Option<i32>, PendingSpend>,
+    // Partition -> offset of the last event booked, including flushed ones
+    booked: HashMap<i32, Option<i64>>,
+}
+
+/// Calendar month of a chargeback period
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct BillingMonth {
+    pub year: i32,
+    pub month: u32,
+}
+
+impl BillingMonth {
+    /// Month containing a timestamp
+    pub fn of(timestamp: DateTime<Utc>) -> Self {
+        Self {
+            year: timestamp.year(),
+            month: timestamp.month(),
+        }
+    }
+
+    /// First instant of the month
+    pub fn start(&self) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(self.year, self.month, 1, 0, 0, 0)
+            .single()
+            .expect("first of month is unambiguous in UTC")
+    }
+
+    /// First instant of the following month
+    pub fn end(&self) -> DateTime<Utc> {
+        let (year, month) = if self.month == 12 {
+            (self.year + 1, 1)
+        } else {
+            (self.year, self.month + 1)
+        };
+        Self { year, month }.start()
+    }
+}
+
+impl fmt::Display for BillingMonth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+impl std::str::FromStr for BillingMonth {
+    type Err = anyhow::Error;
+
+    /// Parse a `YYYY-MM` month
+    fn from_str(s: &str) -> Result<Self> {
+        let (year, month) = s
+            .split_once('-')
+            .with_context(|| format!("Invalid billing month {}: expected YYYY-MM", s))?;
+        let year = year
+            .parse()
+            .with_context(|| format!("Invalid billing month year: {}", year))?;
+        let month = month
+            .parse()
+            .with_context(|| format!("Invalid billing month: {}", month))?;
+        if !(1..=12).contains(&month) {
+            anyhow::bail!("Invalid billing month: {}", month);
+        }
+        Ok(Self { year, month })
+    }
+}
+
+/// Charge of one cost centre in a chargeback report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChargebackLine {
+    pub cost_centre: CostCentre,
+    pub direct_usd: f64,
+    pub shared_usd: f64,
+    pub amortized_usd: f64,
+    pub total_usd: f64,
+    /// Share of all attributed spend in percent
+    pub share_percent: f64,
+    pub tokens: u64,
+    pub requests: u64,
+}
+
+/// Comparison of attributed spend for a team with CostOps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TeamReconciliation {
+    pub team: String,
+    /// Direct and shared spend charged to the team
+    pub attributed_usd: f64,
+    pub costops_usd: f64,
+    pub difference_usd: f64,
+}
+
+/// Comparison of a chargeback report with CostOps totals
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reconciliation {
+    pub costops_summary_id: String,
+    pub costops_total_usd: f64,
+    /// Metered spend in the report (everything except amortised capacity)
+    pub metered_total_usd: f64,
+    pub difference_usd: f64,
+    /// Difference relative to the CostOps total
+    pub difference_ratio: Option<f64>,
+    pub within_tolerance: bool,
+    pub teams: Vec<TeamReconciliation>,
+}
+
+/// Monthly chargeback report per cost centre
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChargebackReport {
+    pub period: BillingMonth,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub lines: Vec<ChargebackLine>,
+    /// Spend no rule could attribute
+    pub unallocated_usd: f64,
+    pub total_usd: f64,
+    pub generated_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reconciliation: Option<Reconciliation>,
+}
+
+impl ChargebackReport {
+    /// Compare the report with a CostOps summary for the same month
+    pub fn reconcile(&mut self, summary: &CostSummary, tolerance: f64) -> Result<&Reconciliation> {
+        if !summary.currency.eq_ignore_ascii_case("USD") {
+            anyhow::bail!("Cannot reconcile against {} totals", summary.currency);
+        }
+        if summary.period_end <= self.period_start || summary.period_start >= self.period_end {
+            anyhow::bail!(
+                "CostOps summary {} does not overlap {}",
+                summary.summary_id,
+                self.period
+            );
+        }
+
+        let metered_total_usd = self
+            .lines
+            .iter()
+            .map(|l| l.direct_usd + l.shared_usd)
+            .sum::<f64>()
+            + self.unallocated_usd;
+        let difference_usd = metered_total_usd - summary.total_cost_usd;
+        let difference_ratio =
+            (summary.total_cost_usd != 0.0).then(|| difference_usd / summary.total_cost_usd);
+        let within_tolerance = match difference_ratio {
+            Some(ratio) => ratio.abs() <= tolerance,
+            None => difference_usd.abs() < f64::EPSILON,
+        };
+
+        let mut attributed: BTreeMap<String, f64> = BTreeMap::new();
+        for line in &self.lines {
+            if let Some(team) = &line.cost_centre.team {
+                *attributed.entry(team.clone()).or_default() += line.direct_usd + line.shared_usd;
+            }
+        }
+        for team in summary.breakdown.by_team.keys() {
+            attributed.entry(team.clone()).or_default();
+        }
+        let teams = attributed
+            .into_iter()
+            .map(|(team, attributed_usd)| {
+                let costops_usd = summary.breakdown.by_team.get(&team).copied().unwrap_or(0.0);
+                TeamReconciliation {
+                    team,
+                    attributed_usd,
+                    costops_usd,
+                    difference_usd: attributed_usd - costops_usd,
+                }
+            })
+            .collect();
+
+        debug!(period = %self.period, difference_usd, within_tolerance, "Reconciled chargeback");
+        Ok(self.reconciliation.insert(Reconciliation {
+            costops_summary_id: summary.summary_id.clone(),
+            costops_total_usd: summary.total_cost_usd,
+            metered_total_usd,
+            difference_usd,
+            difference_ratio,
+            within_tolerance,
+            teams,
+        }))
+    }
+
+    /// Render the report as CSV, one row per cost centre plus unallocated spend
+    pub fn to_csv(&self) -> String {
+        let escape = |s: &str| {
+            if s.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", s.replace('"', "\"\""))
+            } else {
+                s.to_string()
+            }
+        };
+        let field = |p: &Option<String>| p.as_deref().map(escape).unwrap_or_default();
+
+        let mut out = String::from(
+            "period,cost_centre,team,product,feature,direct_usd,shared_usd,amortized_usd,\
+             total_usd,share_percent,tokens,requests\n",
+        );
+        for line in &self.lines {
+            out.push_str(&format!(
+                "{},{},{},{},{},{:.6},{:.6},{:.6},{:.6},{:.4},{},{}\n",
+                self.period,
+                escape(&line.cost_centre.to_string()),
+                field(&line.cost_centre.team),
+                field(&line.cost_centre.product),
+                field(&line.cost_centre.feature),
+                line.direct_usd,
+                line.shared_usd,
+                line.amortized_usd,
+                line.total_usd,
+                line.share_percent,
+                line.tokens,
+                line.requests
+            ));
+        }
+        if self.unallocated_usd != 0.0 {
+            out.push_str(&format!(
+                "{},unallocated,,,,{:.6},0.000000,0.000000,{:.6},,0,0\n",
+                self.period, self.unallocated_usd, self.unallocated_usd
+            ));
+        }
+        out
+    }
+
+    /// Render the report as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("Failed to serialize chargeback report")
+    }
+}
+
+/// Engine attributing spend to cost centres
+pub struct ChargebackEngine {
+    config: ChargebackConfig,
+    // Rule ID -> Rule
+    rules: Arc<DashMap<String, AllocationRule>>,
+    ledger: Arc<Mutex<Ledger>>,
+}
+
+impl ChargebackEngine {
+    /// Create a new chargeback engine
+    pub fn new(config: ChargebackConfig) -> Self {
+        Self {
+            config,
+            rules: Arc::new(DashMap::new()),
+            ledger: Arc::new(Mutex::new(Ledger::default())),
+        }
+    }
+
+    /// Add or replace an allocation rule. Direct and proportional rules apply
+    /// to events recorded afterwards.
+    pub fn upsert_rule(&self, rule: AllocationRule) -> Result<()> {
+        rule.validate()?;
+        info!(rule_id = %rule.rule_id, "Registering allocation rule");
+        self.rules.insert(rule.rule_id.clone(), rule);
+        Ok(())
+    }
+
+    /// Remove an allocation rule
+    pub fn remove_rule(&self, rule_id: &str) -> Option<AllocationRule> {
+        self.rules.remove(rule_id).map(|(_, rule)| rule)
+    }
+
+    /// Allocation rules in evaluation order
+    pub fn rules(&self) -> Vec<AllocationRule> {
+        let mut rules: Vec<AllocationRule> = self.rules.iter().map(|r| r.clone()).collect();
+        rules.sort_by(|a, b| a.priority.cmp(&b.priority).then(a.rule_id.cmp(&b.rule_id)));
+        rules
+    }
+
+    /// Record a cost event, returning whether it carried any spend
+    pub fn record_event(&self, event: &AnalyticsEvent) -> bool {
+        let Some((period, account, usage)) = self.attribute(event) else {
+            return false;
+        };
+        self.ledger
+            .lock()
+            .pending
+            .entry(None)
+            .or_default()
+            .book(period, account, usage);
+        true
+    }
+
+    /// Record a cost event consumed from `partition` at `offset`, skipping
+    /// events at or before the last offset booked on the partition. Returns
+    /// whether the event was booked.
+    pub fn record_consumed(&self, event: &AnalyticsEvent, partition: i32, offset: i64) -> bool {
+        let mut ledger = self.ledger.lock();
+        if matches!(ledger.booked.get(&partition), Some(Some(booked)) if offset <= *booked) {
+            return false;
+        }
+        let Some((period, account, usage)) = self.attribute(event) else {
+            return false;
+        };
+        let pending = ledger.pending.entry(Some(partition)).or_default();
+        pending.book(period, account, usage);
+        pending.offsets = Some(match pending.offsets {
+            Some((first, _)) => (first, offset),
+            None => (offset, offset),
+        });
+        ledger.booked.insert(partition, Some(offset));
+        true
+    }
+
+    /// Attribute the spend of a cost event to a month and account
+    fn attribute(&self, event: &AnalyticsEvent) -> Option<(BillingMonth, LedgerAccount, Usage)> {
+        let usage = match &event.payload {
+            EventPayload::Cost(CostPayload::TokenCost(c)) => Usage {
+                cost_usd: c.total_cost_usd,
+                tokens: c.total_tokens as u64,
+                requests: 1,
+            },
+            EventPayload::Cost(CostPayload::ApiCost(c)) => Usage {
+                cost_usd: c.total_cost_usd,
+                tokens: 0,
+                requests: c.request_count,
+            },
+            EventPayload::Cost(CostPayload::ResourceConsumption(c)) => Usage {
+                cost_usd: c.cost_usd,
+                tokens: 0,
+                requests: 0,
+            },
+            _ => return None,
+        };
+        if !usage.cost_usd.is_finite() {
+            return None;
+        }
+
+        let tags = &event.common.tags;
+        let rules = self.rules();
+        let direct = rules
+            .iter()
+            .find_map(|rule| match &rule.allocation {
+                Allocation::Direct {
+                    tags: selector,
+                    cost_centre,
+                } if matches(selector, tags) => Some(cost_centre.clone()),
+                _ => None,
+            })
+            .or_else(|| CostCentre::from_tags(tags));
+
+        let account = match direct {
+            Some(cost_centre) => LedgerAccount::Direct { cost_centre },
+            None => rules
+                .iter()
+                .find_map(|rule| match &rule.allocation {
+                    Allocation::Proportional {
+                        tags: selector,
+                        basis,
+                    } if matches(selector, tags) => Some(LedgerAccount::Shared {
+                        rule_id: rule.rule_id.clone(),
+                        basis: *basis,
+                    }),
+                    _ => None,
+                })
+                .unwrap_or(LedgerAccount::Unallocated),
+        };
+        Some((BillingMonth::of(event.common.timestamp), account, usage))
+    }
+
+    /// Whether the booked offset of a partition is known
+    pub fn tracks_partition(&self, partition: i32) -> bool {
+        self.ledger.lock().booked.contains_key(&partition)
+    }
+
+    /// Start tracking a partition whose events were booked up to `booked`
+    pub fn track_partition(&self, partition: i32, booked: Option<i64>) {
+        self.ledger.lock().booked.insert(partition, booked);
+    }
+
+    /// Stop tracking a partition, e.g. after it was revoked. Its unflushed
+    /// spend is dropped, since its offsets are not committed and the new
+    /// owner books those events again.
+    pub fn forget_partition(&self, partition: i32) {
+        let mut ledger = self.ledger.lock();
+        ledger.booked.remove(&partition);
+        ledger.pending.remove(&Some(partition));
+    }
+
+    /// Partition -> earliest offset whose spend is not flushed yet
+    pub fn held_offsets(&self) -> HashMap<i32, i64> {
+        self.ledger
+            .lock()
+            .pending
+            .iter()
+            .filter_map(|(partition, pending)| Some(((*partition)?, pending.offsets?.0)))
+            .collect()
+    }
+
+    /// Take the spend booked since the last flush
+    pub fn take_flush(&self) -> LedgerFlush {
+        LedgerFlush {
+            pending: std::mem::take(&mut self.ledger.lock().pending),
+        }
+    }
+
+    /// Return a flush that could not be persisted, to be taken again with
+    /// the next one. Spend of partitions revoked meanwhile is dropped.
+    pub fn restore_flush(&self, flush: LedgerFlush) {
+        let mut ledger = self.ledger.lock();
+        for (partition, restored) in flush.pending {
+            if partition.is_some_and(|p| !ledger.booked.contains_key(&p)) {
+                continue;
+            }
+            let pending = ledger.pending.entry(partition).or_default();
+            merge(&mut pending.months, &restored.months);
+            pending.offsets = match (restored.offsets, pending.offsets) {
+                (Some((first, last)), Some((_, later))) => Some((first, last.max(later))),
+                (restored, current) => restored.or(current),
+            };
+        }
+    }
+
+    /// Build the chargeback report for a month from the spend booked since
+    /// the last flush
+    pub fn report(&self, period: BillingMonth, now: DateTime<Utc>) -> ChargebackReport {
+        let mut months = HashMap::new();
+        for pending in self.ledger.lock().pending.values() {
+            merge(&mut months, &pending.months);
+        }
+        let ledger = months.remove(&period).unwrap_or_default();
+        self.build_report(period, &ledger, now)
+    }
+
+    /// Build the chargeback report for a month from ledger entries, e.g. the
+    /// ledger persisted by every instance
+    pub fn report_from_entries(
+        &self,
+        period: BillingMonth,
+        entries: &[LedgerEntry],
+        now: DateTime<Utc>,
+    ) -> ChargebackReport {
+        let mut ledger = MonthLedger::new();
+        for entry in entries.iter().filter(|e| e.period == period) {
+            ledger.entry(entry.account.clone()).or_default().add(Usage {
+                cost_usd: entry.cost_usd,
+                tokens: entry.tokens,
+                requests: entry.requests,
+            });
+        }
+        self.build_report(period, &ledger, now)
+    }
+
+    fn build_report(
+        &self,
+        period: BillingMonth,
+        ledger: &MonthLedger,
+        now: DateTime<Utc>,
+    ) -> ChargebackReport {
+        let mut direct: BTreeMap<CostCentre, Usage> = BTreeMap::new();
+        let mut shared_pools = Vec::new();
+        let mut unallocated_usd = 0.0;
+        for (account, usage) in ledger {
+            match account {
+                LedgerAccount::Direct { cost_centre } => {
+                    direct.insert(cost_centre.clone(), *usage);
+                }
+                LedgerAccount::Shared { basis, .. } => shared_pools.push((*basis, usage.cost_usd)),
+                LedgerAccount::Unallocated => unallocated_usd += usage.cost_usd,
+            }
+        }
+
+        let mut shared: BTreeMap<CostCentre, f64> = BTreeMap::new();
+        for (basis, pool_usd) in shared_pools {
+            let weights: Vec<(CostCentre, f64)> = direct
+                .iter()
+                .map(|(centre, usage)| (centre.clone(), usage.basis(basis)))
+                .collect();
+            if !split(pool_usd, &weights, &mut shared) {
+                unallocated_usd += pool_usd;
+            }
+        }
+
+        let mut amortized: BTreeMap<CostCentre, f64> = BTreeMap::new();
+        for rule in self.rules() {
+            let Allocation::Amortized {
+                total_cost_usd,
+                term_start,
+                term_end,
+                shares,
+            } = &rule.allocation
+            else {
+                continue;
+            };
+            let overlap = (*term_end).min(period.end()) - (*term_start).max(period.start());
+            if overlap <= chrono::Duration::zero() {
+                continue;
+            }
+            let term = *term_end - *term_start;
+            let slice_usd =
+                total_cost_usd * overlap.num_seconds() as f64 / term.num_seconds() as f64;
+
+            let weights: Vec<(CostCentre, f64)> = if shares.is_empty() {
+                direct
+                    .iter()
+                    .map(|(centre, usage)| (centre.clone(), usage.cost_usd))
+                    .collect()
+            } else {
+                shares
+                    .iter()
+                    .map(|s| (s.cost_centre.clone(), s.weight))
+                    .collect()
+            };
+            if !split(slice_usd, &weights, &mut amortized) {
+                unallocated_usd += slice_usd;
+            }
+        }
+
+        let mut centres: Vec<CostCentre> = direct.keys().cloned().collect();
+        centres.extend(shared.keys().cloned());
+        centres.extend(amortized.keys().cloned());
+        centres.sort();
+        centres.dedup();
+
+        let mut lines: Vec<ChargebackLine> = centres
+            .into_iter()
+            .map(|centre| {
+                let usage = direct.get(&centre).copied().unwrap_or_default();
+                let shared_usd = shared.get(&centre).copied().unwrap_or(0.0);
+                let amortized_usd = amortized.get(&centre).copied().unwrap_or(0.0);
+                ChargebackLine {
+                    cost_centre: centre,
+                    direct_usd: usage.cost_usd,
+                    shared_usd,
+                    amortized_usd,
+                    total_usd: usage.cost_usd + shared_usd + amortized_usd,
+                    share_percent: 0.0,
+                    tokens: usage.tokens,
+                    requests: usage.requests,
+                }
+            })
+            .collect();
+
+        let attributed_usd: f64 = lines.iter().map(|l| l.total_usd).sum();
+        if attributed_usd > 0.0 {
+            for line in &mut lines {
+                line.share_percent = 100.0 * line.total_usd / attributed_usd;
+            }
+        }
+        lines.sort_by(|a, b| {
+            b.total_usd
+                .total_cmp(&a.total_usd)
+                .then_with(|| a.cost_centre.cmp(&b.cost_centre))
+        });
+
+        ChargebackReport {
+            period,
+            period_start: period.start(),
+            period_end: period.end(),
+            lines,
+            unallocated_usd,
+            total_usd: attributed_usd + unallocated_usd,
+            generated_at: now,
+            reconciliation: None,
+        }
+    }
+
+    /// Reconcile a report against the CostOps summary for its month with
+    /// the configured tolerance
+    pub fn reconcile_report(
+        &self,
+        report: &mut ChargebackReport,
+        summary: &CostSummary,
+    ) -> Result<()> {
+        report.reconcile(summary, self.config.reconciliation_tolerance)?;
+        Ok(())
+    }
+
+    /// Months with spend booked since the last flush
+    pub fn months(&self) -> Vec<BillingMonth> {
+        let ledger = self.ledger.lock();
+        let mut months: Vec<BillingMonth> = ledger
+            .pending
+            .values()
+            .flat_map(|pending| pending.months.keys().copied())
+            .collect();
+        months.sort();
+        months.dedup();
+        months
+    }
+}
+
+impl Default for ChargebackEngine {
+    fn default() -> Self {
+        Self::new(ChargebackConfig::default())
+    }
+}
+
+/// Add monthly ledgers into another
+fn merge(
+    into: &mut HashMap<BillingMonth, MonthLedger>,
+    months: &HashMap<BillingMonth, MonthLedger>,
+) {
+    for (period, ledger) in months {
+        let month = into.entry(*period).or_default();
+        for (account, usage) in ledger {
+            month.entry(account.clone()).or_default().add(*usage);
+        }
+    }
+}
+
+/// Whether every selector tag is present on the event
+fn matches(selector: &HashMap<String, String>, tags: &HashMap<String, String>) -> bool {
+    selector.iter().all(|(k, v)| tags.get(k) == Some(v))
+}
+
+/// Split `amount` by weight into `into`, returning false if no weight is positive
+fn split(amount: f64, weights: &[(CostCentre, f64)], into: &mut BTreeMap<CostCentre, f64>) -> bool {
+    let total: f64 = weights.iter().map(|(_, w)| w.max(0.0)).sum();
+    if total <= 0.0 {
+        return false;
+    }
+    for (centre, weight) in weights {
+        if *weight > 0.0 {
+            *into.entry(centre.clone()).or_default() += amount * weight / total;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::costops::CostBreakdown;
+    use crate::schemas::events::{
+        fixtures, ResourceConsumptionEvent, ResourceType, SourceModule, TokenCostEvent,
+    };
+    use uuid::Uuid;
+
+    fn at(day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, day, 12, 0, 0).unwrap()
+    }
+
+    fn event(
+        timestamp: DateTime<Utc>,
+        tags: &[(&str, &str)],
+        payload: CostPayload,
+    ) -> AnalyticsEvent {
+        fixtures::event(EventPayload::Cost(payload))
+            .at(timestamp)
+            .with_source(SourceModule::LlmCostOps)
+            .with_tags(tags)
+    }
+
+    fn token_cost(timestamp: DateTime<Utc>, tags: &[(&str, &str)], cost: f64) -> AnalyticsEvent {
+        event(
+            timestamp,
+            tags,
+            CostPayload::TokenCost(TokenCostEvent {
+                model_id: "gpt-4".to_string(),
+                request_id: Uuid::new_v4().to_string(),
+                prompt_tokens: 800,
+                completion_tokens: 200,
+                total_tokens: 1000,
+                cost_per_prompt_token: 0.0,
+                cost_per_completion_token: 0.0,
+                total_cost_usd: cost,
+                currency: "USD".to_string(),
+            }),
+        )
+    }
+
+    fn gpu(timestamp: DateTime<Utc>, tags: &[(&str, &str)], cost: f64) -> AnalyticsEvent {
+        event(
+            timestamp,
+            tags,
+            CostPayload::ResourceConsumption(ResourceConsumptionEvent {
+                resource_type: ResourceType::Gpu,
+                resource_id: "gpu-pool".to_string(),
+                quantity: 1.0,
+                unit: "hour".to_string(),
+                cost_usd: cost,
+                utilization_percent: 80.0,
+            }),
+        )
+    }
+
+    fn team(name: &str) -> CostCentre {
+        CostCentre {
+            team: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn line<'a>(report: &'a ChargebackReport, name: &str) -> &'a ChargebackLine {
+        report
+            .lines
+            .iter()
+            .find(|l| l.cost_centre.team.as_deref() == Some(name))
+            .unwrap()
+    }
+
+    const OCTOBER: BillingMonth = BillingMonth {
+        year: 2026,
+        month: 10,
+    };
+
+    #[test]
+    fn test_direct_and_proportional_allocation() {
+        let engine = ChargebackEngine::default();
+        engine
+            .upsert_rule(AllocationRule {
+                rule_id: "shared-gpu".to_string(),
+                description: None,
+                priority: 10,
+                allocation: Allocation::Proportional {
+                    tags: HashMap::from([("shared".to_string(), "gpu".to_string())]),
+                    basis: ShareBasis::DirectCost,
+                },
+            })
+            .unwrap();
+        engine
+            .upsert_rule(AllocationRule {
+                rule_id: "legacy-key".to_string(),
+                description: None,
+                priority: 0,
+                allocation: Allocation::Direct {
+                    tags: HashMap::from([("api_key".to_string(), "k-123".to_string())]),
+                    cost_centre: team("search"),
+                },
+            })
+            .unwrap();
+
+        assert!(engine.record_event(&token_cost(at(1), &[("team", "search")], 30.0)));
+        engine.record_event(&token_cost(
+            at(2),
+            &[("team", "chat"), ("feature", "summary")],
+            50.0,
+        ));
+        engine.record_event(&token_cost(at(3), &[("api_key", "k-123")], 10.0));
+        engine.record_event(&gpu(at(4), &[("shared", "gpu")], 90.0));
+        engine.record_event(&gpu(at(5), &[], 7.0));
+        let november = Utc.with_ymd_and_hms(2026, 11, 2, 12, 0, 0).unwrap();
+        engine.record_event(&token_cost(november, &[("team", "chat")], 1000.0));
+
+        let report = engine.report(OCTOBER, at(20));
+        assert_eq!(report.lines.len(), 2);
+
+        let search = line(&report, "search");
+        assert_eq!(search.direct_usd, 40.0);
+        assert_eq!(search.requests, 2);
+        assert!((search.shared_usd - 40.0).abs() < 1e-9);
+
+        let chat = line(&report, "chat");
+        assert_eq!(chat.cost_centre.feature.as_deref(), Some("summary"));
+        assert!((chat.shared_usd - 50.0).abs() < 1e-9);
+
+        assert_eq!(report.unallocated_usd, 7.0);
+        assert!((report.total_usd - 187.0).abs() < 1e-9);
+        assert_eq!(engine.months().len(), 2);
+    }
+
+    #[test]
+    fn test_reserved_capacity_is_amortized_by_month() {
+        let engine = ChargebackEngine::default();
+        // One year reservation starting 2026-01-01
+        let term_start = Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap();
+        let term_end = Utc.with_ymd_and_hms(2027, 1, 1, 0, 0, 0).unwrap();
+        engine
+            .upsert_rule(AllocationRule {
+                rule_id: "reserved-a100".to_string(),
+                description: Some("Reserved A100 capacity".to_string()),
+                priority: 0,
+                allocation: Allocation::Amortized {
+                    total_cost_usd: 36_500.0,
+                    term_start,
+                    term_end,
+                    shares: vec![
+                        CostCentreShare {
+                            cost_centre: team("search"),
+                            weight: 3.0,
+                        },
+                        CostCentreShare {
+                            cost_centre: team("chat"),
+                            weight: 1.0,
+                        },
+                    ],
+                },
+            })
+            .unwrap();
+
+        // October has 31 days at $100 per day
+        let report = engine.report(OCTOBER, at(20));
+        assert!((line(&report, "search").amortized_usd - 2325.0).abs() < 1e-6);
+        assert!((line(&report, "chat").amortized_usd - 775.0).abs() < 1e-6);
+        assert!((report.total_usd - 3100.0).abs() < 1e-6);
+
+        let next_year = engine.report(
+            BillingMonth {
+                year: 2027,
+                month: 1,
+            },
+            at(20),
+        );
+        assert!(next_year.lines.is_empty());
+    }
+
+    #[test]
+    fn test_reconciles_against_costops_and_exports() {
+        let engine = ChargebackEngine::default();
+        engine.record_event(&token_cost(at(1), &[("team", "search")], 60.0));
+        engine.record_event(&token_cost(at(2), &[("team", "chat, internal")], 40.0));
+
+        let summary = CostSummary {
+            summary_id: "oct".to_string(),
+            period_start: OCTOBER.start(),
+            period_end: OCTOBER.end(),
+            total_cost_usd: 100.5,
+            breakdown: CostBreakdown {
+                by_provider: HashMap::new(),
+                by_model: HashMap::new(),
+                by_operation: HashMap::new(),
+                by_team: HashMap::from([
+                    ("search".to_string(), 60.5),
+                    ("chat, internal".to_string(), 40.0),
+                ]),
+            },
+            top_consumers: Vec::new(),
+            currency: "USD".to_string(),
+        };
+
+        let mut report = engine.report(OCTOBER, at(20));
+        engine.reconcile_report(&mut report, &summary).unwrap();
+        let reconciliation = report.reconciliation.as_ref().unwrap();
+        assert!(reconciliation.within_tolerance);
+        assert!((reconciliation.difference_usd + 0.5).abs() < 1e-9);
+        let search = reconciliation
+            .teams
+            .iter()
+            .find(|t| t.team == "search")
+            .unwrap();
+        assert!((search.difference_usd + 0.5).abs() < 1e-9);
+
+        let csv = report.to_csv();
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows.len(), 3);
+        assert!(rows[1].starts_with("2026-10,search/-/-,search,,,60.000000"));
+        assert!(rows[2].contains("\"chat, internal/-/-\",\"chat, internal\""));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["period"]["month"], 10);
+        assert_eq!(json["lines"].as_array().unwrap().len(), 2);
+
+        let mut other = summary.clone();
+        other.period_start = Utc.with_ymd_and_hms(2026, 8, 1, 0, 0, 0).unwrap();
+        other.period_end = Utc.with_ymd_and_hms(2026, 9, 1, 0, 0, 0).unwrap();
+        assert!(engine
+            .reconcile_report(&mut engine.report(OCTOBER, at(20)), &other)
+            .is_err());
+    }
+
+    #[test]
+    fn test_flushes_booked_spend_once_per_offset() {
+        let engine = ChargebackEngine::default();
+        engine.track_partition(0, Some(4));
+        assert!(!engine.record_consumed(&token_cost(at(1), &[("team", "search")], 10.0), 0, 4));
+        assert!(engine.record_consumed(&token_cost(at(1), &[("team", "search")], 10.0), 0, 5));
+        assert!(engine.record_consumed(&token_cost(at(2), &[("team", "chat")], 5.0), 0, 7));
+        assert!(!engine.record_consumed(&token_cost(at(2), &[("team", "chat")], 5.0), 0, 6));
+        assert_eq!(engine.held_offsets(), HashMap::from([(0, 5)]));
+
+        // A failed flush is restored and taken again with the next one
+        let flush = engine.take_flush();
+        assert!(engine.held_offsets().is_empty());
+        engine.restore_flush(flush);
+        engine.track_partition(1, None);
+        engine.record_consumed(&token_cost(at(3), &[("team", "search")], 2.5), 1, 0);
+
+        let flush = engine.take_flush();
+        assert_eq!(flush.offsets(), HashMap::from([(0, 7), (1, 0)]));
+        let entries = flush.entries();
+        assert_eq!(entries.len(), 2);
+        assert!(engine.take_flush().is_empty());
+
+        // Reports add up entries flushed by several instances
+        let mut persisted = entries.clone();
+        persisted.extend(entries);
+        let report = engine.report_from_entries(OCTOBER, &persisted, at(20));
+        assert!((line(&report, "search").total_usd - 25.0).abs() < 1e-9);
+        assert!((line(&report, "chat").total_usd - 10.0).abs() < 1e-9);
+
+        // Unflushed spend of a revoked partition is dropped
+        engine.record_consumed(&token_cost(at(4), &[("team", "chat")], 1.0), 1, 1);
+        engine.forget_partition(1);
+        assert!(engine.take_flush().is_empty());
+        assert!(!engine.tracks_partition(1));
+    }
+
+    #[test]
+    fn test_parses_billing_month() {
+        assert_eq!("2026-10".parse::<BillingMonth>().unwrap(), OCTOBER);
+        assert_eq!(
+            OCTOBER.to_string().parse::<BillingMonth>().unwrap(),
+            OCTOBER
+        );
+        assert!("2026-13".parse::<BillingMonth>().is_err());
+        assert!("202610".parse::<BillingMonth>().is_err());
+    }
+}
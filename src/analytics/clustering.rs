@@ -0,0 +1,1024 @@
+//! Request Clustering
+//!
+//! Groups requests into workload clusters to show which kinds of traffic
+//! drive cost and latency. A request profile is assembled from the events
+//! sharing a `request_id` (latency, token usage, token cost, and an optional
+//! `request_embedding` custom payload) and described by:
+//! - input/output token ratio (log scale)
+//! - total latency (log scale)
+//! - tool-call count (`tool_calls` tag or custom payload field)
+//! - custom embedding vector
+//! - model (one-hot)
+//!
+//! Clusters are fitted with KMeans or DBSCAN from `linfa-clustering`. Refits
+//! keep cluster IDs stable by matching centroids, and each refit is recorded
+//! as a drift report. Events of a profiled request are tagged with
+//! `cluster_id` once the request has been assigned.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use linfa::traits::{Fit, Transformer};
+use linfa::{DatasetBase, ParamGuard};
+use linfa_clustering::{Dbscan, KMeans};
+use ndarray::{Array1, Array2};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::{debug, info};
+
+use super::drift::psi;
+use super::stats;
+use crate::schemas::events::{AnalyticsEvent, CostPayload, EventPayload, TelemetryPayload};
+
+/// Tag carrying the cluster a request was assigned to
+pub const CLUSTER_ID_TAG: &str = "cluster_id";
+
+/// Tag carrying the number of tool calls made by a request
+pub const TOOL_CALLS_TAG: &str = "tool_calls";
+
+/// Custom payload type carrying a request embedding
+pub const EMBEDDING_CUSTOM_TYPE: &str = "request_embedding";
+
+/// Cluster ID given to requests that fit no cluster (DBSCAN noise)
+pub const NOISE_CLUSTER: &str = "noise";
+
+/// Latency samples kept per cluster for percentiles
+const LATENCY_SAMPLES: usize = 1000;
+
+/// Drift reports kept in the history
+const DRIFT_HISTORY: usize = 100;
+
+/// Clustering algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "snake_case")]
+pub enum ClusteringAlgorithm {
+    KMeans {
+        k: usize,
+    },
+    /// Density-based clustering; `eps` is measured in standardized units
+    Dbscan {
+        eps: f64,
+        min_points: usize,
+    },
+}
+
+/// Request clustering configuration
+#[derive(Debug, Clone)]
+pub struct ClusteringConfig {
+    pub algorithm: ClusteringAlgorithm,
+    /// Length of request embeddings; other lengths are ignored (0 disables)
+    pub embedding_dims: usize,
+    /// Weight of the model one-hot features relative to standardized ones
+    pub model_weight: f64,
+    /// Most recent complete profiles kept for fitting
+    pub max_training_samples: usize,
+    /// Complete profiles required before fitting
+    pub min_training_samples: usize,
+    /// Centroid distance (standardized units) within which a refitted
+    /// cluster keeps the ID of a previous cluster
+    pub match_distance: f64,
+    /// How long incomplete request profiles are kept
+    pub profile_ttl: Duration,
+}
+
+impl Default for ClusteringConfig {
+    fn default() -> Self {
+        Self {
+            algorithm: ClusteringAlgorithm::KMeans { k: 8 },
+            embedding_dims: 0,
+            model_weight: 1.0,
+            max_training_samples: 10_000,
+            min_training_samples: 200,
+            match_distance: 1.0,
+            profile_ttl: Duration::minutes(15),
+        }
+    }
+}
+
+/// Features of one request, assembled from its events
+#[derive(Debug, Clone)]
+struct RequestProfile {
+    model_id: Option<String>,
+    tokens: Option<(u32, u32)>,
+    latency_ms: Option<f64>,
+    cost_usd: Option<f64>,
+    tool_calls: u32,
+    embedding: Option<Vec<f64>>,
+    first_seen: DateTime<Utc>,
+    complete: bool,
+    cluster_id: Option<String>,
+    cost_counted: bool,
+}
+
+impl RequestProfile {
+    fn new(first_seen: DateTime<Utc>) -> Self {
+        Self {
+            model_id: None,
+            tokens: None,
+            latency_ms: None,
+            cost_usd: None,
+            tool_calls: 0,
+            embedding: None,
+            first_seen,
+            complete: false,
+            cluster_id: None,
+            cost_counted: false,
+        }
+    }
+
+    /// Numeric features before standardization
+    fn numeric(&self, embedding_dims: usize) -> Vec<Option<f64>> {
+        let (prompt, completion) = self.tokens.unwrap_or((0, 0));
+        let mut features = vec![
+            Some(((prompt as f64 + 1.0) / (completion as f64 + 1.0)).ln()),
+            self.latency_ms.map(|l| l.max(0.0).ln_1p()),
+            Some(self.tool_calls as f64),
+        ];
+        match &self.embedding {
+            Some(embedding) if embedding.len() == embedding_dims => {
+                features.extend(embedding.iter().map(|&v| Some(v)))
+            }
+            _ => features.extend(std::iter::repeat(None).take(embedding_dims)),
+        }
+        features
+    }
+}
+
+/// Centroid of a cluster in raw feature units
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterCentroid {
+    /// Mean log input/output token ratio
+    pub log_token_ratio: f64,
+    /// Mean log(1 + latency ms)
+    pub log_latency_ms: f64,
+    pub tool_calls: f64,
+    pub embedding: Vec<f64>,
+    /// Share of each model among the cluster's requests
+    pub models: HashMap<String, f64>,
+}
+
+impl ClusterCentroid {
+    fn numeric(&self) -> Vec<f64> {
+        let mut numeric = vec![self.log_token_ratio, self.log_latency_ms, self.tool_calls];
+        numeric.extend(&self.embedding);
+        numeric
+    }
+}
+
+/// Standardization and model encoding fitted on a training set
+#[derive(Debug, Clone)]
+struct FeatureSpace {
+    means: Vec<f64>,
+    std_devs: Vec<f64>,
+    models: Vec<String>,
+    embedding_dims: usize,
+    model_weight: f64,
+}
+
+impl FeatureSpace {
+    fn fit(profiles: &[RequestProfile], embedding_dims: usize, model_weight: f64) -> Self {
+        let width = 3 + embedding_dims;
+        let mut means = Vec::with_capacity(width);
+        let mut std_devs = Vec::with_capacity(width);
+        let rows: Vec<Vec<Option<f64>>> =
+            profiles.iter().map(|p| p.numeric(embedding_dims)).collect();
+        for column in 0..width {
+            let values: Vec<f64> = rows.iter().filter_map(|r| r[column]).collect();
+            means.push(stats::mean(&values));
+            let std_dev = stats::std_dev(&values);
+            std_devs.push(if std_dev > 0.0 { std_dev } else { 1.0 });
+        }
+
+        let models: BTreeSet<String> = profiles.iter().filter_map(|p| p.model_id.clone()).collect();
+        Self {
+            means,
+            std_devs,
+            models: models.into_iter().collect(),
+            embedding_dims,
+            model_weight,
+        }
+    }
+
+    fn dims(&self) -> usize {
+        self.means.len() + self.models.len()
+    }
+
+    /// Standardize raw numeric features; missing values map to the mean
+    fn standardize(&self, numeric: &[Option<f64>]) -> Vec<f64> {
+        numeric
+            .iter()
+            .zip(self.means.iter().zip(&self.std_devs))
+            .map(|(value, (mean, std_dev))| value.map_or(0.0, |v| (v - mean) / std_dev))
+            .collect()
+    }
+
+    fn encode_models(&self, shares: &HashMap<String, f64>) -> Vec<f64> {
+        self.models
+            .iter()
+            .map(|m| self.model_weight * shares.get(m).copied().unwrap_or(0.0))
+            .collect()
+    }
+
+    fn vector(&self, profile: &RequestProfile) -> Vec<f64> {
+        let mut vector = self.standardize(&profile.numeric(self.embedding_dims));
+        let shares = profile
+            .model_id
+            .iter()
+            .map(|m| (m.clone(), 1.0))
+            .collect::<HashMap<_, _>>();
+        vector.extend(self.encode_models(&shares));
+        vector
+    }
+
+    fn centroid_vector(&self, centroid: &ClusterCentroid) -> Vec<f64> {
+        let numeric: Vec<Option<f64>> = centroid.numeric().into_iter().map(Some).collect();
+        let mut vector = self.standardize(&numeric);
+        vector.extend(self.encode_models(&centroid.models));
+        vector
+    }
+}
+
+fn distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// A fitted cluster
+#[derive(Debug, Clone)]
+struct FittedCluster {
+    cluster_id: String,
+    centroid: ClusterCentroid,
+    vector: Vec<f64>,
+    share: f64,
+}
+
+/// Model produced by the latest fit
+#[derive(Debug)]
+struct FittedModel {
+    space: FeatureSpace,
+    clusters: Vec<FittedCluster>,
+    /// Clustered training points and their cluster index (DBSCAN only)
+    core_points: Vec<(Vec<f64>, usize)>,
+    eps: Option<f64>,
+    fitted_at: DateTime<Utc>,
+}
+
+impl FittedModel {
+    fn assign(&self, profile: &RequestProfile) -> String {
+        let vector = self.space.vector(profile);
+        let nearest = match self.eps {
+            // DBSCAN: cluster of the nearest clustered training point within eps
+            Some(eps) => self
+                .core_points
+                .iter()
+                .map(|(point, cluster)| (distance(point, &vector), *cluster))
+                .filter(|(d, _)| *d <= eps)
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, cluster)| cluster),
+            None => self
+                .clusters
+                .iter()
+                .enumerate()
+                .map(|(i, c)| (distance(&c.vector, &vector), i))
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, cluster)| cluster),
+        };
+
+        nearest
+            .map(|i| self.clusters[i].cluster_id.clone())
+            .unwrap_or_else(|| NOISE_CLUSTER.to_string())
+    }
+}
+
+/// How a cluster changed between fits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ClusterChange {
+    /// Matched a previous cluster
+    Stable,
+    /// No previous cluster was close enough
+    Emerged,
+}
+
+/// Drift of one cluster between fits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterDrift {
+    pub cluster_id: String,
+    pub change: ClusterChange,
+    /// Distance moved by the centroid, in standardized units
+    pub centroid_shift: Option<f64>,
+    pub previous_share: Option<f64>,
+    pub share: f64,
+    pub centroid: ClusterCentroid,
+}
+
+/// Result of a (re)fit
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterDriftReport {
+    pub fitted_at: DateTime<Utc>,
+    pub algorithm: ClusteringAlgorithm,
+    pub samples: usize,
+    /// Training requests that fit no cluster
+    pub noise_samples: usize,
+    pub clusters: Vec<ClusterDrift>,
+    /// Previous clusters with no successor
+    pub vanished: Vec<String>,
+    /// PSI between previous and current cluster shares
+    pub share_psi: Option<f64>,
+}
+
+/// Cost and latency of a cluster's requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterStats {
+    pub cluster_id: String,
+    pub requests: u64,
+    /// Share of assigned requests
+    pub share: f64,
+    pub costed_requests: u64,
+    pub total_cost_usd: f64,
+    pub mean_cost_usd: Option<f64>,
+    pub mean_latency_ms: f64,
+    pub p95_latency_ms: Option<f64>,
+    pub mean_tool_calls: f64,
+    pub models: HashMap<String, u64>,
+}
+
+#[derive(Debug, Default)]
+struct ClusterAccumulator {
+    requests: u64,
+    costed_requests: u64,
+    total_cost_usd: f64,
+    latency_sum_ms: f64,
+    latencies: VecDeque<f64>,
+    tool_calls: u64,
+    models: HashMap<String, u64>,
+}
+
+/// Request clustering engine
+pub struct RequestClusterer {
+    config: ClusteringConfig,
+    // Request ID -> Profile
+    profiles: Arc<DashMap<String, RequestProfile>>,
+    training: Mutex<VecDeque<RequestProfile>>,
+    model: RwLock<Option<FittedModel>>,
+    // Cluster ID -> Accumulated cost and latency
+    clusters: Arc<DashMap<String, ClusterAccumulator>>,
+    drift_history: Mutex<VecDeque<ClusterDriftReport>>,
+    next_cluster: AtomicU64,
+}
+
+impl RequestClusterer {
+    /// Create a new request clusterer
+    pub fn new(config: ClusteringConfig) -> Self {
+        Self {
+            config,
+            profiles: Arc::new(DashMap::new()),
+            training: Mutex::new(VecDeque::new()),
+            model: RwLock::new(None),
+            clusters: Arc::new(DashMap::new()),
+            drift_history: Mutex::new(VecDeque::new()),
+            next_cluster: AtomicU64::new(0),
+        }
+    }
+
+    /// Record an event into its request's profile and tag it with the
+    /// request's cluster once assigned
+    pub fn record_event(&self, event: &mut AnalyticsEvent) -> Option<String> {
+        let tags = &event.common.tags;
+        let tool_calls = tags.get(TOOL_CALLS_TAG).and_then(|v| v.parse::<u32>().ok());
+        let request_id = match &event.payload {
+            EventPayload::Telemetry(TelemetryPayload::Latency(m)) => m.request_id.clone(),
+            EventPayload::Telemetry(TelemetryPayload::TokenUsage(m)) => m.request_id.clone(),
+            EventPayload::Cost(CostPayload::TokenCost(c)) => c.request_id.clone(),
+            EventPayload::Custom(c) if c.custom_type == EMBEDDING_CUSTOM_TYPE => c
+                .data
+                .get("request_id")
+                .and_then(|v| v.as_str())
+                .map(str::to_string)?,
+            _ => return None,
+        };
+
+        let timestamp = event.common.timestamp;
+        let mut profile = self
+            .profiles
+            .entry(request_id)
+            .or_insert_with(|| RequestProfile::new(timestamp));
+
+        match &event.payload {
+            EventPayload::Telemetry(TelemetryPayload::Latency(m)) => {
+                profile.model_id.get_or_insert_with(|| m.model_id.clone());
+                profile.latency_ms = Some(m.total_latency_ms).filter(|l| l.is_finite());
+            }
+            EventPayload::Telemetry(TelemetryPayload::TokenUsage(m)) => {
+                profile.model_id.get_or_insert_with(|| m.model_id.clone());
+                profile.tokens = Some((m.prompt_tokens, m.completion_tokens));
+            }
+            EventPayload::Cost(CostPayload::TokenCost(c)) => {
+                profile.model_id.get_or_insert_with(|| c.model_id.clone());
+                profile
+                    .tokens
+                    .get_or_insert((c.prompt_tokens, c.completion_tokens));
+                profile.cost_usd = Some(c.total_cost_usd).filter(|c| c.is_finite());
+            }
+            EventPayload::Custom(c) => {
+                profile.embedding = c.data.get("embedding").and_then(|e| {
+                    e.as_array()?
+                        .iter()
+                        .map(|v| v.as_f64())
+                        .collect::<Option<Vec<f64>>>()
+                });
+                if let Some(n) = c.data.get(TOOL_CALLS_TAG).and_then(|v| v.as_u64()) {
+                    profile.tool_calls = profile.tool_calls.max(n as u32);
+                }
+            }
+            _ => {}
+        }
+        if let Some(n) = tool_calls {
+            profile.tool_calls = profile.tool_calls.max(n);
+        }
+
+        if !profile.complete && profile.latency_ms.is_some() && profile.tokens.is_some() {
+            profile.complete = true;
+            profile.cluster_id = self.model.read().as_ref().map(|m| m.assign(&profile));
+            if let Some(cluster_id) = &profile.cluster_id {
+                self.count_request(cluster_id, &profile);
+            }
+
+            let mut training = self.training.lock();
+            training.push_back(profile.clone());
+            while training.len() > self.config.max_training_samples {
+                training.pop_front();
+            }
+        }
+
+        if let (Some(cluster_id), Some(cost), false) = (
+            profile.cluster_id.clone(),
+            profile.cost_usd,
+            profile.cost_counted,
+        ) {
+            let mut cluster = self.clusters.entry(cluster_id).or_default();
+            cluster.costed_requests += 1;
+            cluster.total_cost_usd += cost;
+            profile.cost_counted = true;
+        }
+
+        let cluster_id = profile.cluster_id.clone()?;
+        drop(profile);
+        event
+            .common
+            .tags
+            .insert(CLUSTER_ID_TAG.to_string(), cluster_id.clone());
+        Some(cluster_id)
+    }
+
+    fn count_request(&self, cluster_id: &str, profile: &RequestProfile) {
+        let mut cluster = self.clusters.entry(cluster_id.to_string()).or_default();
+        cluster.requests += 1;
+        cluster.tool_calls += profile.tool_calls as u64;
+        if let Some(latency) = profile.latency_ms {
+            cluster.latency_sum_ms += latency;
+            cluster.latencies.push_back(latency);
+            if cluster.latencies.len() > LATENCY_SAMPLES {
+                cluster.latencies.pop_front();
+            }
+        }
+        if let Some(model) = &profile.model_id {
+            *cluster.models.entry(model.clone()).or_default() += 1;
+        }
+    }
+
+    /// Fit clusters on the collected profiles and report how they drifted
+    /// since the previous fit
+    pub fn fit(&self, now: DateTime<Utc>) -> Result<ClusterDriftReport> {
+        let profiles: Vec<RequestProfile> = self.training.lock().iter().cloned().collect();
+        if profiles.len() < self.config.min_training_samples.max(2) {
+            anyhow::bail!(
+                "Need {} complete requests to fit clusters, have {}",
+                self.config.min_training_samples,
+                profiles.len()
+            );
+        }
+
+        let space = FeatureSpace::fit(
+            &profiles,
+            self.config.embedding_dims,
+            self.config.model_weight,
+        );
+        let vectors: Vec<Vec<f64>> = profiles.iter().map(|p| space.vector(p)).collect();
+        let records = Array2::from_shape_vec(
+            (vectors.len(), space.dims()),
+            vectors.iter().flatten().copied().collect(),
+        )
+        .context("Failed to build feature matrix")?;
+
+        let (labels, eps): (Vec<Option<usize>>, Option<f64>) = match self.config.algorithm {
+            ClusteringAlgorithm::KMeans { k } => {
+                let dataset = DatasetBase::from(records.clone());
+                let model = KMeans::params(k.min(profiles.len()))
+                    .max_n_iterations(200)
+                    .tolerance(1e-5)
+                    .fit(&dataset)
+                    .context("KMeans fit failed")?;
+                let centroids = model.centroids();
+                let labels = vectors
+                    .iter()
+                    .map(|v| {
+                        centroids
+                            .rows()
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, c)| (distance(&c.to_vec(), v), i))
+                            .min_by(|a, b| a.0.total_cmp(&b.0))
+                            .map(|(_, i)| i)
+                    })
+                    .collect();
+                (labels, None)
+            }
+            ClusteringAlgorithm::Dbscan { eps, min_points } => {
+                let labels: Array1<Option<usize>> = Dbscan::params(min_points)
+                    .tolerance(eps)
+                    .check()
+                    .context("Invalid DBSCAN parameters")?
+                    .transform(&records);
+                (labels.to_vec(), Some(eps))
+            }
+        };
+
+        let report = self.install(space, &profiles, &vectors, &labels, eps, now);
+        info!(
+            clusters = report.clusters.len(),
+            samples = report.samples,
+            noise = report.noise_samples,
+            "Fitted request clusters"
+        );
+
+        let mut history = self.drift_history.lock();
+        history.push_back(report.clone());
+        while history.len() > DRIFT_HISTORY {
+            history.pop_front();
+        }
+        self.prune_profiles(now);
+        Ok(report)
+    }
+
+    /// Build centroids from labels, match them to the previous fit and
+    /// install the new model
+    fn install(
+        &self,
+        space: FeatureSpace,
+        profiles: &[RequestProfile],
+        vectors: &[Vec<f64>],
+        labels: &[Option<usize>],
+        eps: Option<f64>,
+        now: DateTime<Utc>,
+    ) -> ClusterDriftReport {
+        let n_labels = labels.iter().flatten().max().map_or(0, |m| m + 1);
+        let mut members: Vec<Vec<usize>> = vec![Vec::new(); n_labels];
+        for (i, label) in labels.iter().enumerate() {
+            if let Some(label) = label {
+                members[*label].push(i);
+            }
+        }
+        // Drop empty labels and order clusters by size for matching
+        let mut groups: Vec<Vec<usize>> = members.into_iter().filter(|m| !m.is_empty()).collect();
+        groups.sort_by_key(|m| std::cmp::Reverse(m.len()));
+
+        let total = profiles.len() as f64;
+        let noise_samples = labels.iter().filter(|l| l.is_none()).count();
+
+        let mut model_guard = self.model.write();
+        let previous: Vec<FittedCluster> = model_guard
+            .as_ref()
+            .map(|m| m.clusters.clone())
+            .unwrap_or_default();
+        let previous_vectors: Vec<Vec<f64>> = previous
+            .iter()
+            .map(|c| space.centroid_vector(&c.centroid))
+            .collect();
+        let mut matched = vec![false; previous.len()];
+
+        let mut clusters = Vec::with_capacity(groups.len());
+        let mut drifts = Vec::with_capacity(groups.len());
+        for group in &groups {
+            let centroid = centroid(profiles, group, space.embedding_dims);
+            let vector = space.centroid_vector(&centroid);
+            let share = group.len() as f64 / total;
+
+            let nearest = previous_vectors
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !matched[*i])
+                .map(|(i, v)| (i, distance(v, &vector)))
+                .filter(|(_, d)| *d <= self.config.match_distance)
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+
+            let (cluster_id, change, centroid_shift, previous_share) = match nearest {
+                Some((i, shift)) => {
+                    matched[i] = true;
+                    (
+                        previous[i].cluster_id.clone(),
+                        ClusterChange::Stable,
+                        Some(shift),
+                        Some(previous[i].share),
+                    )
+                }
+                None => (
+                    format!(
+                        "cluster-{}",
+                        self.next_cluster.fetch_add(1, Ordering::Relaxed)
+                    ),
+                    ClusterChange::Emerged,
+                    None,
+                    None,
+                ),
+            };
+
+            drifts.push(ClusterDrift {
+                cluster_id: cluster_id.clone(),
+                change,
+                centroid_shift,
+                previous_share,
+                share,
+                centroid: centroid.clone(),
+            });
+            clusters.push(FittedCluster {
+                cluster_id,
+                centroid,
+                vector,
+                share,
+            });
+        }
+
+        let vanished: Vec<String> = previous
+            .iter()
+            .zip(&matched)
+            .filter(|(_, m)| !**m)
+            .map(|(c, _)| c.cluster_id.clone())
+            .collect();
+
+        let share_psi = (!previous.is_empty()).then(|| {
+            let mut ids: Vec<&str> = previous.iter().map(|c| c.cluster_id.as_str()).collect();
+            ids.extend(clusters.iter().map(|c| c.cluster_id.as_str()));
+            ids.sort_unstable();
+            ids.dedup();
+            let shares = |fitted: &[FittedCluster]| -> Vec<f64> {
+                ids.iter()
+                    .map(|id| {
+                        fitted
+                            .iter()
+                            .find(|c| c.cluster_id == *id)
+                            .map_or(0.0, |c| c.share)
+                    })
+                    .collect()
+            };
+            psi(&shares(&previous), &shares(&clusters))
+        });
+
+        // Map each original label to its cluster index for DBSCAN assignment
+        let mut label_index: HashMap<usize, usize> = HashMap::new();
+        for (index, group) in groups.iter().enumerate() {
+            if let Some(label) = labels[group[0]] {
+                label_index.insert(label, index);
+            }
+        }
+        let core_points = if eps.is_some() {
+            labels
+                .iter()
+                .zip(vectors)
+                .filter_map(|(label, v)| Some((v.clone(), *label_index.get(&(*label)?)?)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        debug!(vanished = vanished.len(), "Installed request cluster model");
+        *model_guard = Some(FittedModel {
+            space,
+            clusters,
+            core_points,
+            eps,
+            fitted_at: now,
+        });
+
+        ClusterDriftReport {
+            fitted_at: now,
+            algorithm: self.config.algorithm,
+            samples: profiles.len(),
+            noise_samples,
+            clusters: drifts,
+            vanished,
+            share_psi,
+        }
+    }
+
+    fn prune_profiles(&self, now: DateTime<Utc>) {
+        let horizon = now - self.config.profile_ttl;
+        self.profiles.retain(|_, p| p.first_seen >= horizon);
+    }
+
+    /// Cost and latency of every cluster, most requests first
+    pub fn cluster_stats(&self) -> Vec<ClusterStats> {
+        let total: u64 = self.clusters.iter().map(|c| c.requests).sum();
+        let mut stats: Vec<ClusterStats> = self
+            .clusters
+            .iter()
+            .map(|c| summarize(c.key(), c.value(), total))
+            .collect();
+        stats.sort_by(|a, b| {
+            b.requests
+                .cmp(&a.requests)
+                .then_with(|| a.cluster_id.cmp(&b.cluster_id))
+        });
+        stats
+    }
+
+    /// Cost and latency of one cluster
+    pub fn cluster(&self, cluster_id: &str) -> Option<ClusterStats> {
+        let total: u64 = self.clusters.iter().map(|c| c.requests).sum();
+        self.clusters
+            .get(cluster_id)
+            .map(|c| summarize(cluster_id, &c, total))
+    }
+
+    /// Centroids of the current clusters
+    pub fn centroids(&self) -> Vec<(String, ClusterCentroid)> {
+        self.model
+            .read()
+            .as_ref()
+            .map(|m| {
+                m.clusters
+                    .iter()
+                    .map(|c| (c.cluster_id.clone(), c.centroid.clone()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Time of the latest fit
+    pub fn fitted_at(&self) -> Option<DateTime<Utc>> {
+        self.model.read().as_ref().map(|m| m.fitted_at)
+    }
+
+    /// Drift reports of past fits, oldest first
+    pub fn drift_history(&self) -> Vec<ClusterDriftReport> {
+        self.drift_history.lock().iter().cloned().collect()
+    }
+}
+
+impl Default for RequestClusterer {
+    fn default() -> Self {
+        Self::new(ClusteringConfig::default())
+    }
+}
+
+fn centroid(
+    profiles: &[RequestProfile],
+    group: &[usize],
+    embedding_dims: usize,
+) -> ClusterCentroid {
+    let rows: Vec<Vec<Option<f64>>> = group
+        .iter()
+        .map(|&i| profiles[i].numeric(embedding_dims))
+        .collect();
+    let column_mean = |column: usize| {
+        let values: Vec<f64> = rows.iter().filter_map(|r| r[column]).collect();
+        stats::mean(&values)
+    };
+
+    let mut models: HashMap<String, f64> = HashMap::new();
+    for &i in group {
+        if let Some(model) = &profiles[i].model_id {
+            *models.entry(model.clone()).or_default() += 1.0 / group.len() as f64;
+        }
+    }
+
+    ClusterCentroid {
+        log_token_ratio: column_mean(0),
+        log_latency_ms: column_mean(1),
+        tool_calls: column_mean(2),
+        embedding: (0..embedding_dims).map(|d| column_mean(3 + d)).collect(),
+        models,
+    }
+}
+
+fn summarize(cluster_id: &str, cluster: &ClusterAccumulator, total: u64) -> ClusterStats {
+    let requests = cluster.requests.max(1) as f64;
+    let p95_latency_ms = (!cluster.latencies.is_empty()).then(|| {
+        let mut sorted: Vec<f64> = cluster.latencies.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        sorted[((sorted.len() as f64 * 0.95).ceil() as usize).clamp(1, sorted.len()) - 1]
+    });
+
+    ClusterStats {
+        cluster_id: cluster_id.to_string(),
+        requests: cluster.requests,
+        share: if total == 0 {
+            0.0
+        } else {
+            cluster.requests as f64 / total as f64
+        },
+        costed_requests: cluster.costed_requests,
+        total_cost_usd: cluster.total_cost_usd,
+        mean_cost_usd: (cluster.costed_requests > 0)
+            .then(|| cluster.total_cost_usd / cluster.costed_requests as f64),
+        mean_latency_ms: cluster.latency_sum_ms / requests,
+        p95_latency_ms,
+        mean_tool_calls: cluster.tool_calls as f64 / requests,
+        models: cluster.models.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::events::{
+        fixtures, CustomPayload, LatencyMetrics, TokenCostEvent, TokenUsageMetrics,
+    };
+
+    fn event(payload: EventPayload, tags: &[(&str, &str)]) -> AnalyticsEvent {
+        fixtures::event(payload).with_tags(tags)
+    }
+
+    /// Record latency, usage and cost of a request, returning the cluster
+    /// tagged on the last event
+    fn request(
+        clusterer: &RequestClusterer,
+        id: &str,
+        model: &str,
+        latency_ms: f64,
+        tokens: (u32, u32),
+        tool_calls: u32,
+        cost: f64,
+    ) -> Option<String> {
+        let tool_calls = tool_calls.to_string();
+        clusterer.record_event(&mut event(
+            EventPayload::Telemetry(TelemetryPayload::Latency(LatencyMetrics {
+                model_id: model.to_string(),
+                request_id: id.to_string(),
+                total_latency_ms: latency_ms,
+                ttft_ms: None,
+                tokens_per_second: None,
+                breakdown: None,
+            })),
+            &[(TOOL_CALLS_TAG, &tool_calls)],
+        ));
+        clusterer.record_event(&mut event(
+            EventPayload::Telemetry(TelemetryPayload::TokenUsage(TokenUsageMetrics {
+                model_id: model.to_string(),
+                request_id: id.to_string(),
+                prompt_tokens: tokens.0,
+                completion_tokens: tokens.1,
+                total_tokens: tokens.0 + tokens.1,
+            })),
+            &[],
+        ));
+        let mut cost_event = event(
+            EventPayload::Cost(CostPayload::TokenCost(TokenCostEvent {
+                model_id: model.to_string(),
+                request_id: id.to_string(),
+                prompt_tokens: tokens.0,
+                completion_tokens: tokens.1,
+                total_tokens: tokens.0 + tokens.1,
+                cost_per_prompt_token: 0.0,
+                cost_per_completion_token: 0.0,
+                total_cost_usd: cost,
+                currency: "USD".to_string(),
+            })),
+            &[],
+        );
+        let cluster = clusterer.record_event(&mut cost_event);
+        assert_eq!(cost_event.common.tags.get(CLUSTER_ID_TAG), cluster.as_ref());
+        cluster
+    }
+
+    /// Two workloads: short chat on gpt-4o-mini and long agentic runs on gpt-4
+    fn workload(clusterer: &RequestClusterer, prefix: &str, n: usize) -> Vec<Option<String>> {
+        (0..n)
+            .flat_map(|i| {
+                let jitter = (i % 10) as f64;
+                [
+                    request(
+                        clusterer,
+                        &format!("{}-chat-{}", prefix, i),
+                        "gpt-4o-mini",
+                        300.0 + 10.0 * jitter,
+                        (200, 150),
+                        0,
+                        0.001,
+                    ),
+                    request(
+                        clusterer,
+                        &format!("{}-agent-{}", prefix, i),
+                        "gpt-4",
+                        8000.0 + 200.0 * jitter,
+                        (6000, 300),
+                        6,
+                        0.2,
+                    ),
+                ]
+            })
+            .collect()
+    }
+
+    fn config(algorithm: ClusteringAlgorithm) -> ClusteringConfig {
+        ClusteringConfig {
+            algorithm,
+            min_training_samples: 50,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_kmeans_separates_workloads_and_tracks_cost() {
+        let clusterer = RequestClusterer::new(config(ClusteringAlgorithm::KMeans { k: 2 }));
+        assert!(workload(&clusterer, "warmup", 50)
+            .iter()
+            .all(Option::is_none));
+        assert!(clusterer.fit(Utc::now()).is_ok());
+
+        let assigned = workload(&clusterer, "live", 20);
+        let chat = assigned[0].clone().unwrap();
+        let agent = assigned[1].clone().unwrap();
+        assert_ne!(chat, agent);
+        assert!(assigned
+            .iter()
+            .step_by(2)
+            .all(|c| c.as_ref() == Some(&chat)));
+
+        let agent_stats = clusterer.cluster(&agent).unwrap();
+        assert_eq!(agent_stats.requests, 20);
+        assert!((agent_stats.total_cost_usd - 4.0).abs() < 1e-9);
+        assert!(agent_stats.mean_latency_ms > 8000.0);
+        assert_eq!(agent_stats.models["gpt-4"], 20);
+        assert!((agent_stats.mean_tool_calls - 6.0).abs() < 1e-9);
+        assert_eq!(clusterer.cluster_stats().len(), 2);
+    }
+
+    #[test]
+    fn test_refit_keeps_cluster_ids_and_reports_drift() {
+        let clusterer = RequestClusterer::new(config(ClusteringAlgorithm::KMeans { k: 2 }));
+        workload(&clusterer, "a", 50);
+        let first = clusterer.fit(Utc::now()).unwrap();
+        assert!(first
+            .clusters
+            .iter()
+            .all(|c| c.change == ClusterChange::Emerged));
+        assert!(first.share_psi.is_none());
+
+        workload(&clusterer, "b", 50);
+        let second = clusterer.fit(Utc::now()).unwrap();
+        let first_ids: BTreeSet<_> = first.clusters.iter().map(|c| &c.cluster_id).collect();
+        let second_ids: BTreeSet<_> = second.clusters.iter().map(|c| &c.cluster_id).collect();
+        assert_eq!(first_ids, second_ids);
+        assert!(second
+            .clusters
+            .iter()
+            .all(|c| c.change == ClusterChange::Stable));
+        assert!(second.share_psi.unwrap() < 0.01);
+        assert_eq!(clusterer.drift_history().len(), 2);
+    }
+
+    #[test]
+    fn test_dbscan_marks_outliers_as_noise() {
+        let clusterer = RequestClusterer::new(config(ClusteringAlgorithm::Dbscan {
+            eps: 0.5,
+            min_points: 5,
+        }));
+        workload(&clusterer, "a", 50);
+        let report = clusterer.fit(Utc::now()).unwrap();
+        assert_eq!(report.clusters.len(), 2);
+
+        let outlier = request(&clusterer, "odd", "claude", 60_000.0, (10, 4000), 40, 1.0);
+        assert_eq!(outlier.as_deref(), Some(NOISE_CLUSTER));
+    }
+
+    #[test]
+    fn test_embeddings_from_custom_payloads() {
+        let clusterer = RequestClusterer::new(ClusteringConfig {
+            embedding_dims: 2,
+            ..config(ClusteringAlgorithm::KMeans { k: 2 })
+        });
+        clusterer.record_event(&mut event(
+            EventPayload::Custom(CustomPayload {
+                custom_type: EMBEDDING_CUSTOM_TYPE.to_string(),
+                data: serde_json::json!({
+                    "request_id": "req-1",
+                    "embedding": [0.5, -0.5],
+                    "tool_calls": 3,
+                }),
+            }),
+            &[],
+        ));
+
+        let profile = clusterer.profiles.get("req-1").unwrap();
+        assert_eq!(profile.embedding.as_deref(), Some(&[0.5, -0.5][..]));
+        assert_eq!(profile.tool_calls, 3);
+        assert_eq!(profile.numeric(2).len(), 5);
+    }
+}
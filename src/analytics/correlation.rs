@@ -1,34 +1,239 @@
 //! Correlation Engine
 //!
 //! Cross-module event correlation and causal analysis.
+//!
+//! Events are linked by correlation ID, and the numeric metrics they carry
+//! are recorded as per-module series so that lagged correlation and Granger
+//! causality can be computed between them (see [`super::causality`]).
 
-use crate::models::correlation::{
-    EventGraph,
-    TimeWindow,
+use super::causality::{self, Aggregation, CausalityConfig, MetricRelationship};
+use super::event_graph::EventGraphService;
+use crate::models::correlation::{CorrelationQuery, EventGraph};
+use crate::schemas::events::{
+    AnalyticsEvent, CostPayload, EventPayload, SourceModule, TelemetryPayload,
 };
-use crate::schemas::events::AnalyticsEvent;
 use anyhow::Result;
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
 use dashmap::DashMap;
+use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 use uuid::Uuid;
 
+/// Metric name used for per-module event counts
+pub(crate) const EVENT_COUNT_METRIC: &str = "events";
+
+/// Event summaries kept; the oldest are evicted beyond this
+const MAX_TRACKED_EVENTS: usize = 100_000;
+
+/// Timestamped samples of one metric
+type Samples = Vec<(DateTime<Utc>, f64)>;
+
 /// Correlation engine for cross-module event analysis
 pub struct CorrelationEngine {
     correlations: Arc<DashMap<Uuid, Vec<Uuid>>>,
     #[allow(dead_code)]
     correlation_window: Duration,
+    /// Recently recorded events, keyed by event ID
+    events: Arc<DashMap<Uuid, TrackedEvent>>,
+    event_order: EvictionQueue,
+    /// Metric series keyed by "<module>.<metric>"
+    series: Arc<DashMap<String, MetricSeries>>,
+    /// How much series history is kept for causality analysis
+    series_retention: Duration,
+    causality: CausalityConfig,
+}
+
+/// Summary of a recorded event
+#[derive(Debug, Clone)]
+struct TrackedEvent {
+    source_module: SourceModule,
+    latency_ms: Option<f64>,
+}
+
+/// Samples of one metric, pre-aggregated into causality buckets
+#[derive(Debug, Clone)]
+struct MetricSeries {
+    aggregation: Aggregation,
+    buckets: causality::Buckets,
+}
+
+/// Buffered event IDs in arrival order, so the oldest entries can be evicted
+/// without scanning the buffer
+#[derive(Debug)]
+pub(crate) struct EvictionQueue {
+    capacity: usize,
+    order: Mutex<VecDeque<(DateTime<Utc>, Uuid)>>,
+}
+
+impl EvictionQueue {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            order: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Track a newly buffered entry and return the IDs to evict: the oldest
+    /// entries beyond the capacity and leading entries older than `cutoff`
+    pub(crate) fn push(
+        &self,
+        id: Uuid,
+        timestamp: DateTime<Utc>,
+        cutoff: DateTime<Utc>,
+    ) -> Vec<Uuid> {
+        let mut order = self.order.lock();
+        order.push_back((timestamp, id));
+
+        let mut evicted = Vec::new();
+        while order.len() > self.capacity || order.front().is_some_and(|(ts, _)| *ts < cutoff) {
+            if let Some((_, id)) = order.pop_front() {
+                evicted.push(id);
+            }
+        }
+        evicted
+    }
 }
 
 impl CorrelationEngine {
     /// Create new correlation engine
     pub fn new() -> Self {
+        Self::with_causality_config(CausalityConfig::default())
+    }
+
+    /// Create a correlation engine with custom causality settings
+    pub fn with_causality_config(causality: CausalityConfig) -> Self {
         Self {
             correlations: Arc::new(DashMap::new()),
             correlation_window: Duration::minutes(5),
+            events: Arc::new(DashMap::new()),
+            event_order: EvictionQueue::new(MAX_TRACKED_EVENTS),
+            series: Arc::new(DashMap::new()),
+            series_retention: Duration::hours(24),
+            causality,
         }
     }
 
+    /// Record an event: track its correlation ID and the metrics it carries
+    pub fn record_event(&self, event: &AnalyticsEvent) {
+        let common = &event.common;
+        if let Some(correlation_id) = common.correlation_id {
+            self.track_correlation(correlation_id, common.event_id);
+        }
+
+        let samples = event_metrics(event);
+        let latency_ms = samples
+            .iter()
+            .find(|(metric, _, _)| *metric == "latency_ms")
+            .map(|(_, value, _)| *value);
+
+        let tracked = TrackedEvent {
+            source_module: common.source_module.clone(),
+            latency_ms,
+        };
+        if self.events.insert(common.event_id, tracked).is_none() {
+            let cutoff = common.timestamp - self.series_retention;
+            for evicted in self
+                .event_order
+                .push(common.event_id, common.timestamp, cutoff)
+            {
+                self.events.remove(&evicted);
+            }
+        }
+
+        let module = module_name(&common.source_module);
+        for (metric, value, aggregation) in samples {
+            self.record_metric(
+                &format!("{}.{}", module, metric),
+                common.timestamp,
+                value,
+                aggregation,
+            );
+        }
+    }
+
+    /// Record a sample of a named metric series. Samples are added to their
+    /// bucket as they arrive; buckets older than the retention are dropped.
+    pub fn record_metric(
+        &self,
+        name: &str,
+        timestamp: DateTime<Utc>,
+        value: f64,
+        aggregation: Aggregation,
+    ) {
+        let bucket = self.causality.bucket;
+        let index = causality::bucket_index(timestamp, bucket);
+        let mut series = self
+            .series
+            .entry(name.to_string())
+            .or_insert_with(|| MetricSeries {
+                aggregation,
+                buckets: causality::Buckets::new(),
+            });
+
+        let newest = series
+            .buckets
+            .last_key_value()
+            .map_or(index, |(last, _)| (*last).max(index));
+        let cutoff =
+            newest - (self.series_retention.num_milliseconds() / bucket.num_milliseconds().max(1));
+        if index < cutoff {
+            return;
+        }
+
+        let entry = series.buckets.entry(index).or_insert((0.0, 0));
+        entry.0 += value;
+        entry.1 += 1;
+
+        while series
+            .buckets
+            .first_key_value()
+            .is_some_and(|(first, _)| *first < cutoff)
+        {
+            series.buckets.pop_first();
+        }
+    }
+
+    /// Names of the recorded metric series
+    pub fn metric_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.series.iter().map(|e| e.key().clone()).collect();
+        names.sort();
+        names
+    }
+
+    /// Lagged correlation and Granger causality from `cause` to `effect`
+    pub fn analyze_metrics(&self, cause: &str, effect: &str) -> Result<MetricRelationship> {
+        let cause_series = self.regular_series(cause)?;
+        let effect_series = self.regular_series(effect)?;
+        let (x, y) = causality::overlap(&cause_series, &effect_series);
+        causality::analyze_series(cause, x, effect, y, &self.causality)
+    }
+
+    /// Find causal and temporal relationships between all recorded series.
+    ///
+    /// Each pair is analyzed once, oriented so that the leading series is
+    /// reported as the cause. Results are sorted by strength.
+    pub fn discover_relationships(&self) -> Vec<MetricRelationship> {
+        let names = self.metric_names();
+        let series: Vec<(String, causality::RegularSeries)> = names
+            .into_iter()
+            .filter_map(|name| {
+                let regular = self.regular_series(&name).ok()?;
+                Some((name, regular))
+            })
+            .collect();
+        discover(&series, &self.causality)
+    }
+
+    fn regular_series(&self, name: &str) -> Result<causality::RegularSeries> {
+        let series = self
+            .series
+            .get(name)
+            .ok_or_else(|| anyhow::anyhow!("Unknown metric series: {}", name))?;
+        causality::regularize_buckets(&series.buckets, series.aggregation)
+            .ok_or_else(|| anyhow::anyhow!("No data for metric series: {}", name))
+    }
+
     /// Find correlated events by correlation ID
     pub fn find_correlated_events(&self, correlation_id: Uuid) -> Vec<Uuid> {
         self.correlations
@@ -45,61 +250,194 @@ impl CorrelationEngine {
             .push(event_id);
     }
 
-    /// Build event correlation graph (stub implementation)
+    /// Build the event graph of events sharing a correlation ID
     pub fn build_event_graph(
         &self,
         correlation_id: Uuid,
         events: Vec<AnalyticsEvent>,
     ) -> Option<EventGraph> {
-        if events.is_empty() {
-            return None;
+        let first = events.iter().min_by_key(|e| e.common.timestamp)?;
+        let last = events.iter().max_by_key(|e| e.common.timestamp)?;
+        let span_minutes = (last.common.timestamp - first.common.timestamp).num_minutes() + 1;
+
+        let service = EventGraphService::new();
+        for event in &events {
+            service.record_event(event);
         }
 
-        // Stub implementation - returns empty graph
-        let now = Utc::now();
-        Some(EventGraph {
-            graph_id: correlation_id.to_string(),
-            time_range: TimeWindow {
-                start: now - Duration::minutes(5),
-                end: now,
-            },
-            nodes: vec![],
-            edges: vec![],
-            metadata: crate::models::correlation::GraphMetadata {
-                node_count: 0,
-                edge_count: 0,
-                connected_components: 0,
-                avg_degree: 0.0,
-                density: 0.0,
-            },
-        })
+        let query = CorrelationQuery {
+            seed_event_id: first.common.event_id,
+            time_window_minutes: span_minutes,
+            min_strength: 0.0,
+            correlation_types: vec![],
+            include_modules: vec![],
+            max_depth: events.len() as u32,
+        };
+
+        let mut graph = service.execute(&query).ok()?;
+        graph.graph_id = correlation_id.to_string();
+        Some(graph)
     }
 
-    /// Analyze correlation strength between modules (stub)
+    /// Analyze correlation strength between modules.
+    ///
+    /// Compares the per-module event rates at the best lag and returns the
+    /// absolute correlation (0.0 - 1.0). Module names use their serialized
+    /// form, e.g. "llm-observatory".
     pub fn analyze_module_correlation(
         &self,
-        _module1: &str,
-        _module2: &str,
+        module1: &str,
+        module2: &str,
     ) -> Result<f64> {
-        Ok(0.0)
+        let relationship = self.analyze_metrics(
+            &format!("{}.{}", module1, EVENT_COUNT_METRIC),
+            &format!("{}.{}", module2, EVENT_COUNT_METRIC),
+        )?;
+        Ok(relationship.strength)
     }
 
-    /// Detect correlation patterns (stub)
-    pub fn detect_patterns(&self, _events: &[AnalyticsEvent]) -> Result<Vec<String>> {
-        Ok(vec![])
+    /// Detect causal and temporal patterns between the metrics carried by
+    /// `events`, described as e.g. "llm-observatory.total_tokens ->
+    /// llm-cost-ops.cost_usd (causal, lag +300s, ...)".
+    pub fn detect_patterns(&self, events: &[AnalyticsEvent]) -> Result<Vec<String>> {
+        let mut samples: HashMap<String, (Aggregation, Samples)> = HashMap::new();
+        for event in events {
+            let module = module_name(&event.common.source_module);
+            for (metric, value, aggregation) in event_metrics(event) {
+                samples
+                    .entry(format!("{}.{}", module, metric))
+                    .or_insert_with(|| (aggregation, Vec::new()))
+                    .1
+                    .push((event.common.timestamp, value));
+            }
+        }
+
+        let mut series: Vec<(String, causality::RegularSeries)> = samples
+            .into_iter()
+            .filter_map(|(name, (aggregation, points))| {
+                let regular = causality::regularize(&points, self.causality.bucket, aggregation)?;
+                Some((name, regular))
+            })
+            .collect();
+        series.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(discover(&series, &self.causality)
+            .iter()
+            .map(MetricRelationship::describe)
+            .collect())
     }
 
-    /// Calculate correlation metrics (stub)
-    pub fn calculate_metrics(&self, _correlation_id: Uuid) -> Result<CorrelationMetrics> {
+    /// Calculate correlation metrics for the events sharing a correlation ID.
+    ///
+    /// The correlation strength is the strongest relationship between the
+    /// event rates of the participating modules (0.0 for a single module).
+    pub fn calculate_metrics(&self, correlation_id: Uuid) -> Result<CorrelationMetrics> {
+        let event_ids = self.find_correlated_events(correlation_id);
+
+        let mut modules = HashSet::new();
+        let mut latencies = Vec::new();
+        for event_id in &event_ids {
+            if let Some(tracked) = self.events.get(event_id) {
+                modules.insert(module_name(&tracked.source_module));
+                latencies.extend(tracked.latency_ms);
+            }
+        }
+
+        let mut modules: Vec<String> = modules.into_iter().collect();
+        modules.sort();
+
+        let mut correlation_strength: f64 = 0.0;
+        for (i, first) in modules.iter().enumerate() {
+            for second in &modules[i + 1..] {
+                if let Ok(strength) = self.analyze_module_correlation(first, second) {
+                    correlation_strength = correlation_strength.max(strength);
+                }
+            }
+        }
+
         Ok(CorrelationMetrics {
-            event_count: 0,
-            unique_modules: 0,
-            avg_latency_ms: 0.0,
-            correlation_strength: 0.0,
+            event_count: event_ids.len(),
+            unique_modules: modules.len(),
+            avg_latency_ms: super::stats::mean(&latencies),
+            correlation_strength,
         })
     }
 }
 
+/// Analyze every pair of series, keeping those classified as causal or temporal
+fn discover(
+    series: &[(String, causality::RegularSeries)],
+    config: &CausalityConfig,
+) -> Vec<MetricRelationship> {
+    let mut relationships = Vec::new();
+    for (i, (first_name, first)) in series.iter().enumerate() {
+        for (second_name, second) in &series[i + 1..] {
+            let (x, y) = causality::overlap(first, second);
+            let Ok(forward) = causality::analyze_series(first_name, x, second_name, y, config)
+            else {
+                continue;
+            };
+
+            // Re-run in the other direction when the second series leads
+            let relationship = if forward.lag < 0 {
+                causality::analyze_series(second_name, y, first_name, x, config)
+                    .unwrap_or(forward)
+            } else {
+                forward
+            };
+
+            if relationship.correlation_type.is_some() {
+                relationships.push(relationship);
+            }
+        }
+    }
+
+    relationships.sort_by(|a, b| b.strength.total_cmp(&a.strength));
+    relationships
+}
+
+/// Serialized module name, e.g. "llm-cost-ops"
+pub(crate) fn module_name(module: &SourceModule) -> String {
+    serde_json::to_value(module)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("{:?}", module))
+}
+
+/// Numeric metrics carried by an event, with how they aggregate over time
+pub(crate) fn event_metrics(event: &AnalyticsEvent) -> Vec<(&'static str, f64, Aggregation)> {
+    let mut metrics = vec![(EVENT_COUNT_METRIC, 1.0, Aggregation::Sum)];
+
+    match &event.payload {
+        EventPayload::Telemetry(TelemetryPayload::Latency(latency)) => {
+            metrics.push(("latency_ms", latency.total_latency_ms, Aggregation::Mean));
+        }
+        EventPayload::Telemetry(TelemetryPayload::Throughput(throughput)) => {
+            metrics.push((
+                "requests_per_second",
+                throughput.requests_per_second,
+                Aggregation::Mean,
+            ));
+        }
+        EventPayload::Telemetry(TelemetryPayload::ErrorRate(errors)) => {
+            metrics.push(("error_rate_percent", errors.error_rate_percent, Aggregation::Mean));
+        }
+        EventPayload::Telemetry(TelemetryPayload::TokenUsage(usage)) => {
+            metrics.push(("total_tokens", usage.total_tokens as f64, Aggregation::Sum));
+        }
+        EventPayload::Cost(CostPayload::TokenCost(cost)) => {
+            metrics.push(("total_tokens", cost.total_tokens as f64, Aggregation::Sum));
+            metrics.push(("cost_usd", cost.total_cost_usd, Aggregation::Sum));
+        }
+        EventPayload::Cost(CostPayload::ApiCost(cost)) => {
+            metrics.push(("cost_usd", cost.total_cost_usd, Aggregation::Sum));
+        }
+        _ => {}
+    }
+
+    metrics
+}
+
 impl Default for CorrelationEngine {
     fn default() -> Self {
         Self::new()
@@ -113,3 +451,179 @@ pub struct CorrelationMetrics {
     pub avg_latency_ms: f64,
     pub correlation_strength: f64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DurationRound;
+    use crate::schemas::events::{
+        fixtures, EventType, LatencyMetrics, TokenCostEvent, TokenUsageMetrics,
+    };
+
+    fn event(
+        timestamp: DateTime<Utc>,
+        source_module: SourceModule,
+        event_type: EventType,
+        correlation_id: Option<Uuid>,
+        payload: EventPayload,
+    ) -> AnalyticsEvent {
+        fixtures::event(payload)
+            .at(timestamp)
+            .with_source(source_module)
+            .with_event_type(event_type)
+            .with_correlation_id(correlation_id)
+    }
+
+    fn token_usage(timestamp: DateTime<Utc>, tokens: u32) -> AnalyticsEvent {
+        event(
+            timestamp,
+            SourceModule::LlmObservatory,
+            EventType::Telemetry,
+            None,
+            EventPayload::Telemetry(TelemetryPayload::TokenUsage(TokenUsageMetrics {
+                model_id: "gpt-4".to_string(),
+                request_id: Uuid::new_v4().to_string(),
+                prompt_tokens: tokens / 2,
+                completion_tokens: tokens - tokens / 2,
+                total_tokens: tokens,
+            })),
+        )
+    }
+
+    fn token_cost(timestamp: DateTime<Utc>, cost: f64) -> AnalyticsEvent {
+        event(
+            timestamp,
+            SourceModule::LlmCostOps,
+            EventType::Cost,
+            None,
+            EventPayload::Cost(CostPayload::TokenCost(TokenCostEvent {
+                model_id: "gpt-4".to_string(),
+                request_id: Uuid::new_v4().to_string(),
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+                cost_per_prompt_token: 0.00003,
+                cost_per_completion_token: 0.00006,
+                total_cost_usd: cost,
+                currency: "USD".to_string(),
+            })),
+        )
+    }
+
+    /// Token usage per minute, with cost following five minutes later
+    fn token_and_cost_events() -> Vec<AnalyticsEvent> {
+        // Aligned to the minute so each cost event shares its bucket
+        let start = (Utc::now() - Duration::hours(4))
+            .duration_trunc(Duration::minutes(1))
+            .unwrap();
+        let mut events = Vec::new();
+        let tokens: Vec<u32> = (0..180u32)
+            .map(|i| 1000 + (i * i * 7919 + i * 31) % 997)
+            .collect();
+        for (minute, &count) in tokens.iter().enumerate() {
+            let timestamp = start + Duration::minutes(minute as i64);
+            events.push(token_usage(timestamp, count));
+            if minute >= 5 {
+                let cost = tokens[minute - 5] as f64 * 0.00004 + (minute % 3) as f64 * 0.0005;
+                events.push(token_cost(timestamp + Duration::seconds(20), cost));
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn test_detects_token_spike_driving_cost() {
+        let engine = CorrelationEngine::new();
+        for event in token_and_cost_events() {
+            engine.record_event(&event);
+        }
+
+        let relationship = engine
+            .analyze_metrics("llm-observatory.total_tokens", "llm-cost-ops.cost_usd")
+            .unwrap();
+        assert_eq!(
+            relationship.correlation_type,
+            Some(crate::models::correlation::CorrelationType::CausalChain)
+        );
+        assert_eq!(relationship.lag_seconds, 300);
+
+        let discovered = engine.discover_relationships();
+        assert!(discovered.iter().any(|r| {
+            r.cause == "llm-observatory.total_tokens" && r.effect == "llm-cost-ops.cost_usd"
+        }));
+    }
+
+    #[test]
+    fn test_detect_patterns_describes_relationships() {
+        let engine = CorrelationEngine::new();
+        let patterns = engine.detect_patterns(&token_and_cost_events()).unwrap();
+        assert!(patterns.iter().any(|p| p.starts_with(
+            "llm-observatory.total_tokens -> llm-cost-ops.cost_usd (causal, lag +300s"
+        )));
+    }
+
+    #[test]
+    fn test_calculate_metrics_for_correlation() {
+        let engine = CorrelationEngine::new();
+        let correlation_id = Uuid::new_v4();
+        let now = Utc::now();
+
+        for latency in [100.0, 300.0] {
+            engine.record_event(&event(
+                now,
+                SourceModule::LlmObservatory,
+                EventType::Telemetry,
+                Some(correlation_id),
+                EventPayload::Telemetry(TelemetryPayload::Latency(LatencyMetrics {
+                    model_id: "gpt-4".to_string(),
+                    request_id: Uuid::new_v4().to_string(),
+                    total_latency_ms: latency,
+                    ttft_ms: None,
+                    tokens_per_second: None,
+                    breakdown: None,
+                })),
+            ));
+        }
+        engine.record_event(&token_cost(now, 0.5));
+        engine.track_correlation(correlation_id, Uuid::new_v4());
+
+        let metrics = engine.calculate_metrics(correlation_id).unwrap();
+        assert_eq!(metrics.event_count, 3);
+        assert_eq!(metrics.unique_modules, 1);
+        assert_eq!(metrics.avg_latency_ms, 200.0);
+        assert_eq!(metrics.correlation_strength, 0.0);
+    }
+
+    #[test]
+    fn test_eviction_queue_drops_oldest_entries() {
+        let queue = EvictionQueue::new(2);
+        let now = Utc::now();
+        let ids: Vec<Uuid> = (0..4).map(|_| Uuid::new_v4()).collect();
+        let cutoff = now - Duration::hours(1);
+
+        let old = now - Duration::hours(2);
+        assert!(queue.push(ids[0], old, old - Duration::hours(1)).is_empty());
+        // First entry is past the retention cutoff
+        assert_eq!(queue.push(ids[1], now, cutoff), vec![ids[0]]);
+        assert!(queue.push(ids[2], now, cutoff).is_empty());
+        // Capacity exceeded
+        assert_eq!(queue.push(ids[3], now, cutoff), vec![ids[1]]);
+    }
+
+    #[test]
+    fn test_metric_series_keep_buckets_within_retention() {
+        let engine = CorrelationEngine::new();
+        let start = (Utc::now() - Duration::hours(30))
+            .duration_trunc(Duration::minutes(1))
+            .unwrap();
+        for minute in 0..(30 * 60) {
+            let timestamp = start + Duration::minutes(minute);
+            engine.record_metric("m", timestamp, 1.0, Aggregation::Sum);
+            engine.record_metric("m", timestamp + Duration::seconds(30), 1.0, Aggregation::Sum);
+        }
+
+        let series = engine.series.get("m").unwrap();
+        assert_eq!(series.buckets.len(), 24 * 60 + 1);
+        assert!(series.buckets.values().all(|(sum, count)| *sum == 2.0 && *count == 2));
+    }
+}
@@ -0,0 +1,871 @@
+//! Model Quality Drift Detection
+//!
+//! Watches per-model quality signals (quality scores from
+//! `ModelPerformanceMetrics`, output lengths from token usage and finish
+//! reasons tagged on telemetry) and compares a sliding window of recent
+//! samples against a reference window pinned when the model version was
+//! first seen. Numeric features are tested with the two-sample
+//! Kolmogorov–Smirnov test, PSI and Jensen–Shannon divergence; categorical
+//! features with PSI and Jensen–Shannon divergence. Drift is reported as a
+//! `DistributionShift` anomaly, and a new model version (from the
+//! `model_version` tag or LLM-Registry) resets the reference.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use super::stats;
+use crate::adapters::registry::RegistryAdapter;
+use crate::models::correlation::{AnomalyEvent, AnomalyType};
+use crate::schemas::events::{AnalyticsEvent, EventPayload, SourceModule, TelemetryPayload};
+
+/// Tag carrying the model version that produced an event
+pub const MODEL_VERSION_TAG: &str = "model_version";
+
+/// Tag carrying the finish reason of a completion
+pub const FINISH_REASON_TAG: &str = "finish_reason";
+
+/// Smoothing applied to empty bins so PSI stays finite
+const PSI_EPSILON: f64 = 1e-4;
+
+/// Drift detector configuration
+#[derive(Debug, Clone)]
+pub struct DriftConfig {
+    /// Samples per feature collected into the pinned reference window
+    pub reference_size: usize,
+    /// Samples per feature in the sliding current window
+    pub window_size: usize,
+    /// Minimum current samples before a feature is tested
+    pub min_samples: usize,
+    /// New samples between evaluations of a feature
+    pub evaluate_every: usize,
+    /// Number of reference-quantile bins for PSI / JS on numeric features
+    pub bins: usize,
+    /// PSI at or above which a distribution is considered shifted
+    pub psi_threshold: f64,
+    /// Jensen–Shannon divergence (base 2) at or above which a distribution is shifted
+    pub js_threshold: f64,
+    /// KS significance level
+    pub ks_alpha: f64,
+}
+
+impl Default for DriftConfig {
+    fn default() -> Self {
+        Self {
+            reference_size: 500,
+            window_size: 500,
+            min_samples: 100,
+            evaluate_every: 50,
+            bins: 10,
+            psi_threshold: 0.2,
+            js_threshold: 0.1,
+            ks_alpha: 0.01,
+        }
+    }
+}
+
+/// Kind of a monitored feature
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeatureKind {
+    Numeric,
+    Categorical,
+}
+
+/// Drift test results for one feature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeatureDrift {
+    pub feature: String,
+    pub kind: FeatureKind,
+    pub reference_samples: usize,
+    pub current_samples: usize,
+    /// Population stability index
+    pub psi: f64,
+    /// Jensen–Shannon divergence, base 2 (0 to 1)
+    pub js_divergence: f64,
+    /// Kolmogorov–Smirnov statistic (numeric features only)
+    pub ks_statistic: Option<f64>,
+    pub ks_p_value: Option<f64>,
+    /// Reference mean, or reference share of the most shifted category
+    pub baseline: f64,
+    /// Current mean, or current share of the most shifted category
+    pub observed: f64,
+    /// Category whose share moved the most (categorical features only)
+    pub shifted_category: Option<String>,
+    pub drifted: bool,
+}
+
+/// Drift test results for a model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DriftReport {
+    pub model_id: String,
+    pub model_version: Option<String>,
+    pub reference_started_at: DateTime<Utc>,
+    pub evaluated_at: DateTime<Utc>,
+    pub features: Vec<FeatureDrift>,
+}
+
+impl DriftReport {
+    /// Features whose distribution has shifted
+    pub fn drifted(&self) -> impl Iterator<Item = &FeatureDrift> {
+        self.features.iter().filter(|f| f.drifted)
+    }
+}
+
+/// Reference and current samples of one feature
+#[derive(Debug, Clone)]
+struct Samples<T> {
+    reference: Vec<T>,
+    current: VecDeque<T>,
+    since_evaluation: usize,
+    /// Whether drift has been reported and not yet cleared
+    reported: bool,
+}
+
+impl<T> Samples<T> {
+    fn new() -> Self {
+        Self {
+            reference: Vec::new(),
+            current: VecDeque::new(),
+            since_evaluation: 0,
+            reported: false,
+        }
+    }
+
+    /// Fill the reference first; afterwards slide the current window
+    fn push(&mut self, value: T, config: &DriftConfig) {
+        if self.reference.len() < config.reference_size {
+            self.reference.push(value);
+            return;
+        }
+
+        self.current.push_back(value);
+        if self.current.len() > config.window_size {
+            self.current.pop_front();
+        }
+        self.since_evaluation += 1;
+    }
+
+    fn ready(&self, config: &DriftConfig) -> bool {
+        self.reference.len() >= config.reference_size && self.current.len() >= config.min_samples
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Feature {
+    Numeric(Samples<f64>),
+    Categorical(Samples<String>),
+}
+
+impl Feature {
+    fn since_evaluation(&mut self) -> &mut usize {
+        match self {
+            Feature::Numeric(s) => &mut s.since_evaluation,
+            Feature::Categorical(s) => &mut s.since_evaluation,
+        }
+    }
+
+    fn reported(&mut self) -> &mut bool {
+        match self {
+            Feature::Numeric(s) => &mut s.reported,
+            Feature::Categorical(s) => &mut s.reported,
+        }
+    }
+}
+
+/// Per-model tracking state
+#[derive(Debug)]
+struct ModelState {
+    version: Option<String>,
+    reference_started_at: DateTime<Utc>,
+    /// Module the model's telemetry arrives from
+    source_module: SourceModule,
+    features: HashMap<String, Feature>,
+}
+
+impl ModelState {
+    fn new(source_module: SourceModule, now: DateTime<Utc>) -> Self {
+        Self {
+            version: None,
+            reference_started_at: now,
+            source_module,
+            features: HashMap::new(),
+        }
+    }
+
+    fn reset(&mut self, version: Option<String>, now: DateTime<Utc>) {
+        self.version = version;
+        self.reference_started_at = now;
+        self.features.clear();
+    }
+}
+
+/// A sample extracted from an event
+enum Observation {
+    Numeric(String, f64),
+    Categorical(String, String),
+}
+
+/// Distribution drift detector for model quality signals
+pub struct DriftDetector {
+    config: DriftConfig,
+    // Model ID -> Tracking state
+    models: Arc<DashMap<String, ModelState>>,
+}
+
+impl DriftDetector {
+    /// Create a new drift detector
+    pub fn new(config: DriftConfig) -> Self {
+        Self {
+            config,
+            models: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Record a telemetry event and return anomalies for features that
+    /// started drifting
+    pub fn record_event(&self, event: &AnalyticsEvent) -> Vec<AnomalyEvent> {
+        let EventPayload::Telemetry(payload) = &event.payload else {
+            return Vec::new();
+        };
+        let Some(model_id) = model_id(payload) else {
+            return Vec::new();
+        };
+        let observations = observations(payload, &event.common.tags);
+        if observations.is_empty() {
+            return Vec::new();
+        }
+
+        let timestamp = event.common.timestamp;
+        let mut state = self
+            .models
+            .entry(model_id.to_string())
+            .or_insert_with(|| ModelState::new(event.common.source_module.clone(), timestamp));
+        state.source_module = event.common.source_module.clone();
+
+        if let Some(version) = event.common.tags.get(MODEL_VERSION_TAG) {
+            self.observe_version(&mut state, model_id, version, timestamp);
+        }
+
+        for observation in observations {
+            match observation {
+                Observation::Numeric(name, value) if value.is_finite() => {
+                    if let Feature::Numeric(samples) = state
+                        .features
+                        .entry(name)
+                        .or_insert_with(|| Feature::Numeric(Samples::new()))
+                    {
+                        samples.push(value, &self.config);
+                    }
+                }
+                Observation::Numeric(..) => {}
+                Observation::Categorical(name, value) => {
+                    if let Feature::Categorical(samples) = state
+                        .features
+                        .entry(name)
+                        .or_insert_with(|| Feature::Categorical(Samples::new()))
+                    {
+                        samples.push(value, &self.config);
+                    }
+                }
+            }
+        }
+
+        let due: Vec<String> = state
+            .features
+            .iter_mut()
+            .filter_map(|(name, feature)| {
+                (*feature.since_evaluation() >= self.config.evaluate_every).then(|| name.clone())
+            })
+            .collect();
+
+        let mut anomalies = Vec::new();
+        for name in due {
+            let feature = state.features.get_mut(&name).expect("feature exists");
+            *feature.since_evaluation() = 0;
+            let Some(drift) = self.test_feature(&name, feature) else {
+                continue;
+            };
+
+            let was_reported = std::mem::replace(feature.reported(), drift.drifted);
+            if drift.drifted && !was_reported {
+                warn!(
+                    model_id = %model_id,
+                    feature = %name,
+                    psi = drift.psi,
+                    js = drift.js_divergence,
+                    ks_p_value = ?drift.ks_p_value,
+                    "Model quality drift detected"
+                );
+                anomalies.push(self.anomaly(model_id, &state.source_module, &drift, timestamp));
+            }
+        }
+
+        anomalies
+    }
+
+    /// Set the deployed version of a model. A version different from the
+    /// known one discards the model's samples so a new reference is pinned.
+    /// Returns whether the reference was reset.
+    pub fn set_model_version(&self, model_id: &str, version: &str) -> bool {
+        let now = Utc::now();
+        let mut state = self
+            .models
+            .entry(model_id.to_string())
+            .or_insert_with(|| ModelState::new(SourceModule::LlmObservatory, now));
+        self.observe_version(&mut state, model_id, version, now)
+    }
+
+    /// Look up the model's current version in LLM-Registry and reset the
+    /// reference if it changed
+    pub async fn sync_with_registry(
+        &self,
+        registry: &RegistryAdapter,
+        model_id: &str,
+    ) -> Result<bool> {
+        let metadata = registry.fetch_model(model_id).await?;
+        Ok(self.set_model_version(model_id, &metadata.version))
+    }
+
+    /// Sync every tracked model with LLM-Registry, returning the models whose
+    /// reference was reset
+    pub async fn sync_all_with_registry(&self, registry: &RegistryAdapter) -> Vec<String> {
+        let mut reset = Vec::new();
+        for model_id in self.models() {
+            match self.sync_with_registry(registry, &model_id).await {
+                Ok(true) => reset.push(model_id),
+                Ok(false) => {}
+                Err(e) => warn!(model_id = %model_id, "Failed to sync model version: {}", e),
+            }
+        }
+        reset
+    }
+
+    /// Discard a model's samples and start pinning a new reference
+    pub fn reset_reference(&self, model_id: &str) {
+        if let Some(mut state) = self.models.get_mut(model_id) {
+            let version = state.version.clone();
+            state.reset(version, Utc::now());
+        }
+    }
+
+    /// Tracked model IDs
+    pub fn models(&self) -> Vec<String> {
+        self.models.iter().map(|m| m.key().clone()).collect()
+    }
+
+    /// Current drift test results for a model
+    pub fn evaluate(&self, model_id: &str) -> Result<DriftReport> {
+        let state = self
+            .models
+            .get(model_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown model: {}", model_id))?;
+
+        let mut features: Vec<FeatureDrift> = state
+            .features
+            .iter()
+            .filter_map(|(name, feature)| self.test_feature(name, feature))
+            .collect();
+        features.sort_by(|a, b| a.feature.cmp(&b.feature));
+
+        Ok(DriftReport {
+            model_id: model_id.to_string(),
+            model_version: state.version.clone(),
+            reference_started_at: state.reference_started_at,
+            evaluated_at: Utc::now(),
+            features,
+        })
+    }
+
+    fn observe_version(
+        &self,
+        state: &mut ModelState,
+        model_id: &str,
+        version: &str,
+        now: DateTime<Utc>,
+    ) -> bool {
+        match &state.version {
+            Some(known) if known == version => false,
+            // First version seen: adopt it for the reference being built
+            None => {
+                state.version = Some(version.to_string());
+                false
+            }
+            Some(known) => {
+                info!(
+                    model_id = %model_id,
+                    from = %known,
+                    to = %version,
+                    "Model version changed, resetting drift reference"
+                );
+                state.reset(Some(version.to_string()), now);
+                true
+            }
+        }
+    }
+
+    fn test_feature(&self, name: &str, feature: &Feature) -> Option<FeatureDrift> {
+        let config = &self.config;
+        match feature {
+            Feature::Numeric(samples) => {
+                if !samples.ready(config) {
+                    return None;
+                }
+                let current: Vec<f64> = samples.current.iter().copied().collect();
+                let (ks_statistic, ks_p_value) = ks_two_sample(&samples.reference, &current);
+                let (reference_bins, current_bins) =
+                    quantile_histograms(&samples.reference, &current, config.bins);
+                let psi = psi(&reference_bins, &current_bins);
+                let js_divergence = js_divergence(&reference_bins, &current_bins);
+
+                Some(FeatureDrift {
+                    feature: name.to_string(),
+                    kind: FeatureKind::Numeric,
+                    reference_samples: samples.reference.len(),
+                    current_samples: current.len(),
+                    psi,
+                    js_divergence,
+                    ks_statistic: Some(ks_statistic),
+                    ks_p_value: Some(ks_p_value),
+                    baseline: stats::mean(&samples.reference),
+                    observed: stats::mean(&current),
+                    shifted_category: None,
+                    // A significant KS result alone flags trivial shifts on
+                    // large windows, so an effect size is required as well
+                    drifted: ks_p_value < config.ks_alpha
+                        && (psi >= config.psi_threshold || js_divergence >= config.js_threshold),
+                })
+            }
+            Feature::Categorical(samples) => {
+                if !samples.ready(config) {
+                    return None;
+                }
+                let categories: BTreeSet<&String> =
+                    samples.reference.iter().chain(&samples.current).collect();
+                let shares = |values: &mut dyn Iterator<Item = &String>, n: usize| {
+                    let mut counts: HashMap<&String, f64> = HashMap::new();
+                    for value in values {
+                        *counts.entry(value).or_default() += 1.0;
+                    }
+                    categories
+                        .iter()
+                        .map(|c| counts.get(*c).copied().unwrap_or(0.0) / n as f64)
+                        .collect::<Vec<f64>>()
+                };
+                let reference_shares =
+                    shares(&mut samples.reference.iter(), samples.reference.len());
+                let current_shares = shares(&mut samples.current.iter(), samples.current.len());
+                let psi = psi(&reference_shares, &current_shares);
+                let js_divergence = js_divergence(&reference_shares, &current_shares);
+
+                let (shifted, baseline, observed) = categories
+                    .iter()
+                    .zip(reference_shares.iter().zip(&current_shares))
+                    .max_by(|(_, (r1, c1)), (_, (r2, c2))| {
+                        (*c1 - *r1).abs().total_cmp(&(*c2 - *r2).abs())
+                    })
+                    .map(|(category, (r, c))| ((*category).clone(), *r, *c))
+                    .unwrap_or_default();
+
+                Some(FeatureDrift {
+                    feature: name.to_string(),
+                    kind: FeatureKind::Categorical,
+                    reference_samples: samples.reference.len(),
+                    current_samples: samples.current.len(),
+                    psi,
+                    js_divergence,
+                    ks_statistic: None,
+                    ks_p_value: None,
+                    baseline,
+                    observed,
+                    shifted_category: Some(shifted),
+                    drifted: psi >= config.psi_threshold || js_divergence >= config.js_threshold,
+                })
+            }
+        }
+    }
+
+    fn anomaly(
+        &self,
+        model_id: &str,
+        source_module: &SourceModule,
+        drift: &FeatureDrift,
+        timestamp: DateTime<Utc>,
+    ) -> AnomalyEvent {
+        let metric = match &drift.shifted_category {
+            Some(category) => format!("{}.{}.{}", model_id, drift.feature, category),
+            None => format!("{}.{}", model_id, drift.feature),
+        };
+
+        AnomalyEvent {
+            event_id: Uuid::new_v4(),
+            source_module: source_module.clone(),
+            anomaly_type: AnomalyType::DistributionShift,
+            // 0.5 at the PSI threshold, approaching 1 for large shifts
+            anomaly_score: drift.psi / (drift.psi + self.config.psi_threshold),
+            baseline: drift.baseline,
+            observed: drift.observed,
+            deviation: drift.observed - drift.baseline,
+            timestamp,
+            metric,
+        }
+    }
+}
+
+impl Default for DriftDetector {
+    fn default() -> Self {
+        Self::new(DriftConfig::default())
+    }
+}
+
+fn model_id(payload: &TelemetryPayload) -> Option<&str> {
+    match payload {
+        TelemetryPayload::Latency(m) => Some(&m.model_id),
+        TelemetryPayload::Throughput(m) => Some(&m.model_id),
+        TelemetryPayload::ErrorRate(m) => Some(&m.model_id),
+        TelemetryPayload::TokenUsage(m) => Some(&m.model_id),
+        TelemetryPayload::ModelPerformance(m) => Some(&m.model_id),
+        TelemetryPayload::SloStatus(_) => None,
+    }
+}
+
+/// Quality signals carried by a telemetry event
+fn observations(payload: &TelemetryPayload, tags: &HashMap<String, String>) -> Vec<Observation> {
+    let mut observations = Vec::new();
+
+    match payload {
+        TelemetryPayload::ModelPerformance(performance) => {
+            for (name, value) in [
+                ("accuracy", performance.accuracy),
+                ("quality_score", performance.quality_score),
+                ("user_satisfaction", performance.user_satisfaction),
+            ] {
+                if let Some(value) = value {
+                    observations.push(Observation::Numeric(name.to_string(), value));
+                }
+            }
+            for (name, value) in &performance.custom_metrics {
+                observations.push(Observation::Numeric(name.clone(), *value));
+            }
+        }
+        TelemetryPayload::TokenUsage(usage) => {
+            observations.push(Observation::Numeric(
+                "output_tokens".to_string(),
+                usage.completion_tokens as f64,
+            ));
+        }
+        _ => {}
+    }
+
+    if let Some(reason) = tags.get(FINISH_REASON_TAG) {
+        observations.push(Observation::Categorical(
+            FINISH_REASON_TAG.to_string(),
+            reason.clone(),
+        ));
+    }
+
+    observations
+}
+
+/// Two-sample Kolmogorov–Smirnov test, returning the statistic D and its
+/// asymptotic p-value
+pub fn ks_two_sample(a: &[f64], b: &[f64]) -> (f64, f64) {
+    if a.is_empty() || b.is_empty() {
+        return (0.0, 1.0);
+    }
+
+    let mut a = a.to_vec();
+    let mut b = b.to_vec();
+    a.sort_by(f64::total_cmp);
+    b.sort_by(f64::total_cmp);
+
+    let (n, m) = (a.len(), b.len());
+    let (mut i, mut j) = (0, 0);
+    let mut d: f64 = 0.0;
+    while i < n && j < m {
+        let x = a[i].min(b[j]);
+        while i < n && a[i] <= x {
+            i += 1;
+        }
+        while j < m && b[j] <= x {
+            j += 1;
+        }
+        d = d.max((i as f64 / n as f64 - j as f64 / m as f64).abs());
+    }
+
+    let effective = (n * m) as f64 / (n + m) as f64;
+    let sqrt_n = effective.sqrt();
+    let lambda = (sqrt_n + 0.12 + 0.11 / sqrt_n) * d;
+    (d, kolmogorov_survival(lambda))
+}
+
+/// Survival function of the Kolmogorov distribution, Q(λ)
+fn kolmogorov_survival(lambda: f64) -> f64 {
+    if lambda < 1e-3 {
+        return 1.0;
+    }
+
+    let mut sum = 0.0;
+    for k in 1..=100 {
+        let sign = if k % 2 == 1 { 1.0 } else { -1.0 };
+        let term = sign * (-2.0 * (k * k) as f64 * lambda * lambda).exp();
+        sum += term;
+        if term.abs() < 1e-12 {
+            break;
+        }
+    }
+    (2.0 * sum).clamp(0.0, 1.0)
+}
+
+/// Proportions of `reference` and `current` in bins bounded by the
+/// reference quantiles
+fn quantile_histograms(reference: &[f64], current: &[f64], bins: usize) -> (Vec<f64>, Vec<f64>) {
+    let mut sorted = reference.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    let mut edges: Vec<f64> = (1..bins.max(1))
+        .map(|i| sorted[(i * sorted.len() / bins).min(sorted.len() - 1)])
+        .collect();
+    edges.dedup();
+
+    let histogram = |values: &[f64]| {
+        let mut counts = vec![0.0; edges.len() + 1];
+        for value in values {
+            counts[edges.partition_point(|edge| edge <= value)] += 1.0;
+        }
+        let n = values.len().max(1) as f64;
+        counts.into_iter().map(|c| c / n).collect::<Vec<f64>>()
+    };
+
+    (histogram(reference), histogram(current))
+}
+
+/// Population stability index between two binned distributions
+pub fn psi(reference: &[f64], current: &[f64]) -> f64 {
+    reference
+        .iter()
+        .zip(current)
+        .map(|(&r, &c)| {
+            let (r, c) = (r.max(PSI_EPSILON), c.max(PSI_EPSILON));
+            (c - r) * (c / r).ln()
+        })
+        .sum()
+}
+
+/// Jensen–Shannon divergence (base 2) between two binned distributions
+pub fn js_divergence(p: &[f64], q: &[f64]) -> f64 {
+    let kl = |a: &[f64], m: &[f64]| -> f64 {
+        a.iter()
+            .zip(m)
+            .filter(|(&x, _)| x > 0.0)
+            .map(|(&x, &y)| x * (x / y).log2())
+            .sum()
+    };
+
+    let m: Vec<f64> = p.iter().zip(q).map(|(a, b)| 0.5 * (a + b)).collect();
+    (0.5 * kl(p, &m) + 0.5 * kl(q, &m)).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::registry::RegistryConfig;
+    use crate::adapters::EcosystemAdapter;
+    use crate::schemas::events::{fixtures, ModelPerformanceMetrics, TokenUsageMetrics};
+
+    fn config() -> DriftConfig {
+        DriftConfig {
+            reference_size: 100,
+            window_size: 100,
+            min_samples: 50,
+            evaluate_every: 10,
+            ..Default::default()
+        }
+    }
+
+    fn event(payload: TelemetryPayload, tags: &[(&str, &str)]) -> AnalyticsEvent {
+        fixtures::telemetry(payload).with_tags(tags)
+    }
+
+    fn quality(score: f64, version: &str) -> AnalyticsEvent {
+        event(
+            TelemetryPayload::ModelPerformance(ModelPerformanceMetrics {
+                model_id: "gpt-4".to_string(),
+                accuracy: None,
+                quality_score: Some(score),
+                user_satisfaction: None,
+                custom_metrics: HashMap::new(),
+            }),
+            &[(MODEL_VERSION_TAG, version)],
+        )
+    }
+
+    fn completion(tokens: u32, finish_reason: &str) -> AnalyticsEvent {
+        event(
+            TelemetryPayload::TokenUsage(TokenUsageMetrics {
+                model_id: "gpt-4".to_string(),
+                request_id: Uuid::new_v4().to_string(),
+                prompt_tokens: 200,
+                completion_tokens: tokens,
+                total_tokens: 200 + tokens,
+            }),
+            &[(FINISH_REASON_TAG, finish_reason)],
+        )
+    }
+
+    /// Deterministic values spread evenly over [low, low + width)
+    fn spread(i: usize, low: f64, width: f64) -> f64 {
+        low + width * ((i * 37) % 100) as f64 / 100.0
+    }
+
+    #[test]
+    fn test_ks_separates_shifted_samples() {
+        let a: Vec<f64> = (0..200).map(|i| spread(i, 0.0, 1.0)).collect();
+        let b: Vec<f64> = (0..200).map(|i| spread(i + 13, 0.0, 1.0)).collect();
+        let shifted: Vec<f64> = (0..200).map(|i| spread(i, 0.5, 1.0)).collect();
+
+        let (d, p) = ks_two_sample(&a, &b);
+        assert!(d < 0.05);
+        assert!(p > 0.5);
+
+        let (d, p) = ks_two_sample(&a, &shifted);
+        assert!((d - 0.5).abs() < 0.05);
+        assert!(p < 1e-6);
+    }
+
+    #[test]
+    fn test_divergences_are_zero_for_identical_distributions() {
+        let p = [0.2, 0.3, 0.5];
+        assert!(psi(&p, &p).abs() < 1e-12);
+        assert!(js_divergence(&p, &p).abs() < 1e-12);
+
+        let q = [0.0, 0.0, 1.0];
+        let disjoint = [1.0, 0.0, 0.0];
+        assert!(psi(&p, &q) > 0.2);
+        assert!((js_divergence(&disjoint, &q) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_stable_quality_does_not_drift() {
+        let detector = DriftDetector::new(config());
+        let anomalies: Vec<AnomalyEvent> = (0..400)
+            .flat_map(|i| detector.record_event(&quality(spread(i, 0.8, 0.1), "1.0.0")))
+            .collect();
+        assert!(anomalies.is_empty());
+
+        let report = detector.evaluate("gpt-4").unwrap();
+        assert_eq!(report.model_version.as_deref(), Some("1.0.0"));
+        let feature = &report.features[0];
+        assert_eq!(feature.feature, "quality_score");
+        assert!(!feature.drifted);
+    }
+
+    #[test]
+    fn test_quality_drop_raises_a_single_anomaly() {
+        let detector = DriftDetector::new(config());
+        for i in 0..100 {
+            assert!(detector
+                .record_event(&quality(spread(i, 0.8, 0.1), "1.0.0"))
+                .is_empty());
+        }
+
+        let anomalies: Vec<AnomalyEvent> = (0..300)
+            .flat_map(|i| detector.record_event(&quality(spread(i, 0.6, 0.1), "1.0.0")))
+            .collect();
+        assert_eq!(anomalies.len(), 1);
+
+        let anomaly = &anomalies[0];
+        assert_eq!(anomaly.anomaly_type, AnomalyType::DistributionShift);
+        assert_eq!(anomaly.metric, "gpt-4.quality_score");
+        assert!(anomaly.anomaly_score > 0.5);
+        assert!(anomaly.deviation < -0.15);
+
+        let report = detector.evaluate("gpt-4").unwrap();
+        assert_eq!(report.drifted().count(), 1);
+    }
+
+    #[test]
+    fn test_finish_reason_shift_is_detected() {
+        let detector = DriftDetector::new(config());
+        for i in 0..100 {
+            let reason = if i % 10 == 0 { "length" } else { "stop" };
+            detector.record_event(&completion(300, reason));
+        }
+
+        let anomalies: Vec<AnomalyEvent> = (0..100)
+            .flat_map(|i| {
+                let reason = if i % 2 == 0 { "length" } else { "stop" };
+                detector.record_event(&completion(300, reason))
+            })
+            .collect();
+
+        assert_eq!(anomalies.len(), 1);
+        assert!(anomalies[0].metric.starts_with("gpt-4.finish_reason."));
+        assert!((anomalies[0].deviation.abs() - 0.4).abs() < 1e-9);
+
+        let report = detector.evaluate("gpt-4").unwrap();
+        let output_tokens = report
+            .features
+            .iter()
+            .find(|f| f.feature == "output_tokens")
+            .unwrap();
+        assert!(!output_tokens.drifted);
+    }
+
+    #[test]
+    fn test_new_version_resets_reference() {
+        let detector = DriftDetector::new(config());
+        for i in 0..100 {
+            detector.record_event(&quality(spread(i, 0.8, 0.1), "1.0.0"));
+        }
+
+        // The new version's lower scores become its reference, not drift
+        let anomalies: Vec<AnomalyEvent> = (0..300)
+            .flat_map(|i| detector.record_event(&quality(spread(i, 0.6, 0.1), "2.0.0")))
+            .collect();
+        assert!(anomalies.is_empty());
+
+        let report = detector.evaluate("gpt-4").unwrap();
+        assert_eq!(report.model_version.as_deref(), Some("2.0.0"));
+        assert!((report.features[0].baseline - 0.645).abs() < 0.01);
+    }
+
+    #[tokio::test]
+    async fn test_registry_version_change_resets_reference() {
+        let registry = RegistryAdapter::new(RegistryConfig {
+            endpoint: "http://localhost:8084".to_string(),
+            api_key: None,
+            timeout_secs: 5,
+        });
+        registry.connect().await.unwrap();
+
+        let detector = DriftDetector::new(config());
+        for i in 0..100 {
+            detector.record_event(&quality(spread(i, 0.8, 0.1), "0.9.0"));
+        }
+
+        let version = registry.fetch_model("gpt-4").await.unwrap().version;
+        assert_ne!(version, "0.9.0");
+        assert!(detector
+            .sync_with_registry(&registry, "gpt-4")
+            .await
+            .unwrap());
+        assert!(!detector
+            .sync_with_registry(&registry, "gpt-4")
+            .await
+            .unwrap());
+
+        let report = detector.evaluate("gpt-4").unwrap();
+        assert_eq!(report.model_version, Some(version));
+        assert!(report.features.is_empty());
+        assert_eq!(
+            detector.sync_all_with_registry(&registry).await,
+            Vec::<String>::new()
+        );
+    }
+}
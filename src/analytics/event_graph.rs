@@ -0,0 +1,570 @@
+//! Event Graph Service
+//!
+//! Executes [`CorrelationQuery`]s: starting from a seed event, the graph is
+//! expanded breadth-first through parent/child links, events sharing a
+//! correlation ID and detected correlations, up to the query's depth. The
+//! resulting [`EventGraph`] can be exported as DOT, GraphML or Mermaid.
+
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use dashmap::DashMap;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use super::correlation::EvictionQueue;
+use super::root_cause::payload_kind;
+use crate::models::correlation::{
+    CorrelationQuery, CorrelationType, EdgeRelationship, EventCorrelation, EventEdge, EventGraph,
+    EventNode, EventRole, GraphMetadata, TimeWindow,
+};
+use crate::schemas::events::AnalyticsEvent;
+
+/// Buffered events kept; the oldest are evicted beyond this
+const MAX_BUFFERED_EVENTS: usize = 100_000;
+
+/// Weight of edges between consecutive events sharing a correlation ID
+const TRACE_EDGE_WEIGHT: f64 = 0.5;
+
+/// A detected correlation between two events
+#[derive(Debug, Clone)]
+struct CorrelationLink {
+    from: Uuid,
+    to: Uuid,
+    correlation_type: CorrelationType,
+    strength: f64,
+}
+
+/// Graph service over recently observed events
+pub struct EventGraphService {
+    events: Arc<DashMap<Uuid, AnalyticsEvent>>,
+    event_order: EvictionQueue,
+    /// Parent event ID -> child event IDs
+    children: Arc<DashMap<Uuid, Vec<Uuid>>>,
+    /// Correlation ID -> event IDs
+    traces: Arc<DashMap<Uuid, Vec<Uuid>>>,
+    /// Event ID -> correlations it takes part in
+    links: Arc<DashMap<Uuid, Vec<CorrelationLink>>>,
+    retention: Duration,
+}
+
+impl EventGraphService {
+    /// Create a new graph service
+    pub fn new() -> Self {
+        Self {
+            events: Arc::new(DashMap::new()),
+            event_order: EvictionQueue::new(MAX_BUFFERED_EVENTS),
+            children: Arc::new(DashMap::new()),
+            traces: Arc::new(DashMap::new()),
+            links: Arc::new(DashMap::new()),
+            retention: Duration::hours(24),
+        }
+    }
+
+    /// Record an event and index its parent and correlation ID
+    pub fn record_event(&self, event: &AnalyticsEvent) {
+        let common = &event.common;
+        if self.events.insert(common.event_id, event.clone()).is_none() {
+            if let Some(parent) = common.parent_event_id {
+                self.children
+                    .entry(parent)
+                    .or_default()
+                    .push(common.event_id);
+            }
+            if let Some(correlation_id) = common.correlation_id {
+                self.traces
+                    .entry(correlation_id)
+                    .or_default()
+                    .push(common.event_id);
+            }
+
+            let cutoff = common.timestamp - self.retention;
+            for evicted in self
+                .event_order
+                .push(common.event_id, common.timestamp, cutoff)
+            {
+                self.evict(&evicted);
+            }
+        }
+    }
+
+    /// Record a detected correlation.
+    ///
+    /// Every event is linked from the event playing the root cause role, or
+    /// from the earliest event when no root cause is marked.
+    pub fn record_correlation(&self, correlation: &EventCorrelation) {
+        let root = correlation
+            .events
+            .iter()
+            .find(|e| e.role == EventRole::RootCause)
+            .or_else(|| correlation.events.iter().min_by_key(|e| e.timestamp));
+        let Some(root) = root else {
+            return;
+        };
+
+        for event in &correlation.events {
+            if event.event_id != root.event_id {
+                self.link(
+                    root.event_id,
+                    event.event_id,
+                    correlation.correlation_type.clone(),
+                    correlation.strength,
+                );
+            }
+        }
+    }
+
+    /// Record a correlation between two events
+    pub fn link(&self, from: Uuid, to: Uuid, correlation_type: CorrelationType, strength: f64) {
+        let link = CorrelationLink {
+            from,
+            to,
+            correlation_type,
+            strength,
+        };
+        self.links.entry(from).or_default().push(link.clone());
+        self.links.entry(to).or_default().push(link);
+    }
+
+    /// Expand a graph from the query's seed event
+    pub fn execute(&self, query: &CorrelationQuery) -> Result<EventGraph> {
+        let seed = self
+            .events
+            .get(&query.seed_event_id)
+            .map(|e| e.value().clone())
+            .ok_or_else(|| anyhow::anyhow!("Unknown seed event: {}", query.seed_event_id))?;
+
+        let window = Duration::minutes(query.time_window_minutes.max(0));
+        let start = seed.common.timestamp - window;
+        let end = seed.common.timestamp + window;
+        let admit = |event: &AnalyticsEvent| {
+            event.common.timestamp >= start
+                && event.common.timestamp <= end
+                && (query.include_modules.is_empty()
+                    || query.include_modules.contains(&event.common.source_module))
+        };
+        let follow = |link: &CorrelationLink| {
+            link.strength >= query.min_strength
+                && (query.correlation_types.is_empty()
+                    || query.correlation_types.contains(&link.correlation_type))
+        };
+
+        // Breadth-first expansion from the seed
+        let mut included: HashMap<Uuid, AnalyticsEvent> = HashMap::new();
+        let mut queue = VecDeque::from([(seed.common.event_id, 0u32)]);
+        included.insert(seed.common.event_id, seed);
+
+        while let Some((id, depth)) = queue.pop_front() {
+            if depth >= query.max_depth {
+                continue;
+            }
+
+            for neighbor in self.neighbors(&included[&id], &follow) {
+                if included.contains_key(&neighbor) {
+                    continue;
+                }
+                let Some(event) = self.events.get(&neighbor).map(|e| e.value().clone()) else {
+                    continue;
+                };
+                if admit(&event) {
+                    included.insert(neighbor, event);
+                    queue.push_back((neighbor, depth + 1));
+                }
+            }
+        }
+
+        let mut graph = self.induced_graph(&included, &follow);
+        graph.graph_id = format!("graph-{}", query.seed_event_id);
+        Ok(graph)
+    }
+
+    /// Events directly connected to `event`
+    fn neighbors<F>(&self, event: &AnalyticsEvent, follow: &F) -> Vec<Uuid>
+    where
+        F: Fn(&CorrelationLink) -> bool,
+    {
+        let id = event.common.event_id;
+        let mut neighbors = Vec::new();
+
+        neighbors.extend(event.common.parent_event_id);
+        if let Some(children) = self.children.get(&id) {
+            neighbors.extend(children.iter().copied());
+        }
+        if let Some(trace) = event
+            .common
+            .correlation_id
+            .and_then(|correlation_id| self.traces.get(&correlation_id))
+        {
+            neighbors.extend(trace.iter().copied().filter(|&other| other != id));
+        }
+        if let Some(links) = self.links.get(&id) {
+            neighbors.extend(links.iter().filter(|link| follow(link)).map(|link| {
+                if link.from == id {
+                    link.to
+                } else {
+                    link.from
+                }
+            }));
+        }
+
+        neighbors
+    }
+
+    /// Graph of the included events and every edge between them
+    fn induced_graph<F>(&self, included: &HashMap<Uuid, AnalyticsEvent>, follow: &F) -> EventGraph
+    where
+        F: Fn(&CorrelationLink) -> bool,
+    {
+        let mut events: Vec<&AnalyticsEvent> = included.values().collect();
+        events.sort_by_key(|e| (e.common.timestamp, e.common.event_id));
+
+        let node_id = |id: &Uuid| format!("n{}", id.simple());
+        let mut edges: HashMap<(Uuid, Uuid), (EdgeRelationship, f64)> = HashMap::new();
+        let mut add_edge = |from: Uuid, to: Uuid, relationship: EdgeRelationship, weight: f64| {
+            let entry = edges
+                .entry((from, to))
+                .or_insert((relationship.clone(), weight));
+            if weight > entry.1 {
+                *entry = (relationship, weight);
+            }
+        };
+
+        let mut traces: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+        for event in &events {
+            let id = event.common.event_id;
+            if let Some(parent) = event.common.parent_event_id {
+                if included.contains_key(&parent) {
+                    add_edge(parent, id, EdgeRelationship::Causes, 1.0);
+                }
+            }
+            if let Some(correlation_id) = event.common.correlation_id {
+                traces.entry(correlation_id).or_default().push(id);
+            }
+            if let Some(links) = self.links.get(&id) {
+                for link in links.iter().filter(|link| link.from == id && follow(link)) {
+                    let Some(target) = included.get(&link.to) else {
+                        continue;
+                    };
+                    match link.correlation_type {
+                        CorrelationType::CausalChain => {
+                            add_edge(id, link.to, EdgeRelationship::Causes, link.strength)
+                        }
+                        CorrelationType::Temporal
+                            if target.common.timestamp < event.common.timestamp =>
+                        {
+                            add_edge(link.to, id, EdgeRelationship::Precedes, link.strength)
+                        }
+                        CorrelationType::Temporal => {
+                            add_edge(id, link.to, EdgeRelationship::Precedes, link.strength)
+                        }
+                        _ => add_edge(id, link.to, EdgeRelationship::CorrelatesWith, link.strength),
+                    }
+                }
+            }
+        }
+
+        // Events sharing a correlation ID are chained in time order
+        for trace in traces.values() {
+            for pair in trace.windows(2) {
+                add_edge(
+                    pair[0],
+                    pair[1],
+                    EdgeRelationship::Precedes,
+                    TRACE_EDGE_WEIGHT,
+                );
+            }
+        }
+
+        let nodes: Vec<EventNode> = events
+            .iter()
+            .map(|event| {
+                let common = &event.common;
+                let mut attributes = HashMap::new();
+                attributes.insert(
+                    "summary".to_string(),
+                    payload_kind(&event.payload).to_string(),
+                );
+                attributes.insert(
+                    "severity".to_string(),
+                    format!("{:?}", common.severity).to_lowercase(),
+                );
+                if let Some(correlation_id) = common.correlation_id {
+                    attributes.insert("correlation_id".to_string(), correlation_id.to_string());
+                }
+
+                EventNode {
+                    node_id: node_id(&common.event_id),
+                    event_id: common.event_id,
+                    source_module: common.source_module.clone(),
+                    event_type: common.event_type.clone(),
+                    timestamp: common.timestamp,
+                    attributes,
+                }
+            })
+            .collect();
+
+        let position: HashMap<Uuid, usize> = events
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.common.event_id, i))
+            .collect();
+        let mut edges: Vec<((Uuid, Uuid), (EdgeRelationship, f64))> = edges.into_iter().collect();
+        edges.sort_by_key(|((from, to), _)| (position[from], position[to]));
+
+        let edges: Vec<EventEdge> = edges
+            .into_iter()
+            .enumerate()
+            .map(|(i, ((from, to), (relationship, weight)))| EventEdge {
+                edge_id: format!("e{}", i),
+                from_node: node_id(&from),
+                to_node: node_id(&to),
+                relationship_type: relationship,
+                weight,
+                properties: HashMap::new(),
+            })
+            .collect();
+
+        let time_range = TimeWindow {
+            start: events
+                .first()
+                .map(|e| e.common.timestamp)
+                .unwrap_or_else(Utc::now),
+            end: events
+                .last()
+                .map(|e| e.common.timestamp)
+                .unwrap_or_else(Utc::now),
+        };
+
+        EventGraph {
+            graph_id: String::new(),
+            time_range,
+            metadata: GraphMetadata::compute(&nodes, &edges),
+            nodes,
+            edges,
+        }
+    }
+
+    /// Drop an event and the index entries referring to it
+    fn evict(&self, event_id: &Uuid) {
+        let Some((_, event)) = self.events.remove(event_id) else {
+            return;
+        };
+
+        let common = &event.common;
+        if let Some(parent) = common.parent_event_id {
+            self.children.remove_if_mut(&parent, |_, children| {
+                children.retain(|id| id != event_id);
+                children.is_empty()
+            });
+        }
+        if let Some(correlation_id) = common.correlation_id {
+            self.traces.remove_if_mut(&correlation_id, |_, ids| {
+                ids.retain(|id| id != event_id);
+                ids.is_empty()
+            });
+        }
+
+        if let Some((_, links)) = self.links.remove(event_id) {
+            for link in links {
+                let other = if link.from == *event_id {
+                    link.to
+                } else {
+                    link.from
+                };
+                self.links.remove_if_mut(&other, |_, links| {
+                    links.retain(|l| l.from != *event_id && l.to != *event_id);
+                    links.is_empty()
+                });
+            }
+        }
+    }
+}
+
+impl Default for EventGraphService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use crate::schemas::events::{fixtures, CustomPayload, EventPayload, SourceModule};
+
+    fn event(
+        minutes_ago: i64,
+        source_module: SourceModule,
+        parent_event_id: Option<Uuid>,
+        correlation_id: Option<Uuid>,
+    ) -> AnalyticsEvent {
+        fixtures::event(EventPayload::Custom(CustomPayload {
+            custom_type: "test".to_string(),
+            data: serde_json::json!({}),
+        }))
+        .at(Utc::now() - Duration::minutes(minutes_ago))
+        .with_source(source_module)
+        .with_parent(parent_event_id)
+        .with_correlation_id(correlation_id)
+    }
+
+    fn query(seed: Uuid, max_depth: u32) -> CorrelationQuery {
+        CorrelationQuery {
+            seed_event_id: seed,
+            time_window_minutes: 60,
+            min_strength: 0.7,
+            correlation_types: vec![],
+            include_modules: vec![],
+            max_depth,
+        }
+    }
+
+    #[test]
+    fn test_expands_parent_chain_up_to_depth() {
+        let service = EventGraphService::new();
+        let root = event(10, SourceModule::LlmSentinel, None, None);
+        let child = event(
+            8,
+            SourceModule::LlmObservatory,
+            Some(root.common.event_id),
+            None,
+        );
+        let grandchild = event(
+            6,
+            SourceModule::LlmCostOps,
+            Some(child.common.event_id),
+            None,
+        );
+        let unrelated = event(5, SourceModule::LlmRegistry, None, None);
+        for e in [&root, &child, &grandchild, &unrelated] {
+            service.record_event(e);
+        }
+
+        let graph = service.execute(&query(root.common.event_id, 1)).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+
+        let graph = service
+            .execute(&query(grandchild.common.event_id, 5))
+            .unwrap();
+        assert_eq!(graph.nodes.len(), 3);
+        assert_eq!(graph.edges.len(), 2);
+        assert!(graph
+            .edges
+            .iter()
+            .all(|e| e.relationship_type == EdgeRelationship::Causes));
+        assert_eq!(graph.metadata.connected_components, 1);
+        assert!((graph.metadata.density - 2.0 / 6.0).abs() < 1e-12);
+        assert_eq!(graph.nodes[0].event_id, root.common.event_id);
+    }
+
+    #[test]
+    fn test_follows_traces_and_correlations_with_filters() {
+        let service = EventGraphService::new();
+        let trace = Uuid::new_v4();
+        let a = event(9, SourceModule::LlmObservatory, None, Some(trace));
+        let b = event(7, SourceModule::LlmCostOps, None, Some(trace));
+        let c = event(3, SourceModule::LlmSentinel, None, None);
+        let weak = event(2, SourceModule::LlmPolicyEngine, None, None);
+        for e in [&a, &b, &c, &weak] {
+            service.record_event(e);
+        }
+        service.link(
+            b.common.event_id,
+            c.common.event_id,
+            CorrelationType::CausalChain,
+            0.9,
+        );
+        service.link(
+            c.common.event_id,
+            weak.common.event_id,
+            CorrelationType::Temporal,
+            0.3,
+        );
+
+        let graph = service.execute(&query(a.common.event_id, 5)).unwrap();
+        let ids: HashSet<Uuid> = graph.nodes.iter().map(|n| n.event_id).collect();
+        assert_eq!(ids.len(), 3);
+        assert!(!ids.contains(&weak.common.event_id));
+        assert!(graph
+            .edges
+            .iter()
+            .any(|e| e.relationship_type == EdgeRelationship::Precedes));
+
+        let mut filtered = query(a.common.event_id, 5);
+        filtered.include_modules = vec![SourceModule::LlmObservatory, SourceModule::LlmCostOps];
+        let graph = service.execute(&filtered).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+
+        let mut temporal_only = query(a.common.event_id, 5);
+        temporal_only.correlation_types = vec![CorrelationType::Temporal];
+        let graph = service.execute(&temporal_only).unwrap();
+        assert_eq!(graph.nodes.len(), 2);
+
+        assert!(service.execute(&query(Uuid::new_v4(), 3)).is_err());
+    }
+
+    #[test]
+    fn test_exports() {
+        let service = EventGraphService::new();
+        let root = event(10, SourceModule::LlmSentinel, None, None);
+        let child = event(
+            8,
+            SourceModule::LlmObservatory,
+            Some(root.common.event_id),
+            None,
+        );
+        service.record_event(&root);
+        service.record_event(&child);
+        let graph = service.execute(&query(root.common.event_id, 3)).unwrap();
+
+        let from = &graph.edges[0].from_node;
+        let to = &graph.edges[0].to_node;
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph"));
+        assert!(dot.contains(&format!("\"{}\" -> \"{}\"", from, to)));
+        assert!(dot.contains("llm-sentinel"));
+
+        let graphml = graph.to_graphml();
+        assert!(graphml.contains(&format!("source=\"{}\" target=\"{}\"", from, to)));
+        assert!(graphml.contains("<data key=\"relationship\">causes</data>"));
+        assert!(graphml.trim_end().ends_with("</graphml>"));
+
+        let mermaid = graph.to_mermaid();
+        assert!(mermaid.starts_with("flowchart LR"));
+        assert!(mermaid.contains(&format!("{} -->|\"causes 1.00\"| {}", from, to)));
+    }
+
+    #[test]
+    fn test_evicts_events_past_retention_with_their_index_entries() {
+        let service = EventGraphService::new();
+        let trace = Uuid::new_v4();
+        let stale = event(25 * 60, SourceModule::LlmSentinel, None, Some(trace));
+        let stale_child = event(
+            25 * 60,
+            SourceModule::LlmSentinel,
+            Some(stale.common.event_id),
+            None,
+        );
+        service.record_event(&stale);
+        service.record_event(&stale_child);
+        service.link(
+            stale.common.event_id,
+            stale_child.common.event_id,
+            CorrelationType::CausalChain,
+            0.9,
+        );
+
+        let fresh = event(0, SourceModule::LlmObservatory, None, Some(trace));
+        service.record_event(&fresh);
+
+        assert!(!service.events.contains_key(&stale.common.event_id));
+        assert!(!service.events.contains_key(&stale_child.common.event_id));
+        assert!(!service.children.contains_key(&stale.common.event_id));
+        assert!(service.links.is_empty());
+        assert_eq!(
+            service.traces.get(&trace).unwrap().value(),
+            &vec![fresh.common.event_id]
+        );
+    }
+}
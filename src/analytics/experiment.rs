@@ -0,0 +1,1172 @@
+//! A/B and Canary Model Comparison
+//!
+//! Splits telemetry and cost events into a control and a treatment arm using
+//! tag selectors on `CommonEventFields::tags`, and compares the arms on
+//! latency, cost per request, error rate and quality.
+//!
+//! Continuous metrics are compared with Welch's t-test and the Mann–Whitney U
+//! test, error rates with a two-proportion z-test. Because reports are looked
+//! at continuously, early decisions use always-valid p-values from a mixture
+//! sequential probability ratio test (mSPRT); the fixed-horizon tests decide
+//! once both arms reach the planned sample size.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{ContinuousCDF, Normal, StudentsT};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use tracing::{debug, info};
+
+use super::stats;
+use crate::schemas::events::{AnalyticsEvent, CostPayload, EventPayload, TelemetryPayload};
+
+/// Experiment engine configuration
+#[derive(Debug, Clone)]
+pub struct ExperimentConfig {
+    /// Most recent samples kept per arm and metric
+    pub max_samples_per_arm: usize,
+}
+
+impl Default for ExperimentConfig {
+    fn default() -> Self {
+        Self {
+            max_samples_per_arm: 100_000,
+        }
+    }
+}
+
+/// Metric compared between arms
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExperimentMetric {
+    /// Total request latency in milliseconds
+    Latency,
+    /// Token cost of a request in USD
+    CostPerRequest,
+    /// Fraction of failed requests
+    ErrorRate,
+    /// Quality score reported by model performance telemetry
+    Quality,
+}
+
+impl ExperimentMetric {
+    /// Whether a decrease of the metric is an improvement
+    pub fn lower_is_better(&self) -> bool {
+        !matches!(self, ExperimentMetric::Quality)
+    }
+
+    fn is_proportion(&self) -> bool {
+        matches!(self, ExperimentMetric::ErrorRate)
+    }
+}
+
+/// Events belonging to one arm: every listed tag must match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArmSelector {
+    pub name: String,
+    pub tags: HashMap<String, String>,
+}
+
+impl ArmSelector {
+    /// Create a selector matching a single tag
+    pub fn new(name: &str, tag: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            tags: HashMap::from([(tag.to_string(), value.to_string())]),
+        }
+    }
+
+    /// Check whether an event belongs to this arm
+    pub fn matches(&self, tags: &HashMap<String, String>) -> bool {
+        self.tags.iter().all(|(k, v)| tags.get(k) == Some(v))
+    }
+}
+
+/// A/B or canary experiment definition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentDefinition {
+    pub experiment_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub control: ArmSelector,
+    pub treatment: ArmSelector,
+    #[serde(default = "default_metrics")]
+    pub metrics: Vec<ExperimentMetric>,
+    /// Family-wise significance level, split evenly across metrics
+    #[serde(default = "default_alpha")]
+    pub alpha: f64,
+    /// Samples per arm before a metric is tested at all
+    #[serde(default = "default_min_samples")]
+    pub min_samples: usize,
+    /// Planned samples per arm at which the fixed-horizon tests decide
+    #[serde(default = "default_target_samples")]
+    pub target_samples: usize,
+    /// Relative effect size the sequential test is tuned to detect
+    #[serde(default = "default_minimum_effect")]
+    pub minimum_effect: f64,
+}
+
+fn default_metrics() -> Vec<ExperimentMetric> {
+    vec![
+        ExperimentMetric::Latency,
+        ExperimentMetric::CostPerRequest,
+        ExperimentMetric::ErrorRate,
+        ExperimentMetric::Quality,
+    ]
+}
+
+fn default_alpha() -> f64 {
+    0.05
+}
+
+fn default_min_samples() -> usize {
+    30
+}
+
+fn default_target_samples() -> usize {
+    1000
+}
+
+fn default_minimum_effect() -> f64 {
+    0.05
+}
+
+impl ExperimentDefinition {
+    /// Create an experiment with default metrics and thresholds
+    pub fn new(
+        experiment_id: &str,
+        name: &str,
+        control: ArmSelector,
+        treatment: ArmSelector,
+    ) -> Self {
+        Self {
+            experiment_id: experiment_id.to_string(),
+            name: name.to_string(),
+            description: None,
+            control,
+            treatment,
+            metrics: default_metrics(),
+            alpha: default_alpha(),
+            min_samples: default_min_samples(),
+            target_samples: default_target_samples(),
+            minimum_effect: default_minimum_effect(),
+        }
+    }
+
+    /// Validate the definition
+    pub fn validate(&self) -> Result<()> {
+        if self.experiment_id.is_empty() {
+            anyhow::bail!("Experiment ID must not be empty");
+        }
+        if self.control.tags.is_empty() || self.treatment.tags.is_empty() {
+            anyhow::bail!("Both arms need at least one tag selector");
+        }
+        if self.control.tags == self.treatment.tags {
+            anyhow::bail!("Control and treatment select the same events");
+        }
+        if self.metrics.is_empty() {
+            anyhow::bail!("At least one metric must be compared");
+        }
+        if !(self.alpha > 0.0 && self.alpha < 1.0) {
+            anyhow::bail!("Alpha must be between 0 and 1, got {}", self.alpha);
+        }
+        if self.min_samples < 2 || self.target_samples < self.min_samples {
+            anyhow::bail!("Target samples must be at least min samples, which must be at least 2");
+        }
+        if self.minimum_effect <= 0.0 {
+            anyhow::bail!("Minimum effect must be positive");
+        }
+        Ok(())
+    }
+
+    /// Significance level per metric (Bonferroni)
+    pub fn metric_alpha(&self) -> f64 {
+        self.alpha / self.metrics.len().max(1) as f64
+    }
+}
+
+/// Hypothesis test applied to a metric
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatTest {
+    WelchT,
+    MannWhitneyU,
+    TwoProportionZ,
+    /// Mixture sequential probability ratio test (always-valid p-value)
+    Msprt,
+}
+
+/// Outcome of a hypothesis test
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestResult {
+    pub test: StatTest,
+    pub statistic: f64,
+    /// Two-sided p-value
+    pub p_value: f64,
+}
+
+/// Descriptive summary of one arm
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArmSummary {
+    pub name: String,
+    pub samples: u64,
+    pub mean: f64,
+    /// Standard deviation (continuous metrics only)
+    pub std_dev: Option<f64>,
+    /// Median (continuous metrics only)
+    pub median: Option<f64>,
+}
+
+/// Direction of a metric difference
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricOutcome {
+    /// Treatment is significantly better
+    Better,
+    /// Treatment is significantly worse
+    Worse,
+    /// No significant difference yet
+    NoDifference,
+    /// Too few samples to test
+    InsufficientData,
+}
+
+/// Comparison of one metric between arms
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricComparison {
+    pub metric: ExperimentMetric,
+    pub control: ArmSummary,
+    pub treatment: ArmSummary,
+    /// Treatment mean minus control mean
+    pub absolute_change: f64,
+    /// Absolute change relative to the control mean
+    pub relative_change: Option<f64>,
+    pub tests: Vec<TestResult>,
+    /// Running always-valid p-value of the sequential test
+    pub sequential_p_value: Option<f64>,
+    /// Whether both arms reached the planned sample size
+    pub horizon_reached: bool,
+    pub outcome: MetricOutcome,
+}
+
+/// Overall experiment verdict
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// Treatment improves at least one metric and regresses none
+    PromoteTreatment,
+    /// Treatment regresses at least one metric
+    RollbackTreatment,
+    /// Planned sample size reached without a significant difference
+    NoSignificantDifference,
+    /// Keep collecting data
+    Continue,
+}
+
+/// Verdict report of an experiment
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentReport {
+    pub experiment_id: String,
+    pub name: String,
+    pub control_arm: String,
+    pub treatment_arm: String,
+    pub verdict: Verdict,
+    /// Whether the verdict was reached before the planned sample size
+    pub stopped_early: bool,
+    pub alpha: f64,
+    pub metrics: Vec<MetricComparison>,
+    pub started_at: DateTime<Utc>,
+    pub computed_at: DateTime<Utc>,
+}
+
+/// Samples collected for one arm
+#[derive(Debug, Default)]
+struct ArmData {
+    samples: HashMap<ExperimentMetric, VecDeque<f64>>,
+    /// Samples dropped from the sliding window, per metric
+    evicted: HashMap<ExperimentMetric, u64>,
+    failures: u64,
+    trials: u64,
+}
+
+impl ArmData {
+    fn push(&mut self, metric: ExperimentMetric, value: f64, cap: usize) {
+        let samples = self.samples.entry(metric).or_default();
+        samples.push_back(value);
+        if samples.len() > cap {
+            samples.pop_front();
+            *self.evicted.entry(metric).or_default() += 1;
+        }
+    }
+
+    fn evicted(&self, metric: ExperimentMetric) -> u64 {
+        self.evicted.get(&metric).copied().unwrap_or(0)
+    }
+
+    fn values(&self, metric: ExperimentMetric) -> Vec<f64> {
+        self.samples
+            .get(&metric)
+            .map(|s| s.iter().copied().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+struct ExperimentState {
+    definition: ExperimentDefinition,
+    control: ArmData,
+    treatment: ArmData,
+    sequential: HashMap<ExperimentMetric, SequentialTest>,
+    started_at: DateTime<Utc>,
+}
+
+/// State of a metric's sequential test carried between reports
+#[derive(Debug, Default)]
+struct SequentialTest {
+    /// Mixing scale, fixed at the first report with enough data
+    tau: Option<f64>,
+    /// Running minimum of the always-valid p-value, with the sign of the
+    /// difference it was reached at
+    min_p: Option<(f64, f64)>,
+    /// Samples evicted from both arms when the minimum was last updated
+    evicted: u64,
+}
+
+/// Engine comparing experiment arms
+pub struct ExperimentEngine {
+    config: ExperimentConfig,
+    // Experiment ID -> State
+    experiments: Arc<DashMap<String, ExperimentState>>,
+}
+
+impl ExperimentEngine {
+    /// Create a new experiment engine
+    pub fn new(config: ExperimentConfig) -> Self {
+        Self {
+            config,
+            experiments: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Add or replace an experiment. Replacing an experiment whose arms
+    /// changed discards the samples collected so far.
+    pub fn upsert_experiment(&self, definition: ExperimentDefinition) -> Result<()> {
+        definition.validate()?;
+
+        let id = definition.experiment_id.clone();
+        if let Some(mut state) = self.experiments.get_mut(&id) {
+            if state.definition.control == definition.control
+                && state.definition.treatment == definition.treatment
+            {
+                state.definition = definition;
+                return Ok(());
+            }
+        }
+
+        info!(experiment_id = %id, "Starting experiment");
+        self.experiments.insert(
+            id,
+            ExperimentState {
+                definition,
+                control: ArmData::default(),
+                treatment: ArmData::default(),
+                sequential: HashMap::new(),
+                started_at: Utc::now(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Remove an experiment
+    pub fn remove_experiment(&self, experiment_id: &str) -> Option<ExperimentDefinition> {
+        self.experiments
+            .remove(experiment_id)
+            .map(|(_, state)| state.definition)
+    }
+
+    /// Experiment definitions
+    pub fn definitions(&self) -> Vec<ExperimentDefinition> {
+        self.experiments
+            .iter()
+            .map(|e| e.definition.clone())
+            .collect()
+    }
+
+    /// Record an event into the arms it belongs to, returning the number of
+    /// experiments it was counted in
+    pub fn record_event(&self, event: &AnalyticsEvent) -> usize {
+        let observations = observations(&event.payload);
+        if observations.is_empty() {
+            return 0;
+        }
+
+        let tags = &event.common.tags;
+        let mut counted = 0;
+        for mut state in self.experiments.iter_mut() {
+            let state = &mut *state;
+            let in_control = state.definition.control.matches(tags);
+            let in_treatment = state.definition.treatment.matches(tags);
+            let arm = match (in_control, in_treatment) {
+                (true, false) => &mut state.control,
+                (false, true) => &mut state.treatment,
+                (true, true) => {
+                    debug!(
+                        experiment_id = %state.definition.experiment_id,
+                        "Event matches both arms, ignoring"
+                    );
+                    continue;
+                }
+                (false, false) => continue,
+            };
+
+            for observation in &observations {
+                if !state.definition.metrics.contains(&observation.metric()) {
+                    continue;
+                }
+                match *observation {
+                    Observation::Value(metric, value) => {
+                        arm.push(metric, value, self.config.max_samples_per_arm)
+                    }
+                    Observation::Requests { failed, total } => {
+                        arm.failures += failed.min(total);
+                        arm.trials += total;
+                    }
+                }
+            }
+            counted += 1;
+        }
+        counted
+    }
+
+    /// Compare the arms of an experiment
+    pub fn report(&self, experiment_id: &str, now: DateTime<Utc>) -> Result<ExperimentReport> {
+        let mut state = self
+            .experiments
+            .get_mut(experiment_id)
+            .with_context(|| format!("Unknown experiment: {}", experiment_id))?;
+        Ok(build_report(&mut state, now))
+    }
+
+    /// Compare the arms of every experiment
+    pub fn report_all(&self, now: DateTime<Utc>) -> Vec<ExperimentReport> {
+        self.experiments
+            .iter_mut()
+            .map(|mut state| build_report(&mut state, now))
+            .collect()
+    }
+}
+
+impl Default for ExperimentEngine {
+    fn default() -> Self {
+        Self::new(ExperimentConfig::default())
+    }
+}
+
+/// Metric value carried by an event
+enum Observation {
+    Value(ExperimentMetric, f64),
+    Requests { failed: u64, total: u64 },
+}
+
+impl Observation {
+    fn metric(&self) -> ExperimentMetric {
+        match self {
+            Observation::Value(metric, _) => *metric,
+            Observation::Requests { .. } => ExperimentMetric::ErrorRate,
+        }
+    }
+}
+
+fn observations(payload: &EventPayload) -> Vec<Observation> {
+    let observation = match payload {
+        EventPayload::Telemetry(TelemetryPayload::Latency(m)) => {
+            Observation::Value(ExperimentMetric::Latency, m.total_latency_ms)
+        }
+        EventPayload::Telemetry(TelemetryPayload::ErrorRate(m)) => Observation::Requests {
+            failed: m.failed_requests,
+            total: m.total_requests,
+        },
+        EventPayload::Telemetry(TelemetryPayload::ModelPerformance(m)) => match m.quality_score {
+            Some(score) => Observation::Value(ExperimentMetric::Quality, score),
+            None => return Vec::new(),
+        },
+        EventPayload::Cost(CostPayload::TokenCost(c)) => {
+            Observation::Value(ExperimentMetric::CostPerRequest, c.total_cost_usd)
+        }
+        _ => return Vec::new(),
+    };
+
+    match observation {
+        Observation::Value(_, value) if !value.is_finite() => Vec::new(),
+        observation => vec![observation],
+    }
+}
+
+fn build_report(state: &mut ExperimentState, now: DateTime<Utc>) -> ExperimentReport {
+    let definition = &state.definition;
+    let alpha = definition.metric_alpha();
+
+    let metrics: Vec<MetricComparison> = definition
+        .metrics
+        .iter()
+        .map(|&metric| {
+            let sequential = state.sequential.entry(metric).or_default();
+            let mut comparison = if metric.is_proportion() {
+                compare_proportions(
+                    definition,
+                    &state.control,
+                    &state.treatment,
+                    &mut sequential.tau,
+                )
+            } else {
+                compare_continuous(
+                    definition,
+                    metric,
+                    &state.control,
+                    &state.treatment,
+                    &mut sequential.tau,
+                )
+            };
+
+            let mut sequential_sign = comparison.absolute_change.signum();
+            if let Some(p) = comparison.sequential_p_value {
+                // Always-valid p-values may be carried forward as a running
+                // minimum while the arms only grow. Evicting samples from the
+                // sliding window starts a new sequence.
+                let evicted = state.control.evicted(metric) + state.treatment.evicted(metric);
+                if evicted != sequential.evicted {
+                    sequential.min_p = None;
+                    sequential.evicted = evicted;
+                }
+                let (min_p, sign) = match sequential.min_p {
+                    Some((min_p, sign)) if min_p <= p => (min_p, sign),
+                    _ => (p, sequential_sign),
+                };
+                sequential.min_p = Some((min_p, sign));
+                comparison.sequential_p_value = Some(min_p);
+                sequential_sign = sign;
+            }
+
+            comparison.outcome = outcome(&comparison, alpha, sequential_sign);
+            comparison
+        })
+        .collect();
+
+    let any = |o: MetricOutcome| metrics.iter().any(|m| m.outcome == o);
+    let all_at_horizon = metrics.iter().all(|m| m.horizon_reached);
+    let verdict = if any(MetricOutcome::Worse) {
+        Verdict::RollbackTreatment
+    } else if any(MetricOutcome::Better) {
+        Verdict::PromoteTreatment
+    } else if all_at_horizon {
+        Verdict::NoSignificantDifference
+    } else {
+        Verdict::Continue
+    };
+
+    ExperimentReport {
+        experiment_id: definition.experiment_id.clone(),
+        name: definition.name.clone(),
+        control_arm: definition.control.name.clone(),
+        treatment_arm: definition.treatment.name.clone(),
+        verdict,
+        stopped_early: verdict != Verdict::Continue && !all_at_horizon,
+        alpha: definition.alpha,
+        metrics,
+        started_at: state.started_at,
+        computed_at: now,
+    }
+}
+
+/// Decide a metric from the sequential test, or from the fixed-horizon tests
+/// once the planned sample size is reached. A sequential decision takes its
+/// direction from `sequential_sign`, the sign of the difference at which the
+/// running minimum p-value was reached.
+fn outcome(comparison: &MetricComparison, alpha: f64, sequential_sign: f64) -> MetricOutcome {
+    let Some(sequential_p) = comparison.sequential_p_value else {
+        return MetricOutcome::InsufficientData;
+    };
+
+    let fixed_significant = comparison.horizon_reached
+        && !comparison.tests.is_empty()
+        && comparison
+            .tests
+            .iter()
+            .filter(|t| t.test != StatTest::Msprt)
+            .all(|t| t.p_value < alpha);
+
+    if sequential_p >= alpha && !fixed_significant {
+        return MetricOutcome::NoDifference;
+    }
+
+    let direction = if sequential_p < alpha {
+        sequential_sign
+    } else {
+        comparison.absolute_change
+    };
+    let improved = if comparison.metric.lower_is_better() {
+        direction < 0.0
+    } else {
+        direction > 0.0
+    };
+    if direction == 0.0 {
+        MetricOutcome::NoDifference
+    } else if improved {
+        MetricOutcome::Better
+    } else {
+        MetricOutcome::Worse
+    }
+}
+
+fn compare_continuous(
+    definition: &ExperimentDefinition,
+    metric: ExperimentMetric,
+    control: &ArmData,
+    treatment: &ArmData,
+    tau: &mut Option<f64>,
+) -> MetricComparison {
+    let a = control.values(metric);
+    let b = treatment.values(metric);
+    let summary = |name: &str, values: &[f64]| ArmSummary {
+        name: name.to_string(),
+        samples: values.len() as u64,
+        mean: stats::mean(values),
+        std_dev: Some(stats::std_dev(values)),
+        median: median(values),
+    };
+
+    let mut comparison = MetricComparison {
+        metric,
+        control: summary(&definition.control.name, &a),
+        treatment: summary(&definition.treatment.name, &b),
+        absolute_change: 0.0,
+        relative_change: None,
+        tests: Vec::new(),
+        sequential_p_value: None,
+        horizon_reached: a.len() >= definition.target_samples
+            && b.len() >= definition.target_samples,
+        outcome: MetricOutcome::InsufficientData,
+    };
+    if a.len() < definition.min_samples || b.len() < definition.min_samples {
+        return comparison;
+    }
+
+    let (mean_a, mean_b) = (comparison.control.mean, comparison.treatment.mean);
+    comparison.absolute_change = mean_b - mean_a;
+    comparison.relative_change = (mean_a != 0.0).then(|| (mean_b - mean_a) / mean_a.abs());
+
+    let standard_error_sq =
+        stats::variance(&a) / a.len() as f64 + stats::variance(&b) / b.len() as f64;
+    let tau = fixed_tau(tau, || {
+        let tau = definition.minimum_effect * mean_a.abs();
+        if tau > 0.0 {
+            tau
+        } else {
+            stats::std_dev(&a)
+        }
+    });
+    let msprt = msprt_p_value(mean_b - mean_a, standard_error_sq, tau * tau);
+
+    comparison.tests = vec![
+        welch_t_test(&a, &b),
+        mann_whitney_u(&a, &b),
+        TestResult {
+            test: StatTest::Msprt,
+            statistic: mean_b - mean_a,
+            p_value: msprt,
+        },
+    ];
+    comparison.sequential_p_value = Some(msprt);
+    comparison
+}
+
+fn compare_proportions(
+    definition: &ExperimentDefinition,
+    control: &ArmData,
+    treatment: &ArmData,
+    tau: &mut Option<f64>,
+) -> MetricComparison {
+    let rate = |arm: &ArmData| {
+        if arm.trials == 0 {
+            0.0
+        } else {
+            arm.failures as f64 / arm.trials as f64
+        }
+    };
+    let (p_a, p_b) = (rate(control), rate(treatment));
+    let summary = |name: &str, arm: &ArmData, p: f64| ArmSummary {
+        name: name.to_string(),
+        samples: arm.trials,
+        mean: p,
+        std_dev: None,
+        median: None,
+    };
+
+    let target = definition.target_samples as u64;
+    let mut comparison = MetricComparison {
+        metric: ExperimentMetric::ErrorRate,
+        control: summary(&definition.control.name, control, p_a),
+        treatment: summary(&definition.treatment.name, treatment, p_b),
+        absolute_change: 0.0,
+        relative_change: None,
+        tests: Vec::new(),
+        sequential_p_value: None,
+        horizon_reached: control.trials >= target && treatment.trials >= target,
+        outcome: MetricOutcome::InsufficientData,
+    };
+    let min = definition.min_samples as u64;
+    if control.trials < min || treatment.trials < min {
+        return comparison;
+    }
+
+    comparison.absolute_change = p_b - p_a;
+    comparison.relative_change = (p_a > 0.0).then(|| (p_b - p_a) / p_a);
+
+    let standard_error_sq =
+        p_a * (1.0 - p_a) / control.trials as f64 + p_b * (1.0 - p_b) / treatment.trials as f64;
+    let tau = fixed_tau(tau, || {
+        (definition.minimum_effect * p_a).max(definition.minimum_effect * 0.01)
+    });
+    let msprt = msprt_p_value(p_b - p_a, standard_error_sq, tau * tau);
+
+    comparison.tests = vec![
+        two_proportion_z_test(
+            control.failures,
+            control.trials,
+            treatment.failures,
+            treatment.trials,
+        ),
+        TestResult {
+            test: StatTest::Msprt,
+            statistic: p_b - p_a,
+            p_value: msprt,
+        },
+    ];
+    comparison.sequential_p_value = Some(msprt);
+    comparison
+}
+
+/// Mixing scale of the sequential test. It is computed at the first look and
+/// kept for later looks, since re-deriving it from the data at every look
+/// voids the always-valid guarantee. A degenerate scale is not kept.
+fn fixed_tau(tau: &mut Option<f64>, initial: impl FnOnce() -> f64) -> f64 {
+    if let Some(tau) = *tau {
+        return tau;
+    }
+    let initial = initial();
+    if initial > 0.0 {
+        *tau = Some(initial);
+    }
+    initial
+}
+
+fn median(values: &[f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    Some(if sorted.len() % 2 == 0 {
+        (sorted[mid - 1] + sorted[mid]) / 2.0
+    } else {
+        sorted[mid]
+    })
+}
+
+fn two_sided_normal_p(z: f64) -> f64 {
+    let normal = Normal::new(0.0, 1.0).expect("standard normal is valid");
+    (2.0 * (1.0 - normal.cdf(z.abs()))).clamp(0.0, 1.0)
+}
+
+/// p-value for a statistic whose standard error is zero
+fn degenerate_p(difference: f64) -> f64 {
+    if difference == 0.0 {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+/// Welch's unequal-variance t-test of `b` against `a`
+pub fn welch_t_test(a: &[f64], b: &[f64]) -> TestResult {
+    let (na, nb) = (a.len() as f64, b.len() as f64);
+    let (va, vb) = (stats::variance(a) / na, stats::variance(b) / nb);
+    let difference = stats::mean(b) - stats::mean(a);
+    let standard_error = (va + vb).sqrt();
+
+    if a.len() < 2 || b.len() < 2 || standard_error == 0.0 {
+        return TestResult {
+            test: StatTest::WelchT,
+            statistic: 0.0,
+            p_value: degenerate_p(difference),
+        };
+    }
+
+    let t = difference / standard_error;
+    // Welch–Satterthwaite degrees of freedom
+    let df = (va + vb).powi(2) / (va * va / (na - 1.0) + vb * vb / (nb - 1.0));
+    let p_value = StudentsT::new(0.0, 1.0, df)
+        .map(|dist| 2.0 * (1.0 - dist.cdf(t.abs())))
+        .unwrap_or_else(|_| two_sided_normal_p(t));
+
+    TestResult {
+        test: StatTest::WelchT,
+        statistic: t,
+        p_value: p_value.clamp(0.0, 1.0),
+    }
+}
+
+/// Mann–Whitney U test of `b` against `a` using the tie-corrected normal
+/// approximation. The statistic is U for `b`.
+pub fn mann_whitney_u(a: &[f64], b: &[f64]) -> TestResult {
+    let (na, nb) = (a.len() as f64, b.len() as f64);
+    if a.is_empty() || b.is_empty() {
+        return TestResult {
+            test: StatTest::MannWhitneyU,
+            statistic: 0.0,
+            p_value: 1.0,
+        };
+    }
+
+    let mut pooled: Vec<(f64, bool)> = a
+        .iter()
+        .map(|&v| (v, false))
+        .chain(b.iter().map(|&v| (v, true)))
+        .collect();
+    pooled.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    // Average ranks over ties
+    let n = pooled.len();
+    let mut rank_sum_b = 0.0;
+    let mut tie_term = 0.0;
+    let mut i = 0;
+    while i < n {
+        let mut j = i;
+        while j + 1 < n && pooled[j + 1].0 == pooled[i].0 {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        let ties = (j - i + 1) as f64;
+        tie_term += ties.powi(3) - ties;
+        rank_sum_b += rank * pooled[i..=j].iter().filter(|(_, in_b)| *in_b).count() as f64;
+        i = j + 1;
+    }
+
+    let u = rank_sum_b - nb * (nb + 1.0) / 2.0;
+    let mean_u = na * nb / 2.0;
+    let total = na + nb;
+    let variance_u = na * nb / 12.0 * ((total + 1.0) - tie_term / (total * (total - 1.0)));
+
+    let p_value = if variance_u <= 0.0 {
+        degenerate_p(u - mean_u)
+    } else {
+        // Continuity correction
+        let z = ((u - mean_u).abs() - 0.5).max(0.0) / variance_u.sqrt();
+        two_sided_normal_p(z)
+    };
+
+    TestResult {
+        test: StatTest::MannWhitneyU,
+        statistic: u,
+        p_value,
+    }
+}
+
+/// Two-proportion z-test of `x_b / n_b` against `x_a / n_a` with a pooled
+/// standard error
+pub fn two_proportion_z_test(x_a: u64, n_a: u64, x_b: u64, n_b: u64) -> TestResult {
+    if n_a == 0 || n_b == 0 {
+        return TestResult {
+            test: StatTest::TwoProportionZ,
+            statistic: 0.0,
+            p_value: 1.0,
+        };
+    }
+
+    let (n_a, n_b) = (n_a as f64, n_b as f64);
+    let (p_a, p_b) = (x_a as f64 / n_a, x_b as f64 / n_b);
+    let pooled = (x_a + x_b) as f64 / (n_a + n_b);
+    let standard_error = (pooled * (1.0 - pooled) * (1.0 / n_a + 1.0 / n_b)).sqrt();
+
+    if standard_error == 0.0 {
+        return TestResult {
+            test: StatTest::TwoProportionZ,
+            statistic: 0.0,
+            p_value: degenerate_p(p_b - p_a),
+        };
+    }
+
+    let z = (p_b - p_a) / standard_error;
+    TestResult {
+        test: StatTest::TwoProportionZ,
+        statistic: z,
+        p_value: two_sided_normal_p(z),
+    }
+}
+
+/// Always-valid p-value of the normal mixture SPRT for a difference with
+/// squared standard error `variance` and mixing variance `tau_sq`
+/// (Johari et al., "Always Valid Inference").
+pub fn msprt_p_value(difference: f64, variance: f64, tau_sq: f64) -> f64 {
+    if variance <= 0.0 || tau_sq <= 0.0 {
+        return degenerate_p(difference);
+    }
+
+    let log_likelihood_ratio = 0.5 * (variance / (variance + tau_sq)).ln()
+        + difference * difference * tau_sq / (2.0 * variance * (variance + tau_sq));
+    (-log_likelihood_ratio).exp().min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::events::{fixtures, ErrorRateMetrics, LatencyMetrics};
+    use uuid::Uuid;
+
+    fn event(arm: &str, payload: EventPayload) -> AnalyticsEvent {
+        fixtures::event(payload).with_tags(&[("deployment", arm)])
+    }
+
+    fn latency(arm: &str, latency_ms: f64) -> AnalyticsEvent {
+        event(
+            arm,
+            EventPayload::Telemetry(TelemetryPayload::Latency(LatencyMetrics {
+                model_id: "gpt-4".to_string(),
+                request_id: Uuid::new_v4().to_string(),
+                total_latency_ms: latency_ms,
+                ttft_ms: None,
+                tokens_per_second: None,
+                breakdown: None,
+            })),
+        )
+    }
+
+    fn errors(arm: &str, total: u64, failed: u64) -> AnalyticsEvent {
+        event(
+            arm,
+            EventPayload::Telemetry(TelemetryPayload::ErrorRate(ErrorRateMetrics {
+                model_id: "gpt-4".to_string(),
+                total_requests: total,
+                failed_requests: failed,
+                error_rate_percent: 100.0 * failed as f64 / total as f64,
+                error_breakdown: HashMap::new(),
+                window_duration_seconds: 60,
+            })),
+        )
+    }
+
+    fn canary() -> ExperimentDefinition {
+        let mut definition = ExperimentDefinition::new(
+            "canary-gpt-4",
+            "GPT-4 canary",
+            ArmSelector::new("stable", "deployment", "stable"),
+            ArmSelector::new("canary", "deployment", "canary"),
+        );
+        definition.metrics = vec![ExperimentMetric::Latency, ExperimentMetric::ErrorRate];
+        definition.target_samples = 400;
+        definition
+    }
+
+    /// Deterministic values spread evenly over [center - width/2, center + width/2)
+    fn spread(i: usize, center: f64, width: f64) -> f64 {
+        center - width / 2.0 + width * ((i * 37) % 100) as f64 / 100.0
+    }
+
+    #[test]
+    fn test_welch_and_mann_whitney_detect_shift() {
+        let a: Vec<f64> = (0..200).map(|i| spread(i, 100.0, 20.0)).collect();
+        let b: Vec<f64> = (0..200).map(|i| spread(i + 7, 100.0, 20.0)).collect();
+        let shifted: Vec<f64> = (0..200).map(|i| spread(i, 110.0, 20.0)).collect();
+
+        assert!(welch_t_test(&a, &b).p_value > 0.5);
+        assert!(mann_whitney_u(&a, &b).p_value > 0.5);
+
+        let welch = welch_t_test(&a, &shifted);
+        assert!(welch.statistic > 0.0);
+        assert!(welch.p_value < 1e-6);
+
+        let mw = mann_whitney_u(&a, &shifted);
+        assert!(mw.statistic > 200.0 * 200.0 / 2.0);
+        assert!(mw.p_value < 1e-6);
+    }
+
+    #[test]
+    fn test_mann_whitney_handles_ties() {
+        let a = vec![1.0; 50];
+        let b = vec![1.0; 50];
+        assert_eq!(mann_whitney_u(&a, &b).p_value, 1.0);
+
+        let b = vec![2.0; 50];
+        assert_eq!(mann_whitney_u(&a, &b).statistic, 2500.0);
+        assert!(mann_whitney_u(&a, &b).p_value < 1e-10);
+    }
+
+    #[test]
+    fn test_two_proportion_z_test_matches_reference() {
+        // 10% vs 15% over 1000 requests each: z ≈ 3.38
+        let result = two_proportion_z_test(100, 1000, 150, 1000);
+        assert!((result.statistic - 3.38).abs() < 0.01);
+        assert!(result.p_value < 0.001);
+
+        assert!(two_proportion_z_test(100, 1000, 102, 1000).p_value > 0.5);
+    }
+
+    #[test]
+    fn test_msprt_p_value_shrinks_with_evidence() {
+        assert_eq!(msprt_p_value(0.0, 1.0, 1.0), 1.0);
+        let weak = msprt_p_value(1.0, 1.0, 1.0);
+        let strong = msprt_p_value(1.0, 0.01, 1.0);
+        assert!(weak > 0.5);
+        assert!(strong < 1e-10);
+    }
+
+    #[test]
+    fn test_latency_regression_rolls_back_early() {
+        let engine = ExperimentEngine::default();
+        engine.upsert_experiment(canary()).unwrap();
+
+        for i in 0..100 {
+            assert_eq!(
+                engine.record_event(&latency("stable", spread(i, 200.0, 40.0))),
+                1
+            );
+            engine.record_event(&latency("canary", spread(i, 260.0, 40.0)));
+            engine.record_event(&latency("other", 10_000.0));
+        }
+
+        let report = engine.report("canary-gpt-4", Utc::now()).unwrap();
+        assert_eq!(report.verdict, Verdict::RollbackTreatment);
+        assert!(report.stopped_early);
+
+        let latency = &report.metrics[0];
+        assert_eq!(latency.outcome, MetricOutcome::Worse);
+        assert_eq!(latency.control.samples, 100);
+        assert!((latency.relative_change.unwrap() - 0.3).abs() < 0.01);
+        assert_eq!(latency.tests.len(), 3);
+        assert_eq!(report.metrics[1].outcome, MetricOutcome::InsufficientData);
+    }
+
+    #[test]
+    fn test_identical_arms_reach_no_difference_at_horizon() {
+        let engine = ExperimentEngine::default();
+        engine.upsert_experiment(canary()).unwrap();
+
+        for i in 0..400 {
+            engine.record_event(&latency("stable", spread(i, 200.0, 40.0)));
+            engine.record_event(&latency("canary", spread(i + 3, 200.0, 40.0)));
+        }
+        assert_eq!(
+            engine.report("canary-gpt-4", Utc::now()).unwrap().verdict,
+            Verdict::Continue
+        );
+
+        for _ in 0..10 {
+            engine.record_event(&errors("stable", 100, 2));
+            engine.record_event(&errors("canary", 100, 2));
+        }
+        let report = engine.report("canary-gpt-4", Utc::now()).unwrap();
+        assert!(report.metrics.iter().all(|m| m.horizon_reached));
+        assert_eq!(report.verdict, Verdict::NoSignificantDifference);
+        assert!(!report.stopped_early);
+    }
+
+    #[test]
+    fn test_lower_error_rate_promotes_treatment() {
+        let engine = ExperimentEngine::default();
+        let mut definition = canary();
+        definition.metrics = vec![ExperimentMetric::ErrorRate];
+        engine.upsert_experiment(definition).unwrap();
+
+        for _ in 0..20 {
+            engine.record_event(&errors("stable", 100, 10));
+            engine.record_event(&errors("canary", 100, 2));
+        }
+
+        let report = engine.report("canary-gpt-4", Utc::now()).unwrap();
+        assert_eq!(report.verdict, Verdict::PromoteTreatment);
+        let error_rate = &report.metrics[0];
+        assert!((error_rate.absolute_change + 0.08).abs() < 1e-9);
+        assert_eq!(error_rate.tests[0].test, StatTest::TwoProportionZ);
+    }
+
+    #[test]
+    fn test_sequential_minimum_resets_after_eviction() {
+        let engine = ExperimentEngine::new(ExperimentConfig {
+            max_samples_per_arm: 100,
+        });
+        let mut definition = canary();
+        definition.metrics = vec![ExperimentMetric::Latency];
+        engine.upsert_experiment(definition).unwrap();
+
+        for i in 0..100 {
+            engine.record_event(&latency("stable", spread(i, 200.0, 40.0)));
+            engine.record_event(&latency("canary", spread(i, 260.0, 40.0)));
+        }
+        let report = engine.report("canary-gpt-4", Utc::now()).unwrap();
+        assert_eq!(report.metrics[0].outcome, MetricOutcome::Worse);
+        let tau = |engine: &ExperimentEngine| {
+            engine.experiments.get("canary-gpt-4").unwrap().sequential[&ExperimentMetric::Latency]
+                .tau
+        };
+        let first_tau = tau(&engine).unwrap();
+        assert!((first_tau - 0.05 * 200.0).abs() < 1.0);
+
+        // The regressed samples slide out of both windows
+        for i in 0..100 {
+            engine.record_event(&latency("stable", spread(i, 300.0, 40.0)));
+            engine.record_event(&latency("canary", spread(i + 3, 300.0, 40.0)));
+        }
+        let report = engine.report("canary-gpt-4", Utc::now()).unwrap();
+        assert_eq!(report.metrics[0].outcome, MetricOutcome::NoDifference);
+        assert!(report.metrics[0].sequential_p_value.unwrap() > 0.05);
+        assert_eq!(tau(&engine), Some(first_tau));
+    }
+
+    #[test]
+    fn test_sequential_outcome_follows_sign_at_minimum() {
+        let arm = |name: &str| ArmSummary {
+            name: name.to_string(),
+            samples: 100,
+            mean: 200.0,
+            std_dev: None,
+            median: None,
+        };
+        let comparison = MetricComparison {
+            metric: ExperimentMetric::Latency,
+            control: arm("stable"),
+            treatment: arm("canary"),
+            absolute_change: -5.0,
+            relative_change: None,
+            tests: Vec::new(),
+            sequential_p_value: Some(0.001),
+            horizon_reached: false,
+            outcome: MetricOutcome::InsufficientData,
+        };
+
+        // Latency was higher when the minimum p-value was reached
+        assert_eq!(outcome(&comparison, 0.05, 1.0), MetricOutcome::Worse);
+
+        let comparison = MetricComparison {
+            sequential_p_value: Some(0.5),
+            ..comparison
+        };
+        assert_eq!(outcome(&comparison, 0.05, 1.0), MetricOutcome::NoDifference);
+    }
+
+    #[test]
+    fn test_invalid_definitions_are_rejected() {
+        let engine = ExperimentEngine::default();
+        let mut definition = canary();
+        definition.treatment = definition.control.clone();
+        assert!(engine.upsert_experiment(definition).is_err());
+
+        let mut definition = canary();
+        definition.alpha = 1.5;
+        assert!(engine.upsert_experiment(definition).is_err());
+        assert!(engine.report("canary-gpt-4", Utc::now()).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+//! Holt-Winters Exponential Smoothing
+//!
+//! Triple exponential smoothing with additive or multiplicative seasonality
+//! (or Holt's linear trend when no seasonality is requested). Smoothing
+//! parameters are fitted by minimizing the one-step-ahead squared error, and
+//! prediction intervals follow the ETS(A,A,A) variance recursion.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::prediction::ForecastStep;
+use super::stats::{logistic, logit, nelder_mead};
+
+/// Seasonal component type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Seasonality {
+    /// No seasonal component (Holt's linear trend)
+    None,
+    /// Seasonal effect added to the level
+    Additive,
+    /// Seasonal effect scales the level
+    Multiplicative,
+}
+
+/// Smoothing parameters, each in (0, 1)
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HoltWintersParams {
+    /// Level smoothing
+    pub alpha: f64,
+    /// Trend smoothing
+    pub beta: f64,
+    /// Seasonal smoothing (ignored without seasonality)
+    pub gamma: f64,
+}
+
+/// Fitted Holt-Winters model
+#[derive(Debug, Clone)]
+pub struct HoltWinters {
+    seasonality: Seasonality,
+    period: usize,
+    params: HoltWintersParams,
+    level: f64,
+    trend: f64,
+    seasonals: Vec<f64>,
+    /// Index of the last observation, used to align seasonal phases
+    last_index: usize,
+    /// Residual standard deviation (relative for multiplicative models)
+    sigma: f64,
+}
+
+/// Filter output for a given parameter set
+struct FilterState {
+    level: f64,
+    trend: f64,
+    seasonals: Vec<f64>,
+    sse: f64,
+    /// One-step errors; relative errors for multiplicative models
+    errors: Vec<f64>,
+}
+
+impl HoltWinters {
+    /// Fit a model, estimating the smoothing parameters from the data
+    pub fn fit(values: &[f64], seasonality: Seasonality, period: usize) -> Result<Self> {
+        Self::validate(values, seasonality, period)?;
+
+        let seasonal = seasonality != Seasonality::None;
+        let starts: &[[f64; 3]] = &[[0.3, 0.1, 0.1], [0.7, 0.05, 0.3]];
+
+        let objective = |x: &[f64]| {
+            let params = Self::params_from_unconstrained(x, seasonal);
+            Self::filter(values, seasonality, period, &params)
+                .map(|state| state.sse)
+                .unwrap_or(f64::INFINITY)
+        };
+
+        let mut best: Option<(Vec<f64>, f64)> = None;
+        for start in starts {
+            let dims = if seasonal { 3 } else { 2 };
+            let x0: Vec<f64> = start[..dims].iter().map(|&p| logit(p)).collect();
+            let candidate = nelder_mead(objective, &x0, 1.0, 400, 1e-10);
+            let improves = match &best {
+                Some(current) => candidate.1 < current.1,
+                None => true,
+            };
+            if improves {
+                best = Some(candidate);
+            }
+        }
+
+        let (x, sse) = best.expect("at least one starting point");
+        if !sse.is_finite() {
+            anyhow::bail!("Holt-Winters fit did not converge");
+        }
+
+        Self::fit_with_params(
+            values,
+            seasonality,
+            period,
+            Self::params_from_unconstrained(&x, seasonal),
+        )
+    }
+
+    /// Fit a model with fixed smoothing parameters
+    pub fn fit_with_params(
+        values: &[f64],
+        seasonality: Seasonality,
+        period: usize,
+        params: HoltWintersParams,
+    ) -> Result<Self> {
+        Self::validate(values, seasonality, period)?;
+
+        let state = Self::filter(values, seasonality, period, &params).ok_or_else(|| {
+            anyhow::anyhow!("Holt-Winters filter diverged for the given parameters")
+        })?;
+
+        let fitted_params = if seasonality == Seasonality::None {
+            2
+        } else {
+            3
+        };
+        let dof = state.errors.len().saturating_sub(fitted_params).max(1);
+        let sigma = (state.errors.iter().map(|e| e * e).sum::<f64>() / dof as f64).sqrt();
+
+        Ok(Self {
+            seasonality,
+            period: if seasonality == Seasonality::None {
+                0
+            } else {
+                period
+            },
+            params,
+            level: state.level,
+            trend: state.trend,
+            seasonals: state.seasonals,
+            last_index: values.len() - 1,
+            sigma,
+        })
+    }
+
+    /// Forecast `steps` periods ahead with their standard errors
+    pub fn forecast(&self, steps: usize) -> Vec<ForecastStep> {
+        let HoltWintersParams { alpha, beta, gamma } = self.params;
+        let mut variance_factor = 1.0;
+        let mut forecasts = Vec::with_capacity(steps);
+
+        for h in 1..=steps {
+            if h > 1 {
+                let j = (h - 1) as f64;
+                let seasonal_hit = self.period > 0 && (h - 1) % self.period == 0;
+                let c = alpha * (1.0 + j * beta) + if seasonal_hit { gamma } else { 0.0 };
+                variance_factor += c * c;
+            }
+
+            let base = self.level + h as f64 * self.trend;
+            let value = match self.seasonality {
+                Seasonality::None => base,
+                Seasonality::Additive => base + self.seasonal_for(h),
+                Seasonality::Multiplicative => base * self.seasonal_for(h),
+            };
+
+            let std_error = match self.seasonality {
+                Seasonality::Multiplicative => value.abs() * self.sigma * variance_factor.sqrt(),
+                _ => self.sigma * variance_factor.sqrt(),
+            };
+
+            forecasts.push(ForecastStep { value, std_error });
+        }
+
+        forecasts
+    }
+
+    /// Fitted smoothing parameters
+    pub fn params(&self) -> HoltWintersParams {
+        self.params
+    }
+
+    /// Residual standard deviation
+    pub fn sigma(&self) -> f64 {
+        self.sigma
+    }
+
+    fn seasonal_for(&self, h: usize) -> f64 {
+        self.seasonals[(self.last_index + h) % self.period]
+    }
+
+    fn validate(values: &[f64], seasonality: Seasonality, period: usize) -> Result<()> {
+        match seasonality {
+            Seasonality::None => {
+                if values.len() < 4 {
+                    anyhow::bail!("Holt's linear trend needs at least 4 points");
+                }
+            }
+            _ => {
+                if period < 2 {
+                    anyhow::bail!("Seasonal period must be at least 2");
+                }
+                if values.len() < 2 * period + 2 {
+                    anyhow::bail!(
+                        "Holt-Winters needs at least two full seasons ({} points)",
+                        2 * period + 2
+                    );
+                }
+            }
+        }
+
+        if seasonality == Seasonality::Multiplicative && values.iter().any(|&v| v <= 0.0) {
+            anyhow::bail!("Multiplicative seasonality requires strictly positive values");
+        }
+
+        Ok(())
+    }
+
+    fn params_from_unconstrained(x: &[f64], seasonal: bool) -> HoltWintersParams {
+        HoltWintersParams {
+            alpha: logistic(x[0]),
+            beta: logistic(x[1]),
+            gamma: if seasonal { logistic(x[2]) } else { 0.0 },
+        }
+    }
+
+    /// Run the smoothing recursions, returning None if the state degenerates
+    fn filter(
+        values: &[f64],
+        seasonality: Seasonality,
+        period: usize,
+        params: &HoltWintersParams,
+    ) -> Option<FilterState> {
+        let HoltWintersParams { alpha, beta, gamma } = *params;
+
+        let (mut level, mut trend, mut seasonals, start) = match seasonality {
+            Seasonality::None => (values[0], values[1] - values[0], Vec::new(), 1),
+            _ => {
+                let first = &values[..period];
+                let second = &values[period..2 * period];
+                let first_mean = first.iter().sum::<f64>() / period as f64;
+                let second_mean = second.iter().sum::<f64>() / period as f64;
+                let seasonals = first
+                    .iter()
+                    .map(|&y| match seasonality {
+                        Seasonality::Multiplicative => y / first_mean,
+                        _ => y - first_mean,
+                    })
+                    .collect();
+                (
+                    first_mean,
+                    (second_mean - first_mean) / period as f64,
+                    seasonals,
+                    period,
+                )
+            }
+        };
+
+        let mut sse = 0.0;
+        let mut errors = Vec::with_capacity(values.len() - start);
+
+        for (t, &y) in values.iter().enumerate().skip(start) {
+            let base = level + trend;
+            let new_level;
+
+            match seasonality {
+                Seasonality::None => {
+                    let e = y - base;
+                    sse += e * e;
+                    errors.push(e);
+                    new_level = alpha * y + (1.0 - alpha) * base;
+                }
+                Seasonality::Additive => {
+                    let s = seasonals[t % period];
+                    let e = y - (base + s);
+                    sse += e * e;
+                    errors.push(e);
+                    new_level = alpha * (y - s) + (1.0 - alpha) * base;
+                    seasonals[t % period] = gamma * (y - base) + (1.0 - gamma) * s;
+                }
+                Seasonality::Multiplicative => {
+                    let s = seasonals[t % period];
+                    if base <= 0.0 || s <= 0.0 {
+                        return None;
+                    }
+                    let fitted = base * s;
+                    let e = y - fitted;
+                    sse += e * e;
+                    errors.push(e / fitted);
+                    new_level = alpha * (y / s) + (1.0 - alpha) * base;
+                    seasonals[t % period] = gamma * (y / base) + (1.0 - gamma) * s;
+                }
+            }
+
+            trend = beta * (new_level - level) + (1.0 - beta) * trend;
+            level = new_level;
+        }
+
+        if !sse.is_finite() {
+            return None;
+        }
+
+        Some(FilterState {
+            level,
+            trend,
+            seasonals,
+            sse,
+            errors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seasonal_series(n: usize, period: usize) -> Vec<f64> {
+        (0..n)
+            .map(|t| {
+                let phase = 2.0 * std::f64::consts::PI * (t % period) as f64 / period as f64;
+                100.0 + 0.5 * t as f64 + 10.0 * phase.sin() + ((t * 7919) % 13) as f64 * 0.1
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_additive_forecast_tracks_trend_and_season() {
+        let period = 12;
+        let series = seasonal_series(120, period);
+        let model = HoltWinters::fit(&series, Seasonality::Additive, period).unwrap();
+        let forecast = model.forecast(period);
+
+        let expected = seasonal_series(120 + period, period);
+        for (h, step) in forecast.iter().enumerate() {
+            assert!(
+                (step.value - expected[120 + h]).abs() < 3.0,
+                "h={} forecast={} expected={}",
+                h + 1,
+                step.value,
+                expected[120 + h]
+            );
+        }
+    }
+
+    #[test]
+    fn test_intervals_widen_with_horizon() {
+        let series = seasonal_series(96, 12);
+        let model = HoltWinters::fit(&series, Seasonality::Multiplicative, 12).unwrap();
+        let forecast = model.forecast(24);
+        assert!(forecast[23].std_error > forecast[0].std_error);
+    }
+
+    #[test]
+    fn test_multiplicative_rejects_non_positive_values() {
+        let mut series = seasonal_series(48, 12);
+        series[5] = 0.0;
+        assert!(HoltWinters::fit(&series, Seasonality::Multiplicative, 12).is_err());
+    }
+
+    #[test]
+    fn test_holt_linear_without_seasonality() {
+        let series: Vec<f64> = (0..50).map(|t| 10.0 + 2.0 * t as f64).collect();
+        let model = HoltWinters::fit(&series, Seasonality::None, 0).unwrap();
+        let forecast = model.forecast(5);
+        assert!((forecast[4].value - (10.0 + 2.0 * 54.0)).abs() < 1.0);
+    }
+}
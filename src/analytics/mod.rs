@@ -3,14 +3,49 @@
 //! Core analytics capabilities including aggregation, correlation, and prediction.
 
 pub mod aggregation;
+pub mod aggregation_engine;
+pub mod window_functions;
 pub mod correlation;
 pub mod anomaly;
 pub mod prediction;
+pub mod holt_winters;
+pub mod arima;
+pub mod stats;
+pub mod backtest;
+pub mod budget;
+pub mod causality;
+pub mod root_cause;
+pub mod event_graph;
+pub mod cep;
+pub mod slo;
+pub mod drift;
+pub mod experiment;
+pub mod chargeback;
+pub mod session;
+pub mod windowing;
+#[cfg(feature = "ml")]
+pub mod clustering;
 
 pub use aggregation::AggregationEngine;
 pub use correlation::CorrelationEngine;
 pub use anomaly::AnomalyDetector;
 pub use prediction::PredictionEngine;
+pub use holt_winters::{HoltWinters, Seasonality};
+pub use arima::{Arima, ArimaOrder, SeasonalOrder};
+pub use backtest::{BacktestConfig, BacktestResult, ForecastMethod, ModelSelection};
+pub use budget::{Budget, BudgetProjection, BudgetScope, BurnRateConfig, BurnRateEngine};
+pub use causality::{CausalityConfig, GrangerResult, MetricRelationship};
+pub use root_cause::{RcaConfig, RootCauseCandidate, RootCauseEngine, RootCauseReport};
+pub use event_graph::EventGraphService;
+pub use cep::{CepConfig, CepEngine, CepStats};
+pub use slo::{SloConfig, SloDefinition, SloEngine, SloStatus};
+pub use drift::{DriftConfig, DriftDetector, DriftReport, FeatureDrift};
+pub use experiment::{ExperimentDefinition, ExperimentEngine, ExperimentReport, Verdict};
+pub use chargeback::{AllocationRule, BillingMonth, ChargebackEngine, ChargebackReport, CostCentre};
+pub use session::{ClosedSession, SessionConfig, SessionEngine, SessionSummary};
+pub use windowing::{Emission, EventTimeWindows, LateDataPolicy, WatermarkTracker, WindowingConfig};
+#[cfg(feature = "ml")]
+pub use clustering::{ClusterStats, ClusteringAlgorithm, ClusteringConfig, RequestClusterer};
 
 use anyhow::Result;
 use std::sync::Arc;
@@ -35,6 +70,12 @@ pub struct AnalyticsConfig {
 
     /// Number of historical data points for prediction
     pub prediction_history_size: usize,
+
+    /// Nominal coverage of forecast prediction intervals (0.0 - 1.0)
+    pub prediction_confidence_level: f64,
+
+    /// Seasonal period in samples; inferred from the sampling interval when unset
+    pub prediction_seasonal_period: Option<usize>,
 }
 
 impl Default for AnalyticsConfig {
@@ -46,6 +87,8 @@ impl Default for AnalyticsConfig {
             aggregation_windows: vec![60, 300, 900, 3600], // 1m, 5m, 15m, 1h
             anomaly_sensitivity: 0.95,
             prediction_history_size: 100,
+            prediction_confidence_level: 0.95,
+            prediction_seasonal_period: None,
         }
     }
 }
@@ -55,9 +98,13 @@ pub struct AnalyticsEngine {
     #[allow(dead_code)]
     config: Arc<AnalyticsConfig>,
     aggregation: AggregationEngine,
-    correlation: CorrelationEngine,
+    correlation: Arc<CorrelationEngine>,
     anomaly: AnomalyDetector,
     prediction: PredictionEngine,
+    root_cause: RootCauseEngine,
+    event_graph: EventGraphService,
+    cep: CepEngine,
+    drift: DriftDetector,
 }
 
 impl AnalyticsEngine {
@@ -66,9 +113,10 @@ impl AnalyticsEngine {
         let config = Arc::new(config);
 
         let aggregation = AggregationEngine::new(config.clone()).await?;
-        let correlation = CorrelationEngine::new();
+        let correlation = Arc::new(CorrelationEngine::new());
         let anomaly = AnomalyDetector::new(config.clone()).await?;
         let prediction = PredictionEngine::new(config.clone()).await?;
+        let root_cause = RootCauseEngine::new(RcaConfig::default(), correlation.clone());
 
         Ok(Self {
             config,
@@ -76,6 +124,10 @@ impl AnalyticsEngine {
             correlation,
             anomaly,
             prediction,
+            root_cause,
+            event_graph: EventGraphService::new(),
+            cep: CepEngine::default(),
+            drift: DriftDetector::default(),
         })
     }
 
@@ -98,4 +150,24 @@ impl AnalyticsEngine {
     pub fn prediction(&self) -> &PredictionEngine {
         &self.prediction
     }
+
+    /// Get root cause analysis engine
+    pub fn root_cause(&self) -> &RootCauseEngine {
+        &self.root_cause
+    }
+
+    /// Get event graph service
+    pub fn event_graph(&self) -> &EventGraphService {
+        &self.event_graph
+    }
+
+    /// Get complex event processing engine
+    pub fn cep(&self) -> &CepEngine {
+        &self.cep
+    }
+
+    /// Get model quality drift detector
+    pub fn drift(&self) -> &DriftDetector {
+        &self.drift
+    }
 }
@@ -1,13 +1,20 @@
 //! Prediction Engine
 //!
-//! Time-series forecasting using statistical and ML models (ARIMA, Prophet-like, LSTM).
+//! Time-series forecasting using Holt-Winters exponential smoothing and
+//! seasonal ARIMA models. Forecast horizons follow the observed sampling
+//! interval and prediction intervals are derived from each model's error
+//! variance.
 
 use anyhow::Result;
 use chrono::{DateTime, Duration, Utc};
 use dashmap::DashMap;
 use std::collections::VecDeque;
 use std::sync::Arc;
+use tracing::debug;
 
+use super::arima::Arima;
+use super::holt_winters::{HoltWinters, Seasonality};
+use super::stats;
 use super::AnalyticsConfig;
 
 /// Prediction engine for time-series forecasting
@@ -15,8 +22,8 @@ pub struct PredictionEngine {
     config: Arc<AnalyticsConfig>,
     // Metric name -> Historical data for training
     time_series: Arc<DashMap<String, TimeSeriesData>>,
-    // Cached predictions
-    predictions: Arc<DashMap<String, CachedPrediction>>,
+    // (Metric name, method) -> Cached predictions
+    predictions: Arc<DashMap<(String, &'static str), CachedPrediction>>,
 }
 
 impl PredictionEngine {
@@ -41,179 +48,187 @@ impl PredictionEngine {
             .or_insert_with(|| TimeSeriesData::new(self.config.prediction_history_size))
             .add_point(value, timestamp);
 
-        // Invalidate cached prediction
-        self.predictions.remove(metric_name);
+        // Invalidate cached predictions
+        self.predictions.retain(|(metric, _), _| metric != metric_name);
 
         Ok(())
     }
 
-    /// Predict future values using ARIMA-like model
+    /// Predict future values with an automatically selected (S)ARIMA model
     pub fn predict_arima(
         &self,
         metric_name: &str,
         steps_ahead: usize,
     ) -> Result<Vec<PredictionPoint>> {
-        // Check cache
-        if let Some(cached) = self.predictions.get(metric_name) {
-            if cached.is_valid() {
-                return Ok(cached.points.clone());
-            }
-        }
-
-        let ts_data = self
-            .time_series
-            .get(metric_name)
-            .ok_or_else(|| anyhow::anyhow!("No time series data for {}", metric_name))?;
-
-        if ts_data.values.len() < 10 {
-            anyhow::bail!("Insufficient data for prediction (need at least 10 points)");
-        }
-
-        let predictions = self.arima_forecast(&ts_data, steps_ahead)?;
-
-        // Cache predictions
-        self.predictions.insert(
-            metric_name.to_string(),
-            CachedPrediction {
-                points: predictions.clone(),
-                created_at: Utc::now(),
-                ttl_seconds: 300, // 5 minutes
-            },
-        );
-
-        Ok(predictions)
+        self.cached_or_compute(metric_name, "arima", steps_ahead, |series| {
+            let model = Arima::auto_fit(&series.values, series.seasonal_period)?;
+            debug!(
+                metric = metric_name,
+                order = ?model.order(),
+                seasonal = ?model.seasonal_order(),
+                aic = model.aic(),
+                "Fitted ARIMA model"
+            );
+            Ok(model.forecast(steps_ahead))
+        })
     }
 
-    /// Simple ARIMA-like forecasting
-    fn arima_forecast(
+    /// Predict future values with Holt-Winters exponential smoothing.
+    ///
+    /// Falls back to Holt's linear trend when no seasonal period is configured
+    /// or there is not enough history to cover two full seasons.
+    pub fn predict_holt_winters(
         &self,
-        ts_data: &TimeSeriesData,
-        steps: usize,
+        metric_name: &str,
+        steps_ahead: usize,
+        seasonality: Seasonality,
     ) -> Result<Vec<PredictionPoint>> {
-        let values: Vec<f64> = ts_data.values.iter().copied().collect();
-        let last_timestamp = *ts_data.timestamps.back().unwrap();
-
-        // Calculate trend using linear regression
-        let (slope, intercept) = self.calculate_trend(&values);
-
-        // Calculate seasonal component (simplified)
-        let seasonal = self.calculate_seasonality(&values);
-
-        // Generate predictions
-        let mut predictions = Vec::new();
-        let n = values.len();
-
-        for i in 1..=steps {
-            let trend_value = slope * (n + i) as f64 + intercept;
-            let seasonal_idx = (n + i) % seasonal.len();
-            let seasonal_value = seasonal[seasonal_idx];
+        let method = match seasonality {
+            Seasonality::None => "holt",
+            Seasonality::Additive => "holt_winters_additive",
+            Seasonality::Multiplicative => "holt_winters_multiplicative",
+        };
+
+        self.cached_or_compute(metric_name, method, steps_ahead, |series| {
+            let model = match (seasonality, series.seasonal_period) {
+                (Seasonality::None, _) | (_, None) => {
+                    HoltWinters::fit(&series.values, Seasonality::None, 0)?
+                }
+                (seasonality, Some(period)) => {
+                    HoltWinters::fit(&series.values, seasonality, period)?
+                }
+            };
+            debug!(metric = metric_name, params = ?model.params(), "Fitted Holt-Winters model");
+            Ok(model.forecast(steps_ahead))
+        })
+    }
 
-            let predicted_value = trend_value + seasonal_value;
-            let confidence = self.calculate_confidence(i, steps);
+    /// Predict using simple exponential smoothing with a fixed alpha
+    pub fn predict_exponential_smoothing(
+        &self,
+        metric_name: &str,
+        steps_ahead: usize,
+        alpha: f64,
+    ) -> Result<Vec<PredictionPoint>> {
+        let series = self.snapshot(metric_name)?;
 
-            predictions.push(PredictionPoint {
-                timestamp: last_timestamp + Duration::minutes(i as i64),
-                value: predicted_value,
-                confidence,
-                lower_bound: predicted_value * (1.0 - 0.1 * (1.0 - confidence)),
-                upper_bound: predicted_value * (1.0 + 0.1 * (1.0 - confidence)),
-            });
+        if series.values.is_empty() {
+            anyhow::bail!("No data available for prediction");
         }
 
-        Ok(predictions)
-    }
-
-    /// Calculate trend using simple linear regression
-    fn calculate_trend(&self, values: &[f64]) -> (f64, f64) {
-        let n = values.len() as f64;
-        let x_sum: f64 = (0..values.len()).map(|i| i as f64).sum();
-        let y_sum: f64 = values.iter().sum();
-        let xy_sum: f64 = values
-            .iter()
-            .enumerate()
-            .map(|(i, &y)| i as f64 * y)
-            .sum();
-        let x_squared_sum: f64 = (0..values.len()).map(|i| (i as f64).powi(2)).sum();
+        // Initialize with first value and collect one-step errors
+        let mut smoothed = series.values[0];
+        let mut squared_errors = 0.0;
+        for &value in &series.values[1..] {
+            squared_errors += (value - smoothed).powi(2);
+            smoothed = alpha * value + (1.0 - alpha) * smoothed;
+        }
+        let sigma2 = squared_errors / (series.values.len() - 1).max(1) as f64;
 
-        let slope = (n * xy_sum - x_sum * y_sum) / (n * x_squared_sum - x_sum.powi(2));
-        let intercept = (y_sum - slope * x_sum) / n;
+        // ETS(A,N,N): Var(h) = sigma^2 * (1 + (h - 1) * alpha^2)
+        let steps = (1..=steps_ahead)
+            .map(|h| ForecastStep {
+                value: smoothed,
+                std_error: (sigma2 * (1.0 + (h - 1) as f64 * alpha * alpha)).sqrt(),
+            })
+            .collect::<Vec<_>>();
 
-        (slope, intercept)
+        Ok(self.to_prediction_points(&series, &steps))
     }
 
-    /// Calculate seasonality (simplified moving average)
-    fn calculate_seasonality(&self, values: &[f64]) -> Vec<f64> {
-        // Simple seasonal pattern detection (use 7-day or hourly patterns)
-        let period = 24.min(values.len() / 2);
-        let mut seasonal = vec![0.0; period];
-
-        for i in 0..period {
-            let mut sum = 0.0;
-            let mut count = 0;
-
-            let mut j = i;
-            while j < values.len() {
-                sum += values[j];
-                count += 1;
-                j += period;
+    /// Return a cached forecast or fit a model on the current history
+    fn cached_or_compute<F>(
+        &self,
+        metric_name: &str,
+        method: &'static str,
+        steps_ahead: usize,
+        fit: F,
+    ) -> Result<Vec<PredictionPoint>>
+    where
+        F: FnOnce(&SeriesSnapshot) -> Result<Vec<ForecastStep>>,
+    {
+        let key = (metric_name.to_string(), method);
+
+        if let Some(cached) = self.predictions.get(&key) {
+            if cached.is_valid() && cached.points.len() >= steps_ahead {
+                return Ok(cached.points[..steps_ahead].to_vec());
             }
+        }
 
-            seasonal[i] = if count > 0 { sum / count as f64 } else { 0.0 };
+        let series = self.snapshot(metric_name)?;
+        if series.values.len() < 10 {
+            anyhow::bail!("Insufficient data for prediction (need at least 10 points)");
         }
 
-        // Normalize seasonal component
-        let mean: f64 = seasonal.iter().sum::<f64>() / seasonal.len() as f64;
-        seasonal.iter().map(|&v| v - mean).collect()
-    }
+        let predictions = self.to_prediction_points(&series, &fit(&series)?);
 
-    /// Calculate prediction confidence
-    fn calculate_confidence(&self, step: usize, total_steps: usize) -> f64 {
-        // Confidence decreases with prediction horizon
-        let base_confidence = 0.95;
-        let decay_rate = 0.05;
-        (base_confidence - decay_rate * (step as f64 / total_steps as f64)).max(0.5)
+        self.predictions.insert(
+            key,
+            CachedPrediction {
+                points: predictions.clone(),
+                created_at: Utc::now(),
+                ttl_seconds: 300, // 5 minutes
+            },
+        );
+
+        Ok(predictions)
     }
 
-    /// Predict using exponential smoothing
-    pub fn predict_exponential_smoothing(
-        &self,
-        metric_name: &str,
-        steps_ahead: usize,
-        alpha: f64,
-    ) -> Result<Vec<PredictionPoint>> {
+    /// Copy the history of a metric so models can be fitted without holding the map lock
+    fn snapshot(&self, metric_name: &str) -> Result<SeriesSnapshot> {
         let ts_data = self
             .time_series
             .get(metric_name)
             .ok_or_else(|| anyhow::anyhow!("No time series data for {}", metric_name))?;
 
-        if ts_data.values.is_empty() {
-            anyhow::bail!("No data available for prediction");
-        }
-
         let values: Vec<f64> = ts_data.values.iter().copied().collect();
-        let last_timestamp = *ts_data.timestamps.back().unwrap();
+        let interval = stats::sampling_interval(&ts_data.timestamps);
+        let seasonal_period = self.seasonal_period(interval, values.len());
+
+        Ok(SeriesSnapshot {
+            values,
+            last_timestamp: ts_data.timestamps.back().copied().unwrap_or_else(Utc::now),
+            interval,
+            seasonal_period,
+        })
+    }
 
-        // Initialize with first value
-        let mut smoothed = values[0];
+    /// Seasonal period in samples: the configured value, otherwise a daily or
+    /// hourly cycle when the history covers at least two of them
+    fn seasonal_period(&self, interval: Duration, history_len: usize) -> Option<usize> {
+        let fits = |period: usize| period >= 2 && history_len >= 2 * period + 2;
 
-        // Calculate smoothed values
-        for &value in &values[1..] {
-            smoothed = alpha * value + (1.0 - alpha) * smoothed;
+        if let Some(period) = self.config.prediction_seasonal_period {
+            return Some(period).filter(|&p| fits(p));
         }
 
-        // Generate predictions (using last smoothed value)
-        let mut predictions = Vec::new();
-        for i in 1..=steps_ahead {
-            predictions.push(PredictionPoint {
-                timestamp: last_timestamp + Duration::minutes(i as i64),
-                value: smoothed,
-                confidence: self.calculate_confidence(i, steps_ahead),
-                lower_bound: smoothed * 0.9,
-                upper_bound: smoothed * 1.1,
-            });
-        }
+        let interval_ms = interval.num_milliseconds().max(1);
+        [Duration::days(1), Duration::hours(1)]
+            .iter()
+            .map(|cycle| (cycle.num_milliseconds() / interval_ms) as usize)
+            .find(|&period| fits(period))
+    }
 
-        Ok(predictions)
+    /// Attach timestamps and prediction intervals to raw forecasts
+    fn to_prediction_points(
+        &self,
+        series: &SeriesSnapshot,
+        steps: &[ForecastStep],
+    ) -> Vec<PredictionPoint> {
+        let level = self.config.prediction_confidence_level;
+        let z = stats::normal_z(level);
+
+        steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| PredictionPoint {
+                timestamp: series.last_timestamp + series.interval * (i as i32 + 1),
+                value: step.value,
+                confidence: level,
+                lower_bound: step.value - z * step.std_error,
+                upper_bound: step.value + z * step.std_error,
+            })
+            .collect()
     }
 
     /// Get prediction statistics
@@ -277,11 +292,27 @@ impl TimeSeriesData {
     }
 }
 
+/// Copy of a metric's history used for model fitting
+struct SeriesSnapshot {
+    values: Vec<f64>,
+    last_timestamp: DateTime<Utc>,
+    interval: Duration,
+    seasonal_period: Option<usize>,
+}
+
+/// Point forecast with its standard error, as produced by a fitted model
+#[derive(Debug, Clone, Copy)]
+pub struct ForecastStep {
+    pub value: f64,
+    pub std_error: f64,
+}
+
 /// Prediction point
 #[derive(Debug, Clone)]
 pub struct PredictionPoint {
     pub timestamp: DateTime<Utc>,
     pub value: f64,
+    /// Nominal coverage of the prediction interval (e.g. 0.95)
     pub confidence: f64,
     pub lower_bound: f64,
     pub upper_bound: f64,
@@ -308,3 +339,56 @@ pub struct PredictionStats {
     pub total_cached_predictions: usize,
     pub total_prediction_points: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn engine_with_series(values: &[f64], interval: Duration) -> PredictionEngine {
+        let config = Arc::new(AnalyticsConfig {
+            prediction_history_size: values.len(),
+            ..Default::default()
+        });
+        let engine = PredictionEngine::new(config).await.unwrap();
+        let start = Utc::now() - interval * values.len() as i32;
+        for (i, &value) in values.iter().enumerate() {
+            engine
+                .add_data_point("latency", value, start + interval * i as i32)
+                .unwrap();
+        }
+        engine
+    }
+
+    #[tokio::test]
+    async fn test_forecast_uses_sampling_interval() {
+        let values: Vec<f64> = (0..60).map(|i| 100.0 + (i as f64 * 0.3).sin()).collect();
+        let engine = engine_with_series(&values, Duration::seconds(15)).await;
+
+        let last_observed = *engine.time_series.get("latency").unwrap().timestamps.back().unwrap();
+        let points = engine.predict_arima("latency", 3).unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].timestamp - last_observed, Duration::seconds(15));
+        assert_eq!(points[2].timestamp - last_observed, Duration::seconds(45));
+    }
+
+    #[tokio::test]
+    async fn test_prediction_intervals_contain_forecast() {
+        let values: Vec<f64> = (0..72)
+            .map(|i| 200.0 + 20.0 * ((i % 24) as f64 / 24.0 * std::f64::consts::TAU).sin())
+            .collect();
+        let engine = engine_with_series(&values, Duration::hours(1)).await;
+
+        for points in [
+            engine.predict_arima("latency", 12).unwrap(),
+            engine
+                .predict_holt_winters("latency", 12, Seasonality::Additive)
+                .unwrap(),
+            engine.predict_exponential_smoothing("latency", 12, 0.3).unwrap(),
+        ] {
+            for point in &points {
+                assert!(point.lower_bound <= point.value && point.value <= point.upper_bound);
+                assert_eq!(point.confidence, 0.95);
+            }
+        }
+    }
+}
@@ -0,0 +1,1024 @@
+//! Root Cause Analysis
+//!
+//! When an anomaly fires, the events and other anomalies observed around it
+//! are linked into a causal graph using timing, `parent_event_id`, shared
+//! correlation IDs and metric-level statistical relationships. Candidate root
+//! causes are ranked by the strongest causal path to the anomaly, and the
+//! performance, cost and security impact of the incident is assessed. Reports
+//! are kept per anomaly ID.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use statrs::distribution::{ContinuousCDF, Normal};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use super::anomaly::{self, Anomaly};
+use super::causality::MetricRelationship;
+use super::correlation::{
+    event_metrics, module_name, CorrelationEngine, EvictionQueue, EVENT_COUNT_METRIC,
+};
+use crate::models::correlation::{
+    AnomalyCorrelation, AnomalyEvent, AnomalyType, CausalLink, CausalRelationship, CorrelationId,
+    CorrelationType, CostImpact, ImpactAssessment, ImpactSeverity, PerformanceImpact,
+    RootCauseAnalysis, SecurityImpact,
+};
+use crate::schemas::events::{
+    AnalyticsEvent, CostPayload, EventPayload, EventType, GovernancePayload, MitigationStatus,
+    PrivacyOperation, SecurityPayload, Severity, SourceModule, TelemetryPayload, ThreatLevel,
+};
+
+/// Buffered events kept; the oldest are evicted beyond this
+const MAX_BUFFERED_EVENTS: usize = 50_000;
+
+/// Buffered anomalies kept; the oldest are evicted beyond this
+const MAX_BUFFERED_ANOMALIES: usize = 10_000;
+
+/// Root cause analysis configuration
+#[derive(Debug, Clone)]
+pub struct RcaConfig {
+    /// How far before the anomaly to look for causes
+    pub lookback: Duration,
+    /// How far after the anomaly to look for effects
+    pub lookahead: Duration,
+    /// Weakest link kept in the causal graph
+    pub min_link_strength: f64,
+    /// Maximum number of events considered per analysis (closest in time)
+    pub max_events: usize,
+    /// Number of ranked root cause candidates reported
+    pub max_candidates: usize,
+    /// How long events and anomalies are buffered
+    pub retention: Duration,
+}
+
+impl Default for RcaConfig {
+    fn default() -> Self {
+        Self {
+            lookback: Duration::minutes(15),
+            lookahead: Duration::minutes(2),
+            min_link_strength: 0.2,
+            max_events: 500,
+            max_candidates: 5,
+            retention: Duration::hours(6),
+        }
+    }
+}
+
+/// A ranked root cause candidate
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootCauseCandidate {
+    pub event_id: Uuid,
+    pub source_module: SourceModule,
+    pub description: String,
+    /// Strength of the best causal path to the anomaly, weighted by the
+    /// candidate's severity and how likely it is to be an origin (0.0 - 1.0)
+    pub confidence: f64,
+    /// Time between the candidate and the anomaly
+    pub lead_time_ms: i64,
+}
+
+/// Root cause analysis of one anomaly
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootCauseReport {
+    pub anomaly_id: Uuid,
+    /// The anomaly, related anomalies, the selected root cause and impact
+    pub correlation: AnomalyCorrelation,
+    /// Ranked candidates, best first
+    pub candidates: Vec<RootCauseCandidate>,
+    /// Number of events considered
+    pub events_considered: usize,
+    pub analyzed_at: DateTime<Utc>,
+}
+
+impl RootCauseReport {
+    /// Selected root cause, if any candidate was linked to the anomaly
+    pub fn root_cause(&self) -> Option<&RootCauseAnalysis> {
+        self.correlation.root_cause.as_ref()
+    }
+}
+
+/// Root cause analysis engine
+pub struct RootCauseEngine {
+    config: RcaConfig,
+    correlation: Arc<CorrelationEngine>,
+    events: Arc<DashMap<Uuid, AnalyticsEvent>>,
+    event_order: EvictionQueue,
+    anomalies: Arc<DashMap<Uuid, AnomalyEvent>>,
+    anomaly_order: EvictionQueue,
+    reports: Arc<DashMap<Uuid, RootCauseReport>>,
+}
+
+/// Node of the causal graph
+#[derive(Debug, Clone)]
+struct Node {
+    id: Uuid,
+    module: SourceModule,
+    timestamp: DateTime<Utc>,
+    parent: Option<Uuid>,
+    correlation_id: Option<Uuid>,
+    /// Metric series ("<module>.<metric>") the node contributes to
+    series: Vec<String>,
+    /// Prior likelihood of being a cause, from severity or anomaly score
+    weight: f64,
+    /// Event type; None for anomalies
+    event_type: Option<EventType>,
+    description: String,
+}
+
+/// Directed link between two nodes
+#[derive(Debug, Clone)]
+struct Link {
+    relationship: CausalRelationship,
+    strength: f64,
+}
+
+impl RootCauseEngine {
+    /// Create a new engine using `correlation` for metric relationships
+    pub fn new(config: RcaConfig, correlation: Arc<CorrelationEngine>) -> Self {
+        Self {
+            config,
+            correlation,
+            events: Arc::new(DashMap::new()),
+            event_order: EvictionQueue::new(MAX_BUFFERED_EVENTS),
+            anomalies: Arc::new(DashMap::new()),
+            anomaly_order: EvictionQueue::new(MAX_BUFFERED_ANOMALIES),
+            reports: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Buffer an event as a potential cause or effect
+    pub fn record_event(&self, event: &AnalyticsEvent) {
+        let common = &event.common;
+        if self.events.insert(common.event_id, event.clone()).is_none() {
+            let cutoff = common.timestamp - self.config.retention;
+            for evicted in self
+                .event_order
+                .push(common.event_id, common.timestamp, cutoff)
+            {
+                self.events.remove(&evicted);
+            }
+        }
+    }
+
+    /// Buffer an anomaly without analyzing it
+    pub fn record_anomaly(&self, anomaly: &AnomalyEvent) {
+        if self
+            .anomalies
+            .insert(anomaly.event_id, anomaly.clone())
+            .is_none()
+        {
+            let cutoff = anomaly.timestamp - self.config.retention;
+            for evicted in self
+                .anomaly_order
+                .push(anomaly.event_id, anomaly.timestamp, cutoff)
+            {
+                self.anomalies.remove(&evicted);
+            }
+        }
+    }
+
+    /// Analyze an anomaly and store the report under its ID
+    pub fn analyze(&self, anomaly: &AnomalyEvent) -> Result<RootCauseReport> {
+        self.record_anomaly(anomaly);
+
+        let start = anomaly.timestamp - self.config.lookback;
+        let end = anomaly.timestamp + self.config.lookahead;
+
+        let mut events: Vec<AnalyticsEvent> = self
+            .events
+            .iter()
+            .filter(|e| e.common.timestamp >= start && e.common.timestamp <= end)
+            .map(|e| e.value().clone())
+            .collect();
+        events.sort_by_key(|e| {
+            (e.common.timestamp - anomaly.timestamp)
+                .num_milliseconds()
+                .abs()
+        });
+        events.truncate(self.config.max_events);
+
+        let mut related: Vec<AnomalyEvent> = self
+            .anomalies
+            .iter()
+            .filter(|a| a.event_id != anomaly.event_id)
+            .filter(|a| a.timestamp >= start && a.timestamp <= end)
+            .map(|a| a.value().clone())
+            .collect();
+        related.sort_by_key(|a| a.timestamp);
+
+        // Graph nodes in time order; the analyzed anomaly sorts after
+        // anything observed at the same instant
+        let mut nodes: Vec<Node> = events.iter().map(event_node).collect();
+        nodes.extend(related.iter().map(anomaly_node));
+        nodes.sort_by_key(|n| n.timestamp);
+        let split = nodes.partition_point(|n| n.timestamp <= anomaly.timestamp);
+        nodes.insert(split, anomaly_node(anomaly));
+        let target = split;
+
+        let links = self.build_links(&nodes);
+        let ranked = rank_candidates(&nodes, &links, target);
+
+        let candidates: Vec<RootCauseCandidate> = ranked
+            .iter()
+            .take(self.config.max_candidates)
+            .map(|&(index, confidence)| {
+                let node = &nodes[index];
+                RootCauseCandidate {
+                    event_id: node.id,
+                    source_module: node.module.clone(),
+                    description: node.description.clone(),
+                    confidence,
+                    lead_time_ms: (anomaly.timestamp - node.timestamp).num_milliseconds(),
+                }
+            })
+            .collect();
+
+        let root_cause = ranked.first().map(|&(root, confidence)| {
+            let chain = causal_chain(&nodes, &links, root, target);
+            let contributing_factors = ranked
+                .iter()
+                .skip(1)
+                .take(self.config.max_candidates.saturating_sub(1))
+                .map(|&(index, confidence)| {
+                    format!(
+                        "{} (confidence {:.2})",
+                        nodes[index].description, confidence
+                    )
+                })
+                .chain(related.iter().map(|a| anomaly_node(a).description))
+                .collect();
+
+            RootCauseAnalysis {
+                root_event_id: nodes[root].id,
+                confidence,
+                causal_chain: chain,
+                contributing_factors,
+                recommendations: recommendations(&nodes[root], anomaly),
+            }
+        });
+
+        let root_time = root_cause
+            .as_ref()
+            .and_then(|rca| nodes.iter().find(|n| n.id == rca.root_event_id))
+            .map(|n| n.timestamp)
+            .unwrap_or(anomaly.timestamp);
+
+        let mut affected_modules = vec![anomaly.source_module.clone()];
+        for candidate in &candidates {
+            if !affected_modules.contains(&candidate.source_module) {
+                affected_modules.push(candidate.source_module.clone());
+            }
+        }
+
+        let impact = assess_impact(anomaly, &events, start, root_time, end, affected_modules);
+
+        let mut anomalies = vec![anomaly.clone()];
+        anomalies.extend(related);
+
+        let report = RootCauseReport {
+            anomaly_id: anomaly.event_id,
+            correlation: AnomalyCorrelation {
+                correlation_id: CorrelationId::new(),
+                anomalies,
+                strength: candidates.first().map(|c| c.confidence).unwrap_or(0.0),
+                root_cause,
+                impact,
+                detected_at: anomaly.timestamp,
+            },
+            candidates,
+            events_considered: events.len(),
+            analyzed_at: Utc::now(),
+        };
+
+        match report.root_cause() {
+            Some(rca) => info!(
+                "Root cause for anomaly {} in {}: {} (confidence {:.2})",
+                anomaly.event_id, anomaly.metric, rca.root_event_id, rca.confidence
+            ),
+            None => debug!("No root cause candidates for anomaly {}", anomaly.event_id),
+        }
+
+        self.reports.insert(anomaly.event_id, report.clone());
+        Ok(report)
+    }
+
+    /// Get the stored report for an anomaly
+    pub fn get_analysis(&self, anomaly_id: Uuid) -> Option<RootCauseReport> {
+        self.reports.get(&anomaly_id).map(|r| r.clone())
+    }
+
+    /// Most recent reports, newest first
+    pub fn recent_analyses(&self, limit: usize) -> Vec<RootCauseReport> {
+        let mut reports: Vec<RootCauseReport> =
+            self.reports.iter().map(|r| r.value().clone()).collect();
+        reports.sort_by_key(|r| std::cmp::Reverse(r.analyzed_at));
+        reports.truncate(limit);
+        reports
+    }
+
+    /// Links from earlier to later nodes, indexed by (from, to)
+    fn build_links(&self, nodes: &[Node]) -> HashMap<(usize, usize), Link> {
+        let mut relationships: HashMap<(String, String), Option<MetricRelationship>> =
+            HashMap::new();
+        let tau = (self.config.lookback.num_milliseconds() as f64 / 4.0).max(1.0);
+        let mut links = HashMap::new();
+
+        for (i, from) in nodes.iter().enumerate() {
+            for (j, to) in nodes.iter().enumerate().skip(i + 1) {
+                let delta = (to.timestamp - from.timestamp).num_milliseconds().max(0) as f64;
+                let proximity = (-delta / tau).exp();
+                let mut best = Link {
+                    relationship: CausalRelationship::Correlation,
+                    strength: if from.module == to.module {
+                        0.2 * proximity
+                    } else {
+                        0.4 * proximity
+                    },
+                };
+                let mut consider = |relationship, strength: f64| {
+                    if strength > best.strength {
+                        best = Link {
+                            relationship,
+                            strength,
+                        };
+                    }
+                };
+
+                if to.parent == Some(from.id) {
+                    consider(CausalRelationship::DirectCause, 1.0);
+                }
+                if from.correlation_id.is_some() && from.correlation_id == to.correlation_id {
+                    consider(CausalRelationship::IndirectCause, 0.6);
+                }
+
+                for cause in &from.series {
+                    for effect in &to.series {
+                        if cause == effect {
+                            // Observations of the same metric, e.g. a slow
+                            // request and the latency anomaly it feeds into
+                            if cause.rsplit('.').next() != Some(EVENT_COUNT_METRIC) {
+                                consider(CausalRelationship::Correlation, 0.8 * proximity);
+                            }
+                            continue;
+                        }
+                        let relationship = relationships
+                            .entry((cause.clone(), effect.clone()))
+                            .or_insert_with(|| {
+                                self.correlation.analyze_metrics(cause, effect).ok()
+                            });
+                        match relationship {
+                            Some(r) if r.correlation_type == Some(CorrelationType::CausalChain) => {
+                                consider(CausalRelationship::DirectCause, r.strength * r.confidence)
+                            }
+                            Some(r) if r.correlation_type == Some(CorrelationType::Temporal) => {
+                                consider(CausalRelationship::Correlation, 0.7 * r.strength)
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+
+                if best.strength >= self.config.min_link_strength {
+                    links.insert((i, j), best);
+                }
+            }
+        }
+
+        links
+    }
+}
+
+/// Rank nodes before `target` by their strongest causal path to it.
+///
+/// Path strength is the product of link strengths. Nodes that are themselves
+/// strongly explained by an earlier node are discounted, so origins rank
+/// above intermediate steps.
+fn rank_candidates(
+    nodes: &[Node],
+    links: &HashMap<(usize, usize), Link>,
+    target: usize,
+) -> Vec<(usize, f64)> {
+    let reach = path_strengths(nodes.len(), links, target);
+
+    // Strongest incoming link of each node
+    let mut explained = vec![0.0_f64; nodes.len()];
+    for (&(_, to), link) in links {
+        explained[to] = explained[to].max(link.strength);
+    }
+
+    let mut ranked: Vec<(usize, f64)> = (0..target)
+        .filter(|&i| reach[i].0 > 0.0)
+        .map(|i| {
+            let score = reach[i].0 * nodes[i].weight * (1.0 - 0.5 * explained[i]);
+            (i, score.clamp(0.0, 1.0))
+        })
+        .collect();
+
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    ranked
+}
+
+/// Strongest path from every node to `target` and the next hop along it
+fn path_strengths(
+    len: usize,
+    links: &HashMap<(usize, usize), Link>,
+    target: usize,
+) -> Vec<(f64, Option<usize>)> {
+    let mut reach = vec![(0.0, None); len];
+    reach[target].0 = 1.0;
+
+    // Links always point forward in time, so a reverse sweep visits every
+    // successor before its predecessors
+    for i in (0..target).rev() {
+        for j in i + 1..=target {
+            if let Some(link) = links.get(&(i, j)) {
+                let strength = link.strength * reach[j].0;
+                if strength > reach[i].0 {
+                    reach[i] = (strength, Some(j));
+                }
+            }
+        }
+    }
+    reach
+}
+
+/// Causal links along the strongest path from `root` to `target`
+fn causal_chain(
+    nodes: &[Node],
+    links: &HashMap<(usize, usize), Link>,
+    root: usize,
+    target: usize,
+) -> Vec<CausalLink> {
+    let reach = path_strengths(nodes.len(), links, target);
+    let mut chain = Vec::new();
+    let mut current = root;
+    while let Some(next) = reach[current].1 {
+        let link = &links[&(current, next)];
+        chain.push(CausalLink {
+            from_event_id: nodes[current].id,
+            to_event_id: nodes[next].id,
+            relationship: link.relationship.clone(),
+            strength: link.strength,
+            time_delta_ms: (nodes[next].timestamp - nodes[current].timestamp).num_milliseconds(),
+        });
+        current = next;
+    }
+    chain
+}
+
+fn event_node(event: &AnalyticsEvent) -> Node {
+    let module = module_name(&event.common.source_module);
+    let weight = match event.common.severity {
+        Severity::Debug => 0.3,
+        Severity::Info => 0.4,
+        Severity::Warning => 0.6,
+        Severity::Error => 0.8,
+        Severity::Critical => 1.0,
+    };
+
+    Node {
+        id: event.common.event_id,
+        module: event.common.source_module.clone(),
+        timestamp: event.common.timestamp,
+        parent: event.common.parent_event_id,
+        correlation_id: event.common.correlation_id,
+        series: event_metrics(event)
+            .into_iter()
+            .map(|(metric, _, _)| format!("{}.{}", module, metric))
+            .collect(),
+        weight,
+        event_type: Some(event.common.event_type.clone()),
+        description: format!("{} {} event", module, payload_kind(&event.payload)),
+    }
+}
+
+fn anomaly_node(anomaly: &AnomalyEvent) -> Node {
+    let series = anomaly_series(anomaly);
+    let kind = match anomaly.anomaly_type {
+        AnomalyType::Spike => "spike",
+        AnomalyType::Drop => "drop",
+        AnomalyType::PatternDeviation => "pattern deviation",
+        AnomalyType::FrequencyAnomaly => "frequency anomaly",
+        AnomalyType::DistributionShift => "distribution shift",
+    };
+
+    Node {
+        id: anomaly.event_id,
+        module: anomaly.source_module.clone(),
+        timestamp: anomaly.timestamp,
+        parent: None,
+        correlation_id: None,
+        description: format!(
+            "{} in {} ({:.2} vs baseline {:.2})",
+            kind, series, anomaly.observed, anomaly.baseline
+        ),
+        series: vec![series],
+        weight: 0.5 + 0.5 * anomaly.anomaly_score.clamp(0.0, 1.0),
+        event_type: None,
+    }
+}
+
+/// Series name of an anomaly's metric, qualified by module if needed
+fn anomaly_series(anomaly: &AnomalyEvent) -> String {
+    if anomaly.metric.contains('.') {
+        anomaly.metric.clone()
+    } else {
+        format!("{}.{}", module_name(&anomaly.source_module), anomaly.metric)
+    }
+}
+
+pub(crate) fn payload_kind(payload: &EventPayload) -> &'static str {
+    match payload {
+        EventPayload::Telemetry(TelemetryPayload::Latency(_)) => "latency",
+        EventPayload::Telemetry(TelemetryPayload::Throughput(_)) => "throughput",
+        EventPayload::Telemetry(TelemetryPayload::ErrorRate(_)) => "error rate",
+        EventPayload::Telemetry(TelemetryPayload::TokenUsage(_)) => "token usage",
+        EventPayload::Telemetry(TelemetryPayload::ModelPerformance(_)) => "model performance",
+        EventPayload::Telemetry(TelemetryPayload::SloStatus(_)) => "SLO status",
+        EventPayload::Security(SecurityPayload::Threat(_)) => "threat",
+        EventPayload::Security(SecurityPayload::Vulnerability(_)) => "vulnerability",
+        EventPayload::Security(SecurityPayload::ComplianceViolation(_)) => "compliance violation",
+        EventPayload::Security(SecurityPayload::Auth(_)) => "auth",
+        EventPayload::Security(SecurityPayload::Privacy(_)) => "privacy",
+        EventPayload::Cost(CostPayload::TokenCost(_)) => "token cost",
+        EventPayload::Cost(CostPayload::ApiCost(_)) => "API cost",
+        EventPayload::Cost(CostPayload::ResourceConsumption(_)) => "resource consumption",
+        EventPayload::Cost(CostPayload::BudgetAlert(_)) => "budget alert",
+        EventPayload::Governance(GovernancePayload::PolicyViolation(_)) => "policy violation",
+        EventPayload::Governance(GovernancePayload::AuditTrail(_)) => "audit trail",
+        EventPayload::Governance(GovernancePayload::ComplianceCheck(_)) => "compliance check",
+        EventPayload::Governance(GovernancePayload::DataLineage(_)) => "data lineage",
+        EventPayload::Custom(_) => "custom",
+    }
+}
+
+fn recommendations(root: &Node, anomaly: &AnomalyEvent) -> Vec<String> {
+    let mut recommendations = vec![match &root.event_type {
+        None => format!(
+            "Investigate the upstream {} before remediating {}",
+            root.description,
+            anomaly_series(anomaly)
+        ),
+        Some(EventType::Telemetry) => {
+            "Check model latency, error rates and capacity; fail over or scale out if a model is degraded"
+                .to_string()
+        }
+        Some(EventType::Cost) => {
+            "Review token usage and pricing behind the cost change; apply rate limits or route to cheaper models"
+                .to_string()
+        }
+        Some(EventType::Security) => {
+            "Review the LLM-Sentinel findings and contain the affected resources".to_string()
+        }
+        Some(EventType::Governance) => {
+            "Review the triggering policy violation and its remediation status".to_string()
+        }
+        Some(EventType::Lifecycle) | Some(EventType::Audit) => {
+            "Review recent deployments and configuration changes".to_string()
+        }
+        Some(EventType::Alert) => "Follow up on the originating alert".to_string(),
+    }];
+
+    if root.module != anomaly.source_module {
+        recommendations.push(format!(
+            "Coordinate with the {} owners; the anomaly in {} is downstream of it",
+            module_name(&root.module),
+            module_name(&anomaly.source_module)
+        ));
+    }
+
+    recommendations
+}
+
+/// Relative increase from `baseline` to `current` in percent (never negative)
+fn percent_increase(baseline: f64, current: f64) -> f64 {
+    if baseline > 0.0 {
+        (100.0 * (current - baseline) / baseline).max(0.0)
+    } else if current > 0.0 {
+        100.0
+    } else {
+        0.0
+    }
+}
+
+fn mean_of(values: &[f64]) -> Option<f64> {
+    (!values.is_empty()).then(|| super::stats::mean(values))
+}
+
+/// Assess impact by comparing the incident (from the root cause to the end of
+/// the window) against the baseline before it
+fn assess_impact(
+    anomaly: &AnomalyEvent,
+    events: &[AnalyticsEvent],
+    start: DateTime<Utc>,
+    incident_start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    affected_modules: Vec<SourceModule>,
+) -> ImpactAssessment {
+    #[derive(Default)]
+    struct Window {
+        latency: Vec<f64>,
+        throughput: Vec<f64>,
+        error_rate: Vec<f64>,
+        requests: u64,
+        cost_usd: f64,
+        wasted_usd: f64,
+        cost_events: usize,
+    }
+
+    let mut baseline = Window::default();
+    let mut incident = Window::default();
+    let mut security = SecurityImpact {
+        threats_detected: 0,
+        vulnerabilities_exposed: 0,
+        data_at_risk: false,
+        compliance_violations: 0,
+    };
+    let mut security_events = 0;
+
+    for event in events {
+        let window = if event.common.timestamp < incident_start {
+            &mut baseline
+        } else {
+            &mut incident
+        };
+
+        match &event.payload {
+            EventPayload::Telemetry(TelemetryPayload::Latency(latency)) => {
+                window.latency.push(latency.total_latency_ms);
+                window.requests += 1;
+            }
+            EventPayload::Telemetry(TelemetryPayload::Throughput(throughput)) => {
+                window.throughput.push(throughput.requests_per_second);
+            }
+            EventPayload::Telemetry(TelemetryPayload::ErrorRate(errors)) => {
+                window.error_rate.push(errors.error_rate_percent);
+                window.requests += errors.failed_requests;
+            }
+            EventPayload::Cost(CostPayload::TokenCost(cost)) => {
+                window.cost_usd += cost.total_cost_usd;
+                window.cost_events += 1;
+            }
+            EventPayload::Cost(CostPayload::ApiCost(cost)) => {
+                window.cost_usd += cost.total_cost_usd;
+                window.cost_events += 1;
+            }
+            EventPayload::Cost(CostPayload::ResourceConsumption(resource)) => {
+                window.cost_usd += resource.cost_usd;
+                window.wasted_usd += resource.cost_usd
+                    * (1.0 - resource.utilization_percent / 100.0).clamp(0.0, 1.0);
+                window.cost_events += 1;
+            }
+            EventPayload::Security(payload) => {
+                security_events += 1;
+                match payload {
+                    SecurityPayload::Threat(threat) => {
+                        security.threats_detected += 1;
+                        let contained = matches!(
+                            threat.mitigation_status,
+                            MitigationStatus::Blocked
+                                | MitigationStatus::Mitigated
+                                | MitigationStatus::Resolved
+                        );
+                        if threat.threat_level >= ThreatLevel::High && !contained {
+                            security.data_at_risk = true;
+                        }
+                    }
+                    SecurityPayload::Vulnerability(_) => security.vulnerabilities_exposed += 1,
+                    SecurityPayload::ComplianceViolation(_) => security.compliance_violations += 1,
+                    SecurityPayload::Privacy(privacy) => {
+                        if !privacy.user_consent
+                            && matches!(
+                                privacy.operation,
+                                PrivacyOperation::DataAccess | PrivacyOperation::DataSharing
+                            )
+                        {
+                            security.data_at_risk = true;
+                        }
+                    }
+                    SecurityPayload::Auth(_) => {}
+                }
+            }
+            EventPayload::Governance(GovernancePayload::PolicyViolation(_)) => {
+                security_events += 1;
+                security.compliance_violations += 1;
+            }
+            EventPayload::Governance(GovernancePayload::ComplianceCheck(check))
+                if !check.passed =>
+            {
+                security_events += 1;
+                security.compliance_violations += 1;
+            }
+            _ => {}
+        }
+    }
+
+    let has_telemetry = !(baseline.latency.is_empty()
+        && incident.latency.is_empty()
+        && baseline.throughput.is_empty()
+        && incident.throughput.is_empty()
+        && baseline.error_rate.is_empty()
+        && incident.error_rate.is_empty());
+
+    let change = |before: &[f64], after: &[f64]| match (mean_of(before), mean_of(after)) {
+        (Some(before), Some(after)) => Some((before, after)),
+        _ => None,
+    };
+
+    let performance_impact = has_telemetry.then(|| PerformanceImpact {
+        latency_increase_percent: change(&baseline.latency, &incident.latency)
+            .map(|(before, after)| percent_increase(before, after))
+            .unwrap_or(0.0),
+        throughput_decrease_percent: change(&baseline.throughput, &incident.throughput)
+            .map(|(before, after)| {
+                if before > 0.0 {
+                    (100.0 * (before - after) / before).max(0.0)
+                } else {
+                    0.0
+                }
+            })
+            .unwrap_or(0.0),
+        error_rate_increase_percent: change(&baseline.error_rate, &incident.error_rate)
+            .map(|(before, after)| percent_increase(before, after))
+            .unwrap_or(0.0),
+        affected_requests: incident.requests,
+    });
+
+    let cost_impact = (baseline.cost_events + incident.cost_events > 0).then(|| {
+        let baseline_secs = (incident_start - start).num_seconds().max(1) as f64;
+        let incident_secs = (end - incident_start).num_seconds().max(1) as f64;
+        let baseline_rate = baseline.cost_usd / baseline_secs;
+        let incident_rate = incident.cost_usd / incident_secs;
+        CostImpact {
+            additional_cost_usd: (incident.cost_usd - baseline_rate * incident_secs).max(0.0),
+            cost_increase_percent: percent_increase(baseline_rate, incident_rate),
+            wasted_resources_usd: incident.wasted_usd,
+        }
+    });
+
+    let mut severity = match anomaly.anomaly_score {
+        s if s >= 0.999 => ImpactSeverity::High,
+        s if s >= 0.99 => ImpactSeverity::Medium,
+        s if s >= 0.95 => ImpactSeverity::Low,
+        _ => ImpactSeverity::Negligible,
+    };
+    let worst_increase = [
+        performance_impact
+            .as_ref()
+            .map(|p| {
+                p.latency_increase_percent
+                    .max(p.throughput_decrease_percent)
+                    .max(p.error_rate_increase_percent)
+            })
+            .unwrap_or(0.0),
+        cost_impact
+            .as_ref()
+            .map(|c| c.cost_increase_percent)
+            .unwrap_or(0.0),
+    ]
+    .into_iter()
+    .fold(0.0, f64::max);
+    if worst_increase >= 100.0 {
+        severity = severity.max(ImpactSeverity::High);
+    } else if worst_increase >= 25.0 {
+        severity = severity.max(ImpactSeverity::Medium);
+    }
+    if security.threats_detected > 0 || security.compliance_violations > 0 {
+        severity = severity.max(ImpactSeverity::High);
+    }
+    if security.data_at_risk {
+        severity = ImpactSeverity::Critical;
+    }
+
+    ImpactAssessment {
+        severity,
+        affected_modules,
+        performance_impact,
+        cost_impact,
+        security_impact: (security_events > 0).then_some(security),
+        business_impact: None,
+    }
+}
+
+/// Convert a detector anomaly into an [`AnomalyEvent`] for analysis.
+///
+/// The anomaly score is the two-sided normal probability of the deviation,
+/// e.g. a z-score of 3 maps to 0.997.
+pub fn anomaly_event(anomaly: &Anomaly, source_module: SourceModule) -> AnomalyEvent {
+    let normal = Normal::new(0.0, 1.0).expect("standard normal is valid");
+    let z = anomaly.deviation.abs();
+
+    AnomalyEvent {
+        event_id: Uuid::new_v4(),
+        source_module,
+        anomaly_type: match anomaly.anomaly_type {
+            anomaly::AnomalyType::Spike | anomaly::AnomalyType::HighValue => AnomalyType::Spike,
+            anomaly::AnomalyType::Drop | anomaly::AnomalyType::LowValue => AnomalyType::Drop,
+            anomaly::AnomalyType::Pattern => AnomalyType::PatternDeviation,
+        },
+        anomaly_score: (2.0 * normal.cdf(z) - 1.0).clamp(0.0, 1.0),
+        baseline: anomaly.expected_value,
+        observed: anomaly.value,
+        deviation: anomaly.value - anomaly.expected_value,
+        timestamp: anomaly.timestamp,
+        metric: anomaly.metric_name.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::events::{
+        fixtures, LatencyMetrics, ThreatEvent, ThreatType, TokenCostEvent,
+    };
+
+    fn event(
+        timestamp: DateTime<Utc>,
+        source_module: SourceModule,
+        event_type: EventType,
+        severity: Severity,
+        parent_event_id: Option<Uuid>,
+        payload: EventPayload,
+    ) -> AnalyticsEvent {
+        fixtures::event(payload)
+            .at(timestamp)
+            .with_source(source_module)
+            .with_event_type(event_type)
+            .with_severity(severity)
+            .with_parent(parent_event_id)
+    }
+
+    fn latency(timestamp: DateTime<Utc>, ms: f64, parent: Option<Uuid>) -> AnalyticsEvent {
+        event(
+            timestamp,
+            SourceModule::LlmObservatory,
+            EventType::Telemetry,
+            Severity::Info,
+            parent,
+            EventPayload::Telemetry(TelemetryPayload::Latency(LatencyMetrics {
+                model_id: "gpt-4".to_string(),
+                request_id: Uuid::new_v4().to_string(),
+                total_latency_ms: ms,
+                ttft_ms: None,
+                tokens_per_second: None,
+                breakdown: None,
+            })),
+        )
+    }
+
+    fn threat(timestamp: DateTime<Utc>) -> AnalyticsEvent {
+        event(
+            timestamp,
+            SourceModule::LlmSentinel,
+            EventType::Security,
+            Severity::Critical,
+            None,
+            EventPayload::Security(SecurityPayload::Threat(ThreatEvent {
+                threat_id: "t-1".to_string(),
+                threat_type: ThreatType::DenialOfService,
+                threat_level: ThreatLevel::High,
+                source_ip: None,
+                target_resource: "gateway".to_string(),
+                attack_vector: "flood".to_string(),
+                mitigation_status: MitigationStatus::Detected,
+                indicators_of_compromise: vec![],
+            })),
+        )
+    }
+
+    fn token_cost(timestamp: DateTime<Utc>, cost: f64) -> AnalyticsEvent {
+        event(
+            timestamp,
+            SourceModule::LlmCostOps,
+            EventType::Cost,
+            Severity::Info,
+            None,
+            EventPayload::Cost(CostPayload::TokenCost(TokenCostEvent {
+                model_id: "gpt-4".to_string(),
+                request_id: Uuid::new_v4().to_string(),
+                prompt_tokens: 0,
+                completion_tokens: 0,
+                total_tokens: 0,
+                cost_per_prompt_token: 0.00003,
+                cost_per_completion_token: 0.00006,
+                total_cost_usd: cost,
+                currency: "USD".to_string(),
+            })),
+        )
+    }
+
+    fn latency_anomaly(timestamp: DateTime<Utc>) -> AnomalyEvent {
+        AnomalyEvent {
+            event_id: Uuid::new_v4(),
+            source_module: SourceModule::LlmObservatory,
+            anomaly_type: AnomalyType::Spike,
+            anomaly_score: 0.995,
+            baseline: 200.0,
+            observed: 1400.0,
+            deviation: 1200.0,
+            timestamp,
+            metric: "latency_ms".to_string(),
+        }
+    }
+
+    fn engine() -> RootCauseEngine {
+        RootCauseEngine::new(RcaConfig::default(), Arc::new(CorrelationEngine::new()))
+    }
+
+    #[test]
+    fn test_parent_chain_identifies_root_cause() {
+        let engine = engine();
+        let now = Utc::now();
+
+        for minute in (6..14).rev() {
+            engine.record_event(&latency(now - Duration::minutes(minute), 200.0, None));
+        }
+        let attack = threat(now - Duration::minutes(5));
+        let slow = latency(
+            now - Duration::minutes(1),
+            1400.0,
+            Some(attack.common.event_id),
+        );
+        engine.record_event(&attack);
+        engine.record_event(&slow);
+        engine.record_event(&token_cost(now - Duration::minutes(14), 0.2));
+
+        let anomaly = latency_anomaly(now);
+        let report = engine.analyze(&anomaly).unwrap();
+
+        let rca = report.root_cause().unwrap();
+        assert_eq!(rca.root_event_id, attack.common.event_id);
+        assert_eq!(rca.causal_chain[0].from_event_id, attack.common.event_id);
+        assert_eq!(rca.causal_chain[0].to_event_id, slow.common.event_id);
+        assert_eq!(
+            rca.causal_chain[0].relationship,
+            CausalRelationship::DirectCause
+        );
+        assert_eq!(
+            rca.causal_chain.last().unwrap().to_event_id,
+            anomaly.event_id
+        );
+        assert!(!rca.recommendations.is_empty());
+
+        let impact = &report.correlation.impact;
+        assert_eq!(impact.severity, ImpactSeverity::Critical);
+        assert_eq!(impact.security_impact.as_ref().unwrap().threats_detected, 1);
+        assert!(impact.affected_modules.contains(&SourceModule::LlmSentinel));
+        let performance = impact.performance_impact.as_ref().unwrap();
+        assert!(performance.latency_increase_percent > 500.0);
+
+        let stored = engine.get_analysis(anomaly.event_id).unwrap();
+        assert_eq!(stored.anomaly_id, anomaly.event_id);
+        assert!(engine.get_analysis(Uuid::new_v4()).is_none());
+    }
+
+    #[test]
+    fn test_cost_impact_and_related_anomalies() {
+        let engine = engine();
+        let now = Utc::now();
+
+        for minute in 3..15 {
+            engine.record_event(&token_cost(now - Duration::minutes(minute), 1.0));
+        }
+        let earlier = AnomalyEvent {
+            event_id: Uuid::new_v4(),
+            source_module: SourceModule::LlmCostOps,
+            metric: "cost_usd".to_string(),
+            timestamp: now - Duration::minutes(2),
+            ..latency_anomaly(now)
+        };
+        engine.record_anomaly(&earlier);
+        for second in [90, 60, 30] {
+            engine.record_event(&token_cost(now - Duration::seconds(second), 10.0));
+        }
+
+        let report = engine.analyze(&latency_anomaly(now)).unwrap();
+        assert_eq!(report.correlation.anomalies.len(), 2);
+        assert_eq!(report.candidates[0].event_id, earlier.event_id);
+
+        let cost = report.correlation.impact.cost_impact.as_ref().unwrap();
+        assert!(cost.additional_cost_usd > 20.0);
+        assert!(cost.cost_increase_percent > 100.0);
+    }
+
+    #[test]
+    fn test_anomaly_event_from_detector() {
+        let detected = Anomaly {
+            metric_name: "latency_ms".to_string(),
+            timestamp: Utc::now(),
+            value: 500.0,
+            expected_value: 200.0,
+            deviation: 3.0,
+            anomaly_type: anomaly::AnomalyType::HighValue,
+            severity: anomaly::AnomalySeverity::Medium,
+        };
+        let event = anomaly_event(&detected, SourceModule::LlmObservatory);
+        assert_eq!(event.anomaly_type, AnomalyType::Spike);
+        assert!((event.anomaly_score - 0.9973).abs() < 1e-3);
+        assert_eq!(event.deviation, 300.0);
+    }
+}
@@ -0,0 +1,687 @@
+//! Session Analytics
+//!
+//! Reconstructs conversations from isolated requests. Requests are grouped
+//! by `session_id` (or `conversation_id`) tag, or by request-to-session links
+//! imported from LLM-Memory-Graph context lineage. Each distinct request is a
+//! turn, ordered by when it was first seen.
+//!
+//! A session ends after `idle_timeout` without activity. Ended sessions are
+//! summarized (turns, duration, tokens, cost, abandonment) and persisted with
+//! their turns as separate time-series for dashboards.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tracing::debug;
+
+use crate::adapters::memory_graph::{LineageQuery, MemoryGraphAdapter};
+use crate::schemas::events::{
+    AnalyticsEvent, CostPayload, EventPayload, Severity, TelemetryPayload,
+};
+
+/// Tags carrying the session of an event, in order of precedence
+pub const SESSION_TAGS: [&str; 2] = ["session_id", "conversation_id"];
+
+/// Session analytics configuration
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// Inactivity after which a session is considered ended
+    pub idle_timeout: Duration,
+    /// Latency above which a final turn counts as abandoned
+    pub slow_turn_ms: f64,
+    /// Turns itemized per session; later requests are only counted
+    pub max_turns: usize,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            idle_timeout: Duration::minutes(30),
+            slow_turn_ms: 30_000.0,
+            max_turns: 500,
+        }
+    }
+}
+
+/// One request within a session
+#[derive(Debug, Clone)]
+struct Turn {
+    request_id: String,
+    started_at: DateTime<Utc>,
+    prompt_tokens: u64,
+    completion_tokens: Option<u64>,
+    cost_usd: f64,
+    ttft_ms: Option<f64>,
+    latency_ms: Option<f64>,
+    failed: bool,
+}
+
+impl Turn {
+    fn new(request_id: String, started_at: DateTime<Utc>) -> Self {
+        Self {
+            request_id,
+            started_at,
+            prompt_tokens: 0,
+            completion_tokens: None,
+            cost_usd: 0.0,
+            ttft_ms: None,
+            latency_ms: None,
+            failed: false,
+        }
+    }
+
+    fn record(&mut self, event: &AnalyticsEvent) {
+        self.started_at = self.started_at.min(event.common.timestamp);
+        if matches!(event.common.severity, Severity::Error | Severity::Critical) {
+            self.failed = true;
+        }
+
+        match &event.payload {
+            EventPayload::Telemetry(TelemetryPayload::Latency(m)) => {
+                self.latency_ms = Some(m.total_latency_ms);
+                self.ttft_ms = m.ttft_ms.or(self.ttft_ms);
+            }
+            EventPayload::Telemetry(TelemetryPayload::TokenUsage(m)) => {
+                self.prompt_tokens = m.prompt_tokens as u64;
+                self.completion_tokens = Some(m.completion_tokens as u64);
+            }
+            EventPayload::Cost(CostPayload::TokenCost(c)) => {
+                self.cost_usd = c.total_cost_usd;
+                self.prompt_tokens = c.prompt_tokens as u64;
+                self.completion_tokens = Some(c.completion_tokens as u64);
+            }
+            _ => {}
+        }
+    }
+
+    fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens.unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SessionState {
+    first_seen: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    turns: Vec<Turn>,
+    /// Requests beyond `max_turns`
+    overflow_turns: u64,
+}
+
+impl SessionState {
+    fn new(at: DateTime<Utc>) -> Self {
+        Self {
+            first_seen: at,
+            last_seen: at,
+            turns: Vec::new(),
+            overflow_turns: 0,
+        }
+    }
+
+    fn add_turn(&mut self, turn: Turn, max_turns: usize) {
+        self.first_seen = self.first_seen.min(turn.started_at);
+        self.last_seen = self.last_seen.max(turn.started_at);
+        if self.turns.len() < max_turns {
+            // Keep turns ordered by start; late-linked requests may be older
+            let position = self
+                .turns
+                .partition_point(|t| t.started_at <= turn.started_at);
+            self.turns.insert(position, turn);
+        } else {
+            self.overflow_turns += 1;
+        }
+    }
+
+    fn summarize(&self, session_id: &str, config: &SessionConfig) -> SessionSummary {
+        let prompt_tokens: u64 = self.turns.iter().map(|t| t.prompt_tokens).sum();
+        let completion_tokens: u64 = self.turns.iter().filter_map(|t| t.completion_tokens).sum();
+        let turns = self.turns.len() as u64 + self.overflow_turns;
+
+        let abandoned = self.turns.last().is_some_and(|last| {
+            last.failed
+                || last.completion_tokens == Some(0)
+                || last.latency_ms.is_some_and(|l| l > config.slow_turn_ms)
+        });
+
+        SessionSummary {
+            session_id: session_id.to_string(),
+            started_at: self.first_seen,
+            ended_at: self.last_seen,
+            duration_secs: (self.last_seen - self.first_seen).num_milliseconds() as f64 / 1000.0,
+            turns,
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+            cost_usd: self.turns.iter().map(|t| t.cost_usd).sum(),
+            failed_turns: self.turns.iter().filter(|t| t.failed).count() as u64,
+            abandoned,
+            abandonment_turn: abandoned.then_some(self.turns.len() as u32),
+        }
+    }
+
+    fn turn_records(&self, session_id: &str) -> Vec<TurnRecord> {
+        self.turns
+            .iter()
+            .enumerate()
+            .map(|(i, t)| TurnRecord {
+                session_id: session_id.to_string(),
+                turn_index: i as u32 + 1,
+                request_id: t.request_id.clone(),
+                timestamp: t.started_at,
+                ttft_ms: t.ttft_ms,
+                latency_ms: t.latency_ms,
+                total_tokens: t.total_tokens(),
+                cost_usd: t.cost_usd,
+                failed: t.failed,
+            })
+            .collect()
+    }
+}
+
+/// Totals of a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub duration_secs: f64,
+    pub turns: u64,
+    /// Token and cost totals cover itemized turns only
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    pub cost_usd: f64,
+    pub failed_turns: u64,
+    /// The final turn failed, produced no completion, or was too slow
+    pub abandoned: bool,
+    /// Turn index (1-based) at which the session was abandoned
+    pub abandonment_turn: Option<u32>,
+}
+
+/// One turn of a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnRecord {
+    pub session_id: String,
+    /// 1-based position of the turn within the session
+    pub turn_index: u32,
+    pub request_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub ttft_ms: Option<f64>,
+    pub latency_ms: Option<f64>,
+    pub total_tokens: u64,
+    pub cost_usd: f64,
+    pub failed: bool,
+}
+
+/// An ended session and its turns
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClosedSession {
+    pub summary: SessionSummary,
+    pub turns: Vec<TurnRecord>,
+}
+
+/// Session totals per time bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionBucket {
+    pub bucket: DateTime<Utc>,
+    pub sessions: u64,
+    pub avg_turns: f64,
+    pub avg_duration_secs: f64,
+    pub avg_tokens: f64,
+    pub avg_cost_usd: f64,
+    pub total_cost_usd: f64,
+    pub abandonment_rate: f64,
+}
+
+/// Time-to-first-token distribution of one turn index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnLatencyDistribution {
+    pub turn_index: u32,
+    pub samples: u64,
+    pub ttft_p50_ms: f64,
+    pub ttft_p90_ms: f64,
+    pub ttft_p99_ms: f64,
+}
+
+/// Sessions that ended at a turn index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AbandonmentPoint {
+    pub turn_index: u32,
+    /// Sessions whose last turn was this one
+    pub sessions_ended: u64,
+    /// Of those, sessions that were abandoned
+    pub sessions_abandoned: u64,
+}
+
+/// Session reconstruction engine
+pub struct SessionEngine {
+    config: SessionConfig,
+    // Session ID -> State
+    sessions: Arc<DashMap<String, SessionState>>,
+    // Request ID -> (Session ID, Linked at), for events carrying no session tag
+    request_sessions: Arc<DashMap<String, (String, DateTime<Utc>)>>,
+    // Request ID -> Turn seen before its session was known
+    unlinked: Arc<DashMap<String, Turn>>,
+    // Request ID -> Session ID of turns already placed in a session
+    placed: Arc<DashMap<String, String>>,
+}
+
+impl SessionEngine {
+    /// Create a new session engine
+    pub fn new(config: SessionConfig) -> Self {
+        Self {
+            config,
+            sessions: Arc::new(DashMap::new()),
+            request_sessions: Arc::new(DashMap::new()),
+            unlinked: Arc::new(DashMap::new()),
+            placed: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Record an event into its session's turn
+    pub fn record_event(&self, event: &AnalyticsEvent) {
+        let Some(request_id) = request_id(event) else {
+            // Session activity without a request still keeps it alive
+            if let Some(session_id) = session_tag(event) {
+                let mut state = self
+                    .sessions
+                    .entry(session_id.to_string())
+                    .or_insert_with(|| SessionState::new(event.common.timestamp));
+                state.last_seen = state.last_seen.max(event.common.timestamp);
+            }
+            return;
+        };
+
+        let session_id = session_tag(event)
+            .map(str::to_string)
+            .or_else(|| self.placed.get(request_id).map(|s| s.value().clone()))
+            .or_else(|| self.request_sessions.get(request_id).map(|s| s.0.clone()));
+
+        match session_id {
+            Some(session_id) => self.record_turn(&session_id, request_id, event),
+            None => self
+                .unlinked
+                .entry(request_id.to_string())
+                .or_insert_with(|| Turn::new(request_id.to_string(), event.common.timestamp))
+                .record(event),
+        }
+    }
+
+    fn record_turn(&self, session_id: &str, request_id: &str, event: &AnalyticsEvent) {
+        let mut state = self
+            .sessions
+            .entry(session_id.to_string())
+            .or_insert_with(|| SessionState::new(event.common.timestamp));
+        state.last_seen = state.last_seen.max(event.common.timestamp);
+
+        if let Some(turn) = state.turns.iter_mut().find(|t| t.request_id == request_id) {
+            turn.record(event);
+            return;
+        }
+
+        if self.placed.contains_key(request_id) {
+            // Turn beyond max_turns, already counted
+            return;
+        }
+
+        let mut turn = self
+            .unlinked
+            .remove(request_id)
+            .map(|(_, t)| t)
+            .unwrap_or_else(|| Turn::new(request_id.to_string(), event.common.timestamp));
+        turn.record(event);
+        self.placed
+            .insert(request_id.to_string(), session_id.to_string());
+        state.add_turn(turn, self.config.max_turns);
+    }
+
+    /// Attribute a request to a session, moving any turn already seen for it
+    pub fn link_request(&self, request_id: &str, session_id: &str) {
+        self.request_sessions
+            .insert(request_id.to_string(), (session_id.to_string(), Utc::now()));
+
+        if let Some((_, turn)) = self.unlinked.remove(request_id) {
+            self.placed
+                .insert(request_id.to_string(), session_id.to_string());
+            self.sessions
+                .entry(session_id.to_string())
+                .or_insert_with(|| SessionState::new(turn.started_at))
+                .add_turn(turn, self.config.max_turns);
+        }
+    }
+
+    /// Import request-to-session links from LLM-Memory-Graph context lineage
+    /// recorded in the given period, returning the number of links
+    pub async fn sync_with_memory_graph(
+        &self,
+        memory_graph: &MemoryGraphAdapter,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<usize> {
+        let lineage = memory_graph
+            .fetch_context_lineage(LineageQuery {
+                start_time: Some(start),
+                end_time: Some(end),
+                ..Default::default()
+            })
+            .await?;
+
+        let mut linked = 0;
+        for node in &lineage.nodes {
+            let attribute = |key: &str| node.attributes.get(key).and_then(|v| v.as_str());
+            if let (Some(request_id), Some(session_id)) =
+                (attribute("request_id"), attribute("session_id"))
+            {
+                self.link_request(request_id, session_id);
+                linked += 1;
+            }
+        }
+
+        debug!(linked, "Imported session links from Memory-Graph");
+        Ok(linked)
+    }
+
+    /// Remove sessions idle for longer than the timeout and return their
+    /// summaries and turns
+    pub fn close_idle(&self, now: DateTime<Utc>) -> Vec<ClosedSession> {
+        let horizon = now - self.config.idle_timeout;
+        let idle: Vec<String> = self
+            .sessions
+            .iter()
+            .filter(|s| s.last_seen < horizon)
+            .map(|s| s.key().clone())
+            .collect();
+
+        let mut closed = Vec::with_capacity(idle.len());
+        for session_id in idle {
+            if let Some((_, state)) = self
+                .sessions
+                .remove_if(&session_id, |_, s| s.last_seen < horizon)
+            {
+                self.placed.retain(|_, s| *s != session_id);
+                if state.turns.is_empty() {
+                    continue;
+                }
+                closed.push(ClosedSession {
+                    summary: state.summarize(&session_id, &self.config),
+                    turns: state.turn_records(&session_id),
+                });
+            }
+        }
+
+        // Requests never attributed to a session, and links never used, expire
+        self.unlinked.retain(|_, t| t.started_at >= horizon);
+        self.request_sessions.retain(|_, (session_id, linked_at)| {
+            *linked_at >= horizon || self.sessions.contains_key(session_id.as_str())
+        });
+
+        closed
+    }
+
+    /// Summaries of sessions still in progress
+    pub fn active_sessions(&self) -> Vec<SessionSummary> {
+        let mut sessions: Vec<SessionSummary> = self
+            .sessions
+            .iter()
+            .filter(|s| !s.turns.is_empty())
+            .map(|s| s.summarize(s.key(), &self.config))
+            .collect();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.started_at));
+        sessions
+    }
+
+    /// Summary of one session in progress
+    pub fn session(&self, session_id: &str) -> Option<SessionSummary> {
+        self.sessions
+            .get(session_id)
+            .map(|s| s.summarize(session_id, &self.config))
+    }
+
+    /// Turns of one session in progress
+    pub fn turns(&self, session_id: &str) -> Vec<TurnRecord> {
+        self.sessions
+            .get(session_id)
+            .map(|s| s.turn_records(session_id))
+            .unwrap_or_default()
+    }
+}
+
+impl Default for SessionEngine {
+    fn default() -> Self {
+        Self::new(SessionConfig::default())
+    }
+}
+
+/// Time-to-first-token distribution by turn index
+pub fn ttft_by_turn(turns: &[TurnRecord]) -> Vec<TurnLatencyDistribution> {
+    let mut by_turn: BTreeMap<u32, Vec<f64>> = BTreeMap::new();
+    for turn in turns {
+        if let Some(ttft) = turn.ttft_ms {
+            by_turn.entry(turn.turn_index).or_default().push(ttft);
+        }
+    }
+
+    by_turn
+        .into_iter()
+        .map(|(turn_index, mut values)| {
+            values.sort_by(f64::total_cmp);
+            TurnLatencyDistribution {
+                turn_index,
+                samples: values.len() as u64,
+                ttft_p50_ms: percentile(&values, 0.50),
+                ttft_p90_ms: percentile(&values, 0.90),
+                ttft_p99_ms: percentile(&values, 0.99),
+            }
+        })
+        .collect()
+}
+
+/// Where sessions ended, by final turn index
+pub fn abandonment_points(sessions: &[SessionSummary]) -> Vec<AbandonmentPoint> {
+    let mut by_turn: BTreeMap<u32, (u64, u64)> = BTreeMap::new();
+    for session in sessions {
+        let entry = by_turn.entry(session.turns as u32).or_default();
+        entry.0 += 1;
+        entry.1 += session.abandoned as u64;
+    }
+
+    by_turn
+        .into_iter()
+        .map(|(turn_index, (ended, abandoned))| AbandonmentPoint {
+            turn_index,
+            sessions_ended: ended,
+            sessions_abandoned: abandoned,
+        })
+        .collect()
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[f64], q: f64) -> f64 {
+    let rank = ((sorted.len() as f64 * q).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1]
+}
+
+fn session_tag(event: &AnalyticsEvent) -> Option<&str> {
+    SESSION_TAGS
+        .iter()
+        .find_map(|tag| event.common.tags.get(*tag))
+        .map(String::as_str)
+}
+
+fn request_id(event: &AnalyticsEvent) -> Option<&str> {
+    match &event.payload {
+        EventPayload::Telemetry(TelemetryPayload::Latency(m)) => Some(&m.request_id),
+        EventPayload::Telemetry(TelemetryPayload::TokenUsage(m)) => Some(&m.request_id),
+        EventPayload::Cost(CostPayload::TokenCost(c)) => Some(&c.request_id),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::events::{fixtures, LatencyMetrics, TokenUsageMetrics};
+
+    fn event(
+        payload: TelemetryPayload,
+        at: DateTime<Utc>,
+        severity: Severity,
+        session_id: Option<&str>,
+    ) -> AnalyticsEvent {
+        let event = fixtures::telemetry(payload).at(at).with_severity(severity);
+        match session_id {
+            Some(session_id) => event.with_tags(&[("session_id", session_id)]),
+            None => event,
+        }
+    }
+
+    /// Record latency and token usage of one request
+    fn request(
+        engine: &SessionEngine,
+        session_id: Option<&str>,
+        request_id: &str,
+        at: DateTime<Utc>,
+        ttft_ms: f64,
+        completion_tokens: u32,
+        severity: Severity,
+    ) {
+        engine.record_event(&event(
+            TelemetryPayload::Latency(LatencyMetrics {
+                model_id: "gpt-4".to_string(),
+                request_id: request_id.to_string(),
+                total_latency_ms: ttft_ms * 4.0,
+                ttft_ms: Some(ttft_ms),
+                tokens_per_second: None,
+                breakdown: None,
+            }),
+            at,
+            severity,
+            session_id,
+        ));
+        engine.record_event(&event(
+            TelemetryPayload::TokenUsage(TokenUsageMetrics {
+                model_id: "gpt-4".to_string(),
+                request_id: request_id.to_string(),
+                prompt_tokens: 100,
+                completion_tokens,
+                total_tokens: 100 + completion_tokens,
+            }),
+            at,
+            Severity::Info,
+            session_id,
+        ));
+    }
+
+    #[test]
+    fn test_reconstructs_turns_from_session_tags() {
+        let engine = SessionEngine::default();
+        let start = Utc::now() - Duration::hours(2);
+        for (i, ttft) in [200.0, 300.0, 400.0].into_iter().enumerate() {
+            let at = start + Duration::minutes(i as i64 * 2);
+            request(
+                &engine,
+                Some("s1"),
+                &format!("r{}", i),
+                at,
+                ttft,
+                50,
+                Severity::Info,
+            );
+        }
+        request(
+            &engine,
+            Some("s2"),
+            "other",
+            start,
+            250.0,
+            50,
+            Severity::Info,
+        );
+
+        let summary = engine.session("s1").unwrap();
+        assert_eq!(summary.turns, 3);
+        assert_eq!(summary.total_tokens, 450);
+        assert_eq!(summary.duration_secs, 240.0);
+        assert!(!summary.abandoned);
+
+        let turns = engine.turns("s1");
+        assert_eq!(
+            turns.iter().map(|t| t.turn_index).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+
+        let mut all_turns = turns;
+        all_turns.extend(engine.turns("s2"));
+        let ttft = ttft_by_turn(&all_turns);
+        assert_eq!(ttft[0].samples, 2);
+        assert_eq!(ttft[0].ttft_p50_ms, 200.0);
+        assert_eq!(ttft[2].ttft_p99_ms, 400.0);
+    }
+
+    #[test]
+    fn test_idle_sessions_close_with_abandonment_point() {
+        let engine = SessionEngine::default();
+        let start = Utc::now() - Duration::hours(2);
+        request(&engine, Some("s1"), "r1", start, 200.0, 50, Severity::Info);
+        request(
+            &engine,
+            Some("s1"),
+            "r2",
+            start + Duration::minutes(1),
+            200.0,
+            0,
+            Severity::Error,
+        );
+        request(
+            &engine,
+            Some("s2"),
+            "r3",
+            Utc::now(),
+            200.0,
+            50,
+            Severity::Info,
+        );
+
+        let closed = engine.close_idle(Utc::now());
+        assert_eq!(closed.len(), 1);
+        let summary = &closed[0].summary;
+        assert_eq!(summary.session_id, "s1");
+        assert!(summary.abandoned);
+        assert_eq!(summary.abandonment_turn, Some(2));
+        assert_eq!(summary.failed_turns, 1);
+        assert_eq!(closed[0].turns.len(), 2);
+
+        let points = abandonment_points(std::slice::from_ref(summary));
+        assert_eq!(points[0].turn_index, 2);
+        assert_eq!(points[0].sessions_abandoned, 1);
+        assert_eq!(engine.active_sessions().len(), 1);
+    }
+
+    #[test]
+    fn test_linked_requests_join_their_session() {
+        let engine = SessionEngine::default();
+        let now = Utc::now();
+        request(&engine, None, "r1", now, 200.0, 50, Severity::Info);
+        assert!(engine.active_sessions().is_empty());
+
+        engine.link_request("r1", "s1");
+        engine.link_request("r2", "s1");
+        request(
+            &engine,
+            None,
+            "r2",
+            now + Duration::seconds(30),
+            300.0,
+            50,
+            Severity::Info,
+        );
+
+        let turns = engine.turns("s1");
+        assert_eq!(turns.len(), 2);
+        assert_eq!(turns[0].request_id, "r1");
+        assert_eq!(turns[1].ttft_ms, Some(300.0));
+    }
+}
@@ -0,0 +1,947 @@
+//! SLO and Error-Budget Tracking
+//!
+//! A service level objective is the fraction of good events among all events,
+//! each side selected from telemetry payloads. Good and total counts are kept
+//! in fixed-size buckets over the SLO window for rolling error-budget
+//! accounting. Alerts follow the multi-window, multi-burn-rate scheme from the
+//! Google SRE workbook: a policy fires only while both its long and its short
+//! window burn budget faster than the threshold, so alerts are raised quickly
+//! on a fast burn and clear soon after the burn stops.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::schemas::events::{
+    AnalyticsEvent, CommonEventFields, EventPayload, EventType, Severity, SloAlertSeverity,
+    SloStatusMetrics, SourceModule, TelemetryPayload, SCHEMA_VERSION,
+};
+use crate::schemas::metadata::SlaInfo;
+
+/// Telemetry payload kinds an SLO can select events from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TelemetryKind {
+    Latency,
+    Throughput,
+    ErrorRate,
+    TokenUsage,
+    ModelPerformance,
+}
+
+/// Comparison applied by a [`FieldCondition`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+/// Condition on a numeric payload field, e.g. `total_latency_ms <= 500`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FieldCondition {
+    pub field: String,
+    pub op: Comparison,
+    pub value: f64,
+}
+
+impl FieldCondition {
+    /// Whether the condition holds; a missing field never satisfies it
+    pub fn holds(&self, fields: &HashMap<String, f64>) -> bool {
+        let Some(&actual) = fields.get(&self.field) else {
+            return false;
+        };
+
+        match self.op {
+            Comparison::Lt => actual < self.value,
+            Comparison::Le => actual <= self.value,
+            Comparison::Gt => actual > self.value,
+            Comparison::Ge => actual >= self.value,
+            Comparison::Eq => actual == self.value,
+            Comparison::Ne => actual != self.value,
+        }
+    }
+}
+
+/// Selects the telemetry events counted on one side of an SLO ratio
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventSelector {
+    pub kind: TelemetryKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_id: Option<String>,
+    /// Tags the event must carry
+    #[serde(default)]
+    pub tags: HashMap<String, String>,
+    /// Conditions on numeric payload fields, all of which must hold
+    #[serde(default)]
+    pub conditions: Vec<FieldCondition>,
+    /// Numeric field holding the number of requests an event stands for
+    /// (e.g. `total_requests` on error-rate windows); each event counts once
+    /// when unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub count_field: Option<String>,
+}
+
+impl EventSelector {
+    /// Selector for every event of a kind
+    pub fn new(kind: TelemetryKind) -> Self {
+        Self {
+            kind,
+            model_id: None,
+            tags: HashMap::new(),
+            conditions: Vec::new(),
+            count_field: None,
+        }
+    }
+
+    /// Number of events `event` contributes (zero when not selected)
+    pub fn count(&self, event: &AnalyticsEvent) -> f64 {
+        let EventPayload::Telemetry(payload) = &event.payload else {
+            return 0.0;
+        };
+        let Some((kind, model_id, fields)) = telemetry_fields(payload) else {
+            return 0.0;
+        };
+
+        let selected = kind == self.kind
+            && self.model_id.as_deref().map_or(true, |m| m == model_id)
+            && self
+                .tags
+                .iter()
+                .all(|(k, v)| event.common.tags.get(k) == Some(v))
+            && self.conditions.iter().all(|c| c.holds(&fields));
+        if !selected {
+            return 0.0;
+        }
+
+        match &self.count_field {
+            Some(field) => fields.get(field).copied().unwrap_or(0.0).max(0.0),
+            None => 1.0,
+        }
+    }
+}
+
+/// Numeric fields of a telemetry payload, with derived fields such as
+/// `successful_requests` for error-rate windows
+fn telemetry_fields(
+    payload: &TelemetryPayload,
+) -> Option<(TelemetryKind, &str, HashMap<String, f64>)> {
+    let mut fields = HashMap::new();
+    let mut insert = |name: &str, value: f64| {
+        fields.insert(name.to_string(), value);
+    };
+
+    let (kind, model_id) = match payload {
+        TelemetryPayload::Latency(latency) => {
+            insert("total_latency_ms", latency.total_latency_ms);
+            if let Some(ttft) = latency.ttft_ms {
+                insert("ttft_ms", ttft);
+            }
+            if let Some(tps) = latency.tokens_per_second {
+                insert("tokens_per_second", tps);
+            }
+            (TelemetryKind::Latency, latency.model_id.as_str())
+        }
+        TelemetryPayload::Throughput(throughput) => {
+            insert("requests_per_second", throughput.requests_per_second);
+            insert("tokens_per_second", throughput.tokens_per_second);
+            insert("concurrent_requests", throughput.concurrent_requests as f64);
+            (TelemetryKind::Throughput, throughput.model_id.as_str())
+        }
+        TelemetryPayload::ErrorRate(errors) => {
+            insert("total_requests", errors.total_requests as f64);
+            insert("failed_requests", errors.failed_requests as f64);
+            insert(
+                "successful_requests",
+                errors.total_requests.saturating_sub(errors.failed_requests) as f64,
+            );
+            insert("error_rate_percent", errors.error_rate_percent);
+            (TelemetryKind::ErrorRate, errors.model_id.as_str())
+        }
+        TelemetryPayload::TokenUsage(usage) => {
+            insert("prompt_tokens", usage.prompt_tokens as f64);
+            insert("completion_tokens", usage.completion_tokens as f64);
+            insert("total_tokens", usage.total_tokens as f64);
+            (TelemetryKind::TokenUsage, usage.model_id.as_str())
+        }
+        TelemetryPayload::ModelPerformance(performance) => {
+            for (name, value) in [
+                ("accuracy", performance.accuracy),
+                ("quality_score", performance.quality_score),
+                ("user_satisfaction", performance.user_satisfaction),
+            ] {
+                if let Some(value) = value {
+                    insert(name, value);
+                }
+            }
+            for (name, value) in &performance.custom_metrics {
+                insert(name, *value);
+            }
+            (
+                TelemetryKind::ModelPerformance,
+                performance.model_id.as_str(),
+            )
+        }
+        // Our own status events are never counted
+        TelemetryPayload::SloStatus(_) => return None,
+    };
+
+    Some((kind, model_id, fields))
+}
+
+/// A multi-window burn-rate alerting policy
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BurnRatePolicy {
+    pub severity: SloAlertSeverity,
+    pub long_window_secs: u64,
+    pub short_window_secs: u64,
+    /// Burn rate both windows must reach (1.0 spends the budget exactly
+    /// over the SLO window)
+    pub burn_rate: f64,
+}
+
+impl BurnRatePolicy {
+    /// Recommended policies for a 30-day SLO: page on 2% of the budget spent
+    /// in 1h or 5% in 6h, ticket on 10% in 3 days
+    pub fn sre_defaults() -> Vec<Self> {
+        let policy = |severity, long_hours: u64, short_minutes: u64, burn_rate| Self {
+            severity,
+            long_window_secs: long_hours * 3600,
+            short_window_secs: short_minutes * 60,
+            burn_rate,
+        };
+
+        vec![
+            policy(SloAlertSeverity::Page, 1, 5, 14.4),
+            policy(SloAlertSeverity::Page, 6, 30, 6.0),
+            policy(SloAlertSeverity::Ticket, 72, 360, 1.0),
+        ]
+    }
+}
+
+/// Service level objective over a rolling window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SloDefinition {
+    pub slo_id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Required fraction of good events (e.g. 0.999)
+    pub target: f64,
+    pub window_secs: u64,
+    pub good: EventSelector,
+    pub total: EventSelector,
+    #[serde(default = "BurnRatePolicy::sre_defaults")]
+    pub alert_policies: Vec<BurnRatePolicy>,
+}
+
+/// Default SLO window when derived from an SLA
+const SLA_WINDOW_SECS: u64 = 30 * 24 * 3600;
+
+impl SloDefinition {
+    /// Availability and latency SLOs for a model endpoint from its SLA.
+    ///
+    /// Availability counts successful requests from error-rate windows; the
+    /// latency SLO requires the same fraction of requests to complete within
+    /// `max_latency_ms`.
+    pub fn from_sla(endpoint_id: &str, model_id: &str, sla: &SlaInfo) -> Vec<Self> {
+        let target = sla.uptime_percent / 100.0;
+        let selector = |kind| EventSelector {
+            model_id: Some(model_id.to_string()),
+            ..EventSelector::new(kind)
+        };
+
+        let availability = Self {
+            slo_id: format!("{}-availability", endpoint_id),
+            name: format!("{} availability", endpoint_id),
+            description: format!("{}% of requests succeed", sla.uptime_percent),
+            target,
+            window_secs: SLA_WINDOW_SECS,
+            good: EventSelector {
+                count_field: Some("successful_requests".to_string()),
+                ..selector(TelemetryKind::ErrorRate)
+            },
+            total: EventSelector {
+                count_field: Some("total_requests".to_string()),
+                ..selector(TelemetryKind::ErrorRate)
+            },
+            alert_policies: BurnRatePolicy::sre_defaults(),
+        };
+
+        let latency = Self {
+            slo_id: format!("{}-latency", endpoint_id),
+            name: format!("{} latency", endpoint_id),
+            description: format!(
+                "{}% of requests complete within {}ms",
+                sla.uptime_percent, sla.max_latency_ms
+            ),
+            target,
+            window_secs: SLA_WINDOW_SECS,
+            good: EventSelector {
+                conditions: vec![FieldCondition {
+                    field: "total_latency_ms".to_string(),
+                    op: Comparison::Le,
+                    value: sla.max_latency_ms as f64,
+                }],
+                ..selector(TelemetryKind::Latency)
+            },
+            total: selector(TelemetryKind::Latency),
+            alert_policies: BurnRatePolicy::sre_defaults(),
+        };
+
+        vec![availability, latency]
+    }
+
+    fn validate(&self) -> Result<()> {
+        if !(self.target > 0.0 && self.target < 1.0) {
+            anyhow::bail!(
+                "SLO {} target must be between 0 and 1 (got {})",
+                self.slo_id,
+                self.target
+            );
+        }
+        if self.window_secs == 0 {
+            anyhow::bail!("SLO {} has an empty window", self.slo_id);
+        }
+        for policy in &self.alert_policies {
+            if policy.short_window_secs == 0 || policy.short_window_secs > policy.long_window_secs {
+                anyhow::bail!(
+                    "SLO {} alert policy needs 0 < short window <= long window",
+                    self.slo_id
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// SLO engine configuration
+#[derive(Debug, Clone)]
+pub struct SloConfig {
+    /// Size of the good/total count buckets
+    pub bucket: Duration,
+}
+
+impl Default for SloConfig {
+    fn default() -> Self {
+        Self {
+            bucket: Duration::minutes(1),
+        }
+    }
+}
+
+/// Burn rate over one window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WindowBurnRate {
+    pub window_secs: u64,
+    pub burn_rate: f64,
+}
+
+/// A burn-rate policy that is currently firing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BurnRateAlert {
+    pub severity: SloAlertSeverity,
+    pub long_window_secs: u64,
+    pub short_window_secs: u64,
+    pub threshold: f64,
+    pub long_burn_rate: f64,
+    pub short_burn_rate: f64,
+}
+
+/// Error-budget status of an SLO
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SloStatus {
+    pub slo_id: String,
+    pub name: String,
+    pub target: f64,
+    pub window_secs: u64,
+    pub computed_at: DateTime<Utc>,
+    pub good_events: f64,
+    pub total_events: f64,
+    /// Fraction of good events over the window (None without traffic)
+    pub attainment: Option<f64>,
+    /// Bad events the window can absorb at its current volume
+    pub error_budget_events: f64,
+    /// Bad events so far
+    pub bad_events: f64,
+    /// Fraction of the error budget left; negative once exhausted
+    pub error_budget_remaining: f64,
+    /// Burn rate over every window used by the alert policies
+    pub burn_rates: Vec<WindowBurnRate>,
+    pub alerts: Vec<BurnRateAlert>,
+}
+
+impl SloStatus {
+    /// Severity of the most urgent firing alert
+    pub fn alert_severity(&self) -> Option<SloAlertSeverity> {
+        self.alerts.iter().map(|alert| alert.severity).max()
+    }
+
+    /// Self-monitoring event carrying this status
+    pub fn to_event(&self) -> AnalyticsEvent {
+        let severity = match self.alert_severity() {
+            Some(SloAlertSeverity::Page) => Severity::Error,
+            Some(SloAlertSeverity::Ticket) => Severity::Warning,
+            None => Severity::Info,
+        };
+        hub_event(self, EventType::Telemetry, severity, HashMap::new())
+    }
+
+    fn metrics(&self) -> SloStatusMetrics {
+        SloStatusMetrics {
+            slo_id: self.slo_id.clone(),
+            slo_name: self.name.clone(),
+            target: self.target,
+            window_seconds: self.window_secs,
+            good_events: self.good_events,
+            total_events: self.total_events,
+            attainment: self.attainment,
+            error_budget_remaining: self.error_budget_remaining,
+            burn_rates: self
+                .burn_rates
+                .iter()
+                .map(|rate| (window_label(rate.window_secs), rate.burn_rate))
+                .collect(),
+            alert: self.alert_severity(),
+        }
+    }
+}
+
+/// Good and total counts in one bucket
+#[derive(Debug, Clone, Copy, Default)]
+struct Counts {
+    good: f64,
+    total: f64,
+}
+
+impl Counts {
+    fn bad(&self) -> f64 {
+        (self.total - self.good).max(0.0)
+    }
+}
+
+/// Per-SLO tracking state
+struct SloState {
+    definition: SloDefinition,
+    /// Bucket start -> counts in that bucket
+    buckets: BTreeMap<DateTime<Utc>, Counts>,
+    /// Indexes of the alert policies currently firing
+    firing: HashSet<usize>,
+}
+
+/// SLO and error-budget engine
+pub struct SloEngine {
+    config: SloConfig,
+    // SLO ID -> Tracking state
+    slos: Arc<DashMap<String, SloState>>,
+}
+
+impl SloEngine {
+    /// Create a new SLO engine
+    pub fn new(config: SloConfig) -> Self {
+        Self {
+            config,
+            slos: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Register or replace an SLO. Counts already tracked for the same SLO ID
+    /// are kept.
+    pub fn upsert_slo(&self, definition: SloDefinition) -> Result<()> {
+        definition.validate()?;
+        info!(slo_id = %definition.slo_id, target = definition.target, "Tracking SLO");
+
+        if let Some(mut state) = self.slos.get_mut(&definition.slo_id) {
+            state.definition = definition;
+            state.firing.clear();
+            return Ok(());
+        }
+
+        self.slos.insert(
+            definition.slo_id.clone(),
+            SloState {
+                definition,
+                buckets: BTreeMap::new(),
+                firing: HashSet::new(),
+            },
+        );
+        Ok(())
+    }
+
+    /// Stop tracking an SLO
+    pub fn remove_slo(&self, slo_id: &str) {
+        self.slos.remove(slo_id);
+    }
+
+    /// Registered SLO definitions
+    pub fn definitions(&self) -> Vec<SloDefinition> {
+        self.slos.iter().map(|s| s.definition.clone()).collect()
+    }
+
+    /// Count a telemetry event towards every SLO selecting it
+    pub fn record_event(&self, event: &AnalyticsEvent) {
+        if !matches!(event.payload, EventPayload::Telemetry(_)) {
+            return;
+        }
+
+        let bucket = self.bucket_start(event.common.timestamp);
+        for mut state in self.slos.iter_mut() {
+            let good = state.definition.good.count(event);
+            let total = state.definition.total.count(event);
+            if good == 0.0 && total == 0.0 {
+                continue;
+            }
+
+            let counts = state.buckets.entry(bucket).or_default();
+            counts.good += good;
+            counts.total += total;
+        }
+    }
+
+    /// Error-budget status of an SLO at `now`
+    pub fn status(&self, slo_id: &str, now: DateTime<Utc>) -> Result<SloStatus> {
+        let state = self
+            .slos
+            .get(slo_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown SLO: {}", slo_id))?;
+
+        Ok(self.status_of(&state, now))
+    }
+
+    /// Status of every SLO at `now`
+    pub fn status_all(&self, now: DateTime<Utc>) -> Vec<SloStatus> {
+        self.slos
+            .iter()
+            .map(|state| self.status_of(&state, now))
+            .collect()
+    }
+
+    /// Evaluate every SLO at `now`, returning a status event per SLO plus an
+    /// alert event for each burn-rate policy that started firing
+    pub fn evaluate_all(&self, now: DateTime<Utc>) -> Vec<AnalyticsEvent> {
+        let mut events = Vec::new();
+
+        for mut state in self.slos.iter_mut() {
+            let window = Duration::seconds(state.definition.window_secs as i64);
+            let cutoff = self.bucket_start(now - window);
+            state.buckets = state.buckets.split_off(&cutoff);
+
+            let status = self.status_of(&state, now);
+            let mut firing = HashSet::new();
+            for (i, policy) in state.definition.alert_policies.iter().enumerate() {
+                let Some(alert) = status.alerts.iter().find(|a| a.matches(policy)) else {
+                    continue;
+                };
+                firing.insert(i);
+                if state.firing.contains(&i) {
+                    continue;
+                }
+
+                warn!(
+                    slo_id = %status.slo_id,
+                    severity = ?alert.severity,
+                    long_burn_rate = alert.long_burn_rate,
+                    short_burn_rate = alert.short_burn_rate,
+                    "SLO burn-rate alert"
+                );
+                events.push(alert_event(&status, alert));
+            }
+
+            state.firing = firing;
+            events.push(status.to_event());
+        }
+
+        events
+    }
+
+    fn bucket_start(&self, timestamp: DateTime<Utc>) -> DateTime<Utc> {
+        let bucket_ms = self.config.bucket.num_milliseconds().max(1);
+        let ms = timestamp.timestamp_millis();
+        DateTime::from_timestamp_millis(ms - ms.rem_euclid(bucket_ms)).unwrap_or(timestamp)
+    }
+
+    /// Counts in the buckets overlapping `(now - window, now]`
+    fn counts_over(&self, state: &SloState, now: DateTime<Utc>, window_secs: u64) -> Counts {
+        let start = self.bucket_start(now - Duration::seconds(window_secs as i64));
+        state
+            .buckets
+            .range(start..=now)
+            .fold(Counts::default(), |acc, (_, counts)| Counts {
+                good: acc.good + counts.good,
+                total: acc.total + counts.total,
+            })
+    }
+
+    fn status_of(&self, state: &SloState, now: DateTime<Utc>) -> SloStatus {
+        let definition = &state.definition;
+        let error_budget = 1.0 - definition.target;
+        let burn_rate = |window_secs: u64| {
+            let counts = self.counts_over(state, now, window_secs);
+            if counts.total > 0.0 {
+                counts.bad() / counts.total / error_budget
+            } else {
+                0.0
+            }
+        };
+
+        let mut windows: Vec<u64> = definition
+            .alert_policies
+            .iter()
+            .flat_map(|p| [p.short_window_secs, p.long_window_secs])
+            .collect();
+        windows.sort_unstable();
+        windows.dedup();
+        let burn_rates: Vec<WindowBurnRate> = windows
+            .into_iter()
+            .map(|window_secs| WindowBurnRate {
+                window_secs,
+                burn_rate: burn_rate(window_secs),
+            })
+            .collect();
+        let rate_for = |window_secs: u64| {
+            burn_rates
+                .iter()
+                .find(|r| r.window_secs == window_secs)
+                .map_or(0.0, |r| r.burn_rate)
+        };
+
+        let alerts = definition
+            .alert_policies
+            .iter()
+            .filter_map(|policy| {
+                let long_burn_rate = rate_for(policy.long_window_secs);
+                let short_burn_rate = rate_for(policy.short_window_secs);
+                let burning =
+                    long_burn_rate >= policy.burn_rate && short_burn_rate >= policy.burn_rate;
+                burning.then_some(BurnRateAlert {
+                    severity: policy.severity,
+                    long_window_secs: policy.long_window_secs,
+                    short_window_secs: policy.short_window_secs,
+                    threshold: policy.burn_rate,
+                    long_burn_rate,
+                    short_burn_rate,
+                })
+            })
+            .collect();
+
+        let counts = self.counts_over(state, now, definition.window_secs);
+        let error_budget_events = error_budget * counts.total;
+        let bad_events = counts.bad();
+
+        SloStatus {
+            slo_id: definition.slo_id.clone(),
+            name: definition.name.clone(),
+            target: definition.target,
+            window_secs: definition.window_secs,
+            computed_at: now,
+            good_events: counts.good,
+            total_events: counts.total,
+            attainment: (counts.total > 0.0).then(|| 1.0 - bad_events / counts.total),
+            error_budget_events,
+            bad_events,
+            error_budget_remaining: if counts.total > 0.0 {
+                1.0 - bad_events / error_budget_events
+            } else {
+                1.0
+            },
+            burn_rates,
+            alerts,
+        }
+    }
+}
+
+impl Default for SloEngine {
+    fn default() -> Self {
+        Self::new(SloConfig::default())
+    }
+}
+
+impl BurnRateAlert {
+    fn matches(&self, policy: &BurnRatePolicy) -> bool {
+        self.severity == policy.severity
+            && self.long_window_secs == policy.long_window_secs
+            && self.short_window_secs == policy.short_window_secs
+            && self.threshold == policy.burn_rate
+    }
+}
+
+/// Compact window label, e.g. 300 -> "5m", 259200 -> "3d"
+fn window_label(secs: u64) -> String {
+    match secs {
+        s if s > 0 && s % 86_400 == 0 => format!("{}d", s / 86_400),
+        s if s > 0 && s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s > 0 && s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+/// Build the alert event for a burn-rate policy that started firing
+fn alert_event(status: &SloStatus, alert: &BurnRateAlert) -> AnalyticsEvent {
+    let mut tags = HashMap::new();
+    tags.insert(
+        "long_window".to_string(),
+        window_label(alert.long_window_secs),
+    );
+    tags.insert(
+        "short_window".to_string(),
+        window_label(alert.short_window_secs),
+    );
+    tags.insert(
+        "burn_rate_threshold".to_string(),
+        format!("{}", alert.threshold),
+    );
+    tags.insert(
+        "long_burn_rate".to_string(),
+        format!("{:.2}", alert.long_burn_rate),
+    );
+    tags.insert(
+        "short_burn_rate".to_string(),
+        format!("{:.2}", alert.short_burn_rate),
+    );
+
+    let severity = match alert.severity {
+        SloAlertSeverity::Page => Severity::Critical,
+        SloAlertSeverity::Ticket => Severity::Warning,
+    };
+    hub_event(status, EventType::Alert, severity, tags)
+}
+
+fn hub_event(
+    status: &SloStatus,
+    event_type: EventType,
+    severity: Severity,
+    mut tags: HashMap<String, String>,
+) -> AnalyticsEvent {
+    tags.insert("slo_id".to_string(), status.slo_id.clone());
+
+    AnalyticsEvent {
+        common: CommonEventFields {
+            event_id: Uuid::new_v4(),
+            timestamp: status.computed_at,
+            source_module: SourceModule::LlmAnalyticsHub,
+            event_type,
+            correlation_id: None,
+            parent_event_id: None,
+            schema_version: SCHEMA_VERSION.to_string(),
+            severity,
+            environment: "production".to_string(),
+            tags,
+        },
+        payload: EventPayload::Telemetry(TelemetryPayload::SloStatus(status.metrics())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::events::{fixtures, ErrorRateMetrics, LatencyMetrics};
+
+    fn event(timestamp: DateTime<Utc>, payload: TelemetryPayload) -> AnalyticsEvent {
+        fixtures::telemetry(payload).at(timestamp)
+    }
+
+    fn latency(timestamp: DateTime<Utc>, model_id: &str, latency_ms: f64) -> AnalyticsEvent {
+        event(
+            timestamp,
+            TelemetryPayload::Latency(LatencyMetrics {
+                model_id: model_id.to_string(),
+                request_id: Uuid::new_v4().to_string(),
+                total_latency_ms: latency_ms,
+                ttft_ms: None,
+                tokens_per_second: None,
+                breakdown: None,
+            }),
+        )
+    }
+
+    fn error_window(timestamp: DateTime<Utc>, total: u64, failed: u64) -> AnalyticsEvent {
+        event(
+            timestamp,
+            TelemetryPayload::ErrorRate(ErrorRateMetrics {
+                model_id: "gpt-4".to_string(),
+                total_requests: total,
+                failed_requests: failed,
+                error_rate_percent: 100.0 * failed as f64 / total as f64,
+                error_breakdown: HashMap::new(),
+                window_duration_seconds: 60,
+            }),
+        )
+    }
+
+    fn sla() -> SlaInfo {
+        SlaInfo {
+            uptime_percent: 99.9,
+            max_latency_ms: 500,
+            support_level: "gold".to_string(),
+        }
+    }
+
+    fn start() -> DateTime<Utc> {
+        DateTime::<Utc>::UNIX_EPOCH + Duration::days(100)
+    }
+
+    #[test]
+    fn test_latency_slo_budget_accounting() {
+        let engine = SloEngine::default();
+        for definition in SloDefinition::from_sla("chat", "gpt-4", &sla()) {
+            engine.upsert_slo(definition).unwrap();
+        }
+
+        let start = start();
+        for i in 0..2000 {
+            let latency_ms = if i % 1000 == 0 { 900.0 } else { 120.0 };
+            engine.record_event(&latency(start + Duration::seconds(i), "gpt-4", latency_ms));
+        }
+        // Other models are not selected
+        engine.record_event(&latency(start, "claude", 5000.0));
+
+        let status = engine
+            .status("chat-latency", start + Duration::seconds(2000))
+            .unwrap();
+        assert_eq!(status.total_events, 2000.0);
+        assert_eq!(status.good_events, 1998.0);
+        assert_eq!(status.bad_events, 2.0);
+        assert!((status.attainment.unwrap() - 0.999).abs() < 1e-12);
+        // Budget of 0.1% of 2000 events is exactly spent
+        assert!((status.error_budget_events - 2.0).abs() < 1e-9);
+        assert!(status.error_budget_remaining.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_availability_counts_requests_in_error_windows() {
+        let engine = SloEngine::default();
+        for definition in SloDefinition::from_sla("chat", "gpt-4", &sla()) {
+            engine.upsert_slo(definition).unwrap();
+        }
+
+        let start = start();
+        engine.record_event(&error_window(start, 10_000, 2));
+        engine.record_event(&error_window(start + Duration::minutes(1), 10_000, 3));
+
+        let status = engine
+            .status("chat-availability", start + Duration::minutes(2))
+            .unwrap();
+        assert_eq!(status.total_events, 20_000.0);
+        assert_eq!(status.bad_events, 5.0);
+        assert!((status.error_budget_remaining - 0.75).abs() < 1e-9);
+        assert!(status.alerts.is_empty());
+    }
+
+    #[test]
+    fn test_fast_burn_pages_once_and_clears() {
+        let engine = SloEngine::default();
+        for definition in SloDefinition::from_sla("chat", "gpt-4", &sla()) {
+            engine.upsert_slo(definition).unwrap();
+        }
+
+        // A day of healthy traffic followed by an hour of 2% errors
+        let start = start();
+        for minute in 0..24 * 60 {
+            engine.record_event(&error_window(start + Duration::minutes(minute), 1000, 0));
+        }
+        let outage = start + Duration::hours(24);
+        for minute in 0..60 {
+            engine.record_event(&error_window(outage + Duration::minutes(minute), 1000, 20));
+        }
+
+        let now = outage + Duration::minutes(60);
+        let status = engine.status("chat-availability", now).unwrap();
+        let burn = |secs| {
+            status
+                .burn_rates
+                .iter()
+                .find(|r| r.window_secs == secs)
+                .unwrap()
+                .burn_rate
+        };
+        assert!((burn(300) - 20.0).abs() < 1e-9);
+        assert!((burn(3600) - 20.0).abs() < 1e-9);
+        assert_eq!(status.alert_severity(), Some(SloAlertSeverity::Page));
+        // The 6h window has only burnt at 20/6 so far
+        assert_eq!(status.alerts.len(), 1);
+        assert_eq!(status.alerts[0].long_window_secs, 3600);
+
+        let events = engine.evaluate_all(now);
+        let alerts: Vec<_> = events
+            .iter()
+            .filter(|e| e.common.event_type == EventType::Alert)
+            .collect();
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].common.severity, Severity::Critical);
+        assert_eq!(alerts[0].common.tags["slo_id"], "chat-availability");
+        assert_eq!(alerts[0].common.tags["long_window"], "1h");
+        // One status event per SLO
+        assert_eq!(events.len(), 3);
+
+        // Still firing a minute later: no new alert
+        let later = engine.evaluate_all(now + Duration::minutes(1));
+        assert!(later
+            .iter()
+            .all(|e| e.common.event_type != EventType::Alert));
+
+        // Recovery: the short window clears quickly
+        let recovered = now + Duration::minutes(10);
+        for minute in 0..10 {
+            engine.record_event(&error_window(now + Duration::minutes(minute), 1000, 0));
+        }
+        let status = engine.status("chat-availability", recovered).unwrap();
+        assert!(status.alerts.is_empty());
+    }
+
+    #[test]
+    fn test_status_event_payload() {
+        let engine = SloEngine::default();
+        for definition in SloDefinition::from_sla("chat", "gpt-4", &sla()) {
+            engine.upsert_slo(definition).unwrap();
+        }
+        engine.record_event(&error_window(start(), 1000, 0));
+
+        let event = engine
+            .status("chat-availability", start() + Duration::minutes(1))
+            .unwrap()
+            .to_event();
+        assert_eq!(event.common.source_module, SourceModule::LlmAnalyticsHub);
+
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["payload"]["data"]["telemetry_type"], "slo_status");
+        assert_eq!(json["payload"]["data"]["burn_rates"]["5m"], 0.0);
+        assert_eq!(json["payload"]["data"]["attainment"], 1.0);
+
+        // Status events are never counted towards SLOs
+        engine.record_event(&event);
+        let status = engine
+            .status("chat-availability", start() + Duration::minutes(1))
+            .unwrap();
+        assert_eq!(status.total_events, 1000.0);
+    }
+
+    #[test]
+    fn test_rejects_invalid_definitions() {
+        let engine = SloEngine::default();
+        let mut definition = SloDefinition::from_sla("chat", "gpt-4", &sla()).remove(0);
+        definition.target = 1.0;
+        assert!(engine.upsert_slo(definition.clone()).is_err());
+
+        definition.target = 0.99;
+        definition.alert_policies[0].short_window_secs = 7200;
+        assert!(engine.upsert_slo(definition).is_err());
+        assert!(engine.definitions().is_empty());
+    }
+
+    #[test]
+    fn test_window_labels() {
+        assert_eq!(window_label(300), "5m");
+        assert_eq!(window_label(3600), "1h");
+        assert_eq!(window_label(259_200), "3d");
+        assert_eq!(window_label(90), "90s");
+    }
+}
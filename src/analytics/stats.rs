@@ -0,0 +1,216 @@
+//! Statistical Helpers
+//!
+//! Small numerical routines shared by the analytics models: descriptive
+//! statistics, normal quantiles and a derivative-free optimizer used for
+//! fitting model parameters.
+
+use chrono::{DateTime, Duration, Utc};
+use statrs::distribution::{ContinuousCDF, Normal};
+
+/// Arithmetic mean (0.0 for an empty slice)
+pub fn mean(values: &[f64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Unbiased sample variance (0.0 when fewer than two values)
+pub fn variance(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let m = mean(values);
+    values.iter().map(|v| (v - m).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+}
+
+/// Sample standard deviation
+pub fn std_dev(values: &[f64]) -> f64 {
+    variance(values).sqrt()
+}
+
+/// Two-sided standard normal quantile for a confidence level, e.g. 0.95 -> 1.96
+pub fn normal_z(confidence_level: f64) -> f64 {
+    let level = confidence_level.clamp(0.5, 0.9999);
+    let normal = Normal::new(0.0, 1.0).expect("standard normal is valid");
+    normal.inverse_cdf(0.5 + level / 2.0)
+}
+
+/// Median spacing between consecutive timestamps.
+///
+/// Falls back to one minute when fewer than two timestamps are available or
+/// the timestamps are not strictly increasing.
+pub fn sampling_interval<'a, I>(timestamps: I) -> Duration
+where
+    I: IntoIterator<Item = &'a DateTime<Utc>>,
+{
+    let timestamps: Vec<&DateTime<Utc>> = timestamps.into_iter().collect();
+    let mut deltas: Vec<i64> = timestamps
+        .windows(2)
+        .map(|w| (*w[1] - *w[0]).num_milliseconds())
+        .filter(|d| *d > 0)
+        .collect();
+
+    if deltas.is_empty() {
+        return Duration::minutes(1);
+    }
+
+    deltas.sort_unstable();
+    Duration::milliseconds(deltas[deltas.len() / 2])
+}
+
+/// Minimize `f` with the Nelder-Mead simplex method.
+///
+/// Returns the best parameter vector found and its objective value. Non-finite
+/// objective values are treated as +inf so callers can reject infeasible
+/// regions simply by returning `f64::INFINITY`.
+pub fn nelder_mead<F>(
+    f: F,
+    start: &[f64],
+    step: f64,
+    max_iter: usize,
+    tolerance: f64,
+) -> (Vec<f64>, f64)
+where
+    F: Fn(&[f64]) -> f64,
+{
+    let eval = |x: &[f64]| {
+        let v = f(x);
+        if v.is_finite() {
+            v
+        } else {
+            f64::INFINITY
+        }
+    };
+
+    let n = start.len();
+    if n == 0 {
+        return (Vec::new(), eval(start));
+    }
+
+    // Initial simplex: start point plus one step along each axis
+    let mut simplex: Vec<(Vec<f64>, f64)> = Vec::with_capacity(n + 1);
+    simplex.push((start.to_vec(), eval(start)));
+    for i in 0..n {
+        let mut point = start.to_vec();
+        point[i] += step;
+        let value = eval(&point);
+        simplex.push((point, value));
+    }
+
+    for _ in 0..max_iter {
+        simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let best = simplex[0].1;
+        let worst = simplex[n].1;
+        if best.is_finite() && (worst - best).abs() <= tolerance * (1.0 + best.abs()) {
+            break;
+        }
+
+        // Centroid of all points except the worst
+        let mut centroid = vec![0.0; n];
+        for (point, _) in simplex.iter().take(n) {
+            for (c, p) in centroid.iter_mut().zip(point) {
+                *c += p / n as f64;
+            }
+        }
+
+        let towards = |coef: f64| -> Vec<f64> {
+            centroid
+                .iter()
+                .zip(&simplex[n].0)
+                .map(|(c, w)| c + coef * (w - c))
+                .collect()
+        };
+
+        let reflected = towards(-1.0);
+        let reflected_value = eval(&reflected);
+
+        if reflected_value < simplex[0].1 {
+            let expanded = towards(-2.0);
+            let expanded_value = eval(&expanded);
+            simplex[n] = if expanded_value < reflected_value {
+                (expanded, expanded_value)
+            } else {
+                (reflected, reflected_value)
+            };
+        } else if reflected_value < simplex[n - 1].1 {
+            simplex[n] = (reflected, reflected_value);
+        } else {
+            let contracted = if reflected_value < simplex[n].1 {
+                towards(-0.5)
+            } else {
+                towards(0.5)
+            };
+            let contracted_value = eval(&contracted);
+
+            if contracted_value < simplex[n].1.min(reflected_value) {
+                simplex[n] = (contracted, contracted_value);
+            } else {
+                // Shrink towards the best point
+                let best_point = simplex[0].0.clone();
+                for entry in simplex.iter_mut().skip(1) {
+                    let point: Vec<f64> = best_point
+                        .iter()
+                        .zip(&entry.0)
+                        .map(|(b, p)| b + 0.5 * (p - b))
+                        .collect();
+                    let value = eval(&point);
+                    *entry = (point, value);
+                }
+            }
+        }
+    }
+
+    simplex.sort_by(|a, b| a.1.total_cmp(&b.1));
+    simplex.swap_remove(0)
+}
+
+/// Logistic function mapping the real line onto (0, 1)
+pub fn logistic(x: f64) -> f64 {
+    1.0 / (1.0 + (-x).exp())
+}
+
+/// Inverse of [`logistic`]
+pub fn logit(p: f64) -> f64 {
+    let p = p.clamp(1e-6, 1.0 - 1e-6);
+    (p / (1.0 - p)).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normal_z() {
+        assert!((normal_z(0.95) - 1.959964).abs() < 1e-4);
+        assert!((normal_z(0.80) - 1.281552).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_nelder_mead_quadratic() {
+        let (x, fx) = nelder_mead(
+            |p| (p[0] - 3.0).powi(2) + (p[1] + 1.0).powi(2),
+            &[0.0, 0.0],
+            0.5,
+            500,
+            1e-12,
+        );
+        assert!((x[0] - 3.0).abs() < 1e-3);
+        assert!((x[1] + 1.0).abs() < 1e-3);
+        assert!(fx < 1e-6);
+    }
+
+    #[test]
+    fn test_sampling_interval_uses_median() {
+        let start = Utc::now();
+        let timestamps = vec![
+            start,
+            start + Duration::seconds(30),
+            start + Duration::seconds(60),
+            start + Duration::seconds(90),
+            start + Duration::seconds(400), // gap
+        ];
+        assert_eq!(sampling_interval(&timestamps), Duration::seconds(30));
+    }
+}
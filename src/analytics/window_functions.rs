@@ -0,0 +1,282 @@
+//! Window Functions
+//!
+//! Rate-of-change and moving-window functions over time-ordered samples.
+//! Streaming aggregation and historical queries both evaluate through these,
+//! so a rate derived from a counter is computed the same way everywhere.
+//!
+//! Counter semantics follow Prometheus: a drop in a counter's value is a
+//! reset, after which the counter is assumed to have restarted from zero.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::models::metrics::WindowFunction;
+
+/// A timestamped value
+pub type Sample = (DateTime<Utc>, f64);
+
+fn seconds_between(a: DateTime<Utc>, b: DateTime<Utc>) -> f64 {
+    (b - a).num_milliseconds() as f64 / 1000.0
+}
+
+/// Counter values with resets undone, so the series is non-decreasing
+pub fn reset_adjusted(samples: &[Sample]) -> Vec<Sample> {
+    let mut offset = 0.0;
+    let mut previous: Option<f64> = None;
+    samples
+        .iter()
+        .map(|&(t, v)| {
+            if let Some(p) = previous {
+                if v < p {
+                    offset += p;
+                }
+            }
+            previous = Some(v);
+            (t, v + offset)
+        })
+        .collect()
+}
+
+/// Increase over the samples; counters are adjusted for resets
+pub fn increase(samples: &[Sample], counter: bool) -> Option<f64> {
+    if samples.len() < 2 {
+        return None;
+    }
+    if counter {
+        let adjusted = reset_adjusted(samples);
+        Some(adjusted[adjusted.len() - 1].1 - adjusted[0].1)
+    } else {
+        delta(samples)
+    }
+}
+
+/// Per-second increase between the first and last sample
+pub fn rate(samples: &[Sample], counter: bool) -> Option<f64> {
+    let elapsed = seconds_between(samples.first()?.0, samples.last()?.0);
+    if elapsed <= 0.0 {
+        return None;
+    }
+    Some(increase(samples, counter)? / elapsed)
+}
+
+/// Per-second increase between the last two samples
+pub fn irate(samples: &[Sample], counter: bool) -> Option<f64> {
+    let [.., (t0, v0), (t1, v1)] = samples else {
+        return None;
+    };
+    let elapsed = seconds_between(*t0, *t1);
+    if elapsed <= 0.0 {
+        return None;
+    }
+    let increase = if counter && v1 < v0 { *v1 } else { v1 - v0 };
+    Some(increase / elapsed)
+}
+
+/// Difference between the last and first sample
+pub fn delta(samples: &[Sample]) -> Option<f64> {
+    if samples.len() < 2 {
+        return None;
+    }
+    Some(samples[samples.len() - 1].1 - samples[0].1)
+}
+
+/// Per-second slope of a least-squares line through the samples
+pub fn derivative(samples: &[Sample]) -> Option<f64> {
+    if samples.len() < 2 {
+        return None;
+    }
+    let origin = samples[0].0;
+    let n = samples.len() as f64;
+    let xs: Vec<f64> = samples
+        .iter()
+        .map(|(t, _)| seconds_between(origin, *t))
+        .collect();
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = samples.iter().map(|(_, v)| v).sum::<f64>() / n;
+
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (x, (_, y)) in xs.iter().zip(samples) {
+        covariance += (x - mean_x) * (y - mean_y);
+        variance += (x - mean_x).powi(2);
+    }
+    (variance > 0.0).then(|| covariance / variance)
+}
+
+/// Exponentially weighted moving average with smoothing factor `alpha`
+pub fn ewma(samples: &[Sample], alpha: f64) -> Vec<Sample> {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let mut average: Option<f64> = None;
+    samples
+        .iter()
+        .map(|&(t, v)| {
+            let next = average.map_or(v, |a| alpha * v + (1.0 - alpha) * a);
+            average = Some(next);
+            (t, next)
+        })
+        .collect()
+}
+
+/// Nearest-rank percentile (0-100) of the samples in the trailing window
+/// ending at each sample
+pub fn moving_percentile(samples: &[Sample], window: Duration, percentile: f64) -> Vec<Sample> {
+    let q = (percentile / 100.0).clamp(0.0, 1.0);
+    let mut start = 0;
+    samples
+        .iter()
+        .enumerate()
+        .map(|(i, &(t, _))| {
+            while start < i && samples[start].0 <= t - window {
+                start += 1;
+            }
+            let mut values: Vec<f64> = samples[start..=i].iter().map(|(_, v)| *v).collect();
+            values.sort_by(f64::total_cmp);
+            let rank = ((values.len() as f64 * q).ceil() as usize).clamp(1, values.len());
+            (t, values[rank - 1])
+        })
+        .collect()
+}
+
+/// Running total of the sample values
+pub fn cumulative_sum(samples: &[Sample]) -> Vec<Sample> {
+    let mut total = 0.0;
+    samples
+        .iter()
+        .map(|&(t, v)| {
+            total += v;
+            (t, total)
+        })
+        .collect()
+}
+
+impl WindowFunction {
+    /// Whether the function yields one value per window rather than a series
+    pub fn is_scalar(&self) -> bool {
+        matches!(
+            self,
+            WindowFunction::Rate
+                | WindowFunction::Irate
+                | WindowFunction::Delta
+                | WindowFunction::Derivative
+        )
+    }
+
+    /// Evaluate over time-ordered samples; series functions yield their
+    /// value at the last sample
+    pub fn evaluate(&self, samples: &[Sample], counter: bool) -> Option<f64> {
+        match self {
+            WindowFunction::Rate => rate(samples, counter),
+            WindowFunction::Irate => irate(samples, counter),
+            WindowFunction::Delta => delta(samples),
+            WindowFunction::Derivative => derivative(samples),
+            _ => self
+                .evaluate_series(samples, Duration::zero(), counter)
+                .last()
+                .map(|(_, v)| *v),
+        }
+    }
+
+    /// Evaluate at every time-ordered sample. Scalar functions are computed
+    /// over the trailing `range` ending at each sample; samples where they
+    /// are undefined are skipped.
+    pub fn evaluate_series(
+        &self,
+        samples: &[Sample],
+        range: Duration,
+        counter: bool,
+    ) -> Vec<Sample> {
+        match *self {
+            WindowFunction::Ewma { alpha } => ewma(samples, alpha),
+            WindowFunction::MovingPercentile {
+                percentile,
+                window_secs,
+            } => moving_percentile(samples, Duration::seconds(window_secs as i64), percentile),
+            WindowFunction::CumulativeSum => cumulative_sum(samples),
+            _ => {
+                let mut start = 0;
+                samples
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, &(t, _))| {
+                        while start < i && samples[start].0 < t - range {
+                            start += 1;
+                        }
+                        Some((t, self.evaluate(&samples[start..=i], counter)?))
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(values: &[f64]) -> Vec<Sample> {
+        let start = Utc::now();
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| (start + Duration::seconds(10 * i as i64), v))
+            .collect()
+    }
+
+    #[test]
+    fn test_counter_rate_survives_resets() {
+        // 100 -> 150, reset, 0 -> 30: increase of 80 over 30s
+        let samples = series(&[100.0, 150.0, 10.0, 30.0]);
+        assert_eq!(increase(&samples, true), Some(80.0));
+        assert!((rate(&samples, true).unwrap() - 80.0 / 30.0).abs() < 1e-9);
+        assert_eq!(delta(&samples), Some(-70.0));
+
+        assert_eq!(irate(&samples, true), Some(2.0));
+        assert_eq!(irate(&samples[..3], true), Some(1.0));
+        assert_eq!(irate(&samples[..1], true), None);
+    }
+
+    #[test]
+    fn test_derivative_fits_slope() {
+        let samples = series(&[0.0, 21.0, 39.0, 60.0]);
+        let slope = derivative(&samples).unwrap();
+        assert!((slope - 1.98).abs() < 1e-9);
+        assert_eq!(derivative(&samples[..1]), None);
+    }
+
+    #[test]
+    fn test_moving_window_functions() {
+        let samples = series(&[10.0, 20.0, 30.0, 40.0]);
+
+        let smoothed = ewma(&samples, 0.5);
+        assert_eq!(
+            smoothed.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+            vec![10.0, 15.0, 22.5, 31.25]
+        );
+
+        let totals = cumulative_sum(&samples);
+        assert_eq!(totals[3].1, 100.0);
+
+        // 20s window holds the current and previous sample
+        let p100 = moving_percentile(&samples, Duration::seconds(20), 100.0);
+        assert_eq!(
+            p100.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+            vec![10.0, 20.0, 30.0, 40.0]
+        );
+        let p0 = moving_percentile(&samples, Duration::seconds(20), 0.0);
+        assert_eq!(p0[3].1, 30.0);
+    }
+
+    #[test]
+    fn test_series_evaluation_over_trailing_range() {
+        let samples = series(&[0.0, 10.0, 30.0, 60.0]);
+        let rates = WindowFunction::Rate.evaluate_series(&samples, Duration::seconds(10), true);
+        // The first sample has no predecessor within range
+        assert_eq!(
+            rates.iter().map(|(_, v)| *v).collect::<Vec<_>>(),
+            vec![1.0, 2.0, 3.0]
+        );
+        assert_eq!(
+            WindowFunction::CumulativeSum.evaluate(&samples, false),
+            Some(100.0)
+        );
+        assert!(WindowFunction::Derivative.is_scalar());
+    }
+}
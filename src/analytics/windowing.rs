@@ -0,0 +1,531 @@
+//! Event-Time Windowing
+//!
+//! Tumbling windows over event time for out-of-order streams. Each source
+//! partition tracks the latest event time it has seen, and the watermark is
+//! the minimum across partitions less `max_out_of_orderness`. A window closes
+//! only once every partition has moved past it. Partitions idle for
+//! `idle_timeout` stop holding the watermark back, and when every partition
+//! is idle the watermark follows processing time.
+//!
+//! Closed windows keep their state for `allowed_lateness`. A late event
+//! within that time updates its window, which is emitted again so that
+//! stored aggregates are upserted. Events for windows past their lateness
+//! are handled by [`LateDataPolicy`]. Windows also record the earliest
+//! source offset they contain, so that offsets are not committed until the
+//! windows holding them have been purged.
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Handling of events for windows past their allowed lateness
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LateDataPolicy {
+    /// Hand the event back for a side output
+    SideOutput,
+    /// Count and drop the event
+    Drop,
+}
+
+impl std::str::FromStr for LateDataPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "side_output" => Ok(Self::SideOutput),
+            "drop" => Ok(Self::Drop),
+            other => anyhow::bail!("unknown late data policy: {}", other),
+        }
+    }
+}
+
+/// Windowing configuration
+#[derive(Debug, Clone)]
+pub struct WindowingConfig {
+    /// Bound on how far events within a partition arrive out of order
+    pub max_out_of_orderness: Duration,
+    /// How long closed windows accept late events
+    pub allowed_lateness: Duration,
+    /// Processing time after which a silent partition is considered idle
+    pub idle_timeout: Duration,
+    /// Interval for emitting partial results of open windows
+    pub early_firing: Option<Duration>,
+    pub late_data: LateDataPolicy,
+}
+
+impl Default for WindowingConfig {
+    fn default() -> Self {
+        Self {
+            max_out_of_orderness: Duration::seconds(5),
+            allowed_lateness: Duration::minutes(5),
+            idle_timeout: Duration::minutes(1),
+            early_firing: None,
+            late_data: LateDataPolicy::SideOutput,
+        }
+    }
+}
+
+/// Why a window was emitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emission {
+    /// Partial result of an open window
+    Early,
+    /// The watermark passed the end of the window
+    OnTime,
+    /// A closed window was updated by late events
+    Late,
+}
+
+/// A window result
+#[derive(Debug, Clone)]
+pub struct WindowOutput<K, R> {
+    pub key: K,
+    pub window_start: DateTime<Utc>,
+    pub window_end: DateTime<Utc>,
+    pub emission: Emission,
+    pub result: R,
+}
+
+/// Windowing statistics
+#[derive(Debug, Clone, Default)]
+pub struct WindowingStats {
+    pub windows_open: usize,
+    pub windows_fired: u64,
+    pub late_events: u64,
+    pub too_late_events: u64,
+}
+
+#[derive(Debug, Clone)]
+struct PartitionClock {
+    max_event_time: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+}
+
+/// Per-partition event-time watermark
+#[derive(Debug, Clone)]
+pub struct WatermarkTracker {
+    max_out_of_orderness: Duration,
+    idle_timeout: Duration,
+    partitions: HashMap<i32, PartitionClock>,
+    watermark: Option<DateTime<Utc>>,
+}
+
+impl WatermarkTracker {
+    pub fn new(max_out_of_orderness: Duration, idle_timeout: Duration) -> Self {
+        Self {
+            max_out_of_orderness,
+            idle_timeout,
+            partitions: HashMap::new(),
+            watermark: None,
+        }
+    }
+
+    /// Record an event seen on a partition at processing time `now`
+    pub fn observe(&mut self, partition: i32, event_time: DateTime<Utc>, now: DateTime<Utc>) {
+        let clock = self.partitions.entry(partition).or_insert(PartitionClock {
+            max_event_time: event_time,
+            last_seen: now,
+        });
+        clock.max_event_time = clock.max_event_time.max(event_time);
+        clock.last_seen = now;
+    }
+
+    /// Stop tracking a partition, e.g. after it was revoked
+    pub fn remove_partition(&mut self, partition: i32) {
+        self.partitions.remove(&partition);
+    }
+
+    /// Recompute the watermark at processing time `now`; it never decreases
+    pub fn advance(&mut self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let active = self
+            .partitions
+            .values()
+            .filter(|clock| now - clock.last_seen < self.idle_timeout)
+            .map(|clock| clock.max_event_time)
+            .min();
+        let candidate = match active {
+            Some(min) => Some(min - self.max_out_of_orderness),
+            // Every partition is idle: event time follows processing time
+            None => self
+                .partitions
+                .values()
+                .map(|clock| clock.max_event_time + (now - clock.last_seen))
+                .max()
+                .map(|latest| latest - self.max_out_of_orderness),
+        };
+        self.watermark = self.watermark.max(candidate);
+        self.watermark
+    }
+
+    pub fn watermark(&self) -> Option<DateTime<Utc>> {
+        self.watermark
+    }
+}
+
+struct WindowEntry<S> {
+    end: DateTime<Utc>,
+    state: S,
+    fired: bool,
+    dirty: bool,
+    // Partition -> earliest offset contributing to the window
+    offsets: HashMap<i32, i64>,
+}
+
+/// Keyed tumbling windows with watermark-driven firing
+pub struct EventTimeWindows<K, S> {
+    config: WindowingConfig,
+    watermark: WatermarkTracker,
+    windows: HashMap<(K, DateTime<Utc>), WindowEntry<S>>,
+    last_early_firing: Option<DateTime<Utc>>,
+    stats: WindowingStats,
+}
+
+impl<K, S> EventTimeWindows<K, S>
+where
+    K: Hash + Eq + Clone,
+    S: Default,
+{
+    pub fn new(config: WindowingConfig) -> Self {
+        let watermark = WatermarkTracker::new(config.max_out_of_orderness, config.idle_timeout);
+        Self {
+            config,
+            watermark,
+            windows: HashMap::new(),
+            last_early_firing: None,
+            stats: WindowingStats::default(),
+        }
+    }
+
+    pub fn late_data_policy(&self) -> LateDataPolicy {
+        self.config.late_data
+    }
+
+    /// Record an event seen on a partition at processing time `now`
+    pub fn observe(&mut self, partition: i32, event_time: DateTime<Utc>, now: DateTime<Utc>) {
+        self.watermark.observe(partition, event_time, now);
+    }
+
+    /// Stop tracking a revoked partition: it no longer holds back the
+    /// watermark or the offsets to commit
+    pub fn remove_partition(&mut self, partition: i32) {
+        self.watermark.remove_partition(partition);
+        for entry in self.windows.values_mut() {
+            entry.offsets.remove(&partition);
+        }
+    }
+
+    pub fn watermark(&self) -> Option<DateTime<Utc>> {
+        self.watermark.watermark()
+    }
+
+    /// State of the window of `size` containing `event_time`, or `None` if
+    /// the window is past its allowed lateness. `source` is the partition
+    /// and offset of the event.
+    pub fn assign(
+        &mut self,
+        key: K,
+        size: Duration,
+        event_time: DateTime<Utc>,
+        source: Option<(i32, i64)>,
+    ) -> Option<&mut S> {
+        let start = align(event_time, size);
+        let end = start + size;
+        if matches!(self.watermark(), Some(wm) if end + self.config.allowed_lateness <= wm) {
+            self.stats.too_late_events += 1;
+            return None;
+        }
+
+        let entry = self
+            .windows
+            .entry((key, start))
+            .or_insert_with(|| WindowEntry {
+                end,
+                state: S::default(),
+                fired: false,
+                dirty: false,
+                offsets: HashMap::new(),
+            });
+        if entry.fired {
+            self.stats.late_events += 1;
+        }
+        if let Some((partition, offset)) = source {
+            let earliest = entry.offsets.entry(partition).or_insert(offset);
+            *earliest = (*earliest).min(offset);
+        }
+        entry.dirty = true;
+        Some(&mut entry.state)
+    }
+
+    /// Advance the watermark to processing time `now` and emit closed
+    /// windows, late updates and due early results. Windows past their
+    /// allowed lateness are purged.
+    pub fn fire<R>(
+        &mut self,
+        now: DateTime<Utc>,
+        summarize: impl Fn(&K, &S) -> R,
+    ) -> Vec<WindowOutput<K, R>> {
+        let watermark = self.watermark.advance(now);
+        let early = match self.config.early_firing {
+            Some(every) if !matches!(self.last_early_firing, Some(last) if now - last < every) => {
+                self.last_early_firing = Some(now);
+                true
+            }
+            _ => false,
+        };
+
+        let mut outputs = Vec::new();
+        for ((key, start), entry) in self.windows.iter_mut() {
+            let closed = matches!(watermark, Some(wm) if entry.end <= wm);
+            let emission = match (closed, entry.fired, entry.dirty) {
+                (true, false, _) => Emission::OnTime,
+                (true, true, true) => Emission::Late,
+                (false, _, true) if early => Emission::Early,
+                _ => continue,
+            };
+            outputs.push(WindowOutput {
+                key: key.clone(),
+                window_start: *start,
+                window_end: entry.end,
+                emission,
+                result: summarize(key, &entry.state),
+            });
+            entry.dirty = false;
+            entry.fired |= closed;
+        }
+        self.stats.windows_fired += outputs
+            .iter()
+            .filter(|o| o.emission == Emission::OnTime)
+            .count() as u64;
+
+        if let Some(wm) = watermark {
+            let lateness = self.config.allowed_lateness;
+            self.windows
+                .retain(|_, entry| !(entry.fired && entry.end + lateness <= wm));
+        }
+        outputs.sort_by_key(|o| o.window_start);
+        outputs
+    }
+
+    /// Emit every window with unemitted updates and drop all state, e.g. on
+    /// shutdown
+    pub fn flush_all<R>(&mut self, summarize: impl Fn(&K, &S) -> R) -> Vec<WindowOutput<K, R>> {
+        let mut outputs: Vec<_> = self
+            .windows
+            .drain()
+            .filter(|(_, entry)| entry.dirty)
+            .map(|((key, start), entry)| WindowOutput {
+                result: summarize(&key, &entry.state),
+                key,
+                window_start: start,
+                window_end: entry.end,
+                emission: if entry.fired {
+                    Emission::Late
+                } else {
+                    Emission::OnTime
+                },
+            })
+            .collect();
+        outputs.sort_by_key(|o| o.window_start);
+        outputs
+    }
+
+    /// Mark a window for emission on the next firing, e.g. after its result
+    /// failed to store
+    pub fn retry(&mut self, key: &K, window_start: DateTime<Utc>) {
+        if let Some(entry) = self.windows.get_mut(&(key.clone(), window_start)) {
+            entry.dirty = true;
+        }
+    }
+
+    /// Earliest offset per partition still held by a window. Committing
+    /// beyond it would lose the window's state on restart.
+    pub fn held_offsets(&self) -> HashMap<i32, i64> {
+        let mut held: HashMap<i32, i64> = HashMap::new();
+        for entry in self.windows.values() {
+            for (&partition, &offset) in &entry.offsets {
+                let earliest = held.entry(partition).or_insert(offset);
+                *earliest = (*earliest).min(offset);
+            }
+        }
+        held
+    }
+
+    pub fn stats(&self) -> WindowingStats {
+        WindowingStats {
+            windows_open: self.windows.len(),
+            ..self.stats.clone()
+        }
+    }
+}
+
+/// Start of the tumbling window of `size` containing `timestamp`
+pub fn align(timestamp: DateTime<Utc>, size: Duration) -> DateTime<Utc> {
+    let size_ms = size.num_milliseconds().max(1);
+    let ms = timestamp.timestamp_millis();
+    DateTime::from_timestamp_millis(ms - ms.rem_euclid(size_ms)).unwrap_or(timestamp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_040 + secs, 0).unwrap()
+    }
+
+    fn windows(allowed_lateness: i64) -> EventTimeWindows<&'static str, Vec<f64>> {
+        EventTimeWindows::new(WindowingConfig {
+            max_out_of_orderness: Duration::seconds(5),
+            allowed_lateness: Duration::seconds(allowed_lateness),
+            idle_timeout: Duration::seconds(30),
+            ..Default::default()
+        })
+    }
+
+    fn add(
+        w: &mut EventTimeWindows<&'static str, Vec<f64>>,
+        partition: i32,
+        secs: i64,
+        now: i64,
+    ) -> bool {
+        w.observe(partition, at(secs), at(now));
+        match w.assign(
+            "latency",
+            Duration::minutes(1),
+            at(secs),
+            Some((partition, secs)),
+        ) {
+            Some(values) => {
+                values.push(secs as f64);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn sums(outputs: &[WindowOutput<&str, f64>]) -> Vec<(i64, Emission, f64)> {
+        outputs
+            .iter()
+            .map(|o| ((o.window_start - at(0)).num_seconds(), o.emission, o.result))
+            .collect()
+    }
+
+    #[test]
+    fn test_slowest_partition_holds_the_watermark() {
+        let mut w = windows(60);
+        let sum = |_: &&str, v: &Vec<f64>| v.iter().sum::<f64>();
+
+        // Partition 0 races ahead; partition 1 is still in the first minute
+        add(&mut w, 0, 10, 0);
+        add(&mut w, 1, 20, 0);
+        add(&mut w, 0, 70, 1);
+        add(&mut w, 0, 130, 2);
+        assert!(w.fire(at(2), sum).is_empty());
+        assert_eq!(w.watermark(), Some(at(15)));
+
+        // Out-of-order event on partition 1 for the first window
+        add(&mut w, 1, 5, 3);
+        add(&mut w, 1, 66, 4);
+        assert_eq!(sums(&w.fire(at(4), sum)), vec![(0, Emission::OnTime, 35.0)]);
+        add(&mut w, 1, 71, 5);
+        assert!(w.fire(at(5), sum).is_empty());
+        assert_eq!(w.watermark(), Some(at(66)));
+
+        // Partition 1 goes idle; partition 0 alone drives the watermark
+        add(&mut w, 0, 200, 40);
+        assert_eq!(
+            sums(&w.fire(at(40), sum)),
+            vec![
+                (60, Emission::OnTime, 207.0),
+                (120, Emission::OnTime, 130.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_late_events_update_windows_until_allowed_lateness() {
+        let mut w = windows(60);
+        let sum = |_: &&str, v: &Vec<f64>| v.iter().sum::<f64>();
+
+        add(&mut w, 0, 10, 0);
+        add(&mut w, 0, 70, 1);
+        assert_eq!(sums(&w.fire(at(1), sum)), vec![(0, Emission::OnTime, 10.0)]);
+
+        // Within allowed lateness: the stored aggregate is updated
+        assert!(add(&mut w, 0, 30, 2));
+        assert_eq!(sums(&w.fire(at(2), sum)), vec![(0, Emission::Late, 40.0)]);
+        assert!(w.fire(at(3), sum).is_empty());
+
+        // Past allowed lateness: the window is purged and the event rejected
+        add(&mut w, 0, 130, 4);
+        assert_eq!(
+            sums(&w.fire(at(4), sum)),
+            vec![(60, Emission::OnTime, 70.0)]
+        );
+        assert!(!add(&mut w, 0, 20, 5));
+
+        let stats = w.stats();
+        assert_eq!((stats.late_events, stats.too_late_events), (1, 1));
+        assert_eq!(stats.windows_fired, 2);
+    }
+
+    #[test]
+    fn test_offsets_are_held_until_windows_are_purged() {
+        let mut w = windows(0);
+        let sum = |_: &&str, v: &Vec<f64>| v.iter().sum::<f64>();
+
+        add(&mut w, 0, 10, 0);
+        add(&mut w, 0, 50, 0);
+        add(&mut w, 1, 20, 0);
+        assert_eq!(w.held_offsets(), HashMap::from([(0, 10), (1, 20)]));
+
+        add(&mut w, 0, 70, 1);
+        add(&mut w, 1, 80, 1);
+        w.fire(at(1), sum);
+        assert_eq!(w.held_offsets(), HashMap::from([(0, 70), (1, 80)]));
+
+        let rest = w.flush_all(sum);
+        assert_eq!(sums(&rest), vec![(60, Emission::OnTime, 150.0)]);
+        assert!(w.held_offsets().is_empty());
+    }
+
+    #[test]
+    fn test_removed_partitions_release_held_offsets() {
+        let mut w = windows(0);
+        add(&mut w, 0, 10, 0);
+        add(&mut w, 1, 20, 0);
+
+        w.remove_partition(1);
+        assert_eq!(w.held_offsets(), HashMap::from([(0, 10)]));
+    }
+
+    #[test]
+    fn test_early_firing_emits_partial_results() {
+        let mut w = EventTimeWindows::new(WindowingConfig {
+            early_firing: Some(Duration::seconds(10)),
+            ..Default::default()
+        });
+        let count = |_: &&str, v: &Vec<f64>| v.len() as f64;
+        let push = |w: &mut EventTimeWindows<&str, Vec<f64>>, secs: i64| {
+            w.observe(0, at(secs), at(secs));
+            w.assign("latency", Duration::minutes(1), at(secs), None)
+                .unwrap()
+                .push(1.0);
+        };
+
+        push(&mut w, 1);
+        assert_eq!(sums(&w.fire(at(1), count)), vec![(0, Emission::Early, 1.0)]);
+        push(&mut w, 2);
+        assert!(w.fire(at(5), count).is_empty());
+        assert_eq!(
+            sums(&w.fire(at(11), count)),
+            vec![(0, Emission::Early, 2.0)]
+        );
+
+        assert_eq!(
+            "side_output".parse::<LateDataPolicy>().unwrap(),
+            LateDataPolicy::SideOutput
+        );
+        assert!("upsert".parse::<LateDataPolicy>().is_err());
+    }
+}
@@ -4,15 +4,26 @@
 //! Replaces Python script with 10-100x better performance.
 
 use anyhow::{Context, Result};
-use chrono::Utc;
+use chrono::{Duration as ChronoDuration, Utc};
 use clap::Parser;
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
+use llm_analytics_hub::database::copy::CopyConfig;
+use llm_analytics_hub::database::Database;
+use llm_analytics_hub::models::metrics::{
+    AggregatedMetric, MetricValues, StatisticalMeasures, TimeWindow,
+};
+use llm_analytics_hub::schemas::events::{
+    AnalyticsEvent, CommonEventFields, EventPayload, EventType, LatencyMetrics, Severity,
+    SourceModule, TelemetryPayload,
+};
 // use rand::Rng; // Commented out: rand crate not in dependencies
 use sqlx::postgres::{PgPool, PgPoolOptions};
+use std::collections::HashMap;
 use std::time::{Duration, Instant};
 use tokio::task::JoinSet;
 use tracing::{info, warn};
+use uuid::Uuid;
 
 #[derive(Parser)]
 #[command(name = "bench-timescaledb")]
@@ -53,6 +64,20 @@ struct Cli {
     /// Batch size for inserts
     #[arg(long, default_value = "100")]
     batch_size: usize,
+
+    /// Compare INSERT and COPY BINARY event and aggregate writes instead of
+    /// running the load tests
+    #[arg(long)]
+    compare_writes: bool,
+
+    /// Events, and aggregates, written by each path in the write comparison
+    #[arg(long, default_value = "100000")]
+    compare_events: usize,
+
+    /// Rows per write in the write comparison; the INSERT path binds 11
+    /// parameters per event, so keep this under 5957
+    #[arg(long, default_value = "5000")]
+    write_batch: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -130,17 +155,19 @@ fn log_error(msg: &str) {
     println!("{}", format!("[BENCH-DB] {}", msg).red().bold());
 }
 
-async fn create_pool(cli: &Cli) -> Result<PgPool> {
-    let connection_string = format!(
+fn connection_string(cli: &Cli) -> String {
+    format!(
         "postgres://{}:{}@{}:{}/{}",
         cli.user, cli.password, cli.host, cli.port, cli.database
-    );
+    )
+}
 
+async fn create_pool(cli: &Cli) -> Result<PgPool> {
     let pool = PgPoolOptions::new()
         .min_connections(10)
         .max_connections(cli.connections as u32)
         .acquire_timeout(Duration::from_secs(30))
-        .connect(&connection_string)
+        .connect(&connection_string(cli))
         .await
         .context("Failed to create connection pool")?;
 
@@ -177,6 +204,63 @@ fn generate_metric_record() -> MetricRecord {
     }
 }
 
+fn generate_event(seed: usize) -> AnalyticsEvent {
+    let models = ["gpt-4", "gpt-3.5-turbo", "claude-3", "claude-2"];
+    let envs = ["dev", "staging", "prod"];
+
+    let mut common = CommonEventFields::new(
+        SourceModule::LlmObservatory,
+        EventType::Telemetry,
+        Severity::Info,
+        envs[seed % envs.len()],
+    );
+    common.correlation_id = Some(Uuid::new_v4());
+    common
+        .tags
+        .insert("bench".to_string(), "compare-writes".to_string());
+
+    AnalyticsEvent {
+        common,
+        payload: EventPayload::Telemetry(TelemetryPayload::Latency(LatencyMetrics {
+            model_id: models[seed % models.len()].to_string(),
+            request_id: format!("bench-{}", seed),
+            total_latency_ms: ((seed % 450) as f64) + 50.0,
+            ttft_ms: None,
+            tokens_per_second: None,
+            breakdown: None,
+        })),
+    }
+}
+
+fn generate_aggregate(seed: usize, path: &str) -> AggregatedMetric {
+    let models = ["gpt-4", "gpt-3.5-turbo", "claude-3", "claude-2"];
+    let window_start = Utc::now() - ChronoDuration::minutes(seed as i64);
+    let latency = ((seed % 450) as f64) + 50.0;
+
+    AggregatedMetric {
+        name: format!("bench_latency_{}", models[seed % models.len()]),
+        window: TimeWindow::OneMinute,
+        window_start,
+        window_end: window_start + ChronoDuration::minutes(1),
+        values: MetricValues::Stats(StatisticalMeasures {
+            avg: latency,
+            min: latency / 2.0,
+            max: latency * 2.0,
+            p50: latency,
+            p95: latency * 1.5,
+            p99: latency * 1.8,
+            stddev: Some(latency / 4.0),
+            count: 100,
+            sum: latency * 100.0,
+        }),
+        // Per path so neither merges into the other's rows
+        tags: HashMap::from([
+            ("bench".to_string(), "compare-writes".to_string()),
+            ("path".to_string(), path.to_string()),
+        ]),
+    }
+}
+
 async fn insert_batch(pool: &PgPool, batch_size: usize) -> Result<Duration> {
     let start = Instant::now();
 
@@ -349,6 +433,109 @@ async fn test_concurrent_connections(pool: &PgPool, cli: &Cli) -> Result<Duratio
     Ok(start.elapsed())
 }
 
+/// Write the same number of events through `insert_events_batch` and the
+/// COPY BINARY writer, then the same number of aggregates through
+/// `store_aggregated_metric` and the writer, one write at a time, timing
+/// each write
+async fn run_write_comparison(cli: &Cli) -> Result<WriteComparison> {
+    let database = Database::from_url(&connection_string(cli)).await?;
+    let writer = database.copy_writer(CopyConfig::default());
+    let writes = (cli.compare_events + cli.write_batch - 1) / cli.write_batch;
+    let rows = |write: usize| (0..cli.write_batch).map(move |i| write * cli.write_batch + i);
+
+    log_info("Running INSERT event writes...");
+    let pb = ProgressBar::new(writes as u64);
+    let mut timings = Vec::with_capacity(writes);
+    for write in 0..writes {
+        // Fresh events for each path so neither merges into the other's rows
+        let events: Vec<AnalyticsEvent> = rows(write).map(generate_event).collect();
+        let start = Instant::now();
+        database.insert_events_batch(&events).await?;
+        timings.push(start.elapsed());
+        pb.inc(1);
+    }
+    pb.finish_with_message("INSERT writes complete");
+    let insert_events = BenchmarkResults::from_timings(writes * cli.write_batch, &timings);
+
+    log_info("Running COPY BINARY event writes...");
+    let pb = ProgressBar::new(writes as u64);
+    let mut timings = Vec::with_capacity(writes);
+    for write in 0..writes {
+        let events: Vec<AnalyticsEvent> = rows(write).map(generate_event).collect();
+        let start = Instant::now();
+        writer.write_events(&events).await?;
+        timings.push(start.elapsed());
+        pb.inc(1);
+    }
+    pb.finish_with_message("COPY writes complete");
+    let copy_events = BenchmarkResults::from_timings(writes * cli.write_batch, &timings);
+
+    log_info("Running INSERT aggregate writes...");
+    let pb = ProgressBar::new(writes as u64);
+    let mut timings = Vec::with_capacity(writes);
+    for write in 0..writes {
+        let aggregates: Vec<AggregatedMetric> = rows(write)
+            .map(|seed| generate_aggregate(seed, "insert"))
+            .collect();
+        let start = Instant::now();
+        for metric in &aggregates {
+            let MetricValues::Stats(measures) = &metric.values else {
+                continue;
+            };
+            database
+                .store_aggregated_metric(
+                    &metric.name,
+                    metric.window,
+                    metric.window_start,
+                    &serde_json::to_value(&metric.tags)?,
+                    measures,
+                )
+                .await?;
+        }
+        timings.push(start.elapsed());
+        pb.inc(1);
+    }
+    pb.finish_with_message("INSERT writes complete");
+    let insert_aggregates = BenchmarkResults::from_timings(writes * cli.write_batch, &timings);
+
+    log_info("Running COPY BINARY aggregate writes...");
+    let pb = ProgressBar::new(writes as u64);
+    let mut timings = Vec::with_capacity(writes);
+    for write in 0..writes {
+        let aggregates: Vec<AggregatedMetric> = rows(write)
+            .map(|seed| generate_aggregate(seed, "copy"))
+            .collect();
+        let start = Instant::now();
+        writer.write_aggregates(&aggregates).await?;
+        timings.push(start.elapsed());
+        pb.inc(1);
+    }
+    pb.finish_with_message("COPY writes complete");
+    let copy_aggregates = BenchmarkResults::from_timings(writes * cli.write_batch, &timings);
+
+    let stats = writer.stats();
+    info!(
+        "COPY writer: {} batches, {} rows merged, final batch size {}",
+        stats.batches, stats.rows_merged, stats.batch_size
+    );
+
+    database.close().await;
+    Ok(WriteComparison {
+        insert_events,
+        copy_events,
+        insert_aggregates,
+        copy_aggregates,
+    })
+}
+
+/// Results of the INSERT and COPY BINARY write paths
+struct WriteComparison {
+    insert_events: BenchmarkResults,
+    copy_events: BenchmarkResults,
+    insert_aggregates: BenchmarkResults,
+    copy_aggregates: BenchmarkResults,
+}
+
 fn print_results(name: &str, results: &BenchmarkResults) {
     log_success(&format!("{} Results:", name));
     println!("  Total Operations:   {}", format!("{:>12}", results.total_operations).cyan());
@@ -406,6 +593,25 @@ async fn main() -> Result<()> {
     log_info("==================================================");
     println!();
 
+    if cli.compare_writes {
+        let comparison = run_write_comparison(&cli).await?;
+        print_results("INSERT Event Writes", &comparison.insert_events);
+        print_results("COPY BINARY Event Writes", &comparison.copy_events);
+        log_success(&format!(
+            "  COPY Event Speedup:     {:.1}x",
+            comparison.copy_events.ops_per_sec / comparison.insert_events.ops_per_sec
+        ));
+        println!();
+        print_results("INSERT Aggregate Writes", &comparison.insert_aggregates);
+        print_results("COPY BINARY Aggregate Writes", &comparison.copy_aggregates);
+        log_success(&format!(
+            "  COPY Aggregate Speedup: {:.1}x",
+            comparison.copy_aggregates.ops_per_sec / comparison.insert_aggregates.ops_per_sec
+        ));
+        println!();
+        return Ok(());
+    }
+
     // Create connection pool
     log_info(&format!("Connecting to {}:{}/{}...", cli.host, cli.port, cli.database));
     let pool = create_pool(&cli).await?;
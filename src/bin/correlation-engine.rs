@@ -5,18 +5,31 @@
 //! - Event correlation detection
 //! - Graph-based event analysis
 //! - Pattern recognition
+//! - Lagged correlation and Granger causality between per-module metric
+//!   series, cached in Redis (`CAUSALITY_INTERVAL_SECS`)
+//! - Multi-step sequence matching (CEP) from `CEP_PATTERNS_PATH`, with
+//!   negation windows closed by a per-partition event-time watermark
 //! - Root cause analysis
 //! - Redis-backed correlation cache
+//! - Sequence matches published to `CORRELATIONS_TOPIC`, optionally exactly-once
+//!   (`PROCESSING_GUARANTEE=exactly_once`)
 
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use llm_analytics_hub::analytics::correlation::CorrelationEngine as MetricCorrelationEngine;
+use llm_analytics_hub::analytics::{CepEngine, MetricRelationship, WatermarkTracker};
+use llm_analytics_hub::models::correlation::CorrelationPattern;
+use llm_analytics_hub::pipeline::transactions::{self, ProcessingGuarantee};
+use llm_analytics_hub::pipeline::{StageCommitter, TransactionConfig};
 use llm_analytics_hub::{
     AnalyticsEvent, CorrelationId, CorrelationType, EventCorrelation, EventGraph,
 };
 use prometheus::{register_counter_vec, register_histogram_vec, CounterVec, HistogramVec};
-use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
-use rdkafka::{ClientConfig, Message};
+use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::producer::FutureProducer;
+use rdkafka::Message;
 use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
@@ -25,6 +38,9 @@ use tokio::time::{interval, Duration};
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
+/// Redis key holding the latest discovered metric relationships
+const RELATIONSHIPS_KEY: &str = "correlation:metric_relationships";
+
 /// Application state
 struct AppState {
     redis: ConnectionManager,
@@ -75,6 +91,13 @@ struct Config {
     kafka_group_id: String,
     redis_url: String,
     correlation_window_secs: u64,
+    cep_patterns_path: Option<String>,
+    cep_allowed_lateness_secs: u64,
+    cep_idle_timeout_secs: u64,
+    correlations_topic: String,
+    causality_interval_secs: u64,
+    processing_guarantee: ProcessingGuarantee,
+    transactional_id: String,
 }
 
 impl Config {
@@ -91,6 +114,31 @@ impl Config {
                 .unwrap_or_else(|_| "300".to_string())
                 .parse()
                 .expect("Invalid CORRELATION_WINDOW_SECS"),
+            cep_patterns_path: std::env::var("CEP_PATTERNS_PATH").ok(),
+            cep_allowed_lateness_secs: std::env::var("CEP_ALLOWED_LATENESS_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .expect("Invalid CEP_ALLOWED_LATENESS_SECS"),
+            cep_idle_timeout_secs: std::env::var("CEP_IDLE_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .expect("Invalid CEP_IDLE_TIMEOUT_SECS"),
+            correlations_topic: std::env::var("CORRELATIONS_TOPIC")
+                .unwrap_or_else(|_| "llm-correlations".to_string()),
+            causality_interval_secs: std::env::var("CAUSALITY_INTERVAL_SECS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .expect("Invalid CAUSALITY_INTERVAL_SECS"),
+            processing_guarantee: std::env::var("PROCESSING_GUARANTEE")
+                .unwrap_or_else(|_| "at_least_once".to_string())
+                .parse()
+                .expect("Invalid PROCESSING_GUARANTEE"),
+            transactional_id: std::env::var("TRANSACTIONAL_ID").unwrap_or_else(|_| {
+                format!(
+                    "correlation-engine-{}",
+                    std::env::var("HOSTNAME").unwrap_or_else(|_| "0".to_string())
+                )
+            }),
         }
     }
 }
@@ -99,6 +147,9 @@ impl Config {
 struct CorrelationEngine {
     event_cache: Arc<DashMap<Uuid, CachedEvent>>,
     correlations: Arc<DashMap<CorrelationId, Vec<EventCorrelation>>>,
+    cep: Arc<CepEngine>,
+    // Per-module metric series for causality analysis
+    metric_series: MetricCorrelationEngine,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -127,14 +178,21 @@ impl From<&AnalyticsEvent> for CachedEvent {
 }
 
 impl CorrelationEngine {
-    fn new() -> Self {
+    fn new(cep: Arc<CepEngine>) -> Self {
         Self {
             event_cache: Arc::new(DashMap::new()),
             correlations: Arc::new(DashMap::new()),
+            cep,
+            metric_series: MetricCorrelationEngine::new(),
         }
     }
 
-    fn process_event(&self, event: &AnalyticsEvent, metrics: &Arc<Metrics>) {
+    /// Correlate an event, returning the sequence patterns it completed
+    fn process_event(
+        &self,
+        event: &AnalyticsEvent,
+        metrics: &Arc<Metrics>,
+    ) -> Vec<EventCorrelation> {
         let timer = metrics
             .analysis_duration
             .with_label_values(&["event_correlation"])
@@ -147,6 +205,9 @@ impl CorrelationEngine {
         self.detect_temporal_correlation(&cached_event, metrics);
         self.detect_causal_correlation(&cached_event, metrics);
         self.detect_pattern_correlation(&cached_event, metrics);
+        self.metric_series.record_event(event);
+        let matches = self.cep.process_event(event);
+        let matches = self.record_sequence_matches(matches, metrics);
 
         timer.observe_duration();
 
@@ -155,6 +216,8 @@ impl CorrelationEngine {
             .events_processed
             .with_label_values(&[&event_type])
             .inc();
+
+        matches
     }
 
     fn detect_temporal_correlation(&self, event: &CachedEvent, metrics: &Arc<Metrics>) {
@@ -219,6 +282,69 @@ impl CorrelationEngine {
         }
     }
 
+    /// Discover causal and temporal relationships between metric series
+    fn discover_relationships(&self, metrics: &Arc<Metrics>) -> Vec<MetricRelationship> {
+        let timer = metrics
+            .analysis_duration
+            .with_label_values(&["causality"])
+            .start_timer();
+        let relationships = self.metric_series.discover_relationships();
+        timer.observe_duration();
+
+        for relationship in &relationships {
+            let correlation_type = serde_json::to_value(&relationship.correlation_type)
+                .ok()
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_else(|| "temporal".to_string());
+            metrics
+                .correlations_detected
+                .with_label_values(&[&correlation_type])
+                .inc();
+            info!("Metric relationship: {}", relationship.describe());
+        }
+        relationships
+    }
+
+    /// Complete CEP sequences whose negation windows elapsed by `watermark`
+    fn advance_sequences(
+        &self,
+        watermark: DateTime<Utc>,
+        metrics: &Arc<Metrics>,
+    ) -> Vec<EventCorrelation> {
+        let matches = self.cep.advance_to(watermark);
+        self.record_sequence_matches(matches, metrics)
+    }
+
+    fn record_sequence_matches(
+        &self,
+        matches: Vec<EventCorrelation>,
+        metrics: &Arc<Metrics>,
+    ) -> Vec<EventCorrelation> {
+        for correlation in &matches {
+            let correlation_type = serde_json::to_value(&correlation.correlation_type)
+                .ok()
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_else(|| "pattern_match".to_string());
+            metrics
+                .correlations_detected
+                .with_label_values(&[&correlation_type])
+                .inc();
+
+            info!(
+                pattern_id = correlation.metadata.get("pattern_id").map(String::as_str),
+                partition_key = correlation.metadata.get("partition_key").map(String::as_str),
+                events = correlation.events.len(),
+                "Event sequence pattern matched"
+            );
+
+            self.correlations
+                .entry(correlation.correlation_id.clone())
+                .or_default()
+                .push(correlation.clone());
+        }
+        matches
+    }
+
     async fn cleanup_old_events(&self, retention_secs: u64) {
         let cutoff = Utc::now() - chrono::Duration::seconds(retention_secs as i64);
         let to_remove: Vec<_> = self
@@ -231,11 +357,28 @@ impl CorrelationEngine {
         for event_id in to_remove {
             self.event_cache.remove(&event_id);
         }
+        self.correlations.retain(|_, correlations| {
+            correlations.retain(|c| c.detected_at >= cutoff);
+            !correlations.is_empty()
+        });
 
         info!("Cleaned up old events from cache");
     }
 }
 
+/// Queue sequence matches for the next commit
+fn publish_correlations(
+    committer: &mut StageCommitter,
+    topic: &str,
+    matches: &[EventCorrelation],
+) -> anyhow::Result<()> {
+    for correlation in matches {
+        let key = correlation.correlation_id.0.to_string();
+        committer.produce(topic, &key, serde_json::to_vec(correlation)?);
+    }
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Initialize tracing
@@ -261,21 +404,51 @@ async fn main() -> anyhow::Result<()> {
 
     info!("Redis connection established");
 
+    // Load sequence patterns
+    let cep = Arc::new(CepEngine::default());
+    if let Some(path) = &config.cep_patterns_path {
+        let patterns: Vec<CorrelationPattern> =
+            serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        for pattern in patterns {
+            let pattern_id = pattern.pattern_id.clone();
+            if let Err(e) = cep.add_pattern(pattern) {
+                warn!("Skipping invalid pattern {}: {}", pattern_id, e);
+            }
+        }
+        info!("Loaded {} sequence patterns from {}", cep.patterns().len(), path);
+    }
+
     // Create correlation engine
-    let correlation_engine = Arc::new(CorrelationEngine::new());
+    let correlation_engine = Arc::new(CorrelationEngine::new(cep));
+
+    let transaction_config = TransactionConfig {
+        guarantee: config.processing_guarantee,
+        transactional_id: config.transactional_id.clone(),
+        ..Default::default()
+    };
 
     // Create Kafka consumer
-    let consumer: StreamConsumer = ClientConfig::new()
-        .set("bootstrap.servers", &config.kafka_brokers)
-        .set("group.id", &config.kafka_group_id)
-        .set("enable.auto.commit", "false")
-        .set("auto.offset.reset", "earliest")
-        .set("client.id", "correlation-engine-service")
-        .create()?;
+    let consumer: StreamConsumer = transactions::consumer_config(
+        &config.kafka_brokers,
+        &config.kafka_group_id,
+        "correlation-engine-service",
+        config.processing_guarantee,
+    )
+    .create()?;
 
     consumer.subscribe(&[&config.kafka_topic])?;
     info!("Subscribed to Kafka topic: {}", config.kafka_topic);
 
+    // Create Kafka producer for sequence matches; matches are committed
+    // together with the offsets of the events that completed them
+    let producer: FutureProducer = transactions::producer_config(
+        &config.kafka_brokers,
+        "correlation-engine-producer",
+        &transaction_config,
+    )
+    .create()?;
+    let mut committer = StageCommitter::new(producer, transaction_config)?;
+
     // Spawn cleanup task
     let cleanup_engine = correlation_engine.clone();
     tokio::spawn(async move {
@@ -286,6 +459,54 @@ async fn main() -> anyhow::Result<()> {
         }
     });
 
+    // Spawn causality analysis task
+    let causality_engine = correlation_engine.clone();
+    let causality_metrics = metrics.clone();
+    let causality_interval = config.causality_interval_secs;
+    let mut causality_redis = redis_conn.clone();
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(causality_interval));
+        loop {
+            interval.tick().await;
+            let engine = causality_engine.clone();
+            let metrics = causality_metrics.clone();
+            let analysis =
+                tokio::task::spawn_blocking(move || engine.discover_relationships(&metrics));
+            let relationships = match analysis.await {
+                Ok(relationships) => relationships,
+                Err(e) => {
+                    error!("Causality analysis failed: {}", e);
+                    continue;
+                }
+            };
+
+            let result = match serde_json::to_string(&relationships) {
+                Ok(json) => {
+                    causality_redis
+                        .set_ex::<_, _, ()>(RELATIONSHIPS_KEY, json, 2 * causality_interval)
+                        .await
+                }
+                Err(e) => {
+                    error!("Failed to serialize metric relationships: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = result {
+                error!("Failed to cache metric relationships: {}", e);
+            }
+        }
+    });
+
+    // Sequences are advanced in the consumption loop so that their matches
+    // are committed with the consumed offsets. The watermark trails the
+    // slowest active partition's latest event time by the allowed lateness.
+    let mut watermarks = WatermarkTracker::new(
+        chrono::Duration::seconds(config.cep_allowed_lateness_secs as i64),
+        chrono::Duration::seconds(config.cep_idle_timeout_secs as i64),
+    );
+    let mut watermark_interval = interval(Duration::from_secs(5));
+    let mut commit_interval = interval(Duration::from_secs(1));
+
     // Main consumption loop
     let mut shutdown = false;
     while !shutdown {
@@ -296,24 +517,35 @@ async fn main() -> anyhow::Result<()> {
                         if let Some(payload) = m.payload() {
                             match serde_json::from_slice::<AnalyticsEvent>(payload) {
                                 Ok(event) => {
-                                    correlation_engine.process_event(&event, &metrics);
-
-                                    // Commit offset
-                                    if let Err(e) = consumer.commit_message(&m, CommitMode::Async) {
-                                        warn!("Failed to commit offset: {}", e);
-                                    }
+                                    watermarks.observe(m.partition(), event.common.timestamp, Utc::now());
+                                    let matches = correlation_engine.process_event(&event, &metrics);
+                                    publish_correlations(&mut committer, &config.correlations_topic, &matches)?;
                                 }
                                 Err(e) => {
                                     error!("Failed to deserialize event: {}", e);
                                 }
                             }
                         }
+
+                        committer.consumed(m.topic(), m.partition(), m.offset());
+                        if committer.should_commit() {
+                            committer.commit(&consumer).await?;
+                        }
                     }
                     Err(e) => {
                         error!("Kafka consumer error: {}", e);
                     }
                 }
             }
+            _ = watermark_interval.tick() => {
+                if let Some(watermark) = watermarks.advance(Utc::now()) {
+                    let matches = correlation_engine.advance_sequences(watermark, &metrics);
+                    publish_correlations(&mut committer, &config.correlations_topic, &matches)?;
+                }
+            }
+            _ = commit_interval.tick() => {
+                committer.commit(&consumer).await?;
+            }
             _ = signal::ctrl_c() => {
                 info!("Received shutdown signal");
                 shutdown = true;
@@ -321,6 +553,9 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    // Commit the final batch before shutdown
+    committer.commit(&consumer).await?;
+
     info!("Service shutdown complete");
     Ok(())
 }
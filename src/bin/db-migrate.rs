@@ -6,6 +6,7 @@
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use colored::Colorize;
+use llm_analytics_hub::database::schema;
 use sqlx::postgres::{PgPool, PgPoolOptions};
 use std::time::Duration;
 use tracing::info;
@@ -107,6 +108,50 @@ async fn migrate(pool: &PgPool) -> Result<()> {
     apply_migration(pool, "005_create_indexes", CREATE_INDEXES).await?;
     apply_migration(pool, "006_enable_compression", ENABLE_COMPRESSION).await?;
     apply_migration(pool, "007_retention_policies", RETENTION_POLICIES).await?;
+    apply_migration(
+        pool,
+        "008_create_forecast_backtests_table",
+        schema::CREATE_FORECAST_BACKTESTS_TABLE,
+    )
+    .await?;
+    apply_migration(
+        pool,
+        "009_create_root_cause_analyses_table",
+        schema::CREATE_ROOT_CAUSE_ANALYSES_TABLE,
+    )
+    .await?;
+    apply_migration(pool, "010_create_slo_status_table", schema::CREATE_SLO_STATUS_TABLE).await?;
+    apply_migration(
+        pool,
+        "011_create_experiment_reports_table",
+        schema::CREATE_EXPERIMENT_REPORTS_TABLE,
+    )
+    .await?;
+    apply_migration(
+        pool,
+        "012_create_session_metrics_tables",
+        schema::CREATE_SESSION_METRICS_TABLES,
+    )
+    .await?;
+    apply_migration(
+        pool,
+        "013_create_chargeback_reports_table",
+        schema::CREATE_CHARGEBACK_REPORTS_TABLE,
+    )
+    .await?;
+    apply_migration(
+        pool,
+        "014_create_request_cluster_tables",
+        schema::CREATE_REQUEST_CLUSTER_TABLES,
+    )
+    .await?;
+
+    apply_migration(
+        &pool,
+        "015_create_chargeback_ledger_tables",
+        schema::CREATE_CHARGEBACK_LEDGER_TABLES,
+    )
+    .await?;
 
     println!("{}", "✅ All migrations applied successfully!".bold().green());
 
@@ -0,0 +1,251 @@
+//! Embedded Pipeline
+//!
+//! Runs the analytics pipeline in a single process without Kafka, for local
+//! development, CI and edge sites. Events are read from an NDJSON file,
+//! stdin or the HTTP ingestion routes, and written to NDJSON, Parquet or
+//! TimescaleDB.
+//!
+//! ```text
+//! embedded-pipeline --source events.ndjson --replay --events-out out.parquet
+//! cat events.ndjson | embedded-pipeline --aggregates-out stdout
+//! embedded-pipeline --source http --events-out timescaledb
+//! ```
+
+use anyhow::{bail, Result};
+use chrono::Duration;
+use clap::Parser;
+use llm_analytics_hub::database::Database;
+use llm_analytics_hub::models::metrics::AggregatedMetric;
+use llm_analytics_hub::pipeline::{
+    Clock, EmbeddedConfig, EmbeddedPipeline, HttpPushSource, NdjsonSink, NdjsonSource,
+    PipelineConfig, ReplaySource, Sink, Source, TimescaleSink,
+};
+use llm_analytics_hub::schemas::events::AnalyticsEvent;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::signal;
+use tracing::info;
+
+#[derive(Parser)]
+#[command(name = "embedded-pipeline")]
+#[command(about = "Run the LLM Analytics Hub pipeline without Kafka")]
+struct Cli {
+    /// Event source: an NDJSON file, `stdin` or `http`
+    #[arg(long, env = "EMBEDDED_SOURCE", default_value = "stdin")]
+    source: String,
+
+    /// Listen address for the `http` source
+    #[arg(long, env = "EMBEDDED_HTTP_ADDR", default_value = "0.0.0.0:8080")]
+    http_addr: String,
+
+    /// Events buffered by the `http` source
+    #[arg(long, default_value_t = 10000)]
+    http_buffer: usize,
+
+    /// Replay the source in timestamp order, timed by event time
+    #[arg(long)]
+    replay: bool,
+
+    /// Replay pacing relative to the original event gaps (e.g. 2.0);
+    /// replays as fast as possible when unset
+    #[arg(long, requires = "replay")]
+    replay_speed: Option<f64>,
+
+    /// Processed event sink: `stdout`, `timescaledb`, or a `.ndjson` or
+    /// `.parquet` path
+    #[arg(long, env = "EMBEDDED_EVENTS_OUT")]
+    events_out: Option<SinkSpec>,
+
+    /// Aggregate sink: `stdout`, `timescaledb`, or a `.ndjson` or
+    /// `.parquet` path
+    #[arg(long, env = "EMBEDDED_AGGREGATES_OUT")]
+    aggregates_out: Option<SinkSpec>,
+
+    /// TimescaleDB connection string for `timescaledb` sinks
+    #[arg(
+        long,
+        env = "DATABASE_URL",
+        default_value = "postgresql://localhost/llm_analytics"
+    )]
+    database_url: String,
+
+    /// Events per sink write
+    #[arg(long, env = "BATCH_SIZE", default_value_t = 1000)]
+    batch_size: usize,
+
+    /// YAML file of event transforms
+    #[arg(long, env = "TRANSFORMS_PATH")]
+    transforms: Option<PathBuf>,
+
+    /// Seconds of pipeline clock time between aggregate emissions
+    #[arg(long, default_value_t = 60)]
+    emit_interval_secs: i64,
+}
+
+#[derive(Debug, Clone)]
+enum SinkSpec {
+    Stdout,
+    Timescale,
+    Ndjson(PathBuf),
+    Parquet(PathBuf),
+}
+
+impl FromStr for SinkSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let path = PathBuf::from(s);
+        Ok(match s {
+            "stdout" | "-" => Self::Stdout,
+            "timescaledb" => Self::Timescale,
+            _ if path.extension().is_some_and(|ext| ext == "parquet") => Self::Parquet(path),
+            _ => Self::Ndjson(path),
+        })
+    }
+}
+
+#[cfg(feature = "parquet-sink")]
+trait Record: Serialize + Sync + llm_analytics_hub::pipeline::sink::ParquetRecord + 'static {}
+
+#[cfg(feature = "parquet-sink")]
+impl<T: Serialize + Sync + llm_analytics_hub::pipeline::sink::ParquetRecord + 'static> Record
+    for T
+{
+}
+
+#[cfg(not(feature = "parquet-sink"))]
+trait Record: Serialize + Sync + 'static {}
+
+#[cfg(not(feature = "parquet-sink"))]
+impl<T: Serialize + Sync + 'static> Record for T {}
+
+async fn open_sink<T: Record>(
+    spec: &SinkSpec,
+    database: &Option<Arc<Database>>,
+) -> Result<Box<dyn Sink<T>>>
+where
+    TimescaleSink: Sink<T>,
+{
+    let sink: Box<dyn Sink<T>> = match spec {
+        SinkSpec::Stdout => Box::new(NdjsonSink::stdout()),
+        SinkSpec::Ndjson(path) => Box::new(NdjsonSink::create(path).await?),
+        SinkSpec::Timescale => match database {
+            Some(database) => Box::new(TimescaleSink::new(database.clone())),
+            None => bail!("No database connection for the timescaledb sink"),
+        },
+        #[cfg(feature = "parquet-sink")]
+        SinkSpec::Parquet(path) => Box::new(
+            llm_analytics_hub::pipeline::sink::ParquetSink::<T>::create(path)?,
+        ),
+        #[cfg(not(feature = "parquet-sink"))]
+        SinkSpec::Parquet(path) => bail!(
+            "Cannot write {}: built without the parquet-sink feature",
+            path.display()
+        ),
+    };
+    Ok(sink)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Keep stdout free for the stdout sink
+    tracing_subscriber::fmt()
+        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .init();
+
+    let cli = Cli::parse();
+
+    let uses_database = [cli.events_out.as_ref(), cli.aggregates_out.as_ref()]
+        .into_iter()
+        .any(|spec| matches!(spec, Some(SinkSpec::Timescale)));
+    let database = if uses_database {
+        Some(Arc::new(Database::from_url(&cli.database_url).await?))
+    } else {
+        None
+    };
+
+    let mut source: Box<dyn Source> = match cli.source.as_str() {
+        "stdin" | "-" => Box::new(NdjsonSource::stdin()),
+        "http" => {
+            let (buffer, source) = HttpPushSource::new(cli.http_buffer);
+            let listener = tokio::net::TcpListener::bind(&cli.http_addr).await?;
+            info!("Accepting events on {}", cli.http_addr);
+            tokio::spawn(async move {
+                // Dropping the router on shutdown ends the source
+                if let Err(e) = axum::serve(listener, buffer.router())
+                    .with_graceful_shutdown(shutdown_signal())
+                    .await
+                {
+                    tracing::error!("HTTP source failed: {}", e);
+                }
+            });
+            Box::new(source)
+        }
+        path => Box::new(NdjsonSource::open(path).await?),
+    };
+
+    let clock = if cli.replay {
+        let replay = ReplaySource::new(source, cli.replay_speed).await?;
+        let clock = replay.clock();
+        source = Box::new(replay);
+        clock
+    } else {
+        Clock::System
+    };
+
+    let config = EmbeddedConfig {
+        pipeline: PipelineConfig {
+            batch_size: cli.batch_size,
+            transforms_path: cli.transforms.clone(),
+            ..PipelineConfig::default()
+        },
+        emit_interval: Duration::seconds(cli.emit_interval_secs),
+        ..EmbeddedConfig::default()
+    };
+    let mut pipeline = EmbeddedPipeline::new(config, clock).await?;
+    if let Some(spec) = &cli.events_out {
+        pipeline = pipeline.with_event_sink(open_sink::<AnalyticsEvent>(spec, &database).await?);
+    }
+    if let Some(spec) = &cli.aggregates_out {
+        pipeline =
+            pipeline.with_aggregate_sink(open_sink::<AggregatedMetric>(spec, &database).await?);
+    }
+
+    let stats = pipeline.run(source.as_mut()).await?;
+    info!(
+        "Read {} events, wrote {} events and {} aggregates ({} dropped, {} anomalies)",
+        stats.events_read,
+        stats.events_written,
+        stats.aggregates_written,
+        stats.events_dropped,
+        stats.anomalies_detected
+    );
+    Ok(())
+}
+
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        signal::ctrl_c()
+            .await
+            .expect("failed to install Ctrl+C handler");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        signal::unix::signal(signal::unix::SignalKind::terminate())
+            .expect("failed to install signal handler")
+            .recv()
+            .await;
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
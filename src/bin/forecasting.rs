@@ -2,23 +2,28 @@
 //!
 //! Provides time-series forecasting for metrics using statistical methods.
 //! Features:
-//! - Moving average forecasting
-//! - Exponential smoothing
-//! - Trend analysis
-//! - Seasonal decomposition
+//! - Moving average, exponential smoothing, Holt-Winters and ARIMA forecasting
+//! - Rolling-origin backtesting with per-metric model selection
 //! - Prediction intervals
+//! - Live forecast error tracking
 
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use llm_analytics_hub::analytics::backtest::{self, BacktestConfig, ForecastMethod};
+use llm_analytics_hub::analytics::stats;
+use llm_analytics_hub::database::queries;
 use prometheus::{register_counter_vec, register_histogram_vec, CounterVec, HistogramVec};
 use serde::{Deserialize, Serialize};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
-use std::collections::VecDeque;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::signal;
 use tokio::time::{interval, Duration};
-use tracing::{error, info};
+use tracing::{error, info, warn};
+
+/// Nominal coverage of forecast prediction intervals
+const CONFIDENCE_LEVEL: f64 = 0.95;
 
 /// Prometheus metrics
 struct Metrics {
@@ -55,6 +60,7 @@ struct Config {
     database_url: String,
     forecast_interval_secs: u64,
     forecast_horizon_hours: i64,
+    backtest_interval_secs: u64,
 }
 
 impl Config {
@@ -71,6 +77,10 @@ impl Config {
                 .unwrap_or_else(|_| "24".to_string())
                 .parse()
                 .expect("Invalid FORECAST_HORIZON_HOURS"),
+            backtest_interval_secs: std::env::var("BACKTEST_INTERVAL_SECS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .expect("Invalid BACKTEST_INTERVAL_SECS"),
         }
     }
 }
@@ -97,108 +107,171 @@ struct ForecastResult {
 
 /// Forecasting engine
 struct ForecastingEngine {
-    forecasts: Arc<DashMap<String, Vec<ForecastResult>>>,
+    /// Metric name -> pending forecasts keyed by target timestamp
+    forecasts: Arc<DashMap<String, HashMap<DateTime<Utc>, ForecastResult>>>,
+    /// Metric name -> method selected by the latest backtest
+    selected_methods: Arc<DashMap<String, ForecastMethod>>,
 }
 
 impl ForecastingEngine {
     fn new() -> Self {
         Self {
             forecasts: Arc::new(DashMap::new()),
+            selected_methods: Arc::new(DashMap::new()),
         }
     }
 
-    /// Simple moving average forecast
-    fn moving_average_forecast(
-        &self,
-        metric_name: &str,
-        data: &[TimeSeriesPoint],
-        horizon_points: usize,
-        window_size: usize,
-    ) -> Vec<ForecastResult> {
-        if data.len() < window_size {
-            return Vec::new();
-        }
-
-        let mut forecasts = Vec::new();
-
-        // Calculate moving average
-        let recent_values: Vec<f64> = data.iter().rev().take(window_size).map(|p| p.value).collect();
-        let ma: f64 = recent_values.iter().sum::<f64>() / recent_values.len() as f64;
+    /// Get distinct metric names with recent data
+    async fn metric_names(&self, pool: &PgPool) -> Vec<String> {
+        sqlx::query_scalar!(
+            r#"
+            SELECT DISTINCT metric_name
+            FROM aggregated_metrics
+            WHERE window_start > NOW() - INTERVAL '7 days'
+            "#
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+    }
 
-        // Calculate standard deviation for confidence intervals
-        let mean = ma;
-        let variance: f64 = recent_values.iter().map(|v| (v - mean).powi(2)).sum::<f64>()
-            / recent_values.len() as f64;
-        let std_dev = variance.sqrt();
+    /// Fetch historical data for a metric
+    async fn fetch_history(&self, pool: &PgPool, metric_name: &str) -> Vec<TimeSeriesPoint> {
+        sqlx::query_as!(
+            TimeSeriesPoint,
+            r#"
+            SELECT window_start as timestamp, metric_name, mean as value
+            FROM aggregated_metrics
+            WHERE metric_name = $1
+            AND window_start > NOW() - INTERVAL '7 days'
+            ORDER BY window_start ASC
+            "#,
+            metric_name
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+    }
 
-        // Generate forecasts
-        let last_timestamp = data.last().unwrap().timestamp;
-        for i in 1..=horizon_points {
-            let forecast_time = last_timestamp + chrono::Duration::hours(i as i64);
+    /// Backtest all methods for every metric and persist the selection
+    async fn run_backtests(&self, pool: &PgPool) -> anyhow::Result<()> {
+        info!("Running forecast backtests");
 
-            forecasts.push(ForecastResult {
-                metric_name: metric_name.to_string(),
-                timestamp: forecast_time,
-                predicted_value: ma,
-                lower_bound: ma - 2.0 * std_dev,
-                upper_bound: ma + 2.0 * std_dev,
-                confidence: 0.95,
-                method: "moving_average".to_string(),
-            });
+        for metric_name in self.metric_names(pool).await {
+            let data = self.fetch_history(pool, &metric_name).await;
+            if let Err(e) = self.backtest_metric(pool, &metric_name, &data).await {
+                warn!(metric = %metric_name, "Backtest skipped: {}", e);
+            }
         }
 
-        forecasts
+        Ok(())
     }
 
-    /// Exponential smoothing forecast
-    fn exponential_smoothing_forecast(
+    async fn backtest_metric(
         &self,
+        pool: &PgPool,
         metric_name: &str,
         data: &[TimeSeriesPoint],
-        horizon_points: usize,
-        alpha: f64,
-    ) -> Vec<ForecastResult> {
-        if data.is_empty() {
-            return Vec::new();
-        }
-
-        // Initialize with first value
-        let mut smoothed = data[0].value;
+    ) -> anyhow::Result<ForecastMethod> {
+        let values: Vec<f64> = data.iter().map(|p| p.value).collect();
+        let config = BacktestConfig {
+            seasonal_period: seasonal_period(data),
+            confidence_level: CONFIDENCE_LEVEL,
+            ..Default::default()
+        };
+
+        let selection = backtest::select_model(metric_name, &values, &config)?;
+        queries::store_model_selection(pool, &selection).await?;
+
+        info!(
+            metric = %metric_name,
+            method = %selection.selected,
+            smape = selection.selected_result().map(|r| r.smape),
+            "Forecast method selected"
+        );
+
+        self.selected_methods
+            .insert(metric_name.to_string(), selection.selected);
+        Ok(selection.selected)
+    }
 
-        // Apply exponential smoothing
-        for point in data.iter().skip(1) {
-            smoothed = alpha * point.value + (1.0 - alpha) * smoothed;
+    /// Method for a metric: cached selection, persisted selection, or a fresh backtest
+    async fn selected_method(
+        &self,
+        pool: &PgPool,
+        metric_name: &str,
+        data: &[TimeSeriesPoint],
+    ) -> ForecastMethod {
+        if let Some(method) = self.selected_methods.get(metric_name) {
+            return *method;
         }
 
-        // Calculate forecast variance
-        let errors: Vec<f64> = data
-            .iter()
-            .skip(1)
-            .map(|p| p.value - smoothed)
-            .collect();
+        if let Ok(Some(method)) = queries::get_selected_forecast_method(pool, metric_name).await {
+            self.selected_methods.insert(metric_name.to_string(), method);
+            return method;
+        }
 
-        let error_variance: f64 = errors.iter().map(|e| e.powi(2)).sum::<f64>() / errors.len() as f64;
-        let error_std = error_variance.sqrt();
+        self.backtest_metric(pool, metric_name, data)
+            .await
+            .unwrap_or(ForecastMethod::MovingAverage)
+    }
 
-        // Generate forecasts
-        let mut forecasts = Vec::new();
-        let last_timestamp = data.last().unwrap().timestamp;
+    /// Score pending forecasts whose target time now has an actual value
+    fn record_forecast_errors(
+        &self,
+        metric_name: &str,
+        data: &[TimeSeriesPoint],
+        metrics: &Arc<Metrics>,
+    ) {
+        let Some(last_timestamp) = data.last().map(|p| p.timestamp) else {
+            return;
+        };
+
+        if let Some(mut pending) = self.forecasts.get_mut(metric_name) {
+            for point in data {
+                if let Some(forecast) = pending.get(&point.timestamp) {
+                    metrics
+                        .forecast_error
+                        .with_label_values(&[metric_name])
+                        .observe((point.value - forecast.predicted_value).abs());
+                }
+            }
 
-        for i in 1..=horizon_points {
-            let forecast_time = last_timestamp + chrono::Duration::hours(i as i64);
+            // Forecasts at or before the latest actual are either scored or unmatched
+            pending.retain(|timestamp, _| *timestamp > last_timestamp);
+        }
+    }
 
-            forecasts.push(ForecastResult {
+    /// Forecast a metric with the given method
+    fn forecast(
+        &self,
+        metric_name: &str,
+        data: &[TimeSeriesPoint],
+        method: ForecastMethod,
+        horizon: chrono::Duration,
+    ) -> anyhow::Result<Vec<ForecastResult>> {
+        let values: Vec<f64> = data.iter().map(|p| p.value).collect();
+        let interval = stats::sampling_interval(data.iter().map(|p| &p.timestamp));
+        let steps = (horizon.num_seconds() / interval.num_seconds().max(1)).max(1) as usize;
+        let z = stats::normal_z(CONFIDENCE_LEVEL);
+        let last_timestamp = data.last().map(|p| p.timestamp).unwrap_or_else(Utc::now);
+
+        let forecasts = method
+            .forecast(&values, steps, seasonal_period(data))?
+            .into_iter()
+            .enumerate()
+            .map(|(i, step)| ForecastResult {
                 metric_name: metric_name.to_string(),
-                timestamp: forecast_time,
-                predicted_value: smoothed,
-                lower_bound: smoothed - 2.0 * error_std,
-                upper_bound: smoothed + 2.0 * error_std,
-                confidence: 0.95,
-                method: "exponential_smoothing".to_string(),
-            });
-        }
+                timestamp: last_timestamp + interval * (i as i32 + 1),
+                predicted_value: step.value,
+                lower_bound: step.value - z * step.std_error,
+                upper_bound: step.value + z * step.std_error,
+                confidence: CONFIDENCE_LEVEL,
+                method: method.as_str().to_string(),
+            })
+            .collect();
 
-        forecasts
+        Ok(forecasts)
     }
 
     async fn generate_forecasts(
@@ -209,51 +282,33 @@ impl ForecastingEngine {
     ) -> anyhow::Result<()> {
         info!("Generating forecasts");
 
-        // Get distinct metric names
-        let metric_names: Vec<String> = sqlx::query_scalar!(
-            r#"
-            SELECT DISTINCT metric_name
-            FROM aggregated_metrics
-            WHERE window_start > NOW() - INTERVAL '7 days'
-            "#
-        )
-        .fetch_all(pool)
-        .await
-        .unwrap_or_default();
-
-        for metric_name in metric_names {
-            let timer = metrics
-                .forecast_duration
-                .with_label_values(&["moving_average"])
-                .start_timer();
-
-            // Fetch historical data
-            let data: Vec<TimeSeriesPoint> = sqlx::query_as!(
-                TimeSeriesPoint,
-                r#"
-                SELECT window_start as timestamp, metric_name, mean as value
-                FROM aggregated_metrics
-                WHERE metric_name = $1
-                AND window_start > NOW() - INTERVAL '7 days'
-                ORDER BY window_start ASC
-                "#,
-                &metric_name
-            )
-            .fetch_all(pool)
-            .await
-            .unwrap_or_default();
+        for metric_name in self.metric_names(pool).await {
+            let data = self.fetch_history(pool, &metric_name).await;
 
             if data.len() < 10 {
                 continue; // Not enough data
             }
 
-            // Generate forecasts using moving average
-            let forecasts = self.moving_average_forecast(
+            self.record_forecast_errors(&metric_name, &data, metrics);
+
+            let method = self.selected_method(pool, &metric_name, &data).await;
+            let timer = metrics
+                .forecast_duration
+                .with_label_values(&[method.as_str()])
+                .start_timer();
+
+            let forecasts = match self.forecast(
                 &metric_name,
                 &data,
-                horizon_hours as usize,
-                24, // 24-hour window
-            );
+                method,
+                chrono::Duration::hours(horizon_hours),
+            ) {
+                Ok(forecasts) => forecasts,
+                Err(e) => {
+                    warn!(metric = %metric_name, method = %method, "Forecast failed: {}", e);
+                    continue;
+                }
+            };
 
             // Store forecasts
             for forecast in &forecasts {
@@ -285,12 +340,15 @@ impl ForecastingEngine {
                 .await?;
             }
 
-            self.forecasts.insert(metric_name.clone(), forecasts);
+            self.forecasts
+                .entry(metric_name.clone())
+                .or_default()
+                .extend(forecasts.into_iter().map(|f| (f.timestamp, f)));
 
             timer.observe_duration();
             metrics
                 .forecasts_generated
-                .with_label_values(&[&metric_name, "moving_average"])
+                .with_label_values(&[&metric_name, method.as_str()])
                 .inc();
         }
 
@@ -299,6 +357,13 @@ impl ForecastingEngine {
     }
 }
 
+/// Daily season length in samples, if the history covers at least two days
+fn seasonal_period(data: &[TimeSeriesPoint]) -> Option<usize> {
+    let interval = stats::sampling_interval(data.iter().map(|p| &p.timestamp));
+    let period = (chrono::Duration::days(1).num_seconds() / interval.num_seconds().max(1)) as usize;
+    (period >= 2 && data.len() >= 2 * period + 2).then_some(period)
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Initialize tracing
@@ -329,6 +394,21 @@ async fn main() -> anyhow::Result<()> {
     // Create forecasting engine
     let engine = Arc::new(ForecastingEngine::new());
 
+    // Spawn backtest task (re-selects the forecast method per metric)
+    let backtest_pool = db_pool.clone();
+    let backtest_engine = engine.clone();
+    let backtest_interval = config.backtest_interval_secs;
+
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(backtest_interval));
+        loop {
+            interval.tick().await;
+            if let Err(e) = backtest_engine.run_backtests(&backtest_pool).await {
+                error!("Failed to run forecast backtests: {}", e);
+            }
+        }
+    });
+
     // Spawn forecast generation task
     let forecast_pool = db_pool.clone();
     let forecast_engine = engine.clone();
@@ -8,16 +8,21 @@
 //! - Configure ACLs for security
 //! - Verify cluster health
 //! - Performance testing
+//! - Dead-letter queue triage, replay and purge
 
 use anyhow::{anyhow, Context, Result};
-use clap::{Parser, Subcommand};
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand};
 use colored::Colorize;
+use llm_analytics_hub::pipeline::dlq::{self, DlqFilter, DlqRecord, DlqReplayer, ErrorClass, LedgerEntry};
 use rdkafka::admin::{AdminClient, AdminOptions, NewTopic, TopicReplication};
 use rdkafka::client::DefaultClientContext;
 use rdkafka::config::ClientConfig;
 use rdkafka::consumer::{BaseConsumer, Consumer};
 use rdkafka::metadata::Metadata;
+use rdkafka::producer::FutureProducer;
 use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::Duration;
 use tracing::{info, warn};
 
@@ -78,6 +83,90 @@ enum Commands {
         #[arg(short, long, default_value = "1024")]
         size: usize,
     },
+
+    /// Inspect and replay dead-letter queue records
+    Dlq {
+        /// Dead-letter queue topic
+        #[arg(long, default_value = "llm-analytics-events-dlq")]
+        topic: String,
+
+        #[command(subcommand)]
+        command: DlqCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum DlqCommand {
+    /// List records grouped by error class
+    List {
+        /// List the individual records of this class
+        #[arg(long)]
+        class: Option<ErrorClass>,
+
+        /// Include replayed and purged records
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Show a record's failure details and payload
+    Show {
+        /// Record ID (`<partition>-<offset>`)
+        id: String,
+    },
+
+    /// Replay records to their original topic
+    Replay {
+        #[command(flatten)]
+        filter: DlqFilterArgs,
+
+        /// Replay to this topic instead of the original one
+        #[arg(long)]
+        to: Option<String>,
+
+        /// JSON merge patch (RFC 7396) applied to each payload
+        #[arg(long)]
+        patch: Option<PathBuf>,
+    },
+
+    /// Purge records so they are no longer listed or replayed
+    Purge {
+        #[command(flatten)]
+        filter: DlqFilterArgs,
+
+        /// Purge without confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+}
+
+#[derive(Args)]
+struct DlqFilterArgs {
+    /// Only records of this error class
+    #[arg(long)]
+    class: Option<ErrorClass>,
+
+    /// Only records that failed in this stage
+    #[arg(long)]
+    stage: Option<String>,
+
+    /// Only these record IDs
+    #[arg(long = "id")]
+    ids: Vec<String>,
+
+    /// Only records that failed at or after this time (RFC 3339)
+    #[arg(long)]
+    since: Option<DateTime<Utc>>,
+}
+
+impl From<DlqFilterArgs> for DlqFilter {
+    fn from(args: DlqFilterArgs) -> Self {
+        Self {
+            error_class: args.class,
+            stage: args.stage,
+            ids: args.ids,
+            failed_after: args.since,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -528,6 +617,216 @@ async fn verify_cluster(bootstrap_servers: &str) -> Result<()> {
     Ok(())
 }
 
+fn create_dlq_consumer(bootstrap_servers: &str) -> Result<BaseConsumer> {
+    let consumer: BaseConsumer = ClientConfig::new()
+        .set("bootstrap.servers", bootstrap_servers)
+        .set("group.id", "kafka-admin-dlq")
+        .set("enable.auto.commit", "false")
+        .create()
+        .context("Failed to create consumer")?;
+
+    Ok(consumer)
+}
+
+/// Read the DLQ topic and its ledger
+fn load_dlq(bootstrap_servers: &str, topic: &str) -> Result<(Vec<DlqRecord>, HashMap<String, LedgerEntry>)> {
+    let consumer = create_dlq_consumer(bootstrap_servers)?;
+    let timeout = Duration::from_secs(10);
+
+    let records = dlq::read_topic(&consumer, topic, timeout)?
+        .iter()
+        .map(DlqRecord::from_message)
+        .collect::<Result<Vec<_>>>()?;
+    let ledger = dlq::read_ledger(&consumer, topic, timeout)?;
+
+    Ok((records, ledger))
+}
+
+/// Create the compacted ledger topic if it does not exist
+async fn ensure_ledger_topic(bootstrap_servers: &str, topic: &str) -> Result<()> {
+    let admin_client = create_admin_client(bootstrap_servers)?;
+    let ledger = dlq::ledger_topic(topic);
+    let new_topic = NewTopic::new(&ledger, 1, TopicReplication::Fixed(3))
+        .set("cleanup.policy", "compact")
+        .set("min.insync.replicas", "2");
+
+    let results = admin_client
+        .create_topics(&[new_topic], &AdminOptions::default())
+        .await
+        .context("Failed to create ledger topic")?;
+    for result in results {
+        if let Err((topic_name, error)) = result {
+            if !error.to_string().contains("already exists") {
+                return Err(anyhow!("Failed to create ledger topic {}: {}", topic_name, error));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn create_dlq_replayer(bootstrap_servers: &str, topic: &str) -> Result<DlqReplayer> {
+    let producer: FutureProducer = ClientConfig::new()
+        .set("bootstrap.servers", bootstrap_servers)
+        .set("client.id", "kafka-admin-dlq")
+        .set("enable.idempotence", "true")
+        .create()
+        .context("Failed to create producer")?;
+
+    Ok(DlqReplayer::new(producer, topic))
+}
+
+async fn dlq_list(bootstrap_servers: &str, topic: &str, class: Option<ErrorClass>, all: bool) -> Result<()> {
+    log_info(&format!("Reading dead-letter queue: {}", topic));
+    println!();
+
+    let (records, ledger) = load_dlq(bootstrap_servers, topic)?;
+    let pending: Vec<DlqRecord> = records
+        .into_iter()
+        .filter(|r| all || !ledger.contains_key(&r.id))
+        .collect();
+
+    if let Some(class) = class {
+        let records: Vec<_> = pending.iter().filter(|r| r.metadata.error_class == class).collect();
+        log_success(&format!("{} {} records:", records.len(), class.as_str()));
+        println!();
+        for record in records {
+            let status = ledger
+                .get(&record.id)
+                .map(|e| format!(" [{:?}]", e.disposition).to_lowercase())
+                .unwrap_or_default();
+            println!("  {} {} {}{}",
+                     record.id.bold(),
+                     record.metadata.failed_at.to_rfc3339(),
+                     record.metadata.error,
+                     status.yellow());
+        }
+        return Ok(());
+    }
+
+    log_success(&format!("{} records:", pending.len()));
+    println!();
+    for summary in dlq::summarize(&pending) {
+        println!("  {} {}", summary.error_class.as_str().bold(), summary.count.to_string().green());
+        println!("    Stages: {}", summary.stages.into_iter().collect::<Vec<_>>().join(", "));
+        println!("    Topics: {}", summary.original_topics.into_iter().collect::<Vec<_>>().join(", "));
+        println!("    Failed: {} .. {}", summary.first_failed_at.to_rfc3339(), summary.last_failed_at.to_rfc3339());
+        println!("    Top error: {}", summary.top_error);
+        println!();
+    }
+
+    Ok(())
+}
+
+async fn dlq_show(bootstrap_servers: &str, topic: &str, id: &str) -> Result<()> {
+    let (records, ledger) = load_dlq(bootstrap_servers, topic)?;
+    let record = records
+        .iter()
+        .find(|r| r.id == id)
+        .ok_or_else(|| anyhow!("Record not found: {}", id))?;
+    let metadata = &record.metadata;
+
+    log_success(&format!("Record {}:", record.id));
+    println!();
+    println!("  {}: {}", "Error class".bold(), metadata.error_class.as_str());
+    println!("  {}: {}", "Error".bold(), metadata.error);
+    println!("  {}: {}", "Stage".bold(), metadata.stage);
+    println!("  {}: {}", "Attempts".bold(), metadata.attempts);
+    println!("  {}: {}/{}@{}", "Original".bold(),
+             metadata.original_topic, metadata.original_partition, metadata.original_offset);
+    println!("  {}: {}", "Failed at".bold(), metadata.failed_at.to_rfc3339());
+    if let Some(key) = &record.key {
+        println!("  {}: {}", "Key".bold(), key);
+    }
+    if let Some(entry) = ledger.get(&record.id) {
+        println!("  {}: {:?} at {}", "Status".bold(), entry.disposition, entry.at.to_rfc3339());
+    }
+
+    println!();
+    println!("  {}:", "Payload".bold());
+    match serde_json::from_slice::<serde_json::Value>(&record.payload) {
+        Ok(value) => println!("{}", serde_json::to_string_pretty(&value)?),
+        Err(_) => println!("{}", String::from_utf8_lossy(&record.payload)),
+    }
+
+    Ok(())
+}
+
+async fn dlq_replay(
+    bootstrap_servers: &str,
+    topic: &str,
+    filter: DlqFilter,
+    to: Option<String>,
+    patch: Option<PathBuf>,
+    dry_run: bool,
+) -> Result<()> {
+    let patch: Option<serde_json::Value> = match &patch {
+        Some(path) => Some(serde_json::from_str(&std::fs::read_to_string(path)?)
+            .with_context(|| format!("Invalid merge patch: {}", path.display()))?),
+        None => None,
+    };
+
+    let (records, ledger) = load_dlq(bootstrap_servers, topic)?;
+    let selected: Vec<_> = records
+        .iter()
+        .filter(|r| filter.matches(r) && !ledger.contains_key(&r.id))
+        .collect();
+    log_info(&format!("{} records to replay", selected.len()));
+
+    if dry_run {
+        for record in &selected {
+            // Surface records the patch cannot apply to
+            dlq::replay_payload(record, patch.as_ref())?;
+            let target = to.as_deref().unwrap_or(&record.metadata.original_topic);
+            log_warn(&format!("  [DRY RUN] Would replay {} to {}", record.id, target));
+        }
+        return Ok(());
+    }
+
+    ensure_ledger_topic(bootstrap_servers, topic).await?;
+    let replayer = create_dlq_replayer(bootstrap_servers, topic)?;
+    let report = replayer
+        .replay(&records, &ledger, &filter, patch.as_ref(), to.as_deref())
+        .await;
+
+    log_success(&format!("✓ Replayed {} records ({} already handled, {} filtered)",
+                         report.replayed, report.skipped, report.filtered));
+    for (id, error) in &report.failed {
+        log_error(&format!("  ✗ {}: {}", id, error));
+    }
+    if !report.failed.is_empty() {
+        return Err(anyhow!("{} records failed to replay", report.failed.len()));
+    }
+
+    Ok(())
+}
+
+async fn dlq_purge(bootstrap_servers: &str, topic: &str, filter: DlqFilter, force: bool, dry_run: bool) -> Result<()> {
+    let (records, ledger) = load_dlq(bootstrap_servers, topic)?;
+    let ids: Vec<String> = records
+        .iter()
+        .filter(|r| filter.matches(r) && !ledger.contains_key(&r.id))
+        .map(|r| r.id.clone())
+        .collect();
+    log_info(&format!("{} records to purge", ids.len()));
+
+    if dry_run || !force {
+        for id in &ids {
+            log_warn(&format!("  Would purge {}", id));
+        }
+        if !dry_run {
+            log_warn("Re-run with --force to purge");
+        }
+        return Ok(());
+    }
+
+    ensure_ledger_topic(bootstrap_servers, topic).await?;
+    let purged = create_dlq_replayer(bootstrap_servers, topic)?.purge(&ids).await?;
+    log_success(&format!("✓ Purged {} records", purged));
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     tracing_subscriber::fmt()
@@ -565,6 +864,20 @@ async fn main() -> Result<()> {
             log_error("Performance testing not yet implemented");
             log_warn("Use kafka-producer-perf-test and kafka-consumer-perf-test");
         }
+        Commands::Dlq { topic, command } => match command {
+            DlqCommand::List { class, all } => {
+                dlq_list(&cli.bootstrap_servers, &topic, class, all).await?;
+            }
+            DlqCommand::Show { id } => {
+                dlq_show(&cli.bootstrap_servers, &topic, &id).await?;
+            }
+            DlqCommand::Replay { filter, to, patch } => {
+                dlq_replay(&cli.bootstrap_servers, &topic, filter.into(), to, patch, cli.dry_run).await?;
+            }
+            DlqCommand::Purge { filter, force } => {
+                dlq_purge(&cli.bootstrap_servers, &topic, filter.into(), force, cli.dry_run).await?;
+            }
+        },
     }
 
     Ok(())
@@ -9,7 +9,7 @@ use colored::Colorize;
 use tracing_subscriber::EnvFilter;
 
 use llm_analytics_hub::cli::{
-    BenchmarkCommand, DatabaseCommand, DeployCommand, HealthCommand, KafkaCommand, RedisCommand, UtilsCommand, ValidateCommand,
+    BenchmarkCommand, ChargebackCommand, DatabaseCommand, DeployCommand, ExperimentCommand, HealthCommand, KafkaCommand, RedisCommand, UtilsCommand, ValidateCommand,
 };
 use llm_analytics_hub::common::ExecutionContext;
 
@@ -87,6 +87,18 @@ enum Commands {
         #[command(subcommand)]
         command: BenchmarkCommand,
     },
+
+    /// A/B and canary experiment verdicts
+    Experiment {
+        #[command(subcommand)]
+        command: ExperimentCommand,
+    },
+
+    /// Cost centre chargeback reports
+    Chargeback {
+        #[command(subcommand)]
+        command: ChargebackCommand,
+    },
 }
 
 #[tokio::main]
@@ -125,6 +137,8 @@ async fn main() -> Result<()> {
         Commands::Health { command } => command.execute(&ctx).await,
         Commands::Utils { command } => command.execute(&ctx).await,
         Commands::Benchmark { command } => command.execute().await,
+        Commands::Experiment { command } => command.execute(&ctx).await,
+        Commands::Chargeback { command } => command.execute(&ctx).await,
     };
 
     // Handle result
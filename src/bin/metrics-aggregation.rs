@@ -4,25 +4,57 @@
 //! Features:
 //! - Kafka consumer with consumer group
 //! - Time-window aggregations (1m, 5m, 15m, 1h)
+//! - Running event counts per event type stored as counter aggregates
+//!   (`<EventType>_events_total`), with their per-second rate
+//! - Event-time windows closed by per-partition watermarks, with late events
+//!   upserting stored windows (`ALLOWED_LATENESS_SECS`) and events past the
+//!   lateness sent to `LATE_EVENTS_TOPIC`
 //! - TimescaleDB batch writes
 //! - Redis caching for intermediate state
 //! - Prometheus metrics
+//! - SLO error-budget tracking with burn-rate alerts (`SLO_DEFINITIONS_PATH`)
+//! - A/B and canary model comparison verdicts (`EXPERIMENT_DEFINITIONS_PATH`)
+//! - Session reconstruction from `session_id` tags and Memory-Graph lineage
+//! - Budget burn-rate forecasts with forecasted-overrun alerts, budgets synced
+//!   from LLM-CostOps (`COSTOPS_BUDGETS`, `BUDGET_TEAMS`)
+//! - Monthly chargeback reports per cost centre (`CHARGEBACK_RULES_PATH`),
+//!   reconciled against LLM-CostOps totals (`COSTOPS_RECONCILIATION`)
+//! - Window aggregates published to `AGGREGATES_TOPIC`, optionally exactly-once
+//!   (`PROCESSING_GUARANTEE=exactly_once`)
+//! - Revoked partitions release their windows' offsets on rebalance
 //! - Graceful shutdown with offset commit
 
-use chrono::{DateTime, Duration as ChronoDuration, Utc};
-use dashmap::DashMap;
+use chrono::{Duration as ChronoDuration, Utc};
+use llm_analytics_hub::adapters::costops::{
+    CostOpsAdapter, CostOpsConfig, CostSummaryQuery, Granularity,
+};
+use llm_analytics_hub::adapters::memory_graph::{MemoryGraphAdapter, MemoryGraphConfig};
+use llm_analytics_hub::adapters::EcosystemAdapter;
+use llm_analytics_hub::analytics::{
+    AggregationEngine, AllocationRule, AnalyticsConfig, BillingMonth, Budget, BurnRateEngine,
+    ChargebackEngine, Emission, EventTimeWindows, ExperimentDefinition, ExperimentEngine,
+    LateDataPolicy, SessionConfig, SessionEngine, SloDefinition, SloEngine, WindowingConfig,
+};
+use llm_analytics_hub::database::copy::{CopyConfig, CopyWriter};
+use llm_analytics_hub::database::queries;
+use llm_analytics_hub::models::metrics::{CounterMetric, MetricValues, WindowFunction};
+use llm_analytics_hub::pipeline::sampling::sample_weight;
+use llm_analytics_hub::pipeline::transactions::{self, ProcessingGuarantee};
+use llm_analytics_hub::pipeline::{StageCommitter, TransactionConfig};
 use llm_analytics_hub::{AggregatedMetric, AnalyticsEvent, StatisticalMeasures, TimeWindow};
+use parking_lot::Mutex;
 use prometheus::{
-    register_counter_vec, register_histogram_vec, register_int_gauge, CounterVec, Encoder,
-    HistogramVec, IntGauge, TextEncoder,
+    register_counter_vec, register_gauge_vec, register_histogram_vec, register_int_gauge,
+    CounterVec, Encoder, GaugeVec, HistogramVec, IntGauge, TextEncoder,
 };
-use rdkafka::consumer::{CommitMode, Consumer, StreamConsumer};
-use rdkafka::{ClientConfig, Message};
+use rdkafka::consumer::{Consumer, ConsumerContext, Rebalance, StreamConsumer};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{ClientConfig, ClientContext, Message};
 use redis::aio::ConnectionManager;
 use serde::{Deserialize, Serialize};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::{PgPool, Postgres};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use tokio::signal;
 use tokio::sync::RwLock;
@@ -45,6 +77,9 @@ struct Metrics {
     db_write_duration: HistogramVec,
     kafka_lag: IntGauge,
     aggregation_duration: HistogramVec,
+    late_events: CounterVec,
+    windows_open: IntGauge,
+    event_rate: GaugeVec,
 }
 
 impl Metrics {
@@ -76,6 +111,20 @@ impl Metrics {
                 "Metrics aggregation duration",
                 &["window"]
             )?,
+            late_events: register_counter_vec!(
+                "llm_metrics_late_events_total",
+                "Events arriving after their window's allowed lateness",
+                &["action"]
+            )?,
+            windows_open: register_int_gauge!(
+                "llm_metrics_windows_open",
+                "Aggregation windows held in memory"
+            )?,
+            event_rate: register_gauge_vec!(
+                "llm_metrics_event_rate",
+                "Consumed events per second over the last minute",
+                &["event_type"]
+            )?,
         })
     }
 }
@@ -89,6 +138,28 @@ struct Config {
     database_url: String,
     redis_url: String,
     aggregation_interval_secs: u64,
+    slo_definitions_path: Option<String>,
+    slo_evaluation_interval_secs: u64,
+    self_monitoring_topic: String,
+    experiment_definitions_path: Option<String>,
+    experiment_evaluation_interval_secs: u64,
+    session_idle_timeout_secs: i64,
+    session_flush_interval_secs: u64,
+    memory_graph_sessions: bool,
+    costops_budgets: bool,
+    budget_teams: Vec<String>,
+    budget_sync_interval_secs: u64,
+    chargeback_rules_path: Option<String>,
+    chargeback_interval_secs: u64,
+    costops_reconciliation: bool,
+    aggregates_topic: String,
+    processing_guarantee: ProcessingGuarantee,
+    transactional_id: String,
+    max_out_of_orderness_secs: i64,
+    allowed_lateness_secs: i64,
+    early_firing_secs: Option<i64>,
+    late_data_policy: LateDataPolicy,
+    late_events_topic: String,
 }
 
 impl Config {
@@ -108,19 +179,110 @@ impl Config {
                 .unwrap_or_else(|_| "60".to_string())
                 .parse()
                 .expect("Invalid AGGREGATION_INTERVAL_SECS"),
+            slo_definitions_path: std::env::var("SLO_DEFINITIONS_PATH").ok(),
+            slo_evaluation_interval_secs: std::env::var("SLO_EVALUATION_INTERVAL_SECS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .expect("Invalid SLO_EVALUATION_INTERVAL_SECS"),
+            self_monitoring_topic: std::env::var("SELF_MONITORING_TOPIC")
+                .unwrap_or_else(|_| "llm-events".to_string()),
+            experiment_definitions_path: std::env::var("EXPERIMENT_DEFINITIONS_PATH").ok(),
+            experiment_evaluation_interval_secs: std::env::var(
+                "EXPERIMENT_EVALUATION_INTERVAL_SECS",
+            )
+            .unwrap_or_else(|_| "60".to_string())
+            .parse()
+            .expect("Invalid EXPERIMENT_EVALUATION_INTERVAL_SECS"),
+            session_idle_timeout_secs: std::env::var("SESSION_IDLE_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "1800".to_string())
+                .parse()
+                .expect("Invalid SESSION_IDLE_TIMEOUT_SECS"),
+            session_flush_interval_secs: std::env::var("SESSION_FLUSH_INTERVAL_SECS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .expect("Invalid SESSION_FLUSH_INTERVAL_SECS"),
+            memory_graph_sessions: std::env::var("MEMORY_GRAPH_SESSIONS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            costops_budgets: std::env::var("COSTOPS_BUDGETS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            budget_teams: std::env::var("BUDGET_TEAMS")
+                .map(|v| {
+                    v.split(',')
+                        .map(str::trim)
+                        .filter(|team| !team.is_empty())
+                        .map(String::from)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            budget_sync_interval_secs: std::env::var("BUDGET_SYNC_INTERVAL_SECS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .expect("Invalid BUDGET_SYNC_INTERVAL_SECS"),
+            chargeback_rules_path: std::env::var("CHARGEBACK_RULES_PATH").ok(),
+            chargeback_interval_secs: std::env::var("CHARGEBACK_INTERVAL_SECS")
+                .unwrap_or_else(|_| "3600".to_string())
+                .parse()
+                .expect("Invalid CHARGEBACK_INTERVAL_SECS"),
+            costops_reconciliation: std::env::var("COSTOPS_RECONCILIATION")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            aggregates_topic: std::env::var("AGGREGATES_TOPIC")
+                .unwrap_or_else(|_| "llm-aggregated-metrics".to_string()),
+            processing_guarantee: std::env::var("PROCESSING_GUARANTEE")
+                .unwrap_or_else(|_| "at_least_once".to_string())
+                .parse()
+                .expect("Invalid PROCESSING_GUARANTEE"),
+            transactional_id: std::env::var("TRANSACTIONAL_ID").unwrap_or_else(|_| {
+                format!(
+                    "metrics-aggregation-{}",
+                    std::env::var("HOSTNAME").unwrap_or_else(|_| "0".to_string())
+                )
+            }),
+            max_out_of_orderness_secs: std::env::var("MAX_OUT_OF_ORDERNESS_SECS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .expect("Invalid MAX_OUT_OF_ORDERNESS_SECS"),
+            allowed_lateness_secs: std::env::var("ALLOWED_LATENESS_SECS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .expect("Invalid ALLOWED_LATENESS_SECS"),
+            early_firing_secs: std::env::var("EARLY_FIRING_SECS")
+                .ok()
+                .map(|v| v.parse().expect("Invalid EARLY_FIRING_SECS")),
+            late_data_policy: std::env::var("LATE_DATA_POLICY")
+                .unwrap_or_else(|_| "side_output".to_string())
+                .parse()
+                .expect("Invalid LATE_DATA_POLICY"),
+            late_events_topic: std::env::var("LATE_EVENTS_TOPIC")
+                .unwrap_or_else(|_| "llm-late-events".to_string()),
+        }
+    }
+
+    fn windowing(&self) -> WindowingConfig {
+        WindowingConfig {
+            max_out_of_orderness: ChronoDuration::seconds(self.max_out_of_orderness_secs),
+            allowed_lateness: ChronoDuration::seconds(self.allowed_lateness_secs),
+            early_firing: self.early_firing_secs.map(ChronoDuration::seconds),
+            late_data: self.late_data_policy,
+            ..Default::default()
         }
     }
 }
 
 /// Metrics aggregator
 struct MetricsAggregator {
-    windows: Arc<DashMap<String, WindowAggregation>>,
+    // Event type -> one-minute event-time windows
+    windows: Mutex<EventTimeWindows<String, WindowAggregation>>,
+    // Event type -> events consumed since start
+    totals: Mutex<HashMap<String, f64>>,
+    counters: AggregationEngine,
+    writer: CopyWriter,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct WindowAggregation {
-    window_start: DateTime<Utc>,
-    window_end: DateTime<Utc>,
     values: Vec<f64>,
     count: u64,
     sum: f64,
@@ -128,11 +290,9 @@ struct WindowAggregation {
     max: f64,
 }
 
-impl WindowAggregation {
-    fn new(window_start: DateTime<Utc>, window_end: DateTime<Utc>) -> Self {
+impl Default for WindowAggregation {
+    fn default() -> Self {
         Self {
-            window_start,
-            window_end,
             values: Vec::new(),
             count: 0,
             sum: 0.0,
@@ -140,7 +300,9 @@ impl WindowAggregation {
             max: f64::MIN,
         }
     }
+}
 
+impl WindowAggregation {
     fn add_value(&mut self, value: f64) {
         self.values.push(value);
         self.count += 1;
@@ -207,90 +369,470 @@ impl WindowAggregation {
 }
 
 impl MetricsAggregator {
-    fn new() -> Self {
+    fn new(config: WindowingConfig, counters: AggregationEngine, writer: CopyWriter) -> Self {
         Self {
-            windows: Arc::new(DashMap::new()),
+            windows: Mutex::new(EventTimeWindows::new(config)),
+            totals: Mutex::new(HashMap::new()),
+            counters,
+            writer,
         }
     }
 
-    fn aggregate_event(&self, event: &AnalyticsEvent) {
-        let window_key = format!("{:?}_{}", event.common.event_type, event.common.timestamp.timestamp() / 60);
+    /// Add an event consumed from `partition` at `offset` to its window.
+    /// Returns `false` if the window is past its allowed lateness.
+    fn aggregate_event(&self, event: &AnalyticsEvent, partition: i32, offset: i64) -> bool {
+        let event_type = format!("{:?}", event.common.event_type);
 
         // For simplicity, we're just counting events
         // In production, extract actual metric values from event payload
-        let value = 1.0;
-
-        self.windows
-            .entry(window_key.clone())
-            .or_insert_with(|| {
-                let window_start = event.common.timestamp;
-                let window_end = window_start + ChronoDuration::minutes(1);
-                WindowAggregation::new(window_start, window_end)
-            })
-            .add_value(value);
+        // Sampled events count for the events they stand for, so `sum` is
+        // the estimated event count
+        let value = sample_weight(event);
+        *self.totals.lock().entry(event_type.clone()).or_default() += value;
+
+        let mut windows = self.windows.lock();
+        windows.observe(partition, event.common.timestamp, Utc::now());
+        match windows.assign(
+            event_type,
+            ChronoDuration::minutes(1),
+            event.common.timestamp,
+            Some((partition, offset)),
+        ) {
+            Some(window) => {
+                window.add_value(value);
+                true
+            }
+            None => false,
+        }
     }
 
-    async fn flush_to_db(&self, pool: &PgPool, metrics: &Arc<Metrics>) -> anyhow::Result<()> {
-        let windows: Vec<_> = self.windows.iter().map(|e| (e.key().clone(), e.value().clone())).collect();
+    /// Record the running event count of every event type as a counter
+    /// reading, returning the counter aggregates over the last minute
+    fn counter_aggregates(&self, metrics: &Metrics) -> Vec<AggregatedMetric> {
+        let now = Utc::now();
+        let totals = self.totals.lock().clone();
+        let mut aggregates = Vec::with_capacity(totals.len());
 
-        for (key, window) in windows {
-            let stats = window.calculate_statistics();
-            let timer = metrics.db_write_duration.with_label_values(&["metrics"]).start_timer();
+        for (event_type, total) in totals {
+            let name = format!("{}_events_total", event_type);
+            let reading = CounterMetric {
+                name: name.clone(),
+                value: total.round() as u64,
+                rate: None,
+                tags: HashMap::new(),
+                timestamp: now,
+            };
+            if let Err(e) = self.counters.add_counter(&reading) {
+                error!("Failed to record counter {}: {}", name, e);
+                continue;
+            }
+            let rate = self
+                .counters
+                .compute(&name, TimeWindow::OneMinute, WindowFunction::Rate);
+            if let Some(rate) = rate {
+                metrics
+                    .event_rate
+                    .with_label_values(&[&event_type])
+                    .set(rate);
+            }
+            aggregates.extend(self.counters.get_aggregated(&name, TimeWindow::OneMinute));
+        }
 
-            // Insert aggregated metrics into TimescaleDB
-            // Using sqlx::query instead of sqlx::query! to avoid DATABASE_URL requirement at compile time
-            let result = sqlx::query(
-                r#"
-                INSERT INTO aggregated_metrics (
-                    window_start, window_end, metric_name, metric_type,
-                    count, sum, mean, median, std_dev, min, max, p50, p95, p99
-                )
-                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
-                ON CONFLICT (window_start, metric_name) DO UPDATE
-                SET count = EXCLUDED.count,
-                    sum = EXCLUDED.sum,
-                    mean = EXCLUDED.mean,
-                    median = EXCLUDED.median,
-                    std_dev = EXCLUDED.std_dev,
-                    min = EXCLUDED.min,
-                    max = EXCLUDED.max,
-                    p50 = EXCLUDED.p50,
-                    p95 = EXCLUDED.p95,
-                    p99 = EXCLUDED.p99
-                "#,
-            )
-            .bind(&window.window_start)
-            .bind(&window.window_end)
-            .bind(&key)
-            .bind("counter")
-            .bind(window.count as i64)
-            .bind(window.sum)
-            .bind(stats.avg)
-            .bind(stats.p50)
-            .bind(stats.stddev)
-            .bind(stats.min)
-            .bind(stats.max)
-            .bind(stats.p50)
-            .bind(stats.p95)
-            .bind(stats.p99)
-            .execute(pool)
-            .await;
+        aggregates
+    }
 
-            timer.observe_duration();
+    /// Store the counter aggregates, returning those written
+    async fn flush_counters(&self, metrics: &Arc<Metrics>) -> Vec<AggregatedMetric> {
+        let counters = self.counter_aggregates(metrics);
+        if counters.is_empty() {
+            return counters;
+        }
+
+        let timer = metrics
+            .db_write_duration
+            .with_label_values(&["counters"])
+            .start_timer();
+        let result = self.writer.write_aggregates(&counters).await;
+        timer.observe_duration();
+
+        match result {
+            Ok(_) => {
+                metrics
+                    .db_writes
+                    .with_label_values(&["counters", "success"])
+                    .inc();
+                counters
+            }
+            Err(e) => {
+                error!("Failed to write counters to database: {}", e);
+                metrics
+                    .db_writes
+                    .with_label_values(&["counters", "error"])
+                    .inc();
+                Vec::new()
+            }
+        }
+    }
+
+    fn late_data_policy(&self) -> LateDataPolicy {
+        self.windows.lock().late_data_policy()
+    }
 
-            match result {
-                Ok(_) => {
-                    metrics.db_writes.with_label_values(&["metrics", "success"]).inc();
-                    self.windows.remove(&key);
+    /// Earliest offset per partition whose window is still in memory
+    fn held_offsets(&self) -> HashMap<i32, i64> {
+        self.windows.lock().held_offsets()
+    }
+
+    /// Stop tracking a revoked partition
+    fn remove_partition(&self, partition: i32) {
+        self.windows.lock().remove_partition(partition);
+    }
+
+    /// Write windows closed by the watermark and late updates to
+    /// TimescaleDB, returning the aggregates written
+    async fn flush_to_db(&self, metrics: &Arc<Metrics>) -> anyhow::Result<Vec<AggregatedMetric>> {
+        let windows = {
+            let mut windows = self.windows.lock();
+            let fired = windows.fire(Utc::now(), |_, w| w.clone());
+            metrics
+                .windows_open
+                .set(windows.stats().windows_open as i64);
+            fired
+        };
+        if windows.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let aggregates: Vec<AggregatedMetric> = windows
+            .iter()
+            .map(|output| AggregatedMetric {
+                name: output.key.clone(),
+                window: TimeWindow::OneMinute,
+                window_start: output.window_start,
+                window_end: output.window_end,
+                values: MetricValues::Stats(output.result.calculate_statistics()),
+                tags: HashMap::new(),
+            })
+            .collect();
+
+        let timer = metrics
+            .db_write_duration
+            .with_label_values(&["metrics"])
+            .start_timer();
+        let result = self.writer.write_aggregates(&aggregates).await;
+        timer.observe_duration();
+
+        match result {
+            Ok(_) => {
+                metrics
+                    .db_writes
+                    .with_label_values(&["metrics", "success"])
+                    .inc();
+                Ok(aggregates)
+            }
+            Err(e) => {
+                metrics
+                    .db_writes
+                    .with_label_values(&["metrics", "error"])
+                    .inc();
+                let mut retained = self.windows.lock();
+                for output in windows.iter().filter(|o| o.emission != Emission::Early) {
+                    retained.retry(&output.key, output.window_start);
                 }
-                Err(e) => {
-                    error!("Failed to write metrics to database: {}", e);
-                    metrics.db_writes.with_label_values(&["metrics", "error"]).inc();
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Consumer context that releases the windows' hold on revoked partitions
+struct AggregationContext {
+    aggregator: Arc<MetricsAggregator>,
+    chargeback: Arc<ChargebackEngine>,
+    // Partitions revoked since the committer last dropped their offsets
+    revoked: Mutex<Vec<(String, i32)>>,
+}
+
+impl AggregationContext {
+    /// Drop the offsets of revoked partitions from the next commit
+    fn release_revoked(&self, committer: &mut StageCommitter) {
+        for (topic, partition) in self.revoked.lock().drain(..) {
+            committer.revoke(&topic, partition);
+        }
+    }
+}
+
+impl ClientContext for AggregationContext {}
+
+impl ConsumerContext for AggregationContext {
+    fn pre_rebalance(&self, rebalance: &Rebalance) {
+        let Rebalance::Revoke(tpl) = rebalance else {
+            return;
+        };
+        info!(partitions = tpl.count(), "Partitions revoked");
+        let mut revoked = self.revoked.lock();
+        for element in tpl.elements() {
+            self.aggregator.remove_partition(element.partition());
+            self.chargeback.forget_partition(element.partition());
+            revoked.push((element.topic().to_string(), element.partition()));
+        }
+    }
+}
+
+type AggregationConsumer = StreamConsumer<AggregationContext>;
+
+/// Flush window aggregates and commit them to the aggregates topic together
+/// with the consumed offsets. Offsets of events in windows that are still
+/// open, or whose chargeback spend is not persisted, are not committed, so
+/// they are consumed again after a restart.
+async fn flush_aggregates(
+    aggregator: &MetricsAggregator,
+    chargeback: &ChargebackEngine,
+    metrics: &Arc<Metrics>,
+    committer: &mut StageCommitter,
+    consumer: &AggregationConsumer,
+    source_topic: &str,
+    topic: &str,
+) -> anyhow::Result<()> {
+    match aggregator.flush_to_db(metrics).await {
+        Ok(aggregates) => {
+            for aggregate in &aggregates {
+                committer.produce(topic, &aggregate.name, serde_json::to_vec(aggregate)?);
+            }
+        }
+        Err(e) => error!("Failed to flush metrics: {}", e),
+    }
+    for counter in aggregator.flush_counters(metrics).await {
+        committer.produce(topic, &counter.name, serde_json::to_vec(&counter)?);
+    }
+    consumer.context().release_revoked(committer);
+    for (partition, offset) in aggregator.held_offsets() {
+        committer.hold(source_topic, partition, offset);
+    }
+    for (partition, offset) in chargeback.held_offsets() {
+        committer.hold(source_topic, partition, offset);
+    }
+    committer.commit(consumer).await
+}
+
+/// Book a consumed cost event to the chargeback ledger, loading the offset
+/// booked on its partition the first time the partition is seen
+async fn book_chargeback(
+    engine: &ChargebackEngine,
+    pool: &PgPool,
+    topic: &str,
+    event: &AnalyticsEvent,
+    partition: i32,
+    offset: i64,
+) {
+    if !engine.tracks_partition(partition) {
+        match queries::get_chargeback_offset(pool, topic, partition).await {
+            Ok(booked) => engine.track_partition(partition, booked),
+            Err(e) => {
+                error!(
+                    "Failed to load chargeback offset of partition {}: {}",
+                    partition, e
+                );
+                engine.track_partition(partition, None);
+            }
+        }
+    }
+    engine.record_consumed(event, partition, offset);
+}
+
+/// Add the chargeback spend booked since the last flush to the persisted
+/// ledger. Spend that could not be persisted is kept for the next flush and
+/// holds back the offsets of its events.
+async fn flush_chargeback(engine: &ChargebackEngine, pool: &PgPool, topic: &str) {
+    let flush = engine.take_flush();
+    if flush.is_empty() {
+        return;
+    }
+    if let Err(e) = queries::add_chargeback_ledger_entries(pool, topic, &flush).await {
+        error!("Failed to persist chargeback ledger: {}", e);
+        engine.restore_flush(flush);
+    }
+}
+
+/// Evaluate SLOs, persist their status and publish status and alert events
+async fn evaluate_slos(
+    engine: &SloEngine,
+    pool: &PgPool,
+    producer: &FutureProducer,
+    topic: &str,
+) -> anyhow::Result<()> {
+    let now = Utc::now();
+
+    for status in engine.status_all(now) {
+        if let Err(e) = queries::store_slo_status(pool, &status).await {
+            error!("Failed to store status of SLO {}: {}", status.slo_id, e);
+        }
+    }
+
+    for event in engine.evaluate_all(now) {
+        let payload = serde_json::to_vec(&event)?;
+        let key = event.common.event_id.to_string();
+        let record = FutureRecord::to(topic).key(&key).payload(&payload);
+        if let Err((e, _)) = producer.send(record, Duration::from_secs(5)).await {
+            error!("Failed to publish SLO event: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Publish forecasted-overrun alerts
+async fn publish_budget_alerts(
+    producer: &FutureProducer,
+    topic: &str,
+    alerts: Vec<AnalyticsEvent>,
+) {
+    for alert in alerts {
+        let payload = match serde_json::to_vec(&alert) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Failed to serialize budget alert: {}", e);
+                continue;
+            }
+        };
+        let key = alert.common.event_id.to_string();
+        let record = FutureRecord::to(topic).key(&key).payload(&payload);
+        if let Err((e, _)) = producer.send(record, Duration::from_secs(5)).await {
+            error!("Failed to publish budget alert: {}", e);
+        }
+    }
+}
+
+/// Refresh tracked budgets from CostOps for the current month. Budgets
+/// CostOps no longer reports are dropped. Returns the synced budget IDs.
+async fn sync_budgets(
+    engine: &BurnRateEngine,
+    costops: &CostOpsAdapter,
+    teams: &[String],
+    previous: &HashSet<String>,
+) -> HashSet<String> {
+    let now = Utc::now();
+    let month = BillingMonth::of(now);
+
+    // No teams configured tracks the organisation-wide budget
+    let teams: Vec<Option<&str>> = if teams.is_empty() {
+        vec![None]
+    } else {
+        teams.iter().map(|team| Some(team.as_str())).collect()
+    };
+
+    let mut synced = HashSet::new();
+    for team in teams {
+        match costops.fetch_budget_status(team).await {
+            Ok(status) if status.period_budget_usd > 0.0 => {
+                let name = team.unwrap_or("organization");
+                synced.insert(status.budget_id.clone());
+                engine.upsert_budget(Budget::from_status(
+                    &status,
+                    name,
+                    None,
+                    month.start(),
+                    month.end(),
+                    now,
+                ));
+            }
+            Ok(_) => {}
+            Err(e) => {
+                warn!("Failed to fetch budget status for {:?}: {}", team, e);
+                // Keep tracking the previous snapshot until CostOps recovers
+                return previous.clone();
+            }
+        }
+    }
+
+    for budget_id in previous.difference(&synced) {
+        engine.remove_budget(budget_id);
+    }
+
+    synced
+}
+
+/// Build the chargeback report of the previous and later months from the
+/// persisted ledger, reconcile it against CostOps when an adapter is given
+/// and persist it
+async fn report_chargeback(
+    engine: &ChargebackEngine,
+    pool: &PgPool,
+    costops: Option<&CostOpsAdapter>,
+) {
+    let now = Utc::now();
+    // Keep reporting the previous month for late cost events
+    let previous = BillingMonth::of(BillingMonth::of(now).start() - ChronoDuration::days(1));
+    let periods = match queries::list_chargeback_periods(pool, previous).await {
+        Ok(periods) => periods,
+        Err(e) => {
+            error!("Failed to list chargeback periods: {}", e);
+            return;
+        }
+    };
+
+    for period in periods {
+        let entries = match queries::get_chargeback_ledger(pool, period).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Failed to load chargeback ledger for {}: {}", period, e);
+                continue;
+            }
+        };
+        let mut report = engine.report_from_entries(period, &entries, now);
+
+        if let Some(costops) = costops {
+            let query = CostSummaryQuery {
+                start_time: Some(period.start()),
+                end_time: Some(period.end()),
+                granularity: Some(Granularity::Monthly),
+                ..Default::default()
+            };
+            match costops.fetch_cost_summary(query).await {
+                Ok(summary) => {
+                    if let Err(e) = engine.reconcile_report(&mut report, &summary) {
+                        warn!("Failed to reconcile chargeback for {}: {}", period, e);
+                    }
                 }
+                Err(e) => warn!("Failed to fetch CostOps summary for {}: {}", period, e),
+            }
+        }
+        if let Some(reconciliation) = &report.reconciliation {
+            if !reconciliation.within_tolerance {
+                warn!(
+                    "Chargeback for {} differs from CostOps by {:.2} USD",
+                    period, reconciliation.difference_usd
+                );
             }
         }
 
-        Ok(())
+        if let Err(e) = queries::store_chargeback_report(pool, &report).await {
+            error!("Failed to store chargeback report for {}: {}", period, e);
+        }
+    }
+}
+
+/// Compare experiment arms and persist their verdict reports
+async fn evaluate_experiments(engine: &ExperimentEngine, pool: &PgPool) {
+    for report in engine.report_all(Utc::now()) {
+        if let Err(e) = queries::store_experiment_report(pool, &report).await {
+            error!(
+                "Failed to store report of experiment {}: {}",
+                report.experiment_id, e
+            );
+        }
+    }
+}
+
+/// Close idle sessions and persist their summaries and turns
+async fn flush_sessions(engine: &SessionEngine, pool: &PgPool) {
+    for session in engine.close_idle(Utc::now()) {
+        if let Err(e) = queries::store_closed_session(pool, &session).await {
+            error!(
+                "Failed to store session {}: {}",
+                session.summary.session_id, e
+            );
+        }
     }
 }
 
@@ -328,32 +870,218 @@ async fn main() -> anyhow::Result<()> {
     info!("Redis connection established");
 
     // Create aggregator
-    let aggregator = Arc::new(MetricsAggregator::new());
+    let counters = AggregationEngine::new(Arc::new(AnalyticsConfig {
+        aggregation_windows: vec![TimeWindow::OneMinute.to_seconds()],
+        ..Default::default()
+    }))
+    .await?;
+    let aggregator = Arc::new(MetricsAggregator::new(
+        config.windowing(),
+        counters,
+        CopyWriter::new(db_pool.clone(), CopyConfig::default()),
+    ));
 
-    // Create Kafka consumer
-    let consumer: StreamConsumer = ClientConfig::new()
-        .set("bootstrap.servers", &config.kafka_brokers)
-        .set("group.id", &config.kafka_group_id)
-        .set("enable.auto.commit", "false")
-        .set("auto.offset.reset", "earliest")
-        .set("client.id", "metrics-aggregation-service")
-        .create()?;
+    // Load SLO definitions
+    let slo_engine = Arc::new(SloEngine::default());
+    if let Some(path) = &config.slo_definitions_path {
+        let definitions: Vec<SloDefinition> =
+            serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        for definition in definitions {
+            let slo_id = definition.slo_id.clone();
+            if let Err(e) = slo_engine.upsert_slo(definition) {
+                warn!("Skipping invalid SLO {}: {}", slo_id, e);
+            }
+        }
+        info!("Loaded {} SLO definitions from {}", slo_engine.definitions().len(), path);
+    }
+
+    // Load experiment definitions
+    let experiment_engine = Arc::new(ExperimentEngine::default());
+    if let Some(path) = &config.experiment_definitions_path {
+        let definitions: Vec<ExperimentDefinition> =
+            serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        for definition in definitions {
+            let experiment_id = definition.experiment_id.clone();
+            if let Err(e) = experiment_engine.upsert_experiment(definition) {
+                warn!("Skipping invalid experiment {}: {}", experiment_id, e);
+            }
+        }
+        info!(
+            "Loaded {} experiment definitions from {}",
+            experiment_engine.definitions().len(),
+            path
+        );
+    }
+
+    // Create session engine, optionally linking requests via Memory-Graph
+    let session_engine = Arc::new(SessionEngine::new(SessionConfig {
+        idle_timeout: ChronoDuration::seconds(config.session_idle_timeout_secs),
+        ..Default::default()
+    }));
+    let memory_graph = if config.memory_graph_sessions {
+        let adapter = MemoryGraphAdapter::new(MemoryGraphConfig::from_env()?);
+        adapter.connect().await?;
+        Some(adapter)
+    } else {
+        None
+    };
+
+    // Load chargeback allocation rules
+    let chargeback_engine = Arc::new(ChargebackEngine::default());
+    if let Some(path) = &config.chargeback_rules_path {
+        let rules: Vec<AllocationRule> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        for rule in rules {
+            let rule_id = rule.rule_id.clone();
+            if let Err(e) = chargeback_engine.upsert_rule(rule) {
+                warn!("Skipping invalid allocation rule {}: {}", rule_id, e);
+            }
+        }
+        info!(
+            "Loaded {} chargeback allocation rules from {}",
+            chargeback_engine.rules().len(),
+            path
+        );
+    }
+
+    // Create burn-rate engine, tracking budgets synced from CostOps
+    let burn_rate_engine = Arc::new(BurnRateEngine::default());
+    let costops = if config.costops_budgets || config.costops_reconciliation {
+        let adapter = CostOpsAdapter::new(CostOpsConfig::from_env()?);
+        adapter.connect().await?;
+        Some(Arc::new(adapter))
+    } else {
+        None
+    };
+
+    let transaction_config = TransactionConfig {
+        guarantee: config.processing_guarantee,
+        transactional_id: config.transactional_id.clone(),
+        ..Default::default()
+    };
+
+    // Create Kafka consumer. Revoked partitions stop holding back the
+    // watermark and their offsets are no longer committed by this instance.
+    let consumer: AggregationConsumer = transactions::consumer_config(
+        &config.kafka_brokers,
+        &config.kafka_group_id,
+        "metrics-aggregation-service",
+        config.processing_guarantee,
+    )
+    .create_with_context(AggregationContext {
+        aggregator: aggregator.clone(),
+        chargeback: chargeback_engine.clone(),
+        revoked: Mutex::new(Vec::new()),
+    })?;
 
     consumer.subscribe(&[&config.kafka_topic])?;
     info!("Subscribed to Kafka topic: {}", config.kafka_topic);
 
-    // Spawn aggregation flush task
-    let flush_pool = db_pool.clone();
-    let flush_aggregator = aggregator.clone();
-    let flush_metrics = metrics.clone();
-    let flush_interval = config.aggregation_interval_secs;
+    // Create Kafka producer for window aggregates. Aggregates are flushed in
+    // the consumption loop and committed together with the offsets of the
+    // events they cover, so offsets only advance on flush.
+    let aggregates_producer: FutureProducer = transactions::producer_config(
+        &config.kafka_brokers,
+        "metrics-aggregation-aggregates",
+        &transaction_config,
+    )
+    .create()?;
+    let mut committer = StageCommitter::new(aggregates_producer, transaction_config)?;
+    let mut aggregation_interval = interval(Duration::from_secs(config.aggregation_interval_secs));
+    aggregation_interval.tick().await;
+
+    // Spawn SLO evaluation task
+    let producer: FutureProducer = ClientConfig::new()
+        .set("bootstrap.servers", &config.kafka_brokers)
+        .set("message.timeout.ms", "5000")
+        .set("client.id", "metrics-aggregation-producer")
+        .create()?;
+    let slo_pool = db_pool.clone();
+    let slo_evaluator = slo_engine.clone();
+    let slo_producer = producer.clone();
+    let slo_topic = config.self_monitoring_topic.clone();
+    let slo_interval = config.slo_evaluation_interval_secs;
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(slo_interval));
+        loop {
+            interval.tick().await;
+            if let Err(e) =
+                evaluate_slos(&slo_evaluator, &slo_pool, &slo_producer, &slo_topic).await
+            {
+                error!("Failed to evaluate SLOs: {}", e);
+            }
+        }
+    });
+
+    // Spawn budget sync and burn-rate evaluation task
+    let budget_evaluator = burn_rate_engine.clone();
+    let budget_producer = producer.clone();
+    let budget_topic = config.self_monitoring_topic.clone();
+    let budget_teams = config.budget_teams.clone();
+    let budget_interval = config.budget_sync_interval_secs;
+    let budget_costops = costops.clone().filter(|_| config.costops_budgets);
     tokio::spawn(async move {
-        let mut interval = interval(Duration::from_secs(flush_interval));
+        let mut interval = interval(Duration::from_secs(budget_interval));
+        let mut synced = HashSet::new();
         loop {
             interval.tick().await;
-            if let Err(e) = flush_aggregator.flush_to_db(&flush_pool, &flush_metrics).await {
-                error!("Failed to flush metrics: {}", e);
+            if let Some(costops) = &budget_costops {
+                synced = sync_budgets(&budget_evaluator, costops, &budget_teams, &synced).await;
             }
+            let alerts = budget_evaluator.evaluate_all(Utc::now());
+            publish_budget_alerts(&budget_producer, &budget_topic, alerts).await;
+        }
+    });
+
+    // Spawn chargeback report and reconciliation task
+    let chargeback_pool = db_pool.clone();
+    let chargeback_reporter = chargeback_engine.clone();
+    let chargeback_costops = costops.filter(|_| config.costops_reconciliation);
+    let chargeback_interval = config.chargeback_interval_secs;
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(chargeback_interval));
+        loop {
+            interval.tick().await;
+            report_chargeback(
+                &chargeback_reporter,
+                &chargeback_pool,
+                chargeback_costops.as_deref(),
+            )
+            .await;
+        }
+    });
+
+    // Spawn experiment evaluation task
+    let experiment_pool = db_pool.clone();
+    let experiment_evaluator = experiment_engine.clone();
+    let experiment_interval = config.experiment_evaluation_interval_secs;
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(experiment_interval));
+        loop {
+            interval.tick().await;
+            evaluate_experiments(&experiment_evaluator, &experiment_pool).await;
+        }
+    });
+
+    // Spawn session flush task
+    let session_pool = db_pool.clone();
+    let session_flusher = session_engine.clone();
+    let session_interval = config.session_flush_interval_secs;
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(session_interval));
+        let mut synced_until = Utc::now();
+        loop {
+            interval.tick().await;
+            if let Some(memory_graph) = &memory_graph {
+                let now = Utc::now();
+                match session_flusher
+                    .sync_with_memory_graph(memory_graph, synced_until, now)
+                    .await
+                {
+                    Ok(_) => synced_until = now,
+                    Err(e) => warn!("Failed to sync sessions with Memory-Graph: {}", e),
+                }
+            }
+            flush_sessions(&session_flusher, &session_pool).await;
         }
     });
 
@@ -362,6 +1090,7 @@ async fn main() -> anyhow::Result<()> {
     while !shutdown {
         tokio::select! {
             message_result = consumer.recv() => {
+                consumer.context().release_revoked(&mut committer);
                 match message_result {
                     Ok(m) => {
                         if let Some(payload) = m.payload() {
@@ -372,11 +1101,37 @@ async fn main() -> anyhow::Result<()> {
                                         .with_label_values(&[&config.kafka_topic, &partition])
                                         .inc();
 
-                                    aggregator.aggregate_event(&event);
+                                    if !aggregator.aggregate_event(&event, m.partition(), m.offset()) {
+                                        match aggregator.late_data_policy() {
+                                            LateDataPolicy::SideOutput => {
+                                                metrics.late_events.with_label_values(&["side_output"]).inc();
+                                                committer.produce(
+                                                    &config.late_events_topic,
+                                                    &event.common.event_id.to_string(),
+                                                    payload.to_vec(),
+                                                );
+                                            }
+                                            LateDataPolicy::Drop => {
+                                                metrics.late_events.with_label_values(&["dropped"]).inc();
+                                            }
+                                        }
+                                    }
+                                    slo_engine.record_event(&event);
+                                    experiment_engine.record_event(&event);
+                                    session_engine.record_event(&event);
+                                    book_chargeback(
+                                        &chargeback_engine,
+                                        &db_pool,
+                                        &config.kafka_topic,
+                                        &event,
+                                        m.partition(),
+                                        m.offset(),
+                                    )
+                                    .await;
 
-                                    // Commit offset
-                                    if let Err(e) = consumer.commit_message(&m, CommitMode::Async) {
-                                        warn!("Failed to commit offset: {}", e);
+                                    let alerts = burn_rate_engine.record_event(&event);
+                                    if !alerts.is_empty() {
+                                        publish_budget_alerts(&producer, &config.self_monitoring_topic, alerts).await;
                                     }
                                 }
                                 Err(e) => {
@@ -384,12 +1139,27 @@ async fn main() -> anyhow::Result<()> {
                                 }
                             }
                         }
+
+                        committer.consumed(m.topic(), m.partition(), m.offset());
                     }
                     Err(e) => {
                         error!("Kafka consumer error: {}", e);
                     }
                 }
             }
+            _ = aggregation_interval.tick() => {
+                flush_chargeback(&chargeback_engine, &db_pool, &config.kafka_topic).await;
+                flush_aggregates(
+                    &aggregator,
+                    &chargeback_engine,
+                    &metrics,
+                    &mut committer,
+                    &consumer,
+                    &config.kafka_topic,
+                    &config.aggregates_topic,
+                )
+                .await?;
+            }
             _ = signal::ctrl_c() => {
                 info!("Received shutdown signal");
                 shutdown = true;
@@ -397,9 +1167,20 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
-    // Final flush before shutdown
+    // Final flush before shutdown. Windows that are still open are not
+    // stored; their offsets stay uncommitted and they are rebuilt on restart.
     info!("Performing final metrics flush");
-    aggregator.flush_to_db(&db_pool, &metrics).await?;
+    flush_chargeback(&chargeback_engine, &db_pool, &config.kafka_topic).await;
+    flush_aggregates(
+        &aggregator,
+        &chargeback_engine,
+        &metrics,
+        &mut committer,
+        &consumer,
+        &config.kafka_topic,
+        &config.aggregates_topic,
+    )
+    .await?;
 
     info!("Service shutdown complete");
     Ok(())
@@ -0,0 +1,80 @@
+//! Chargeback report export command
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use clap::{Args, ValueEnum};
+
+use crate::analytics::chargeback::BillingMonth;
+use crate::cli::connect_timescaledb;
+use crate::common::{
+    output::{print_dry_run, print_success},
+    ExecutionContext,
+};
+use crate::database::queries;
+
+/// Export file format
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// One row per cost centre plus unallocated spend
+    Csv,
+    /// Full report including the CostOps reconciliation
+    Json,
+}
+
+/// Chargeback export arguments
+#[derive(Debug, Args)]
+pub struct ChargebackExportArgs {
+    /// Billing month as YYYY-MM (defaults to the current month)
+    #[arg(long)]
+    pub period: Option<String>,
+
+    /// Export format
+    #[arg(long, value_enum, default_value = "csv")]
+    pub format: ExportFormat,
+
+    /// Write the export to a file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// TimescaleDB connection URL (overrides configuration)
+    #[arg(long, env = "DATABASE_URL")]
+    pub database_url: Option<String>,
+}
+
+impl ChargebackExportArgs {
+    /// Execute chargeback export
+    pub async fn execute(&self, ctx: &ExecutionContext) -> Result<()> {
+        let period = match &self.period {
+            Some(period) => period.parse::<BillingMonth>()?,
+            None => BillingMonth::of(Utc::now()),
+        };
+
+        if ctx.dry_run {
+            print_dry_run(&format!("Would export chargeback report for {}", period));
+            return Ok(());
+        }
+
+        let pool = connect_timescaledb(ctx, self.database_url.as_deref()).await?;
+        let report = queries::get_chargeback_report(&pool, period)
+            .await?
+            .with_context(|| format!("No chargeback report for {}", period))?;
+
+        let export = match self.format {
+            ExportFormat::Csv => report.to_csv(),
+            ExportFormat::Json => report.to_json()?,
+        };
+
+        match &self.output {
+            Some(path) => {
+                std::fs::write(path, &export)
+                    .with_context(|| format!("Failed to write {}", path))?;
+                print_success(&format!(
+                    "Chargeback report for {} written to {}",
+                    period, path
+                ));
+            }
+            None => print!("{}", export),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+//! Cost centre chargeback commands
+
+pub mod export;
+
+use anyhow::Result;
+use clap::Subcommand;
+
+use crate::common::ExecutionContext;
+
+/// Chargeback command
+#[derive(Debug, Subcommand)]
+pub enum ChargebackCommand {
+    /// Export the chargeback report of a month as CSV or JSON
+    Export(export::ChargebackExportArgs),
+}
+
+impl ChargebackCommand {
+    /// Execute the chargeback command
+    pub async fn execute(&self, ctx: &ExecutionContext) -> Result<()> {
+        match self {
+            ChargebackCommand::Export(args) => args.execute(ctx).await,
+        }
+    }
+}
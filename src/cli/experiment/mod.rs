@@ -0,0 +1,28 @@
+//! A/B and canary experiment commands
+
+pub mod report;
+
+use anyhow::Result;
+use clap::Subcommand;
+
+use crate::common::ExecutionContext;
+
+/// Experiment command
+#[derive(Debug, Subcommand)]
+pub enum ExperimentCommand {
+    /// Show the verdict report of an experiment
+    Report(report::ExperimentReportArgs),
+
+    /// List the verdicts of all experiments
+    List(report::ExperimentListArgs),
+}
+
+impl ExperimentCommand {
+    /// Execute the experiment command
+    pub async fn execute(&self, ctx: &ExecutionContext) -> Result<()> {
+        match self {
+            ExperimentCommand::Report(args) => args.execute(ctx).await,
+            ExperimentCommand::List(args) => args.execute(ctx).await,
+        }
+    }
+}
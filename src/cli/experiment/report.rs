@@ -0,0 +1,194 @@
+//! Experiment verdict report commands
+
+use anyhow::{Context, Result};
+use clap::Args;
+
+use crate::analytics::experiment::{ExperimentReport, MetricComparison, Verdict};
+use crate::cli::connect_timescaledb;
+use crate::common::{
+    output::{
+        print_dry_run, print_error, print_header, print_info, print_kv, print_success,
+        print_warning, CommandOutput, FormattedTable,
+    },
+    ExecutionContext,
+};
+use crate::database::queries;
+
+/// Experiment report arguments
+#[derive(Debug, Args)]
+pub struct ExperimentReportArgs {
+    /// Experiment ID
+    pub experiment_id: String,
+
+    /// TimescaleDB connection URL (overrides configuration)
+    #[arg(long, env = "DATABASE_URL")]
+    pub database_url: Option<String>,
+}
+
+/// Experiment list arguments
+#[derive(Debug, Args)]
+pub struct ExperimentListArgs {
+    /// TimescaleDB connection URL (overrides configuration)
+    #[arg(long, env = "DATABASE_URL")]
+    pub database_url: Option<String>,
+}
+
+impl ExperimentReportArgs {
+    /// Execute experiment report
+    pub async fn execute(&self, ctx: &ExecutionContext) -> Result<()> {
+        if ctx.dry_run {
+            print_dry_run(&format!(
+                "Would show report of experiment {}",
+                self.experiment_id
+            ));
+            return Ok(());
+        }
+
+        let pool = connect_timescaledb(ctx, self.database_url.as_deref()).await?;
+        let report = queries::get_experiment_report(&pool, &self.experiment_id)
+            .await?
+            .with_context(|| format!("No report for experiment {}", self.experiment_id))?;
+
+        if ctx.json_output {
+            CommandOutput::success_with_data(
+                verdict_label(report.verdict),
+                serde_json::to_value(&report)?,
+            )
+            .output_json();
+            return Ok(());
+        }
+
+        print_header(&format!(
+            "Experiment {} ({})",
+            report.name, report.experiment_id
+        ));
+        print_kv("Control", &report.control_arm);
+        print_kv("Treatment", &report.treatment_arm);
+        print_kv("Started", &report.started_at.to_rfc3339());
+        print_kv("Computed", &report.computed_at.to_rfc3339());
+        print_kv("Alpha", &report.alpha.to_string());
+        println!();
+
+        let mut table = FormattedTable::new(vec![
+            "Metric",
+            "Control",
+            "Treatment",
+            "Change",
+            "p-value",
+            "Outcome",
+        ]);
+        for comparison in &report.metrics {
+            table.add_row(metric_row(comparison));
+        }
+        table.print();
+
+        println!();
+        print_verdict(&report);
+        Ok(())
+    }
+}
+
+impl ExperimentListArgs {
+    /// Execute experiment list
+    pub async fn execute(&self, ctx: &ExecutionContext) -> Result<()> {
+        if ctx.dry_run {
+            print_dry_run("Would list experiment reports");
+            return Ok(());
+        }
+
+        let pool = connect_timescaledb(ctx, self.database_url.as_deref()).await?;
+        let reports = queries::list_experiment_reports(&pool).await?;
+
+        if ctx.json_output {
+            CommandOutput::success_with_data(
+                format!("{} experiments", reports.len()),
+                serde_json::to_value(&reports)?,
+            )
+            .output_json();
+            return Ok(());
+        }
+
+        print_header("Experiments");
+        if reports.is_empty() {
+            print_warning("No experiment reports found");
+            return Ok(());
+        }
+
+        let mut table = FormattedTable::new(vec![
+            "ID",
+            "Name",
+            "Control",
+            "Treatment",
+            "Verdict",
+            "Computed",
+        ]);
+        for report in &reports {
+            table.add_row(vec![
+                report.experiment_id.clone(),
+                report.name.clone(),
+                report.control_arm.clone(),
+                report.treatment_arm.clone(),
+                verdict_label(report.verdict).to_string(),
+                report.computed_at.to_rfc3339(),
+            ]);
+        }
+        table.print();
+        Ok(())
+    }
+}
+
+fn metric_row(comparison: &MetricComparison) -> Vec<String> {
+    let format_arm = |mean: f64, samples: u64| format!("{:.4} (n={})", mean, samples);
+    let change = match comparison.relative_change {
+        Some(relative) => format!(
+            "{:+.4} ({:+.1}%)",
+            comparison.absolute_change,
+            relative * 100.0
+        ),
+        None => format!("{:+.4}", comparison.absolute_change),
+    };
+    let p_value = comparison
+        .sequential_p_value
+        .map(|p| format!("{:.4}", p))
+        .unwrap_or_else(|| "-".to_string());
+
+    vec![
+        serde_plain(&comparison.metric),
+        format_arm(comparison.control.mean, comparison.control.samples),
+        format_arm(comparison.treatment.mean, comparison.treatment.samples),
+        change,
+        p_value,
+        serde_plain(&comparison.outcome),
+    ]
+}
+
+fn print_verdict(report: &ExperimentReport) {
+    let early = if report.stopped_early {
+        " (stopped early)"
+    } else {
+        ""
+    };
+    let message = format!("Verdict: {}{}", verdict_label(report.verdict), early);
+    match report.verdict {
+        Verdict::PromoteTreatment => print_success(&message),
+        Verdict::RollbackTreatment => print_error(&message),
+        Verdict::NoSignificantDifference | Verdict::Continue => print_info(&message),
+    }
+}
+
+fn verdict_label(verdict: Verdict) -> &'static str {
+    match verdict {
+        Verdict::PromoteTreatment => "Promote treatment",
+        Verdict::RollbackTreatment => "Roll back treatment",
+        Verdict::NoSignificantDifference => "No significant difference",
+        Verdict::Continue => "Continue collecting data",
+    }
+}
+
+/// Render a unit enum by its serde name
+fn serde_plain<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
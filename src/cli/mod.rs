@@ -9,10 +9,14 @@
 //! - health: Health check commands
 //! - utils: Utility commands
 //! - benchmark: Performance benchmark commands
+//! - experiment: A/B and canary experiment verdict commands
+//! - chargeback: Cost centre chargeback report commands
 
 pub mod benchmark;
+pub mod chargeback;
 pub mod database;
 pub mod deploy;
+pub mod experiment;
 pub mod health;
 pub mod kafka;
 pub mod redis;
@@ -21,10 +25,33 @@ pub mod validate;
 
 // Re-export command structs for convenience
 pub use benchmark::BenchmarkCommand;
+pub use chargeback::ChargebackCommand;
 pub use database::DatabaseCommand;
 pub use deploy::DeployCommand;
+pub use experiment::ExperimentCommand;
 pub use health::HealthCommand;
 pub use kafka::KafkaCommand;
 pub use redis::RedisCommand;
 pub use utils::UtilsCommand;
 pub use validate::ValidateCommand;
+
+use anyhow::{Context, Result};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+
+use crate::common::ExecutionContext;
+
+/// Connect to TimescaleDB using the argument or the configured URL
+pub(crate) async fn connect_timescaledb(
+    ctx: &ExecutionContext,
+    database_url: Option<&str>,
+) -> Result<PgPool> {
+    let url = database_url
+        .or(ctx.config.database.timescaledb_url.as_deref())
+        .context("No database URL: pass --database-url or set DATABASE_URL")?;
+
+    PgPoolOptions::new()
+        .max_connections(1)
+        .connect(url)
+        .await
+        .context("Failed to connect to TimescaleDB")
+}
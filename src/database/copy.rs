@@ -0,0 +1,501 @@
+//! COPY BINARY Writer
+//!
+//! Bulk writes to `events` and `aggregated_metrics` with
+//! `COPY ... FROM STDIN (FORMAT binary)`. Each batch is copied into a
+//! per-connection temporary staging table and merged into the target table
+//! in the same transaction, resolving conflicts like the INSERT path:
+//! duplicate events are skipped and aggregates are upserted.
+//!
+//! Writes are split into batches copied concurrently over the pool. The
+//! batch size adapts to observed batch latency. A failed write may leave
+//! some of its batches committed; retrying it is safe because the merge is
+//! idempotent.
+
+use super::Database;
+use crate::models::metrics::{AggregatedMetric, StatisticalMeasures, TimeWindow};
+use crate::schemas::events::AnalyticsEvent;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::debug;
+use uuid::Uuid;
+
+/// COPY writer configuration
+#[derive(Debug, Clone)]
+pub struct CopyConfig {
+    /// Rows in the first batch
+    pub initial_batch_size: usize,
+
+    pub min_batch_size: usize,
+
+    pub max_batch_size: usize,
+
+    /// Batch latency the batch size is adjusted towards
+    pub target_latency: Duration,
+
+    /// Batches copied concurrently, each on its own pooled connection
+    pub max_in_flight: usize,
+}
+
+impl Default for CopyConfig {
+    fn default() -> Self {
+        Self {
+            initial_batch_size: 5000,
+            min_batch_size: 500,
+            max_batch_size: 100_000,
+            target_latency: Duration::from_millis(500),
+            max_in_flight: 4,
+        }
+    }
+}
+
+/// Batch size steered by batch latency: grows by a quarter while full
+/// batches finish in under half the target latency, and halves when a batch
+/// exceeds it
+pub struct AdaptiveBatchSize {
+    current: AtomicUsize,
+    min: usize,
+    max: usize,
+    target: Duration,
+}
+
+impl AdaptiveBatchSize {
+    pub fn new(config: &CopyConfig) -> Self {
+        let min = config.min_batch_size.max(1);
+        let max = config.max_batch_size.max(min);
+        Self {
+            current: AtomicUsize::new(config.initial_batch_size.clamp(min, max)),
+            min,
+            max,
+            target: config.target_latency,
+        }
+    }
+
+    pub fn get(&self) -> usize {
+        self.current.load(Ordering::Relaxed)
+    }
+
+    /// Adjust for a batch of `rows` that took `latency`
+    pub fn observe(&self, rows: usize, latency: Duration) {
+        let _ = self
+            .current
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+                if latency > self.target {
+                    Some((current / 2).max(self.min))
+                } else if latency < self.target / 2 && rows >= current {
+                    // Short tail batches say nothing about larger ones
+                    Some((current + current / 4).min(self.max))
+                } else {
+                    None
+                }
+            });
+    }
+}
+
+/// COPY writer counters
+#[derive(Debug, Clone, Default)]
+pub struct CopyStats {
+    pub rows_copied: u64,
+    pub rows_merged: u64,
+    pub batches: u64,
+    pub batch_size: usize,
+}
+
+/// Bulk writer using COPY BINARY through staging tables
+pub struct CopyWriter {
+    pool: PgPool,
+    config: CopyConfig,
+    batch_size: AdaptiveBatchSize,
+    rows_copied: AtomicU64,
+    rows_merged: AtomicU64,
+    batches: AtomicU64,
+}
+
+impl CopyWriter {
+    pub fn new(pool: PgPool, config: CopyConfig) -> Self {
+        Self {
+            pool,
+            batch_size: AdaptiveBatchSize::new(&config),
+            config,
+            rows_copied: AtomicU64::new(0),
+            rows_merged: AtomicU64::new(0),
+            batches: AtomicU64::new(0),
+        }
+    }
+
+    /// Write events, skipping those already stored; returns the number of
+    /// new rows
+    pub async fn write_events(&self, events: &[AnalyticsEvent]) -> Result<u64> {
+        self.write(events).await
+    }
+
+    /// Upsert the statistical and counter aggregates among `metrics`; for
+    /// repeated keys the last one wins
+    pub async fn write_aggregates(&self, metrics: &[AggregatedMetric]) -> Result<u64> {
+        let mut rows: Vec<AggregateRow> = Vec::with_capacity(metrics.len());
+        let mut keys = HashMap::new();
+        for metric in metrics {
+            let Some(measures) = metric.values.stored_measures() else {
+                continue;
+            };
+            let row = AggregateRow {
+                name: &metric.name,
+                window: metric.window,
+                window_start: metric.window_start,
+                tags: serde_json::to_value(&metric.tags)?,
+                measures,
+            };
+            // A merge can't update the same row twice
+            let key = (row.name, row.window, row.window_start, row.tags.to_string());
+            match keys.get(&key) {
+                Some(&i) => rows[i] = row,
+                None => {
+                    keys.insert(key, rows.len());
+                    rows.push(row);
+                }
+            }
+        }
+        self.write(&rows).await
+    }
+
+    pub fn stats(&self) -> CopyStats {
+        CopyStats {
+            rows_copied: self.rows_copied.load(Ordering::Relaxed),
+            rows_merged: self.rows_merged.load(Ordering::Relaxed),
+            batches: self.batches.load(Ordering::Relaxed),
+            batch_size: self.batch_size.get(),
+        }
+    }
+
+    async fn write<R: CopyRow + Sync>(&self, rows: &[R]) -> Result<u64> {
+        // Batches are cut as pipeline slots free up, at the current size
+        let mut remaining = rows;
+        let batches = std::iter::from_fn(|| {
+            if remaining.is_empty() {
+                return None;
+            }
+            let size = self.batch_size.get().min(remaining.len());
+            let (batch, rest) = remaining.split_at(size);
+            remaining = rest;
+            Some(batch)
+        });
+
+        // Boxed, or the closure types keep the future from being provably
+        // `Send` behind `async_trait`
+        let mut copies = stream::iter(batches)
+            .map(|batch| self.copy_batch(batch))
+            .buffer_unordered(self.config.max_in_flight.max(1))
+            .boxed();
+        let mut merged = 0;
+        while let Some(result) = copies.next().await {
+            merged += result?;
+        }
+        Ok(merged)
+    }
+
+    async fn copy_batch<R: CopyRow>(&self, rows: &[R]) -> Result<u64> {
+        let start = Instant::now();
+        let mut encoder = CopyEncoder::new();
+        for row in rows {
+            row.encode(&mut encoder)?;
+        }
+        let data = encoder.finish();
+
+        let mut tx = self.pool.begin().await?;
+        // Emptied on commit, kept for the connection's next batch
+        sqlx::query(&format!(
+            "CREATE TEMP TABLE IF NOT EXISTS {staging} ON COMMIT DELETE ROWS AS \
+             SELECT {columns} FROM {table} WITH NO DATA",
+            staging = R::STAGING,
+            columns = R::COLUMNS,
+            table = R::TABLE,
+        ))
+        .execute(&mut *tx)
+        .await
+        .context("Failed to create staging table")?;
+
+        let mut copy = tx
+            .copy_in_raw(&format!(
+                "COPY {} ({}) FROM STDIN (FORMAT binary)",
+                R::STAGING,
+                R::COLUMNS
+            ))
+            .await?;
+        copy.send(data).await?;
+        let copied = copy
+            .finish()
+            .await
+            .with_context(|| format!("Failed to copy into {}", R::STAGING))?;
+
+        let merged = sqlx::query(R::MERGE)
+            .execute(&mut *tx)
+            .await
+            .with_context(|| format!("Failed to merge into {}", R::TABLE))?
+            .rows_affected();
+        tx.commit().await?;
+
+        let latency = start.elapsed();
+        self.batch_size.observe(rows.len(), latency);
+        self.rows_copied.fetch_add(copied, Ordering::Relaxed);
+        self.rows_merged.fetch_add(merged, Ordering::Relaxed);
+        self.batches.fetch_add(1, Ordering::Relaxed);
+        debug!(
+            "Copied {} rows into {} in {:?} ({} merged, next batch {})",
+            copied,
+            R::TABLE,
+            latency,
+            merged,
+            self.batch_size.get()
+        );
+        Ok(merged)
+    }
+}
+
+impl Database {
+    /// COPY BINARY writer over this database's pool
+    pub fn copy_writer(&self, config: CopyConfig) -> CopyWriter {
+        CopyWriter::new(self.pool.clone(), config)
+    }
+}
+
+/// A row of a table written through a staging table
+trait CopyRow {
+    const TABLE: &'static str;
+    const STAGING: &'static str;
+    const COLUMNS: &'static str;
+    /// Moves the staged rows into `TABLE`
+    const MERGE: &'static str;
+
+    fn encode(&self, encoder: &mut CopyEncoder) -> Result<()>;
+}
+
+impl CopyRow for AnalyticsEvent {
+    const TABLE: &'static str = "events";
+    const STAGING: &'static str = "events_staging";
+    const COLUMNS: &'static str = "event_id, timestamp, source_module, event_type, \
+        correlation_id, parent_event_id, schema_version, severity, environment, tags, payload";
+    const MERGE: &'static str = "INSERT INTO events (event_id, timestamp, source_module, \
+        event_type, correlation_id, parent_event_id, schema_version, severity, environment, \
+        tags, payload) \
+        SELECT event_id, timestamp, source_module, event_type, correlation_id, \
+        parent_event_id, schema_version, severity, environment, tags, payload \
+        FROM events_staging \
+        ON CONFLICT DO NOTHING";
+
+    fn encode(&self, encoder: &mut CopyEncoder) -> Result<()> {
+        let common = &self.common;
+        encoder.row(11);
+        encoder.uuid(common.event_id);
+        encoder.timestamptz(common.timestamp);
+        encoder.jsonb(&common.source_module)?;
+        encoder.jsonb(&common.event_type)?;
+        encoder.optional_uuid(common.correlation_id);
+        encoder.optional_uuid(common.parent_event_id);
+        encoder.text(&common.schema_version)?;
+        encoder.jsonb(&common.severity)?;
+        encoder.text(&common.environment)?;
+        encoder.jsonb(&common.tags)?;
+        // The whole event, as stored by `insert_event`
+        encoder.jsonb(self)
+    }
+}
+
+struct AggregateRow<'a> {
+    name: &'a str,
+    window: TimeWindow,
+    window_start: DateTime<Utc>,
+    tags: serde_json::Value,
+    measures: StatisticalMeasures,
+}
+
+impl CopyRow for AggregateRow<'_> {
+    const TABLE: &'static str = "aggregated_metrics";
+    const STAGING: &'static str = "aggregated_metrics_staging";
+    const COLUMNS: &'static str = "metric_name, time_window, window_start, tags, \
+        avg, min, max, p50, p95, p99, stddev, count, sum";
+    const MERGE: &'static str = "INSERT INTO aggregated_metrics (metric_name, time_window, \
+        window_start, tags, avg, min, max, p50, p95, p99, stddev, count, sum) \
+        SELECT metric_name, time_window, window_start, tags, \
+        avg, min, max, p50, p95, p99, stddev, count, sum \
+        FROM aggregated_metrics_staging \
+        ON CONFLICT (metric_name, time_window, window_start, tags) \
+        DO UPDATE SET \
+        avg = EXCLUDED.avg, min = EXCLUDED.min, max = EXCLUDED.max, \
+        p50 = EXCLUDED.p50, p95 = EXCLUDED.p95, p99 = EXCLUDED.p99, \
+        stddev = EXCLUDED.stddev, count = EXCLUDED.count, sum = EXCLUDED.sum";
+
+    fn encode(&self, encoder: &mut CopyEncoder) -> Result<()> {
+        let measures = &self.measures;
+        encoder.row(13);
+        encoder.text(self.name)?;
+        encoder.text(self.window.as_str())?;
+        encoder.timestamptz(self.window_start);
+        encoder.jsonb(&self.tags)?;
+        encoder.float8(measures.avg);
+        encoder.float8(measures.min);
+        encoder.float8(measures.max);
+        encoder.float8(measures.p50);
+        encoder.float8(measures.p95);
+        encoder.float8(measures.p99);
+        match measures.stddev {
+            Some(stddev) => encoder.float8(stddev),
+            None => encoder.null(),
+        }
+        encoder.int8(measures.count as i64);
+        encoder.float8(measures.sum);
+        Ok(())
+    }
+}
+
+/// PostgreSQL binary COPY signature
+const SIGNATURE: &[u8] = b"PGCOPY\n\xff\r\n\0";
+
+/// Microseconds from the Unix epoch to the PostgreSQL epoch, 2000-01-01
+const POSTGRES_EPOCH_MICROS: i64 = 946_684_800_000_000;
+
+/// Encodes rows in the PostgreSQL binary COPY format: a header, then per row
+/// a field count and length-prefixed fields in network byte order, then a
+/// trailer
+struct CopyEncoder {
+    buf: Vec<u8>,
+}
+
+impl CopyEncoder {
+    fn new() -> Self {
+        let mut buf = Vec::with_capacity(64 * 1024);
+        buf.extend_from_slice(SIGNATURE);
+        // Flags and header extension length
+        buf.extend_from_slice(&0i32.to_be_bytes());
+        buf.extend_from_slice(&0i32.to_be_bytes());
+        Self { buf }
+    }
+
+    fn row(&mut self, fields: i16) {
+        self.buf.extend_from_slice(&fields.to_be_bytes());
+    }
+
+    fn null(&mut self) {
+        self.buf.extend_from_slice(&(-1i32).to_be_bytes());
+    }
+
+    fn field(&mut self, bytes: &[u8]) -> Result<()> {
+        let len = i32::try_from(bytes.len()).context("Field too large for COPY")?;
+        self.buf.extend_from_slice(&len.to_be_bytes());
+        self.buf.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn text(&mut self, value: &str) -> Result<()> {
+        self.field(value.as_bytes())
+    }
+
+    fn uuid(&mut self, id: Uuid) {
+        self.buf.extend_from_slice(&16i32.to_be_bytes());
+        self.buf.extend_from_slice(id.as_bytes());
+    }
+
+    fn optional_uuid(&mut self, id: Option<Uuid>) {
+        match id {
+            Some(id) => self.uuid(id),
+            None => self.null(),
+        }
+    }
+
+    fn timestamptz(&mut self, time: DateTime<Utc>) {
+        self.int8(time.timestamp_micros() - POSTGRES_EPOCH_MICROS);
+    }
+
+    fn int8(&mut self, value: i64) {
+        self.buf.extend_from_slice(&8i32.to_be_bytes());
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    fn float8(&mut self, value: f64) {
+        self.buf.extend_from_slice(&8i32.to_be_bytes());
+        self.buf.extend_from_slice(&value.to_bits().to_be_bytes());
+    }
+
+    /// JSONB: a version byte followed by the JSON text
+    fn jsonb<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        let mut json = vec![1];
+        serde_json::to_writer(&mut json, value)?;
+        self.field(&json)
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.buf.extend_from_slice(&(-1i16).to_be_bytes());
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoder_framing() {
+        let mut encoder = CopyEncoder::new();
+        encoder.row(2);
+        encoder.text("gpt-4").unwrap();
+        encoder.null();
+        let data = encoder.finish();
+
+        assert!(data.starts_with(SIGNATURE));
+        let body = &data[SIGNATURE.len() + 8..];
+        assert_eq!(&body[..2], &2i16.to_be_bytes());
+        assert_eq!(&body[2..6], &5i32.to_be_bytes());
+        assert_eq!(&body[6..11], b"gpt-4");
+        assert_eq!(&body[11..15], &(-1i32).to_be_bytes());
+        assert_eq!(&body[15..], &(-1i16).to_be_bytes());
+    }
+
+    #[test]
+    fn test_encoder_values() {
+        let mut encoder = CopyEncoder::new();
+        encoder.timestamptz(DateTime::from_timestamp(946_684_801, 0).unwrap());
+        encoder.jsonb(&serde_json::json!({"a": 1})).unwrap();
+        let data = encoder.finish();
+        let body = &data[SIGNATURE.len() + 8..];
+
+        // One second after the PostgreSQL epoch
+        assert_eq!(&body[..4], &8i32.to_be_bytes());
+        assert_eq!(&body[4..12], &1_000_000i64.to_be_bytes());
+        // JSONB version byte, then the text
+        assert_eq!(&body[12..16], &8i32.to_be_bytes());
+        assert_eq!(&body[16..24], b"\x01{\"a\":1}");
+    }
+
+    #[test]
+    fn test_adaptive_batch_size() {
+        let batch_size = AdaptiveBatchSize::new(&CopyConfig {
+            initial_batch_size: 1000,
+            min_batch_size: 300,
+            max_batch_size: 1500,
+            target_latency: Duration::from_millis(100),
+            ..CopyConfig::default()
+        });
+
+        // Fast full batches grow it, up to the maximum
+        batch_size.observe(1000, Duration::from_millis(10));
+        assert_eq!(batch_size.get(), 1250);
+        batch_size.observe(1250, Duration::from_millis(10));
+        assert_eq!(batch_size.get(), 1500);
+
+        // Fast tail batches and on-target batches leave it alone
+        batch_size.observe(20, Duration::from_millis(1));
+        batch_size.observe(1500, Duration::from_millis(80));
+        assert_eq!(batch_size.get(), 1500);
+
+        // Slow batches halve it, down to the minimum
+        batch_size.observe(1500, Duration::from_millis(200));
+        assert_eq!(batch_size.get(), 750);
+        batch_size.observe(750, Duration::from_millis(200));
+        batch_size.observe(375, Duration::from_millis(200));
+        assert_eq!(batch_size.get(), 300);
+    }
+}
@@ -12,6 +12,7 @@ use std::time::Duration;
 use tracing::{info, instrument};
 use uuid::Uuid;
 
+pub mod copy;
 pub mod queries;
 pub mod schema;
 
@@ -136,7 +137,7 @@ impl Database {
         let event_json = serde_json::to_value(event)
             .context("Failed to serialize event")?;
 
-        let row = sqlx::query(
+        sqlx::query(
             r#"
             INSERT INTO events (
                 event_id, timestamp, source_module, event_type,
@@ -144,7 +145,7 @@ impl Database {
                 severity, environment, tags, payload
             )
             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
-            RETURNING event_id
+            ON CONFLICT DO NOTHING
             "#
         )
         .bind(event.common.event_id)
@@ -158,11 +159,12 @@ impl Database {
         .bind(&event.common.environment)
         .bind(serde_json::to_value(&event.common.tags)?)
         .bind(event_json)
-        .fetch_one(&self.pool)
+        .execute(&self.pool)
         .await
         .context("Failed to insert event")?;
 
-        Ok(row.try_get("event_id")?)
+        // Redelivered events are already stored; inserting them is a no-op
+        Ok(event.common.event_id)
     }
 
     /// Batch insert analytics events for high throughput
@@ -199,6 +201,8 @@ impl Database {
                     .push_bind(event_json);
             });
 
+            query_builder.push(" ON CONFLICT DO NOTHING");
+
             let result = query_builder.build().execute(&mut *tx).await?;
             inserted = result.rows_affected();
         } else {
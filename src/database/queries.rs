@@ -2,10 +2,23 @@
 //!
 //! Pre-defined queries for common operations with optimized execution plans.
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use sqlx::{PgPool, Row};
 use uuid::Uuid;
 
+use crate::analytics::backtest::{ForecastMethod, ModelSelection};
+use crate::analytics::chargeback::{BillingMonth, ChargebackReport, LedgerEntry, LedgerFlush};
+#[cfg(feature = "ml")]
+use crate::analytics::clustering::{ClusterDriftReport, ClusterStats};
+use crate::analytics::experiment::ExperimentReport;
+use crate::analytics::root_cause::RootCauseReport;
+use crate::analytics::session::{
+    AbandonmentPoint, ClosedSession, SessionBucket, TurnLatencyDistribution,
+};
+use crate::analytics::slo::SloStatus;
+use crate::analytics::window_functions::Sample;
+use crate::models::metrics::{TimeRange, WindowFunction};
+
 /// Query to get event count by source module over time
 pub async fn get_event_count_by_module(
     pool: &PgPool,
@@ -68,6 +81,56 @@ pub async fn get_metric_timeseries(
         .collect())
 }
 
+/// Query a window function over a metric's time buckets
+///
+/// Scalar functions (rate, irate, delta, derivative) are evaluated at each
+/// bucket over the trailing `lookback`. Counter metrics use the latest value
+/// in each bucket and are adjusted for resets.
+pub async fn get_metric_window_series(
+    pool: &PgPool,
+    metric_name: &str,
+    bucket_size: &str,
+    range: &TimeRange,
+    function: WindowFunction,
+    lookback: Duration,
+    counter: bool,
+) -> anyhow::Result<Vec<Sample>> {
+    let query_str = format!(
+        r#"
+        SELECT
+            time_bucket('{}', window_start) as bucket,
+            {} as value
+        FROM aggregated_metrics
+        WHERE metric_name = $1
+          AND window_start >= $2
+          AND window_start < $3
+        GROUP BY bucket
+        ORDER BY bucket ASC
+        "#,
+        bucket_size,
+        if counter { "MAX(max)" } else { "AVG(avg)" }
+    );
+
+    // Fetch the lookback before the range so the first buckets are complete
+    let rows = sqlx::query(&query_str)
+        .bind(metric_name)
+        .bind(range.start - lookback)
+        .bind(range.end)
+        .fetch_all(pool)
+        .await?;
+
+    let samples: Vec<Sample> = rows
+        .into_iter()
+        .map(|row| (row.get("bucket"), row.get("value")))
+        .collect();
+
+    Ok(function
+        .evaluate_series(&samples, lookback, counter)
+        .into_iter()
+        .filter(|(t, _)| *t >= range.start)
+        .collect())
+}
+
 /// Query to find correlated events
 pub async fn find_correlated_events(
     pool: &PgPool,
@@ -125,3 +188,770 @@ pub async fn get_top_anomalies(
         ))
         .collect())
 }
+
+/// Persist backtest results for a metric, marking the selected method
+pub async fn store_model_selection(
+    pool: &PgPool,
+    selection: &ModelSelection,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    for result in &selection.results {
+        sqlx::query(
+            r#"
+            INSERT INTO forecast_backtests (
+                metric_name, method, origins, forecasts, mae, mape, smape,
+                coverage, confidence_level, selected, evaluated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            ON CONFLICT (metric_name, method)
+            DO UPDATE SET
+                origins = EXCLUDED.origins,
+                forecasts = EXCLUDED.forecasts,
+                mae = EXCLUDED.mae,
+                mape = EXCLUDED.mape,
+                smape = EXCLUDED.smape,
+                coverage = EXCLUDED.coverage,
+                confidence_level = EXCLUDED.confidence_level,
+                selected = EXCLUDED.selected,
+                evaluated_at = EXCLUDED.evaluated_at
+            "#
+        )
+        .bind(&selection.metric_name)
+        .bind(result.method.as_str())
+        .bind(result.origins as i32)
+        .bind(result.forecasts as i32)
+        .bind(result.mae)
+        .bind(result.mape)
+        .bind(result.smape)
+        .bind(result.coverage)
+        .bind(selection.confidence_level)
+        .bind(result.method == selection.selected)
+        .bind(selection.evaluated_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    // Clear the flag on methods that could not be evaluated this round
+    sqlx::query(
+        r#"
+        UPDATE forecast_backtests
+        SET selected = FALSE
+        WHERE metric_name = $1 AND method <> $2
+        "#
+    )
+    .bind(&selection.metric_name)
+    .bind(selection.selected.as_str())
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Query the currently selected forecast method for a metric
+pub async fn get_selected_forecast_method(
+    pool: &PgPool,
+    metric_name: &str,
+) -> anyhow::Result<Option<ForecastMethod>> {
+    let row = sqlx::query(
+        r#"
+        SELECT method
+        FROM forecast_backtests
+        WHERE metric_name = $1 AND selected
+        "#
+    )
+    .bind(metric_name)
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| row.get::<String, _>("method").parse())
+        .transpose()
+}
+
+/// Persist a root cause analysis report, replacing any earlier analysis of
+/// the same anomaly
+pub async fn store_root_cause_analysis(
+    pool: &PgPool,
+    report: &RootCauseReport,
+) -> anyhow::Result<()> {
+    let anomaly = report
+        .correlation
+        .anomalies
+        .iter()
+        .find(|a| a.event_id == report.anomaly_id);
+    let severity = serde_json::to_value(&report.correlation.impact.severity)?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO root_cause_analyses (
+            anomaly_id, metric_name, root_event_id, confidence,
+            impact_severity, report, analyzed_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (anomaly_id)
+        DO UPDATE SET
+            root_event_id = EXCLUDED.root_event_id,
+            confidence = EXCLUDED.confidence,
+            impact_severity = EXCLUDED.impact_severity,
+            report = EXCLUDED.report,
+            analyzed_at = EXCLUDED.analyzed_at
+        "#
+    )
+    .bind(report.anomaly_id)
+    .bind(anomaly.map(|a| a.metric.as_str()).unwrap_or_default())
+    .bind(report.root_cause().map(|rca| rca.root_event_id))
+    .bind(report.root_cause().map(|rca| rca.confidence).unwrap_or(0.0))
+    .bind(severity.as_str().unwrap_or_default())
+    .bind(serde_json::to_value(report)?)
+    .bind(report.analyzed_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Query the root cause analysis report for an anomaly
+pub async fn get_root_cause_analysis(
+    pool: &PgPool,
+    anomaly_id: Uuid,
+) -> anyhow::Result<Option<RootCauseReport>> {
+    let row = sqlx::query(
+        r#"
+        SELECT report
+        FROM root_cause_analyses
+        WHERE anomaly_id = $1
+        "#
+    )
+    .bind(anomaly_id)
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| serde_json::from_value(row.get::<serde_json::Value, _>("report")))
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// Persist the latest status of an SLO
+pub async fn store_slo_status(pool: &PgPool, status: &SloStatus) -> anyhow::Result<()> {
+    let severity = status
+        .alert_severity()
+        .map(serde_json::to_value)
+        .transpose()?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO slo_status (
+            slo_id, slo_name, target, window_seconds, attainment,
+            error_budget_remaining, alert_severity, status, computed_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (slo_id)
+        DO UPDATE SET
+            slo_name = EXCLUDED.slo_name,
+            target = EXCLUDED.target,
+            window_seconds = EXCLUDED.window_seconds,
+            attainment = EXCLUDED.attainment,
+            error_budget_remaining = EXCLUDED.error_budget_remaining,
+            alert_severity = EXCLUDED.alert_severity,
+            status = EXCLUDED.status,
+            computed_at = EXCLUDED.computed_at
+        "#
+    )
+    .bind(&status.slo_id)
+    .bind(&status.name)
+    .bind(status.target)
+    .bind(status.window_secs as i64)
+    .bind(status.attainment)
+    .bind(status.error_budget_remaining)
+    .bind(severity.as_ref().and_then(|s| s.as_str()))
+    .bind(serde_json::to_value(status)?)
+    .bind(status.computed_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Query the latest status of an SLO
+pub async fn get_slo_status(pool: &PgPool, slo_id: &str) -> anyhow::Result<Option<SloStatus>> {
+    let row = sqlx::query(
+        r#"
+        SELECT status
+        FROM slo_status
+        WHERE slo_id = $1
+        "#
+    )
+    .bind(slo_id)
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| serde_json::from_value(row.get::<serde_json::Value, _>("status")))
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// Query the latest status of every SLO, least remaining error budget first
+pub async fn list_slo_statuses(pool: &PgPool) -> anyhow::Result<Vec<SloStatus>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT status
+        FROM slo_status
+        ORDER BY error_budget_remaining ASC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| serde_json::from_value(row.get::<serde_json::Value, _>("status")))
+        .collect::<Result<_, _>>()
+        .map_err(Into::into)
+}
+
+/// Persist the latest verdict report of an experiment
+pub async fn store_experiment_report(
+    pool: &PgPool,
+    report: &ExperimentReport,
+) -> anyhow::Result<()> {
+    let verdict = serde_json::to_value(report.verdict)?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO experiment_reports (
+            experiment_id, experiment_name, control_arm, treatment_arm,
+            verdict, stopped_early, report, started_at, computed_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (experiment_id)
+        DO UPDATE SET
+            experiment_name = EXCLUDED.experiment_name,
+            control_arm = EXCLUDED.control_arm,
+            treatment_arm = EXCLUDED.treatment_arm,
+            verdict = EXCLUDED.verdict,
+            stopped_early = EXCLUDED.stopped_early,
+            report = EXCLUDED.report,
+            started_at = EXCLUDED.started_at,
+            computed_at = EXCLUDED.computed_at
+        "#
+    )
+    .bind(&report.experiment_id)
+    .bind(&report.name)
+    .bind(&report.control_arm)
+    .bind(&report.treatment_arm)
+    .bind(verdict.as_str())
+    .bind(report.stopped_early)
+    .bind(serde_json::to_value(report)?)
+    .bind(report.started_at)
+    .bind(report.computed_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Query the latest verdict report of an experiment
+pub async fn get_experiment_report(
+    pool: &PgPool,
+    experiment_id: &str,
+) -> anyhow::Result<Option<ExperimentReport>> {
+    let row = sqlx::query(
+        r#"
+        SELECT report
+        FROM experiment_reports
+        WHERE experiment_id = $1
+        "#
+    )
+    .bind(experiment_id)
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| serde_json::from_value(row.get::<serde_json::Value, _>("report")))
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// Query the latest verdict report of every experiment, most recently started first
+pub async fn list_experiment_reports(pool: &PgPool) -> anyhow::Result<Vec<ExperimentReport>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT report
+        FROM experiment_reports
+        ORDER BY started_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| serde_json::from_value(row.get::<serde_json::Value, _>("report")))
+        .collect::<Result<_, _>>()
+        .map_err(Into::into)
+}
+
+/// Persist the latest chargeback report of a month
+pub async fn store_chargeback_report(
+    pool: &PgPool,
+    report: &ChargebackReport,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO chargeback_reports (
+            period, period_start, period_end, total_usd, unallocated_usd,
+            within_tolerance, report, generated_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (period)
+        DO UPDATE SET
+            total_usd = EXCLUDED.total_usd,
+            unallocated_usd = EXCLUDED.unallocated_usd,
+            within_tolerance = EXCLUDED.within_tolerance,
+            report = EXCLUDED.report,
+            generated_at = EXCLUDED.generated_at
+        "#
+    )
+    .bind(report.period.to_string())
+    .bind(report.period_start)
+    .bind(report.period_end)
+    .bind(report.total_usd)
+    .bind(report.unallocated_usd)
+    .bind(report.reconciliation.as_ref().map(|r| r.within_tolerance))
+    .bind(serde_json::to_value(report)?)
+    .bind(report.generated_at)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Query the latest chargeback report of a month
+pub async fn get_chargeback_report(
+    pool: &PgPool,
+    period: BillingMonth,
+) -> anyhow::Result<Option<ChargebackReport>> {
+    let row = sqlx::query(
+        r#"
+        SELECT report
+        FROM chargeback_reports
+        WHERE period = $1
+        "#
+    )
+    .bind(period.to_string())
+    .fetch_optional(pool)
+    .await?;
+
+    row.map(|row| serde_json::from_value(row.get::<serde_json::Value, _>("report")))
+        .transpose()
+        .map_err(Into::into)
+}
+
+/// Add booked chargeback spend to the ledger and record the booked offsets
+/// in the same transaction
+pub async fn add_chargeback_ledger_entries(
+    pool: &PgPool,
+    topic: &str,
+    flush: &LedgerFlush,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    for entry in flush.entries() {
+        sqlx::query(
+            r#"
+            INSERT INTO chargeback_ledger (period, account, cost_usd, tokens, requests)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (period, account)
+            DO UPDATE SET
+                cost_usd = chargeback_ledger.cost_usd + EXCLUDED.cost_usd,
+                tokens = chargeback_ledger.tokens + EXCLUDED.tokens,
+                requests = chargeback_ledger.requests + EXCLUDED.requests,
+                updated_at = NOW()
+            "#
+        )
+        .bind(entry.period.to_string())
+        .bind(serde_json::to_value(&entry.account)?)
+        .bind(entry.cost_usd)
+        .bind(entry.tokens as i64)
+        .bind(entry.requests as i64)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for (partition, offset) in flush.offsets() {
+        sqlx::query(
+            r#"
+            INSERT INTO chargeback_ledger_offsets (topic, partition, booked_offset)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (topic, partition)
+            DO UPDATE SET
+                booked_offset = GREATEST(chargeback_ledger_offsets.booked_offset, EXCLUDED.booked_offset)
+            "#
+        )
+        .bind(topic)
+        .bind(partition)
+        .bind(offset)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Query the chargeback ledger of a month
+pub async fn get_chargeback_ledger(
+    pool: &PgPool,
+    period: BillingMonth,
+) -> anyhow::Result<Vec<LedgerEntry>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT account, cost_usd, tokens, requests
+        FROM chargeback_ledger
+        WHERE period = $1
+        "#
+    )
+    .bind(period.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| {
+            Ok(LedgerEntry {
+                period,
+                account: serde_json::from_value(row.get::<serde_json::Value, _>("account"))?,
+                cost_usd: row.get("cost_usd"),
+                tokens: row.get::<i64, _>("tokens") as u64,
+                requests: row.get::<i64, _>("requests") as u64,
+            })
+        })
+        .collect()
+}
+
+/// Query the months with chargeback spend booked, from `since` onwards
+pub async fn list_chargeback_periods(
+    pool: &PgPool,
+    since: BillingMonth,
+) -> anyhow::Result<Vec<BillingMonth>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT DISTINCT period
+        FROM chargeback_ledger
+        WHERE period >= $1
+        ORDER BY period
+        "#
+    )
+    .bind(since.to_string())
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| row.get::<String, _>("period").parse())
+        .collect()
+}
+
+/// Query the offset of the last event booked to the chargeback ledger from
+/// a partition
+pub async fn get_chargeback_offset(
+    pool: &PgPool,
+    topic: &str,
+    partition: i32,
+) -> anyhow::Result<Option<i64>> {
+    let offset = sqlx::query_scalar(
+        r#"
+        SELECT booked_offset
+        FROM chargeback_ledger_offsets
+        WHERE topic = $1 AND partition = $2
+        "#
+    )
+    .bind(topic)
+    .bind(partition)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(offset)
+}
+
+/// Persist a snapshot of the request clusters
+#[cfg(feature = "ml")]
+pub async fn store_cluster_stats(
+    pool: &PgPool,
+    stats: &[ClusterStats],
+    computed_at: DateTime<Utc>,
+) -> anyhow::Result<()> {
+    let mut tx = pool.begin().await?;
+
+    for cluster in stats {
+        sqlx::query(
+            r#"
+            INSERT INTO request_cluster_stats (
+                cluster_id, computed_at, requests, share, total_cost_usd,
+                mean_latency_ms, p95_latency_ms, stats
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (cluster_id, computed_at) DO NOTHING
+            "#
+        )
+        .bind(&cluster.cluster_id)
+        .bind(computed_at)
+        .bind(cluster.requests as i64)
+        .bind(cluster.share)
+        .bind(cluster.total_cost_usd)
+        .bind(cluster.mean_latency_ms)
+        .bind(cluster.p95_latency_ms)
+        .bind(serde_json::to_value(cluster)?)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Query the latest snapshot of the request clusters, most requests first
+#[cfg(feature = "ml")]
+pub async fn get_cluster_stats(pool: &PgPool) -> anyhow::Result<Vec<ClusterStats>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT stats
+        FROM request_cluster_stats
+        WHERE computed_at = (SELECT MAX(computed_at) FROM request_cluster_stats)
+        ORDER BY requests DESC, cluster_id
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| serde_json::from_value(row.get::<serde_json::Value, _>("stats")))
+        .collect::<Result<_, _>>()
+        .map_err(Into::into)
+}
+
+/// Persist the drift report of a request cluster fit
+#[cfg(feature = "ml")]
+pub async fn store_cluster_drift_report(
+    pool: &PgPool,
+    report: &ClusterDriftReport,
+) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO request_cluster_drift (
+            fitted_at, samples, noise_samples, share_psi, report
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (fitted_at) DO NOTHING
+        "#
+    )
+    .bind(report.fitted_at)
+    .bind(report.samples as i32)
+    .bind(report.noise_samples as i32)
+    .bind(report.share_psi)
+    .bind(serde_json::to_value(report)?)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Query the drift reports of the latest request cluster fits, newest first
+#[cfg(feature = "ml")]
+pub async fn list_cluster_drift_reports(
+    pool: &PgPool,
+    limit: i64,
+) -> anyhow::Result<Vec<ClusterDriftReport>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT report
+        FROM request_cluster_drift
+        ORDER BY fitted_at DESC
+        LIMIT $1
+        "#
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(|row| serde_json::from_value(row.get::<serde_json::Value, _>("report")))
+        .collect::<Result<_, _>>()
+        .map_err(Into::into)
+}
+
+/// Persist an ended session and its turns
+pub async fn store_closed_session(pool: &PgPool, session: &ClosedSession) -> anyhow::Result<()> {
+    let summary = &session.summary;
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        INSERT INTO session_metrics (
+            session_id, started_at, ended_at, duration_seconds, turns,
+            prompt_tokens, completion_tokens, total_tokens, cost_usd,
+            failed_turns, abandoned, abandonment_turn
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+        ON CONFLICT (session_id, started_at) DO NOTHING
+        "#
+    )
+    .bind(&summary.session_id)
+    .bind(summary.started_at)
+    .bind(summary.ended_at)
+    .bind(summary.duration_secs)
+    .bind(summary.turns as i32)
+    .bind(summary.prompt_tokens as i64)
+    .bind(summary.completion_tokens as i64)
+    .bind(summary.total_tokens as i64)
+    .bind(summary.cost_usd)
+    .bind(summary.failed_turns as i32)
+    .bind(summary.abandoned)
+    .bind(summary.abandonment_turn.map(|t| t as i32))
+    .execute(&mut *tx)
+    .await?;
+
+    for turn in &session.turns {
+        sqlx::query(
+            r#"
+            INSERT INTO session_turns (
+                session_id, turn_index, request_id, timestamp, ttft_ms,
+                latency_ms, total_tokens, cost_usd, failed
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (session_id, turn_index, timestamp) DO NOTHING
+            "#
+        )
+        .bind(&turn.session_id)
+        .bind(turn.turn_index as i32)
+        .bind(&turn.request_id)
+        .bind(turn.timestamp)
+        .bind(turn.ttft_ms)
+        .bind(turn.latency_ms)
+        .bind(turn.total_tokens as i64)
+        .bind(turn.cost_usd)
+        .bind(turn.failed)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Query session totals over time buckets, by session start
+pub async fn get_session_timeseries(
+    pool: &PgPool,
+    bucket_size: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> anyhow::Result<Vec<SessionBucket>> {
+    let query_str = format!(
+        r#"
+        SELECT
+            time_bucket('{}', started_at) as bucket,
+            COUNT(*) as sessions,
+            AVG(turns)::DOUBLE PRECISION as avg_turns,
+            AVG(duration_seconds) as avg_duration_secs,
+            AVG(total_tokens)::DOUBLE PRECISION as avg_tokens,
+            AVG(cost_usd) as avg_cost_usd,
+            SUM(cost_usd) as total_cost_usd,
+            AVG(abandoned::INT)::DOUBLE PRECISION as abandonment_rate
+        FROM session_metrics
+        WHERE started_at >= $1
+          AND started_at < $2
+        GROUP BY bucket
+        ORDER BY bucket ASC
+        "#,
+        bucket_size
+    );
+
+    let rows = sqlx::query(&query_str)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows.into_iter()
+        .map(|row| SessionBucket {
+            bucket: row.get("bucket"),
+            sessions: row.get::<i64, _>("sessions") as u64,
+            avg_turns: row.get("avg_turns"),
+            avg_duration_secs: row.get("avg_duration_secs"),
+            avg_tokens: row.get("avg_tokens"),
+            avg_cost_usd: row.get("avg_cost_usd"),
+            total_cost_usd: row.get("total_cost_usd"),
+            abandonment_rate: row.get("abandonment_rate"),
+        })
+        .collect())
+}
+
+/// Query time-to-first-token percentiles by turn index
+pub async fn get_ttft_by_turn(
+    pool: &PgPool,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    max_turn: u32,
+) -> anyhow::Result<Vec<TurnLatencyDistribution>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            turn_index,
+            COUNT(*) as samples,
+            percentile_cont(0.50) WITHIN GROUP (ORDER BY ttft_ms) as p50,
+            percentile_cont(0.90) WITHIN GROUP (ORDER BY ttft_ms) as p90,
+            percentile_cont(0.99) WITHIN GROUP (ORDER BY ttft_ms) as p99
+        FROM session_turns
+        WHERE timestamp >= $1
+          AND timestamp < $2
+          AND turn_index <= $3
+          AND ttft_ms IS NOT NULL
+        GROUP BY turn_index
+        ORDER BY turn_index ASC
+        "#
+    )
+    .bind(start)
+    .bind(end)
+    .bind(max_turn as i32)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter()
+        .map(|row| TurnLatencyDistribution {
+            turn_index: row.get::<i32, _>("turn_index") as u32,
+            samples: row.get::<i64, _>("samples") as u64,
+            ttft_p50_ms: row.get("p50"),
+            ttft_p90_ms: row.get("p90"),
+            ttft_p99_ms: row.get("p99"),
+        })
+        .collect())
+}
+
+/// Query how many sessions ended, and were abandoned, at each turn index
+pub async fn get_abandonment_points(
+    pool: &PgPool,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> anyhow::Result<Vec<AbandonmentPoint>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+            turns as turn_index,
+            COUNT(*) as sessions_ended,
+            COUNT(*) FILTER (WHERE abandoned) as sessions_abandoned
+        FROM session_metrics
+        WHERE started_at >= $1
+          AND started_at < $2
+        GROUP BY turns
+        ORDER BY turns ASC
+        "#
+    )
+    .bind(start)
+    .bind(end)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter()
+        .map(|row| AbandonmentPoint {
+            turn_index: row.get::<i32, _>("turn_index") as u32,
+            sessions_ended: row.get::<i64, _>("sessions_ended") as u64,
+            sessions_abandoned: row.get::<i64, _>("sessions_abandoned") as u64,
+        })
+        .collect())
+}
@@ -149,6 +149,196 @@ CREATE INDEX IF NOT EXISTS idx_correlations_type ON correlations (correlation_ty
 CREATE INDEX IF NOT EXISTS idx_correlations_strength ON correlations (strength DESC);
 "#;
 
+/// SQL to create forecast backtest results table
+pub const CREATE_FORECAST_BACKTESTS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS forecast_backtests (
+    metric_name TEXT NOT NULL,
+    method TEXT NOT NULL,
+    origins INTEGER NOT NULL,
+    forecasts INTEGER NOT NULL,
+    mae DOUBLE PRECISION NOT NULL,
+    mape DOUBLE PRECISION,
+    smape DOUBLE PRECISION NOT NULL,
+    coverage DOUBLE PRECISION NOT NULL,
+    confidence_level DOUBLE PRECISION NOT NULL,
+    selected BOOLEAN NOT NULL DEFAULT FALSE,
+    evaluated_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (metric_name, method)
+);
+
+-- Create indexes
+CREATE INDEX IF NOT EXISTS idx_forecast_backtests_selected
+    ON forecast_backtests (metric_name) WHERE selected;
+"#;
+
+/// SQL to create root cause analysis results table
+pub const CREATE_ROOT_CAUSE_ANALYSES_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS root_cause_analyses (
+    anomaly_id UUID PRIMARY KEY,
+    metric_name TEXT NOT NULL,
+    root_event_id UUID,
+    confidence DOUBLE PRECISION NOT NULL,
+    impact_severity TEXT NOT NULL,
+    report JSONB NOT NULL,
+    analyzed_at TIMESTAMPTZ NOT NULL
+);
+
+-- Create indexes
+CREATE INDEX IF NOT EXISTS idx_root_cause_analyses_analyzed_at
+    ON root_cause_analyses (analyzed_at DESC);
+CREATE INDEX IF NOT EXISTS idx_root_cause_analyses_root_event
+    ON root_cause_analyses (root_event_id);
+"#;
+
+/// SQL to create current SLO status table
+pub const CREATE_SLO_STATUS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS slo_status (
+    slo_id TEXT PRIMARY KEY,
+    slo_name TEXT NOT NULL,
+    target DOUBLE PRECISION NOT NULL,
+    window_seconds BIGINT NOT NULL,
+    attainment DOUBLE PRECISION,
+    error_budget_remaining DOUBLE PRECISION NOT NULL,
+    alert_severity TEXT,
+    status JSONB NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL
+);
+
+-- Create indexes
+CREATE INDEX IF NOT EXISTS idx_slo_status_alerting
+    ON slo_status (alert_severity) WHERE alert_severity IS NOT NULL;
+"#;
+
+/// SQL to create latest experiment verdict table
+pub const CREATE_EXPERIMENT_REPORTS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS experiment_reports (
+    experiment_id TEXT PRIMARY KEY,
+    experiment_name TEXT NOT NULL,
+    control_arm TEXT NOT NULL,
+    treatment_arm TEXT NOT NULL,
+    verdict TEXT NOT NULL,
+    stopped_early BOOLEAN NOT NULL,
+    report JSONB NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL
+);
+
+-- Create indexes
+CREATE INDEX IF NOT EXISTS idx_experiment_reports_verdict
+    ON experiment_reports (verdict);
+"#;
+
+/// SQL to create session summary and turn hypertables
+pub const CREATE_SESSION_METRICS_TABLES: &str = r#"
+CREATE TABLE IF NOT EXISTS session_metrics (
+    session_id TEXT NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    ended_at TIMESTAMPTZ NOT NULL,
+    duration_seconds DOUBLE PRECISION NOT NULL,
+    turns INTEGER NOT NULL,
+    prompt_tokens BIGINT NOT NULL,
+    completion_tokens BIGINT NOT NULL,
+    total_tokens BIGINT NOT NULL,
+    cost_usd DOUBLE PRECISION NOT NULL,
+    failed_turns INTEGER NOT NULL,
+    abandoned BOOLEAN NOT NULL,
+    abandonment_turn INTEGER,
+    PRIMARY KEY (session_id, started_at)
+);
+
+SELECT create_hypertable('session_metrics', 'started_at', if_not_exists => TRUE);
+
+CREATE TABLE IF NOT EXISTS session_turns (
+    session_id TEXT NOT NULL,
+    turn_index INTEGER NOT NULL,
+    request_id TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    ttft_ms DOUBLE PRECISION,
+    latency_ms DOUBLE PRECISION,
+    total_tokens BIGINT NOT NULL,
+    cost_usd DOUBLE PRECISION NOT NULL,
+    failed BOOLEAN NOT NULL,
+    PRIMARY KEY (session_id, turn_index, timestamp)
+);
+
+SELECT create_hypertable('session_turns', 'timestamp', if_not_exists => TRUE);
+
+-- Create indexes
+CREATE INDEX IF NOT EXISTS idx_session_metrics_started_at
+    ON session_metrics (started_at DESC);
+CREATE INDEX IF NOT EXISTS idx_session_turns_turn_index
+    ON session_turns (turn_index, timestamp DESC);
+
+-- Keep per-turn detail for 90 days
+SELECT add_retention_policy('session_turns', INTERVAL '90 days', if_not_exists => TRUE);
+"#;
+
+/// SQL to create monthly chargeback report table
+pub const CREATE_CHARGEBACK_REPORTS_TABLE: &str = r#"
+CREATE TABLE IF NOT EXISTS chargeback_reports (
+    period TEXT PRIMARY KEY,
+    period_start TIMESTAMPTZ NOT NULL,
+    period_end TIMESTAMPTZ NOT NULL,
+    total_usd DOUBLE PRECISION NOT NULL,
+    unallocated_usd DOUBLE PRECISION NOT NULL,
+    within_tolerance BOOLEAN,
+    report JSONB NOT NULL,
+    generated_at TIMESTAMPTZ NOT NULL
+);
+
+-- Create indexes
+CREATE INDEX IF NOT EXISTS idx_chargeback_reports_period_start
+    ON chargeback_reports (period_start DESC);
+"#;
+
+/// SQL to create the chargeback ledger and its booked offsets
+pub const CREATE_CHARGEBACK_LEDGER_TABLES: &str = r#"
+CREATE TABLE IF NOT EXISTS chargeback_ledger (
+    period TEXT NOT NULL,
+    account JSONB NOT NULL,
+    cost_usd DOUBLE PRECISION NOT NULL,
+    tokens BIGINT NOT NULL,
+    requests BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (period, account)
+);
+
+CREATE TABLE IF NOT EXISTS chargeback_ledger_offsets (
+    topic TEXT NOT NULL,
+    partition INTEGER NOT NULL,
+    booked_offset BIGINT NOT NULL,
+    PRIMARY KEY (topic, partition)
+);
+"#;
+
+/// SQL to create request cluster snapshot and drift tables
+pub const CREATE_REQUEST_CLUSTER_TABLES: &str = r#"
+CREATE TABLE IF NOT EXISTS request_cluster_stats (
+    cluster_id TEXT NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL,
+    requests BIGINT NOT NULL,
+    share DOUBLE PRECISION NOT NULL,
+    total_cost_usd DOUBLE PRECISION NOT NULL,
+    mean_latency_ms DOUBLE PRECISION NOT NULL,
+    p95_latency_ms DOUBLE PRECISION,
+    stats JSONB NOT NULL,
+    PRIMARY KEY (cluster_id, computed_at)
+);
+
+SELECT create_hypertable('request_cluster_stats', 'computed_at', if_not_exists => TRUE);
+
+CREATE TABLE IF NOT EXISTS request_cluster_drift (
+    fitted_at TIMESTAMPTZ PRIMARY KEY,
+    samples INTEGER NOT NULL,
+    noise_samples INTEGER NOT NULL,
+    share_psi DOUBLE PRECISION,
+    report JSONB NOT NULL
+);
+
+-- Keep cluster snapshots for 90 days
+SELECT add_retention_policy('request_cluster_stats', INTERVAL '90 days', if_not_exists => TRUE);
+"#;
+
 /// SQL to create retention policies
 pub const CREATE_RETENTION_POLICIES: &str = r#"
 -- Retention policy for events: keep raw events for 30 days
@@ -175,6 +365,14 @@ pub async fn initialize_schema(pool: &sqlx::PgPool) -> anyhow::Result<()> {
     sqlx::query(CREATE_AGGREGATED_METRICS_TABLE).execute(pool).await?;
     sqlx::query(CREATE_ANOMALIES_TABLE).execute(pool).await?;
     sqlx::query(CREATE_CORRELATIONS_TABLE).execute(pool).await?;
+    sqlx::query(CREATE_FORECAST_BACKTESTS_TABLE).execute(pool).await?;
+    sqlx::query(CREATE_ROOT_CAUSE_ANALYSES_TABLE).execute(pool).await?;
+    sqlx::query(CREATE_SLO_STATUS_TABLE).execute(pool).await?;
+    sqlx::query(CREATE_EXPERIMENT_REPORTS_TABLE).execute(pool).await?;
+    sqlx::query(CREATE_SESSION_METRICS_TABLES).execute(pool).await?;
+    sqlx::query(CREATE_CHARGEBACK_REPORTS_TABLE).execute(pool).await?;
+    sqlx::query(CREATE_REQUEST_CLUSTER_TABLES).execute(pool).await?;
+    sqlx::query(CREATE_CHARGEBACK_LEDGER_TABLES).execute(pool).await?;
 
     // Create retention policies
     sqlx::query(CREATE_RETENTION_POLICIES).execute(pool).await?;
@@ -210,6 +210,7 @@ fn cost_event_examples() {
             current_spend_usd: 8500.0,
             threshold_percent: 85.0,
             alert_type: BudgetAlertType::Warning,
+            projected_exhaustion_at: None,
         })),
     };
 
@@ -162,6 +162,11 @@ pub struct CorrelationPattern {
 
     /// Expected event sequence
     pub sequence: Vec<PatternStep>,
+
+    /// Event fields identifying the entity a sequence is tracked for
+    /// (e.g. `user_id`); empty tracks a single sequence across all events
+    #[serde(default)]
+    pub partition_by: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -171,6 +176,9 @@ pub struct PatternStep {
     pub event_type: EventType,
     pub time_offset_ms: Option<i64>, // Relative to previous step
     pub conditions: HashMap<String, String>,
+    /// The step is satisfied by the absence of a matching event
+    #[serde(default)]
+    pub negated: bool,
 }
 
 /// Anomaly correlation for detecting related anomalies
@@ -411,6 +419,21 @@ pub enum EdgeRelationship {
     Mitigates,
 }
 
+impl EdgeRelationship {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EdgeRelationship::Causes => "causes",
+            EdgeRelationship::TriggeredBy => "triggered_by",
+            EdgeRelationship::RelatedTo => "related_to",
+            EdgeRelationship::Precedes => "precedes",
+            EdgeRelationship::Follows => "follows",
+            EdgeRelationship::CorrelatesWith => "correlates_with",
+            EdgeRelationship::Amplifies => "amplifies",
+            EdgeRelationship::Mitigates => "mitigates",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GraphMetadata {
     pub node_count: usize,
@@ -420,6 +443,216 @@ pub struct GraphMetadata {
     pub density: f64,
 }
 
+impl GraphMetadata {
+    /// Compute metadata for a directed graph.
+    ///
+    /// Components are weakly connected (edge direction is ignored); density is
+    /// edges over the n * (n - 1) possible directed edges.
+    pub fn compute(nodes: &[EventNode], edges: &[EventEdge]) -> Self {
+        let index: HashMap<&str, usize> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.node_id.as_str(), i))
+            .collect();
+
+        // Union-find over node indices
+        let mut parent: Vec<usize> = (0..nodes.len()).collect();
+        fn find(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+
+        for edge in edges {
+            if let (Some(&a), Some(&b)) = (
+                index.get(edge.from_node.as_str()),
+                index.get(edge.to_node.as_str()),
+            ) {
+                let (ra, rb) = (find(&mut parent, a), find(&mut parent, b));
+                if ra != rb {
+                    parent[ra] = rb;
+                }
+            }
+        }
+
+        let connected_components = (0..nodes.len())
+            .filter(|&i| find(&mut parent, i) == i)
+            .count();
+
+        let n = nodes.len() as f64;
+        let e = edges.len() as f64;
+        Self {
+            node_count: nodes.len(),
+            edge_count: edges.len(),
+            connected_components,
+            avg_degree: if nodes.is_empty() { 0.0 } else { 2.0 * e / n },
+            density: if nodes.len() < 2 {
+                0.0
+            } else {
+                e / (n * (n - 1.0))
+            },
+        }
+    }
+}
+
+impl EventGraph {
+    /// Render the graph in Graphviz DOT format
+    pub fn to_dot(&self) -> String {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut out = format!("digraph \"{}\" {{\n", escape(&self.graph_id));
+        out.push_str("    rankdir=LR;\n    node [shape=box, style=rounded];\n");
+
+        for node in &self.nodes {
+            out.push_str(&format!(
+                "    \"{}\" [label=\"{}\"];\n",
+                escape(&node.node_id),
+                escape(&node.label()).replace('\n', "\\n")
+            ));
+        }
+        for edge in &self.edges {
+            out.push_str(&format!(
+                "    \"{}\" -> \"{}\" [label=\"{} ({:.2})\", penwidth={:.1}];\n",
+                escape(&edge.from_node),
+                escape(&edge.to_node),
+                edge.relationship_type.as_str(),
+                edge.weight,
+                1.0 + 2.0 * edge.weight.clamp(0.0, 1.0)
+            ));
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    /// Render the graph as GraphML
+    pub fn to_graphml(&self) -> String {
+        let escape = |s: &str| {
+            s.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+                .replace('\'', "&apos;")
+        };
+
+        let mut out = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+        );
+        for (id, domain, kind) in [
+            ("event_id", "node", "string"),
+            ("source_module", "node", "string"),
+            ("event_type", "node", "string"),
+            ("timestamp", "node", "string"),
+            ("relationship", "edge", "string"),
+            ("weight", "edge", "double"),
+        ] {
+            out.push_str(&format!(
+                "  <key id=\"{0}\" for=\"{1}\" attr.name=\"{0}\" attr.type=\"{2}\"/>\n",
+                id, domain, kind
+            ));
+        }
+        out.push_str(&format!(
+            "  <graph id=\"{}\" edgedefault=\"directed\">\n",
+            escape(&self.graph_id)
+        ));
+
+        for node in &self.nodes {
+            out.push_str(&format!("    <node id=\"{}\">\n", escape(&node.node_id)));
+            for (key, value) in [
+                ("event_id", node.event_id.to_string()),
+                ("source_module", serde_name(&node.source_module)),
+                ("event_type", serde_name(&node.event_type)),
+                ("timestamp", node.timestamp.to_rfc3339()),
+            ] {
+                out.push_str(&format!(
+                    "      <data key=\"{}\">{}</data>\n",
+                    key,
+                    escape(&value)
+                ));
+            }
+            out.push_str("    </node>\n");
+        }
+        for edge in &self.edges {
+            out.push_str(&format!(
+                "    <edge id=\"{}\" source=\"{}\" target=\"{}\">\n",
+                escape(&edge.edge_id),
+                escape(&edge.from_node),
+                escape(&edge.to_node)
+            ));
+            out.push_str(&format!(
+                "      <data key=\"relationship\">{}</data>\n",
+                edge.relationship_type.as_str()
+            ));
+            out.push_str(&format!(
+                "      <data key=\"weight\">{}</data>\n",
+                edge.weight
+            ));
+            out.push_str("    </edge>\n");
+        }
+
+        out.push_str("  </graph>\n</graphml>\n");
+        out
+    }
+
+    /// Render the graph as a Mermaid flowchart
+    pub fn to_mermaid(&self) -> String {
+        let escape = |s: &str| s.replace('"', "#quot;").replace('\n', "<br/>");
+        // Mermaid node IDs must be plain identifiers
+        let id = |s: &str| -> String {
+            s.chars()
+                .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+                .collect()
+        };
+        let mut out = String::from("flowchart LR\n");
+
+        for node in &self.nodes {
+            out.push_str(&format!(
+                "    {}[\"{}\"]\n",
+                id(&node.node_id),
+                escape(&node.label())
+            ));
+        }
+        for edge in &self.edges {
+            out.push_str(&format!(
+                "    {} -->|\"{} {:.2}\"| {}\n",
+                id(&edge.from_node),
+                edge.relationship_type.as_str(),
+                edge.weight,
+                id(&edge.to_node)
+            ));
+        }
+
+        out
+    }
+}
+
+impl EventNode {
+    /// Multi-line display label: module, event type, time and summary
+    pub fn label(&self) -> String {
+        let mut label = format!(
+            "{}\n{}\n{}",
+            serde_name(&self.source_module),
+            serde_name(&self.event_type),
+            self.timestamp.format("%H:%M:%S")
+        );
+        if let Some(summary) = self.attributes.get("summary") {
+            label.push('\n');
+            label.push_str(summary);
+        }
+        label
+    }
+}
+
+/// Serialized name of a unit enum variant, e.g. "llm-observatory"
+fn serde_name<T: Serialize + std::fmt::Debug>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("{:?}", value))
+}
+
 /// Correlation query for finding related events
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CorrelationQuery {
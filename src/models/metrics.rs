@@ -261,6 +261,32 @@ pub enum MetricValues {
     },
 }
 
+impl MetricValues {
+    /// Columns of a row in the aggregates table. Counters are stored as a
+    /// single reading with the value in `max` (and `min`, the percentiles and
+    /// `sum`) and the per-second rate in `avg`; gauges are not stored.
+    pub fn stored_measures(&self) -> Option<StatisticalMeasures> {
+        match self {
+            MetricValues::Stats(measures) => Some(measures.clone()),
+            MetricValues::Counter { value, rate } => {
+                let value = *value as f64;
+                Some(StatisticalMeasures {
+                    avg: *rate,
+                    min: value,
+                    max: value,
+                    p50: value,
+                    p95: value,
+                    p99: value,
+                    stddev: None,
+                    count: 1,
+                    sum: value,
+                })
+            }
+            MetricValues::Gauge { .. } => None,
+        }
+    }
+}
+
 /// Composite metric combining multiple module metrics
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompositeMetric {
@@ -363,6 +389,22 @@ pub enum StatType {
     Count,
     Sum,
     Rate,
+    Irate,
+    Delta,
+    Derivative,
+}
+
+impl StatType {
+    /// Window function computing this stat, for rate-of-change stats
+    pub fn window_function(&self) -> Option<WindowFunction> {
+        match self {
+            StatType::Rate => Some(WindowFunction::Rate),
+            StatType::Irate => Some(WindowFunction::Irate),
+            StatType::Delta => Some(WindowFunction::Delta),
+            StatType::Derivative => Some(WindowFunction::Derivative),
+            _ => None,
+        }
+    }
 }
 
 /// Metric rollup configuration for data retention
@@ -390,6 +432,41 @@ pub enum AggregationFunction {
     Max,
     Last,
     First,
+    Rate,
+    Delta,
+    Derivative,
+}
+
+impl AggregationFunction {
+    /// Window function computing this aggregation, for rate-of-change functions
+    pub fn window_function(&self) -> Option<WindowFunction> {
+        match self {
+            AggregationFunction::Rate => Some(WindowFunction::Rate),
+            AggregationFunction::Delta => Some(WindowFunction::Delta),
+            AggregationFunction::Derivative => Some(WindowFunction::Derivative),
+            _ => None,
+        }
+    }
+}
+
+/// Rate-of-change and moving-window functions over a metric's samples
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "function", rename_all = "snake_case")]
+pub enum WindowFunction {
+    /// Per-second increase over the window, adjusted for counter resets
+    Rate,
+    /// Per-second increase between the last two samples
+    Irate,
+    /// Difference between the last and first sample
+    Delta,
+    /// Per-second slope of a least-squares fit
+    Derivative,
+    /// Exponentially weighted moving average
+    Ewma { alpha: f64 },
+    /// Percentile over a trailing window
+    MovingPercentile { percentile: f64, window_secs: u64 },
+    /// Running total of sample values
+    CumulativeSum,
 }
 
 #[cfg(test)]
@@ -461,4 +538,22 @@ mod tests {
         assert!(json.contains("histogram"));
         assert!(json.contains("request_latency_ms"));
     }
+
+    #[test]
+    fn test_counter_values_are_stored_as_a_reading() {
+        let counter = MetricValues::Counter {
+            value: 1200,
+            rate: 2.5,
+        };
+        let measures = counter.stored_measures().unwrap();
+        assert_eq!(measures.max, 1200.0);
+        assert_eq!(measures.avg, 2.5);
+        assert_eq!(measures.count, 1);
+
+        let gauge = MetricValues::Gauge {
+            value: 1.0,
+            delta: None,
+        };
+        assert!(gauge.stored_measures().is_none());
+    }
 }
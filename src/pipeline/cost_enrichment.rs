@@ -0,0 +1,521 @@
+//! Cost Enrichment Stage
+//!
+//! Computes the cost of `TokenUsageMetrics` events that arrive without a
+//! matching `TokenCostEvent`, using model pricing from LLM-Registry.
+//!
+//! Prices are versioned by effective date so that backfilled usage is costed
+//! with the price in force at the time of the request. Versions come from
+//! operator-supplied pricing history and from periodic registry lookups; a
+//! changed registry price becomes a new version effective from the model's
+//! `last_updated` time.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::adapters::registry::{ModelPricing, RegistryAdapter};
+use crate::schemas::events::{
+    AnalyticsEvent, CommonEventFields, CostPayload, EventPayload, EventType, TelemetryPayload,
+    TokenCostEvent, TokenUsageMetrics,
+};
+
+/// How computed costs are emitted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CostEnrichmentMode {
+    /// Emit a derived `CostPayload::TokenCost` event next to the usage event
+    Synthesize,
+    /// Attach `cost_*` tags to the usage event itself
+    Attach,
+}
+
+/// Cost enrichment configuration
+#[derive(Debug, Clone)]
+pub struct CostEnrichmentConfig {
+    pub mode: CostEnrichmentMode,
+    /// How long registry pricing is cached before it is looked up again
+    pub refresh_interval: Duration,
+    /// How long request IDs of cost events are remembered for de-duplication
+    pub dedup_window: Duration,
+}
+
+impl Default for CostEnrichmentConfig {
+    fn default() -> Self {
+        Self {
+            mode: CostEnrichmentMode::Synthesize,
+            refresh_interval: Duration::hours(1),
+            dedup_window: Duration::hours(1),
+        }
+    }
+}
+
+/// Model pricing in force from `effective_from` until the next version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricingVersion {
+    pub model_id: String,
+    pub effective_from: DateTime<Utc>,
+    pub pricing: ModelPricing,
+}
+
+/// Pricing applied to a request
+#[derive(Debug, Clone)]
+pub struct PriceQuote {
+    pub effective_from: DateTime<Utc>,
+    pub pricing: ModelPricing,
+    /// The request predates every known version; the earliest was used
+    pub approximate: bool,
+}
+
+/// Enrichment counters
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CostEnrichmentStats {
+    pub events_costed: u64,
+    pub events_already_costed: u64,
+    pub events_skipped: u64,
+    pub registry_lookups: u64,
+    pub registry_failures: u64,
+}
+
+#[derive(Debug, Default)]
+struct ModelPrices {
+    versions: BTreeMap<DateTime<Utc>, ModelPricing>,
+    refreshed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    events_costed: AtomicU64,
+    events_already_costed: AtomicU64,
+    events_skipped: AtomicU64,
+    registry_lookups: AtomicU64,
+    registry_failures: AtomicU64,
+}
+
+/// Enrichment stage computing token costs from registry pricing
+pub struct CostEnricher {
+    config: CostEnrichmentConfig,
+    registry: Arc<RegistryAdapter>,
+    // Model ID -> Pricing versions
+    prices: Arc<DashMap<String, ModelPrices>>,
+    // Request ID -> Time its cost was seen
+    costed_requests: Arc<DashMap<String, DateTime<Utc>>>,
+    counters: Counters,
+}
+
+impl CostEnricher {
+    /// Create a new cost enricher
+    pub fn new(config: CostEnrichmentConfig, registry: Arc<RegistryAdapter>) -> Self {
+        Self {
+            config,
+            registry,
+            prices: Arc::new(DashMap::new()),
+            costed_requests: Arc::new(DashMap::new()),
+            counters: Counters::default(),
+        }
+    }
+
+    /// Add a historical pricing version
+    pub fn add_version(&self, version: PricingVersion) {
+        self.prices
+            .entry(version.model_id)
+            .or_default()
+            .versions
+            .insert(version.effective_from, version.pricing);
+    }
+
+    /// Load a pricing history file (JSON array of `PricingVersion`)
+    pub fn load_history(&self, path: &str) -> Result<usize> {
+        let versions: Vec<PricingVersion> = serde_json::from_str(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read pricing history {}", path))?,
+        )
+        .with_context(|| format!("Invalid pricing history {}", path))?;
+
+        let count = versions.len();
+        for version in versions {
+            self.add_version(version);
+        }
+        info!("Loaded {} pricing versions from {}", count, path);
+        Ok(count)
+    }
+
+    /// Pricing versions known for a model, oldest first
+    pub fn versions(&self, model_id: &str) -> Vec<PricingVersion> {
+        self.prices
+            .get(model_id)
+            .map(|prices| {
+                prices
+                    .versions
+                    .iter()
+                    .map(|(effective_from, pricing)| PricingVersion {
+                        model_id: model_id.to_string(),
+                        effective_from: *effective_from,
+                        pricing: pricing.clone(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Pricing in force for a model at `at`, refreshing from the registry
+    /// when the cached pricing is stale
+    pub async fn price_at(&self, model_id: &str, at: DateTime<Utc>) -> Result<PriceQuote> {
+        let now = Utc::now();
+        let stale = self
+            .prices
+            .get(model_id)
+            .and_then(|p| p.refreshed_at)
+            .map_or(true, |refreshed| {
+                now - refreshed >= self.config.refresh_interval
+            });
+
+        if stale {
+            self.counters
+                .registry_lookups
+                .fetch_add(1, Ordering::Relaxed);
+            match self.registry.fetch_model(model_id).await {
+                Ok(metadata) => self.apply_registry_pricing(
+                    model_id,
+                    metadata.pricing,
+                    metadata.last_updated,
+                    now,
+                ),
+                Err(e) => {
+                    self.counters
+                        .registry_failures
+                        .fetch_add(1, Ordering::Relaxed);
+                    warn!(model_id = %model_id, "Registry pricing lookup failed: {}", e);
+                    // Keep serving cached versions until the next refresh
+                    self.prices
+                        .entry(model_id.to_string())
+                        .or_default()
+                        .refreshed_at = Some(now);
+                }
+            }
+        }
+
+        let prices = self
+            .prices
+            .get(model_id)
+            .with_context(|| format!("No pricing known for model {}", model_id))?;
+        let quote = match prices.versions.range(..=at).next_back() {
+            Some((effective_from, pricing)) => PriceQuote {
+                effective_from: *effective_from,
+                pricing: pricing.clone(),
+                approximate: false,
+            },
+            None => {
+                let (effective_from, pricing) = prices
+                    .versions
+                    .iter()
+                    .next()
+                    .with_context(|| format!("No pricing known for model {}", model_id))?;
+                PriceQuote {
+                    effective_from: *effective_from,
+                    pricing: pricing.clone(),
+                    approximate: true,
+                }
+            }
+        };
+        Ok(quote)
+    }
+
+    /// Record registry pricing as a new version if it differs from the latest
+    fn apply_registry_pricing(
+        &self,
+        model_id: &str,
+        pricing: ModelPricing,
+        last_updated: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) {
+        let mut prices = self.prices.entry(model_id.to_string()).or_default();
+        prices.refreshed_at = Some(now);
+
+        let latest = prices.versions.iter().next_back();
+        if latest.is_some_and(|(_, current)| same_price(current, &pricing)) {
+            return;
+        }
+        // A price change reported with an older timestamp than the latest
+        // known version only applies from the time it was observed
+        let effective_from = match latest {
+            Some((latest_from, _)) if *latest_from >= last_updated => now,
+            _ => last_updated,
+        };
+        debug!(model_id = %model_id, %effective_from, "New registry pricing version");
+        prices.versions.insert(effective_from, pricing);
+    }
+
+    /// Enrich an event. Token usage without a known cost is priced: in
+    /// synthesize mode the derived cost event is returned, in attach mode the
+    /// cost is added to the event's tags. Other events pass through unchanged.
+    pub async fn enrich(&self, event: &mut AnalyticsEvent) -> Result<Option<AnalyticsEvent>> {
+        let usage = match &event.payload {
+            EventPayload::Cost(CostPayload::TokenCost(cost)) => {
+                self.mark_costed(&cost.request_id, event.common.timestamp);
+                return Ok(None);
+            }
+            EventPayload::Telemetry(TelemetryPayload::TokenUsage(usage)) => usage.clone(),
+            _ => return Ok(None),
+        };
+
+        if self.costed_requests.contains_key(&usage.request_id)
+            || event.common.tags.contains_key("cost_usd")
+        {
+            self.counters
+                .events_already_costed
+                .fetch_add(1, Ordering::Relaxed);
+            return Ok(None);
+        }
+
+        let quote = match self.price_at(&usage.model_id, event.common.timestamp).await {
+            Ok(quote) => quote,
+            Err(e) => {
+                self.counters.events_skipped.fetch_add(1, Ordering::Relaxed);
+                return Err(e);
+            }
+        };
+        if !quote.pricing.currency.eq_ignore_ascii_case("USD") {
+            self.counters.events_skipped.fetch_add(1, Ordering::Relaxed);
+            anyhow::bail!(
+                "Pricing for model {} is in {}, not USD",
+                usage.model_id,
+                quote.pricing.currency
+            );
+        }
+
+        let cost = token_cost(&usage, &quote.pricing);
+        self.mark_costed(&usage.request_id, event.common.timestamp);
+        self.counters.events_costed.fetch_add(1, Ordering::Relaxed);
+
+        match self.config.mode {
+            CostEnrichmentMode::Attach => {
+                let tags = &mut event.common.tags;
+                tags.insert("cost_usd".to_string(), format!("{}", cost.total_cost_usd));
+                tags.insert("cost_currency".to_string(), cost.currency.clone());
+                tags.insert(
+                    "cost_pricing_effective_from".to_string(),
+                    quote.effective_from.to_rfc3339(),
+                );
+                if quote.approximate {
+                    tags.insert("cost_pricing_approximate".to_string(), "true".to_string());
+                }
+                Ok(None)
+            }
+            CostEnrichmentMode::Synthesize => Ok(Some(cost_event(event, cost, &quote))),
+        }
+    }
+
+    /// Enrichment counters
+    pub fn stats(&self) -> CostEnrichmentStats {
+        let load = |c: &AtomicU64| c.load(Ordering::Relaxed);
+        CostEnrichmentStats {
+            events_costed: load(&self.counters.events_costed),
+            events_already_costed: load(&self.counters.events_already_costed),
+            events_skipped: load(&self.counters.events_skipped),
+            registry_lookups: load(&self.counters.registry_lookups),
+            registry_failures: load(&self.counters.registry_failures),
+        }
+    }
+
+    fn mark_costed(&self, request_id: &str, at: DateTime<Utc>) {
+        self.costed_requests.insert(request_id.to_string(), at);
+
+        let horizon = at - self.config.dedup_window;
+        if self.costed_requests.len() % 1024 == 0 {
+            self.costed_requests.retain(|_, seen| *seen >= horizon);
+        }
+    }
+}
+
+fn same_price(a: &ModelPricing, b: &ModelPricing) -> bool {
+    a.currency == b.currency
+        && a.input_cost_per_1k_tokens == b.input_cost_per_1k_tokens
+        && a.output_cost_per_1k_tokens == b.output_cost_per_1k_tokens
+}
+
+/// Cost of a request under the given pricing
+pub fn token_cost(usage: &TokenUsageMetrics, pricing: &ModelPricing) -> TokenCostEvent {
+    let cost_per_prompt_token = pricing.input_cost_per_1k_tokens / 1000.0;
+    let cost_per_completion_token = pricing.output_cost_per_1k_tokens / 1000.0;
+
+    TokenCostEvent {
+        model_id: usage.model_id.clone(),
+        request_id: usage.request_id.clone(),
+        prompt_tokens: usage.prompt_tokens,
+        completion_tokens: usage.completion_tokens,
+        total_tokens: usage.total_tokens,
+        cost_per_prompt_token,
+        cost_per_completion_token,
+        total_cost_usd: usage.prompt_tokens as f64 * cost_per_prompt_token
+            + usage.completion_tokens as f64 * cost_per_completion_token,
+        currency: pricing.currency.to_uppercase(),
+    }
+}
+
+/// Derived cost event sharing the usage event's time, tags and correlation
+fn cost_event(usage: &AnalyticsEvent, cost: TokenCostEvent, quote: &PriceQuote) -> AnalyticsEvent {
+    let mut tags = usage.common.tags.clone();
+    tags.insert("cost_source".to_string(), "registry_pricing".to_string());
+    tags.insert(
+        "cost_pricing_effective_from".to_string(),
+        quote.effective_from.to_rfc3339(),
+    );
+    if quote.approximate {
+        tags.insert("cost_pricing_approximate".to_string(), "true".to_string());
+    }
+
+    AnalyticsEvent {
+        common: CommonEventFields {
+            event_id: Uuid::new_v4(),
+            timestamp: usage.common.timestamp,
+            source_module: usage.common.source_module.clone(),
+            event_type: EventType::Cost,
+            correlation_id: usage.common.correlation_id.or(Some(usage.common.event_id)),
+            parent_event_id: Some(usage.common.event_id),
+            schema_version: usage.common.schema_version.clone(),
+            severity: usage.common.severity.clone(),
+            environment: usage.common.environment.clone(),
+            tags,
+        },
+        payload: EventPayload::Cost(CostPayload::TokenCost(cost)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adapters::registry::RegistryConfig;
+    use crate::adapters::EcosystemAdapter;
+    use crate::schemas::events::fixtures;
+    use chrono::TimeZone;
+
+    fn pricing(input: f64, output: f64) -> ModelPricing {
+        ModelPricing {
+            currency: "USD".to_string(),
+            input_cost_per_1k_tokens: input,
+            output_cost_per_1k_tokens: output,
+            image_cost_per_unit: None,
+            audio_cost_per_minute: None,
+        }
+    }
+
+    fn day(month: u32, day: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, month, day, 0, 0, 0).unwrap()
+    }
+
+    fn usage(timestamp: DateTime<Utc>, request_id: &str) -> AnalyticsEvent {
+        fixtures::telemetry(TelemetryPayload::TokenUsage(TokenUsageMetrics {
+            model_id: "gpt-4".to_string(),
+            request_id: request_id.to_string(),
+            prompt_tokens: 1000,
+            completion_tokens: 500,
+            total_tokens: 1500,
+        }))
+        .at(timestamp)
+        .with_tags(&[("team", "search")])
+    }
+
+    async fn enricher(mode: CostEnrichmentMode) -> CostEnricher {
+        let registry = RegistryAdapter::new(RegistryConfig {
+            endpoint: "http://localhost:8084".to_string(),
+            api_key: None,
+            timeout_secs: 5,
+        });
+        registry.connect().await.unwrap();
+
+        let enricher = CostEnricher::new(
+            CostEnrichmentConfig {
+                mode,
+                ..Default::default()
+            },
+            Arc::new(registry),
+        );
+        for (effective_from, price) in [
+            (day(1, 1), pricing(0.03, 0.06)),
+            (day(6, 1), pricing(0.01, 0.03)),
+        ] {
+            enricher.add_version(PricingVersion {
+                model_id: "gpt-4".to_string(),
+                effective_from,
+                pricing: price,
+            });
+        }
+        enricher
+    }
+
+    #[tokio::test]
+    async fn test_backfill_uses_historical_pricing() {
+        let enricher = enricher(CostEnrichmentMode::Synthesize).await;
+
+        let mut march = usage(day(3, 15), "req-1");
+        let cost_event = enricher.enrich(&mut march).await.unwrap().unwrap();
+        let EventPayload::Cost(CostPayload::TokenCost(cost)) = &cost_event.payload else {
+            panic!("expected a token cost event");
+        };
+        assert!((cost.total_cost_usd - 0.06).abs() < 1e-12);
+        assert_eq!(
+            cost_event.common.parent_event_id,
+            Some(march.common.event_id)
+        );
+        assert_eq!(cost_event.common.tags["team"], "search");
+
+        let mut july = usage(day(7, 15), "req-2");
+        let cost_event = enricher.enrich(&mut july).await.unwrap().unwrap();
+        let EventPayload::Cost(CostPayload::TokenCost(cost)) = &cost_event.payload else {
+            panic!("expected a token cost event");
+        };
+        assert!((cost.total_cost_usd - 0.025).abs() < 1e-12);
+        assert_eq!(
+            cost_event.common.tags["cost_pricing_effective_from"],
+            day(6, 1).to_rfc3339()
+        );
+
+        // The registry's current (placeholder) price became a new version
+        assert_eq!(enricher.versions("gpt-4").len(), 3);
+        assert_eq!(enricher.stats().registry_lookups, 1);
+    }
+
+    #[tokio::test]
+    async fn test_attach_mode_tags_usage_events() {
+        let enricher = enricher(CostEnrichmentMode::Attach).await;
+
+        let mut event = usage(day(3, 15), "req-1");
+        assert!(enricher.enrich(&mut event).await.unwrap().is_none());
+        let cost: f64 = event.common.tags["cost_usd"].parse().unwrap();
+        assert!((cost - 0.06).abs() < 1e-12);
+        assert_eq!(event.common.tags["cost_currency"], "USD");
+
+        let mut early = usage(Utc.with_ymd_and_hms(2025, 12, 1, 0, 0, 0).unwrap(), "req-0");
+        enricher.enrich(&mut early).await.unwrap();
+        assert_eq!(early.common.tags["cost_pricing_approximate"], "true");
+    }
+
+    #[tokio::test]
+    async fn test_requests_with_cost_events_are_not_priced_again() {
+        let enricher = enricher(CostEnrichmentMode::Synthesize).await;
+
+        let mut cost = usage(day(3, 15), "req-1");
+        cost.payload = EventPayload::Cost(CostPayload::TokenCost(token_cost(
+            &TokenUsageMetrics {
+                model_id: "gpt-4".to_string(),
+                request_id: "req-1".to_string(),
+                prompt_tokens: 1000,
+                completion_tokens: 500,
+                total_tokens: 1500,
+            },
+            &pricing(0.03, 0.06),
+        )));
+        assert!(enricher.enrich(&mut cost).await.unwrap().is_none());
+
+        let mut event = usage(day(3, 15), "req-1");
+        assert!(enricher.enrich(&mut event).await.unwrap().is_none());
+        assert_eq!(enricher.stats().events_already_costed, 1);
+        assert_eq!(enricher.stats().events_costed, 0);
+    }
+}
@@ -0,0 +1,652 @@
+//! Dead Letter Queue
+//!
+//! Records that fail a pipeline stage are written to the DLQ topic with
+//! their original payload and headers describing the failure: error class
+//! and cause, stage, attempt count and the original topic, partition and
+//! offset. The inspection side reads the topic back for triage and replays
+//! or purges records.
+//!
+//! Kafka retains DLQ records until they expire, so replays and purges are
+//! tracked in a compacted ledger topic keyed by record ID. Records already
+//! in the ledger are skipped, which makes replays idempotent; a replay
+//! interrupted before its ledger write is absorbed downstream, where events
+//! are stored by event ID.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::message::{Header, Headers, Message, OwnedHeaders, OwnedMessage};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{Offset, TopicPartitionList};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::time::Duration;
+
+/// DLQ header names
+pub mod headers {
+    pub const ERROR_CLASS: &str = "dlq.error_class";
+    pub const ERROR: &str = "dlq.error";
+    pub const STAGE: &str = "dlq.stage";
+    pub const ATTEMPTS: &str = "dlq.attempts";
+    pub const ORIGINAL_TOPIC: &str = "dlq.original_topic";
+    pub const ORIGINAL_PARTITION: &str = "dlq.original_partition";
+    pub const ORIGINAL_OFFSET: &str = "dlq.original_offset";
+    pub const FAILED_AT: &str = "dlq.failed_at";
+    /// Set on replayed messages to the ID of the DLQ record they came from
+    pub const REPLAY_OF: &str = "dlq.replay_of";
+}
+
+/// Broad cause of a DLQ record, used to group records for triage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorClass {
+    Deserialization,
+    Validation,
+    Transform,
+    Storage,
+    Processing,
+    Unknown,
+}
+
+impl ErrorClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Deserialization => "deserialization",
+            Self::Validation => "validation",
+            Self::Transform => "transform",
+            Self::Storage => "storage",
+            Self::Processing => "processing",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+impl std::str::FromStr for ErrorClass {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "deserialization" => Ok(Self::Deserialization),
+            "validation" => Ok(Self::Validation),
+            "transform" => Ok(Self::Transform),
+            "storage" => Ok(Self::Storage),
+            "processing" => Ok(Self::Processing),
+            "unknown" => Ok(Self::Unknown),
+            other => bail!("unknown error class: {}", other),
+        }
+    }
+}
+
+/// Failure details carried in DLQ record headers
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DlqMetadata {
+    pub error_class: ErrorClass,
+    pub error: String,
+    pub stage: String,
+    pub attempts: u32,
+    pub original_topic: String,
+    pub original_partition: i32,
+    pub original_offset: i64,
+    pub failed_at: DateTime<Utc>,
+}
+
+impl DlqMetadata {
+    pub fn to_headers(&self) -> OwnedHeaders {
+        let values = [
+            (headers::ERROR_CLASS, self.error_class.as_str().to_string()),
+            (headers::ERROR, self.error.clone()),
+            (headers::STAGE, self.stage.clone()),
+            (headers::ATTEMPTS, self.attempts.to_string()),
+            (headers::ORIGINAL_TOPIC, self.original_topic.clone()),
+            (
+                headers::ORIGINAL_PARTITION,
+                self.original_partition.to_string(),
+            ),
+            (headers::ORIGINAL_OFFSET, self.original_offset.to_string()),
+            (headers::FAILED_AT, self.failed_at.to_rfc3339()),
+        ];
+        values
+            .iter()
+            .fold(OwnedHeaders::new(), |headers, (key, value)| {
+                headers.insert(Header {
+                    key,
+                    value: Some(value.as_str()),
+                })
+            })
+    }
+
+    /// Read failure details from headers. Records written before headers
+    /// were added have no class and are reported as `Unknown`.
+    pub fn from_headers<H: Headers>(record_headers: Option<&H>) -> Result<Self> {
+        let mut values: HashMap<&str, String> = HashMap::new();
+        if let Some(record_headers) = record_headers {
+            for header in record_headers.iter() {
+                if let Some(value) = header.value {
+                    values.insert(header.key, String::from_utf8_lossy(value).into_owned());
+                }
+            }
+        }
+        let get = |key: &str| values.get(key).cloned().unwrap_or_default();
+        let parse_number = |key: &str| -> Result<i64> {
+            match values.get(key) {
+                Some(value) => value
+                    .parse()
+                    .with_context(|| format!("Invalid {} header: {}", key, value)),
+                None => Ok(-1),
+            }
+        };
+
+        Ok(Self {
+            error_class: values
+                .get(headers::ERROR_CLASS)
+                .map(|v| v.parse())
+                .transpose()?
+                .unwrap_or(ErrorClass::Unknown),
+            error: get(headers::ERROR),
+            stage: get(headers::STAGE),
+            attempts: parse_number(headers::ATTEMPTS)?.max(0) as u32,
+            original_topic: get(headers::ORIGINAL_TOPIC),
+            original_partition: parse_number(headers::ORIGINAL_PARTITION)? as i32,
+            original_offset: parse_number(headers::ORIGINAL_OFFSET)?,
+            failed_at: match values.get(headers::FAILED_AT) {
+                Some(value) => DateTime::parse_from_rfc3339(value)
+                    .with_context(|| format!("Invalid {} header: {}", headers::FAILED_AT, value))?
+                    .with_timezone(&Utc),
+                None => DateTime::<Utc>::UNIX_EPOCH,
+            },
+        })
+    }
+}
+
+/// A record read back from the DLQ topic
+#[derive(Debug, Clone)]
+pub struct DlqRecord {
+    /// `<partition>-<offset>` within the DLQ topic
+    pub id: String,
+    pub partition: i32,
+    pub offset: i64,
+    pub key: Option<String>,
+    pub metadata: DlqMetadata,
+    pub payload: Vec<u8>,
+}
+
+impl DlqRecord {
+    pub fn from_message<M: Message>(message: &M) -> Result<Self> {
+        Ok(Self {
+            id: record_id(message.partition(), message.offset()),
+            partition: message.partition(),
+            offset: message.offset(),
+            key: message
+                .key()
+                .map(|k| String::from_utf8_lossy(k).into_owned()),
+            metadata: DlqMetadata::from_headers(message.headers())?,
+            payload: message.payload().unwrap_or_default().to_vec(),
+        })
+    }
+}
+
+/// ID of the record at a DLQ position
+pub fn record_id(partition: i32, offset: i64) -> String {
+    format!("{}-{}", partition, offset)
+}
+
+/// Ledger topic tracking replays and purges of a DLQ topic
+pub fn ledger_topic(dlq_topic: &str) -> String {
+    format!("{}-ledger", dlq_topic)
+}
+
+/// What has been done with a DLQ record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DlqDisposition {
+    Replayed,
+    Purged,
+}
+
+/// Ledger entry for a DLQ record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub disposition: DlqDisposition,
+    pub at: DateTime<Utc>,
+    /// Topic the record was replayed to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_topic: Option<String>,
+}
+
+/// Writes failed records to the DLQ topic
+#[derive(Clone)]
+pub struct DlqProducer {
+    producer: FutureProducer,
+    topic: String,
+}
+
+impl DlqProducer {
+    pub fn new(producer: FutureProducer, topic: impl Into<String>) -> Self {
+        Self {
+            producer,
+            topic: topic.into(),
+        }
+    }
+
+    pub fn topic(&self) -> &str {
+        &self.topic
+    }
+
+    pub async fn send(
+        &self,
+        key: Option<&[u8]>,
+        payload: &[u8],
+        metadata: &DlqMetadata,
+    ) -> Result<()> {
+        let mut record = FutureRecord::to(&self.topic)
+            .payload(payload)
+            .headers(metadata.to_headers());
+        if let Some(key) = key {
+            record = record.key(key);
+        }
+        self.producer
+            .send(record, Duration::from_secs(5))
+            .await
+            .map_err(|(e, _)| anyhow!("Failed to send to DLQ {}: {}", self.topic, e))?;
+        Ok(())
+    }
+}
+
+/// Read every message currently in `topic`, from the low to the high
+/// watermark of each partition
+pub fn read_topic(
+    consumer: &BaseConsumer,
+    topic: &str,
+    timeout: Duration,
+) -> Result<Vec<OwnedMessage>> {
+    let metadata = consumer
+        .fetch_metadata(Some(topic), timeout)
+        .context("Failed to fetch metadata")?;
+    let partitions = metadata
+        .topics()
+        .iter()
+        .find(|t| t.name() == topic)
+        .ok_or_else(|| anyhow!("Topic not found: {}", topic))?
+        .partitions();
+
+    let mut tpl = TopicPartitionList::new();
+    // Partition -> high watermark
+    let mut remaining: HashMap<i32, i64> = HashMap::new();
+    for partition in partitions {
+        let (low, high) = consumer.fetch_watermarks(topic, partition.id(), timeout)?;
+        if high > low {
+            tpl.add_partition_offset(topic, partition.id(), Offset::Offset(low))?;
+            remaining.insert(partition.id(), high);
+        }
+    }
+    if remaining.is_empty() {
+        return Ok(Vec::new());
+    }
+    consumer.assign(&tpl)?;
+
+    let mut messages = Vec::new();
+    while !remaining.is_empty() {
+        let message = consumer
+            .poll(timeout)
+            .ok_or_else(|| anyhow!("Timed out reading {}", topic))??;
+        let (partition, offset) = (message.partition(), message.offset());
+        if remaining
+            .get(&partition)
+            .is_some_and(|high| offset + 1 >= *high)
+        {
+            remaining.remove(&partition);
+        }
+        messages.push(message.detach());
+    }
+    consumer.unassign()?;
+
+    Ok(messages)
+}
+
+/// Read the ledger of a DLQ topic; later entries override earlier ones
+pub fn read_ledger(
+    consumer: &BaseConsumer,
+    dlq_topic: &str,
+    timeout: Duration,
+) -> Result<HashMap<String, LedgerEntry>> {
+    let topic = ledger_topic(dlq_topic);
+    let metadata = consumer.fetch_metadata(Some(&topic), timeout)?;
+    // Nothing has been replayed or purged before the ledger exists
+    if !metadata
+        .topics()
+        .iter()
+        .any(|t| t.name() == topic && !t.partitions().is_empty())
+    {
+        return Ok(HashMap::new());
+    }
+
+    let mut ledger = HashMap::new();
+    for message in read_topic(consumer, &topic, timeout)? {
+        let Some(key) = message.key() else {
+            continue;
+        };
+        let id = String::from_utf8_lossy(key).into_owned();
+        match message.payload() {
+            Some(payload) => {
+                ledger.insert(id, serde_json::from_slice(payload)?);
+            }
+            None => {
+                ledger.remove(&id);
+            }
+        }
+    }
+    Ok(ledger)
+}
+
+/// Records of one error class
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorClassSummary {
+    pub error_class: ErrorClass,
+    pub count: usize,
+    pub stages: BTreeSet<String>,
+    pub original_topics: BTreeSet<String>,
+    pub first_failed_at: DateTime<Utc>,
+    pub last_failed_at: DateTime<Utc>,
+    /// Most frequent error message
+    pub top_error: String,
+}
+
+/// Group records by error class, most frequent class first
+pub fn summarize(records: &[DlqRecord]) -> Vec<ErrorClassSummary> {
+    let mut groups: BTreeMap<ErrorClass, Vec<&DlqRecord>> = BTreeMap::new();
+    for record in records {
+        groups
+            .entry(record.metadata.error_class)
+            .or_default()
+            .push(record);
+    }
+
+    let mut summaries: Vec<ErrorClassSummary> = groups
+        .into_iter()
+        .map(|(error_class, records)| {
+            let mut errors: HashMap<&str, usize> = HashMap::new();
+            for record in &records {
+                *errors.entry(record.metadata.error.as_str()).or_default() += 1;
+            }
+            let top_error = errors
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(a.0)))
+                .map(|(error, _)| error.to_string())
+                .unwrap_or_default();
+
+            ErrorClassSummary {
+                error_class,
+                count: records.len(),
+                stages: records.iter().map(|r| r.metadata.stage.clone()).collect(),
+                original_topics: records
+                    .iter()
+                    .map(|r| r.metadata.original_topic.clone())
+                    .collect(),
+                first_failed_at: records.iter().map(|r| r.metadata.failed_at).min().unwrap(),
+                last_failed_at: records.iter().map(|r| r.metadata.failed_at).max().unwrap(),
+                top_error,
+            }
+        })
+        .collect();
+    summaries.sort_by_key(|s| std::cmp::Reverse(s.count));
+    summaries
+}
+
+/// Selects DLQ records for replay or purge; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct DlqFilter {
+    pub error_class: Option<ErrorClass>,
+    pub stage: Option<String>,
+    pub ids: Vec<String>,
+    pub failed_after: Option<DateTime<Utc>>,
+}
+
+impl DlqFilter {
+    pub fn matches(&self, record: &DlqRecord) -> bool {
+        self.error_class
+            .map_or(true, |c| c == record.metadata.error_class)
+            && self
+                .stage
+                .as_ref()
+                .map_or(true, |s| *s == record.metadata.stage)
+            && (self.ids.is_empty() || self.ids.contains(&record.id))
+            && self
+                .failed_after
+                .map_or(true, |t| record.metadata.failed_at >= t)
+    }
+}
+
+/// Apply an RFC 7396 JSON merge patch
+pub fn merge_patch(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = serde_json::Value::Object(Default::default());
+    }
+    let target = target.as_object_mut().unwrap();
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(
+                target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                value,
+            );
+        }
+    }
+}
+
+/// Payload to replay for a record, with an optional merge patch applied
+pub fn replay_payload(record: &DlqRecord, patch: Option<&serde_json::Value>) -> Result<Vec<u8>> {
+    let Some(patch) = patch else {
+        return Ok(record.payload.clone());
+    };
+    let mut payload: serde_json::Value = serde_json::from_slice(&record.payload)
+        .with_context(|| format!("Record {} is not JSON and cannot be patched", record.id))?;
+    merge_patch(&mut payload, patch);
+    Ok(serde_json::to_vec(&payload)?)
+}
+
+/// Outcome of a replay run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReplayReport {
+    pub replayed: usize,
+    /// Already replayed or purged
+    pub skipped: usize,
+    /// Excluded by the filter
+    pub filtered: usize,
+    pub failed: Vec<(String, String)>,
+}
+
+/// Replays DLQ records to their original topic, recording each in the ledger
+pub struct DlqReplayer {
+    producer: FutureProducer,
+    dlq_topic: String,
+}
+
+impl DlqReplayer {
+    pub fn new(producer: FutureProducer, dlq_topic: impl Into<String>) -> Self {
+        Self {
+            producer,
+            dlq_topic: dlq_topic.into(),
+        }
+    }
+
+    /// Replay matching records not yet in the ledger. Records go to
+    /// `target_topic` if set, otherwise to their original topic.
+    pub async fn replay(
+        &self,
+        records: &[DlqRecord],
+        ledger: &HashMap<String, LedgerEntry>,
+        filter: &DlqFilter,
+        patch: Option<&serde_json::Value>,
+        target_topic: Option<&str>,
+    ) -> ReplayReport {
+        let mut report = ReplayReport::default();
+        for record in records {
+            if !filter.matches(record) {
+                report.filtered += 1;
+                continue;
+            }
+            if ledger.contains_key(&record.id) {
+                report.skipped += 1;
+                continue;
+            }
+            match self.replay_record(record, patch, target_topic).await {
+                Ok(()) => report.replayed += 1,
+                Err(e) => report.failed.push((record.id.clone(), e.to_string())),
+            }
+        }
+        report
+    }
+
+    async fn replay_record(
+        &self,
+        record: &DlqRecord,
+        patch: Option<&serde_json::Value>,
+        target_topic: Option<&str>,
+    ) -> Result<()> {
+        let topic = target_topic.unwrap_or(&record.metadata.original_topic);
+        if topic.is_empty() {
+            bail!("Record {} has no original topic", record.id);
+        }
+        let payload = replay_payload(record, patch)?;
+
+        let mut message =
+            FutureRecord::to(topic)
+                .payload(&payload)
+                .headers(OwnedHeaders::new().insert(Header {
+                    key: headers::REPLAY_OF,
+                    value: Some(record.id.as_str()),
+                }));
+        if let Some(key) = &record.key {
+            message = message.key(key);
+        }
+        self.producer
+            .send(message, Duration::from_secs(5))
+            .await
+            .map_err(|(e, _)| anyhow!("Failed to replay to {}: {}", topic, e))?;
+
+        self.record_disposition(&record.id, DlqDisposition::Replayed, Some(topic))
+            .await
+    }
+
+    /// Mark records as purged so they are no longer listed or replayed
+    pub async fn purge(&self, ids: &[String]) -> Result<usize> {
+        for id in ids {
+            self.record_disposition(id, DlqDisposition::Purged, None)
+                .await?;
+        }
+        Ok(ids.len())
+    }
+
+    async fn record_disposition(
+        &self,
+        id: &str,
+        disposition: DlqDisposition,
+        target_topic: Option<&str>,
+    ) -> Result<()> {
+        let entry = serde_json::to_vec(&LedgerEntry {
+            disposition,
+            at: Utc::now(),
+            target_topic: target_topic.map(str::to_string),
+        })?;
+        let ledger = ledger_topic(&self.dlq_topic);
+        self.producer
+            .send(
+                FutureRecord::to(&ledger).key(id).payload(&entry),
+                Duration::from_secs(5),
+            )
+            .await
+            .map_err(|(e, _)| anyhow!("Failed to write ledger {}: {}", ledger, e))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(offset: i64, class: ErrorClass, stage: &str, error: &str) -> DlqRecord {
+        DlqRecord {
+            id: record_id(0, offset),
+            partition: 0,
+            offset,
+            key: None,
+            metadata: DlqMetadata {
+                error_class: class,
+                error: error.to_string(),
+                stage: stage.to_string(),
+                attempts: 1,
+                original_topic: "llm-analytics-events".to_string(),
+                original_partition: 3,
+                original_offset: 100 + offset,
+                failed_at: Utc::now(),
+            },
+            payload: br#"{"model":"gpt-4","tokens":12}"#.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_metadata_roundtrips_through_headers() {
+        let metadata = record(7, ErrorClass::Storage, "ingestion", "connection reset").metadata;
+        let headers = metadata.to_headers();
+        let mut parsed = DlqMetadata::from_headers(Some(&headers)).unwrap();
+        assert_eq!(parsed.failed_at.timestamp(), metadata.failed_at.timestamp());
+        parsed.failed_at = metadata.failed_at;
+        assert_eq!(parsed, metadata);
+
+        let legacy = DlqMetadata::from_headers::<OwnedHeaders>(None).unwrap();
+        assert_eq!(legacy.error_class, ErrorClass::Unknown);
+        assert_eq!(legacy.original_offset, -1);
+    }
+
+    #[test]
+    fn test_summary_groups_by_error_class() {
+        let records = vec![
+            record(
+                0,
+                ErrorClass::Deserialization,
+                "ingestion",
+                "expected value",
+            ),
+            record(
+                1,
+                ErrorClass::Deserialization,
+                "ingestion",
+                "expected value",
+            ),
+            record(2, ErrorClass::Deserialization, "ingestion", "missing field"),
+            record(3, ErrorClass::Storage, "storage", "connection reset"),
+        ];
+        let summary = summarize(&records);
+
+        assert_eq!(summary.len(), 2);
+        assert_eq!(summary[0].error_class, ErrorClass::Deserialization);
+        assert_eq!(summary[0].count, 3);
+        assert_eq!(summary[0].top_error, "expected value");
+        assert_eq!(summary[1].stages.iter().next().unwrap(), "storage");
+    }
+
+    #[test]
+    fn test_replay_filter_and_patch() {
+        let storage = record(3, ErrorClass::Storage, "storage", "connection reset");
+        let filter = DlqFilter {
+            error_class: Some(ErrorClass::Storage),
+            ..Default::default()
+        };
+        assert!(filter.matches(&storage));
+        assert!(!filter.matches(&record(4, ErrorClass::Validation, "ingestion", "bad")));
+
+        let patch = json!({"tokens": null, "model": "gpt-4o", "tags": {"replayed": "true"}});
+        let payload: serde_json::Value =
+            serde_json::from_slice(&replay_payload(&storage, Some(&patch)).unwrap()).unwrap();
+        assert_eq!(
+            payload,
+            json!({"model": "gpt-4o", "tags": {"replayed": "true"}})
+        );
+    }
+}
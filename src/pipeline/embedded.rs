@@ -0,0 +1,386 @@
+//! Embedded Pipeline
+//!
+//! Runs processing, aggregation, anomaly detection and correlation in a
+//! single process without Kafka or Redis: events are read from a [`Source`]
+//! and written to [`Sink`]s. Aggregates are emitted each time the pipeline
+//! [`Clock`] crosses an emit interval, and once more when the source ends.
+//!
+//! With a [`ReplaySource`](super::source::ReplaySource) clock, event
+//! enrichment and aggregate emission follow event time, so replaying the same
+//! input produces the same output.
+
+use super::processing::EventProcessor;
+use super::sink::Sink;
+use super::source::{Clock, Source};
+use super::PipelineConfig;
+use crate::analytics::{AnalyticsConfig, AnalyticsEngine};
+use crate::models::metrics::{AggregatedMetric, TimeWindow};
+use crate::schemas::events::AnalyticsEvent;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Utc};
+use std::time::Duration as StdDuration;
+use tracing::{debug, info, warn};
+
+/// Embedded pipeline configuration
+#[derive(Debug, Clone)]
+pub struct EmbeddedConfig {
+    /// Processing configuration; `batch_size` is the sink write batch size
+    pub pipeline: PipelineConfig,
+
+    /// Analytics configuration
+    pub analytics: AnalyticsConfig,
+
+    /// Aggregation window emitted to aggregate sinks
+    pub emit_window: TimeWindow,
+
+    /// Pipeline clock time between aggregate emissions
+    pub emit_interval: Duration,
+
+    /// Longest an event waits in the write buffer while the source is idle
+    pub flush_interval: StdDuration,
+}
+
+impl Default for EmbeddedConfig {
+    fn default() -> Self {
+        Self {
+            pipeline: PipelineConfig::default(),
+            analytics: AnalyticsConfig {
+                enable_prediction: false,
+                ..AnalyticsConfig::default()
+            },
+            emit_window: TimeWindow::OneMinute,
+            emit_interval: Duration::seconds(60),
+            flush_interval: StdDuration::from_secs(1),
+        }
+    }
+}
+
+/// Embedded pipeline statistics
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddedStats {
+    pub events_read: u64,
+    pub events_processed: u64,
+    pub events_dropped: u64,
+    pub events_written: u64,
+    pub aggregates_written: u64,
+    pub anomalies_detected: u64,
+}
+
+/// Kafka-free pipeline reading from a source and writing to sinks
+pub struct EmbeddedPipeline {
+    config: EmbeddedConfig,
+    clock: Clock,
+    processor: EventProcessor,
+    analytics: AnalyticsEngine,
+    event_sinks: Vec<Box<dyn Sink<AnalyticsEvent>>>,
+    aggregate_sinks: Vec<Box<dyn Sink<AggregatedMetric>>>,
+    pending: Vec<AnalyticsEvent>,
+    last_emit: Option<DateTime<Utc>>,
+    stats: EmbeddedStats,
+}
+
+impl EmbeddedPipeline {
+    /// Create an embedded pipeline timed by `clock`
+    pub async fn new(config: EmbeddedConfig, clock: Clock) -> Result<Self> {
+        let processor = EventProcessor::with_clock(&config.pipeline, clock.clone()).await?;
+        let analytics = AnalyticsEngine::new(config.analytics.clone()).await?;
+
+        Ok(Self {
+            config,
+            clock,
+            processor,
+            analytics,
+            event_sinks: Vec::new(),
+            aggregate_sinks: Vec::new(),
+            pending: Vec::new(),
+            last_emit: None,
+            stats: EmbeddedStats::default(),
+        })
+    }
+
+    /// Write processed events to `sink`
+    pub fn with_event_sink(mut self, sink: impl Sink<AnalyticsEvent> + 'static) -> Self {
+        self.event_sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Write emitted aggregates to `sink`
+    pub fn with_aggregate_sink(mut self, sink: impl Sink<AggregatedMetric> + 'static) -> Self {
+        self.aggregate_sinks.push(Box::new(sink));
+        self
+    }
+
+    /// Analytics state built up from the processed events
+    pub fn analytics(&self) -> &AnalyticsEngine {
+        &self.analytics
+    }
+
+    pub fn stats(&self) -> &EmbeddedStats {
+        &self.stats
+    }
+
+    /// Run until `source` is exhausted, then emit final aggregates and close
+    /// every sink
+    pub async fn run(&mut self, source: &mut dyn Source) -> Result<EmbeddedStats> {
+        info!(
+            "Embedded pipeline reading from {} ({} event sinks, {} aggregate sinks)",
+            source.name(),
+            self.event_sinks.len(),
+            self.aggregate_sinks.len()
+        );
+
+        loop {
+            let next = match tokio::time::timeout(self.config.flush_interval, source.next()).await {
+                Ok(next) => next?,
+                Err(_) => {
+                    // Source idle: don't hold events back
+                    self.flush().await?;
+                    self.emit_if_due().await?;
+                    continue;
+                }
+            };
+            let Some(event) = next else {
+                break;
+            };
+
+            self.stats.events_read += 1;
+            self.process(event).await;
+            if self.pending.len() >= self.config.pipeline.batch_size.max(1) {
+                self.flush().await?;
+            }
+            self.emit_if_due().await?;
+        }
+
+        self.flush().await?;
+        self.emit().await?;
+        for sink in &mut self.event_sinks {
+            sink.close().await?;
+        }
+        for sink in &mut self.aggregate_sinks {
+            sink.close().await?;
+        }
+
+        info!("Embedded pipeline finished: {:?}", self.stats);
+        Ok(self.stats.clone())
+    }
+
+    /// Process and analyze one event, buffering it for the event sinks
+    async fn process(&mut self, event: AnalyticsEvent) {
+        let event = match self.processor.process(event).await {
+            Ok(Some(event)) => event,
+            Ok(None) => {
+                self.stats.events_dropped += 1;
+                return;
+            }
+            Err(e) => {
+                self.stats.events_dropped += 1;
+                warn!("Failed to process event: {}", e);
+                return;
+            }
+        };
+        self.stats.events_processed += 1;
+
+        if let Err(e) = self.analyze(&event) {
+            warn!("Failed to analyze event {}: {}", event.common.event_id, e);
+        }
+        self.pending.push(event);
+    }
+
+    fn analyze(&mut self, event: &AnalyticsEvent) -> Result<()> {
+        let analytics = &self.config.analytics;
+        let timestamp = event.common.timestamp;
+
+        self.analytics.correlation().record_event(event);
+        for (name, value) in numeric_fields(event) {
+            if analytics.enable_realtime_aggregation {
+                self.analytics.aggregation().add_point(
+                    &name,
+                    value,
+                    timestamp,
+                    event.common.tags.clone(),
+                )?;
+            }
+            if analytics.enable_anomaly_detection {
+                if let Some(anomaly) = self
+                    .analytics
+                    .anomaly()
+                    .check_anomaly(&name, value, timestamp)?
+                {
+                    self.stats.anomalies_detected += 1;
+                    warn!(
+                        "Anomaly in {}: {} (expected {:.2}, {:?})",
+                        anomaly.metric_name,
+                        anomaly.value,
+                        anomaly.expected_value,
+                        anomaly.severity
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write buffered events to every event sink
+    async fn flush(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        for sink in &mut self.event_sinks {
+            sink.write(&self.pending).await?;
+        }
+        debug!("Wrote {} events", self.pending.len());
+        self.stats.events_written += self.pending.len() as u64;
+        self.pending.clear();
+        Ok(())
+    }
+
+    async fn emit_if_due(&mut self) -> Result<()> {
+        let now = self.clock.now();
+        match self.last_emit {
+            None => self.last_emit = Some(now),
+            Some(last) if now - last >= self.config.emit_interval => {
+                // Aggregates reflect every event up to now
+                self.flush().await?;
+                self.emit().await?;
+                self.last_emit = Some(now);
+            }
+            Some(_) => {}
+        }
+        Ok(())
+    }
+
+    /// Write the current aggregates to every aggregate sink
+    async fn emit(&mut self) -> Result<()> {
+        if self.aggregate_sinks.is_empty() {
+            return Ok(());
+        }
+        let mut aggregates = self
+            .analytics
+            .aggregation()
+            .get_all_aggregated(self.config.emit_window);
+        if aggregates.is_empty() {
+            return Ok(());
+        }
+        // Stable output order for replay
+        aggregates.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for sink in &mut self.aggregate_sinks {
+            sink.write(&aggregates).await?;
+        }
+        self.stats.aggregates_written += aggregates.len() as u64;
+        Ok(())
+    }
+}
+
+/// Numeric fields of the event payload, e.g. `total_latency_ms`
+fn numeric_fields(event: &AnalyticsEvent) -> Vec<(String, f64)> {
+    let Ok(payload) = serde_json::to_value(&event.payload) else {
+        return Vec::new();
+    };
+    payload
+        .get("data")
+        .and_then(|data| data.as_object())
+        .map(|data| {
+            data.iter()
+                .filter_map(|(key, value)| Some((key.clone(), value.as_f64()?)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::sink::NdjsonSink;
+    use crate::pipeline::source::{NdjsonSource, ReplaySource};
+    use crate::schemas::events::*;
+
+    fn latency(timestamp: DateTime<Utc>, latency_ms: f64) -> AnalyticsEvent {
+        fixtures::latency("gpt-4", latency_ms).at(timestamp)
+    }
+
+    fn input(count: i64) -> String {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        (0..count)
+            .rev()
+            .map(|i| {
+                let event = latency(start + Duration::seconds(i * 10), 100.0 + i as f64);
+                serde_json::to_string(&event).unwrap()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Replay `input` into NDJSON files, returning the stats and both outputs
+    async fn replay(input: &str) -> (EmbeddedStats, Vec<serde_json::Value>, Vec<String>) {
+        let dir = tempfile::tempdir().unwrap();
+        let events_path = dir.path().join("events.ndjson");
+        let aggregates_path = dir.path().join("aggregates.ndjson");
+
+        let mut source = ReplaySource::new(NdjsonSource::new("test", input.as_bytes()), None)
+            .await
+            .unwrap();
+        let config = EmbeddedConfig {
+            pipeline: PipelineConfig {
+                batch_size: 4,
+                ..PipelineConfig::default()
+            },
+            ..EmbeddedConfig::default()
+        };
+        let mut pipeline = EmbeddedPipeline::new(config, source.clock())
+            .await
+            .unwrap()
+            .with_event_sink(NdjsonSink::create(&events_path).await.unwrap())
+            .with_aggregate_sink(NdjsonSink::create(&aggregates_path).await.unwrap());
+        let stats = pipeline.run(&mut source).await.unwrap();
+
+        // Tags are a map, so compare events as JSON values
+        let events = std::fs::read_to_string(&events_path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let aggregates = std::fs::read_to_string(&aggregates_path)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        (stats, events, aggregates)
+    }
+
+    #[tokio::test]
+    async fn test_replay_processes_every_event() {
+        let (stats, events, aggregates) = replay(&input(30)).await;
+        assert_eq!(stats.events_read, 30);
+        assert_eq!(stats.events_processed, 30);
+        assert_eq!(stats.events_written, 30);
+        assert_eq!(events.len(), 30);
+        // Five minutes of events emit each minute, plus the final emission
+        assert_eq!(stats.aggregates_written, 5);
+        assert_eq!(aggregates.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_replay_is_deterministic() {
+        let input = input(30);
+        let (_, first_events, first_aggregates) = replay(&input).await;
+        let (_, second_events, second_aggregates) = replay(&input).await;
+        assert_eq!(first_events, second_events);
+        assert_eq!(first_aggregates, second_aggregates);
+
+        // Enrichment follows event time, not wall-clock time
+        let event = &first_events[0];
+        let processed_at = event["tags"]["processed_at"].as_str().unwrap();
+        let timestamp = event["timestamp"].as_str().unwrap();
+        assert_eq!(
+            DateTime::parse_from_rfc3339(processed_at).unwrap(),
+            DateTime::parse_from_rfc3339(timestamp).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_numeric_fields_come_from_payload_data() {
+        let fields = numeric_fields(&latency(Utc::now(), 250.0));
+        assert_eq!(fields, vec![("total_latency_ms".to_string(), 250.0)]);
+    }
+}
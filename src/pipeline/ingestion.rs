@@ -2,19 +2,31 @@
 //!
 //! High-performance event ingestion from Kafka with support for 100k+ events/sec,
 //! including dead letter queue, metrics tracking, and automatic retry logic.
+//!
+//! Delivery is at-least-once: offsets are committed manually, and only after
+//! a batch has been stored and accepted by the processing queue.
+//!
+//! Storage runs on a pool of workers sharded by key (see [`super::workers`]),
+//! so a slow batch only holds up the keys of one worker.
 
+use super::dlq::{DlqMetadata, DlqProducer, ErrorClass};
+use super::workers::{OffsetTracker, ShardBy, WorkerPool, WorkerPoolConfig, WorkerStats};
+use crate::database::copy::CopyConfig;
 use crate::database::Database;
 use crate::schemas::events::AnalyticsEvent;
 use anyhow::{Context, Result};
+use chrono::Utc;
+use parking_lot::Mutex;
 use rdkafka::config::ClientConfig;
-use rdkafka::consumer::{Consumer, StreamConsumer};
+use rdkafka::consumer::{CommitMode, Consumer, ConsumerContext, Rebalance, StreamConsumer};
 use rdkafka::message::Message;
 use rdkafka::producer::{FutureProducer, FutureRecord};
+use rdkafka::{ClientContext, Offset, TopicPartitionList};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock, Weak};
 use std::time::{Duration, Instant};
-use tokio::sync::{mpsc, RwLock};
-use tracing::{debug, error, info, instrument, warn};
+use tokio::sync::mpsc;
+use tracing::{error, info, instrument, warn};
 
 /// Event ingestion configuration
 #[derive(Debug, Clone)]
@@ -27,6 +39,11 @@ pub struct IngestionConfig {
     pub max_retries: u32,
     pub enable_dlq: bool,
     pub dlq_topic: String,
+    /// Storage workers events are sharded across
+    pub num_workers: usize,
+    /// Events each worker may have queued before consumption pauses
+    pub worker_queue_size: usize,
+    pub shard_by: ShardBy,
 }
 
 impl Default for IngestionConfig {
@@ -40,6 +57,112 @@ impl Default for IngestionConfig {
             max_retries: 3,
             enable_dlq: true,
             dlq_topic: "llm-analytics-events-dlq".to_string(),
+            num_workers: 4,
+            worker_queue_size: 2500,
+            shard_by: ShardBy::CorrelationId,
+        }
+    }
+}
+
+type IngestionConsumer = StreamConsumer<IngestionContext>;
+
+/// Consumer context that commits acknowledged offsets before partitions
+/// are revoked
+pub struct IngestionContext {
+    tracker: Arc<Mutex<OffsetTracker>>,
+    consumer: OnceLock<Weak<IngestionConsumer>>,
+    metrics: Arc<IngestionMetrics>,
+}
+
+impl IngestionContext {
+    /// Commit the offsets acknowledged by the workers and rewind partitions
+    /// with failed batches so they are redelivered. An error means offsets
+    /// can no longer be tracked safely and the consumer must stop.
+    fn commit_and_rewind(&self, mode: CommitMode) -> Result<()> {
+        let consumer = self
+            .consumer
+            .get()
+            .and_then(Weak::upgrade)
+            .context("Kafka consumer dropped")?;
+        let (positions, rewinds) = {
+            let mut tracker = self.tracker.lock();
+            (tracker.commit_positions(), tracker.take_rewinds())
+        };
+
+        if !positions.is_empty() {
+            let mut tpl = TopicPartitionList::new();
+            for ((topic, partition), offset) in positions {
+                tpl.add_partition_offset(&topic, partition, Offset::Offset(offset))?;
+            }
+            self.commit(&consumer, &tpl, mode);
+        }
+
+        for ((topic, partition), offset) in rewinds {
+            consumer
+                .seek(&topic, partition, Offset::Offset(offset), Duration::from_secs(5))
+                .with_context(|| {
+                    format!("Failed to rewind {}/{} to offset {}", topic, partition, offset)
+                })?;
+        }
+        Ok(())
+    }
+
+    fn commit(&self, consumer: &IngestionConsumer, tpl: &TopicPartitionList, mode: CommitMode) {
+        match consumer.commit(tpl, mode) {
+            Ok(()) => {
+                self.metrics.offsets_committed.fetch_add(tpl.count() as u64, Ordering::Relaxed);
+            }
+            // A later commit covers these offsets; until then they are redelivered
+            Err(e) => {
+                self.metrics.commit_errors.fetch_add(1, Ordering::Relaxed);
+                warn!("Failed to commit offsets: {}", e);
+            }
+        }
+    }
+}
+
+impl ClientContext for IngestionContext {}
+
+impl ConsumerContext for IngestionContext {
+    fn pre_rebalance(&self, rebalance: &Rebalance) {
+        let Rebalance::Revoke(tpl) = rebalance else {
+            return;
+        };
+        self.metrics.rebalances.fetch_add(1, Ordering::Relaxed);
+        info!(
+            partitions = tpl.count(),
+            "Partitions revoked, committing acknowledged offsets"
+        );
+
+        // Events of revoked partitions still queued for the workers are
+        // skipped; their new owner consumes them from the last commit
+        let mut revoked = TopicPartitionList::new();
+        {
+            let mut tracker = self.tracker.lock();
+            for element in tpl.elements() {
+                if let Some(position) = tracker.revoke(element.topic(), element.partition()) {
+                    let _ = revoked.add_partition_offset(
+                        element.topic(),
+                        element.partition(),
+                        Offset::Offset(position),
+                    );
+                }
+            }
+        }
+
+        if revoked.count() > 0 {
+            if let Some(consumer) = self.consumer.get().and_then(Weak::upgrade) {
+                self.commit(&consumer, &revoked, CommitMode::Sync);
+            }
+        }
+    }
+
+    fn post_rebalance(&self, rebalance: &Rebalance) {
+        if let Rebalance::Assign(tpl) = rebalance {
+            info!(
+                partitions = tpl.count(),
+                "Partitions assigned, resuming from committed offsets"
+            );
         }
     }
 }
@@ -47,10 +170,10 @@ impl Default for IngestionConfig {
 /// Event ingester with high-performance Kafka integration
 pub struct EventIngester {
     config: IngestionConfig,
-    consumer: Arc<StreamConsumer>,
+    consumer: Arc<IngestionConsumer>,
     producer: FutureProducer,
-    database: Arc<Database>,
     metrics: Arc<IngestionMetrics>,
+    pool: Arc<WorkerPool>,
     event_tx: mpsc::Sender<AnalyticsEvent>,
     event_rx: Option<mpsc::Receiver<AnalyticsEvent>>,
 }
@@ -61,15 +184,35 @@ impl EventIngester {
     pub async fn new(config: IngestionConfig, database: Arc<Database>) -> Result<Self> {
         info!("Initializing event ingester");
 
-        // Configure Kafka consumer for high throughput
-        let consumer: StreamConsumer = ClientConfig::new()
+        let (event_tx, event_rx) = mpsc::channel(config.buffer_size);
+        let metrics = Arc::new(IngestionMetrics::new());
+        let pool = Arc::new(WorkerPool::spawn(
+            WorkerPoolConfig {
+                num_workers: config.num_workers,
+                queue_size: config.worker_queue_size,
+                batch_size: config.batch_size,
+                max_retries: config.max_retries,
+                shard_by: config.shard_by,
+                ..Default::default()
+            },
+            Arc::new(database.copy_writer(CopyConfig::default())),
+            event_tx.clone(),
+        ));
+        let context = IngestionContext {
+            tracker: pool.tracker().clone(),
+            consumer: OnceLock::new(),
+            metrics: metrics.clone(),
+        };
+
+        // Configure Kafka consumer for high throughput; offsets are committed
+        // manually once a batch is acknowledged
+        let consumer: IngestionConsumer = ClientConfig::new()
             .set("group.id", &config.group_id)
             .set("bootstrap.servers", config.kafka_brokers.join(","))
             .set("enable.partition.eof", "false")
             .set("session.timeout.ms", "6000")
             .set("heartbeat.interval.ms", "2000")
-            .set("enable.auto.commit", "true")
-            .set("auto.commit.interval.ms", "5000")
+            .set("enable.auto.commit", "false")
             .set("auto.offset.reset", "earliest")
             .set("compression.type", "snappy")
             .set("fetch.min.bytes", "1048576") // 1MB minimum fetch
@@ -77,8 +220,10 @@ impl EventIngester {
             .set("max.partition.fetch.bytes", "10485760") // 10MB per partition
             .set("receive.message.max.bytes", "10485760") // 10MB max message
             .set("queued.min.messages", "100000") // Large queue for buffering
-            .create()
+            .create_with_context(context)
             .context("Failed to create Kafka consumer")?;
+        let consumer = Arc::new(consumer);
+        let _ = consumer.context().consumer.set(Arc::downgrade(&consumer));
 
         // Configure Kafka producer for reliability
         let producer: FutureProducer = ClientConfig::new()
@@ -93,16 +238,12 @@ impl EventIngester {
             .create()
             .context("Failed to create Kafka producer")?;
 
-        let (event_tx, event_rx) = mpsc::channel(config.buffer_size);
-
-        let metrics = Arc::new(IngestionMetrics::new());
-
         Ok(Self {
             config,
-            consumer: Arc::new(consumer),
+            consumer,
             producer,
-            database,
             metrics,
+            pool,
             event_tx,
             event_rx: Some(event_rx),
         })
@@ -120,74 +261,81 @@ impl EventIngester {
         info!("Subscribed to topics: {:?}", topics);
 
         let consumer = self.consumer.clone();
-        let producer = self.producer.clone();
-        let tx = self.event_tx.clone();
-        let database = self.database.clone();
         let metrics = self.metrics.clone();
-        let batch_size = self.config.batch_size;
+        let pool = self.pool.clone();
         let enable_dlq = self.config.enable_dlq;
-        let dlq_topic = self.config.dlq_topic.clone();
+        let dlq = DlqProducer::new(self.producer.clone(), self.config.dlq_topic.clone());
 
         // Spawn consumer task
         tokio::spawn(async move {
             info!("Starting high-performance Kafka consumer");
 
-            let mut batch = Vec::with_capacity(batch_size);
-            let mut last_flush = Instant::now();
-            let flush_interval = Duration::from_millis(500);
+            let context = consumer.context();
+            let mut commit_interval = tokio::time::interval(Duration::from_millis(500));
 
             loop {
-                match consumer.recv().await {
-                    Ok(message) => {
-                        metrics.messages_received.fetch_add(1, Ordering::Relaxed);
-
-                        if let Some(payload) = message.payload() {
-                            match serde_json::from_slice::<AnalyticsEvent>(payload) {
-                                Ok(event) => {
-                                    batch.push(event);
-
-                                    // Flush batch if full or timeout reached
-                                    let should_flush = batch.len() >= batch_size
-                                        || last_flush.elapsed() >= flush_interval;
-
-                                    if should_flush {
-                                        let events_to_process = std::mem::replace(
-                                            &mut batch,
-                                            Vec::with_capacity(batch_size)
-                                        );
-
-                                        // Process batch
-                                        Self::process_batch(
-                                            events_to_process,
-                                            &tx,
-                                            &database,
-                                            &metrics,
-                                        ).await;
-
-                                        last_flush = Instant::now();
+                tokio::select! {
+                    result = consumer.recv() => match result {
+                        Ok(message) => {
+                            metrics.messages_received.fetch_add(1, Ordering::Relaxed);
+
+                            let event = match message.payload() {
+                                Some(payload) => match serde_json::from_slice::<AnalyticsEvent>(payload) {
+                                    Ok(event) => Some(event),
+                                    Err(e) => {
+                                        metrics.deserialization_errors.fetch_add(1, Ordering::Relaxed);
+                                        warn!("Failed to deserialize event: {}", e);
+
+                                        // Send to DLQ if enabled
+                                        if enable_dlq {
+                                            let metadata = DlqMetadata {
+                                                error_class: ErrorClass::Deserialization,
+                                                error: e.to_string(),
+                                                stage: "ingestion".to_string(),
+                                                attempts: 1,
+                                                original_topic: message.topic().to_string(),
+                                                original_partition: message.partition(),
+                                                original_offset: message.offset(),
+                                                failed_at: Utc::now(),
+                                            };
+                                            if let Err(e) = dlq.send(message.key(), payload, &metadata).await {
+                                                error!("{}", e);
+                                            }
+                                        }
+                                        None
                                     }
+                                },
+                                None => None,
+                            };
+
+                            // Waits while the event's worker is full
+                            let dispatched = match event {
+                                Some(event) => {
+                                    pool.dispatch(message.topic(), message.partition(), message.offset(), event)
+                                        .await
                                 }
-                                Err(e) => {
-                                    metrics.deserialization_errors.fetch_add(1, Ordering::Relaxed);
-                                    warn!("Failed to deserialize event: {}", e);
-
-                                    // Send to DLQ if enabled
-                                    if enable_dlq {
-                                        Self::send_to_dlq(
-                                            &producer,
-                                            &dlq_topic,
-                                            payload,
-                                            &format!("Deserialization error: {}", e),
-                                        ).await;
-                                    }
+                                None => {
+                                    context.tracker.lock().skip(message.topic(), message.partition(), message.offset());
+                                    Ok(())
                                 }
+                            };
+                            if let Err(e) = dispatched {
+                                error!("Stopping consumer: {}", e);
+                                break;
                             }
                         }
-                    }
-                    Err(e) => {
-                        metrics.kafka_errors.fetch_add(1, Ordering::Relaxed);
-                        error!("Kafka consumer error: {}", e);
-                        tokio::time::sleep(Duration::from_secs(1)).await;
+                        Err(e) => {
+                            metrics.kafka_errors.fetch_add(1, Ordering::Relaxed);
+                            error!("Kafka consumer error: {}", e);
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                        }
+                    },
+                    _ = commit_interval.tick() => {
+                        if let Err(e) = context.commit_and_rewind(CommitMode::Async) {
+                            // Uncommitted offsets are replayed once the consumer restarts
+                            error!("Stopping consumer: {}", e);
+                            break;
+                        }
                     }
                 }
             }
@@ -197,58 +345,6 @@ impl EventIngester {
         Ok(())
     }
 
-    /// Process a batch of events
-    async fn process_batch(
-        events: Vec<AnalyticsEvent>,
-        tx: &mpsc::Sender<AnalyticsEvent>,
-        database: &Arc<Database>,
-        metrics: &Arc<IngestionMetrics>,
-    ) {
-        let start = Instant::now();
-        let count = events.len();
-
-        // Insert batch into database
-        match database.insert_events_batch(&events).await {
-            Ok(inserted) => {
-                metrics.events_stored.fetch_add(inserted, Ordering::Relaxed);
-                debug!("Stored batch of {} events", inserted);
-            }
-            Err(e) => {
-                error!("Failed to store event batch: {}", e);
-                metrics.storage_errors.fetch_add(count as u64, Ordering::Relaxed);
-            }
-        }
-
-        // Send to processing pipeline
-        for event in events {
-            if tx.send(event).await.is_err() {
-                metrics.processing_errors.fetch_add(1, Ordering::Relaxed);
-                error!("Failed to send event to processing queue");
-            } else {
-                metrics.events_processed.fetch_add(1, Ordering::Relaxed);
-            }
-        }
-
-        let duration = start.elapsed();
-        metrics.record_batch_duration(duration);
-    }
-
-    /// Send failed event to dead letter queue
-    async fn send_to_dlq(
-        producer: &FutureProducer,
-        dlq_topic: &str,
-        payload: &[u8],
-        error_msg: &str,
-    ) {
-        let dlq_record = FutureRecord::to(dlq_topic)
-            .payload(payload)
-            .key(error_msg);
-
-        if let Err((e, _)) = producer.send(dlq_record, Duration::from_secs(5)).await {
-            error!("Failed to send to DLQ: {}", e);
-        }
-    }
-
     /// Publish an event to Kafka
     #[instrument(skip(self, event))]
     pub async fn publish(&self, event: &AnalyticsEvent) -> Result<()> {
@@ -291,12 +387,13 @@ impl EventIngester {
 
     /// Get ingestion statistics
     pub fn get_stats(&self) -> IngestionStats {
-        self.metrics.get_stats()
+        self.metrics.get_stats(self.pool.stats())
     }
 
     /// Get current throughput (events/second)
     pub fn current_throughput(&self) -> f64 {
-        self.metrics.calculate_throughput()
+        let processed = self.pool.stats().iter().map(|w| w.events_forwarded).sum();
+        self.metrics.calculate_throughput(processed)
     }
 
     /// Shutdown the ingester gracefully
@@ -308,17 +405,16 @@ impl EventIngester {
     }
 }
 
-/// Ingestion metrics tracking
+/// Ingestion metrics tracking. Storage counters are kept per worker.
 pub struct IngestionMetrics {
     messages_received: AtomicU64,
     messages_sent: AtomicU64,
-    events_processed: AtomicU64,
-    events_stored: AtomicU64,
     deserialization_errors: AtomicU64,
-    storage_errors: AtomicU64,
     processing_errors: AtomicU64,
     kafka_errors: AtomicU64,
-    batch_durations: RwLock<Vec<Duration>>,
+    offsets_committed: AtomicU64,
+    commit_errors: AtomicU64,
+    rebalances: AtomicU64,
     start_time: Instant,
 }
 
@@ -327,47 +423,43 @@ impl IngestionMetrics {
         Self {
             messages_received: AtomicU64::new(0),
             messages_sent: AtomicU64::new(0),
-            events_processed: AtomicU64::new(0),
-            events_stored: AtomicU64::new(0),
             deserialization_errors: AtomicU64::new(0),
-            storage_errors: AtomicU64::new(0),
             processing_errors: AtomicU64::new(0),
             kafka_errors: AtomicU64::new(0),
-            batch_durations: RwLock::new(Vec::new()),
+            offsets_committed: AtomicU64::new(0),
+            commit_errors: AtomicU64::new(0),
+            rebalances: AtomicU64::new(0),
             start_time: Instant::now(),
         }
     }
 
-    fn record_batch_duration(&self, duration: Duration) {
-        if let Ok(mut durations) = self.batch_durations.try_write() {
-            durations.push(duration);
-            // Keep only last 1000 measurements
-            if durations.len() > 1000 {
-                durations.drain(0..100);
-            }
-        }
-    }
-
-    fn calculate_throughput(&self) -> f64 {
+    fn calculate_throughput(&self, events_processed: u64) -> f64 {
         let elapsed = self.start_time.elapsed().as_secs_f64();
         if elapsed > 0.0 {
-            self.events_processed.load(Ordering::Relaxed) as f64 / elapsed
+            events_processed as f64 / elapsed
         } else {
             0.0
         }
     }
 
-    fn get_stats(&self) -> IngestionStats {
+    fn get_stats(&self, workers: Vec<WorkerStats>) -> IngestionStats {
+        let events_processed = workers.iter().map(|w| w.events_forwarded).sum();
+        let batches_rewound = workers.iter().map(|w| w.batches_rewound).sum();
         IngestionStats {
             messages_received: self.messages_received.load(Ordering::Relaxed),
             messages_sent: self.messages_sent.load(Ordering::Relaxed),
-            events_processed: self.events_processed.load(Ordering::Relaxed),
-            events_stored: self.events_stored.load(Ordering::Relaxed),
+            events_processed,
+            events_stored: workers.iter().map(|w| w.events_stored).sum(),
             deserialization_errors: self.deserialization_errors.load(Ordering::Relaxed),
-            storage_errors: self.storage_errors.load(Ordering::Relaxed),
+            storage_errors: batches_rewound,
             processing_errors: self.processing_errors.load(Ordering::Relaxed),
             kafka_errors: self.kafka_errors.load(Ordering::Relaxed),
-            avg_throughput: self.calculate_throughput(),
+            offsets_committed: self.offsets_committed.load(Ordering::Relaxed),
+            commit_errors: self.commit_errors.load(Ordering::Relaxed),
+            batches_rewound,
+            rebalances: self.rebalances.load(Ordering::Relaxed),
+            avg_throughput: self.calculate_throughput(events_processed),
+            workers,
         }
     }
 }
@@ -383,5 +475,11 @@ pub struct IngestionStats {
     pub storage_errors: u64,
     pub processing_errors: u64,
     pub kafka_errors: u64,
+    pub offsets_committed: u64,
+    pub commit_errors: u64,
+    pub batches_rewound: u64,
+    pub rebalances: u64,
     pub avg_throughput: f64,
+    /// Queue depth, lag and storage counters per worker
+    pub workers: Vec<WorkerStats>,
 }
@@ -3,22 +3,45 @@
 //! Core pipeline for ingesting, processing, and storing analytics events.
 //! Implements event-driven architecture with CQRS pattern.
 
+pub mod dlq;
+pub mod embedded;
 pub mod ingestion;
+pub mod offsets;
 pub mod processing;
+pub mod sampling;
+pub mod sink;
+pub mod source;
+pub mod spill;
 pub mod storage;
 pub mod cache;
 pub mod stream;
+pub mod cost_enrichment;
+pub mod transactions;
+pub mod transform;
+pub mod workers;
 
+pub use dlq::{DlqMetadata, DlqProducer, DlqRecord, DlqReplayer, ErrorClass};
+pub use embedded::{EmbeddedConfig, EmbeddedPipeline, EmbeddedStats};
 pub use ingestion::EventIngester;
+pub use offsets::{AckingBatcher, BatchSink, FlushOutcome, PartitionOffsets};
 pub use processing::EventProcessor;
+pub use sampling::{Sampler, SamplingConfig};
+pub use sink::{NdjsonSink, Sink, TimescaleSink};
+pub use source::{Clock, HttpPushSource, NdjsonSource, PushBuffer, ReplaySource, Source};
+pub use spill::{BufferFull, FsyncPolicy, SpillBuffer, SpillConfig, SpillRecord, SpillStats};
 pub use storage::StorageManager;
 pub use cache::CacheManager;
 pub use stream::StreamManager;
+pub use cost_enrichment::{CostEnricher, CostEnrichmentConfig, CostEnrichmentMode};
+pub use transactions::{ProcessingGuarantee, StageCommitter, TransactionConfig};
+pub use transform::{ChainOutcome, Transform, TransformChain, TransformConfig};
+pub use workers::{OffsetTracker, ShardBy, WorkerPool, WorkerPoolConfig, WorkerStats};
 
 use crate::schemas::events::AnalyticsEvent;
 use crate::database::Database;
 use anyhow::Result;
 use async_trait::async_trait;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 /// Pipeline configuration
@@ -36,7 +59,7 @@ pub struct PipelineConfig {
     /// Batch size for event processing
     pub batch_size: usize,
 
-    /// Processing parallelism
+    /// Processing parallelism: storage workers events are sharded across
     pub num_workers: usize,
 
     /// Buffer size for event queue
@@ -44,6 +67,9 @@ pub struct PipelineConfig {
 
     /// Enable compression
     pub enable_compression: bool,
+
+    /// YAML file of event transforms applied before storage
+    pub transforms_path: Option<PathBuf>,
 }
 
 impl Default for PipelineConfig {
@@ -56,6 +82,7 @@ impl Default for PipelineConfig {
             num_workers: 4,
             buffer_size: 10000,
             enable_compression: true,
+            transforms_path: None,
         }
     }
 }
@@ -89,6 +116,10 @@ impl Pipeline {
             max_retries: 3,
             enable_dlq: true,
             dlq_topic: "llm-analytics-events-dlq".to_string(),
+            num_workers: config.num_workers,
+            // Keep the total buffered across workers at the configured size
+            worker_queue_size: config.buffer_size / config.num_workers.max(1),
+            shard_by: ShardBy::CorrelationId,
         };
 
         let ingester = EventIngester::new(ingestion_config, database.clone()).await?;
@@ -124,7 +155,9 @@ impl Pipeline {
     /// Process a single event
     pub async fn process_event(&mut self, event: AnalyticsEvent) -> Result<()> {
         // Process the event
-        let processed = self.processor.process(event).await?;
+        let Some(processed) = self.processor.process(event).await? else {
+            return Ok(());
+        };
 
         // Store in database
         self.storage.store_event(&processed).await?;
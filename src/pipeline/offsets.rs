@@ -0,0 +1,265 @@
+//! Offset Tracking
+//!
+//! At-least-once delivery for Kafka ingestion. Messages are collected into a
+//! batch together with their partition offsets; offsets only become
+//! committable once the batch has been durably stored and accepted by the
+//! processing queue. A failed batch yields the offsets to rewind to so it is
+//! consumed again, which relies on storage ignoring already-stored events.
+
+use crate::database::copy::CopyWriter;
+use crate::schemas::events::AnalyticsEvent;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// Durable storage for ingested batches
+#[async_trait]
+pub trait BatchSink: Send + Sync {
+    /// Store a batch, returning the number of newly stored events. Storing
+    /// an already-stored event must succeed without duplicating it.
+    async fn store_batch(&self, events: &[AnalyticsEvent]) -> Result<u64>;
+}
+
+#[async_trait]
+impl BatchSink for CopyWriter {
+    async fn store_batch(&self, events: &[AnalyticsEvent]) -> Result<u64> {
+        self.write_events(events).await
+    }
+}
+
+/// A Kafka topic partition
+pub type TopicPartition = (String, i32);
+
+/// Offset ranges covered by a batch, per partition
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PartitionOffsets {
+    // (Topic, Partition) -> (First offset, Last offset)
+    ranges: BTreeMap<TopicPartition, (i64, i64)>,
+    // (Topic, Partition) -> Offset the commit position may not pass
+    holds: BTreeMap<TopicPartition, i64>,
+}
+
+impl PartitionOffsets {
+    /// Record a consumed message
+    pub fn record(&mut self, topic: &str, partition: i32, offset: i64) {
+        self.ranges
+            .entry((topic.to_string(), partition))
+            .and_modify(|(first, last)| {
+                *first = (*first).min(offset);
+                *last = (*last).max(offset);
+            })
+            .or_insert((offset, offset));
+    }
+
+    /// Keep the commit position of a partition at or below `offset`, e.g.
+    /// the earliest offset whose effects are still only held in memory
+    pub fn hold(&mut self, topic: &str, partition: i32, offset: i64) {
+        self.holds
+            .entry((topic.to_string(), partition))
+            .and_modify(|held| *held = (*held).min(offset))
+            .or_insert(offset);
+    }
+
+    /// Offsets to commit once the batch is processed: the next offset to
+    /// consume on each partition, unless held back
+    pub fn commit_positions(&self) -> Vec<(TopicPartition, i64)> {
+        self.ranges
+            .iter()
+            .map(|(tp, (_, last))| {
+                let position = match self.holds.get(tp) {
+                    Some(held) => (last + 1).min(*held),
+                    None => last + 1,
+                };
+                (tp.clone(), position)
+            })
+            .collect()
+    }
+
+    /// Offsets to seek to so the batch is consumed again
+    pub fn rewind_positions(&self) -> Vec<(TopicPartition, i64)> {
+        self.ranges
+            .iter()
+            .map(|(tp, (first, _))| (tp.clone(), *first))
+            .collect()
+    }
+
+    /// Forget a partition, e.g. after it was revoked
+    pub fn remove(&mut self, topic: &str, partition: i32) {
+        let tp = (topic.to_string(), partition);
+        self.ranges.remove(&tp);
+        self.holds.remove(&tp);
+    }
+
+    /// Partitions covered by the batch
+    pub fn partitions(&self) -> impl Iterator<Item = &TopicPartition> {
+        self.ranges.keys()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+/// Outcome of flushing a batch
+#[derive(Debug)]
+pub enum FlushOutcome {
+    /// Nothing was pending
+    Empty,
+    /// Stored and forwarded; the offsets may be committed
+    Committable {
+        offsets: PartitionOffsets,
+        stored: u64,
+        forwarded: u64,
+    },
+    /// Storage or forwarding failed; the consumer must rewind so the batch
+    /// is redelivered
+    Rewind {
+        offsets: PartitionOffsets,
+        error: String,
+    },
+}
+
+/// Batch of consumed messages awaiting acknowledgement
+pub struct AckingBatcher {
+    sink: std::sync::Arc<dyn BatchSink>,
+    tx: mpsc::Sender<AnalyticsEvent>,
+    max_retries: u32,
+    retry_backoff: Duration,
+    events: Vec<AnalyticsEvent>,
+    offsets: PartitionOffsets,
+}
+
+impl AckingBatcher {
+    /// Create a batcher storing to `sink` and forwarding to `tx`
+    pub fn new(
+        sink: std::sync::Arc<dyn BatchSink>,
+        tx: mpsc::Sender<AnalyticsEvent>,
+        max_retries: u32,
+    ) -> Self {
+        Self {
+            sink,
+            tx,
+            max_retries,
+            retry_backoff: Duration::from_millis(100),
+            events: Vec::new(),
+            offsets: PartitionOffsets::default(),
+        }
+    }
+
+    /// Set the backoff before the first storage retry; it doubles per retry
+    pub fn with_retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Add a consumed message. Messages without an event (e.g. sent to the
+    /// DLQ) still advance their partition's offset.
+    pub fn push(
+        &mut self,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+        event: Option<AnalyticsEvent>,
+    ) {
+        self.offsets.record(topic, partition, offset);
+        self.events.extend(event);
+    }
+
+    /// Pending events
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty() && self.offsets.is_empty()
+    }
+
+    /// Store and forward the pending batch
+    pub async fn flush(&mut self) -> FlushOutcome {
+        if self.is_empty() {
+            return FlushOutcome::Empty;
+        }
+        let events = std::mem::take(&mut self.events);
+        let offsets = std::mem::take(&mut self.offsets);
+
+        let mut backoff = self.retry_backoff;
+        let mut attempt = 0;
+        let stored = loop {
+            match self.sink.store_batch(&events).await {
+                Ok(stored) => break stored,
+                Err(e) if attempt < self.max_retries => {
+                    attempt += 1;
+                    warn!(attempt, "Failed to store event batch, retrying: {}", e);
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => {
+                    return FlushOutcome::Rewind {
+                        offsets,
+                        error: format!("storage failed: {}", e),
+                    }
+                }
+            }
+        };
+
+        let mut forwarded = 0;
+        for event in events {
+            if self.tx.send(event).await.is_err() {
+                return FlushOutcome::Rewind {
+                    offsets,
+                    error: "processing queue closed".to_string(),
+                };
+            }
+            forwarded += 1;
+        }
+
+        debug!(stored, forwarded, "Acknowledged event batch");
+        FlushOutcome::Committable {
+            offsets,
+            stored,
+            forwarded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offsets_cover_batch_ranges() {
+        let mut offsets = PartitionOffsets::default();
+        offsets.record("events", 0, 12);
+        offsets.record("events", 0, 10);
+        offsets.record("events", 1, 7);
+
+        assert_eq!(
+            offsets.commit_positions(),
+            vec![
+                (("events".to_string(), 0), 13),
+                (("events".to_string(), 1), 8)
+            ]
+        );
+        assert_eq!(
+            offsets.rewind_positions(),
+            vec![
+                (("events".to_string(), 0), 10),
+                (("events".to_string(), 1), 7)
+            ]
+        );
+
+        // Held offsets cap the commit position
+        offsets.hold("events", 0, 11);
+        offsets.hold("events", 1, 20);
+        assert_eq!(
+            offsets.commit_positions(),
+            vec![
+                (("events".to_string(), 0), 11),
+                (("events".to_string(), 1), 8)
+            ]
+        );
+    }
+}
@@ -1,6 +1,9 @@
 //! Event Processing Module
 //!
 //! Core event processing logic including validation, enrichment, and transformation.
+//!
+//! Every event runs through a [`TransformChain`]: validation, then the stages
+//! configured in `PipelineConfig::transforms_path`, then enrichment.
 
 use crate::schemas::events::AnalyticsEvent;
 use anyhow::Result;
@@ -8,39 +11,60 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use tracing::{debug, info};
 
+use super::source::Clock;
+use super::transform::{Action, ChainOutcome, Transform, TransformChain};
 use super::{HealthStatus, PipelineComponent, PipelineConfig};
 
 /// Event processor with validation and enrichment
 pub struct EventProcessor {
     #[allow(dead_code)]
     config: Arc<PipelineConfig>,
+    chain: TransformChain,
     stats: Arc<RwLock<ProcessingStats>>,
 }
 
 impl EventProcessor {
     /// Create a new event processor
     pub async fn new(config: &PipelineConfig) -> Result<Self> {
+        Self::with_clock(config, Clock::System).await
+    }
+
+    /// Create an event processor stamping events with time from `clock`
+    pub async fn with_clock(config: &PipelineConfig, clock: Clock) -> Result<Self> {
+        let mut chain = match &config.transforms_path {
+            Some(path) => TransformChain::from_file(path)?,
+            None => TransformChain::new(),
+        };
+        chain.push_front(Box::new(Validate));
+        chain.push(Box::new(Enrich { clock }));
+        info!("Event transforms: {}", chain.stage_names().join(" -> "));
+
         Ok(Self {
             config: Arc::new(config.clone()),
+            chain,
             stats: Arc::new(RwLock::new(ProcessingStats::default())),
         })
     }
 
-    /// Process a single event
-    pub async fn process(&self, mut event: AnalyticsEvent) -> Result<AnalyticsEvent> {
+    /// Process a single event, returning `None` if a transform dropped it
+    pub async fn process(&self, mut event: AnalyticsEvent) -> Result<Option<AnalyticsEvent>> {
         debug!("Processing event: {}", event.common.event_id);
 
-        // Validate event
-        self.validate(&event)?;
-
-        // Enrich event
-        self.enrich(&mut event).await?;
+        let outcome = self.chain.apply(&mut event)?;
 
         // Update statistics
         let mut stats = self.stats.write().await;
-        stats.events_processed += 1;
-
-        Ok(event)
+        match outcome {
+            ChainOutcome::Emit => {
+                stats.events_processed += 1;
+                Ok(Some(event))
+            }
+            ChainOutcome::Dropped { stage } => {
+                debug!("Event {} dropped by {}", event.common.event_id, stage);
+                stats.events_dropped += 1;
+                Ok(None)
+            }
+        }
     }
 
     /// Process a batch of events
@@ -49,7 +73,8 @@ impl EventProcessor {
 
         for event in events {
             match self.process(event).await {
-                Ok(processed_event) => processed.push(processed_event),
+                Ok(Some(processed_event)) => processed.push(processed_event),
+                Ok(None) => {}
                 Err(e) => {
                     let mut stats = self.stats.write().await;
                     stats.events_failed += 1;
@@ -61,8 +86,38 @@ impl EventProcessor {
         Ok(processed)
     }
 
-    /// Validate an event
-    fn validate(&self, event: &AnalyticsEvent) -> Result<()> {
+    /// Get processing statistics
+    pub async fn get_stats(&self) -> ProcessingStats {
+        self.stats.read().await.clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl PipelineComponent for EventProcessor {
+    async fn initialize(&mut self) -> Result<()> {
+        info!("Event processor initialized");
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<()> {
+        info!("Event processor shutting down");
+        Ok(())
+    }
+
+    async fn health_check(&self) -> Result<HealthStatus> {
+        Ok(HealthStatus::healthy())
+    }
+}
+
+/// Validates required event fields
+struct Validate;
+
+impl Transform for Validate {
+    fn name(&self) -> &str {
+        "validate"
+    }
+
+    fn apply(&self, event: &mut AnalyticsEvent) -> Result<Action> {
         // Check schema version
         if event.common.schema_version.is_empty() {
             anyhow::bail!("Schema version is required");
@@ -74,16 +129,27 @@ impl EventProcessor {
         }
 
         // Additional validation logic
-        Ok(())
+        Ok(Action::Continue)
     }
+}
 
-    /// Enrich event with additional metadata
-    async fn enrich(&self, event: &mut AnalyticsEvent) -> Result<()> {
+/// Enriches events with additional metadata
+struct Enrich {
+    clock: Clock,
+}
+
+impl Transform for Enrich {
+    fn name(&self) -> &str {
+        "enrich"
+    }
+
+    fn apply(&self, event: &mut AnalyticsEvent) -> Result<Action> {
         // Add processing timestamp tag
-        event.common.tags.insert(
-            "processed_at".to_string(),
-            chrono::Utc::now().to_rfc3339(),
-        );
+        let processed_at = self.clock.now().to_rfc3339();
+        event
+            .common
+            .tags
+            .insert("processed_at".to_string(), processed_at);
 
         // Add pipeline version
         event.common.tags.insert(
@@ -91,29 +157,7 @@ impl EventProcessor {
             env!("CARGO_PKG_VERSION").to_string(),
         );
 
-        Ok(())
-    }
-
-    /// Get processing statistics
-    pub async fn get_stats(&self) -> ProcessingStats {
-        self.stats.read().await.clone()
-    }
-}
-
-#[async_trait::async_trait]
-impl PipelineComponent for EventProcessor {
-    async fn initialize(&mut self) -> Result<()> {
-        info!("Event processor initialized");
-        Ok(())
-    }
-
-    async fn shutdown(&mut self) -> Result<()> {
-        info!("Event processor shutting down");
-        Ok(())
-    }
-
-    async fn health_check(&self) -> Result<HealthStatus> {
-        Ok(HealthStatus::healthy())
+        Ok(Action::Continue)
     }
 }
 
@@ -122,5 +166,6 @@ impl PipelineComponent for EventProcessor {
 pub struct ProcessingStats {
     pub events_processed: u64,
     pub events_failed: u64,
+    pub events_dropped: u64,
     pub avg_processing_time_ms: f64,
 }
@@ -0,0 +1,504 @@
+//! Event Sampling
+//!
+//! Reduces event volume while keeping every event that matters. Events at
+//! or above `keep_severity`, security events and cost events are always
+//! kept; spend is attributed per event and is never re-weighted. Other
+//! events are sampled by three cooperating policies:
+//!
+//! - **Head sampling** keeps a fixed fraction of traces, decided by a hash
+//!   of the `correlation_id` (or event ID) so that every hub instance keeps
+//!   or drops a whole trace together.
+//! - **Tail sampling** buffers events by `correlation_id` for `tail_wait`.
+//!   A group containing an always-kept or slow event is kept in full; other
+//!   groups fall back to the head and dynamic rates when the wait expires.
+//! - **Dynamic rates** share an events/sec budget between the values of
+//!   `rate_key`, so that one noisy producer is sampled harder than quiet ones.
+//!
+//! Kept events carry their inverse sampling probability in the
+//! [`SAMPLE_WEIGHT_TAG`] tag so that counts and sums can be re-weighted.
+
+use super::transform::{field_string, sample_fraction};
+use crate::schemas::events::{AnalyticsEvent, EventPayload, EventType, Severity, TelemetryPayload};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{HashMap, VecDeque};
+
+/// Tag holding the number of original events a kept event stands for
+pub const SAMPLE_WEIGHT_TAG: &str = "sample_weight";
+
+/// Number of original events an event stands for
+pub fn sample_weight(event: &AnalyticsEvent) -> f64 {
+    event
+        .common
+        .tags
+        .get(SAMPLE_WEIGHT_TAG)
+        .and_then(|w| w.parse::<f64>().ok())
+        .filter(|w| w.is_finite() && *w >= 1.0)
+        .unwrap_or(1.0)
+}
+
+/// Record that an event was kept with probability `1 / weight`, compounding
+/// any weight from earlier sampling
+pub fn apply_weight(event: &mut AnalyticsEvent, weight: f64) {
+    if weight <= 1.0 {
+        return;
+    }
+    let weight = sample_weight(event) * weight;
+    event
+        .common
+        .tags
+        .insert(SAMPLE_WEIGHT_TAG.to_string(), weight.to_string());
+}
+
+/// Sampling configuration
+#[derive(Debug, Clone)]
+pub struct SamplingConfig {
+    /// Severity at or above which events are always kept
+    pub keep_severity: Severity,
+    /// Fraction of traces kept by head sampling
+    pub head_rate: f64,
+    /// How long correlation groups are buffered; `None` disables tail sampling
+    pub tail_wait: Option<Duration>,
+    /// Latency at or above which a correlation group is kept
+    pub slow_latency_ms: f64,
+    /// Buffered events beyond which the oldest groups are decided early
+    pub max_buffered: usize,
+    /// Events/sec shared between rate keys; `None` disables dynamic rates
+    pub budget_eps: Option<f64>,
+    /// Field whose values get a share of the budget
+    pub rate_key: String,
+    /// How often dynamic rates are recomputed
+    pub adjust_interval: Duration,
+    /// Lowest dynamic rate
+    pub min_rate: f64,
+}
+
+impl Default for SamplingConfig {
+    fn default() -> Self {
+        Self {
+            keep_severity: Severity::Error,
+            head_rate: 1.0,
+            tail_wait: None,
+            slow_latency_ms: 5_000.0,
+            max_buffered: 100_000,
+            budget_eps: None,
+            rate_key: "source_module".to_string(),
+            adjust_interval: Duration::seconds(10),
+            min_rate: 0.001,
+        }
+    }
+}
+
+/// Sampling statistics
+#[derive(Debug, Clone, Default)]
+pub struct SamplingStats {
+    pub events_seen: u64,
+    pub events_kept: u64,
+    pub events_dropped: u64,
+    pub events_buffered: usize,
+    pub groups_buffered: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Verdict {
+    Keep { weight: f64 },
+    Drop,
+}
+
+struct Group {
+    events: Vec<AnalyticsEvent>,
+    rate_key: String,
+}
+
+/// Per-key rates sharing an events/sec budget
+#[derive(Default)]
+struct DynamicRates {
+    rates: HashMap<String, f64>,
+    seen: HashMap<String, u64>,
+    window_start: Option<DateTime<Utc>>,
+}
+
+impl DynamicRates {
+    fn rate(&self, key: &str) -> f64 {
+        self.rates.get(key).copied().unwrap_or(1.0)
+    }
+
+    fn observe(&mut self, key: &str, now: DateTime<Utc>, config: &SamplingConfig, budget: f64) {
+        let start = *self.window_start.get_or_insert(now);
+        let elapsed = now - start;
+        if elapsed >= config.adjust_interval {
+            // Max-min fair shares: keys under an equal share keep everything
+            // and leave the rest of the budget to the noisier keys
+            let mut counts: Vec<_> = self.seen.drain().collect();
+            counts.sort_by_key(|(_, count)| *count);
+            let mut remaining = budget * elapsed.num_milliseconds() as f64 / 1000.0;
+            let mut keys = counts.len();
+            self.rates.clear();
+            for (key, count) in counts {
+                let share = remaining / keys as f64;
+                let rate = (share / count as f64).clamp(config.min_rate, 1.0);
+                remaining -= count as f64 * rate;
+                keys -= 1;
+                self.rates.insert(key, rate);
+            }
+            self.window_start = Some(now);
+        }
+        *self.seen.entry(key.to_string()).or_default() += 1;
+    }
+}
+
+/// Head, tail and dynamic event sampler
+pub struct Sampler {
+    config: SamplingConfig,
+    groups: HashMap<String, Group>,
+    // Decision deadlines in arrival order
+    deadlines: VecDeque<(DateTime<Utc>, String)>,
+    // Decided groups, remembered for late events until they expire
+    decided: HashMap<String, (Verdict, DateTime<Utc>)>,
+    rates: DynamicRates,
+    stats: SamplingStats,
+}
+
+impl Sampler {
+    pub fn new(config: SamplingConfig) -> Self {
+        Self {
+            config,
+            groups: HashMap::new(),
+            deadlines: VecDeque::new(),
+            decided: HashMap::new(),
+            rates: DynamicRates::default(),
+            stats: SamplingStats::default(),
+        }
+    }
+
+    /// Offer an event, returning the events released by this call
+    pub fn offer(&mut self, event: AnalyticsEvent, now: DateTime<Utc>) -> Vec<AnalyticsEvent> {
+        let mut released = self.tick(now);
+        self.stats.events_seen += 1;
+
+        let always_keep = self.always_keep(&event);
+        let rate_key = field_string(&event, &self.config.rate_key).unwrap_or_default();
+        if let (false, Some(budget)) = (always_keep, self.config.budget_eps) {
+            self.rates.observe(&rate_key, now, &self.config, budget);
+        }
+
+        let (Some(wait), Some(correlation_id)) =
+            (self.config.tail_wait, event.common.correlation_id)
+        else {
+            // Head and dynamic sampling
+            let verdict = if always_keep {
+                Verdict::Keep { weight: 1.0 }
+            } else {
+                let key = event
+                    .common
+                    .correlation_id
+                    .unwrap_or(event.common.event_id)
+                    .to_string();
+                self.verdict(&key, &rate_key)
+            };
+            self.release(vec![event], verdict, &mut released);
+            return released;
+        };
+
+        let key = correlation_id.to_string();
+        let interesting = always_keep || self.is_slow(&event);
+        if let Some((verdict, _)) = self.decided.get(&key) {
+            // Late event of a decided group
+            let verdict = if always_keep {
+                Verdict::Keep { weight: 1.0 }
+            } else {
+                *verdict
+            };
+            self.release(vec![event], verdict, &mut released);
+        } else if interesting {
+            let mut events = self
+                .groups
+                .remove(&key)
+                .map(|group| group.events)
+                .unwrap_or_default();
+            self.stats.events_buffered -= events.len();
+            events.push(event);
+            let verdict = Verdict::Keep { weight: 1.0 };
+            self.decided.insert(key, (verdict, now + wait));
+            self.release(events, verdict, &mut released);
+        } else {
+            let group = self.groups.entry(key.clone()).or_insert_with(|| {
+                self.deadlines.push_back((now + wait, key));
+                Group {
+                    events: Vec::new(),
+                    rate_key,
+                }
+            });
+            group.events.push(event);
+            self.stats.events_buffered += 1;
+
+            while self.stats.events_buffered > self.config.max_buffered {
+                let Some((_, key)) = self.deadlines.pop_front() else {
+                    break;
+                };
+                self.decide(key, now, &mut released);
+            }
+        }
+
+        released
+    }
+
+    /// Decide groups whose wait expired, returning the events they release
+    pub fn tick(&mut self, now: DateTime<Utc>) -> Vec<AnalyticsEvent> {
+        let mut released = Vec::new();
+        while matches!(self.deadlines.front(), Some((deadline, _)) if *deadline <= now) {
+            let (_, key) = self.deadlines.pop_front().unwrap();
+            self.decide(key, now, &mut released);
+        }
+        self.decided.retain(|_, (_, expires)| *expires > now);
+        released
+    }
+
+    /// Decide every buffered group, e.g. on shutdown
+    pub fn flush(&mut self) -> Vec<AnalyticsEvent> {
+        let mut released = Vec::new();
+        let now = Utc::now();
+        while let Some((_, key)) = self.deadlines.pop_front() {
+            self.decide(key, now, &mut released);
+        }
+        released
+    }
+
+    pub fn stats(&self) -> SamplingStats {
+        SamplingStats {
+            groups_buffered: self.groups.len(),
+            ..self.stats.clone()
+        }
+    }
+
+    /// Current dynamic rate of a rate key value
+    pub fn dynamic_rate(&self, key: &str) -> f64 {
+        self.rates.rate(key)
+    }
+
+    fn always_keep(&self, event: &AnalyticsEvent) -> bool {
+        event.common.severity >= self.config.keep_severity
+            || matches!(
+                event.common.event_type,
+                EventType::Security | EventType::Cost
+            )
+    }
+
+    fn is_slow(&self, event: &AnalyticsEvent) -> bool {
+        matches!(
+            &event.payload,
+            EventPayload::Telemetry(TelemetryPayload::Latency(latency))
+                if latency.total_latency_ms >= self.config.slow_latency_ms
+        )
+    }
+
+    fn verdict(&self, key: &str, rate_key: &str) -> Verdict {
+        let rate = self.config.head_rate.min(self.rates.rate(rate_key));
+        if rate > 0.0 && sample_fraction(key) < rate {
+            Verdict::Keep { weight: 1.0 / rate }
+        } else {
+            Verdict::Drop
+        }
+    }
+
+    /// Decide a group that had no always-kept or slow event
+    fn decide(&mut self, key: String, now: DateTime<Utc>, released: &mut Vec<AnalyticsEvent>) {
+        let Some(group) = self.groups.remove(&key) else {
+            return;
+        };
+        self.stats.events_buffered -= group.events.len();
+        let verdict = self.verdict(&key, &group.rate_key);
+        let expires = now + self.config.tail_wait.unwrap_or_else(Duration::zero);
+        self.decided.insert(key, (verdict, expires));
+        self.release(group.events, verdict, released);
+    }
+
+    fn release(
+        &mut self,
+        events: Vec<AnalyticsEvent>,
+        verdict: Verdict,
+        released: &mut Vec<AnalyticsEvent>,
+    ) {
+        match verdict {
+            Verdict::Keep { weight } => {
+                self.stats.events_kept += events.len() as u64;
+                released.extend(events.into_iter().map(|mut event| {
+                    apply_weight(&mut event, weight);
+                    event
+                }));
+            }
+            Verdict::Drop => self.stats.events_dropped += events.len() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::events::*;
+    use uuid::Uuid;
+
+    fn event(
+        severity: Severity,
+        correlation_id: Option<Uuid>,
+        source: SourceModule,
+        latency_ms: f64,
+    ) -> AnalyticsEvent {
+        fixtures::latency("gpt-4", latency_ms)
+            .with_source(source)
+            .with_severity(severity)
+            .with_correlation_id(correlation_id)
+            .with_environment("production")
+    }
+
+    fn debug(correlation_id: Option<Uuid>) -> AnalyticsEvent {
+        event(
+            Severity::Debug,
+            correlation_id,
+            SourceModule::LlmObservatory,
+            100.0,
+        )
+    }
+
+    #[test]
+    fn test_head_sampling_keeps_whole_traces_and_all_errors() {
+        let mut sampler = Sampler::new(SamplingConfig {
+            head_rate: 0.2,
+            ..Default::default()
+        });
+        let now = Utc::now();
+
+        let mut traces_kept = 0;
+        for _ in 0..2_000 {
+            let trace = Some(Uuid::new_v4());
+            let kept: Vec<_> = (0..3)
+                .flat_map(|_| sampler.offer(debug(trace), now))
+                .collect();
+            assert!(kept.is_empty() || kept.len() == 3, "trace split");
+            if !kept.is_empty() {
+                traces_kept += 1;
+                assert!(kept.iter().all(|e| sample_weight(e) == 5.0));
+            }
+        }
+        assert!((300..500).contains(&traces_kept), "kept {}", traces_kept);
+
+        let error = event(Severity::Error, None, SourceModule::LlmObservatory, 10.0);
+        let mut security = debug(None);
+        security.common.event_type = EventType::Security;
+        for always in [error, security] {
+            let kept = sampler.offer(always, now);
+            assert_eq!(kept.len(), 1);
+            assert_eq!(sample_weight(&kept[0]), 1.0);
+        }
+        assert_eq!(sampler.stats().events_seen, 6_002);
+    }
+
+    #[test]
+    fn test_cost_events_are_never_sampled() {
+        let mut sampler = Sampler::new(SamplingConfig {
+            head_rate: 0.0,
+            budget_eps: Some(1.0),
+            ..Default::default()
+        });
+        let now = Utc::now();
+
+        for _ in 0..100 {
+            assert!(sampler.offer(debug(None), now).is_empty());
+            let kept = sampler.offer(debug(None).with_event_type(EventType::Cost), now);
+            assert_eq!(kept.len(), 1);
+            assert_eq!(sample_weight(&kept[0]), 1.0);
+        }
+    }
+
+    #[test]
+    fn test_tail_sampling_keeps_groups_with_errors_or_slow_requests() {
+        let mut sampler = Sampler::new(SamplingConfig {
+            head_rate: 0.0,
+            tail_wait: Some(Duration::seconds(10)),
+            ..Default::default()
+        });
+        let start = Utc::now();
+        let (failed, slow, quiet) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        for trace in [failed, slow, quiet] {
+            assert!(sampler.offer(debug(Some(trace)), start).is_empty());
+            assert!(sampler.offer(debug(Some(trace)), start).is_empty());
+        }
+        assert_eq!(sampler.stats().events_buffered, 6);
+
+        // An error releases its whole group immediately
+        let error = event(
+            Severity::Error,
+            Some(failed),
+            SourceModule::LlmObservatory,
+            10.0,
+        );
+        assert_eq!(sampler.offer(error, start).len(), 3);
+        let slow_event = event(
+            Severity::Info,
+            Some(slow),
+            SourceModule::LlmObservatory,
+            9_000.0,
+        );
+        assert_eq!(sampler.offer(slow_event, start).len(), 3);
+        // Late events of a kept group are kept
+        assert_eq!(sampler.offer(debug(Some(failed)), start).len(), 1);
+
+        // The uninteresting group is decided by head rate when the wait expires
+        assert!(sampler.tick(start + Duration::seconds(5)).is_empty());
+        assert_eq!(sampler.stats().groups_buffered, 1);
+        assert!(sampler.tick(start + Duration::seconds(11)).is_empty());
+        assert_eq!(sampler.stats().events_buffered, 0);
+        assert!(sampler
+            .offer(debug(Some(quiet)), start + Duration::seconds(12))
+            .is_empty());
+
+        let stats = sampler.stats();
+        assert_eq!((stats.events_kept, stats.events_dropped), (7, 3));
+    }
+
+    #[test]
+    fn test_dynamic_rates_share_the_budget_between_keys() {
+        let mut sampler = Sampler::new(SamplingConfig {
+            budget_eps: Some(100.0),
+            adjust_interval: Duration::seconds(10),
+            ..Default::default()
+        });
+        let start = Utc::now();
+
+        // 10s of a noisy producer at 900/s and a quiet one at 10/s
+        let second = |sampler: &mut Sampler, at: DateTime<Utc>| {
+            let mut kept = 0;
+            for _ in 0..900 {
+                kept += sampler.offer(debug(None), at).len();
+            }
+            for _ in 0..10 {
+                let quiet = event(Severity::Info, None, SourceModule::LlmRegistry, 100.0);
+                kept += sampler.offer(quiet, at).len();
+            }
+            kept
+        };
+        for s in 0..10 {
+            second(&mut sampler, start + Duration::seconds(s));
+        }
+
+        let kept = second(&mut sampler, start + Duration::seconds(10));
+        assert_eq!(sampler.dynamic_rate("llm-registry"), 1.0);
+        assert!((sampler.dynamic_rate("llm-observatory") - 0.1).abs() < 1e-9);
+        assert!((60..140).contains(&kept), "kept {}", kept);
+    }
+
+    #[test]
+    fn test_weights_compound_across_sampling_stages() {
+        let mut e = debug(None);
+        assert_eq!(sample_weight(&e), 1.0);
+        apply_weight(&mut e, 4.0);
+        apply_weight(&mut e, 2.5);
+        assert_eq!(e.common.tags.get(SAMPLE_WEIGHT_TAG).unwrap(), "10");
+        assert_eq!(sample_weight(&e), 10.0);
+
+        e.common
+            .tags
+            .insert(SAMPLE_WEIGHT_TAG.to_string(), "bogus".to_string());
+        assert_eq!(sample_weight(&e), 1.0);
+    }
+}
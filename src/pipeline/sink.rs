@@ -0,0 +1,413 @@
+//! Output Sinks
+//!
+//! Sinks receive the records the embedded pipeline produces: processed
+//! events and window aggregates. Records go to TimescaleDB, to NDJSON files
+//! or stdout, or, with the `parquet-sink` feature, to Parquet files.
+
+use crate::database::copy::{CopyConfig, CopyWriter};
+use crate::database::Database;
+use crate::models::metrics::AggregatedMetric;
+use crate::schemas::events::AnalyticsEvent;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::fs::File;
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter, Stdout};
+
+/// Destination for records of type `T`
+#[async_trait]
+pub trait Sink<T: Sync>: Send {
+    fn name(&self) -> &str;
+
+    async fn write(&mut self, records: &[T]) -> Result<()>;
+
+    /// Flush buffered records and finish the output
+    async fn close(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: Sync, S: Sink<T> + ?Sized> Sink<T> for Box<S> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    async fn write(&mut self, records: &[T]) -> Result<()> {
+        (**self).write(records).await
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        (**self).close().await
+    }
+}
+
+/// Stores events and aggregates in TimescaleDB with COPY BINARY
+pub struct TimescaleSink {
+    writer: CopyWriter,
+}
+
+impl TimescaleSink {
+    pub fn new(database: Arc<Database>) -> Self {
+        Self {
+            writer: database.copy_writer(CopyConfig::default()),
+        }
+    }
+}
+
+#[async_trait]
+impl Sink<AnalyticsEvent> for TimescaleSink {
+    fn name(&self) -> &str {
+        "timescaledb"
+    }
+
+    async fn write(&mut self, records: &[AnalyticsEvent]) -> Result<()> {
+        self.writer.write_events(records).await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Sink<AggregatedMetric> for TimescaleSink {
+    fn name(&self) -> &str {
+        "timescaledb"
+    }
+
+    /// Gauge aggregates are not stored
+    async fn write(&mut self, records: &[AggregatedMetric]) -> Result<()> {
+        self.writer.write_aggregates(records).await?;
+        Ok(())
+    }
+}
+
+/// Writes records as newline-delimited JSON
+pub struct NdjsonSink<W> {
+    name: String,
+    writer: BufWriter<W>,
+}
+
+impl<W: AsyncWrite + Unpin + Send> NdjsonSink<W> {
+    pub fn new(name: impl Into<String>, writer: W) -> Self {
+        Self {
+            name: name.into(),
+            writer: BufWriter::new(writer),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner()
+    }
+}
+
+impl NdjsonSink<File> {
+    /// Write to a new NDJSON file, replacing any existing one
+    pub async fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::create(path)
+            .await
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        Ok(Self::new(path.display().to_string(), file))
+    }
+}
+
+impl NdjsonSink<Stdout> {
+    /// Write to standard output
+    pub fn stdout() -> Self {
+        Self::new("stdout", tokio::io::stdout())
+    }
+}
+
+#[async_trait]
+impl<T, W> Sink<T> for NdjsonSink<W>
+where
+    T: Serialize + Sync,
+    W: AsyncWrite + Unpin + Send,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn write(&mut self, records: &[T]) -> Result<()> {
+        let mut buffer = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut buffer, record)?;
+            buffer.push(b'\n');
+        }
+        self.writer.write_all(&buffer).await?;
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<()> {
+        self.writer.flush().await?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "parquet-sink")]
+pub use parquet_sink::{ParquetRecord, ParquetSink};
+
+#[cfg(feature = "parquet-sink")]
+mod parquet_sink {
+    use super::*;
+    use crate::models::metrics::MetricValues;
+    use arrow::array::{
+        ArrayRef, Float64Array, StringArray, TimestampMicrosecondArray, UInt64Array,
+    };
+    use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+    use arrow::record_batch::RecordBatch;
+    use chrono::{DateTime, Utc};
+    use parquet::arrow::ArrowWriter;
+    use parquet::basic::Compression;
+    use parquet::file::properties::WriterProperties;
+    use std::marker::PhantomData;
+
+    /// Records with a fixed columnar layout
+    pub trait ParquetRecord: Sized {
+        fn schema() -> SchemaRef;
+
+        fn record_batch(records: &[Self]) -> Result<RecordBatch>;
+    }
+
+    fn timestamp_type() -> DataType {
+        DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into()))
+    }
+
+    fn timestamps(values: impl Iterator<Item = DateTime<Utc>>) -> ArrayRef {
+        Arc::new(
+            TimestampMicrosecondArray::from(
+                values.map(|t| t.timestamp_micros()).collect::<Vec<_>>(),
+            )
+            .with_timezone("UTC"),
+        )
+    }
+
+    fn strings<S: Into<String>>(values: impl Iterator<Item = S>) -> ArrayRef {
+        Arc::new(StringArray::from(
+            values.map(Into::into).collect::<Vec<String>>(),
+        ))
+    }
+
+    fn optional_strings(values: impl Iterator<Item = Option<String>>) -> ArrayRef {
+        Arc::new(StringArray::from(values.collect::<Vec<_>>()))
+    }
+
+    /// Serialized name of a unit enum variant, e.g. `llm_observatory`
+    fn variant<T: Serialize>(value: &T) -> String {
+        match serde_json::to_value(value) {
+            Ok(serde_json::Value::String(s)) => s,
+            Ok(other) => other.to_string(),
+            Err(_) => String::new(),
+        }
+    }
+
+    fn json<T: Serialize>(value: &T) -> Result<String> {
+        Ok(serde_json::to_string(value)?)
+    }
+
+    impl ParquetRecord for AnalyticsEvent {
+        fn schema() -> SchemaRef {
+            Arc::new(Schema::new(vec![
+                Field::new("event_id", DataType::Utf8, false),
+                Field::new("timestamp", timestamp_type(), false),
+                Field::new("source_module", DataType::Utf8, false),
+                Field::new("event_type", DataType::Utf8, false),
+                Field::new("correlation_id", DataType::Utf8, true),
+                Field::new("parent_event_id", DataType::Utf8, true),
+                Field::new("schema_version", DataType::Utf8, false),
+                Field::new("severity", DataType::Utf8, false),
+                Field::new("environment", DataType::Utf8, false),
+                Field::new("tags", DataType::Utf8, false),
+                Field::new("payload", DataType::Utf8, false),
+            ]))
+        }
+
+        fn record_batch(records: &[Self]) -> Result<RecordBatch> {
+            let common = || records.iter().map(|e| &e.common);
+            let tags = records
+                .iter()
+                .map(|e| json(&e.common.tags))
+                .collect::<Result<Vec<_>>>()?;
+            let payloads = records
+                .iter()
+                .map(|e| json(&e.payload))
+                .collect::<Result<Vec<_>>>()?;
+
+            let columns = vec![
+                strings(common().map(|c| c.event_id.to_string())),
+                timestamps(common().map(|c| c.timestamp)),
+                strings(common().map(|c| variant(&c.source_module))),
+                strings(common().map(|c| variant(&c.event_type))),
+                optional_strings(common().map(|c| c.correlation_id.map(|id| id.to_string()))),
+                optional_strings(common().map(|c| c.parent_event_id.map(|id| id.to_string()))),
+                strings(common().map(|c| c.schema_version.clone())),
+                strings(common().map(|c| variant(&c.severity))),
+                strings(common().map(|c| c.environment.clone())),
+                strings(tags.into_iter()),
+                strings(payloads.into_iter()),
+            ];
+            Ok(RecordBatch::try_new(Self::schema(), columns)?)
+        }
+    }
+
+    impl ParquetRecord for AggregatedMetric {
+        fn schema() -> SchemaRef {
+            let stat = |name| Field::new(name, DataType::Float64, true);
+            Arc::new(Schema::new(vec![
+                Field::new("name", DataType::Utf8, false),
+                Field::new("window", DataType::Utf8, false),
+                Field::new("window_start", timestamp_type(), false),
+                Field::new("window_end", timestamp_type(), false),
+                Field::new("count", DataType::UInt64, true),
+                stat("avg"),
+                stat("min"),
+                stat("max"),
+                stat("p50"),
+                stat("p95"),
+                stat("p99"),
+                Field::new("values", DataType::Utf8, false),
+                Field::new("tags", DataType::Utf8, false),
+            ]))
+        }
+
+        fn record_batch(records: &[Self]) -> Result<RecordBatch> {
+            let measures = || {
+                records.iter().map(|m| match &m.values {
+                    MetricValues::Stats(measures) => Some(measures),
+                    _ => None,
+                })
+            };
+            let stat = |f: fn(&crate::models::metrics::StatisticalMeasures) -> f64| -> ArrayRef {
+                Arc::new(Float64Array::from(
+                    measures().map(|m| m.map(f)).collect::<Vec<_>>(),
+                ))
+            };
+            let values = records
+                .iter()
+                .map(|m| json(&m.values))
+                .collect::<Result<Vec<_>>>()?;
+            let tags = records
+                .iter()
+                .map(|m| json(&m.tags))
+                .collect::<Result<Vec<_>>>()?;
+
+            let columns = vec![
+                strings(records.iter().map(|m| m.name.clone())),
+                strings(records.iter().map(|m| m.window.as_str())),
+                timestamps(records.iter().map(|m| m.window_start)),
+                timestamps(records.iter().map(|m| m.window_end)),
+                Arc::new(UInt64Array::from(
+                    measures().map(|m| m.map(|m| m.count)).collect::<Vec<_>>(),
+                )) as ArrayRef,
+                stat(|m| m.avg),
+                stat(|m| m.min),
+                stat(|m| m.max),
+                stat(|m| m.p50),
+                stat(|m| m.p95),
+                stat(|m| m.p99),
+                strings(values.into_iter()),
+                strings(tags.into_iter()),
+            ];
+            Ok(RecordBatch::try_new(Self::schema(), columns)?)
+        }
+    }
+
+    /// Writes records to a Snappy-compressed Parquet file. The file is only
+    /// readable once the sink is closed.
+    pub struct ParquetSink<T> {
+        name: String,
+        writer: Option<ArrowWriter<std::fs::File>>,
+        _records: PhantomData<fn(&T)>,
+    }
+
+    impl<T: ParquetRecord> ParquetSink<T> {
+        pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+            let path = path.as_ref();
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            let properties = WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build();
+            Ok(Self {
+                name: path.display().to_string(),
+                writer: Some(ArrowWriter::try_new(file, T::schema(), Some(properties))?),
+                _records: PhantomData,
+            })
+        }
+    }
+
+    #[async_trait]
+    impl<T: ParquetRecord + Sync> Sink<T> for ParquetSink<T> {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        async fn write(&mut self, records: &[T]) -> Result<()> {
+            if records.is_empty() {
+                return Ok(());
+            }
+            let writer = self.writer.as_mut().context("Parquet sink is closed")?;
+            writer.write(&T::record_batch(records)?)?;
+            Ok(())
+        }
+
+        async fn close(&mut self) -> Result<()> {
+            if let Some(writer) = self.writer.take() {
+                writer.close()?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::source::{NdjsonSource, Source};
+    use crate::schemas::events::*;
+    use uuid::Uuid;
+
+    fn event() -> AnalyticsEvent {
+        fixtures::latency("gpt-4", 100.0)
+            .with_correlation_id(Some(Uuid::new_v4()))
+            .with_tags(&[("team", "search")])
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_sink_output_reads_back() {
+        let events = vec![event(), event()];
+        let mut sink = NdjsonSink::new("memory", Vec::new());
+        sink.write(&events).await.unwrap();
+        Sink::<AnalyticsEvent>::close(&mut sink).await.unwrap();
+
+        let output = sink.into_inner();
+        let mut source = NdjsonSource::new("memory", output.as_slice());
+        for expected in &events {
+            let read = source.next().await.unwrap().unwrap();
+            assert_eq!(read.common.event_id, expected.common.event_id);
+            assert_eq!(read.common.tags, expected.common.tags);
+        }
+        assert!(source.next().await.unwrap().is_none());
+    }
+
+    #[cfg(feature = "parquet-sink")]
+    #[tokio::test]
+    async fn test_parquet_sink_writes_every_row() {
+        use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.parquet");
+        let mut sink = ParquetSink::<AnalyticsEvent>::create(&path).unwrap();
+        sink.write(&[event(), event()]).await.unwrap();
+        sink.write(&[event()]).await.unwrap();
+        sink.close().await.unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let rows: usize = reader.map(|batch| batch.unwrap().num_rows()).sum();
+        assert_eq!(rows, 3);
+    }
+}
@@ -0,0 +1,373 @@
+//! Event Sources
+//!
+//! Sources feed events to the embedded pipeline without Kafka: NDJSON files,
+//! stdin, and an HTTP push buffer exposing the ingestion API routes. Any
+//! source can be wrapped in a [`ReplaySource`], which emits its events in
+//! timestamp order and drives the pipeline [`Clock`] from event time, so
+//! replaying the same input always produces the same output.
+
+use crate::models::api::{ApiError, ApiResponse};
+use crate::schemas::events::AnalyticsEvent;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use axum::extract::{Json, State};
+use axum::http::StatusCode;
+use axum::routing::post;
+use axum::Router;
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::fs::File;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader, Lines, Stdin};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// A stream of events
+#[async_trait]
+pub trait Source: Send {
+    fn name(&self) -> &str;
+
+    /// Next event, or `None` once the source is exhausted
+    async fn next(&mut self) -> Result<Option<AnalyticsEvent>>;
+}
+
+#[async_trait]
+impl<S: Source + ?Sized> Source for Box<S> {
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    async fn next(&mut self) -> Result<Option<AnalyticsEvent>> {
+        (**self).next().await
+    }
+}
+
+/// Time source of the pipeline: the system clock, or the time of the latest
+/// replayed event
+#[derive(Debug, Clone, Default)]
+pub enum Clock {
+    #[default]
+    System,
+    EventTime(Arc<RwLock<Option<DateTime<Utc>>>>),
+}
+
+impl Clock {
+    pub fn now(&self) -> DateTime<Utc> {
+        match self {
+            Self::System => Utc::now(),
+            Self::EventTime(time) => time.read().unwrap_or(DateTime::UNIX_EPOCH),
+        }
+    }
+
+    pub fn is_event_time(&self) -> bool {
+        matches!(self, Self::EventTime(_))
+    }
+
+    fn advance(&self, to: DateTime<Utc>) {
+        if let Self::EventTime(time) = self {
+            let mut time = time.write();
+            if !matches!(*time, Some(current) if current >= to) {
+                *time = Some(to);
+            }
+        }
+    }
+}
+
+/// Newline-delimited JSON events. Blank lines are ignored and malformed
+/// lines are logged and skipped.
+pub struct NdjsonSource<R> {
+    name: String,
+    lines: Lines<R>,
+    line: u64,
+    skipped: u64,
+}
+
+impl<R: AsyncBufRead + Unpin + Send> NdjsonSource<R> {
+    pub fn new(name: impl Into<String>, reader: R) -> Self {
+        Self {
+            name: name.into(),
+            lines: reader.lines(),
+            line: 0,
+            skipped: 0,
+        }
+    }
+
+    /// Malformed lines skipped so far
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+impl NdjsonSource<BufReader<File>> {
+    /// Read events from an NDJSON file
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .await
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+        Ok(Self::new(path.display().to_string(), BufReader::new(file)))
+    }
+}
+
+impl NdjsonSource<BufReader<Stdin>> {
+    /// Read events from standard input
+    pub fn stdin() -> Self {
+        Self::new("stdin", BufReader::new(tokio::io::stdin()))
+    }
+}
+
+#[async_trait]
+impl<R: AsyncBufRead + Unpin + Send> Source for NdjsonSource<R> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn next(&mut self) -> Result<Option<AnalyticsEvent>> {
+        while let Some(line) = self.lines.next_line().await? {
+            self.line += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(event) => return Ok(Some(event)),
+                Err(e) => {
+                    self.skipped += 1;
+                    warn!(
+                        "Skipping malformed event at {}:{}: {}",
+                        self.name, self.line, e
+                    );
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Handle pushing events into an [`HttpPushSource`]
+#[derive(Clone)]
+pub struct PushBuffer {
+    tx: mpsc::Sender<AnalyticsEvent>,
+}
+
+#[derive(Debug, Serialize)]
+struct PushResponse {
+    accepted: usize,
+}
+
+impl PushBuffer {
+    /// Queue an event, waiting while the buffer is full
+    pub async fn push(&self, event: AnalyticsEvent) -> Result<()> {
+        self.tx
+            .send(event)
+            .await
+            .map_err(|_| anyhow::anyhow!("push source closed"))
+    }
+
+    /// Routes accepting events like the ingestion API:
+    /// `POST /api/v1/events` and `POST /api/v1/events/batch`
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/api/v1/events", post(push_event))
+            .route("/api/v1/events/batch", post(push_batch))
+            .with_state(self)
+    }
+}
+
+type PushResult = (StatusCode, Json<ApiResponse<PushResponse>>);
+
+async fn push_event(
+    State(buffer): State<PushBuffer>,
+    Json(event): Json<AnalyticsEvent>,
+) -> PushResult {
+    push_all(&buffer, vec![event]).await
+}
+
+async fn push_batch(
+    State(buffer): State<PushBuffer>,
+    Json(events): Json<Vec<AnalyticsEvent>>,
+) -> PushResult {
+    push_all(&buffer, events).await
+}
+
+async fn push_all(buffer: &PushBuffer, events: Vec<AnalyticsEvent>) -> PushResult {
+    let mut accepted = 0;
+    for event in events {
+        if buffer.push(event).await.is_err() {
+            let error = ApiError::new("unavailable", "Pipeline is shutting down", 503);
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ApiResponse::error(error)),
+            );
+        }
+        accepted += 1;
+    }
+    (
+        StatusCode::ACCEPTED,
+        Json(ApiResponse::success(PushResponse { accepted })),
+    )
+}
+
+/// Events pushed over HTTP, buffered up to a fixed capacity. The source is
+/// exhausted once every [`PushBuffer`] is dropped.
+pub struct HttpPushSource {
+    rx: mpsc::Receiver<AnalyticsEvent>,
+}
+
+impl HttpPushSource {
+    /// Create a source and the buffer handle pushing into it
+    pub fn new(capacity: usize) -> (PushBuffer, Self) {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        (PushBuffer { tx }, Self { rx })
+    }
+}
+
+#[async_trait]
+impl Source for HttpPushSource {
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    async fn next(&mut self) -> Result<Option<AnalyticsEvent>> {
+        Ok(self.rx.recv().await)
+    }
+}
+
+/// Deterministic replay: events of the inner source sorted by timestamp
+/// (ties keep their input order), optionally paced by the gaps between
+/// timestamps. The inner source is read to the end on creation.
+pub struct ReplaySource {
+    name: String,
+    events: VecDeque<AnalyticsEvent>,
+    speed: Option<f64>,
+    clock: Clock,
+}
+
+impl ReplaySource {
+    /// Replay `source`; `speed` scales the original pacing (2.0 replays
+    /// twice as fast), and `None` replays as fast as possible
+    pub async fn new(mut source: impl Source, speed: Option<f64>) -> Result<Self> {
+        let mut events = Vec::new();
+        while let Some(event) = source.next().await? {
+            events.push(event);
+        }
+        events.sort_by_key(|e| e.common.timestamp);
+        info!("Replaying {} events from {}", events.len(), source.name());
+
+        Ok(Self {
+            name: format!("replay:{}", source.name()),
+            events: events.into(),
+            speed: speed.filter(|s| *s > 0.0),
+            clock: Clock::EventTime(Arc::default()),
+        })
+    }
+
+    /// Clock following the timestamp of the latest replayed event
+    pub fn clock(&self) -> Clock {
+        self.clock.clone()
+    }
+}
+
+#[async_trait]
+impl Source for ReplaySource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn next(&mut self) -> Result<Option<AnalyticsEvent>> {
+        let Some(event) = self.events.pop_front() else {
+            return Ok(None);
+        };
+        if let (Some(speed), Clock::EventTime(time)) = (self.speed, &self.clock) {
+            let last = *time.read();
+            if let Some(gap) = last.and_then(|last| (event.common.timestamp - last).to_std().ok()) {
+                tokio::time::sleep(gap.div_f64(speed)).await;
+            }
+        }
+        self.clock.advance(event.common.timestamp);
+        Ok(Some(event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::events::*;
+    use chrono::Duration;
+
+    fn event(timestamp: DateTime<Utc>) -> AnalyticsEvent {
+        fixtures::latency("gpt-4", 100.0).at(timestamp)
+    }
+
+    fn ndjson(events: &[AnalyticsEvent]) -> String {
+        events
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[tokio::test]
+    async fn test_ndjson_source_skips_malformed_lines() {
+        let start = Utc::now();
+        let events = [event(start), event(start + Duration::seconds(1))];
+        let input = format!(
+            "{}\n\n{{not json}}\n{}\n",
+            ndjson(&events[..1]),
+            ndjson(&events[1..])
+        );
+        let mut source = NdjsonSource::new("test", input.as_bytes());
+
+        let mut read = Vec::new();
+        while let Some(event) = source.next().await.unwrap() {
+            read.push(event.common.event_id);
+        }
+        assert_eq!(
+            read,
+            vec![events[0].common.event_id, events[1].common.event_id]
+        );
+        assert_eq!(source.skipped(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_replay_orders_by_timestamp_and_drives_clock() {
+        let start = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let events = vec![
+            event(start + Duration::seconds(2)),
+            event(start),
+            event(start + Duration::seconds(1)),
+        ];
+        let input = ndjson(&events);
+        let mut replay = ReplaySource::new(NdjsonSource::new("test", input.as_bytes()), None)
+            .await
+            .unwrap();
+        let clock = replay.clock();
+        assert!(clock.is_event_time());
+
+        let mut times = Vec::new();
+        while let Some(event) = replay.next().await.unwrap() {
+            assert_eq!(clock.now(), event.common.timestamp);
+            times.push(event.common.timestamp);
+        }
+        assert_eq!(
+            times,
+            vec![
+                start,
+                start + Duration::seconds(1),
+                start + Duration::seconds(2)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_push_source_ends_when_buffers_dropped() {
+        let (buffer, mut source) = HttpPushSource::new(4);
+        buffer.push(event(Utc::now())).await.unwrap();
+        drop(buffer);
+
+        assert!(source.next().await.unwrap().is_some());
+        assert!(source.next().await.unwrap().is_none());
+    }
+}
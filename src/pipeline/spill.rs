@@ -0,0 +1,810 @@
+//! Spill Buffer
+//!
+//! Durable local write-ahead buffer for records that cannot be published
+//! while the broker is unavailable. Records are appended to segment files,
+//! each framed with its length and a CRC32 of its contents, and drained
+//! oldest first once the broker recovers.
+//!
+//! Segments roll when they reach a size or age cap. Fully drained segments
+//! are deleted, and sealed segments older than the retention age are dropped
+//! undrained. The drain position is kept in a cursor file, so a restart
+//! resumes where draining stopped; records published but not yet acked when
+//! the process stops are drained again. A torn write at the tail of a
+//! segment is truncated away on open; a record damaged later seals its
+//! segment at that record and the rest of the segment is skipped.
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+const SEGMENT_EXTENSION: &str = "seg";
+const CURSOR_FILE: &str = "cursor";
+
+/// Length and CRC32 preceding every record
+const FRAME_HEADER: u64 = 8;
+
+/// When appended records are flushed to disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// After every append
+    Always,
+    /// At most once per interval; a crash loses up to one interval
+    Interval(Duration),
+    /// Left to the operating system
+    Never,
+}
+
+/// Spill buffer configuration
+#[derive(Debug, Clone)]
+pub struct SpillConfig {
+    /// Directory holding the segments and cursor
+    pub dir: PathBuf,
+
+    /// Segment size at which a new segment is started
+    pub segment_max_bytes: u64,
+
+    /// Segment age at which a new segment is started
+    pub segment_max_age: Duration,
+
+    /// Undrained bytes at which appends are rejected
+    pub max_bytes: u64,
+
+    /// Age at which sealed segments are dropped undrained
+    pub max_age: Duration,
+
+    pub fsync: FsyncPolicy,
+}
+
+impl Default for SpillConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("/var/lib/llm-analytics/spill"),
+            segment_max_bytes: 64 * 1024 * 1024,
+            segment_max_age: Duration::from_secs(60),
+            max_bytes: 1024 * 1024 * 1024,
+            max_age: Duration::from_secs(24 * 3600),
+            fsync: FsyncPolicy::Interval(Duration::from_secs(1)),
+        }
+    }
+}
+
+/// Append rejected because the buffer is at `max_bytes`
+#[derive(Debug, thiserror::Error)]
+#[error("spill buffer full ({used} of {max} bytes)")]
+pub struct BufferFull {
+    pub used: u64,
+    pub max: u64,
+}
+
+/// A buffered record bound for `topic`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpillRecord {
+    pub topic: String,
+    pub key: String,
+    pub payload: Vec<u8>,
+}
+
+/// Position just past a record read from the buffer; acking it drains the
+/// record and every record before it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpillPosition {
+    seq: u64,
+    offset: u64,
+    /// Records before this position since the buffer was opened
+    index: u64,
+}
+
+/// Spill buffer depth and counters
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SpillStats {
+    pub records: u64,
+    pub bytes: u64,
+    pub max_bytes: u64,
+    pub segments: usize,
+    pub records_appended: u64,
+    pub records_drained: u64,
+    pub records_expired: u64,
+    pub corrupt_segments: u64,
+}
+
+struct Segment {
+    seq: u64,
+    path: PathBuf,
+    bytes: u64,
+    records: u64,
+    /// Index of the first record
+    first_index: u64,
+    modified: SystemTime,
+}
+
+impl Segment {
+    fn end_index(&self) -> u64 {
+        self.first_index + self.records
+    }
+}
+
+/// Segment-based on-disk record buffer
+pub struct SpillBuffer {
+    config: SpillConfig,
+    /// Oldest first; the last segment is the one being appended to
+    segments: VecDeque<Segment>,
+    active: File,
+    active_opened: Instant,
+    cursor: SpillPosition,
+    dirty: bool,
+    last_sync: Instant,
+    stats: SpillStats,
+}
+
+impl SpillBuffer {
+    /// Open the buffer in `config.dir`, recovering buffered records
+    pub fn open(config: SpillConfig) -> Result<Self> {
+        fs::create_dir_all(&config.dir)
+            .with_context(|| format!("Failed to create {}", config.dir.display()))?;
+
+        let mut seqs = Vec::new();
+        for entry in fs::read_dir(&config.dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION) {
+                if let Some(seq) = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str()?.parse::<u64>().ok())
+                {
+                    seqs.push(seq);
+                }
+            }
+        }
+        seqs.sort_unstable();
+
+        let saved = read_cursor(&config.dir)?;
+        let mut stats = SpillStats::default();
+        let mut segments = VecDeque::new();
+        let mut cursor = None;
+        let mut next_index = 0;
+
+        for seq in seqs {
+            let path = segment_path(&config.dir, seq);
+            if matches!(saved, Some((cursor_seq, _)) if seq < cursor_seq) {
+                // Drained before the last shutdown
+                fs::remove_file(&path)?;
+                continue;
+            }
+            let cursor_offset = saved.and_then(|(s, offset)| (s == seq).then_some(offset));
+            let scan = scan_segment(&path, cursor_offset.unwrap_or(0))?;
+            if scan.valid_bytes < scan.file_bytes {
+                warn!(
+                    "Truncating {} from {} to {} bytes after a torn or corrupt record",
+                    path.display(),
+                    scan.file_bytes,
+                    scan.valid_bytes
+                );
+                OpenOptions::new()
+                    .write(true)
+                    .open(&path)?
+                    .set_len(scan.valid_bytes)?;
+                stats.corrupt_segments += 1;
+            }
+            if cursor_offset.is_some() {
+                cursor = Some(SpillPosition {
+                    seq,
+                    offset: scan.cursor_offset,
+                    index: next_index + scan.records_before_cursor,
+                });
+            }
+            let modified = fs::metadata(&path)?.modified()?;
+            segments.push_back(Segment {
+                seq,
+                path,
+                bytes: scan.valid_bytes,
+                records: scan.records,
+                first_index: next_index,
+                modified,
+            });
+            next_index += scan.records;
+        }
+
+        // Recovered segments are sealed; appends go to a new segment
+        let seq = segments
+            .back()
+            .map(|s| s.seq + 1)
+            .max(saved.map(|(seq, _)| seq + 1))
+            .unwrap_or(0);
+        let (active, segment) = create_segment(&config.dir, seq, next_index)?;
+        let cursor = cursor.unwrap_or_else(|| {
+            let head = segments.front().unwrap_or(&segment);
+            SpillPosition {
+                seq: head.seq,
+                offset: 0,
+                index: head.first_index,
+            }
+        });
+        segments.push_back(segment);
+
+        let mut buffer = Self {
+            config,
+            segments,
+            active,
+            active_opened: Instant::now(),
+            cursor,
+            dirty: false,
+            last_sync: Instant::now(),
+            stats,
+        };
+        buffer.remove_drained()?;
+        buffer.write_cursor()?;
+
+        let stats = buffer.stats();
+        if stats.records > 0 {
+            info!(
+                "Recovered {} spilled records ({} bytes) in {} segments",
+                stats.records, stats.bytes, stats.segments
+            );
+        }
+        Ok(buffer)
+    }
+
+    /// Append a record, failing with [`BufferFull`] at the size cap
+    pub fn append(&mut self, record: &SpillRecord) -> Result<()> {
+        let frame = encode(record)?;
+        let frame_len = frame.len() as u64;
+        let used = self.bytes();
+        if used + frame_len > self.config.max_bytes {
+            return Err(BufferFull {
+                used,
+                max: self.config.max_bytes,
+            }
+            .into());
+        }
+
+        let active = self.segments.back().expect("active segment");
+        if active.bytes > 0
+            && (active.bytes + frame_len > self.config.segment_max_bytes
+                || self.active_opened.elapsed() >= self.config.segment_max_age)
+        {
+            self.roll()?;
+        }
+
+        self.active.write_all(&frame)?;
+        let active = self.segments.back_mut().expect("active segment");
+        active.bytes += frame_len;
+        active.records += 1;
+        active.modified = SystemTime::now();
+        self.stats.records_appended += 1;
+        self.dirty = true;
+
+        match self.config.fsync {
+            FsyncPolicy::Always => self.sync()?,
+            FsyncPolicy::Interval(interval) if self.last_sync.elapsed() >= interval => {
+                self.sync()?
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Read up to `max` undrained records, oldest first, without draining
+    /// them
+    pub fn read(&mut self, max: usize) -> Result<Vec<(SpillPosition, SpillRecord)>> {
+        let mut records = Vec::new();
+        let mut index = self.cursor.index;
+        // Records lost to corruption in earlier segments
+        let mut lost = 0;
+        let mut active_corrupt = false;
+        let active_seq = self.segments.back().expect("active segment").seq;
+
+        for segment in self.segments.iter_mut() {
+            if segment.seq < self.cursor.seq {
+                continue;
+            }
+            segment.first_index -= lost;
+            if records.len() >= max {
+                continue;
+            }
+            if segment.seq > self.cursor.seq {
+                index = segment.first_index;
+            }
+            let mut offset = if segment.seq == self.cursor.seq {
+                self.cursor.offset
+            } else {
+                0
+            };
+            if offset >= segment.bytes {
+                continue;
+            }
+
+            let mut reader = BufReader::new(File::open(&segment.path)?);
+            reader.seek(SeekFrom::Start(offset))?;
+            while offset < segment.bytes && records.len() < max {
+                let Some((record, len)) = read_frame(&mut reader)? else {
+                    // Damaged since it was written: skip the rest
+                    warn!(
+                        "Corrupt record in {} at offset {}, skipping {} bytes",
+                        segment.path.display(),
+                        offset,
+                        segment.bytes - offset
+                    );
+                    self.stats.corrupt_segments += 1;
+                    // Seal the segment after the records read so far
+                    let read = index - segment.first_index;
+                    lost += segment.records - read;
+                    segment.records = read;
+                    segment.bytes = offset;
+                    active_corrupt |= segment.seq == active_seq;
+                    break;
+                };
+                offset += len;
+                index += 1;
+                records.push((
+                    SpillPosition {
+                        seq: segment.seq,
+                        offset,
+                        index,
+                    },
+                    record,
+                ));
+            }
+        }
+        if active_corrupt {
+            // Appends would land behind the damaged record
+            self.roll()?;
+        }
+        Ok(records)
+    }
+
+    /// Drain every record up to and including the one read at `position`
+    pub fn ack(&mut self, position: SpillPosition) -> Result<()> {
+        if position.index <= self.cursor.index {
+            return Ok(());
+        }
+        self.stats.records_drained += position.index - self.cursor.index;
+        self.cursor = position;
+        self.remove_drained()?;
+        self.write_cursor()
+    }
+
+    /// Periodic maintenance: interval fsync, age-based segment rolls and
+    /// retention
+    pub fn tick(&mut self) -> Result<()> {
+        if let FsyncPolicy::Interval(interval) = self.config.fsync {
+            if self.dirty && self.last_sync.elapsed() >= interval {
+                self.sync()?;
+            }
+        }
+        let active = self.segments.back().expect("active segment");
+        if active.bytes > 0 && self.active_opened.elapsed() >= self.config.segment_max_age {
+            self.roll()?;
+        }
+        self.expire(SystemTime::now())
+    }
+
+    /// Flush appended records to disk
+    pub fn sync(&mut self) -> Result<()> {
+        if self.dirty {
+            self.active.sync_data()?;
+            self.dirty = false;
+        }
+        self.last_sync = Instant::now();
+        Ok(())
+    }
+
+    /// Whether every appended record has been drained or expired
+    pub fn is_empty(&self) -> bool {
+        self.records() == 0
+    }
+
+    pub fn stats(&self) -> SpillStats {
+        SpillStats {
+            records: self.records(),
+            bytes: self.bytes(),
+            max_bytes: self.config.max_bytes,
+            segments: self.segments.len(),
+            ..self.stats.clone()
+        }
+    }
+
+    fn records(&self) -> u64 {
+        let active = self.segments.back().expect("active segment");
+        active.end_index() - self.cursor.index
+    }
+
+    fn bytes(&self) -> u64 {
+        let total: u64 = self
+            .segments
+            .iter()
+            .filter(|s| s.seq >= self.cursor.seq)
+            .map(|s| s.bytes)
+            .sum();
+        total.saturating_sub(self.cursor.offset)
+    }
+
+    /// Seal the active segment and start a new one
+    fn roll(&mut self) -> Result<()> {
+        if self.config.fsync != FsyncPolicy::Never {
+            self.sync()?;
+        }
+        let last = self.segments.back().expect("active segment");
+        let (active, segment) = create_segment(&self.config.dir, last.seq + 1, last.end_index())?;
+        self.active = active;
+        self.active_opened = Instant::now();
+        self.dirty = false;
+        self.segments.push_back(segment);
+        Ok(())
+    }
+
+    /// Delete sealed segments at the head whose records are all drained
+    fn remove_drained(&mut self) -> Result<()> {
+        while self.segments.len() > 1 && self.cursor.index >= self.segments[0].end_index() {
+            self.remove_head()?;
+        }
+        Ok(())
+    }
+
+    /// Drop sealed segments last written before the retention age
+    fn expire(&mut self, now: SystemTime) -> Result<()> {
+        while self.segments.len() > 1 {
+            let head = &self.segments[0];
+            let age = now.duration_since(head.modified).unwrap_or_default();
+            if age < self.config.max_age {
+                break;
+            }
+            let expired = head.end_index().saturating_sub(self.cursor.index);
+            if expired > 0 {
+                warn!(
+                    "Dropping {} spilled records older than {:?} in {}",
+                    expired,
+                    self.config.max_age,
+                    head.path.display()
+                );
+                self.stats.records_expired += expired;
+            }
+            self.cursor.index = self.cursor.index.max(head.end_index());
+            self.remove_head()?;
+            self.write_cursor()?;
+        }
+        Ok(())
+    }
+
+    fn remove_head(&mut self) -> Result<()> {
+        let head = self.segments.pop_front().expect("head segment");
+        fs::remove_file(&head.path)
+            .with_context(|| format!("Failed to remove {}", head.path.display()))?;
+        let next = self.segments.front().expect("active segment");
+        if self.cursor.seq < next.seq {
+            self.cursor.seq = next.seq;
+            self.cursor.offset = 0;
+        }
+        Ok(())
+    }
+
+    /// Persist the drain position, replacing the cursor file atomically
+    fn write_cursor(&self) -> Result<()> {
+        let path = self.config.dir.join(CURSOR_FILE);
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        file.write_all(&self.cursor.seq.to_le_bytes())?;
+        file.write_all(&self.cursor.offset.to_le_bytes())?;
+        if self.config.fsync != FsyncPolicy::Never {
+            file.sync_all()?;
+        }
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+fn segment_path(dir: &Path, seq: u64) -> PathBuf {
+    dir.join(format!("{:020}.{}", seq, SEGMENT_EXTENSION))
+}
+
+fn create_segment(dir: &Path, seq: u64, first_index: u64) -> Result<(File, Segment)> {
+    let path = segment_path(dir, seq);
+    let file = OpenOptions::new()
+        .create_new(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to create {}", path.display()))?;
+    let segment = Segment {
+        seq,
+        path,
+        bytes: 0,
+        records: 0,
+        first_index,
+        modified: SystemTime::now(),
+    };
+    Ok((file, segment))
+}
+
+/// Saved `(segment, offset)` drain position
+fn read_cursor(dir: &Path) -> Result<Option<(u64, u64)>> {
+    let bytes = match fs::read(dir.join(CURSOR_FILE)) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if bytes.len() != 16 {
+        warn!("Ignoring malformed spill cursor; draining from the oldest segment");
+        return Ok(None);
+    }
+    let seq = u64::from_le_bytes(bytes[..8].try_into()?);
+    let offset = u64::from_le_bytes(bytes[8..].try_into()?);
+    Ok(Some((seq, offset)))
+}
+
+struct SegmentScan {
+    file_bytes: u64,
+    /// Bytes up to the first torn or corrupt record
+    valid_bytes: u64,
+    records: u64,
+    /// Cursor offset rounded down to a record boundary
+    cursor_offset: u64,
+    records_before_cursor: u64,
+}
+
+fn scan_segment(path: &Path, cursor_offset: u64) -> Result<SegmentScan> {
+    let file = File::open(path)?;
+    let mut scan = SegmentScan {
+        file_bytes: file.metadata()?.len(),
+        valid_bytes: 0,
+        records: 0,
+        cursor_offset: 0,
+        records_before_cursor: 0,
+    };
+    let mut reader = BufReader::new(file);
+    while let Some((_, len)) = read_frame(&mut reader)? {
+        scan.valid_bytes += len;
+        scan.records += 1;
+        if scan.valid_bytes <= cursor_offset {
+            scan.cursor_offset = scan.valid_bytes;
+            scan.records_before_cursor = scan.records;
+        }
+    }
+    Ok(scan)
+}
+
+/// Frame a record: body length, CRC32 of the body, then the body of
+/// length-prefixed topic and key followed by the payload
+fn encode(record: &SpillRecord) -> Result<Vec<u8>> {
+    let topic_len = u16::try_from(record.topic.len()).context("Topic name too long")?;
+    let key_len = u16::try_from(record.key.len()).context("Record key too long")?;
+    let body_len = 4 + record.topic.len() + record.key.len() + record.payload.len();
+    let body_len = u32::try_from(body_len).context("Record too large")?;
+
+    let mut frame = Vec::with_capacity(FRAME_HEADER as usize + body_len as usize);
+    frame.extend_from_slice(&body_len.to_le_bytes());
+    frame.extend_from_slice(&[0; 4]);
+    frame.extend_from_slice(&topic_len.to_le_bytes());
+    frame.extend_from_slice(record.topic.as_bytes());
+    frame.extend_from_slice(&key_len.to_le_bytes());
+    frame.extend_from_slice(record.key.as_bytes());
+    frame.extend_from_slice(&record.payload);
+
+    let crc = crc32fast::hash(&frame[FRAME_HEADER as usize..]);
+    frame[4..8].copy_from_slice(&crc.to_le_bytes());
+    Ok(frame)
+}
+
+fn decode(body: &[u8]) -> Option<SpillRecord> {
+    let field = |at: usize| -> Option<(String, usize)> {
+        let len = u16::from_le_bytes(body.get(at..at + 2)?.try_into().ok()?) as usize;
+        let value = String::from_utf8(body.get(at + 2..at + 2 + len)?.to_vec()).ok()?;
+        Some((value, at + 2 + len))
+    };
+    let (topic, at) = field(0)?;
+    let (key, at) = field(at)?;
+    Some(SpillRecord {
+        topic,
+        key,
+        payload: body[at..].to_vec(),
+    })
+}
+
+/// Read one record and its framed length, or `None` at the end of the
+/// segment or at a torn or corrupt record
+fn read_frame(reader: &mut impl Read) -> Result<Option<(SpillRecord, u64)>> {
+    let mut header = [0; FRAME_HEADER as usize];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_le_bytes(header[..4].try_into()?) as u64;
+    let crc = u32::from_le_bytes(header[4..].try_into()?);
+
+    // Bounded by the file, whatever a corrupt header claims
+    let mut body = Vec::new();
+    reader.take(len).read_to_end(&mut body)?;
+    if body.len() as u64 != len || crc32fast::hash(&body) != crc {
+        return Ok(None);
+    }
+    Ok(decode(&body).map(|record| (record, FRAME_HEADER + len)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(dir: &Path) -> SpillConfig {
+        SpillConfig {
+            dir: dir.to_path_buf(),
+            segment_max_bytes: 256,
+            fsync: FsyncPolicy::Always,
+            ..SpillConfig::default()
+        }
+    }
+
+    fn record(i: usize) -> SpillRecord {
+        SpillRecord {
+            topic: "llm-events".to_string(),
+            key: format!("key-{}", i),
+            payload: format!("{{\"n\":{}}}", i).into_bytes(),
+        }
+    }
+
+    fn segment_files(dir: &Path) -> usize {
+        fs::read_dir(dir)
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .path()
+                    .extension()
+                    .is_some_and(|ext| ext == SEGMENT_EXTENSION)
+            })
+            .count()
+    }
+
+    #[test]
+    fn test_drains_in_order_across_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut buffer = SpillBuffer::open(config(dir.path())).unwrap();
+        for i in 0..20 {
+            buffer.append(&record(i)).unwrap();
+        }
+        assert!(buffer.stats().segments > 1);
+        assert_eq!(buffer.stats().records, 20);
+
+        let mut drained = Vec::new();
+        loop {
+            let batch = buffer.read(7).unwrap();
+            let Some((position, _)) = batch.last() else {
+                break;
+            };
+            let position = *position;
+            drained.extend(batch.into_iter().map(|(_, r)| r));
+            buffer.ack(position).unwrap();
+        }
+
+        assert_eq!(drained, (0..20).map(record).collect::<Vec<_>>());
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.stats().bytes, 0);
+        // Only the active segment is left
+        assert_eq!(segment_files(dir.path()), 1);
+    }
+
+    #[test]
+    fn test_reopen_resumes_from_cursor_and_truncates_torn_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let mut buffer = SpillBuffer::open(config(dir.path())).unwrap();
+            for i in 0..5 {
+                buffer.append(&record(i)).unwrap();
+            }
+            let batch = buffer.read(2).unwrap();
+            buffer.ack(batch[1].0).unwrap();
+        }
+
+        // Simulate a crash in the middle of a write
+        let newest = (0..u64::MAX)
+            .map(|seq| segment_path(dir.path(), seq))
+            .take_while(|path| path.exists())
+            .last()
+            .unwrap();
+        let mut file = OpenOptions::new().append(true).open(&newest).unwrap();
+        file.write_all(&encode(&record(5)).unwrap()[..10]).unwrap();
+
+        let mut buffer = SpillBuffer::open(config(dir.path())).unwrap();
+        assert_eq!(buffer.stats().records, 3);
+        assert_eq!(buffer.stats().corrupt_segments, 1);
+        let remaining: Vec<_> = buffer
+            .read(10)
+            .unwrap()
+            .into_iter()
+            .map(|(_, r)| r)
+            .collect();
+        assert_eq!(remaining, (2..5).map(record).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_skips_corrupt_records_and_drains_to_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let frame_len = encode(&record(0)).unwrap().len() as u64;
+        let per_segment = (256 / frame_len) as usize;
+        let mut buffer = SpillBuffer::open(config(dir.path())).unwrap();
+        for i in 0..10 {
+            buffer.append(&record(i)).unwrap();
+        }
+        assert_eq!(buffer.stats().segments, 2);
+
+        // Damage the second record of the sealed segment and the last record
+        // of the active one
+        let active_len = (10 - per_segment) as u64 * frame_len;
+        for (seq, at) in [(0, frame_len + FRAME_HEADER), (1, active_len - 1)] {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .open(segment_path(dir.path(), seq))
+                .unwrap();
+            file.seek(SeekFrom::Start(at)).unwrap();
+            file.write_all(&[0xff]).unwrap();
+        }
+
+        let mut drained = Vec::new();
+        loop {
+            let batch = buffer.read(4).unwrap();
+            let Some((position, _)) = batch.last() else {
+                break;
+            };
+            let position = *position;
+            drained.extend(batch.into_iter().map(|(_, r)| r));
+            buffer.ack(position).unwrap();
+        }
+
+        let expected: Vec<_> = [0].into_iter().chain(per_segment..9).map(record).collect();
+        assert_eq!(drained, expected);
+        assert_eq!(buffer.stats().corrupt_segments, 2);
+        assert!(buffer.is_empty());
+
+        // Appends after the damaged record are drained too
+        buffer.append(&record(10)).unwrap();
+        let batch = buffer.read(10).unwrap();
+        assert_eq!(batch.len(), 1);
+        assert_eq!(batch[0].1, record(10));
+        buffer.ack(batch[0].0).unwrap();
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_rejects_appends_when_full() {
+        let dir = tempfile::tempdir().unwrap();
+        let frame_len = encode(&record(0)).unwrap().len() as u64;
+        let mut buffer = SpillBuffer::open(SpillConfig {
+            max_bytes: frame_len * 3,
+            ..config(dir.path())
+        })
+        .unwrap();
+
+        for i in 0..3 {
+            buffer.append(&record(i)).unwrap();
+        }
+        let err = buffer.append(&record(3)).unwrap_err();
+        assert!(err.downcast_ref::<BufferFull>().is_some());
+
+        // Draining frees room
+        let batch = buffer.read(1).unwrap();
+        buffer.ack(batch[0].0).unwrap();
+        buffer.append(&record(3)).unwrap();
+    }
+
+    #[test]
+    fn test_expires_sealed_segments_past_retention() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut buffer = SpillBuffer::open(SpillConfig {
+            segment_max_age: Duration::ZERO,
+            max_age: Duration::ZERO,
+            ..config(dir.path())
+        })
+        .unwrap();
+        buffer.append(&record(0)).unwrap();
+        buffer.append(&record(1)).unwrap();
+
+        // The first record was sealed by the age roll; tick seals the second
+        buffer.tick().unwrap();
+        let stats = buffer.stats();
+        assert_eq!(stats.records_expired, 2);
+        assert!(buffer.is_empty());
+        assert!(buffer.read(10).unwrap().is_empty());
+    }
+}
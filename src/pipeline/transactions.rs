@@ -0,0 +1,320 @@
+//! Transactional Stages
+//!
+//! Commit handling for consume-transform-produce stages. In exactly-once
+//! mode the records a stage produces and the input offsets they were derived
+//! from are committed in one Kafka transaction, so downstream consumers
+//! reading with `isolation.level=read_committed` see every output exactly
+//! once. An aborted transaction leaves the offsets uncommitted; the stage
+//! must then stop so that a restart reprocesses the input from the last
+//! commit with fresh state.
+
+use super::offsets::PartitionOffsets;
+use anyhow::{Context, Result};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{CommitMode, Consumer, ConsumerContext};
+use rdkafka::producer::{FutureProducer, FutureRecord, Producer};
+use rdkafka::{Offset, TopicPartitionList};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, warn};
+
+/// Delivery guarantee of a stage's output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessingGuarantee {
+    /// Outputs are produced before offsets are committed; a crash between
+    /// the two produces them again
+    AtLeastOnce,
+    /// Outputs and offsets are committed in one transaction
+    ExactlyOnce,
+}
+
+impl std::str::FromStr for ProcessingGuarantee {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "at_least_once" => Ok(Self::AtLeastOnce),
+            "exactly_once" => Ok(Self::ExactlyOnce),
+            other => anyhow::bail!("unknown processing guarantee: {}", other),
+        }
+    }
+}
+
+/// Transactional stage configuration
+#[derive(Debug, Clone)]
+pub struct TransactionConfig {
+    pub guarantee: ProcessingGuarantee,
+    /// Must be stable across restarts of the same stage instance so that a
+    /// restarted producer fences off its predecessor
+    pub transactional_id: String,
+    /// Consumed messages per commit
+    pub max_batch: usize,
+    /// Longest time between commits
+    pub commit_interval: Duration,
+    /// Timeout for transaction operations
+    pub timeout: Duration,
+}
+
+impl Default for TransactionConfig {
+    fn default() -> Self {
+        Self {
+            guarantee: ProcessingGuarantee::AtLeastOnce,
+            transactional_id: "llm-analytics-stage".to_string(),
+            max_batch: 500,
+            commit_interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Consumer settings for a stage; exactly-once stages only read committed
+/// upstream transactions
+pub fn consumer_config(
+    brokers: &str,
+    group_id: &str,
+    client_id: &str,
+    guarantee: ProcessingGuarantee,
+) -> ClientConfig {
+    let mut config = ClientConfig::new();
+    config
+        .set("bootstrap.servers", brokers)
+        .set("group.id", group_id)
+        .set("enable.auto.commit", "false")
+        .set("auto.offset.reset", "earliest")
+        .set("client.id", client_id);
+    if guarantee == ProcessingGuarantee::ExactlyOnce {
+        config.set("isolation.level", "read_committed");
+    }
+    config
+}
+
+/// Producer settings for a stage
+pub fn producer_config(brokers: &str, client_id: &str, config: &TransactionConfig) -> ClientConfig {
+    let mut producer = ClientConfig::new();
+    producer
+        .set("bootstrap.servers", brokers)
+        .set("message.timeout.ms", "5000")
+        .set("client.id", client_id)
+        .set("enable.idempotence", "true");
+    if config.guarantee == ProcessingGuarantee::ExactlyOnce {
+        producer
+            .set("transactional.id", &config.transactional_id)
+            .set(
+                "transaction.timeout.ms",
+                (config.timeout.as_millis() * 2).to_string(),
+            );
+    }
+    producer
+}
+
+struct OutputRecord {
+    topic: String,
+    key: String,
+    payload: Vec<u8>,
+}
+
+/// Collects a stage's outputs and consumed offsets and commits them together
+pub struct StageCommitter {
+    config: TransactionConfig,
+    producer: FutureProducer,
+    outputs: Vec<OutputRecord>,
+    offsets: PartitionOffsets,
+    consumed: usize,
+    last_commit: Instant,
+}
+
+impl StageCommitter {
+    /// Create a committer, initializing transactions in exactly-once mode
+    pub fn new(producer: FutureProducer, config: TransactionConfig) -> Result<Self> {
+        if config.guarantee == ProcessingGuarantee::ExactlyOnce {
+            producer
+                .init_transactions(config.timeout)
+                .context("Failed to initialize Kafka transactions")?;
+        }
+        Ok(Self {
+            config,
+            producer,
+            outputs: Vec::new(),
+            offsets: PartitionOffsets::default(),
+            consumed: 0,
+            last_commit: Instant::now(),
+        })
+    }
+
+    pub fn guarantee(&self) -> ProcessingGuarantee {
+        self.config.guarantee
+    }
+
+    /// Record a consumed input message
+    pub fn consumed(&mut self, topic: &str, partition: i32, offset: i64) {
+        self.offsets.record(topic, partition, offset);
+        self.consumed += 1;
+    }
+
+    /// Do not commit past `offset` on a partition in the next commit
+    pub fn hold(&mut self, topic: &str, partition: i32, offset: i64) {
+        self.offsets.hold(topic, partition, offset);
+    }
+
+    /// Drop the offsets of a revoked partition from the next commit; its new
+    /// owner consumes it from the last committed offset
+    pub fn revoke(&mut self, topic: &str, partition: i32) {
+        self.offsets.remove(topic, partition);
+    }
+
+    /// Queue an output record for the next commit
+    pub fn produce(&mut self, topic: &str, key: &str, payload: Vec<u8>) {
+        self.outputs.push(OutputRecord {
+            topic: topic.to_string(),
+            key: key.to_string(),
+            payload,
+        });
+    }
+
+    /// Whether the batch is full or the commit interval elapsed
+    pub fn should_commit(&self) -> bool {
+        self.consumed >= self.config.max_batch
+            || (self.consumed > 0 && self.last_commit.elapsed() >= self.config.commit_interval)
+    }
+
+    /// Produce pending outputs and commit them with the consumed offsets.
+    /// In at-least-once mode offsets are only committed once every output
+    /// was published; outputs that failed are retried with the offsets on
+    /// the next commit. In exactly-once mode a failure aborts the
+    /// transaction and is returned; the caller must stop consuming.
+    pub async fn commit<C, X>(&mut self, consumer: &C) -> Result<()>
+    where
+        C: Consumer<X>,
+        X: ConsumerContext + 'static,
+    {
+        self.last_commit = Instant::now();
+        if self.consumed == 0 && self.outputs.is_empty() {
+            return Ok(());
+        }
+        let outputs = std::mem::take(&mut self.outputs);
+        let offsets = std::mem::take(&mut self.offsets);
+        let consumed = std::mem::take(&mut self.consumed);
+
+        let mut tpl = TopicPartitionList::new();
+        for ((topic, partition), offset) in offsets.commit_positions() {
+            tpl.add_partition_offset(&topic, partition, Offset::Offset(offset))?;
+        }
+
+        match self.config.guarantee {
+            ProcessingGuarantee::AtLeastOnce => {
+                let mut failed = Vec::new();
+                for output in outputs {
+                    if let Err(e) = self.send(&output).await {
+                        error!("Failed to publish to {}: {}", output.topic, e);
+                        failed.push(output);
+                    }
+                }
+                if !failed.is_empty() {
+                    warn!(
+                        "Not committing offsets: {} outputs failed to publish",
+                        failed.len()
+                    );
+                    self.outputs = failed;
+                    self.offsets = offsets;
+                    self.consumed = consumed;
+                    return Ok(());
+                }
+                if tpl.count() > 0 {
+                    if let Err(e) = consumer.commit(&tpl, CommitMode::Async) {
+                        warn!("Failed to commit offsets: {}", e);
+                    }
+                }
+                Ok(())
+            }
+            ProcessingGuarantee::ExactlyOnce => {
+                let result = self.commit_transaction(consumer, &outputs, &tpl).await;
+                if let Err(e) = &result {
+                    error!("Aborting transaction: {}", e);
+                    if let Err(abort) = self.producer.abort_transaction(self.config.timeout) {
+                        error!("Failed to abort transaction: {}", abort);
+                    }
+                }
+                result
+            }
+        }
+    }
+
+    async fn commit_transaction<C, X>(
+        &self,
+        consumer: &C,
+        outputs: &[OutputRecord],
+        offsets: &TopicPartitionList,
+    ) -> Result<()>
+    where
+        C: Consumer<X>,
+        X: ConsumerContext + 'static,
+    {
+        self.producer.begin_transaction()?;
+        for output in outputs {
+            self.send(output)
+                .await
+                .with_context(|| format!("Failed to publish to {}", output.topic))?;
+        }
+        if offsets.count() > 0 {
+            let group = consumer
+                .group_metadata()
+                .context("Consumer has no group metadata")?;
+            self.producer
+                .send_offsets_to_transaction(offsets, &group, self.config.timeout)?;
+        }
+        self.producer.commit_transaction(self.config.timeout)?;
+
+        debug!(
+            outputs = outputs.len(),
+            partitions = offsets.count(),
+            "Committed transaction"
+        );
+        Ok(())
+    }
+
+    async fn send(&self, output: &OutputRecord) -> Result<()> {
+        let record = FutureRecord::to(&output.topic)
+            .key(&output.key)
+            .payload(&output.payload);
+        self.producer
+            .send(record, Duration::from_secs(5))
+            .await
+            .map_err(|(e, _)| e)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exactly_once_reads_committed_and_is_transactional() {
+        let config = TransactionConfig {
+            guarantee: ProcessingGuarantee::ExactlyOnce,
+            transactional_id: "anomaly-detection-0".to_string(),
+            ..Default::default()
+        };
+
+        let consumer = consumer_config("kafka:9092", "anomaly-detection", "svc", config.guarantee);
+        assert_eq!(consumer.get("isolation.level"), Some("read_committed"));
+        let producer = producer_config("kafka:9092", "svc", &config);
+        assert_eq!(
+            producer.get("transactional.id"),
+            Some("anomaly-detection-0")
+        );
+        assert_eq!(producer.get("transaction.timeout.ms"), Some("60000"));
+
+        assert_eq!(
+            "exactly_once".parse::<ProcessingGuarantee>().unwrap(),
+            ProcessingGuarantee::ExactlyOnce
+        );
+        assert!("exactly-once".parse::<ProcessingGuarantee>().is_err());
+
+        let at_least_once = TransactionConfig::default();
+        let consumer = consumer_config("kafka:9092", "g", "svc", at_least_once.guarantee);
+        assert_eq!(consumer.get("isolation.level"), None);
+        let producer = producer_config("kafka:9092", "svc", &at_least_once);
+        assert_eq!(producer.get("transactional.id"), None);
+        assert_eq!(producer.get("enable.idempotence"), Some("true"));
+    }
+}
@@ -0,0 +1,988 @@
+//! Event Transforms
+//!
+//! A chain of [`Transform`] stages applied to every event before it is
+//! stored. Besides stages implemented in code, chains are built from YAML so
+//! that noisy producers can be cleaned up by configuration:
+//!
+//! ```yaml
+//! transforms:
+//!   - type: filter
+//!     condition: 'severity == "debug" && environment != "production"'
+//!     action: drop
+//!   - type: drop_fields
+//!     when: 'source_module == "llm-observatory"'
+//!     fields: [tags.user_email, payload.data.breakdown]
+//!   - type: rename_tags
+//!     tags: { svc: service }
+//!   - type: set_defaults
+//!     values: { tags.team: unknown }
+//!   - type: route
+//!     routes:
+//!       - when: 'severity >= "error"'
+//!         to: llm-errors
+//!   - type: sample
+//!     when: 'event_type == "telemetry"'
+//!     rate: 0.1
+//!   - type: map_severity
+//!     mapping: { warning: info }
+//! ```
+//!
+//! Conditions are expressions over the event's JSON form: `event_type`,
+//! `severity`, `tags.<name>`, `payload.data.<field>` and so on. They support
+//! `==`, `!=`, `<`, `<=`, `>`, `>=`, `in [..]`, `&&`, `||`, `!`, parentheses
+//! and the functions `exists`, `contains`, `starts_with`, `ends_with`,
+//! `lower` and `matches` (regex). Severity names compare by level.
+
+use super::sampling::apply_weight;
+use crate::schemas::events::{AnalyticsEvent, Severity};
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+use std::cell::OnceCell;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Tag set by `route` stages to the destination chosen for an event
+pub const ROUTE_TAG: &str = "route";
+
+/// What to do with an event after a stage
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Continue,
+    Drop,
+}
+
+/// A processing stage
+pub trait Transform: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn apply(&self, event: &mut AnalyticsEvent) -> Result<Action>;
+
+    /// Destinations this stage may set as the route of an event
+    fn route_targets(&self) -> Vec<&str> {
+        Vec::new()
+    }
+}
+
+// ============================================================================
+// EXPRESSIONS
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A condition or value expression over an event
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Literal(Value),
+    Field(String),
+    List(Vec<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(CompareOp, Box<Expr>, Box<Expr>),
+    In(Box<Expr>, Box<Expr>),
+    Exists(String),
+    Contains(Box<Expr>, Box<Expr>),
+    StartsWith(Box<Expr>, Box<Expr>),
+    EndsWith(Box<Expr>, Box<Expr>),
+    Lower(Box<Expr>),
+    Matches(Box<Expr>, Regex),
+}
+
+impl Expr {
+    /// Whether the expression evaluates to `true` for the event
+    pub fn matches(&self, event: &AnalyticsEvent) -> bool {
+        self.eval(&EventView::new(event)) == Value::Bool(true)
+    }
+
+    fn eval(&self, view: &EventView) -> Value {
+        match self {
+            Expr::Literal(value) => value.clone(),
+            Expr::Field(path) => view.field(path).unwrap_or(Value::Null),
+            Expr::List(items) => Value::Array(items.iter().map(|e| e.eval(view)).collect()),
+            Expr::Not(inner) => Value::Bool(inner.eval(view) != Value::Bool(true)),
+            Expr::And(a, b) => {
+                Value::Bool(a.eval(view) == Value::Bool(true) && b.eval(view) == Value::Bool(true))
+            }
+            Expr::Or(a, b) => {
+                Value::Bool(a.eval(view) == Value::Bool(true) || b.eval(view) == Value::Bool(true))
+            }
+            Expr::Compare(op, a, b) => {
+                let ordering = compare(&a.eval(view), &b.eval(view));
+                Value::Bool(match op {
+                    CompareOp::Eq => ordering == Some(Ordering::Equal),
+                    CompareOp::Ne => ordering != Some(Ordering::Equal),
+                    CompareOp::Lt => ordering == Some(Ordering::Less),
+                    CompareOp::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                    CompareOp::Gt => ordering == Some(Ordering::Greater),
+                    CompareOp::Ge => {
+                        matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+                    }
+                })
+            }
+            Expr::In(needle, haystack) => {
+                let needle = needle.eval(view);
+                Value::Bool(match haystack.eval(view) {
+                    Value::Array(items) => items
+                        .iter()
+                        .any(|item| compare(&needle, item) == Some(Ordering::Equal)),
+                    _ => false,
+                })
+            }
+            Expr::Exists(path) => Value::Bool(view.field(path).is_some()),
+            Expr::Contains(a, b) => Value::Bool(match (a.eval(view), b.eval(view)) {
+                (Value::Array(items), needle) => items
+                    .iter()
+                    .any(|item| compare(item, &needle) == Some(Ordering::Equal)),
+                (Value::Null, _) | (_, Value::Null) => false,
+                (a, b) => value_string(&a).contains(&value_string(&b)),
+            }),
+            Expr::StartsWith(a, b) => {
+                string_test(a.eval(view), b.eval(view), |a, b| a.starts_with(b))
+            }
+            Expr::EndsWith(a, b) => string_test(a.eval(view), b.eval(view), |a, b| a.ends_with(b)),
+            Expr::Lower(inner) => match inner.eval(view) {
+                Value::Null => Value::Null,
+                value => Value::String(value_string(&value).to_lowercase()),
+            },
+            Expr::Matches(inner, regex) => Value::Bool(match inner.eval(view) {
+                Value::Null => false,
+                value => regex.is_match(&value_string(&value)),
+            }),
+        }
+    }
+}
+
+impl std::str::FromStr for Expr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            bail!("unexpected {:?} in expression: {}", token, s);
+        }
+        Ok(expr)
+    }
+}
+
+impl<'de> Deserialize<'de> for Expr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        source.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+fn string_test(a: Value, b: Value, test: fn(&str, &str) -> bool) -> Value {
+    Value::Bool(match (&a, &b) {
+        (Value::Null, _) | (_, Value::Null) => false,
+        _ => test(&value_string(&a), &value_string(&b)),
+    })
+}
+
+fn value_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn severity_level(value: &str) -> Option<Severity> {
+    serde_json::from_value(Value::String(value.to_string())).ok()
+}
+
+/// Order two values: numerically if either is a number, by level if both
+/// are severities, otherwise as strings. `None` if they are not comparable.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Number(_), _) | (_, Value::Number(_)) => as_number(a)?.partial_cmp(&as_number(b)?),
+        (Value::String(a), Value::String(b)) => match (severity_level(a), severity_level(b)) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => Some(a.cmp(b)),
+        },
+        (a, b) => (a == b).then_some(Ordering::Equal),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(f64),
+    Op(&'static str),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    const OPS: [&str; 14] = [
+        "==", "!=", "<=", ">=", "&&", "||", "<", ">", "!", "(", ")", "[", "]", ",",
+    ];
+    let mut tokens = Vec::new();
+    let mut rest = source.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(op) = OPS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else if c == '"' || c == '\'' {
+            let mut value = String::new();
+            let mut chars = rest[1..].char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, ch)) if ch == c => break i + 1,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, escaped)) => value.push(escaped),
+                        None => bail!("unterminated string in expression: {}", source),
+                    },
+                    Some((_, ch)) => value.push(ch),
+                    None => bail!("unterminated string in expression: {}", source),
+                }
+            };
+            tokens.push(Token::Str(value));
+            rest = &rest[end + 1..];
+        } else if c.is_ascii_digit() || c == '-' {
+            let len = rest
+                .char_indices()
+                .skip(1)
+                .find(|(_, ch)| !(ch.is_ascii_digit() || *ch == '.'))
+                .map_or(rest.len(), |(i, _)| i);
+            let number = rest[..len].parse().map_err(|_| {
+                anyhow!(
+                    "invalid number {:?} in expression: {}",
+                    &rest[..len],
+                    source
+                )
+            })?;
+            tokens.push(Token::Num(number));
+            rest = &rest[len..];
+        } else if c.is_alphanumeric() || c == '_' {
+            let len = rest
+                .find(|ch: char| !(ch.is_alphanumeric() || ch == '_' || ch == '.' || ch == '-'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_string()));
+            rest = &rest[len..];
+        } else {
+            bail!("unexpected {:?} in expression: {}", c, source);
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Op(o)) if *o == op);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, op: &str) -> Result<()> {
+        if !self.eat(op) {
+            bail!("expected '{}', found {:?}", op, self.peek());
+        }
+        Ok(())
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.not()?;
+        while self.eat("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.eat("!") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let left = self.operand()?;
+        let op = match self.peek() {
+            Some(Token::Op("==")) => CompareOp::Eq,
+            Some(Token::Op("!=")) => CompareOp::Ne,
+            Some(Token::Op("<")) => CompareOp::Lt,
+            Some(Token::Op("<=")) => CompareOp::Le,
+            Some(Token::Op(">")) => CompareOp::Gt,
+            Some(Token::Op(">=")) => CompareOp::Ge,
+            Some(Token::Ident(word)) if word == "in" => {
+                self.pos += 1;
+                return Ok(Expr::In(Box::new(left), Box::new(self.operand()?)));
+            }
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        Ok(Expr::Compare(op, Box::new(left), Box::new(self.operand()?)))
+    }
+
+    fn operand(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(Token::Num(n)) => Ok(Expr::Literal(serde_json::json!(n))),
+            Some(Token::Op("(")) => {
+                let expr = self.or()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Op("[")) => {
+                let mut items = Vec::new();
+                if !self.eat("]") {
+                    loop {
+                        items.push(self.operand()?);
+                        if self.eat("]") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::List(items))
+            }
+            Some(Token::Ident(word)) => match word.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                _ if self.eat("(") => self.call(&word),
+                _ => Ok(Expr::Field(word)),
+            },
+            other => bail!("expected a value, found {:?}", other),
+        }
+    }
+
+    fn call(&mut self, function: &str) -> Result<Expr> {
+        let mut args = Vec::new();
+        if !self.eat(")") {
+            loop {
+                args.push(self.or()?);
+                if self.eat(")") {
+                    break;
+                }
+                self.expect(",")?;
+            }
+        }
+
+        let arity = |args: &[Expr], n: usize| -> Result<()> {
+            if args.len() != n {
+                bail!("{}() takes {} arguments", function, n);
+            }
+            Ok(())
+        };
+        match function {
+            "exists" => {
+                arity(&args, 1)?;
+                match args.pop() {
+                    Some(Expr::Field(path)) => Ok(Expr::Exists(path)),
+                    _ => bail!("exists() takes a field"),
+                }
+            }
+            "lower" => {
+                arity(&args, 1)?;
+                Ok(Expr::Lower(Box::new(args.remove(0))))
+            }
+            "contains" | "starts_with" | "ends_with" | "matches" => {
+                arity(&args, 2)?;
+                let b = args.pop().unwrap();
+                let a = Box::new(args.pop().unwrap());
+                match function {
+                    "contains" => Ok(Expr::Contains(a, Box::new(b))),
+                    "starts_with" => Ok(Expr::StartsWith(a, Box::new(b))),
+                    "ends_with" => Ok(Expr::EndsWith(a, Box::new(b))),
+                    _ => match b {
+                        Expr::Literal(Value::String(pattern)) => Ok(Expr::Matches(
+                            a,
+                            Regex::new(&pattern)
+                                .with_context(|| format!("invalid regex {}", pattern))?,
+                        )),
+                        _ => bail!("matches() takes a string pattern"),
+                    },
+                }
+            }
+            other => bail!("unknown function {}()", other),
+        }
+    }
+}
+
+/// Value of a field of an event as a string
+pub fn field_string(event: &AnalyticsEvent, path: &str) -> Option<String> {
+    EventView::new(event).field(path).map(|v| value_string(&v))
+}
+
+/// An event with its JSON form computed on first non-tag lookup
+struct EventView<'a> {
+    event: &'a AnalyticsEvent,
+    json: OnceCell<Value>,
+}
+
+impl<'a> EventView<'a> {
+    fn new(event: &'a AnalyticsEvent) -> Self {
+        Self {
+            event,
+            json: OnceCell::new(),
+        }
+    }
+
+    fn field(&self, path: &str) -> Option<Value> {
+        if let Some(tag) = path.strip_prefix("tags.") {
+            return self.event.common.tags.get(tag).cloned().map(Value::String);
+        }
+        let json = self
+            .json
+            .get_or_init(|| serde_json::to_value(self.event).unwrap_or(Value::Null));
+        path.split('.')
+            .try_fold(json, |value, part| value.get(part))
+            .filter(|value| !value.is_null())
+            .cloned()
+    }
+}
+
+// ============================================================================
+// FIELD EDITING
+// ============================================================================
+
+/// Edit non-tag fields through the event's JSON form
+fn edit_json(event: &mut AnalyticsEvent, edit: impl FnOnce(&mut Value)) -> Result<()> {
+    let mut json = serde_json::to_value(&*event)?;
+    edit(&mut json);
+    *event = serde_json::from_value(json).context("edited event is no longer valid")?;
+    Ok(())
+}
+
+fn remove_path(json: &mut Value, path: &str) {
+    let (parent, last) = match path.rsplit_once('.') {
+        Some((parent, last)) => (parent.split('.').try_fold(json, |v, p| v.get_mut(p)), last),
+        None => (Some(json), path),
+    };
+    if let Some(Value::Object(map)) = parent {
+        map.remove(last);
+    }
+}
+
+fn set_path_default(json: &mut Value, path: &str, value: &Value) {
+    let mut current = json;
+    for part in path.split('.') {
+        if !current.is_object() {
+            return;
+        }
+        current = current
+            .as_object_mut()
+            .unwrap()
+            .entry(part.to_string())
+            .or_insert(Value::Null);
+    }
+    if current.is_null() {
+        *current = value.clone();
+    }
+}
+
+// ============================================================================
+// BUILT-IN STAGES
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    /// Keep only matching events
+    #[default]
+    Keep,
+    /// Drop matching events
+    Drop,
+}
+
+/// A route destination
+#[derive(Debug, Clone, Deserialize)]
+pub struct RouteRule {
+    pub when: Expr,
+    pub to: String,
+}
+
+/// Built-in stage configuration
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StageConfig {
+    Filter {
+        condition: Expr,
+        #[serde(default)]
+        action: FilterAction,
+    },
+    DropFields {
+        fields: Vec<String>,
+    },
+    RenameTags {
+        tags: BTreeMap<String, String>,
+    },
+    SetDefaults {
+        values: BTreeMap<String, Value>,
+    },
+    /// Set the route tag from the first matching rule
+    Route {
+        routes: Vec<RouteRule>,
+        #[serde(default)]
+        default: Option<String>,
+    },
+    /// Keep a deterministic fraction of events, by `key` or event ID,
+    /// recording the sample weight of kept events
+    Sample {
+        rate: f64,
+        #[serde(default)]
+        key: Option<String>,
+    },
+    MapSeverity {
+        mapping: BTreeMap<Severity, Severity>,
+    },
+}
+
+impl StageConfig {
+    fn kind(&self) -> &'static str {
+        match self {
+            StageConfig::Filter { .. } => "filter",
+            StageConfig::DropFields { .. } => "drop_fields",
+            StageConfig::RenameTags { .. } => "rename_tags",
+            StageConfig::SetDefaults { .. } => "set_defaults",
+            StageConfig::Route { .. } => "route",
+            StageConfig::Sample { .. } => "sample",
+            StageConfig::MapSeverity { .. } => "map_severity",
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self {
+            StageConfig::Sample { rate, .. } if !(0.0..=1.0).contains(rate) => {
+                bail!("sample rate must be between 0 and 1, got {}", rate)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn apply(&self, event: &mut AnalyticsEvent) -> Result<Action> {
+        match self {
+            StageConfig::Filter { condition, action } => {
+                let matched = condition.matches(event);
+                let keep = match action {
+                    FilterAction::Keep => matched,
+                    FilterAction::Drop => !matched,
+                };
+                return Ok(if keep { Action::Continue } else { Action::Drop });
+            }
+            StageConfig::DropFields { fields } => {
+                let (tags, others): (Vec<_>, Vec<_>) =
+                    fields.iter().partition(|f| f.starts_with("tags."));
+                for tag in tags {
+                    event.common.tags.remove(&tag["tags.".len()..]);
+                }
+                if !others.is_empty() {
+                    edit_json(event, |json| {
+                        for field in others {
+                            remove_path(json, field);
+                        }
+                    })?;
+                }
+            }
+            StageConfig::RenameTags { tags } => {
+                for (from, to) in tags {
+                    if let Some(value) = event.common.tags.remove(from) {
+                        event.common.tags.insert(to.clone(), value);
+                    }
+                }
+            }
+            StageConfig::SetDefaults { values } => {
+                let mut others = Vec::new();
+                for (field, value) in values {
+                    match field.strip_prefix("tags.") {
+                        Some(tag) => {
+                            event
+                                .common
+                                .tags
+                                .entry(tag.to_string())
+                                .or_insert_with(|| value_string(value));
+                        }
+                        None => others.push((field, value)),
+                    }
+                }
+                if !others.is_empty() {
+                    edit_json(event, |json| {
+                        for (field, value) in others {
+                            set_path_default(json, field, value);
+                        }
+                    })?;
+                }
+            }
+            StageConfig::Route { routes, default } => {
+                let route = routes
+                    .iter()
+                    .find(|rule| rule.when.matches(event))
+                    .map(|rule| &rule.to)
+                    .or(default.as_ref());
+                if let Some(route) = route {
+                    event
+                        .common
+                        .tags
+                        .insert(ROUTE_TAG.to_string(), route.clone());
+                }
+            }
+            StageConfig::Sample { rate, key } => {
+                let key = match key {
+                    Some(field) => field_string(event, field).unwrap_or_default(),
+                    None => event.common.event_id.to_string(),
+                };
+                if sample_fraction(&key) >= *rate {
+                    return Ok(Action::Drop);
+                }
+                apply_weight(event, 1.0 / rate);
+            }
+            StageConfig::MapSeverity { mapping } => {
+                if let Some(severity) = mapping.get(&event.common.severity) {
+                    event.common.severity = severity.clone();
+                }
+            }
+        }
+        Ok(Action::Continue)
+    }
+}
+
+/// Position of a key in [0, 1), stable across processes (FNV-1a with a
+/// final mix so that similar keys spread evenly)
+pub(crate) fn sample_fraction(key: &str) -> f64 {
+    let mut hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// A configured stage, optionally applied only to events matching `when`
+#[derive(Debug, Clone, Deserialize)]
+pub struct StageSpec {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub when: Option<Expr>,
+    #[serde(flatten)]
+    pub stage: StageConfig,
+}
+
+impl Transform for StageSpec {
+    fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.stage.kind())
+    }
+
+    fn apply(&self, event: &mut AnalyticsEvent) -> Result<Action> {
+        if let Some(when) = &self.when {
+            if !when.matches(event) {
+                return Ok(Action::Continue);
+            }
+        }
+        self.stage.apply(event)
+    }
+
+    fn route_targets(&self) -> Vec<&str> {
+        match &self.stage {
+            StageConfig::Route { routes, default } => routes
+                .iter()
+                .map(|rule| rule.to.as_str())
+                .chain(default.as_deref())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Transform chain configuration
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TransformConfig {
+    #[serde(default)]
+    pub transforms: Vec<StageSpec>,
+}
+
+// ============================================================================
+// CHAIN
+// ============================================================================
+
+/// Result of running an event through a chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainOutcome {
+    Emit,
+    Dropped { stage: String },
+}
+
+/// An ordered chain of transforms
+#[derive(Default)]
+pub struct TransformChain {
+    stages: Vec<Box<dyn Transform>>,
+    route_targets: BTreeSet<String>,
+}
+
+impl TransformChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_config(config: TransformConfig) -> Result<Self> {
+        let mut chain = Self::new();
+        for spec in config.transforms {
+            spec.stage
+                .validate()
+                .with_context(|| format!("Invalid transform {}", spec.name()))?;
+            chain.push(Box::new(spec));
+        }
+        Ok(chain)
+    }
+
+    pub fn from_yaml(yaml: &str) -> Result<Self> {
+        Self::from_config(serde_yaml::from_str(yaml).context("Invalid transform config")?)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let yaml = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read transforms from {}", path.display()))?;
+        Self::from_yaml(&yaml)
+    }
+
+    /// Append a stage
+    pub fn push(&mut self, stage: Box<dyn Transform>) {
+        self.add_route_targets(stage.as_ref());
+        self.stages.push(stage);
+    }
+
+    /// Prepend a stage
+    pub fn push_front(&mut self, stage: Box<dyn Transform>) {
+        self.add_route_targets(stage.as_ref());
+        self.stages.insert(0, stage);
+    }
+
+    fn add_route_targets(&mut self, stage: &dyn Transform) {
+        self.route_targets
+            .extend(stage.route_targets().into_iter().map(str::to_string));
+    }
+
+    /// Whether a `route` stage of this chain may route events to `target`
+    pub fn routes_to(&self, target: &str) -> bool {
+        self.route_targets.contains(target)
+    }
+
+    pub fn stage_names(&self) -> Vec<&str> {
+        self.stages.iter().map(|s| s.name()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Run the event through every stage, stopping at the first drop
+    pub fn apply(&self, event: &mut AnalyticsEvent) -> Result<ChainOutcome> {
+        for stage in &self.stages {
+            let action = stage
+                .apply(event)
+                .with_context(|| format!("Transform {} failed", stage.name()))?;
+            if action == Action::Drop {
+                return Ok(ChainOutcome::Dropped {
+                    stage: stage.name().to_string(),
+                });
+            }
+        }
+        Ok(ChainOutcome::Emit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::events::*;
+
+    fn event(severity: Severity, tags: &[(&str, &str)]) -> AnalyticsEvent {
+        fixtures::telemetry(TelemetryPayload::Latency(LatencyMetrics {
+            model_id: "gpt-4".to_string(),
+            request_id: "req-1".to_string(),
+            total_latency_ms: 1250.0,
+            ttft_ms: Some(200.0),
+            tokens_per_second: None,
+            breakdown: None,
+        }))
+        .with_severity(severity)
+        .with_environment("staging")
+        .with_tags(tags)
+    }
+
+    fn eval(expr: &str, event: &AnalyticsEvent) -> bool {
+        expr.parse::<Expr>().unwrap().matches(event)
+    }
+
+    #[test]
+    fn test_expressions_over_event_fields() {
+        let e = event(Severity::Warning, &[("tenant", "acme"), ("retries", "3")]);
+
+        assert!(eval(
+            r#"source_module == "llm-observatory" && event_type == "telemetry""#,
+            &e
+        ));
+        assert!(eval("payload.data.total_latency_ms > 1000", &e));
+        assert!(eval("tags.retries >= 3 && tags.retries < 4", &e));
+        assert!(eval(r#"severity >= "warning" && severity < "error""#, &e));
+        assert!(eval(r#"tags.tenant in ["acme", "globex"]"#, &e));
+        assert!(eval(r#"!(environment == "production") || false"#, &e));
+        assert!(eval(r#"exists(tags.tenant) && !exists(tags.user)"#, &e));
+        assert!(eval(
+            r#"starts_with(payload.data.model_id, "gpt") && matches(environment, "^stag")"#,
+            &e
+        ));
+        assert!(eval(
+            r#"lower("ACME") == tags.tenant && contains(environment, "agi")"#,
+            &e
+        ));
+        // Missing fields are null and never ordered
+        assert!(!eval("payload.data.tokens_per_second > 0", &e));
+
+        assert!("severity ==".parse::<Expr>().is_err());
+        assert!("exists(1)".parse::<Expr>().is_err());
+        assert!(r#"matches(environment, "(")"#.parse::<Expr>().is_err());
+        assert!(r#"tags.tenant == "acme"#.parse::<Expr>().is_err());
+    }
+
+    #[test]
+    fn test_yaml_chain_cleans_up_events() {
+        let chain = TransformChain::from_yaml(
+            r#"
+transforms:
+  - type: filter
+    condition: 'severity == "debug"'
+    action: drop
+  - type: drop_fields
+    fields: [tags.user_email, payload.data.ttft_ms]
+  - type: rename_tags
+    tags: { svc: service }
+  - type: set_defaults
+    values: { tags.team: unknown, tags.service: none, correlation_id: null }
+  - name: errors-to-alerts
+    type: route
+    routes:
+      - when: 'severity >= "error"'
+        to: llm-alerts
+    default: llm-events
+  - type: map_severity
+    when: 'tags.service == "batch"'
+    mapping: { critical: error }
+"#,
+        )
+        .unwrap();
+        assert_eq!(chain.stage_names()[4], "errors-to-alerts");
+
+        let mut debug = event(Severity::Debug, &[]);
+        assert_eq!(
+            chain.apply(&mut debug).unwrap(),
+            ChainOutcome::Dropped {
+                stage: "filter".to_string()
+            }
+        );
+
+        let mut cleaned = event(
+            Severity::Critical,
+            &[("user_email", "a@b.c"), ("svc", "batch")],
+        );
+        assert_eq!(chain.apply(&mut cleaned).unwrap(), ChainOutcome::Emit);
+        let tags = &cleaned.common.tags;
+        assert!(!tags.contains_key("user_email"));
+        assert_eq!(tags.get("service").map(String::as_str), Some("batch"));
+        assert_eq!(tags.get("team").map(String::as_str), Some("unknown"));
+        assert_eq!(tags.get(ROUTE_TAG).map(String::as_str), Some("llm-alerts"));
+        assert!(chain.routes_to("llm-alerts"));
+        assert!(chain.routes_to("llm-events"));
+        assert!(!chain.routes_to("llm-internal"));
+        assert_eq!(cleaned.common.severity, Severity::Error);
+        match cleaned.payload {
+            EventPayload::Telemetry(TelemetryPayload::Latency(metrics)) => {
+                assert_eq!(metrics.ttft_ms, None)
+            }
+            _ => panic!("payload changed type"),
+        }
+    }
+
+    #[test]
+    fn test_sampling_is_deterministic_per_key() {
+        let chain = TransformChain::from_yaml(
+            "transforms:\n  - type: sample\n    rate: 0.25\n    key: tags.tenant\n",
+        )
+        .unwrap();
+        let kept = |tenant: &str| {
+            let mut event = event(Severity::Info, &[("tenant", tenant)]);
+            let outcome = chain.apply(&mut event).unwrap();
+            if outcome == ChainOutcome::Emit {
+                assert_eq!(crate::pipeline::sampling::sample_weight(&event), 4.0);
+            }
+            outcome == ChainOutcome::Emit
+        };
+        for tenant in ["acme", "globex", "initech"] {
+            assert_eq!(kept(tenant), kept(tenant));
+        }
+
+        let kept = (0..10_000)
+            .filter(|i| sample_fraction(&format!("tenant-{}", i)) < 0.25)
+            .count();
+        assert!((2_200..2_800).contains(&kept), "kept {}", kept);
+
+        assert!(TransformChain::from_yaml("transforms:\n  - type: sample\n    rate: 2\n").is_err());
+    }
+
+    #[test]
+    fn test_invalid_edits_fail_the_stage() {
+        let chain = TransformChain::from_yaml(
+            "transforms:\n  - type: drop_fields\n    fields: [source_module]\n",
+        )
+        .unwrap();
+        let error = chain.apply(&mut event(Severity::Info, &[])).unwrap_err();
+        assert!(error.to_string().contains("drop_fields"));
+    }
+}
@@ -0,0 +1,626 @@
+//! Partition-Parallel Workers
+//!
+//! Consumed events are sharded by key across a fixed pool of workers, each
+//! storing and forwarding its own batches. Events with the same key always
+//! go to the same worker and are handled in consumption order, while a slow
+//! batch only holds up the keys of its worker. Every worker has a bounded
+//! queue; dispatching to a full queue waits, which backpressures the
+//! consumer.
+//!
+//! Workers finish messages out of partition order, so [`OffsetTracker`] only
+//! lets a partition commit up to its lowest offset still in flight. A failed
+//! batch rewinds its partitions to that offset; messages of the partition
+//! still queued are skipped and consumed again after the seek.
+
+use super::offsets::{AckingBatcher, BatchSink, FlushOutcome, TopicPartition};
+use crate::schemas::events::{AnalyticsEvent, CostPayload, EventPayload, TelemetryPayload};
+use anyhow::{anyhow, Result};
+use parking_lot::Mutex;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+/// Key events are sharded by. Events without the key are spread by event ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShardBy {
+    /// Keep the events of a request chain in order
+    CorrelationId,
+    /// Keep the events of a model in order, e.g. for aggregation
+    ModelId,
+}
+
+impl FromStr for ShardBy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "correlation_id" => Ok(Self::CorrelationId),
+            "model_id" => Ok(Self::ModelId),
+            other => Err(anyhow!("unknown shard key: {}", other)),
+        }
+    }
+}
+
+impl ShardBy {
+    fn hash(&self, event: &AnalyticsEvent) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        match (self, event.common.correlation_id, model_id(&event.payload)) {
+            (Self::CorrelationId, Some(correlation_id), _) => correlation_id.hash(&mut hasher),
+            (Self::ModelId, _, Some(model_id)) => model_id.hash(&mut hasher),
+            _ => event.common.event_id.hash(&mut hasher),
+        }
+        hasher.finish()
+    }
+}
+
+fn model_id(payload: &EventPayload) -> Option<&str> {
+    match payload {
+        EventPayload::Telemetry(TelemetryPayload::Latency(m)) => Some(&m.model_id),
+        EventPayload::Telemetry(TelemetryPayload::Throughput(m)) => Some(&m.model_id),
+        EventPayload::Telemetry(TelemetryPayload::ErrorRate(m)) => Some(&m.model_id),
+        EventPayload::Telemetry(TelemetryPayload::TokenUsage(m)) => Some(&m.model_id),
+        EventPayload::Telemetry(TelemetryPayload::ModelPerformance(m)) => Some(&m.model_id),
+        EventPayload::Cost(CostPayload::TokenCost(m)) => Some(&m.model_id),
+        _ => None,
+    }
+}
+
+/// Worker pool configuration
+#[derive(Debug, Clone)]
+pub struct WorkerPoolConfig {
+    pub num_workers: usize,
+    /// Events each worker may have queued before dispatch waits
+    pub queue_size: usize,
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+    pub max_retries: u32,
+    pub shard_by: ShardBy,
+}
+
+impl Default for WorkerPoolConfig {
+    fn default() -> Self {
+        Self {
+            num_workers: 4,
+            queue_size: 1000,
+            batch_size: 1000,
+            flush_interval: Duration::from_millis(500),
+            max_retries: 3,
+            shard_by: ShardBy::CorrelationId,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PartitionState {
+    epoch: u64,
+    in_flight: BTreeSet<i64>,
+    // Next offset after the last dispatched message
+    next: i64,
+    committed: Option<i64>,
+    failed: bool,
+}
+
+/// Commit positions of partitions whose messages complete out of order
+#[derive(Debug, Default)]
+pub struct OffsetTracker {
+    partitions: HashMap<TopicPartition, PartitionState>,
+    // Epochs are never reused, so completions from before a rewind or
+    // revocation are recognised after the partition is tracked again
+    epochs: u64,
+}
+
+impl OffsetTracker {
+    /// Track a dispatched message, returning the epoch to complete it with
+    pub fn dispatch(&mut self, topic: &str, partition: i32, offset: i64) -> u64 {
+        let state = self.state(topic, partition);
+        state.in_flight.insert(offset);
+        state.next = state.next.max(offset + 1);
+        state.epoch
+    }
+
+    /// Record a message that needs no processing, e.g. one sent to the DLQ
+    pub fn skip(&mut self, topic: &str, partition: i32, offset: i64) {
+        let state = self.state(topic, partition);
+        state.next = state.next.max(offset + 1);
+    }
+
+    /// Whether a message dispatched in `epoch` should still be processed
+    pub fn is_current(&self, topic: &str, partition: i32, epoch: u64) -> bool {
+        self.partitions
+            .get(&(topic.to_string(), partition))
+            .is_some_and(|state| state.epoch == epoch)
+    }
+
+    /// Mark a message as stored and forwarded
+    pub fn complete(&mut self, topic: &str, partition: i32, offset: i64, epoch: u64) {
+        if let Some(state) = self.current(topic, partition, epoch) {
+            state.in_flight.remove(&offset);
+        }
+    }
+
+    /// Mark a message as failed; its partition is rewound by
+    /// [`take_rewinds`](Self::take_rewinds)
+    pub fn fail(&mut self, topic: &str, partition: i32, epoch: u64) {
+        if let Some(state) = self.current(topic, partition, epoch) {
+            state.failed = true;
+        }
+    }
+
+    /// Positions that advanced since they were last returned: the lowest
+    /// offset in flight, or the next offset when nothing is in flight
+    pub fn commit_positions(&mut self) -> Vec<(TopicPartition, i64)> {
+        let mut positions = Vec::new();
+        for (tp, state) in &mut self.partitions {
+            if state.failed {
+                continue;
+            }
+            let position = state.in_flight.first().copied().unwrap_or(state.next);
+            if !matches!(state.committed, Some(committed) if position <= committed) {
+                state.committed = Some(position);
+                positions.push((tp.clone(), position));
+            }
+        }
+        positions.sort();
+        positions
+    }
+
+    /// Offsets to seek failed partitions to. Their messages still in flight
+    /// belong to the old epoch and are skipped.
+    pub fn take_rewinds(&mut self) -> Vec<(TopicPartition, i64)> {
+        let mut rewinds = Vec::new();
+        for (tp, state) in &mut self.partitions {
+            if !state.failed {
+                continue;
+            }
+            let position = state.in_flight.first().copied().unwrap_or(state.next);
+            self.epochs += 1;
+            state.epoch = self.epochs;
+            state.in_flight.clear();
+            state.next = position;
+            state.failed = false;
+            rewinds.push((tp.clone(), position));
+        }
+        rewinds.sort();
+        rewinds
+    }
+
+    /// Stop tracking a revoked partition, returning the position to commit
+    /// for it
+    pub fn revoke(&mut self, topic: &str, partition: i32) -> Option<i64> {
+        let state = self.partitions.remove(&(topic.to_string(), partition))?;
+        let position = state.in_flight.first().copied().unwrap_or(state.next);
+        (!state.failed && !matches!(state.committed, Some(committed) if position <= committed))
+            .then_some(position)
+    }
+
+    fn state(&mut self, topic: &str, partition: i32) -> &mut PartitionState {
+        let epochs = &mut self.epochs;
+        self.partitions
+            .entry((topic.to_string(), partition))
+            .or_insert_with(|| {
+                *epochs += 1;
+                PartitionState {
+                    epoch: *epochs,
+                    in_flight: BTreeSet::new(),
+                    next: 0,
+                    committed: None,
+                    failed: false,
+                }
+            })
+    }
+
+    fn current(&mut self, topic: &str, partition: i32, epoch: u64) -> Option<&mut PartitionState> {
+        self.partitions
+            .get_mut(&(topic.to_string(), partition))
+            .filter(|state| state.epoch == epoch)
+    }
+}
+
+/// A consumed event queued for a worker
+struct WorkItem {
+    topic: String,
+    partition: i32,
+    offset: i64,
+    epoch: u64,
+    event: AnalyticsEvent,
+    enqueued: Instant,
+}
+
+/// Counters of a single worker
+#[derive(Debug, Default)]
+pub struct WorkerMetrics {
+    queue_depth: AtomicU64,
+    lag_ms: AtomicU64,
+    events_stored: AtomicU64,
+    events_forwarded: AtomicU64,
+    events_skipped: AtomicU64,
+    batches: AtomicU64,
+    batches_rewound: AtomicU64,
+    last_batch_ms: AtomicU64,
+}
+
+impl WorkerMetrics {
+    fn snapshot(&self, worker: usize) -> WorkerStats {
+        WorkerStats {
+            worker,
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            lag_ms: self.lag_ms.load(Ordering::Relaxed),
+            events_stored: self.events_stored.load(Ordering::Relaxed),
+            events_forwarded: self.events_forwarded.load(Ordering::Relaxed),
+            events_skipped: self.events_skipped.load(Ordering::Relaxed),
+            batches: self.batches.load(Ordering::Relaxed),
+            batches_rewound: self.batches_rewound.load(Ordering::Relaxed),
+            last_batch_ms: self.last_batch_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Worker statistics snapshot
+#[derive(Debug, Clone)]
+pub struct WorkerStats {
+    pub worker: usize,
+    /// Events waiting in the worker's queue
+    pub queue_depth: u64,
+    /// Time the most recently dequeued event spent in the queue
+    pub lag_ms: u64,
+    pub events_stored: u64,
+    pub events_forwarded: u64,
+    /// Events dropped from the queue because their partition was rewound
+    /// or revoked
+    pub events_skipped: u64,
+    pub batches: u64,
+    pub batches_rewound: u64,
+    pub last_batch_ms: u64,
+}
+
+/// Pool of workers storing and forwarding events sharded by key
+pub struct WorkerPool {
+    shard_by: ShardBy,
+    senders: Vec<mpsc::Sender<WorkItem>>,
+    metrics: Vec<Arc<WorkerMetrics>>,
+    tracker: Arc<Mutex<OffsetTracker>>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl WorkerPool {
+    /// Spawn the workers, each storing to `sink` and forwarding to `tx`
+    pub fn spawn(
+        config: WorkerPoolConfig,
+        sink: Arc<dyn BatchSink>,
+        tx: mpsc::Sender<AnalyticsEvent>,
+    ) -> Self {
+        let tracker = Arc::new(Mutex::new(OffsetTracker::default()));
+        let num_workers = config.num_workers.max(1);
+        let mut senders = Vec::with_capacity(num_workers);
+        let mut metrics = Vec::with_capacity(num_workers);
+        let mut handles = Vec::with_capacity(num_workers);
+
+        for worker in 0..num_workers {
+            let (worker_tx, worker_rx) = mpsc::channel(config.queue_size.max(1));
+            let worker_metrics = Arc::new(WorkerMetrics::default());
+            let batcher = AckingBatcher::new(sink.clone(), tx.clone(), config.max_retries);
+            handles.push(tokio::spawn(run_worker(
+                worker,
+                worker_rx,
+                batcher,
+                tracker.clone(),
+                worker_metrics.clone(),
+                config.batch_size.max(1),
+                config.flush_interval,
+            )));
+            senders.push(worker_tx);
+            metrics.push(worker_metrics);
+        }
+        info!(workers = num_workers, shard_by = ?config.shard_by, "Started ingestion workers");
+
+        Self {
+            shard_by: config.shard_by,
+            senders,
+            metrics,
+            tracker,
+            handles,
+        }
+    }
+
+    /// Offsets of the messages dispatched to the pool
+    pub fn tracker(&self) -> &Arc<Mutex<OffsetTracker>> {
+        &self.tracker
+    }
+
+    /// Worker an event is sharded to
+    pub fn worker_for(&self, event: &AnalyticsEvent) -> usize {
+        (self.shard_by.hash(event) % self.senders.len() as u64) as usize
+    }
+
+    /// Queue a consumed event, waiting while its worker's queue is full
+    pub async fn dispatch(
+        &self,
+        topic: &str,
+        partition: i32,
+        offset: i64,
+        event: AnalyticsEvent,
+    ) -> Result<()> {
+        let worker = self.worker_for(&event);
+        let epoch = self.tracker.lock().dispatch(topic, partition, offset);
+        self.metrics[worker]
+            .queue_depth
+            .fetch_add(1, Ordering::Relaxed);
+
+        let item = WorkItem {
+            topic: topic.to_string(),
+            partition,
+            offset,
+            epoch,
+            event,
+            enqueued: Instant::now(),
+        };
+        self.senders[worker]
+            .send(item)
+            .await
+            .map_err(|_| anyhow!("ingestion worker {} stopped", worker))
+    }
+
+    /// Statistics of every worker
+    pub fn stats(&self) -> Vec<WorkerStats> {
+        self.metrics
+            .iter()
+            .enumerate()
+            .map(|(worker, metrics)| metrics.snapshot(worker))
+            .collect()
+    }
+
+    /// Close the queues and wait for the workers to flush their batches
+    pub async fn shutdown(self) {
+        drop(self.senders);
+        for handle in self.handles {
+            let _ = handle.await;
+        }
+    }
+}
+
+async fn run_worker(
+    worker: usize,
+    mut rx: mpsc::Receiver<WorkItem>,
+    mut batcher: AckingBatcher,
+    tracker: Arc<Mutex<OffsetTracker>>,
+    metrics: Arc<WorkerMetrics>,
+    batch_size: usize,
+    flush_interval: Duration,
+) {
+    // (Topic, Partition, Offset, Epoch) of the events in the batch
+    let mut pending: Vec<(String, i32, i64, u64)> = Vec::new();
+    let mut deadline = tokio::time::Instant::now() + flush_interval;
+
+    loop {
+        let closed = match tokio::time::timeout_at(deadline, rx.recv()).await {
+            Ok(Some(item)) => {
+                metrics.queue_depth.fetch_sub(1, Ordering::Relaxed);
+                metrics.lag_ms.store(
+                    item.enqueued.elapsed().as_millis() as u64,
+                    Ordering::Relaxed,
+                );
+
+                if !tracker
+                    .lock()
+                    .is_current(&item.topic, item.partition, item.epoch)
+                {
+                    metrics.events_skipped.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                batcher.push(&item.topic, item.partition, item.offset, Some(item.event));
+                pending.push((item.topic, item.partition, item.offset, item.epoch));
+                if batcher.len() < batch_size {
+                    continue;
+                }
+                false
+            }
+            Ok(None) => true,
+            Err(_) => false,
+        };
+
+        let start = Instant::now();
+        match batcher.flush().await {
+            FlushOutcome::Empty => {}
+            FlushOutcome::Committable {
+                stored, forwarded, ..
+            } => {
+                metrics.events_stored.fetch_add(stored, Ordering::Relaxed);
+                metrics
+                    .events_forwarded
+                    .fetch_add(forwarded, Ordering::Relaxed);
+                metrics.batches.fetch_add(1, Ordering::Relaxed);
+                let mut tracker = tracker.lock();
+                for (topic, partition, offset, epoch) in pending.drain(..) {
+                    tracker.complete(&topic, partition, offset, epoch);
+                }
+                debug!(worker, stored, forwarded, "Worker batch acknowledged");
+            }
+            FlushOutcome::Rewind { error, .. } => {
+                metrics.batches.fetch_add(1, Ordering::Relaxed);
+                metrics.batches_rewound.fetch_add(1, Ordering::Relaxed);
+                error!(worker, "Event batch not acknowledged: {}", error);
+                let mut tracker = tracker.lock();
+                for (topic, partition, _, epoch) in pending.drain(..) {
+                    tracker.fail(&topic, partition, epoch);
+                }
+            }
+        }
+        metrics
+            .last_batch_ms
+            .store(start.elapsed().as_millis() as u64, Ordering::Relaxed);
+
+        if closed {
+            break;
+        }
+        deadline = tokio::time::Instant::now() + flush_interval;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schemas::events::*;
+    use async_trait::async_trait;
+    use uuid::Uuid;
+
+    fn tp(partition: i32) -> TopicPartition {
+        ("events".to_string(), partition)
+    }
+
+    fn event(correlation_id: Option<Uuid>, model_id: &str) -> AnalyticsEvent {
+        fixtures::latency(model_id, 100.0).with_correlation_id(correlation_id)
+    }
+
+    /// Sink that is slow for one model and records the order events are stored in
+    #[derive(Default)]
+    struct RecordingSink {
+        stored: Mutex<Vec<Uuid>>,
+        slow_model: Option<String>,
+    }
+
+    #[async_trait]
+    impl BatchSink for RecordingSink {
+        async fn store_batch(&self, events: &[AnalyticsEvent]) -> Result<u64> {
+            let slow = events.iter().any(
+                |e| matches!(&self.slow_model, Some(slow) if model_id(&e.payload) == Some(slow)),
+            );
+            if slow {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            self.stored
+                .lock()
+                .extend(events.iter().map(|e| e.common.event_id));
+            Ok(events.len() as u64)
+        }
+    }
+
+    #[test]
+    fn test_tracker_commits_below_lowest_in_flight_offset() {
+        let mut tracker = OffsetTracker::default();
+        let e0 = tracker.dispatch("events", 0, 10);
+        let e1 = tracker.dispatch("events", 0, 11);
+        tracker.skip("events", 0, 12);
+        tracker.dispatch("events", 1, 3);
+        assert_eq!(tracker.commit_positions(), vec![(tp(0), 10), (tp(1), 3)]);
+
+        // A later offset completing first does not advance the position
+        tracker.complete("events", 0, 11, e1);
+        assert_eq!(tracker.commit_positions(), vec![]);
+        tracker.complete("events", 0, 10, e0);
+        assert_eq!(tracker.commit_positions(), vec![(tp(0), 13)]);
+    }
+
+    #[test]
+    fn test_tracker_rewinds_failed_partition_and_ignores_old_epoch() {
+        let mut tracker = OffsetTracker::default();
+        let epoch = tracker.dispatch("events", 0, 5);
+        tracker.dispatch("events", 0, 6);
+        tracker.dispatch("events", 0, 7);
+        tracker.complete("events", 0, 5, epoch);
+        tracker.fail("events", 0, epoch);
+
+        assert_eq!(tracker.commit_positions(), vec![]);
+        assert_eq!(tracker.take_rewinds(), vec![(tp(0), 6)]);
+        assert!(!tracker.is_current("events", 0, epoch));
+
+        // Completions from before the rewind are ignored
+        tracker.complete("events", 0, 7, epoch);
+        let replayed = tracker.dispatch("events", 0, 6);
+        assert_ne!(replayed, epoch);
+        assert_eq!(tracker.commit_positions(), vec![(tp(0), 6)]);
+        tracker.complete("events", 0, 6, replayed);
+        assert_eq!(tracker.commit_positions(), vec![(tp(0), 7)]);
+
+        assert_eq!(tracker.revoke("events", 0), None);
+        assert!(!tracker.is_current("events", 0, replayed));
+    }
+
+    #[test]
+    fn test_events_shard_by_key() {
+        let correlation_id = Some(Uuid::new_v4());
+        let a = event(correlation_id, "gpt-4");
+        let b = event(correlation_id, "claude-3");
+        assert_eq!(
+            ShardBy::CorrelationId.hash(&a),
+            ShardBy::CorrelationId.hash(&b)
+        );
+        assert_ne!(ShardBy::ModelId.hash(&a), ShardBy::ModelId.hash(&b));
+        assert_eq!(
+            ShardBy::ModelId.hash(&a),
+            ShardBy::ModelId.hash(&event(None, "gpt-4"))
+        );
+        assert_eq!("model_id".parse::<ShardBy>().unwrap(), ShardBy::ModelId);
+        assert!("tenant".parse::<ShardBy>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_slow_key_does_not_stall_other_workers() {
+        let sink = Arc::new(RecordingSink {
+            slow_model: Some("slow".to_string()),
+            ..Default::default()
+        });
+        let (tx, mut rx) = mpsc::channel(64);
+        let pool = WorkerPool::spawn(
+            WorkerPoolConfig {
+                num_workers: 2,
+                batch_size: 1,
+                shard_by: ShardBy::ModelId,
+                ..Default::default()
+            },
+            sink.clone(),
+            tx,
+        );
+
+        let slow = event(None, "slow");
+        let fast = (0..)
+            .map(|i| event(None, &format!("model-{}", i)))
+            .find(|e| pool.worker_for(e) != pool.worker_for(&slow))
+            .unwrap();
+        let ordered: Vec<_> = (0..5).map(|_| event(None, "slow")).collect();
+
+        pool.dispatch("events", 0, 0, slow.clone()).await.unwrap();
+        for (i, e) in ordered.iter().enumerate() {
+            pool.dispatch("events", 0, 1 + i as i64, e.clone())
+                .await
+                .unwrap();
+        }
+        pool.dispatch("events", 1, 0, fast.clone()).await.unwrap();
+
+        // The fast key is forwarded while the slow worker is still storing
+        let first = rx.recv().await.unwrap();
+        assert_eq!(first.common.event_id, fast.common.event_id);
+        let mut positions = Vec::new();
+        for _ in 0..20 {
+            positions.extend(pool.tracker().lock().commit_positions());
+            if positions.contains(&(tp(1), 1)) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(positions.contains(&(tp(1), 1)));
+        assert!(positions.contains(&(tp(0), 0)));
+
+        let stats = pool.stats();
+        pool.shutdown().await;
+        assert_eq!(stats.len(), 2);
+
+        // Events of the slow key keep their consumption order
+        let stored = sink.stored.lock().clone();
+        let expected: Vec<Uuid> = std::iter::once(&slow)
+            .chain(&ordered)
+            .map(|e| e.common.event_id)
+            .collect();
+        let slow_stored: Vec<Uuid> = stored
+            .into_iter()
+            .filter(|id| *id != fast.common.event_id)
+            .collect();
+        assert_eq!(slow_stored, expected);
+    }
+}
@@ -54,6 +54,29 @@ fn default_schema_version() -> String {
     SCHEMA_VERSION.to_string()
 }
 
+impl CommonEventFields {
+    /// Fields of a new event emitted now, with no correlation and no tags
+    pub fn new(
+        source_module: SourceModule,
+        event_type: EventType,
+        severity: Severity,
+        environment: impl Into<String>,
+    ) -> Self {
+        Self {
+            event_id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            source_module,
+            event_type,
+            correlation_id: None,
+            parent_event_id: None,
+            schema_version: SCHEMA_VERSION.to_string(),
+            severity,
+            environment: environment.into(),
+            tags: HashMap::new(),
+        }
+    }
+}
+
 /// Source modules in the LLM ecosystem
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "kebab-case")]
@@ -180,6 +203,10 @@ pub enum TelemetryPayload {
     /// Model performance metrics
     #[serde(rename = "model_performance")]
     ModelPerformance(ModelPerformanceMetrics),
+
+    /// SLO error-budget status computed by the analytics hub
+    #[serde(rename = "slo_status")]
+    SloStatus(SloStatusMetrics),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -251,6 +278,37 @@ pub struct ModelPerformanceMetrics {
     pub custom_metrics: HashMap<String, f64>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SloStatusMetrics {
+    pub slo_id: String,
+    pub slo_name: String,
+    /// Objective as the required fraction of good events (e.g. 0.999)
+    pub target: f64,
+    pub window_seconds: u64,
+    pub good_events: f64,
+    pub total_events: f64,
+    /// Fraction of good events over the window (None without traffic)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attainment: Option<f64>,
+    /// Fraction of the error budget left; negative once exhausted
+    pub error_budget_remaining: f64,
+    /// Burn rate per alerting window, keyed by window (e.g. "1h")
+    pub burn_rates: HashMap<String, f64>,
+    /// Most urgent burn-rate alert currently firing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alert: Option<SloAlertSeverity>,
+}
+
+/// Burn-rate alert severity
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum SloAlertSeverity {
+    /// Slow burn to be handled during working hours
+    Ticket,
+    /// Fast burn requiring immediate attention
+    Page,
+}
+
 // ============================================================================
 // SECURITY PAYLOADS (LLM-Sentinel)
 // ============================================================================
@@ -469,6 +527,9 @@ pub struct BudgetAlertEvent {
     pub current_spend_usd: f64,
     pub threshold_percent: f64,
     pub alert_type: BudgetAlertType,
+    /// Projected time at which the budget will be exhausted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub projected_exhaustion_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -477,6 +538,8 @@ pub enum BudgetAlertType {
     Warning,
     Critical,
     Exceeded,
+    /// Spend is projected to exceed the budget before the period ends
+    ForecastedOverrun,
 }
 
 // ============================================================================
@@ -595,6 +658,92 @@ pub struct CustomPayload {
     pub data: serde_json::Value,
 }
 
+/// Event builders shared by unit tests
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::*;
+
+    /// Event emitted now by LLM-Observatory in the `test` environment, typed
+    /// after its payload
+    pub(crate) fn event(payload: EventPayload) -> AnalyticsEvent {
+        let event_type = match &payload {
+            EventPayload::Telemetry(_) | EventPayload::Custom(_) => EventType::Telemetry,
+            EventPayload::Security(_) => EventType::Security,
+            EventPayload::Cost(_) => EventType::Cost,
+            EventPayload::Governance(_) => EventType::Governance,
+        };
+        AnalyticsEvent {
+            common: CommonEventFields::new(
+                SourceModule::LlmObservatory,
+                event_type,
+                Severity::Info,
+                "test",
+            ),
+            payload,
+        }
+    }
+
+    pub(crate) fn telemetry(payload: TelemetryPayload) -> AnalyticsEvent {
+        event(EventPayload::Telemetry(payload))
+    }
+
+    /// Latency telemetry of request `req-1`
+    pub(crate) fn latency(model_id: &str, total_latency_ms: f64) -> AnalyticsEvent {
+        telemetry(TelemetryPayload::Latency(LatencyMetrics {
+            model_id: model_id.to_string(),
+            request_id: "req-1".to_string(),
+            total_latency_ms,
+            ttft_ms: None,
+            tokens_per_second: None,
+            breakdown: None,
+        }))
+    }
+
+    impl AnalyticsEvent {
+        pub(crate) fn at(mut self, timestamp: DateTime<Utc>) -> Self {
+            self.common.timestamp = timestamp;
+            self
+        }
+
+        pub(crate) fn with_source(mut self, source_module: SourceModule) -> Self {
+            self.common.source_module = source_module;
+            self
+        }
+
+        pub(crate) fn with_event_type(mut self, event_type: EventType) -> Self {
+            self.common.event_type = event_type;
+            self
+        }
+
+        pub(crate) fn with_severity(mut self, severity: Severity) -> Self {
+            self.common.severity = severity;
+            self
+        }
+
+        pub(crate) fn with_correlation_id(mut self, correlation_id: Option<Uuid>) -> Self {
+            self.common.correlation_id = correlation_id;
+            self
+        }
+
+        pub(crate) fn with_parent(mut self, parent_event_id: Option<Uuid>) -> Self {
+            self.common.parent_event_id = parent_event_id;
+            self
+        }
+
+        pub(crate) fn with_environment(mut self, environment: &str) -> Self {
+            self.common.environment = environment.to_string();
+            self
+        }
+
+        pub(crate) fn with_tags(mut self, tags: &[(&str, &str)]) -> Self {
+            self.common
+                .tags
+                .extend(tags.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+            self
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -910,12 +1059,36 @@ mod tests {
             current_spend_usd: 9500.0,
             threshold_percent: 95.0,
             alert_type: BudgetAlertType::Critical,
+            projected_exhaustion_at: None,
         };
 
         assert_eq!(alert.alert_type, BudgetAlertType::Critical);
         assert!(alert.current_spend_usd > alert.budget_limit_usd * 0.9);
     }
 
+    #[test]
+    fn test_forecasted_overrun_alert_serialization() {
+        let alert = BudgetAlertEvent {
+            budget_id: "budget-123".to_string(),
+            budget_name: "Q1 LLM Budget".to_string(),
+            budget_limit_usd: 10000.0,
+            current_spend_usd: 4000.0,
+            threshold_percent: 130.0,
+            alert_type: BudgetAlertType::ForecastedOverrun,
+            projected_exhaustion_at: Some(Utc::now()),
+        };
+
+        let json = serde_json::to_value(&alert).unwrap();
+        assert_eq!(json["alert_type"], "forecasted_overrun");
+        assert!(json.get("projected_exhaustion_at").is_some());
+
+        // Older alerts without a projection still deserialize
+        let legacy = r#"{"budget_id":"b","budget_name":"n","budget_limit_usd":1.0,
+            "current_spend_usd":0.5,"threshold_percent":50.0,"alert_type":"warning"}"#;
+        let parsed: BudgetAlertEvent = serde_json::from_str(legacy).unwrap();
+        assert!(parsed.projected_exhaustion_at.is_none());
+    }
+
     #[test]
     fn test_resource_consumption_event() {
         let resource = ResourceConsumptionEvent {